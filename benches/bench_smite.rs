@@ -1,11 +1,16 @@
 use std::rc::Rc;
+use std::time::Instant;
 
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
 
-use xrust::item::Node;
+use xrust::item::{Item, Node};
+use xrust::parser::xml::{parse as xmlparse, parse_with_ns};
 use xrust::qname::QualifiedName;
+use xrust::transform::context::StaticContextBuilder;
 use xrust::trees::smite::{Node as SmiteNode, RNode};
 use xrust::value::Value;
+use xrust::xdmerror::{Error, ErrorKind};
+use xrust::xslt::from_document;
 
 fn make_rnode(n: u64) -> RNode {
     let mut a = Rc::new(SmiteNode::new());
@@ -37,5 +42,154 @@ fn rnode(c: &mut Criterion) {
     c.bench_function("rnode 100", |b| b.iter(|| make_rnode(black_box(1000))));
 }
 
-criterion_group!(benches, rnode);
+// A document with many elements of the same type and name
+fn make_uniform(n: usize) -> RNode {
+    let mut a = Rc::new(SmiteNode::new());
+    let mut b = a
+        .new_element(QualifiedName::new(None, None, String::from("Test")))
+        .expect("unable to create element");
+    a.push(b.clone()).expect("unable to add node");
+    (0..n).for_each(|_| {
+        b.push(
+            a.new_element(QualifiedName::new(None, None, String::from("Level-1")))
+                .expect("unable to create element"),
+        )
+        .expect("unable to add node");
+    });
+    a
+}
+
+// Apply templates to every element, with several templates that cannot match the elements
+fn apply_templates(sd: RNode, cache: bool) -> usize {
+    let (styledoc, stylens) = parse_with_ns(
+        Rc::new(SmiteNode::new()),
+        "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='/'><xsl:apply-templates/></xsl:template>
+  <xsl:template match='child::Test'><xsl:apply-templates/></xsl:template>
+  <xsl:template match='child::Level-1'><L/></xsl:template>
+  <xsl:template match='child::Level-2'><M/></xsl:template>
+  <xsl:template match='child::Level-3'><N/></xsl:template>
+  <xsl:template match='child::Level-4'><O/></xsl:template>
+  <xsl:template match='child::Level-5'><P/></xsl:template>
+  <xsl:template match='child::Level-6'><Q/></xsl:template>
+</xsl:stylesheet>",
+        None,
+    )
+    .expect("unable to parse stylesheet");
+    let mut stctxt = StaticContextBuilder::new()
+        .message(|_| Ok(()))
+        .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .build();
+    let mut ctxt = from_document(
+        styledoc,
+        stylens,
+        None,
+        |s| {
+            let d = Rc::new(SmiteNode::new());
+            xmlparse(d.clone(), s, None)?;
+            Ok(d)
+        },
+        |_| Ok(String::new()),
+    )
+    .expect("unable to compile stylesheet");
+    ctxt.template_cache(cache);
+    ctxt.context(vec![Item::Node(sd)], 0);
+    ctxt.result_document(Rc::new(SmiteNode::new()));
+    ctxt.evaluate(&mut stctxt).expect("evaluation failed").len()
+}
+
+fn templates(c: &mut Criterion) {
+    let sd = make_uniform(10000);
+    // With the cache, only the template that can match an element's name is tested against each element
+    let start = Instant::now();
+    apply_templates(sd.clone(), true);
+    let cached = start.elapsed();
+    let start = Instant::now();
+    apply_templates(sd.clone(), false);
+    let uncached = start.elapsed();
+    println!(
+        "apply-templates 10000: {:?} cached, {:?} uncached",
+        cached, uncached
+    );
+    c.bench_function("apply-templates 10000 cached", |b| {
+        b.iter(|| apply_templates(black_box(sd.clone()), true))
+    });
+    c.bench_function("apply-templates 10000 uncached", |b| {
+        b.iter(|| apply_templates(black_box(sd.clone()), false))
+    });
+}
+
+// A document with many elements, each with a string-value held in a descendant text node
+fn make_keyed(n: usize) -> RNode {
+    let mut a = Rc::new(SmiteNode::new());
+    let mut b = a
+        .new_element(QualifiedName::new(None, None, String::from("Test")))
+        .expect("unable to create element");
+    a.push(b.clone()).expect("unable to add node");
+    (0..n).for_each(|i| {
+        let mut l1 = a
+            .new_element(QualifiedName::new(None, None, String::from("Level-1")))
+            .expect("unable to create element");
+        b.push(l1.clone()).expect("unable to add node");
+        let mut l2 = a
+            .new_element(QualifiedName::new(None, None, String::from("Level-2")))
+            .expect("unable to create element");
+        l1.push(l2.clone()).expect("unable to add node");
+        l2.push(
+            a.new_text(Rc::new(Value::from(format!("key {}", (i * 7919) % n))))
+                .expect("unable to create text node"),
+        )
+        .expect("unable to add node");
+    });
+    a
+}
+
+// Sort every element by its string-value
+fn sort_by_value(sd: RNode) -> usize {
+    let (styledoc, stylens) = parse_with_ns(
+        Rc::new(SmiteNode::new()),
+        "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='/'><xsl:apply-templates/></xsl:template>
+  <xsl:template match='child::Test'><xsl:apply-templates><xsl:sort select='.'/></xsl:apply-templates></xsl:template>
+  <xsl:template match='child::Level-1'><L/></xsl:template>
+</xsl:stylesheet>",
+        None,
+    )
+    .expect("unable to parse stylesheet");
+    let mut stctxt = StaticContextBuilder::new()
+        .message(|_| Ok(()))
+        .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .build();
+    let mut ctxt = from_document(
+        styledoc,
+        stylens,
+        None,
+        |s| {
+            let d = Rc::new(SmiteNode::new());
+            xmlparse(d.clone(), s, None)?;
+            Ok(d)
+        },
+        |_| Ok(String::new()),
+    )
+    .expect("unable to compile stylesheet");
+    ctxt.context(vec![Item::Node(sd)], 0);
+    ctxt.result_document(Rc::new(SmiteNode::new()));
+    ctxt.evaluate(&mut stctxt).expect("evaluation failed").len()
+}
+
+fn sort(c: &mut Criterion) {
+    // A fresh tree has no cached string-values
+    c.bench_function("sort 10000 by string value, fresh tree", |b| {
+        b.iter_batched(|| make_keyed(10000), sort_by_value, BatchSize::LargeInput)
+    });
+    // Subsequent sorts of the same tree reuse the cached string-values
+    let sd = make_keyed(10000);
+    c.bench_function("sort 10000 by string value, cached", |b| {
+        b.iter(|| sort_by_value(black_box(sd.clone())))
+    });
+}
+
+criterion_group!(benches, rnode, templates, sort);
 criterion_main!(benches);
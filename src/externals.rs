@@ -1,7 +1,104 @@
 /*! Defines interfaces for closures and functions that are used to communicate with external processes.
 */
 
-use crate::xdmerror::Error;
+use regex::Regex;
+use std::fs;
+use std::path::PathBuf;
+use url::Url;
+
+use crate::item::{Item, Node, Sequence};
+use crate::qname::QualifiedName;
+use crate::xdmerror::{Error, ErrorKind};
 
 /// Resolves a URL, given as a base URI and a relative URL, and returns the content of the resource as a string.
 pub(crate) type URLResolver = fn(Option<String>, String) -> Result<String, Error>;
+
+/// A collection resolver, for fn:collection, that reads the files in a directory.
+/// It is not installed by default, since it gives the stylesheet access to the file system;
+/// the application installs it with [StaticContextBuilder::collection](crate::transform::context::StaticContextBuilder::collection).
+///
+/// The URI of a collection must be a file URL for a directory,
+/// optionally with a "select" query parameter that is a glob pattern for the names of the files, e.g. "file:///data/?select=*.xml".
+/// Each file, in order of their names, is read and then parsed using the given parser. There is no default collection.
+pub fn directory_collection<N: Node, P>(
+    mut parser: P,
+) -> impl FnMut(Option<&str>) -> Result<Sequence<N>, Error>
+where
+    P: FnMut(&str) -> Result<N, Error>,
+{
+    move |uri| {
+        directory_files(uri)?.iter().try_fold(vec![], |mut acc, f| {
+            let path = f
+                .to_file_path()
+                .map_err(|_| unavailable(format!("unable to read \"{}\"", f)))?;
+            let content = fs::read_to_string(&path)
+                .map_err(|e| unavailable(format!("unable to read \"{}\": {}", f, e)))?;
+            acc.push(Item::Node(parser(content.as_str())?));
+            Ok(acc)
+        })
+    }
+}
+
+/// A URI collection resolver, for fn:uri-collection, that gives the URLs of the files in a directory.
+/// The URI of a collection is the same as for [directory_collection]. It is not installed by default;
+/// the application installs it with [StaticContextBuilder::uri_collection](crate::transform::context::StaticContextBuilder::uri_collection).
+pub fn directory_uri_collection() -> impl FnMut(Option<&str>) -> Result<Vec<String>, Error> {
+    |uri| Ok(directory_files(uri)?.iter().map(Url::to_string).collect())
+}
+
+// The URLs of the files in a directory, identified by a file URL, in order of their names.
+// The "select" query parameter is a glob pattern that the file names must match.
+fn directory_files(uri: Option<&str>) -> Result<Vec<Url>, Error> {
+    let uri = uri.ok_or_else(|| unavailable(String::from("there is no default collection")))?;
+    let url = Url::parse(uri).map_err(|_| {
+        Error::new_with_code(
+            ErrorKind::TypeError,
+            format!("invalid collection URI \"{}\"", uri),
+            Some(QualifiedName::new(None, None, "FODC0004")),
+        )
+    })?;
+    let dir = url
+        .to_file_path()
+        .map_err(|_| unavailable(format!("unable to resolve collection \"{}\"", url)))?;
+    // The glob pattern is matched against the file name
+    let select = url
+        .query_pairs()
+        .find(|(k, _)| k == "select")
+        .map_or(String::from("*"), |(_, v)| v.to_string());
+    let re = Regex::new(
+        format!(
+            "^{}$",
+            regex::escape(select.as_str())
+                .replace(r"\*", ".*")
+                .replace(r"\?", ".")
+        )
+        .as_str(),
+    )
+    .map_err(|_| unavailable(format!("invalid select pattern \"{}\"", select)))?;
+    let mut files: Vec<PathBuf> = fs::read_dir(&dir)
+        .map_err(|e| unavailable(format!("unable to read collection \"{}\": {}", url, e)))?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| {
+            p.is_file()
+                && p.file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|n| re.is_match(n))
+        })
+        .collect();
+    files.sort();
+    files
+        .iter()
+        .map(|p| {
+            Url::from_file_path(p)
+                .map_err(|_| unavailable(format!("unable to read \"{}\"", p.display())))
+        })
+        .collect()
+}
+
+fn unavailable(msg: String) -> Error {
+    Error::new_with_code(
+        ErrorKind::DynamicAbsent,
+        msg,
+        Some(QualifiedName::new(None, None, "FODC0002")),
+    )
+}
@@ -8,8 +8,9 @@ An [Item] is a [Node], Function or atomic [Value].
 */
 
 use crate::item;
-use crate::output::OutputDefinition;
+use crate::output::{json_string, OutputDefinition};
 use crate::qname::QualifiedName;
+use crate::transform::Transform;
 use crate::value::{Operator, Value};
 use crate::xdmerror::{Error, ErrorKind};
 use crate::xmldecl::XMLDecl;
@@ -25,17 +26,35 @@ use std::rc::Rc;
 /// See [SequenceTrait] for methods.
 pub type Sequence<N> = Vec<Item<N>>;
 
+/// The default maximum number of characters of a value shown by [SequenceTrait::describe] and [Item::describe].
+pub const DESCRIBE_LENGTH: usize = 32;
+
 pub trait SequenceTrait<N: Node> {
+    /// Create an empty [Sequence]. Its effective boolean value is false and its string value is the empty string.
+    fn empty() -> Self
+    where
+        Self: Sized;
     /// Return the string value of the [Sequence].
     fn to_string(&self) -> String;
     /// Return a XML formatted representation of the [Sequence].
     fn to_xml(&self) -> String;
     /// Return a XML formatted representation of the [Sequence], controlled by the supplied output definition.
     fn to_xml_with_options(&self, od: &OutputDefinition) -> String;
+    /// Return a XML formatted representation of the [Sequence] as bytes, in the encoding of the supplied output definition.
+    fn to_xml_bytes(&self, od: &OutputDefinition) -> Result<Vec<u8>, Error> {
+        od.encode(self.to_xml_with_options(od).as_str())
+    }
     /// Return a JSON formatted representation of the [Sequence].
     fn to_json(&self) -> String;
+    /// Serialize the [Sequence] with the output method of the output definition:
+    /// xml (the default), html, xhtml, text, json or adaptive.
+    /// An XML declaration is not written.
+    fn serialize(&self, od: &OutputDefinition) -> Result<String, Error>;
     /// Return the Effective Boolean Value of the [Sequence].
     fn to_bool(&self) -> bool;
+    /// Return the Effective Boolean Value of the [Sequence], as defined by XPath 2.4.3.
+    /// Unlike [SequenceTrait::to_bool], this is an error (FORG0006) if the sequence does not have an effective boolean value.
+    fn effective_boolean_value(&self) -> Result<bool, Error>;
     /// Convert the [Sequence] to an integer. The [Sequence] must be a singleton value.
     fn to_int(&self) -> Result<i64, Error>;
     /// Push an [Node] to the [Sequence]
@@ -47,6 +66,9 @@ pub trait SequenceTrait<N: Node> {
 }
 
 impl<N: Node> SequenceTrait<N> for Sequence<N> {
+    fn empty() -> Self {
+        Vec::new()
+    }
     /// Returns the string value of the Sequence.
     fn to_string(&self) -> String {
         let mut r = String::new();
@@ -79,6 +101,55 @@ impl<N: Node> SequenceTrait<N> for Sequence<N> {
         }
         r
     }
+    /// Serializes the Sequence using the output method.
+    /// The adaptive method writes each item on its own line.
+    /// The json method requires at most one item (SERE0023); the empty sequence is null.
+    fn serialize(&self, od: &OutputDefinition) -> Result<String, Error> {
+        match od.get_method().as_deref() {
+            None | Some("xml") | Some("xhtml") | Some("html") => Ok(self.to_xml_with_options(od)),
+            Some("text") => Ok(self.to_string()),
+            Some("adaptive") => Ok(od.normalize(
+                self.iter()
+                    .map(|i| i.to_adaptive(od))
+                    .collect::<Vec<String>>()
+                    .join("\n"),
+            )),
+            Some("json") => match self.as_slice() {
+                [] => Ok(String::from("null")),
+                [i] => i.to_json_with_options(od).map(|j| od.normalize(j)),
+                _ => Err(Error::new_with_code(
+                    ErrorKind::TypeError,
+                    format!(
+                        "a sequence of {} items cannot be serialized as JSON",
+                        self.len()
+                    ),
+                    Some(QualifiedName::new(None, None, "SERE0023")),
+                )),
+            },
+            Some(m) => Err(Error::new_with_code(
+                ErrorKind::NotImplemented,
+                format!("output method \"{}\" is not supported", m),
+                Some(QualifiedName::new(None, None, "SEPM0016")),
+            )),
+        }
+    }
+    /// Describes each item in the Sequence, prefixed by its index
+    fn describe_with_length(&self, len: usize) -> String {
+        if self.is_empty() {
+            return String::from("empty sequence");
+        }
+        let mut r = String::new();
+        for (i, item) in self.iter().enumerate() {
+            if i > 0 {
+                r.push('\n');
+            }
+            r.push('[');
+            r.push_str(i.to_string().as_str());
+            r.push_str("] ");
+            item.describe_into(&mut r, len);
+        }
+        r
+    }
     /// Push a document's [Node] on to the [Sequence]. This clones the node.
     fn push_node(&mut self, n: &N) {
         self.push(Item::Node(n.clone()));
@@ -112,6 +183,23 @@ impl<N: Node> SequenceTrait<N> for Sequence<N> {
         }
     }
 
+    /// Calculate the effective boolean value of the Sequence.
+    /// A sequence of more than one item, where the first item is not a node, does not have an effective boolean value.
+    fn effective_boolean_value(&self) -> Result<bool, Error> {
+        match self.len() {
+            0 => Ok(false),
+            1 => self[0].effective_boolean_value(),
+            _ => match self[0] {
+                Item::Node(..) => Ok(true),
+                _ => Err(Error::new_with_code(
+                    ErrorKind::TypeError,
+                    "sequence of more than one item does not have an effective boolean value",
+                    Some(QualifiedName::new(None, None, "FORG0006")),
+                )),
+            },
+        }
+    }
+
     /// Convenience routine for integer value of the [Sequence]. The Sequence must be a singleton; i.e. be a single item.
     fn to_int(&self) -> Result<i64, Error> {
         if self.len() == 1 {
@@ -139,7 +227,7 @@ impl<N: Node> From<Item<N>> for Sequence<N> {
 /// All [Node]s have a type. The type of the [Node] determines what components are meaningful, such as name and content.
 ///
 /// Every document must have a single node as it's toplevel node that is of type "Document".
-#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
 pub enum NodeType {
     Document,
     Element,
@@ -178,14 +266,18 @@ impl fmt::Display for NodeType {
 
 /// An Item in a [Sequence]. Can be a node, function or [Value].
 ///
-/// Functions are not yet implemented.
+/// The behaviour of each kind of item is decided in the methods of this type.
+/// These use exhaustive matches, so that adding a new kind of item requires each behaviour to be decided.
 #[derive(Clone)]
 pub enum Item<N: Node> {
     /// A [Node] in the source document.
     Node(N),
 
-    /// Functions are not yet supported
-    Function,
+    /// A function item.
+    Function(Rc<Function<N>>),
+
+    /// A map item. A map is also a function item, so it behaves as one except where maps are defined differently.
+    Map(Rc<Map<N>>),
 
     /// A scalar value. These are in an Rc since they are frequently shared.
     Value(Rc<Value>),
@@ -196,7 +288,8 @@ impl<N: item::Node> fmt::Display for Item<N> {
         // Gives the string value of an item. All items have a string value.
         let result = match self {
             Item::Node(n) => n.to_string(),
-            Item::Function => "".to_string(),
+            // A function item does not have a string value, see [Item::string_value]
+            Item::Function(_) | Item::Map(_) => "".to_string(),
             Item::Value(v) => v.to_string(),
         };
         f.write_str(result.as_str())
@@ -208,7 +301,7 @@ impl<N: Node> Item<N> {
     pub fn to_xml(&self) -> String {
         match self {
             Item::Node(n) => n.to_xml(),
-            Item::Function => "".to_string(),
+            Item::Function(_) | Item::Map(_) => "".to_string(),
             Item::Value(v) => v.to_string(),
         }
     }
@@ -216,28 +309,133 @@ impl<N: Node> Item<N> {
     pub fn to_xml_with_options(&self, od: &OutputDefinition) -> String {
         match self {
             Item::Node(n) => n.to_xml_with_options(od),
-            Item::Function => "".to_string(),
-            Item::Value(v) => v.to_string(),
+            Item::Function(_) | Item::Map(_) => "".to_string(),
+            Item::Value(v) => od.normalize(v.to_string()),
         }
     }
     /// Serialize as JSON
     pub fn to_json(&self) -> String {
         match self {
             Item::Node(n) => n.to_json(),
-            Item::Function => "".to_string(),
+            Item::Function(_) | Item::Map(_) => "".to_string(),
             Item::Value(v) => v.to_string(),
         }
     }
+    /// Serialize with the adaptive output method. See Serialization 3.1, 10.
+    /// Nodes are serialized as XML, except that an attribute is written as name="value".
+    pub fn to_adaptive(&self, od: &OutputDefinition) -> String {
+        match self {
+            Item::Node(n) if n.node_type() == NodeType::Attribute => format!(
+                "{}=\"{}\"",
+                n.name(),
+                n.value().to_string().replace('"', "&quot;")
+            ),
+            Item::Node(n) => n.to_xml_with_options(od),
+            Item::Function(f) => format!(
+                "{}#{}",
+                f.name()
+                    .map_or(String::from("(anonymous-function)"), |n| n.to_string()),
+                f.arity()
+            ),
+            Item::Map(m) => format!(
+                "map{{{}}}",
+                m.iter()
+                    .map(|(k, v)| {
+                        let value: Vec<String> = v.iter().map(|i| i.to_adaptive(od)).collect();
+                        match value.len() {
+                            1 => format!("{}:{}", k.to_adaptive(), value[0]),
+                            _ => format!("{}:({})", k.to_adaptive(), value.join(",")),
+                        }
+                    })
+                    .collect::<Vec<String>>()
+                    .join(",")
+            ),
+            Item::Value(v) => v.to_adaptive(),
+        }
+    }
+    /// Serialize with the json output method. See Serialization 3.1, 11.
+    /// A node is serialized as XML, and the result is a JSON string.
+    /// A map is serialized as a JSON object, each of whose values must be a single item (SERE0023).
+    /// A function item cannot be serialized as JSON (SERE0021).
+    pub fn to_json_with_options(&self, od: &OutputDefinition) -> Result<String, Error> {
+        match self {
+            Item::Node(n) => Ok(json_string(n.to_xml_with_options(od).as_str())),
+            Item::Function(_) => Err(Error::new_with_code(
+                ErrorKind::TypeError,
+                "a function item cannot be serialized as JSON",
+                Some(QualifiedName::new(None, None, "SERE0021")),
+            )),
+            Item::Map(m) => Ok(format!(
+                "{{{}}}",
+                m.iter()
+                    .map(|(k, v)| match v.as_slice() {
+                        [i] => Ok(format!(
+                            "{}:{}",
+                            json_string(k.to_string().as_str()),
+                            i.to_json_with_options(od)?
+                        )),
+                        _ => Err(Error::new_with_code(
+                            ErrorKind::TypeError,
+                            "the value of a map entry must be a single item to be serialized as JSON",
+                            Some(QualifiedName::new(None, None, "SERE0023")),
+                        )),
+                    })
+                    .collect::<Result<Vec<String>, Error>>()?
+                    .join(",")
+            )),
+            Item::Value(v) => v.to_json_value(),
+        }
+    }
 
     /// Determine the effective boolean value of the item.
     /// See XPath 2.4.3.
+    /// A function item does not have an effective boolean value, so gives false. See [Item::effective_boolean_value].
     pub fn to_bool(&self) -> bool {
         match self {
             Item::Node(..) => true,
-            Item::Function => false,
+            Item::Function(_) | Item::Map(_) => false,
             Item::Value(v) => v.to_bool(),
         }
     }
+    /// Determine the effective boolean value of the item.
+    /// See XPath 2.4.3. A function item does not have an effective boolean value (FORG0006).
+    pub fn effective_boolean_value(&self) -> Result<bool, Error> {
+        match self {
+            Item::Node(..) => Ok(true),
+            Item::Function(_) | Item::Map(_) => Err(Error::new_with_code(
+                ErrorKind::TypeError,
+                "function item does not have an effective boolean value",
+                Some(QualifiedName::new(None, None, "FORG0006")),
+            )),
+            Item::Value(v) => Ok(v.to_bool()),
+        }
+    }
+    /// Gives the string value of the item.
+    /// A function item does not have a string value (FOTY0014).
+    pub fn string_value(&self) -> Result<String, Error> {
+        match self {
+            Item::Node(n) => Ok(n.to_string()),
+            Item::Function(_) | Item::Map(_) => Err(Error::new_with_code(
+                ErrorKind::TypeError,
+                "function item does not have a string value",
+                Some(QualifiedName::new(None, None, "FOTY0014")),
+            )),
+            Item::Value(v) => Ok(v.to_string()),
+        }
+    }
+    /// Atomize the item. See XPath 2.4.2.
+    /// The typed value of a node is its string value. A function item cannot be atomized (FOTY0013).
+    pub fn atomize(&self) -> Result<Rc<Value>, Error> {
+        match self {
+            Item::Node(n) => Ok(Rc::new(Value::from(n.to_string()))),
+            Item::Function(_) | Item::Map(_) => Err(Error::new_with_code(
+                ErrorKind::TypeError,
+                "function item cannot be atomized",
+                Some(QualifiedName::new(None, None, "FOTY0013")),
+            )),
+            Item::Value(v) => Ok(v.clone()),
+        }
+    }
 
     /// Gives the integer value of the item, if possible.
     pub fn to_int(&self) -> Result<i64, Error> {
@@ -246,9 +444,10 @@ impl<N: Node> Item<N> {
                 ErrorKind::TypeError,
                 String::from("type error: item is a node"),
             )),
-            Item::Function => Result::Err(Error::new(
+            Item::Function(_) | Item::Map(_) => Result::Err(Error::new_with_code(
                 ErrorKind::TypeError,
                 String::from("type error: item is a function"),
+                Some(QualifiedName::new(None, None, "FOTY0013")),
             )),
             Item::Value(v) => match v.to_int() {
                 Ok(i) => Ok(i),
@@ -261,34 +460,35 @@ impl<N: Node> Item<N> {
     pub fn to_double(&self) -> f64 {
         match self {
             Item::Node(..) => f64::NAN,
-            Item::Function => f64::NAN,
+            Item::Function(_) | Item::Map(_) => f64::NAN,
             Item::Value(v) => v.to_double(),
         }
     }
 
     /// Gives the name of the item. Certain types of Nodes have names, such as element-type nodes. If the item does not have a name returns an empty string.
+    /// A named function item has the name of the function.
     pub fn name(&self) -> QualifiedName {
         match self {
             Item::Node(n) => n.name(),
-            _ => QualifiedName::new(None, None, "".to_string()),
+            Item::Function(f) => f
+                .name()
+                .cloned()
+                .unwrap_or_else(|| QualifiedName::new(None, None, "".to_string())),
+            Item::Map(_) | Item::Value(_) => QualifiedName::new(None, None, "".to_string()),
         }
     }
 
-    // TODO: atomization
-    // fn atomize(&self);
-
-    /// Compare two items.
+    /// Compare two items. The items are atomized, so a function item cannot be compared (FOTY0013).
     pub fn compare(&self, other: &Item<N>, op: Operator) -> Result<bool, Error> {
-        match self {
-            Item::Value(v) => match other {
-                Item::Value(w) => v.compare(w, op),
-                Item::Node(..) => v.compare(&Value::String(other.to_string()), op),
-                _ => Result::Err(Error::new(ErrorKind::TypeError, String::from("type error"))),
-            },
-            Item::Node(..) => {
+        match (self, other) {
+            (Item::Value(v), Item::Value(w)) => v.compare(w, op),
+            (Item::Value(v), Item::Node(..)) => v.compare(&Value::String(other.to_string()), op),
+            (Item::Node(..), Item::Value(_) | Item::Node(..)) => {
                 other.compare(&Item::Value(Rc::new(Value::String(self.to_string()))), op)
             }
-            _ => Result::Err(Error::new(ErrorKind::TypeError, String::from("type error"))),
+            // Atomizing a function item is an error
+            (Item::Function(_) | Item::Map(_), _) => self.atomize().map(|_| false),
+            (_, Item::Function(_) | Item::Map(_)) => other.atomize().map(|_| false),
         }
     }
 
@@ -296,13 +496,7 @@ impl<N: Node> Item<N> {
     pub fn is_element_node(&self) -> bool {
         match self {
             Item::Node(n) => matches!(n.node_type(), NodeType::Element),
-            /*
-                match n.node_type() {
-                NodeType::Element => true,
-                _ => false,
-            },
-                 */
-            _ => false,
+            Item::Function(_) | Item::Map(_) | Item::Value(_) => false,
         }
     }
 
@@ -314,7 +508,7 @@ impl<N: Node> Item<N> {
                 NodeType::Element => n.add_attribute(a),
                 _ => Ok(()),
             },
-            _ => Ok(()),
+            Item::Function(_) | Item::Map(_) | Item::Value(_) => Ok(()),
         }
     }
 
@@ -322,20 +516,55 @@ impl<N: Node> Item<N> {
     pub fn item_type(&self) -> &'static str {
         match self {
             Item::Node(..) => "Node",
-            Item::Function => "Function",
+            Item::Function(_) => "Function",
+            Item::Map(_) => "Map",
             Item::Value(v) => v.value_type(),
         }
     }
+    /// Describe the item for debugging.
+    /// This is the kind of item, and its string value truncated to [DESCRIBE_LENGTH] characters.
+    /// For a node, the node type, name and path are also given, e.g. `Element Level1 /Test/Level1[2] "two"`.
+    pub fn describe(&self) -> String {
+        self.describe_with_length(DESCRIBE_LENGTH)
+    }
+    /// Describe the item for debugging, truncating its value to at most the given number of characters.
+    pub fn describe_with_length(&self, len: usize) -> String {
+        let mut r = String::new();
+        self.describe_into(&mut r, len);
+        r
+    }
+    fn describe_into(&self, r: &mut String, len: usize) {
+        match self {
+            Item::Node(n) => {
+                r.push_str(n.node_type().to_string());
+                let name = n.name().to_string();
+                if !name.is_empty() {
+                    r.push(' ');
+                    r.push_str(name.as_str());
+                }
+                r.push(' ');
+                r.push_str(n.path().as_str());
+                r.push(' ');
+                push_truncated(r, n.to_string().as_str(), len)
+            }
+            Item::Function(f) => r.push_str(format!("Function {:?}", f).as_str()),
+            Item::Map(m) => r.push_str(format!("Map of {} entries", m.len()).as_str()),
+            Item::Value(v) => {
+                r.push_str(v.value_type());
+                r.push(' ');
+                push_truncated(r, v.to_string().as_str(), len)
+            }
+        }
+    }
     /// Make a shallow copy of an item.
     /// That is, the item is duplicated but not it's content, including attributes.
     pub fn shallow_copy(&self) -> Result<Self, Error> {
         match self {
             Item::Value(v) => Ok(Item::Value(v.clone())),
             Item::Node(n) => Ok(Item::Node(n.shallow_copy()?)),
-            _ => Result::Err(Error::new(
-                ErrorKind::NotImplemented,
-                "not implemented".to_string(),
-            )),
+            // Function items and maps are immutable, so a copy is the same item
+            Item::Function(f) => Ok(Item::Function(f.clone())),
+            Item::Map(m) => Ok(Item::Map(m.clone())),
         }
     }
     /// Make a deep copy of an item.
@@ -343,14 +572,39 @@ impl<N: Node> Item<N> {
         match self {
             Item::Value(v) => Ok(Item::Value(v.clone())),
             Item::Node(n) => Ok(Item::Node(n.deep_copy()?)),
-            _ => Result::Err(Error::new(
-                ErrorKind::NotImplemented,
-                "not implemented".to_string(),
-            )),
+            Item::Function(f) => Ok(Item::Function(f.clone())),
+            Item::Map(m) => Ok(Item::Map(m.clone())),
+        }
+    }
+}
+
+// Append a quoted string, truncated to the given number of characters
+fn push_truncated(r: &mut String, s: &str, len: usize) {
+    r.push('"');
+    match s.char_indices().nth(len) {
+        Some((i, _)) => {
+            r.push_str(&s[..i]);
+            r.push_str("\"...")
+        }
+        None => {
+            r.push_str(s);
+            r.push('"')
         }
     }
 }
 
+/// The error for a sequence that should have exactly one item, describing the sequence.
+pub(crate) fn not_singleton<N: Node>(s: &Sequence<N>) -> Error {
+    Error::new(
+        ErrorKind::TypeError,
+        format!(
+            "not a singleton sequence: got a sequence of {} items\n{}",
+            s.len(),
+            s.describe()
+        ),
+    )
+}
+
 impl<N: Node> fmt::Debug for Item<N> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -363,8 +617,11 @@ impl<N: Node> fmt::Debug for Item<N> {
                       //                    n.name()
                 )
             }
-            Item::Function => {
-                write!(f, "function type item")
+            Item::Function(func) => {
+                write!(f, "function type item ({:?})", func)
+            }
+            Item::Map(m) => {
+                write!(f, "map type item ({} entries)", m.len())
             }
             Item::Value(v) => {
                 write!(f, "value type item ({})", v)
@@ -373,6 +630,145 @@ impl<N: Node> fmt::Debug for Item<N> {
     }
 }
 
+/// A function item. See XPath 2.8.4.
+///
+/// A function has positional parameters and a body that is evaluated when the function is called.
+/// Named functions, such as those created by a named function reference, have a name.
+#[derive(Clone)]
+pub struct Function<N: Node> {
+    name: Option<QualifiedName>,
+    parameters: Vec<QualifiedName>,
+    body: Transform<N>,
+}
+
+impl<N: Node> Function<N> {
+    /// Create a function with the given formal parameters and body.
+    pub fn new(
+        name: Option<QualifiedName>,
+        parameters: Vec<QualifiedName>,
+        body: Transform<N>,
+    ) -> Self {
+        Function {
+            name,
+            parameters,
+            body,
+        }
+    }
+    /// The name of the function. Anonymous functions do not have a name.
+    pub fn name(&self) -> Option<&QualifiedName> {
+        self.name.as_ref()
+    }
+    /// The number of parameters of the function.
+    pub fn arity(&self) -> usize {
+        self.parameters.len()
+    }
+    /// The formal parameters of the function.
+    pub fn parameters(&self) -> &Vec<QualifiedName> {
+        &self.parameters
+    }
+    /// The body of the function.
+    pub fn body(&self) -> &Transform<N> {
+        &self.body
+    }
+}
+
+impl<N: Node> fmt::Debug for Function<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(qn) => write!(f, "function {}#{}", qn, self.arity()),
+            None => write!(f, "anonymous function#{}", self.arity()),
+        }
+    }
+}
+
+/// A map item. See XPath 3.1, 2.8.5.
+///
+/// A map associates atomic keys with values, which are sequences.
+/// Two keys are the same if they have the same type and the same string value.
+/// The entries are kept in the order in which they were inserted.
+#[derive(Clone)]
+pub struct Map<N: Node> {
+    entries: Vec<(Rc<Value>, Sequence<N>)>,
+}
+
+impl<N: Node> Map<N> {
+    /// Create an empty map.
+    pub fn new() -> Self {
+        Map { entries: vec![] }
+    }
+    /// Add an entry. If the map already has an entry with the same key, it is replaced.
+    pub fn insert(&mut self, k: Rc<Value>, v: Sequence<N>) {
+        match self.entries.iter_mut().find(|(e, _)| same_key(e, &k)) {
+            Some(e) => e.1 = v,
+            None => self.entries.push((k, v)),
+        }
+    }
+    /// The value of the entry with the given key, if there is one.
+    pub fn get(&self, k: &Value) -> Option<&Sequence<N>> {
+        self.entries
+            .iter()
+            .find(|(e, _)| same_key(e, k))
+            .map(|(_, v)| v)
+    }
+    /// Does the map have an entry with the given key?
+    pub fn contains_key(&self, k: &Value) -> bool {
+        self.get(k).is_some()
+    }
+    /// The number of entries in the map.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    /// Does the map have no entries?
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    /// An iterator over the keys and values of the entries.
+    pub fn iter(&self) -> impl Iterator<Item = (&Rc<Value>, &Sequence<N>)> {
+        self.entries.iter().map(|(k, v)| (k, v))
+    }
+}
+
+impl<N: Node> Default for Map<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn same_key(k: &Value, l: &Value) -> bool {
+    k.value_type() == l.value_type() && k.to_string() == l.to_string()
+}
+
+// A step in the path of a node. See [Node::path].
+fn path_step<N: Node>(n: &N) -> String {
+    let test = match n.node_type() {
+        NodeType::Attribute => return format!("@{}", n.name()),
+        NodeType::Namespace => return format!("namespace::{}", n.name()),
+        NodeType::Text => String::from("text()"),
+        NodeType::Comment => String::from("comment()"),
+        NodeType::ProcessingInstruction => format!("processing-instruction({})", n.name()),
+        NodeType::Element => n.name().to_string(),
+        NodeType::Document | NodeType::Reference | NodeType::Unknown => return String::new(),
+    };
+    match n.parent() {
+        Some(p) => {
+            let similar: Vec<N> = p
+                .child_iter()
+                .filter(|c| c.node_type() == n.node_type() && c.name() == n.name())
+                .collect();
+            if similar.len() > 1 {
+                let pos = similar
+                    .iter()
+                    .position(|c| c.is_same(n))
+                    .map_or(0, |p| p + 1);
+                format!("{}[{}]", test, pos)
+            } else {
+                test
+            }
+        }
+        None => test,
+    }
+}
+
 /// Nodes make up a document tree. Nodes must be fully navigable. The tree must be mutable but also stable (i.e. removing a node from the tree does not invalidate the remaining nodes).
 ///
 /// Some nodes have names, such as elements. Some nodes have values, such as text or comments. Some have both a name and a value, such as attributes and processing instructions.
@@ -393,14 +789,29 @@ pub trait Node: Clone + PartialEq + fmt::Debug {
     fn value(&self) -> Rc<Value>;
 
     /// Get a unique identifier for this node.
+    /// The identifier depends only on the identity of the node, so a node has the same identifier however it is reached.
+    /// It consists of ASCII letters and digits, and starts with a letter, as required for the generate-id function.
     fn get_id(&self) -> String;
 
+    /// The line and column, both starting at 1, where the node begins in the text it was parsed from.
+    /// Nodes that were not created by a parser, and trees that do not record positions, have no location.
+    fn location(&self) -> Option<(usize, usize)> {
+        None
+    }
+    /// Record where the node begins in the text it was parsed from.
+    /// Trees that do not record positions ignore this.
+    fn set_location(&self, _loc: (usize, usize)) {}
+
     /// Get the string value of the node. See XPath ???
     fn to_string(&self) -> String;
     /// Serialise the node as XML
     fn to_xml(&self) -> String;
     /// Serialise the node as XML, with options such as indentation.
     fn to_xml_with_options(&self, od: &OutputDefinition) -> String;
+    /// Serialise the node as XML bytes, in the encoding of the output definition.
+    fn to_xml_bytes(&self, od: &OutputDefinition) -> Result<Vec<u8>, Error> {
+        od.encode(self.to_xml_with_options(od).as_str())
+    }
     /// Serialise the node as JSON
     fn to_json(&self) -> String {
         String::new()
@@ -416,6 +827,40 @@ pub trait Node: Clone + PartialEq + fmt::Debug {
     /// Compare the document order of this node with another node in the same document.
     fn cmp_document_order(&self, other: &Self) -> Ordering;
 
+    /// A simple XPath expression that locates the node in its document, such as "/Test/Level1[2]".
+    /// A positional predicate is added to a step when the node has siblings of the same kind and name.
+    fn path(&self) -> String {
+        let mut steps: Vec<String> = Some(self.clone())
+            .into_iter()
+            .chain(self.ancestor_iter())
+            .filter(|n| n.node_type() != NodeType::Document)
+            .map(|n| path_step(&n))
+            .collect();
+        steps.reverse();
+        format!("/{}", steps.join("/"))
+    }
+
+    /// The length, in characters, of the string value of the node.
+    /// This is the same as the length of [Node::to_string], but the string value is not constructed.
+    fn text_content_len(&self) -> usize {
+        let len = |n: &Self| match &*n.value() {
+            Value::String(s) => s.chars().count(),
+            v => v.to_string().chars().count(),
+        };
+        match self.node_type() {
+            NodeType::Document | NodeType::Element => self
+                .descend_iter()
+                .filter(|d| d.node_type() == NodeType::Text)
+                .map(|d| len(&d))
+                .sum(),
+            NodeType::Text
+            | NodeType::Attribute
+            | NodeType::Comment
+            | NodeType::ProcessingInstruction => len(self),
+            _ => self.to_string().chars().count(),
+        }
+    }
+
     /// Check if a node is an element-type
     fn is_element(&self) -> bool {
         self.node_type() == NodeType::Element
@@ -449,6 +894,8 @@ pub trait Node: Clone + PartialEq + fmt::Debug {
     fn prev_iter(&self) -> Self::NodeIterator;
     /// An iterator over the attributes of an element
     fn attribute_iter(&self) -> Self::NodeIterator;
+    /// An iterator over the namespace nodes of an element. Other types of node have no namespace nodes.
+    fn namespace_iter(&self) -> Self::NodeIterator;
     /// Get an attribute of the node. Returns a copy of the attribute's value. If the node does not have an attribute of the given name, a value containing an empty string is returned.
     fn get_attribute(&self, a: &QualifiedName) -> Rc<Value>;
     /// Get an attribute of the node. If the node is not an element returns None. Otherwise returns the attribute node. If the node does not have an attribute of the given name, returns None.
@@ -2,13 +2,28 @@
 */
 
 use crate::qname::QualifiedName;
+use crate::xdmerror::{Error, ErrorKind};
 use core::fmt;
+use std::collections::HashMap;
+use unicode_normalization::UnicodeNormalization;
 
 /// An output definition. See XSLT v3.0 26 Serialization
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct OutputDefinition {
     name: Option<QualifiedName>, // TODO: EQName
     indent: bool,
+    indent_string: String,
+    method: Option<String>,
+    encoding: Option<String>,
+    normalization_form: Option<String>,
+    byte_order_mark: bool,
+    regenerate_prefixes: bool,
+    preferred_prefixes: HashMap<String, String>,
+    omit_xml_declaration: bool,
+    cdata_section_elements: Vec<QualifiedName>,
+    character_map: HashMap<char, String>,
+    doctype_public: Option<String>,
+    doctype_system: Option<String>,
     // TODO: all the other myriad output parameters
 }
 
@@ -23,6 +38,18 @@ impl OutputDefinition {
         OutputDefinition {
             name: None,
             indent: false,
+            indent_string: String::from("  "),
+            method: None,
+            encoding: None,
+            normalization_form: None,
+            byte_order_mark: false,
+            regenerate_prefixes: false,
+            preferred_prefixes: HashMap::new(),
+            omit_xml_declaration: true,
+            cdata_section_elements: vec![],
+            character_map: HashMap::new(),
+            doctype_public: None,
+            doctype_system: None,
         }
     }
     pub fn get_name(&self) -> Option<QualifiedName> {
@@ -44,7 +71,277 @@ impl OutputDefinition {
     pub fn set_indent(&mut self, ind: bool) {
         self.indent = ind;
     }
+    /// The spacing for each level of indentation. The default is two spaces.
+    pub fn get_indent_string(&self) -> &str {
+        self.indent_string.as_str()
+    }
+    pub fn get_method(&self) -> Option<String> {
+        self.method.clone()
+    }
+    pub fn set_method(&mut self, method: Option<String>) {
+        self.method = method;
+    }
+    pub fn get_encoding(&self) -> Option<String> {
+        self.encoding.clone()
+    }
+    pub fn set_encoding(&mut self, encoding: Option<String>) {
+        self.encoding = encoding;
+    }
+    pub fn get_normalization_form(&self) -> Option<String> {
+        self.normalization_form.clone()
+    }
+    /// Set the Unicode normalization form that is applied to the serialized output.
+    /// The supported forms are "NFC", "NFD", "NFKC" and "NFKD". "none" means no normalization.
+    pub fn set_normalization_form(&mut self, nf: Option<String>) -> Result<(), Error> {
+        match nf.as_deref().map(str::trim) {
+            None | Some("none") => {
+                self.normalization_form = None;
+                Ok(())
+            }
+            Some(f @ ("NFC" | "NFD" | "NFKC" | "NFKD")) => {
+                self.normalization_form = Some(f.to_string());
+                Ok(())
+            }
+            Some(f) => Err(Error::new_with_code(
+                ErrorKind::NotImplemented,
+                format!("normalization form \"{}\" is not supported", f),
+                Some(QualifiedName::new(None, None, "SESU0011")),
+            )),
+        }
+    }
+    pub fn get_byte_order_mark(&self) -> bool {
+        self.byte_order_mark
+    }
+    pub fn set_byte_order_mark(&mut self, bom: bool) {
+        self.byte_order_mark = bom;
+    }
+    pub fn get_regenerate_prefixes(&self) -> bool {
+        self.regenerate_prefixes
+    }
+    /// By default, the prefixes of the source and stylesheet are preserved in the serialized output,
+    /// redeclaring a prefix if it is bound to a different namespace URI by an ancestor.
+    /// If regenerate is set, a new "nsN" prefix is invented for a name whose prefix collides instead.
+    pub fn set_regenerate_prefixes(&mut self, regenerate: bool) {
+        self.regenerate_prefixes = regenerate;
+    }
+    pub fn get_preferred_prefix(&self, uri: &str) -> Option<&str> {
+        self.preferred_prefixes.get(uri).map(|p| p.as_str())
+    }
+    /// The prefix to use for a namespace when the serializer has to choose one,
+    /// i.e. for a name that does not have a prefix of its own, or whose prefix cannot be used.
+    /// The preferred prefix is only used if it is not already bound to a different namespace;
+    /// otherwise a new "nsN" prefix is invented.
+    pub fn set_preferred_prefix(&mut self, uri: &str, prefix: &str) {
+        self.preferred_prefixes
+            .insert(uri.to_string(), prefix.to_string());
+    }
+    pub fn get_omit_xml_declaration(&self) -> bool {
+        self.omit_xml_declaration
+    }
+    /// Serializing a tree does not write an XML declaration, so by default the declaration is omitted.
+    /// If omit is unset, then the serialize function writes a declaration before XML output.
+    pub fn set_omit_xml_declaration(&mut self, omit: bool) {
+        self.omit_xml_declaration = omit;
+    }
+    /// The elements whose text content is to be output as CDATA sections.
+    /// These are recorded in the definition, but serializers do not yet write CDATA sections.
+    pub fn get_cdata_section_elements(&self) -> &[QualifiedName] {
+        &self.cdata_section_elements
+    }
+    /// The string that is output in place of a character, if the character is mapped.
+    pub fn get_character_map(&self, c: char) -> Option<&str> {
+        self.character_map.get(&c).map(String::as_str)
+    }
+    /// Apply the character map to serialized text.
+    pub fn map_characters(&self, s: &str) -> String {
+        if self.character_map.is_empty() {
+            s.to_string()
+        } else {
+            s.chars()
+                .fold(String::with_capacity(s.len()), |mut acc, c| {
+                    match self.character_map.get(&c) {
+                        Some(m) => acc.push_str(m),
+                        None => acc.push(c),
+                    }
+                    acc
+                })
+        }
+    }
+    pub fn get_doctype_public(&self) -> Option<String> {
+        self.doctype_public.clone()
+    }
+    pub fn get_doctype_system(&self) -> Option<String> {
+        self.doctype_system.clone()
+    }
+    /// The document type declaration for a document whose document element has the given name.
+    /// There is a declaration only if the doctype-system parameter is set.
+    pub fn doctype_declaration(&self, name: &str) -> Option<String> {
+        self.doctype_system
+            .as_ref()
+            .map(|s| match &self.doctype_public {
+                Some(p) => format!("<!DOCTYPE {} PUBLIC \"{}\" \"{}\">", name, p, s),
+                None => format!("<!DOCTYPE {} SYSTEM \"{}\">", name, s),
+            })
+    }
+    /// Encode serialized output as bytes, using the encoding of the output definition.
+    /// The supported encodings are UTF-8 (the default), UTF-16 (big-endian), UTF-16BE and UTF-16LE.
+    /// If the byte-order-mark parameter is set, the output is preceded by a byte order mark.
+    pub fn encode(&self, s: &str) -> Result<Vec<u8>, Error> {
+        let encoding = self
+            .encoding
+            .as_deref()
+            .map_or(String::from("UTF-8"), |e| e.trim().to_uppercase());
+        let utf16 = |to_bytes: fn(u16) -> [u8; 2]| {
+            let bom = self.byte_order_mark.then_some(0xFEFF);
+            bom.into_iter()
+                .chain(s.encode_utf16())
+                .flat_map(to_bytes)
+                .collect()
+        };
+        match encoding.as_str() {
+            "UTF-8" | "UTF8" => {
+                let mut result = vec![];
+                if self.byte_order_mark {
+                    result.extend_from_slice(&[0xEF, 0xBB, 0xBF])
+                }
+                result.extend_from_slice(s.as_bytes());
+                Ok(result)
+            }
+            "UTF-16" | "UTF-16BE" => Ok(utf16(u16::to_be_bytes)),
+            "UTF-16LE" => Ok(utf16(u16::to_le_bytes)),
+            e => Err(Error::new_with_code(
+                ErrorKind::NotImplemented,
+                format!("encoding \"{}\" is not supported", e),
+                Some(QualifiedName::new(None, None, "SESU0007")),
+            )),
+        }
+    }
+    /// Apply the normalization form to serialized output.
+    pub fn normalize(&self, s: String) -> String {
+        match self.normalization_form.as_deref() {
+            Some("NFC") => s.nfc().collect(),
+            Some("NFD") => s.nfd().collect(),
+            Some("NFKC") => s.nfkc().collect(),
+            Some("NFKD") => s.nfkd().collect(),
+            _ => s,
+        }
+    }
+}
+/// Builder for an [OutputDefinition].
+/// Setters for parameters that have a restricted set of values return an error if the value is not valid (SEPM0016).
+///
+/// ```rust
+/// use xrust::output::OutputDefinitionBuilder;
+///
+/// let od = OutputDefinitionBuilder::new()
+///     .method("xml")
+///     .expect("invalid method")
+///     .indent(true)
+///     .indent_string("\t")
+///     .expect("invalid indent string")
+///     .doctype_system("example.dtd")
+///     .build();
+/// assert!(od.get_indent());
+/// assert_eq!(od.get_indent_string(), "\t");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct OutputDefinitionBuilder(OutputDefinition);
+
+impl OutputDefinitionBuilder {
+    pub fn new() -> Self {
+        OutputDefinitionBuilder(OutputDefinition::new())
+    }
+    pub fn name(mut self, name: QualifiedName) -> Self {
+        self.0.name = Some(name);
+        self
+    }
+    pub fn indent(mut self, ind: bool) -> Self {
+        self.0.indent = ind;
+        self
+    }
+    /// The spacing for each level of indentation. This must be whitespace.
+    pub fn indent_string(mut self, s: &str) -> Result<Self, Error> {
+        if s.chars().all(|c| matches!(c, ' ' | '\t' | '\n' | '\r')) {
+            self.0.indent_string = s.to_string();
+            Ok(self)
+        } else {
+            Err(invalid_parameter("indent-string", s))
+        }
+    }
+    /// The serialization method. This must be one of xml, html, text, json or adaptive.
+    pub fn method(mut self, m: &str) -> Result<Self, Error> {
+        match m.trim() {
+            v @ ("xml" | "html" | "text" | "json" | "adaptive") => {
+                self.0.method = Some(v.to_string());
+                Ok(self)
+            }
+            _ => Err(invalid_parameter("method", m)),
+        }
+    }
+    pub fn encoding(mut self, e: &str) -> Self {
+        self.0.encoding = Some(e.to_string());
+        self
+    }
+    /// See [OutputDefinition::set_normalization_form].
+    pub fn normalization_form(mut self, nf: &str) -> Result<Self, Error> {
+        self.0.set_normalization_form(Some(nf.to_string()))?;
+        Ok(self)
+    }
+    pub fn byte_order_mark(mut self, bom: bool) -> Self {
+        self.0.byte_order_mark = bom;
+        self
+    }
+    pub fn regenerate_prefixes(mut self, regenerate: bool) -> Self {
+        self.0.regenerate_prefixes = regenerate;
+        self
+    }
+    pub fn preferred_prefix(mut self, uri: &str, prefix: &str) -> Self {
+        self.0.set_preferred_prefix(uri, prefix);
+        self
+    }
+    pub fn omit_xml_declaration(mut self, omit: bool) -> Self {
+        self.0.omit_xml_declaration = omit;
+        self
+    }
+    /// Add an element whose text content is to be output as CDATA sections.
+    pub fn cdata_section_element(mut self, qn: QualifiedName) -> Self {
+        if !self.0.cdata_section_elements.contains(&qn) {
+            self.0.cdata_section_elements.push(qn)
+        }
+        self
+    }
+    /// Map a character to a string that replaces it in the serialized output.
+    pub fn character_map(mut self, c: char, s: &str) -> Self {
+        self.0.character_map.insert(c, s.to_string());
+        self
+    }
+    pub fn doctype_public(mut self, p: &str) -> Self {
+        self.0.doctype_public = Some(p.to_string());
+        self
+    }
+    pub fn doctype_system(mut self, s: &str) -> Self {
+        self.0.doctype_system = Some(s.to_string());
+        self
+    }
+    pub fn build(self) -> OutputDefinition {
+        self.0
+    }
+}
+
+impl From<OutputDefinition> for OutputDefinitionBuilder {
+    fn from(od: OutputDefinition) -> Self {
+        OutputDefinitionBuilder(od)
+    }
+}
+
+fn invalid_parameter(param: &str, value: &str) -> Error {
+    Error::new_with_code(
+        ErrorKind::TypeError,
+        format!("\"{}\" is not a valid value for {}", value, param),
+        Some(QualifiedName::new(None, None, "SEPM0016")),
+    )
 }
+
 impl fmt::Display for OutputDefinition {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         if self.indent {
@@ -54,3 +351,38 @@ impl fmt::Display for OutputDefinition {
         }
     }
 }
+
+/// A string as a JSON string literal. See Serialization 3.1, 11.
+/// Quotation marks, backslashes and control characters are escaped, and so is the solidus.
+pub fn json_string(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+    result.push('"');
+    s.chars().for_each(|c| match c {
+        '"' => result.push_str("\\\""),
+        '\\' => result.push_str("\\\\"),
+        '/' => result.push_str("\\/"),
+        '\n' => result.push_str("\\n"),
+        '\r' => result.push_str("\\r"),
+        '\t' => result.push_str("\\t"),
+        '\u{8}' => result.push_str("\\b"),
+        '\u{c}' => result.push_str("\\f"),
+        c if c.is_control() => result.push_str(format!("\\u{:04X}", c as u32).as_str()),
+        c => result.push(c),
+    });
+    result.push('"');
+    result
+}
+
+/// Make a string suitable as the content of a comment.
+/// A comment must not contain "--" or end with "-", so a space is inserted after any hyphen that is followed by another hyphen or that ends the string. See XSLT v3.0 11.7.
+pub fn comment_content(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut it = s.chars().peekable();
+    while let Some(c) = it.next() {
+        result.push(c);
+        if c == '-' && it.peek().is_none_or(|n| *n == '-') {
+            result.push(' ')
+        }
+    }
+    result
+}
@@ -19,7 +19,7 @@ use crate::transform::Transform;
 
 /// AVT ::= text* "{" xpath "}" text*
 pub fn parse<N: Node>(input: &str) -> Result<Transform<N>, Error> {
-    let state = ParserState::new(None, None);
+    let state = ParserState::default();
     match avt_expr((input, state)) {
         Ok((_, x)) => Ok(x),
         Err(err) => match err {
@@ -95,7 +95,7 @@ fn braced_expr<'a, N: Node + 'a>(
         Some("{") => match input.find('}') {
             None => Err(ParseError::Combinator),
             Some(ind) => match expr()((input.get(1..ind).unwrap(), state.clone())) {
-                Ok((_, result)) => Ok(((input.get(ind..).map_or("", |r| r), state), result)),
+                Ok((_, result)) => Ok(((input.get(ind + 1..).map_or("", |r| r), state), result)),
                 Err(e) => Err(e),
             },
         },
@@ -5,13 +5,10 @@ pub fn tag<N: Node>(
     expected: &str,
 ) -> impl Fn(ParseInput<N>) -> Result<(ParseInput<N>, ()), ParseError> + '_ {
     move |(input, state)| match input.get(0..expected.len()) {
-        None => Err(ParseError::Combinator),
-        Some(chars) => {
-            if chars == expected {
-                Ok(((&input[expected.len()..], state), ()))
-            } else {
-                Err(ParseError::Combinator)
-            }
+        Some(chars) if chars == expected => Ok(((&input[expected.len()..], state), ())),
+        _ => {
+            state.expect(input, expected);
+            Err(ParseError::Combinator)
         }
     }
 }
@@ -55,6 +52,7 @@ pub(crate) fn anychar<N: Node>(
         if input.starts_with(expected) {
             Ok(((&input[1..], state), ()))
         } else {
+            state.expect(input, expected.encode_utf8(&mut [0; 4]));
             Err(ParseError::Combinator)
         }
     }
@@ -5,11 +5,13 @@ This parser combinator passes a context into the function, which includes the st
 */
 
 use crate::externals::URLResolver;
-use crate::item::Node;
+use crate::item::{Node, NodeType};
 use crate::xdmerror::{Error, ErrorKind};
 use crate::xmldecl::DTD;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::fmt;
+use std::rc::Rc;
 
 pub(crate) mod avt;
 pub mod combinators;
@@ -46,6 +48,84 @@ pub enum ParseError {
     ExtDTDLoadError,
 }
 
+/// The configuration of the XML parser, for use with [xml::parse_with_options].
+/// Options are set using the builder methods, for example:
+///
+/// ```rust
+/// use xrust::parser::ParseOptions;
+/// let options = ParseOptions::new()
+///     .entity_depth(4)
+///     .docloc("file:///tmp/")
+///     .strip_comments(true);
+/// ```
+#[derive(Clone)]
+pub struct ParseOptions {
+    ext_dtd_resolver: Option<URLResolver>,
+    docloc: Option<String>,
+    entitydepth: usize,
+    strip_comments: bool,
+    strip_pis: bool,
+    locations: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl ParseOptions {
+    /// The default options: no external resolver, no document location,
+    /// entity expansion limited to 8 levels, comments and processing instructions are kept,
+    /// and the locations of elements are not recorded.
+    pub fn new() -> Self {
+        ParseOptions {
+            ext_dtd_resolver: None,
+            docloc: None,
+            entitydepth: 8,
+            strip_comments: false,
+            strip_pis: false,
+            locations: false,
+        }
+    }
+    /// If you need to resolve external DTDs and entities, you will need to provide your own resolver.
+    pub fn resolver(mut self, r: URLResolver) -> Self {
+        self.ext_dtd_resolver = Some(r);
+        self
+    }
+    /// The location of the string being parsed, which is provided to the resolver to work out relative URLs.
+    pub fn docloc(mut self, l: impl Into<String>) -> Self {
+        self.docloc = Some(l.into());
+        self
+    }
+    /// How many recursive layers of entity expansion are allowed.
+    pub fn entity_depth(mut self, d: usize) -> Self {
+        self.entitydepth = d;
+        self
+    }
+    /// Whether comments are dropped, rather than being added to the tree.
+    /// Text on either side of a dropped comment becomes a single text node.
+    pub fn strip_comments(mut self, b: bool) -> Self {
+        self.strip_comments = b;
+        self
+    }
+    /// Whether processing instructions are dropped, rather than being added to the tree.
+    /// Text on either side of a dropped processing instruction becomes a single text node.
+    pub fn strip_processing_instructions(mut self, b: bool) -> Self {
+        self.strip_pis = b;
+        self
+    }
+    /// Whether to record the line and column at which each element begins, see [Node::location].
+    /// This is intended for stylesheets, so that errors and messages can report where an instruction occurs.
+    pub fn locations(mut self, b: bool) -> Self {
+        self.locations = b;
+        self
+    }
+    pub(crate) fn records_locations(&self) -> bool {
+        self.locations
+    }
+}
+
+/// The previous form of parser configuration. Use [ParseOptions] instead.
 pub struct ParserConfig {
     /// If you need to resolve external DTDs, you will need to provide your own resolver.
     pub ext_dtd_resolver: Option<URLResolver>,
@@ -76,6 +156,19 @@ impl ParserConfig {
     }
 }
 
+impl From<ParserConfig> for ParseOptions {
+    fn from(pc: ParserConfig) -> Self {
+        ParseOptions {
+            ext_dtd_resolver: pc.ext_dtd_resolver,
+            docloc: pc.docloc,
+            entitydepth: pc.entitydepth,
+            strip_comments: false,
+            strip_pis: false,
+            locations: false,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct ParserState<N: Node> {
     doc: Option<N>,
@@ -113,15 +206,68 @@ pub struct ParserState<N: Node> {
     so we need to track when we are currently in the main document or outside it.
      */
     currentlyexternal: bool,
+    /* Comments and processing instructions that are not to be added to the tree */
+    strip_comments: bool,
+    strip_pis: bool,
+    /* The text being parsed, for recording where elements occur. Only kept when locations are recorded. */
+    source: Option<Rc<SourceText>>,
+    /* The furthest point at which a token was expected but not found, as the length of the remaining input,
+    and the tokens that were expected there. This is shared by all copies of the state, so that it survives backtracking. */
+    expected: Option<Rc<RefCell<(usize, Vec<String>)>>>,
+}
+
+/// The text being parsed, with the offset of the start of each line.
+/// Locations are mostly found in increasing order, so the last one is kept,
+/// and the column of the next one on the same line is counted on from there.
+struct SourceText {
+    len: usize,
+    lines: Vec<usize>,
+    // The text is only needed to count characters, so it is not kept when it is ASCII
+    text: Option<String>,
+    // The offset, line and column of the last location that was found
+    last: Cell<(usize, usize, usize)>,
+}
+
+impl SourceText {
+    fn new(text: &str) -> Self {
+        SourceText {
+            len: text.len(),
+            lines: std::iter::once(0)
+                .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+                .collect(),
+            text: (!text.is_ascii()).then(|| text.to_string()),
+            last: Cell::new((0, 1, 1)),
+        }
+    }
+    fn location(&self, input: &str) -> Option<(usize, usize)> {
+        let offset = self.len.checked_sub(input.len())?;
+        let line = self.lines.partition_point(|l| *l <= offset);
+        let start = self.lines[line - 1];
+        let column = match &self.text {
+            None => offset - start + 1,
+            Some(text) => {
+                let (last_offset, last_line, last_column) = self.last.get();
+                if last_line == line && last_offset <= offset {
+                    last_column + text.get(last_offset..offset)?.chars().count()
+                } else {
+                    text.get(start..offset)?.chars().count() + 1
+                }
+            }
+        };
+        self.last.set((offset, line, column));
+        Some((line, column))
+    }
 }
 
 impl<N: Node> ParserState<N> {
     pub fn new(doc: Option<N>, parser_config: Option<ParserConfig>) -> Self {
-        let pc = if let Some(..) = parser_config {
-            parser_config.unwrap()
-        } else {
-            ParserConfig::new()
-        };
+        Self::with_options(
+            doc,
+            parser_config.map_or_else(ParseOptions::new, ParseOptions::from),
+        )
+    }
+    /// Create the parser state from the parser options.
+    pub(crate) fn with_options(doc: Option<N>, pc: ParseOptions) -> Self {
         ParserState {
             doc,
             dtd: DTD::new(),
@@ -139,8 +285,42 @@ impl<N: Node> ParserState<N> {
             ext_entities_to_parse: vec![],
             docloc: pc.docloc,
             currentlyexternal: false,
+            strip_comments: pc.strip_comments,
+            strip_pis: pc.strip_pis,
+            source: None,
+            expected: None,
         }
     }
+    /// Keep the text that is being parsed, so that the location of elements can be recorded.
+    pub(crate) fn set_source(&mut self, text: &str) {
+        self.source = Some(Rc::new(SourceText::new(text)))
+    }
+    /// The line and column, both starting at 1, of the remaining input within the text being parsed.
+    pub(crate) fn location(&self, input: &str) -> Option<(usize, usize)> {
+        self.source.as_deref()?.location(input)
+    }
+    /// Keep track of the tokens that are expected, so that a syntax error can report where it occurred.
+    pub(crate) fn track_expected(&mut self) {
+        self.expected = Some(Rc::new(RefCell::new((usize::MAX, vec![]))))
+    }
+    /// Record that a token was expected at the start of the remaining input, but was not found.
+    pub(crate) fn expect(&self, input: &str, token: &str) {
+        if let Some(e) = &self.expected {
+            let mut e = e.borrow_mut();
+            if input.len() < e.0 {
+                *e = (input.len(), vec![token.to_string()])
+            } else if input.len() == e.0 && !e.1.iter().any(|t| t == token) {
+                e.1.push(token.to_string())
+            }
+        }
+    }
+    /// The furthest point at which a token was expected, as the length of the remaining input, and the tokens that were expected there.
+    pub(crate) fn furthest_expected(&self) -> Option<(usize, Vec<String>)> {
+        self.expected
+            .as_ref()
+            .map(|e| e.borrow().clone())
+            .filter(|(len, _)| *len != usize::MAX)
+    }
     //pub fn stack_push(&mut self, msg: String) {
     //    self.stack.push(msg);
     //    if self.limit.is_some() {
@@ -160,6 +340,14 @@ impl<N: Node> ParserState<N> {
     pub fn doc(&self) -> Option<N> {
         self.doc.clone()
     }
+    /// Is the node to be left out of the tree?
+    pub(crate) fn is_stripped(&self, n: &N) -> bool {
+        match n.node_type() {
+            NodeType::Comment => self.strip_comments,
+            NodeType::ProcessingInstruction => self.strip_pis,
+            _ => false,
+        }
+    }
     /// Get a copy of all namespaces
     pub fn namespaces_ref(&self) -> &Vec<HashMap<String, String>> {
         &self.namespace
@@ -175,6 +363,12 @@ impl<N: Node> ParserState<N> {
     }
 }
 
+impl<N: Node> Default for ParserState<N> {
+    fn default() -> Self {
+        Self::with_options(None, ParseOptions::new())
+    }
+}
+
 impl<N: Node> PartialEq for ParserState<N> {
     fn eq(&self, _: &ParserState<N>) -> bool {
         true
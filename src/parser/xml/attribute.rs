@@ -90,10 +90,14 @@ pub(crate) fn attributes<N: Node>(
                 };
 
                 //Check if the xml:space attribute is present and if so, does it have
-                //"Preserved" or "Default" as its value. We'll actually handle in a future release.
+                //"preserve" or "default" as its value. It is up to the application to act on it.
+                //If the document has a DTD then the attribute must be declared, like any other.
+                //NB. attribute list declarations don't record which attributes are declared,
+                //so this only detects a DTD that has no attribute list declarations at all.
                 if qn.get_prefix() == Some("xml".to_string())
                     && qn.get_localname() == *"space"
-                    && !(qn.to_string() == "Default" || qn.to_string() == "Preserve")
+                    && (!(val == "default" || val == "preserve")
+                        || (!state1.dtd.elements.is_empty() && state1.dtd.attlists.is_empty()))
                 {
                     return Err(ParseError::Validation {
                         row: state1.currentrow,
@@ -138,6 +142,17 @@ pub(crate) fn attributes<N: Node>(
                     } else {
                         resnodenames.push((qn.get_nsuri(), qn.get_localname()));
                     }
+                } else if !attrval.is_empty() && qn.get_localname() != *"xml" {
+                    // A namespace declaration becomes a namespace node of the element,
+                    // if the tree supports them.
+                    let prefix = if qn.get_prefix().is_some() {
+                        Some(qn.get_localname())
+                    } else {
+                        None
+                    };
+                    if let Ok(nsnode) = state1.doc.clone().unwrap().new_namespace(attrval, prefix) {
+                        resnodes.push(nsnode)
+                    }
                 }
             }
             Ok(((input1, state1), resnodes))
@@ -173,8 +188,11 @@ fn attribute_value<N: Node>(
                         wellformed(chardata_unicode_codepoint(), |c| c != &'<'),
                         |c| c.to_string(),
                     ),
-                    textreference(),
-                    wellformed(take_while(|c| c != '&' && c != '\''), |c| !c.contains('<')),
+                    map(textreference(), |t| normalize_whitespace(&t)),
+                    map(
+                        wellformed(take_while(|c| c != '&' && c != '\''), |c| !c.contains('<')),
+                        |t| normalize_whitespace(&t),
+                    ),
                 )),
                 tag("'"),
             ),
@@ -185,8 +203,11 @@ fn attribute_value<N: Node>(
                         wellformed(chardata_unicode_codepoint(), |c| c != &'<'),
                         |c| c.to_string(),
                     ),
-                    textreference(),
-                    wellformed(take_while(|c| c != '&' && c != '\"'), |c| !c.contains('<')),
+                    map(textreference(), |t| normalize_whitespace(&t)),
+                    map(
+                        wellformed(take_while(|c| c != '&' && c != '\"'), |c| !c.contains('<')),
+                        |t| normalize_whitespace(&t),
+                    ),
                 )),
                 tag("\""),
             ),
@@ -202,12 +223,10 @@ fn attribute_value<N: Node>(
                    For an entity reference, recursively apply step 3 of this algorithm to the replacement text of the entity.
                    For a white space character (#x20, #xD, #xA, #x9), append a space character (#x20) to the normalized value.
                    For another character, append the character to the normalized value.
+
+                   This has been done for each token as it was parsed, so that character references are not normalized.
                 */
-                let r = rn
-                    .concat()
-                    .replace(['\n', '\r', '\t', '\n'], " ")
-                    .trim()
-                    .to_string();
+                let r = rn.concat().trim_matches(' ').to_string();
                 //NEL character cannot be in attributes.
                 if state1.xmlversion == "1.1" && r.find(|c| !is_char11(&c)).is_some() {
                     Err(ParseError::NotWellFormed(r))
@@ -222,3 +241,9 @@ fn attribute_value<N: Node>(
         }
     }
 }
+
+// Replace literal white space characters with a space.
+// A CR LF sequence is a single line ending, so becomes a single space.
+fn normalize_whitespace(s: &str) -> String {
+    s.replace("\r\n", " ").replace(['\r', '\n', '\t'], " ")
+}
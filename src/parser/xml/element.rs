@@ -20,12 +20,18 @@ use std::rc::Rc;
 // Element ::= EmptyElemTag | STag content ETag
 pub(crate) fn element<N: Node>() -> impl Fn(ParseInput<N>) -> Result<(ParseInput<N>, N), ParseError>
 {
-    move |input| alt2(emptyelem(), taggedelem())(input)
+    move |input: ParseInput<N>| {
+        // The location is found once, rather than by each alternative
+        let loc = input.1.location(input.0);
+        alt2(emptyelem(loc), taggedelem(loc))(input)
+    }
 }
 
 // EmptyElemTag ::= '<' Name (Attribute)* '/>'
-fn emptyelem<N: Node>() -> impl Fn(ParseInput<N>) -> Result<(ParseInput<N>, N), ParseError> {
-    move |input| {
+fn emptyelem<N: Node>(
+    loc: Option<(usize, usize)>,
+) -> impl Fn(ParseInput<N>) -> Result<(ParseInput<N>, N), ParseError> {
+    move |input: ParseInput<N>| {
         match tuple5(
             tag("<"),
             wellformed(qualname(), |qn| {
@@ -76,8 +82,15 @@ fn emptyelem<N: Node>() -> impl Fn(ParseInput<N>) -> Result<(ParseInput<N>, N),
                     .unwrap()
                     .new_element(QualifiedName::new(ens, n.get_prefix(), n.get_localname()))
                     .expect("unable to create element");
-                av.iter()
-                    .for_each(|b| e.add_attribute(b.clone()).expect("unable to add attribute"));
+                if let Some(l) = loc {
+                    e.set_location(l)
+                }
+                av.iter().for_each(|b| match b.node_type() {
+                    NodeType::Namespace => e
+                        .add_namespace(b.clone())
+                        .expect("unable to add namespace node"),
+                    _ => e.add_attribute(b.clone()).expect("unable to add attribute"),
+                });
                 //Add namespace nodes
                 /*
                 match namespaces {
@@ -122,8 +135,10 @@ fn emptyelem<N: Node>() -> impl Fn(ParseInput<N>) -> Result<(ParseInput<N>, N),
 // STag ::= '<' Name (Attribute)* '>'
 // ETag ::= '</' Name '>'
 // TODO: Check that names match and throw meaningful error
-fn taggedelem<N: Node>() -> impl Fn(ParseInput<N>) -> Result<(ParseInput<N>, N), ParseError> {
-    move |input| {
+fn taggedelem<N: Node>(
+    loc: Option<(usize, usize)>,
+) -> impl Fn(ParseInput<N>) -> Result<(ParseInput<N>, N), ParseError> {
+    move |input: ParseInput<N>| {
         match wellformed(
             tuple10(
                 tag("<"),
@@ -175,8 +190,15 @@ fn taggedelem<N: Node>() -> impl Fn(ParseInput<N>) -> Result<(ParseInput<N>, N),
                     .unwrap()
                     .new_element(QualifiedName::new(ens, n.get_prefix(), n.get_localname()))
                     .expect("unable to create element");
-                av.iter()
-                    .for_each(|b| e.add_attribute(b.clone()).expect("unable to add attribute"));
+                if let Some(l) = loc {
+                    e.set_location(l)
+                }
+                av.iter().for_each(|b| match b.node_type() {
+                    NodeType::Namespace => e
+                        .add_namespace(b.clone())
+                        .expect("unable to add namespace node"),
+                    _ => e.add_attribute(b.clone()).expect("unable to add attribute"),
+                });
                 c.iter().for_each(|d| {
                     e.push(d.clone()).expect("unable to add node");
                 });
@@ -214,6 +236,7 @@ pub(crate) fn content<N: Node>(
                     for x in w {
                         match x.node_type() {
                             NodeType::Text => notex.push(x.to_string()),
+                            _ if state1.1.is_stripped(&x) => {}
                             _ => {
                                 if !notex.is_empty() {
                                     new.push(
@@ -16,7 +16,7 @@ use crate::parser::xml::dtd::doctypedecl;
 use crate::parser::xml::element::element;
 use crate::parser::xml::misc::misc;
 use crate::parser::xml::xmldecl::xmldecl;
-use crate::parser::{ParseError, ParseInput, ParserConfig, ParserState};
+use crate::parser::{ParseError, ParseInput, ParseOptions, ParserConfig, ParserState};
 use crate::xdmerror::{Error, ErrorKind};
 use crate::xmldecl::XMLDecl;
 use std::collections::HashMap;
@@ -31,7 +31,30 @@ pub fn parse_with_ns<N: Node>(
     input: &str,
     config: Option<ParserConfig>,
 ) -> Result<(N, Vec<HashMap<String, String>>), Error> {
-    let state = ParserState::new(Some(doc), config);
+    parse_with_ns_options(
+        doc,
+        input,
+        config.map_or_else(ParseOptions::new, ParseOptions::from),
+    )
+}
+
+/// Parse an XML document into the given document node, as configured by the parser options.
+pub fn parse_with_options<N: Node>(doc: N, input: &str, options: ParseOptions) -> Result<N, Error> {
+    let (xmldoc, _) = parse_with_ns_options(doc, input, options)?;
+    Ok(xmldoc)
+}
+
+/// As for [parse_with_options], but also returns the namespaces declared in the document.
+pub fn parse_with_ns_options<N: Node>(
+    doc: N,
+    input: &str,
+    options: ParseOptions,
+) -> Result<(N, Vec<HashMap<String, String>>), Error> {
+    let locations = options.records_locations();
+    let mut state = ParserState::with_options(Some(doc), options);
+    if locations {
+        state.set_source(input);
+    }
     match document((input, state)) {
         Ok(((_, state1), xmldoc)) => Ok((xmldoc, state1.namespaces_ref().clone())),
         Err(err) => {
@@ -97,28 +120,33 @@ fn document<N: Node>(input: ParseInput<N>) -> Result<(ParseInput<N>, N), ParseEr
             if input1.is_empty() {
                 let pr = p.unwrap_or((None, vec![]));
 
-                pr.1.iter().for_each(|n| {
-                    state1
-                        .doc
-                        .clone()
-                        .unwrap()
-                        .push(n.clone())
-                        .expect("unable to add node")
-                });
+                pr.1.iter()
+                    .filter(|n| !state1.is_stripped(n))
+                    .for_each(|n| {
+                        state1
+                            .doc
+                            .clone()
+                            .unwrap()
+                            .push(n.clone())
+                            .expect("unable to add node")
+                    });
                 state1
                     .doc
                     .clone()
                     .unwrap()
                     .push(e)
                     .expect("unable to add node");
-                m.unwrap_or_default().iter().for_each(|n| {
-                    state1
-                        .doc
-                        .clone()
-                        .unwrap()
-                        .push(n.clone())
-                        .expect("unable to add node")
-                });
+                m.unwrap_or_default()
+                    .iter()
+                    .filter(|n| !state1.is_stripped(n))
+                    .for_each(|n| {
+                        state1
+                            .doc
+                            .clone()
+                            .unwrap()
+                            .push(n.clone())
+                            .expect("unable to add node")
+                    });
                 if let Some(x) = pr.0 {
                     let _ = state1.doc.clone().unwrap().set_xmldecl(x);
                 }
@@ -76,6 +76,8 @@ pub(crate) fn reference<N: Node>(
                                     //Parse the entity, using the parserstate which has information on namespaces
                                     let mut tempstate = state1.clone();
                                     tempstate.currententitydepth += 1;
+                                    // The replacement text is not part of the source text, so elements in it have no location
+                                    tempstate.source = None;
 
                                     /*
                                     We want to reuse the "Content" combinator to parse the entity, but
@@ -204,6 +206,8 @@ pub(crate) fn textreference<N: Node>(
                                     //Parse the entity, using the parserstate which has information on namespaces
                                     let mut tempstate = state1.clone();
                                     tempstate.currententitydepth += 1;
+                                    // The replacement text is not part of the source text, so elements in it have no location
+                                    tempstate.source = None;
 
                                     /*
                                     We want to reuse the "Content" combinator to parse the entity, but
@@ -1,39 +1,101 @@
 //! General productions for XPath expressions.
 
+use crate::item::Item;
 use crate::item::Node;
-use crate::parser::combinators::alt::{alt2, alt5};
+use crate::parser::combinators::alt::{alt2, alt6};
+use crate::parser::combinators::list::separated_list0;
+use crate::parser::combinators::many::many0;
 use crate::parser::combinators::map::map;
+use crate::parser::combinators::pair::pair;
+use crate::parser::xml::qname::ncname;
 use crate::parser::{ParseError, ParseInput};
 //use crate::parser::combinators::debug::inspect;
 use crate::parser::combinators::delimited::delimited;
 use crate::parser::combinators::tag::tag;
+use crate::parser::combinators::tuple::{tuple2, tuple3, tuple4};
+use crate::parser::combinators::whitespace::xpwhitespace;
 use crate::parser::xpath::context::context_item;
-use crate::parser::xpath::expr_wrapper;
 use crate::parser::xpath::functions::function_call;
 use crate::parser::xpath::literals::literal;
 use crate::parser::xpath::variables::variable_reference;
+use crate::parser::xpath::{expr_single_wrapper, expr_wrapper};
 use crate::transform::Transform;
+use crate::value::Value;
+use std::rc::Rc;
 
 // PostfixExpr ::= PrimaryExpr (Predicate | ArgumentList | Lookup)*
 // TODO: predicates, arg list, lookup
+// TODO: Predicate and ArgumentList
 pub(crate) fn postfix_expr<'a, N: Node + 'a>(
 ) -> Box<dyn Fn(ParseInput<N>) -> Result<(ParseInput<N>, Transform<N>), ParseError> + 'a> {
-    Box::new(primary_expr::<N>())
+    Box::new(map(
+        pair(primary_expr::<N>(), many0(lookup::<N>())),
+        |(p, l)| {
+            l.into_iter()
+                .fold(p, |m, k| Transform::Lookup(Box::new(m), Box::new(k)))
+        },
+    ))
+}
+
+// Lookup ::= '?' KeySpecifier
+// KeySpecifier ::= NCName | ParenthesizedExpr
+// TODO: IntegerLiteral and '*'
+fn lookup<'a, N: Node + 'a>(
+) -> Box<dyn Fn(ParseInput<N>) -> Result<(ParseInput<N>, Transform<N>), ParseError> + 'a> {
+    Box::new(map(
+        tuple3(
+            tuple2(xpwhitespace(), tag("?")),
+            xpwhitespace(),
+            alt2(
+                map(ncname(), |n| {
+                    Transform::Literal(Item::Value(Rc::new(Value::from(n))))
+                }),
+                parenthesized_expr::<N>(),
+            ),
+        ),
+        |(_, _, k)| k,
+    ))
 }
 
 // PrimaryExpr ::= Literal | VarRef | ParenthesizedExpr | ContextItemExpr | FunctionCall | FunctionItemExpr | MapConstructor | ArrayConstructor | UnaryLookup
 // TODO: finish this parser
 fn primary_expr<'a, N: Node + 'a>(
 ) -> Box<dyn Fn(ParseInput<N>) -> Result<(ParseInput<N>, Transform<N>), ParseError> + 'a> {
-    Box::new(alt5(
+    Box::new(alt6(
         literal::<N>(),
         parenthesized_expr::<N>(),
+        map_constructor::<N>(),
         function_call::<N>(),
         variable_reference::<N>(),
         context_item::<N>(),
     ))
 }
 
+// MapConstructor ::= 'map' '{' (MapConstructorEntry (',' MapConstructorEntry)*)? '}'
+// MapConstructorEntry ::= ExprSingle ':' ExprSingle
+fn map_constructor<'a, N: Node + 'a>(
+) -> Box<dyn Fn(ParseInput<N>) -> Result<(ParseInput<N>, Transform<N>), ParseError> + 'a> {
+    Box::new(map(
+        tuple3(
+            tuple3(tag("map"), xpwhitespace(), tag("{")),
+            separated_list0(
+                map(tuple3(xpwhitespace(), tag(","), xpwhitespace()), |_| ()),
+                map(
+                    tuple4(
+                        xpwhitespace(),
+                        expr_single_wrapper::<N>(true),
+                        tuple3(xpwhitespace(), tag(":"), xpwhitespace()),
+                        expr_single_wrapper::<N>(true),
+                    ),
+                    |(_, k, _, v)| (k, v),
+                ),
+            ),
+            tuple2(xpwhitespace(), tag("}")),
+        ),
+        |(_, e, _)| Transform::MapConstructor(e),
+    ))
+}
+
 // ParenthesizedExpr ::= '(' Expr? ')'
 pub(crate) fn parenthesized_expr<'a, N: Node + 'a>(
 ) -> Box<dyn Fn(ParseInput<N>) -> Result<(ParseInput<N>, Transform<N>), ParseError> + 'a> {
@@ -44,13 +106,15 @@ pub(crate) fn parenthesized_expr<'a, N: Node + 'a>(
 }
 fn parenthesized_expr_empty<'a, N: Node + 'a>(
 ) -> Box<dyn Fn(ParseInput<N>) -> Result<(ParseInput<N>, Transform<N>), ParseError> + 'a> {
-    Box::new(map(tag("()"), |_| Transform::Empty))
+    Box::new(map(tuple3(tag("("), xpwhitespace(), tag(")")), |_| {
+        Transform::Empty
+    }))
 }
 fn parenthesized_expr_nonempty<'a, N: Node + 'a>(
 ) -> Box<dyn Fn(ParseInput<N>) -> Result<(ParseInput<N>, Transform<N>), ParseError> + 'a> {
     Box::new(delimited(
-        tag("("),
+        tuple2(tag("("), xpwhitespace()),
         map(expr_wrapper::<N>(true), |e| e),
-        tag(")"),
+        tuple2(xpwhitespace(), tag(")")),
     ))
 }
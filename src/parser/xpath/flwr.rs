@@ -1,6 +1,7 @@
 //! XPath FLWR expressions.
 
 use crate::item::Node;
+use crate::parser::combinators::alt::alt2;
 use crate::parser::combinators::list::separated_list1;
 use crate::parser::combinators::map::map;
 use crate::parser::combinators::pair::pair;
@@ -60,30 +61,49 @@ pub(crate) fn for_expr<'a, N: Node + 'a>(
 }
 
 // SimpleForClause ::= 'for' SimpleForBinding (',' SimpleForBinding)*
-// SimpleForBinding ::= '$' VarName 'in' ExprSingle
 fn simple_for_clause<'a, N: Node + 'a>() -> Box<
     dyn Fn(ParseInput<N>) -> Result<(ParseInput<N>, Vec<(String, Transform<N>)>), ParseError> + 'a,
 > {
     Box::new(map(
-        tuple3(
-            tag("for"),
-            xpwhitespace(),
-            separated_list1(
-                map(tuple3(xpwhitespace(), tag(","), xpwhitespace()), |_| ()),
-                map(
-                    tuple6(
-                        tag("$"),
-                        qualname_test(),
-                        xpwhitespace(),
-                        tag("in"),
-                        xpwhitespace(),
-                        expr_single_wrapper::<N>(true),
-                    ),
-                    |(_, qn, _, _, _, e)| (get_nt_localname(&qn), e),
-                ),
+        tuple3(tag("for"), xpwhitespace(), in_bindings::<N>()),
+        |(_, _, v)| v,
+    ))
+}
+
+// SimpleForBinding (',' SimpleForBinding)*
+// SimpleForBinding ::= '$' VarName 'in' ExprSingle
+// Quantified expressions have the same form of bindings.
+fn in_bindings<'a, N: Node + 'a>() -> Box<
+    dyn Fn(ParseInput<N>) -> Result<(ParseInput<N>, Vec<(String, Transform<N>)>), ParseError> + 'a,
+> {
+    Box::new(separated_list1(
+        map(tuple3(xpwhitespace(), tag(","), xpwhitespace()), |_| ()),
+        map(
+            tuple6(
+                tag("$"),
+                qualname_test(),
+                xpwhitespace(),
+                tag("in"),
+                xpwhitespace(),
+                expr_single_wrapper::<N>(true),
             ),
+            |(_, qn, _, _, _, e)| (get_nt_localname(&qn), e),
         ),
-        |(_, _, v)| v,
+    ))
+}
+
+// QuantifiedExpr ::= ('some' | 'every') '$' VarName 'in' ExprSingle (',' '$' VarName 'in' ExprSingle)* 'satisfies' ExprSingle
+pub(crate) fn quantified_expr<'a, N: Node + 'a>(
+) -> Box<dyn Fn(ParseInput<N>) -> Result<(ParseInput<N>, Transform<N>), ParseError> + 'a> {
+    Box::new(map(
+        tuple5(
+            alt2(map(tag("some"), |_| false), map(tag("every"), |_| true)),
+            xpwhitespace(),
+            in_bindings::<N>(),
+            tuple3(xpwhitespace(), tag("satisfies"), xpwhitespace()),
+            expr_single_wrapper::<N>(true),
+        ),
+        |(every, _, v, _, e)| Transform::Quantified(every, v, Box::new(e)),
     ))
 }
 
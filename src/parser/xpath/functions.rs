@@ -7,7 +7,7 @@ use crate::parser::combinators::map::map;
 use crate::parser::combinators::opt::opt;
 use crate::parser::combinators::pair::pair;
 use crate::parser::combinators::tag::tag;
-use crate::parser::combinators::tuple::{tuple3, tuple6};
+use crate::parser::combinators::tuple::{tuple2, tuple3, tuple6};
 use crate::parser::combinators::whitespace::xpwhitespace;
 //use crate::parser::combinators::debug::inspect;
 use crate::parser::xml::qname::qualname;
@@ -83,6 +83,28 @@ pub(crate) fn function_call<'a, N: Node + 'a>(
                         Transform::Error(ErrorKind::ParseError, String::from("too many arguments"))
                     }
                 }
+                "exists" => {
+                    if a.len() == 1 {
+                        Transform::Exists(Box::new(a.pop().unwrap()))
+                    } else {
+                        // Wrong number of arguments
+                        Transform::Error(
+                            ErrorKind::ParseError,
+                            String::from("wrong number of arguments"),
+                        )
+                    }
+                }
+                "empty" => {
+                    if a.len() == 1 {
+                        Transform::IsEmpty(Box::new(a.pop().unwrap()))
+                    } else {
+                        // Wrong number of arguments
+                        Transform::Error(
+                            ErrorKind::ParseError,
+                            String::from("wrong number of arguments"),
+                        )
+                    }
+                }
                 "local-name" => {
                     if a.is_empty() {
                         Transform::LocalName(None)
@@ -183,6 +205,114 @@ pub(crate) fn function_call<'a, N: Node + 'a>(
                         )
                     }
                 }
+                "distinct-values" => match a.len() {
+                    1 => {
+                        let s = a.pop().unwrap();
+                        Transform::DistinctValues(Box::new(s), None)
+                    }
+                    2 => {
+                        let c = a.pop().unwrap();
+                        let s = a.pop().unwrap();
+                        Transform::DistinctValues(Box::new(s), Some(Box::new(c)))
+                    }
+                    _ => Transform::Error(
+                        ErrorKind::ParseError,
+                        String::from("wrong number of arguments"),
+                    ),
+                },
+                "index-of" => match a.len() {
+                    2 => {
+                        let t = a.pop().unwrap();
+                        let s = a.pop().unwrap();
+                        Transform::IndexOf(Box::new(s), Box::new(t), None)
+                    }
+                    3 => {
+                        let c = a.pop().unwrap();
+                        let t = a.pop().unwrap();
+                        let s = a.pop().unwrap();
+                        Transform::IndexOf(Box::new(s), Box::new(t), Some(Box::new(c)))
+                    }
+                    _ => Transform::Error(
+                        ErrorKind::ParseError,
+                        String::from("wrong number of arguments"),
+                    ),
+                },
+                "insert-before" => match a.len() {
+                    3 => {
+                        let i = a.pop().unwrap();
+                        let p = a.pop().unwrap();
+                        let t = a.pop().unwrap();
+                        Transform::InsertBefore(Box::new(t), Box::new(p), Box::new(i))
+                    }
+                    _ => Transform::Error(
+                        ErrorKind::ParseError,
+                        String::from("wrong number of arguments"),
+                    ),
+                },
+                "remove" => match a.len() {
+                    2 => {
+                        let p = a.pop().unwrap();
+                        let t = a.pop().unwrap();
+                        Transform::Remove(Box::new(t), Box::new(p))
+                    }
+                    _ => Transform::Error(
+                        ErrorKind::ParseError,
+                        String::from("wrong number of arguments"),
+                    ),
+                },
+                "contains-token" => match a.len() {
+                    2 => {
+                        let t = a.pop().unwrap();
+                        let s = a.pop().unwrap();
+                        Transform::ContainsToken(Box::new(s), Box::new(t), None)
+                    }
+                    3 => {
+                        let c = a.pop().unwrap();
+                        let t = a.pop().unwrap();
+                        let s = a.pop().unwrap();
+                        Transform::ContainsToken(Box::new(s), Box::new(t), Some(Box::new(c)))
+                    }
+                    _ => Transform::Error(
+                        ErrorKind::ParseError,
+                        String::from("wrong number of arguments"),
+                    ),
+                },
+                "tokenize" => match a.len() {
+                    1 => {
+                        let s = a.pop().unwrap();
+                        Transform::Tokenize(Box::new(s), None, None)
+                    }
+                    2 => {
+                        let p = a.pop().unwrap();
+                        let s = a.pop().unwrap();
+                        Transform::Tokenize(Box::new(s), Some(Box::new(p)), None)
+                    }
+                    3 => {
+                        let f = a.pop().unwrap();
+                        let p = a.pop().unwrap();
+                        let s = a.pop().unwrap();
+                        Transform::Tokenize(Box::new(s), Some(Box::new(p)), Some(Box::new(f)))
+                    }
+                    _ => Transform::Error(
+                        ErrorKind::ParseError,
+                        String::from("wrong number of arguments"),
+                    ),
+                },
+                "string-join" => match a.len() {
+                    1 => {
+                        let s = a.pop().unwrap();
+                        Transform::StringJoin(Box::new(s), None)
+                    }
+                    2 => {
+                        let t = a.pop().unwrap();
+                        let s = a.pop().unwrap();
+                        Transform::StringJoin(Box::new(s), Some(Box::new(t)))
+                    }
+                    _ => Transform::Error(
+                        ErrorKind::ParseError,
+                        String::from("wrong number of arguments"),
+                    ),
+                },
                 "translate" => {
                     if a.len() == 3 {
                         let b = a.pop().unwrap();
@@ -210,6 +340,45 @@ pub(crate) fn function_call<'a, N: Node + 'a>(
                         )
                     }
                 }
+                "root" => {
+                    if a.is_empty() {
+                        Transform::RootOf(None)
+                    } else if a.len() == 1 {
+                        Transform::RootOf(Some(Box::new(a.pop().unwrap())))
+                    } else {
+                        // Wrong number of arguments
+                        Transform::Error(
+                            ErrorKind::ParseError,
+                            String::from("wrong number of arguments"),
+                        )
+                    }
+                }
+                "path" => {
+                    if a.is_empty() {
+                        Transform::Path(None)
+                    } else if a.len() == 1 {
+                        Transform::Path(Some(Box::new(a.pop().unwrap())))
+                    } else {
+                        // Wrong number of arguments
+                        Transform::Error(
+                            ErrorKind::ParseError,
+                            String::from("wrong number of arguments"),
+                        )
+                    }
+                }
+                "nilled" => {
+                    if a.is_empty() {
+                        Transform::Nilled(None)
+                    } else if a.len() == 1 {
+                        Transform::Nilled(Some(Box::new(a.pop().unwrap())))
+                    } else {
+                        // Wrong number of arguments
+                        Transform::Error(
+                            ErrorKind::ParseError,
+                            String::from("wrong number of arguments"),
+                        )
+                    }
+                }
                 "boolean" => {
                     if a.len() == 1 {
                         Transform::Boolean(Box::new(a.pop().unwrap()))
@@ -258,6 +427,37 @@ pub(crate) fn function_call<'a, N: Node + 'a>(
                         Transform::Error(ErrorKind::ParseError, String::from("too many arguments"))
                     }
                 }
+                "avg" => match a.len() {
+                    1 => Transform::Avg(Box::new(a.pop().unwrap())),
+                    _ => Transform::Error(
+                        ErrorKind::ParseError,
+                        String::from("wrong number of arguments"),
+                    ),
+                },
+                "max" => match a.len() {
+                    1 => Transform::Max(Box::new(a.pop().unwrap()), None),
+                    2 => {
+                        let c = a.pop().unwrap();
+                        let s = a.pop().unwrap();
+                        Transform::Max(Box::new(s), Some(Box::new(c)))
+                    }
+                    _ => Transform::Error(
+                        ErrorKind::ParseError,
+                        String::from("wrong number of arguments"),
+                    ),
+                },
+                "min" => match a.len() {
+                    1 => Transform::Min(Box::new(a.pop().unwrap()), None),
+                    2 => {
+                        let c = a.pop().unwrap();
+                        let s = a.pop().unwrap();
+                        Transform::Min(Box::new(s), Some(Box::new(c)))
+                    }
+                    _ => Transform::Error(
+                        ErrorKind::ParseError,
+                        String::from("wrong number of arguments"),
+                    ),
+                },
                 "floor" => {
                     if a.len() == 1 {
                         Transform::Floor(Box::new(a.pop().unwrap()))
@@ -383,6 +583,17 @@ pub(crate) fn function_call<'a, N: Node + 'a>(
                         Transform::Error(ErrorKind::ParseError, String::from("too many arguments"))
                     }
                 }
+                "parse-ietf-date" => {
+                    if a.len() == 1 {
+                        Transform::ParseIetfDate(Box::new(a.pop().unwrap()))
+                    } else {
+                        // Wrong number of arguments
+                        Transform::Error(
+                            ErrorKind::ParseError,
+                            String::from("wrong number of arguments"),
+                        )
+                    }
+                }
                 "format-number" => {
                     if a.is_empty() || a.len() == 1 {
                         // Too few arguments
@@ -458,6 +669,21 @@ pub(crate) fn function_call<'a, N: Node + 'a>(
                         )
                     }
                 }
+                "trace" => match a.len() {
+                    1 => {
+                        let v = a.pop().unwrap();
+                        Transform::Trace(Box::new(v), Box::new(Transform::Empty))
+                    }
+                    2 => {
+                        let l = a.pop().unwrap();
+                        let v = a.pop().unwrap();
+                        Transform::Trace(Box::new(v), Box::new(l))
+                    }
+                    _ => Transform::Error(
+                        ErrorKind::ParseError,
+                        String::from("wrong number of arguments"),
+                    ),
+                },
                 "document" => match a.len() {
                     0 => Transform::Document(Box::new(Transform::Empty), None),
                     1 => {
@@ -474,6 +700,48 @@ pub(crate) fn function_call<'a, N: Node + 'a>(
                         String::from("wrong number of arguments"),
                     ),
                 },
+                "parse-xml" => match a.len() {
+                    1 => Transform::ParseXml(Box::new(a.pop().unwrap())),
+                    _ => Transform::Error(
+                        ErrorKind::ParseError,
+                        String::from("wrong number of arguments"),
+                    ),
+                },
+                "serialize" => match a.len() {
+                    1 => Transform::Serialize(Box::new(a.pop().unwrap()), None),
+                    2 => {
+                        let p = a.pop().unwrap();
+                        let s = a.pop().unwrap();
+                        Transform::Serialize(Box::new(s), Some(Box::new(p)))
+                    }
+                    _ => Transform::Error(
+                        ErrorKind::ParseError,
+                        String::from("wrong number of arguments"),
+                    ),
+                },
+                "transform" => match a.len() {
+                    1 => Transform::NestedTransform(Box::new(a.pop().unwrap())),
+                    _ => Transform::Error(
+                        ErrorKind::ParseError,
+                        String::from("wrong number of arguments"),
+                    ),
+                },
+                "collection" => match a.len() {
+                    0 => Transform::Collection(None),
+                    1 => Transform::Collection(Some(Box::new(a.pop().unwrap()))),
+                    _ => Transform::Error(
+                        ErrorKind::ParseError,
+                        String::from("wrong number of arguments"),
+                    ),
+                },
+                "uri-collection" => match a.len() {
+                    0 => Transform::UriCollection(None),
+                    1 => Transform::UriCollection(Some(Box::new(a.pop().unwrap()))),
+                    _ => Transform::Error(
+                        ErrorKind::ParseError,
+                        String::from("wrong number of arguments"),
+                    ),
+                },
                 _ => Transform::Error(
                     ErrorKind::ParseError,
                     format!("undefined function \"{}\"", qn),
@@ -506,12 +774,12 @@ fn argumentlist<'a, N: Node + 'a>(
 ) -> Box<dyn Fn(ParseInput<N>) -> Result<(ParseInput<N>, Vec<Transform<N>>), ParseError> + 'a> {
     Box::new(map(
         tuple3(
-            tag("("),
+            tuple2(tag("("), xpwhitespace()),
             separated_list0(
                 map(tuple3(xpwhitespace(), tag(","), xpwhitespace()), |_| ()),
                 argument::<N>(),
             ),
-            tag(")"),
+            tuple2(xpwhitespace(), tag(")")),
         ),
         |(_, a, _)| a,
     ))
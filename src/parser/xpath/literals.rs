@@ -152,7 +152,7 @@ fn string_literal_single<'a, N: Node + 'a>(
         |s| Transform::Literal(Item::Value(Rc::new(Value::from(s)))),
     ))
 }
-fn string_literal<'a, N: Node + 'a>(
+pub(crate) fn string_literal<'a, N: Node + 'a>(
 ) -> Box<dyn Fn(ParseInput<N>) -> Result<(ParseInput<N>, Transform<N>), ParseError> + 'a> {
     Box::new(alt2(
         string_literal_double::<N>(),
@@ -60,20 +60,22 @@ pub(crate) mod support;
 mod types;
 pub(crate) mod variables;
 
-use crate::parser::combinators::alt::alt4;
+use crate::parser::combinators::alt::{alt2, alt4};
 use crate::parser::combinators::list::separated_list1;
 use crate::parser::combinators::map::map;
 use crate::parser::combinators::tag::tag;
 use crate::parser::combinators::tuple::tuple3;
 use crate::parser::combinators::whitespace::xpwhitespace;
 //use crate::parser::combinators::debug::inspect;
-use crate::parser::xpath::flwr::{for_expr, if_expr, let_expr};
+use crate::parser::xpath::flwr::{for_expr, if_expr, let_expr, quantified_expr};
 use crate::parser::xpath::logic::or_expr;
 use crate::parser::xpath::support::noop;
+use crate::parser::xpath::types::sequencetype_expr;
 use crate::parser::{ParseError, ParseInput, ParserState};
 
 use crate::item::Node;
-use crate::transform::Transform;
+use crate::qname::QualifiedName;
+use crate::transform::{SequenceType, Transform};
 use crate::xdmerror::{Error, ErrorKind};
 
 pub fn parse<N: Node>(input: &str) -> Result<Transform<N>, Error> {
@@ -82,21 +84,15 @@ pub fn parse<N: Node>(input: &str) -> Result<Transform<N>, Error> {
         return Ok(Transform::Empty);
     }
 
-    let state = ParserState::new(None, None);
+    let mut state = ParserState::default();
+    state.track_expected();
+    let tracker = state.clone();
     match xpath_expr((input, state)) {
         Ok((_, x)) => Ok(x),
         Err(err) => match err {
-            ParseError::Combinator => Err(Error::new(
-                ErrorKind::ParseError,
-                format!(
-                    "Unrecoverable parser error while parsing XPath expression \"{}\"",
-                    input
-                ),
-            )),
-            ParseError::NotWellFormed(e) => Err(Error::new(
-                ErrorKind::ParseError,
-                format!("Unrecognised extra characters: \"{}\"", e),
-            )),
+            ParseError::Combinator | ParseError::NotWellFormed(_) => {
+                Err(syntax_error(input, &tracker))
+            }
             ParseError::MissingNameSpace => Err(Error::new(
                 ErrorKind::ParseError,
                 "Missing namespace declaration.".to_string(),
@@ -110,6 +106,59 @@ pub fn parse<N: Node>(input: &str) -> Result<Transform<N>, Error> {
     }
 }
 
+/// Parse a sequence type, such as the value of an as attribute in a stylesheet.
+/// Prefixes in the names of atomic types are not resolved.
+pub fn parse_sequence_type<N: Node>(input: &str) -> Result<SequenceType, Error> {
+    let input = input.trim();
+    let mut state = ParserState::<N>::default();
+    state.track_expected();
+    let tracker = state.clone();
+    match sequencetype_expr::<N>()((input, state)) {
+        Ok(((rest, _), t)) if rest.is_empty() => Ok(t),
+        _ => Err(syntax_error(input, &tracker)),
+    }
+}
+
+// The number of expected tokens that are listed in a syntax error
+const EXPECTED_HINTS: usize = 6;
+
+// A syntax error (XPST0003) at the furthest point that the parser reached.
+// The message gives the character offset within the expression, the tokens that were expected there,
+// and the line of the expression with a caret under the offending character.
+fn syntax_error<N: Node>(input: &str, state: &ParserState<N>) -> Error {
+    let (remaining, expected) = state.furthest_expected().unwrap_or((input.len(), vec![]));
+    let pos = input.len() - remaining.min(input.len());
+    let offset = input[..pos].chars().count();
+    let mut message = format!("syntax error at offset {} in XPath expression", offset);
+    if !expected.is_empty() {
+        message.push_str(": expected one of ");
+        message.push_str(
+            expected
+                .iter()
+                .take(EXPECTED_HINTS)
+                .map(|t| format!("\"{}\"", t))
+                .collect::<Vec<String>>()
+                .join(", ")
+                .as_str(),
+        );
+        if expected.len() > EXPECTED_HINTS {
+            message.push_str(", ...")
+        }
+    }
+    let start = input[..pos].rfind('\n').map_or(0, |i| i + 1);
+    let end = input[pos..].find('\n').map_or(input.len(), |i| pos + i);
+    message.push('\n');
+    message.push_str(&input[start..end]);
+    message.push('\n');
+    message.push_str(" ".repeat(input[start..pos].chars().count()).as_str());
+    message.push('^');
+    Error::new_with_code(
+        ErrorKind::StaticSyntax,
+        message,
+        Some(QualifiedName::new(None, None, "XPST0003")),
+    )
+}
+
 fn xpath_expr<N: Node>(input: ParseInput<N>) -> Result<(ParseInput<N>, Transform<N>), ParseError> {
     match expr::<N>()(input) {
         Err(err) => Err(err),
@@ -160,7 +209,10 @@ pub(crate) fn expr_wrapper<N: Node>(
 // ExprSingle ::= ForExpr | LetExpr | QuantifiedExpr | IfExpr | OrExpr
 fn expr_single<'a, N: Node + 'a>(
 ) -> Box<dyn Fn(ParseInput<N>) -> Result<(ParseInput<N>, Transform<N>), ParseError> + 'a> {
-    Box::new(alt4(let_expr(), for_expr(), if_expr(), or_expr()))
+    Box::new(alt2(
+        alt4(let_expr(), for_expr(), quantified_expr(), if_expr()),
+        or_expr(),
+    ))
 }
 
 pub(crate) fn expr_single_wrapper<N: Node>(
@@ -6,8 +6,9 @@ use crate::parser::combinators::map::map;
 use crate::parser::combinators::opt::opt;
 use crate::parser::combinators::tag::tag;
 use crate::parser::combinators::tuple::tuple3;
+use crate::parser::xpath::literals::string_literal;
 use crate::parser::{ParseError, ParseInput};
-use crate::transform::{KindTest, NameTest, NodeTest, WildcardOrName};
+use crate::transform::{KindTest, NameTest, NodeTest, Transform, WildcardOrName};
 //use crate::parser::combinators::debug::inspect;
 use crate::parser::xml::qname::{ncname, qualname};
 
@@ -47,7 +48,7 @@ pub(crate) fn nodetest<'a, N: Node + 'a>(
 }
 
 // KindTest ::= DocumentTest | ElementTest | AttributeTest | SchemaElementTest | SchemaAttributeTest | PITest | CommentTest | TextTest | NamespaceNodeTest | AnyKindTest
-fn kindtest<'a, N: Node + 'a>(
+pub(crate) fn kindtest<'a, N: Node + 'a>(
 ) -> Box<dyn Fn(ParseInput<N>) -> Result<(ParseInput<N>, NodeTest), ParseError> + 'a> {
     // Need alt10
     Box::new(alt2(
@@ -127,10 +128,20 @@ fn schema_attribute_test<'a, N: Node + 'a>(
 // PITest ::= "processing-instruction" "(" (NCName | StringLiteral)? ")"
 fn pi_test<'a, N: Node + 'a>(
 ) -> Box<dyn Fn(ParseInput<N>) -> Result<(ParseInput<N>, NodeTest), ParseError> + 'a> {
-    // TODO: NCName | StringLiteral
-    Box::new(map(tag("processing-instruction()"), |_| {
-        NodeTest::Kind(KindTest::PI)
-    }))
+    Box::new(map(
+        tuple3(
+            tag("processing-instruction("),
+            opt(alt2(
+                ncname(),
+                map(string_literal::<N>(), |t| match t {
+                    Transform::Literal(i) => i.to_string().trim().to_string(),
+                    _ => String::new(),
+                }),
+            )),
+            tag(")"),
+        ),
+        |(_, t, _)| NodeTest::Kind(KindTest::PI(t)),
+    ))
 }
 // CommentTest ::= "comment" "(" ")"
 fn comment_test<'a, N: Node + 'a>(
@@ -1,6 +1,7 @@
 //! Functions that manipulate type information
 
 use crate::item::Node;
+use crate::parser::combinators::alt::{alt2, alt3};
 use crate::parser::combinators::map::map;
 use crate::parser::combinators::opt::opt;
 use crate::parser::combinators::pair::pair;
@@ -8,9 +9,12 @@ use crate::parser::combinators::tag::tag;
 use crate::parser::combinators::tuple::tuple6;
 use crate::parser::combinators::whitespace::xpwhitespace;
 use crate::parser::xpath::functions::arrow_expr;
-use crate::parser::xpath::nodetests::qualname_test;
+use crate::parser::xpath::nodetests::{kindtest, qualname_test};
 use crate::parser::{ParseError, ParseInput};
-use crate::transform::Transform;
+use crate::qname::QualifiedName;
+use crate::transform::{
+    ItemType, NameTest, NodeTest, Occurrence, SequenceType, Transform, WildcardOrName,
+};
 
 // InstanceOfExpr ::= TreatExpr ( 'instance' 'of' SequenceType)?
 pub(crate) fn instanceof_expr<'a, N: Node + 'a>(
@@ -27,22 +31,53 @@ pub(crate) fn instanceof_expr<'a, N: Node + 'a>(
                 sequencetype_expr::<N>(),
             )),
         ),
-        |(v, o)| {
-            if o.is_none() {
-                v
-            } else {
-                Transform::NotImplemented("instanceof_expr".to_string())
-            }
+        |(v, o)| match o {
+            None => v,
+            Some((_, _, _, _, _, t)) => Transform::InstanceOf(Box::new(v), t),
         },
     ))
 }
 
 // SequenceType ::= ( 'empty-sequence' '(' ')' | (ItemType OccurrenceIndicator?)
-// TODO: implement this parser fully
-fn sequencetype_expr<'a, N: Node + 'a>(
-) -> Box<dyn Fn(ParseInput<N>) -> Result<(ParseInput<N>, Transform<N>), ParseError> + 'a> {
-    Box::new(map(tag("empty-sequence()"), |_| {
-        Transform::NotImplemented("sequencetype_expr".to_string())
+// ItemType ::= KindTest | ('item' '(' ')') | AtomicOrUnionType
+// OccurrenceIndicator ::= '?' | '*' | '+'
+// TODO: function, map and array tests
+pub(crate) fn sequencetype_expr<'a, N: Node + 'a>(
+) -> Box<dyn Fn(ParseInput<N>) -> Result<(ParseInput<N>, SequenceType), ParseError> + 'a> {
+    Box::new(alt2(
+        map(tag("empty-sequence()"), |_| SequenceType::Empty),
+        map(
+            pair(
+                alt3(
+                    map(tag("item()"), |_| ItemType::Item),
+                    map(kindtest(), |k| match k {
+                        NodeTest::Kind(kt) => ItemType::Kind(kt),
+                        _ => ItemType::Item,
+                    }),
+                    map(type_name(), ItemType::Atomic),
+                ),
+                opt(alt3(
+                    map(tag("?"), |_| Occurrence::ZeroOrOne),
+                    map(tag("*"), |_| Occurrence::ZeroOrMore),
+                    map(tag("+"), |_| Occurrence::OneOrMore),
+                )),
+            ),
+            |(t, o)| SequenceType::Items(t, o.unwrap_or(Occurrence::One)),
+        ),
+    ))
+}
+
+// TypeName ::= EQName
+// TODO: allow URIQualifiedName
+fn type_name<'a, N: Node + 'a>(
+) -> Box<dyn Fn(ParseInput<N>) -> Result<(ParseInput<N>, QualifiedName), ParseError> + 'a> {
+    Box::new(map(qualname_test(), |nt| match nt {
+        NodeTest::Name(NameTest {
+            prefix,
+            name: Some(WildcardOrName::Name(localname)),
+            ..
+        }) => QualifiedName::new(None, prefix, localname),
+        _ => QualifiedName::new(None, None, ""),
     }))
 }
 
@@ -86,12 +121,9 @@ fn castable_expr<'a, N: Node + 'a>(
                 singletype_expr::<N>(),
             )),
         ),
-        |(v, o)| {
-            if o.is_none() {
-                v
-            } else {
-                Transform::NotImplemented("castable_expr".to_string())
-            }
+        |(v, o)| match o {
+            None => v,
+            Some((_, _, _, _, _, (t, optional))) => Transform::Castable(Box::new(v), t, optional),
         },
     ))
 }
@@ -108,11 +140,11 @@ fn castable_expr<'a, N: Node + 'a>(
 // LocalPart ::= NCName
 // NCName ::= Name - (Char* ':' Char*)
 // Char ::= #x9 | #xA |#xD | [#x20-#xD7FF] | [#xE000-#xFFFD | [#x10000-#x10FFFF]
-// TODO: implement this parser fully
+// The result is the type name and whether the empty sequence is allowed.
 fn singletype_expr<'a, N: Node + 'a>(
-) -> Box<dyn Fn(ParseInput<N>) -> Result<(ParseInput<N>, Transform<N>), ParseError> + 'a> {
-    Box::new(map(pair(qualname_test(), tag("?")), |_| {
-        Transform::NotImplemented("singletype_expr".to_string())
+) -> Box<dyn Fn(ParseInput<N>) -> Result<(ParseInput<N>, (QualifiedName, bool)), ParseError> + 'a> {
+    Box::new(map(pair(type_name(), opt(tag("?"))), |(t, q)| {
+        (t, q.is_some())
     }))
 }
 
@@ -131,12 +163,9 @@ fn cast_expr<'a, N: Node + 'a>(
                 singletype_expr::<N>(),
             )),
         ),
-        |(v, o)| {
-            if o.is_none() {
-                v
-            } else {
-                Transform::NotImplemented("cast_expr".to_string())
-            }
+        |(v, o)| match o {
+            None => v,
+            Some((_, _, _, _, _, (t, optional))) => Transform::Cast(Box::new(v), t, optional),
         },
     ))
 }
@@ -75,9 +75,10 @@ use crate::parser::xpath::literals::literal;
 use crate::parser::xpath::nodetests::{nodetest, qualname_test};
 use crate::parser::xpath::predicates::predicate_list;
 use crate::parser::xpath::variables::variable_reference;
+use crate::qname::QualifiedName;
 use crate::transform::context::{Context, ContextBuilder, StaticContext};
 use crate::transform::{Axis, KindTest, NameTest, NodeTest, Transform, WildcardOrName};
-use crate::value::Value;
+use crate::value::{Value, XSNS};
 use crate::xdmerror::{Error, ErrorKind};
 
 use crate::parser::combinators::alt::{alt2, alt4, alt6};
@@ -100,7 +101,7 @@ use crate::parser::{ParseError, ParseInput, ParserState};
 #[derive(Clone)]
 pub enum Pattern<N: Node> {
     Predicate(Transform<N>),
-    Selection(Path),
+    Selection(Path<N>),
     Error(Error),
 }
 
@@ -128,9 +129,10 @@ impl<N: Node> Pattern<N> {
                 // First step is the terminal case,
                 // next steps are non-terminal
                 p.t.as_ref().map_or(false, |((term, nonterm), nt)| {
-                    if is_match(term, nt, i) {
+                    if is_match(term, nt, i) && satisfies(ctxt, stctxt, p, i) {
                         // TODO: select item depending on non-terminal axis
-                        find_node(nonterm, i).map_or(false, |f| nonterminal(p.next.clone(), &f))
+                        find_node(nonterm, i)
+                            .map_or(false, |f| nonterminal(ctxt, stctxt, p.next.clone(), &f))
                     } else {
                         false
                     }
@@ -139,6 +141,21 @@ impl<N: Node> Pattern<N> {
             _ => false, // not yet implemented
         }
     }
+    /// Returns whether the given item may match the pattern, considering only the terminal step.
+    /// Only the node type and name of the item are examined,
+    /// so the result applies to all nodes with the same type and name.
+    pub(crate) fn terminal_match(&self, i: &Item<N>) -> bool {
+        match self {
+            Pattern::Predicate(_) => true,
+            Pattern::Selection(p) => p.t.as_ref().is_some_and(|((term, _), nt)| match term {
+                Axis::SelfDocument | Axis::SelfAxis | Axis::SelfAttribute | Axis::SelfNamespace => {
+                    is_match(term, nt, i)
+                }
+                _ => true,
+            }),
+            Pattern::Error(_) => false,
+        }
+    }
 }
 
 fn find_node<N: Node>(a: &Axis, i: &Item<N>) -> Option<Item<N>> {
@@ -162,15 +179,25 @@ fn find_node<N: Node>(a: &Axis, i: &Item<N>) -> Option<Item<N>> {
     }
 }
 
-fn nonterminal<N: Node>(p: Option<Rc<Path>>, i: &Item<N>) -> bool {
+fn nonterminal<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    p: Option<Rc<Path<N>>>,
+    i: &Item<N>,
+) -> bool {
     p.map_or(
         true, // all steps have succeeded so far
         |q| {
             let ((term, nonterm), nt) = q.t.as_ref().unwrap();
-            if is_match(term, nt, i) {
+            if is_match(term, nt, i) && satisfies(ctxt, stctxt, &q, i) {
                 find_node(nonterm, i).map_or(
                     false, // couldn't find the next node
-                    |p| nonterminal(q.next.clone(), &p),
+                    |p| nonterminal(ctxt, stctxt, q.next.clone(), &p),
                 )
             } else {
                 false
@@ -179,6 +206,119 @@ fn nonterminal<N: Node>(p: Option<Rc<Path>>, i: &Item<N>) -> bool {
     )
 }
 
+// Evaluate the predicates of a step for an item that matches the step's axis and node test.
+// The item is also the current item for the predicates.
+// The context position and size are those of the item amongst the siblings that match the step,
+// as if the step had been evaluated from the item's parent (XSLT 5.5.3).
+// Only the last predicate is evaluated for the item alone; the others must filter all of the siblings.
+fn satisfies<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    p: &Path<N>,
+    i: &Item<N>,
+) -> bool {
+    let preds: Vec<&Transform<N>> = match &p.predicates {
+        None => return true,
+        Some(Transform::Compose(v)) => v
+            .iter()
+            .map(|f| match f {
+                Transform::Filter(e) => e.as_ref(),
+                _ => f,
+            })
+            .collect(),
+        Some(t) => vec![t],
+    };
+    let mut candidates = siblings(p, i);
+    let (last, rest) = preds.split_last().unwrap();
+    for pred in rest {
+        let size = candidates.len();
+        let mut kept = vec![];
+        for (pos, c) in candidates.into_iter().enumerate() {
+            if predicate_holds(ctxt, stctxt, pred, &c, i, pos + 1, size) {
+                kept.push(c)
+            }
+        }
+        candidates = kept;
+    }
+    candidates
+        .iter()
+        .position(|c| is_same_item(c, i))
+        .is_some_and(|pos| predicate_holds(ctxt, stctxt, last, i, i, pos + 1, candidates.len()))
+}
+
+// The nodes that the step would select from the item's parent, in document order.
+// An item that has no parent, or a step that does not select from the self axis, has no other siblings.
+fn siblings<N: Node>(p: &Path<N>, i: &Item<N>) -> Vec<Item<N>> {
+    match (p.t.as_ref(), i) {
+        (Some(((term, _), nt)), Item::Node(n))
+            if matches!(term, Axis::SelfAxis | Axis::SelfAttribute) =>
+        {
+            n.parent().map_or_else(
+                || vec![i.clone()],
+                |parent| {
+                    let nodes = if n.node_type() == NodeType::Attribute {
+                        parent.attribute_iter()
+                    } else {
+                        parent.child_iter()
+                    };
+                    nodes
+                        .map(Item::Node)
+                        .filter(|c| is_match(term, nt, c))
+                        .collect()
+                },
+            )
+        }
+        _ => vec![i.clone()],
+    }
+}
+
+fn is_same_item<N: Node>(a: &Item<N>, b: &Item<N>) -> bool {
+    match (a, b) {
+        (Item::Node(m), Item::Node(n)) => m.is_same(n),
+        _ => false,
+    }
+}
+
+// A numeric predicate is true if it is equal to the context position, otherwise its effective boolean value is used (XPath 3.2.2).
+fn predicate_holds<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    pred: &Transform<N>,
+    c: &Item<N>,
+    current: &Item<N>,
+    position: usize,
+    size: usize,
+) -> bool {
+    ContextBuilder::from(ctxt)
+        .context(vec![c.clone()])
+        .position(position, size)
+        .previous_context(Some(current.clone()))
+        .build()
+        .dispatch(stctxt, pred)
+        .is_ok_and(|s| match s.as_slice() {
+            [Item::Value(v)]
+                if v.instance_of(&QualifiedName::new(
+                    Some(String::from(XSNS)),
+                    None,
+                    "numeric",
+                )) =>
+            {
+                v.to_double() == position as f64
+            }
+            _ => s.to_bool(),
+        })
+}
+
 fn is_match<N: Node>(a: &Axis, nt: &NodeTest, i: &Item<N>) -> bool {
     match a {
         Axis::SelfDocument => {
@@ -195,9 +335,23 @@ fn is_match<N: Node>(a: &Axis, nt: &NodeTest, i: &Item<N>) -> bool {
             }
         }
         Axis::SelfAxis => {
-            // Select item if it is an element-type node
-            nt.matches(i)
+            // The principal node kind is element, so attributes and namespaces are never selected
+            match i {
+                Item::Node(n) => {
+                    !matches!(n.node_type(), NodeType::Attribute | NodeType::Namespace)
+                        && nt.matches(i)
+                }
+                _ => false,
+            }
         }
+        Axis::SelfAttribute => match i {
+            Item::Node(n) => n.node_type() == NodeType::Attribute && nt.matches(i),
+            _ => false,
+        },
+        Axis::SelfNamespace => match i {
+            Item::Node(n) => n.node_type() == NodeType::Namespace && nt.matches(i),
+            _ => false,
+        },
         Axis::Parent => {
             // Select the parent node
             match i {
@@ -219,24 +373,35 @@ impl<N: Node> Debug for Pattern<N> {
     }
 }
 
-#[derive(Clone, Default, Debug)]
-pub struct Path {
+#[derive(Clone, Debug)]
+pub struct Path<N: Node> {
     //    Each step in the Path consists of (terminal, non-terminal) axes and a NodeTest
     // If next == None, then the terminal axis is used.
     // Otherwise the non-terminal axis applies.
     pub t: Option<((Axis, Axis), NodeTest)>,
-    pub next: Option<Rc<Path>>,
+    // The predicates of the step, if any
+    pub predicates: Option<Transform<N>>,
+    pub next: Option<Rc<Path<N>>>,
 }
 
-impl Path {
+impl<N: Node> Path<N> {
     pub fn new() -> Self {
-        //Path { t: None, next: None }
-        Default::default()
+        Path {
+            t: None,
+            predicates: None,
+            next: None,
+        }
+    }
+}
+
+impl<N: Node> Default for Path<N> {
+    fn default() -> Self {
+        Path::new()
     }
 }
 
-pub struct PathBuilder(Path);
-impl PathBuilder {
+pub struct PathBuilder<N: Node>(Path<N>);
+impl<N: Node> PathBuilder<N> {
     pub fn new() -> Self {
         PathBuilder(Path::new())
     }
@@ -244,7 +409,7 @@ impl PathBuilder {
         self.0.t = Some(((t, l), nt));
         self
     }
-    pub fn build(self) -> Path {
+    pub fn build(self) -> Path<N> {
         self.0
     }
 }
@@ -259,7 +424,7 @@ impl<N: Node> TryFrom<&str> for Pattern<N> {
                 String::from("empty string is not allowed as an XPath pattern"),
             ))
         } else {
-            let state = ParserState::new(None, None);
+            let state = ParserState::default();
             match pattern::<N>((e, state)) {
                 Ok(((rem, _), f)) => {
                     if rem.is_empty() {
@@ -527,26 +692,44 @@ fn paren_expr_pattern<'a, N: Node + 'a>(
 }
 
 // AxisStepP ::= ForwardStepP PredicateList
-// TODO: predicate
 fn axis_step_pattern<'a, N: Node + 'a>(
 ) -> Box<dyn Fn(ParseInput<N>) -> Result<(ParseInput<N>, Pattern<N>), ParseError> + 'a> {
     Box::new(map(
         tuple2(forward_step_pattern(), predicate_list::<N>()),
-        |(f, _p)| f, // TODO: pass predicate back to caller
+        |(f, p)| match (f, p) {
+            (Pattern::Selection(mut s), Transform::Compose(v)) if !v.is_empty() => {
+                s.predicates = Some(Transform::Compose(v));
+                Pattern::Selection(s)
+            }
+            (f, _) => f,
+        },
     ))
 }
 
 // ForwardStepP ::= (ForwardAxisP NodeTest) | AbbrevForwardStep
 // Returns the node test, the terminal axis and the non-terminal axis
-// TODO: abbreviated step
 fn forward_step_pattern<'a, N: Node + 'a>(
 ) -> Box<dyn Fn(ParseInput<N>) -> Result<(ParseInput<N>, Pattern<N>), ParseError> + 'a> {
     Box::new(map(
-        tuple2(forward_axis_pattern(), nodetest()),
+        alt2(
+            tuple2(forward_axis_pattern(), nodetest()),
+            abbrev_forward_step_pattern(),
+        ),
         |((a, c), nt)| Pattern::Selection(PathBuilder::new().step(a, c, nt).build()),
     ))
 }
 
+// AbbrevForwardStep ::= "@"? NodeTest
+// "@" is the attribute axis, otherwise the child axis
+fn abbrev_forward_step_pattern<'a, N: Node + 'a>(
+) -> Box<dyn Fn(ParseInput<N>) -> Result<(ParseInput<N>, ((Axis, Axis), NodeTest)), ParseError> + 'a>
+{
+    Box::new(map(pair(opt(tag("@")), nodetest()), |(a, nt)| match a {
+        Some(_) => ((Axis::SelfAttribute, Axis::Parent), nt),
+        None => ((Axis::SelfAxis, Axis::Parent), nt),
+    }))
+}
+
 // ForwardAxisP ::= ("child" | "descendant" | "attribute" | "self" | "descendant-or-self" | "namespace" ) "::"
 // Returns a pair: the axis to match this step, and the axis for the previous step
 // TODO: abbreviated step
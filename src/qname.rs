@@ -1,5 +1,6 @@
 //! Support for Qualified Names.
 
+use crate::output::OutputDefinition;
 use crate::parser::xml::qname::eqname;
 use crate::parser::ParserState;
 use crate::trees::nullo::Nullo;
@@ -72,6 +73,94 @@ impl QualifiedName {
             _ => Ok(()),
         }
     }
+    /// The name in expanded form, Q{uri}localname, for use in diagnostics.
+    /// A name that is not in a namespace is just its local name.
+    pub fn eqname(&self) -> String {
+        self.nsuri.as_ref().map_or_else(
+            || self.localname.clone(),
+            |u| format!("Q{{{}}}{}", u, self.localname),
+        )
+    }
+    /// Serialise the name, choosing a prefix that is bound to the name's namespace URI.
+    /// "ns" is the namespaces in scope, as (URI, prefix) pairs where a later declaration overrides an earlier one,
+    /// and a prefix of None is the default namespace.
+    /// The name's own prefix is used if it is bound to the right URI, otherwise a prefix that is already bound to the URI.
+    /// Failing that, the own prefix is declared or, if it is unusable, a new "nsN" prefix is invented.
+    /// An attribute in a namespace must have a prefix, since the default namespace does not apply to it.
+    /// A name without a prefix of its own uses the preferred prefix of the output definition for its namespace, if there is one.
+    /// If the output definition regenerates prefixes then a prefix that is bound to a different URI is never redeclared;
+    /// the preferred prefix, or a new "nsN" prefix, is used instead.
+    /// Invented prefixes do not depend on anything but the names and the namespaces in scope,
+    /// so a tree is serialised the same way every time.
+    /// Returns the lexical name, and the namespace declaration, (URI, prefix), that is required, if any.
+    pub fn serialize_in(
+        &self,
+        ns: &[(String, Option<String>)],
+        attribute: bool,
+        od: &OutputDefinition,
+    ) -> (String, Option<(String, Option<String>)>) {
+        let bound = |p: &Option<String>| {
+            ns.iter()
+                .rev()
+                .find(|(_, q)| q == p)
+                .map(|(u, _)| u.as_str())
+                .filter(|u| !u.is_empty())
+        };
+        let lexical = |p: &Option<String>| {
+            p.as_ref().map_or(self.localname.clone(), |q| {
+                format!("{}:{}", q, self.localname)
+            })
+        };
+        let usable = |p: &Option<String>| match p.as_deref() {
+            None => !attribute,
+            Some("xml") | Some("xmlns") => false,
+            Some(_) => true,
+        };
+        match self.nsuri.as_deref() {
+            None => {
+                // An unprefixed element may need to undeclare the default namespace
+                if !attribute && bound(&None).is_some() {
+                    (self.localname.clone(), Some((String::new(), None)))
+                } else {
+                    (self.localname.clone(), None)
+                }
+            }
+            Some("http://www.w3.org/XML/1998/namespace") => {
+                (format!("xml:{}", self.localname), None)
+            }
+            Some(uri) => {
+                let preferred = od.get_preferred_prefix(uri).map(String::from);
+                let own = if self.prefix.is_none() {
+                    preferred.clone()
+                } else {
+                    self.prefix.clone()
+                };
+                if usable(&own) && bound(&own) == Some(uri) {
+                    return (lexical(&own), None);
+                }
+                if let Some((_, p)) = ns
+                    .iter()
+                    .rev()
+                    .find(|(u, p)| u == uri && usable(p) && bound(p) == Some(uri))
+                {
+                    return (lexical(p), None);
+                }
+                // An element may redeclare its own prefix, but an attribute must not disturb its element's prefix
+                let redeclare = !(attribute || (od.get_regenerate_prefixes() && own.is_some()));
+                let p = if usable(&own) && (redeclare || bound(&own).is_none()) {
+                    own
+                } else {
+                    preferred
+                        .into_iter()
+                        .map(Some)
+                        .chain((0..).map(|i| Some(format!("ns{}", i))))
+                        .find(|p| usable(p) && ns.iter().all(|(_, q)| q != p))
+                        .unwrap()
+                };
+                (lexical(&p), Some((uri.to_string(), p)))
+            }
+        }
+    }
 }
 
 impl fmt::Display for QualifiedName {
@@ -158,10 +247,10 @@ impl Hash for QualifiedName {
 impl TryFrom<&str> for QualifiedName {
     type Error = Error;
     fn try_from(s: &str) -> Result<Self, Self::Error> {
-        let state: ParserState<Nullo> = ParserState::new(None, None);
+        let state: ParserState<Nullo> = ParserState::default();
         match eqname()((s, state)) {
-            Ok((_, qn)) => Ok(qn),
-            Err(_) => Err(Error::new(
+            Ok((("", _), qn)) => Ok(qn),
+            _ => Err(Error::new(
                 ErrorKind::ParseError,
                 String::from("unable to parse qualified name"),
             )),
@@ -175,9 +264,9 @@ impl TryFrom<&str> for QualifiedName {
 impl TryFrom<(&str, &Vec<HashMap<String, String>>)> for QualifiedName {
     type Error = Error;
     fn try_from(s: (&str, &Vec<HashMap<String, String>>)) -> Result<Self, Self::Error> {
-        let state: ParserState<Nullo> = ParserState::new(None, None);
+        let state: ParserState<Nullo> = ParserState::default();
         match eqname()((s.0, state)) {
-            Ok((_, qn)) => {
+            Ok((("", _), qn)) => {
                 if qn.get_prefix().is_some() && qn.get_nsuri_ref().is_none() {
                     match s
                         .1
@@ -191,16 +280,21 @@ impl TryFrom<(&str, &Vec<HashMap<String, String>>)> for QualifiedName {
                             Some(qn.get_prefix().unwrap()),
                             qn.get_localname(),
                         )),
-                        _ => Err(Error::new(
-                            ErrorKind::Unknown,
-                            format!("unable to match prefix \"{}\"", qn.get_prefix().unwrap()),
+                        _ => Err(Error::new_with_code(
+                            ErrorKind::StaticNamespace,
+                            format!(
+                                "unable to match prefix \"{}\", namespaces in scope are: {}",
+                                qn.get_prefix().unwrap(),
+                                namespaces_to_string(s.1)
+                            ),
+                            Some(QualifiedName::new(None, None, "XPST0081")),
                         )),
                     }
                 } else {
                     Ok(qn)
                 }
             }
-            Err(_) => Err(Error::new(
+            _ => Err(Error::new(
                 ErrorKind::ParseError,
                 String::from("unable to parse qualified name"),
             )),
@@ -208,6 +302,22 @@ impl TryFrom<(&str, &Vec<HashMap<String, String>>)> for QualifiedName {
     }
 }
 
+/// List a set of XML Namespace declarations, for use in error messages.
+/// Each declaration is shown as prefix=URI, in prefix order.
+pub fn namespaces_to_string(namespaces: &[HashMap<String, String>]) -> String {
+    let mut decls: Vec<String> = namespaces
+        .iter()
+        .flat_map(|h| h.iter().map(|(p, u)| format!("{}={}", p, u)))
+        .collect();
+    decls.sort();
+    decls.dedup();
+    if decls.is_empty() {
+        String::from("(none)")
+    } else {
+        decls.join(", ")
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
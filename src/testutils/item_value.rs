@@ -3,7 +3,9 @@ macro_rules! item_value_tests (
     ( $x:ty ) => {
 	use std::rc::Rc;
 	use xrust::value::Value;
-	use xrust::item::{Sequence, SequenceTrait, Item};
+	use xrust::item::{Sequence, SequenceTrait, Item, Function};
+	use xrust::transform::Transform;
+	use xrust::value::Operator;
 
 	#[test]
 	fn item_value_string_empty_to_bool() {
@@ -36,6 +38,44 @@ macro_rules! item_value_tests (
 	    assert_eq!(Item::<$x>::Value(Rc::new(Value::from("2.0"))).to_double(), 2.0)
 	}
 
+	fn function_item() -> Item<$x> {
+	    Item::Function(Rc::new(Function::new(None, vec![], Transform::Empty)))
+	}
+	fn error_code<T>(r: Result<T, xrust::xdmerror::Error>) -> String {
+	    match r {
+		Ok(_) => panic!("operation on function item succeeded"),
+		Err(e) => e.code.map_or(String::new(), |c| c.to_string()),
+	    }
+	}
+	#[test]
+	fn item_function_string_value() {
+	    assert_eq!(error_code(function_item().string_value()), "FOTY0014")
+	}
+	#[test]
+	fn item_function_atomize() {
+	    assert_eq!(error_code(function_item().atomize()), "FOTY0013")
+	}
+	#[test]
+	fn item_function_compare() {
+	    assert_eq!(
+		error_code(function_item().compare(&Item::Value(Rc::new(Value::from(1))), Operator::Equal)),
+		"FOTY0013"
+	    );
+	    assert_eq!(
+		error_code(Item::Value(Rc::new(Value::from(1))).compare(&function_item(), Operator::Equal)),
+		"FOTY0013"
+	    )
+	}
+	#[test]
+	fn item_function_to_int() {
+	    assert_eq!(error_code(function_item().to_int()), "FOTY0013")
+	}
+	#[test]
+	fn item_function_effective_boolean_value() {
+	    assert_eq!(error_code(function_item().effective_boolean_value()), "FORG0006");
+	    assert_eq!(error_code(vec![function_item()].effective_boolean_value()), "FORG0006")
+	}
+
 	#[test]
 	fn sequence() {
             let _s = Sequence::<$x>::new();
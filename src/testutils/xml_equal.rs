@@ -0,0 +1,360 @@
+/*! Compare a result with expected XML.
+
+Comparing the serialisation of a result with an expected string is brittle, since the same tree may be serialised with different namespace prefixes, quotes, or attribute order. Instead, the expected string is parsed and the trees are compared with deep-equal semantics: attributes may be in any order, and names are compared by namespace URI and local name, so the choice of prefix does not matter.
+
+```rust
+use std::rc::Rc;
+use xrust::item::Item;
+use xrust::parser::xml::parse;
+use xrust::testutils::xml_equal::assert_xml_equal;
+use xrust::trees::smite::Node as SmiteNode;
+
+let doc = parse(Rc::new(SmiteNode::new()), "<a:Test xmlns:a='urn:x' one='1' two='2'/>", None)
+    .expect("unable to parse XML");
+assert_xml_equal(&vec![Item::Node(doc)], r#"<Test xmlns="urn:x" two="2" one="1"/>"#)
+```
+*/
+
+use crate::item::{Item, Node, NodeType, Sequence};
+use crate::parser::xml::parse;
+use crate::trees::smite::Node as SmiteNode;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+// The expected XML is parsed as the content of this element, so that it may have several top-level nodes, or text.
+const WRAPPER: &str = "xrust-expected";
+
+/// Assert that a sequence is equal to an XML fragment.
+/// If it is not, then panic with a description of the first difference.
+pub fn assert_xml_equal<N: Node>(actual: &Sequence<N>, expected: &str) {
+    if let Err(d) = compare_xml(actual, expected) {
+        panic!("{}", d)
+    }
+}
+
+/// Compare a sequence with an XML fragment. The fragment may have any number of top-level nodes.
+/// A document node in the sequence is compared by its children, and atomic values are compared as text.
+/// Returns a description of the first difference.
+pub fn compare_xml<N: Node>(actual: &Sequence<N>, expected: &str) -> Result<(), String> {
+    let doc = parse(
+        Rc::new(SmiteNode::new()),
+        format!("<{0}>{1}</{0}>", WRAPPER, expected).as_str(),
+        None,
+    )
+    .map_err(|e| format!("unable to parse the expected XML: {}", e))?;
+    let wrapper = doc
+        .child_iter()
+        .find(|c| c.node_type() == NodeType::Element)
+        .ok_or_else(|| String::from("unable to parse the expected XML"))?;
+    compare_content(
+        &content(actual.iter().cloned()),
+        &content(wrapper.child_iter().map(Item::Node)),
+        "",
+    )
+}
+
+// The content of a tree, in a form that can be compared.
+// Adjacent text is merged, since a sequence may have several text nodes or values where the parsed XML has one text node.
+enum Content<N: Node> {
+    Text(String),
+    Node(N),
+}
+
+fn content<N: Node>(items: impl Iterator<Item = Item<N>>) -> Vec<Content<N>> {
+    let mut result = vec![];
+    items.for_each(|i| add_content(&mut result, i));
+    result
+}
+
+fn add_content<N: Node>(result: &mut Vec<Content<N>>, i: Item<N>) {
+    let text = match i {
+        Item::Node(n) => match n.node_type() {
+            NodeType::Document => {
+                n.child_iter()
+                    .for_each(|c| add_content(result, Item::Node(c)));
+                return;
+            }
+            NodeType::Text => n.to_string(),
+            _ => {
+                result.push(Content::Node(n));
+                return;
+            }
+        },
+        _ => i.to_string(),
+    };
+    if text.is_empty() {
+        return;
+    }
+    match result.last_mut() {
+        Some(Content::Text(t)) => t.push_str(text.as_str()),
+        _ => result.push(Content::Text(text)),
+    }
+}
+
+fn compare_content<A: Node, B: Node>(
+    actual: &[Content<A>],
+    expected: &[Content<B>],
+    path: &str,
+) -> Result<(), String> {
+    // Steps in the path are numbered amongst siblings of the same kind and name
+    let mut positions: BTreeMap<String, usize> = BTreeMap::new();
+    for i in 0..actual.len().max(expected.len()) {
+        let step = match expected.get(i).map_or_else(
+            || actual.get(i).map(content_step),
+            |e| Some(content_step(e)),
+        ) {
+            Some(s) => s,
+            None => break,
+        };
+        let p = positions.entry(step.clone()).or_insert(0);
+        *p += 1;
+        let here = format!("{}/{}[{}]", path, step, p);
+        match (actual.get(i), expected.get(i)) {
+            (Some(Content::Text(a)), Some(Content::Text(e))) => {
+                if a != e {
+                    return Err(format!(
+                        "at {}: expected text \"{}\", found text \"{}\"",
+                        here, e, a
+                    ));
+                }
+            }
+            (Some(Content::Node(a)), Some(Content::Node(e))) => compare_nodes(a, e, &here)?,
+            (Some(a), Some(e)) => {
+                return Err(format!(
+                    "at {}: expected {}, found {}",
+                    here,
+                    describe(e),
+                    describe(a)
+                ))
+            }
+            (None, Some(e)) => {
+                return Err(format!(
+                    "at {}: expected {}, found nothing",
+                    here,
+                    describe(e)
+                ))
+            }
+            (Some(a), None) => {
+                return Err(format!("at {}: found unexpected {}", here, describe(a)))
+            }
+            (None, None) => break,
+        }
+    }
+    Ok(())
+}
+
+fn compare_nodes<A: Node, B: Node>(actual: &A, expected: &B, path: &str) -> Result<(), String> {
+    let differ = || {
+        Err(format!(
+            "at {}: expected {}, found {}",
+            path,
+            describe_node(expected),
+            describe_node(actual)
+        ))
+    };
+    if actual.node_type() != expected.node_type() {
+        return differ();
+    }
+    match expected.node_type() {
+        NodeType::Element => {
+            if actual.name().eqname() != expected.name().eqname() {
+                return differ();
+            }
+            let a_atts = attributes(actual);
+            let e_atts = attributes(expected);
+            if let Some((n, v)) = e_atts.iter().find(|(n, _)| !a_atts.contains_key(*n)) {
+                return Err(format!(
+                    "at {}: expected attribute {}=\"{}\", found no such attribute",
+                    path, n, v
+                ));
+            }
+            if let Some((n, v)) = a_atts.iter().find(|(n, _)| !e_atts.contains_key(*n)) {
+                return Err(format!(
+                    "at {}: found unexpected attribute {}=\"{}\"",
+                    path, n, v
+                ));
+            }
+            if let Some((n, v)) = e_atts.iter().find(|(n, v)| a_atts.get(*n) != Some(*v)) {
+                return Err(format!(
+                    "at {}: expected attribute {}=\"{}\", found {}=\"{}\"",
+                    path, n, v, n, a_atts[n]
+                ));
+            }
+            compare_content(
+                &content(actual.child_iter().map(Item::Node)),
+                &content(expected.child_iter().map(Item::Node)),
+                path,
+            )
+        }
+        NodeType::ProcessingInstruction | NodeType::Attribute | NodeType::Namespace => {
+            if actual.name().eqname() != expected.name().eqname()
+                || actual.value().to_string() != expected.value().to_string()
+            {
+                differ()
+            } else {
+                Ok(())
+            }
+        }
+        _ => {
+            if actual.value().to_string() != expected.value().to_string() {
+                differ()
+            } else {
+                Ok(())
+            }
+        }
+    }
+}
+
+// The attributes of an element, keyed by expanded name.
+// Namespace declarations are not attributes, since prefixes do not matter.
+fn attributes<N: Node>(n: &N) -> BTreeMap<String, String> {
+    n.attribute_iter()
+        .filter(|a| {
+            let qn = a.name();
+            qn.get_nsuri_ref() != Some("http://www.w3.org/2000/xmlns/")
+                && qn.get_prefix().as_deref() != Some("xmlns")
+                && !(qn.get_prefix().is_none() && qn.get_localname() == "xmlns")
+        })
+        .map(|a| (a.name().eqname(), a.value().to_string()))
+        .collect()
+}
+
+fn content_step<N: Node>(c: &Content<N>) -> String {
+    match c {
+        Content::Text(_) => String::from("text()"),
+        Content::Node(n) => match n.node_type() {
+            NodeType::Element => n.name().eqname(),
+            NodeType::Attribute => format!("@{}", n.name().eqname()),
+            NodeType::Comment => String::from("comment()"),
+            NodeType::ProcessingInstruction => String::from("processing-instruction()"),
+            _ => String::from("node()"),
+        },
+    }
+}
+
+fn describe<N: Node>(c: &Content<N>) -> String {
+    match c {
+        Content::Text(t) => format!("text \"{}\"", t),
+        Content::Node(n) => describe_node(n),
+    }
+}
+
+fn describe_node<N: Node>(n: &N) -> String {
+    match n.node_type() {
+        NodeType::Element => format!("element {} {}", n.name().eqname(), n.to_xml()),
+        NodeType::Attribute => format!("attribute {}=\"{}\"", n.name().eqname(), n.value()),
+        NodeType::Comment => format!("comment \"{}\"", n.value()),
+        NodeType::ProcessingInstruction => format!(
+            "processing instruction {} \"{}\"",
+            n.name().eqname(),
+            n.value()
+        ),
+        t => format!("{} node \"{}\"", t, n.value()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qname::QualifiedName;
+    use crate::trees::smite::RNode;
+    use crate::value::Value;
+
+    fn sequence(s: &str) -> Sequence<RNode> {
+        vec![Item::Node(
+            parse(Rc::new(SmiteNode::new()), s, None).expect("unable to parse XML"),
+        )]
+    }
+
+    #[test]
+    fn xml_equal_attribute_order() {
+        assert_eq!(
+            compare_xml(
+                &sequence("<Test one='1' two='2'><a/></Test>"),
+                r#"<Test two="2" one="1"><a></a></Test>"#
+            ),
+            Ok(())
+        )
+    }
+    #[test]
+    fn xml_equal_prefix() {
+        assert_eq!(
+            compare_xml(
+                &sequence("<p:Test xmlns:p='urn:x' p:att='v'><p:a/></p:Test>"),
+                "<Test xmlns='urn:x' xmlns:q='urn:x' q:att='v'><a/></Test>"
+            ),
+            Ok(())
+        )
+    }
+    #[test]
+    fn xml_equal_namespace_uri() {
+        assert!(compare_xml(
+            &sequence("<p:Test xmlns:p='urn:x'><p:a/></p:Test>"),
+            "<p:Test xmlns:p='urn:x'><p:a xmlns:p='urn:y'/></p:Test>"
+        )
+        .is_err_and(
+            |d| d.starts_with("at /Q{urn:x}Test[1]/Q{urn:y}a[1]: expected element Q{urn:y}a ")
+        ))
+    }
+    #[test]
+    fn xml_equal_attribute_value() {
+        assert_eq!(
+            compare_xml(
+                &sequence("<Test><a b='1'/></Test>"),
+                "<Test><a b='2'/></Test>"
+            ),
+            Err(String::from(
+                "at /Test[1]/a[1]: expected attribute b=\"2\", found b=\"1\""
+            ))
+        )
+    }
+    #[test]
+    fn xml_equal_missing_attribute() {
+        assert_eq!(
+            compare_xml(&sequence("<Test/>"), "<Test b='2'/>"),
+            Err(String::from(
+                "at /Test[1]: expected attribute b=\"2\", found no such attribute"
+            ))
+        )
+    }
+    #[test]
+    fn xml_equal_text() {
+        // Adjacent text and values are merged
+        let d = Rc::new(SmiteNode::new());
+        let t = d
+            .new_text(Rc::new(Value::from("one")))
+            .expect("unable to create text node");
+        let s: Sequence<RNode> = vec![
+            Item::Node(t),
+            Item::Value(Rc::new(Value::from("two"))),
+            Item::Node(
+                d.new_element(QualifiedName::new(None, None, "a"))
+                    .expect("unable to create element"),
+            ),
+        ];
+        assert_eq!(compare_xml(&s, "onetwo<a/>"), Ok(()));
+        assert_eq!(
+            compare_xml(&s, "one two<a/>"),
+            Err(String::from(
+                "at /text()[1]: expected text \"one two\", found text \"onetwo\""
+            ))
+        )
+    }
+    #[test]
+    fn xml_equal_extra_content() {
+        assert_eq!(
+            compare_xml(
+                &sequence("<Test><a/><a/><!--c--></Test>"),
+                "<Test><a/><a/></Test>"
+            ),
+            Err(String::from(
+                "at /Test[1]/comment()[1]: found unexpected comment \"c\""
+            ))
+        )
+    }
+    #[test]
+    #[should_panic(expected = "at /Test[1]/b[1]: expected element b <b></b>, found nothing")]
+    fn xml_equal_assert() {
+        assert_xml_equal(&sequence("<Test><a/></Test>"), "<Test><a/><b/></Test>")
+    }
+}
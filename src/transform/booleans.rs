@@ -5,6 +5,7 @@ use url::Url;
 
 use crate::item::{Item, Node, Sequence, SequenceTrait};
 use crate::transform::context::{Context, StaticContext};
+use crate::transform::navigate::first_items;
 use crate::transform::Transform;
 use crate::value::Value;
 use crate::xdmerror::Error;
@@ -21,7 +22,7 @@ pub fn boolean<
     b: &Transform<N>,
 ) -> Result<Sequence<N>, Error> {
     Ok(vec![Item::Value(Rc::new(Value::Boolean(
-        ctxt.dispatch(stctxt, b)?.to_bool(),
+        first_items(ctxt, stctxt, b, 2)?.effective_boolean_value()?,
     )))])
 }
 
@@ -37,7 +38,7 @@ pub fn not<
     n: &Transform<N>,
 ) -> Result<Sequence<N>, Error> {
     Ok(vec![Item::Value(Rc::new(Value::Boolean(
-        !ctxt.dispatch(stctxt, n)?.to_bool(),
+        !first_items(ctxt, stctxt, n, 2)?.effective_boolean_value()?,
     )))])
 }
 
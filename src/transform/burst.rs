@@ -0,0 +1,399 @@
+//! Burst-mode processing of a source document.
+//!
+//! A document that xsl:source-document requests to be streamed is processed one selected element at a time,
+//! rather than being materialized in full. This is possible when the content of xsl:source-document
+//! consists only of text, and of xsl:apply-templates and xsl:for-each instructions whose select expression
+//! is a path of child steps with name tests, such as "child::list/child::item", without predicates or sort keys.
+//!
+//! The document text is scanned for the selected elements. Each one is parsed, along with the start and end tags
+//! of its ancestors, but not their other content, and then processed before the next one is found.
+//! Templates and the body of xsl:for-each may therefore navigate within the selected element and to its ancestors,
+//! but the siblings of the element and of its ancestors are not available.
+//! A document with a document type declaration is not scanned, since its entities could not be expanded.
+//!
+//! Anything else is fully materialized instead, and the message callback is told why.
+
+use std::ops::Range;
+use url::Url;
+
+use crate::item::{Item, Node, NodeType, Sequence};
+use crate::qname::QualifiedName;
+use crate::transform::context::{Context, ContextBuilder, StaticContext};
+use crate::transform::template::apply_template_to;
+use crate::transform::{Axis, NameTest, NodeMatch, NodeTest, Transform, WildcardOrName};
+use crate::xdmerror::{Error, ErrorKind};
+
+const XMLNS: &str = "http://www.w3.org/XML/1998/namespace";
+
+/// How an instruction in the content of xsl:source-document is evaluated.
+enum Burst<'a, N: Node> {
+    /// Text, which does not depend on the document
+    Text(&'a Transform<N>),
+    /// Apply templates, in the given mode, to each selected element
+    ApplyTemplates(Vec<&'a NameTest>, &'a Option<QualifiedName>),
+    /// Evaluate the body for each selected element
+    ForEach(Vec<&'a NameTest>, &'a Transform<N>),
+}
+
+/// Evaluates the content of xsl:source-document one selected element at a time.
+/// If either the content or the document is not suitable for burst-mode processing,
+/// then the reason is passed to the message callback, nothing is evaluated, and the result is None.
+pub(crate) fn burst_document<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    uri: &str,
+    text: &str,
+    body: &Transform<N>,
+) -> Result<Option<Sequence<N>>, Error> {
+    let plan = match plan(body) {
+        Some(p) => p,
+        None => {
+            return fallback(
+                stctxt,
+                uri,
+                "its content does not only select elements by a path of child steps",
+            )
+        }
+    };
+    // Count the elements that each instruction selects.
+    // This also finds any problem with the document before anything is evaluated.
+    let mut sizes = vec![];
+    for b in &plan {
+        match b {
+            Burst::Text(_) => sizes.push(0),
+            Burst::ApplyTemplates(steps, _) | Burst::ForEach(steps, _) => {
+                match scan(text, steps, |_, _| Ok(())) {
+                    Ok(n) => sizes.push(n),
+                    Err(e) => return fallback(stctxt, uri, e.message.as_str()),
+                }
+            }
+        }
+    }
+
+    let mut result = vec![];
+    for (b, size) in plan.iter().zip(sizes) {
+        match b {
+            Burst::Text(t) => result.append(&mut ctxt.dispatch(stctxt, t)?),
+            Burst::ApplyTemplates(steps, m) => {
+                let depth = ctxt.deeper(stctxt)?;
+                let mut p = 0;
+                scan(text, steps, |ancestors, element| {
+                    p += 1;
+                    let i = selected(stctxt, fragment(text, ancestors, element), steps.len())?;
+                    result.append(&mut apply_template_to(ctxt, stctxt, &i, p, size, m, depth)?);
+                    Ok(())
+                })?;
+            }
+            Burst::ForEach(steps, t) => {
+                let mut p = 0;
+                scan(text, steps, |ancestors, element| {
+                    p += 1;
+                    let i = selected(stctxt, fragment(text, ancestors, element), steps.len())?;
+                    result.append(
+                        &mut ContextBuilder::from(ctxt)
+                            .context(vec![i.clone()])
+                            .position(p, size)
+                            .previous_context(Some(i))
+                            .build()
+                            .dispatch(stctxt, t)?,
+                    );
+                    Ok(())
+                })?;
+            }
+        }
+    }
+    Ok(Some(result))
+}
+
+fn fallback<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    stctxt: &mut StaticContext<N, F, G, H>,
+    uri: &str,
+    reason: &str,
+) -> Result<Option<Sequence<N>>, Error> {
+    if let Some(f) = &mut stctxt.message {
+        f(format!(
+            "xsl:source-document: {} cannot be streamed, since {}, so it will be fully materialized",
+            uri, reason
+        )
+        .as_str())?
+    }
+    Ok(None)
+}
+
+/// Find how each instruction is evaluated. The result is None if any instruction cannot be evaluated in burst mode.
+fn plan<N: Node>(body: &Transform<N>) -> Option<Vec<Burst<'_, N>>> {
+    let instructions: Vec<&Transform<N>> = match body {
+        Transform::SequenceItems(v) => v.iter().collect(),
+        t => vec![t],
+    };
+    instructions
+        .into_iter()
+        .map(|t| match t {
+            Transform::LiteralText(s, _) if matches!(s.as_ref(), Transform::Literal(_)) => {
+                Some(Burst::Text(t))
+            }
+            Transform::ApplyTemplates(s, m, o) if o.is_empty() => {
+                steps(s).map(|p| Burst::ApplyTemplates(p, m))
+            }
+            Transform::ForEach(None, s, b, o) if o.is_empty() => {
+                steps(s).map(|p| Burst::ForEach(p, b))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// The name tests of a select expression that is a path of child steps.
+fn steps<N: Node>(s: &Transform<N>) -> Option<Vec<&NameTest>> {
+    fn add<'a, N: Node>(t: &'a Transform<N>, v: &mut Vec<&'a NameTest>) -> bool {
+        match t {
+            Transform::Step(NodeMatch {
+                axis: Axis::Child,
+                nodetest: NodeTest::Name(nt),
+            }) => {
+                v.push(nt);
+                true
+            }
+            // An empty predicate list is also a Compose
+            Transform::Compose(c) => c.iter().all(|u| add(u, v)),
+            _ => false,
+        }
+    }
+    let mut v = vec![];
+    if add(s, &mut v) && !v.is_empty() {
+        Some(v)
+    } else {
+        None
+    }
+}
+
+/// Parses an element, wrapped in its ancestors, and finds it in the resulting document.
+fn selected<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    stctxt: &mut StaticContext<N, F, G, H>,
+    xml: String,
+    depth: usize,
+) -> Result<Item<N>, Error> {
+    let doc = match &mut stctxt.parser {
+        Some(g) => g(xml.as_str())?,
+        None => {
+            return Err(Error::new(
+                ErrorKind::StaticAbsent,
+                "function to parse document not supplied",
+            ))
+        }
+    };
+    (0..depth)
+        .try_fold(doc, |n, _| {
+            n.child_iter().find(|c| c.node_type() == NodeType::Element)
+        })
+        .map(Item::Node)
+        .ok_or(Error::new(
+            ErrorKind::Unknown,
+            "selected element not found in its parsed subtree",
+        ))
+}
+
+/// An element that is open while the document is scanned.
+struct Open<'a> {
+    /// The name of the element, as written
+    name: &'a str,
+    /// The start tag of the element
+    tag: Range<usize>,
+    /// The namespace declarations of the element: the prefix, or None for the default namespace, and the URI
+    namespaces: Vec<(Option<&'a str>, &'a str)>,
+    /// Whether the element, and all of its ancestors, match the steps of the path
+    on_path: bool,
+}
+
+/// Scans the text of a document for the elements that are selected by a path of child steps.
+/// For each one, the callback is given the open ancestors of the element and the range of the element in the text.
+/// The result is the number of elements found.
+fn scan<'a>(
+    text: &'a str,
+    steps: &[&NameTest],
+    mut f: impl FnMut(&[Open<'a>], Range<usize>) -> Result<(), Error>,
+) -> Result<usize, Error> {
+    let mut open: Vec<Open> = vec![];
+    let mut count = 0;
+    let mut pos = 0;
+    while let Some(lt) = text[pos..].find('<').map(|i| pos + i) {
+        let rest = &text[lt..];
+        if rest.starts_with("<!--") {
+            pos = skip(text, lt, "-->")?
+        } else if rest.starts_with("<![CDATA[") {
+            pos = skip(text, lt, "]]>")?
+        } else if rest.starts_with("<?") {
+            pos = skip(text, lt, "?>")?
+        } else if rest.starts_with("<!") {
+            return Err(scan_error("the document has a document type declaration"));
+        } else if rest.starts_with("</") {
+            pos = skip(text, lt, ">")?;
+            let e = open
+                .pop()
+                .ok_or(scan_error("the document is not well-formed"))?;
+            if e.on_path && open.len() + 1 == steps.len() {
+                count += 1;
+                f(&open, e.tag.start..pos)?
+            }
+        } else {
+            let (mut e, empty) = start_tag(text, lt)?;
+            pos = e.tag.end;
+            let depth = open.len();
+            e.on_path = depth < steps.len()
+                && open.iter().all(|o| o.on_path)
+                && resolve(&e, &open).map(|(ns, local)| name_matches(steps[depth], ns, local))?;
+            if !empty {
+                open.push(e)
+            } else if e.on_path && depth + 1 == steps.len() {
+                count += 1;
+                f(&open, lt..pos)?
+            }
+        }
+    }
+    if open.is_empty() {
+        Ok(count)
+    } else {
+        Err(scan_error("the document is not well-formed"))
+    }
+}
+
+/// The position after the given terminator.
+fn skip(text: &str, from: usize, terminator: &str) -> Result<usize, Error> {
+    text[from..]
+        .find(terminator)
+        .map(|i| from + i + terminator.len())
+        .ok_or(scan_error("the document is not well-formed"))
+}
+
+/// Scans a start tag, or an empty element tag, which begins at the given position.
+/// The result is the element and whether it is empty.
+fn start_tag(text: &str, lt: usize) -> Result<(Open<'_>, bool), Error> {
+    let bytes = text.as_bytes();
+    let malformed = || scan_error("the document is not well-formed");
+    let name_end = text[lt + 1..]
+        .find(|c: char| c.is_ascii_whitespace() || c == '/' || c == '>')
+        .map(|i| lt + 1 + i)
+        .ok_or_else(malformed)?;
+    let mut e = Open {
+        name: &text[lt + 1..name_end],
+        tag: lt..name_end,
+        namespaces: vec![],
+        on_path: false,
+    };
+    let mut i = name_end;
+    loop {
+        while matches!(bytes.get(i), Some(b) if b.is_ascii_whitespace()) {
+            i += 1
+        }
+        match bytes.get(i) {
+            Some(b'>') => {
+                e.tag.end = i + 1;
+                return Ok((e, false));
+            }
+            Some(b'/') if bytes.get(i + 1) == Some(&b'>') => {
+                e.tag.end = i + 2;
+                return Ok((e, true));
+            }
+            Some(_) => {
+                // An attribute: its name, an equals sign, and a quoted value
+                let eq = text[i..].find('=').map(|n| i + n).ok_or_else(malformed)?;
+                let name = text[i..eq].trim();
+                let mut q = eq + 1;
+                while matches!(bytes.get(q), Some(b) if b.is_ascii_whitespace()) {
+                    q += 1
+                }
+                let quote = match bytes.get(q) {
+                    Some(b'"') => '"',
+                    Some(b'\'') => '\'',
+                    _ => return Err(malformed()),
+                };
+                let close = text[q + 1..]
+                    .find(quote)
+                    .map(|n| q + 1 + n)
+                    .ok_or_else(malformed)?;
+                if name == "xmlns" {
+                    e.namespaces.push((None, &text[q + 1..close]))
+                } else if let Some(p) = name.strip_prefix("xmlns:") {
+                    e.namespaces.push((Some(p), &text[q + 1..close]))
+                }
+                i = close + 1
+            }
+            None => return Err(malformed()),
+        }
+    }
+}
+
+/// The namespace URI and local name of an element, using the namespace declarations of the element and its ancestors.
+fn resolve<'a>(e: &Open<'a>, ancestors: &[Open<'a>]) -> Result<(Option<&'a str>, &'a str), Error> {
+    let (prefix, local) = match e.name.split_once(':') {
+        Some((p, l)) => (Some(p), l),
+        None => (None, e.name),
+    };
+    if prefix == Some("xml") {
+        return Ok((Some(XMLNS), local));
+    }
+    let declared = e
+        .namespaces
+        .iter()
+        .chain(ancestors.iter().rev().flat_map(|a| a.namespaces.iter()))
+        .find(|(p, _)| *p == prefix)
+        .map(|(_, u)| *u);
+    match (prefix, declared) {
+        (_, Some("")) | (None, None) => Ok((None, local)),
+        (_, Some(u)) => Ok((Some(u), local)),
+        (Some(p), None) => Err(scan_error(
+            format!("the namespace prefix \"{}\" is not declared", p).as_str(),
+        )),
+    }
+}
+
+/// Does an element's name match a name test?
+fn name_matches(nt: &NameTest, ns: Option<&str>, local: &str) -> bool {
+    let ns_matches = match (&nt.ns, ns) {
+        (None, None) => true,
+        (None, Some(_)) => false,
+        (Some(WildcardOrName::Wildcard), _) => true,
+        (Some(WildcardOrName::Name(u)), Some(v)) => u == v,
+        (Some(WildcardOrName::Name(_)), None) => false,
+    };
+    ns_matches
+        && match &nt.name {
+            Some(WildcardOrName::Wildcard) => true,
+            Some(WildcardOrName::Name(n)) => n == local,
+            None => false,
+        }
+}
+
+/// The text of an element, wrapped in the start and end tags of its ancestors.
+fn fragment(text: &str, ancestors: &[Open], element: Range<usize>) -> String {
+    let mut xml = String::new();
+    ancestors
+        .iter()
+        .for_each(|a| xml.push_str(&text[a.tag.clone()]));
+    xml.push_str(&text[element]);
+    ancestors.iter().rev().for_each(|a| {
+        xml.push_str("</");
+        xml.push_str(a.name);
+        xml.push('>')
+    });
+    xml
+}
+
+fn scan_error(reason: &str) -> Error {
+    Error::new(ErrorKind::ParseError, reason)
+}
@@ -3,12 +3,11 @@
 //! The difference between them is that named templates have named parameters,
 //! whereas functions have positional parameters.
 
-// TODO: tunneling parameters
-
 use crate::item::Node;
 use crate::qname::QualifiedName;
 use crate::transform::context::StaticContext;
-use crate::transform::Transform;
+use crate::transform::types::{coerce, constructor, is_constructor};
+use crate::transform::{SequenceType, Transform};
 use crate::{Context, Error, ErrorKind, Sequence};
 use std::collections::HashMap;
 use url::Url;
@@ -17,19 +16,29 @@ use url::Url;
 pub struct Callable<N: Node> {
     pub(crate) body: Transform<N>,
     pub(crate) parameters: FormalParameters<N>,
-    // TODO: return type
+    pub(crate) result_type: Option<SequenceType>,
 }
 
 impl<N: Node> Callable<N> {
     pub fn new(body: Transform<N>, parameters: FormalParameters<N>) -> Self {
-        Callable { body, parameters }
+        Callable {
+            body,
+            parameters,
+            result_type: None,
+        }
+    }
+    /// Declare the type of the result. The result of the body is converted to this type,
+    /// and it is a type error (XTTE0780) if that is not possible.
+    pub fn result_type(mut self, t: SequenceType) -> Self {
+        self.result_type = Some(t);
+        self
     }
 }
 
 // TODO: parameter type ("as" attribute)
 #[derive(Clone, Debug)]
 pub enum FormalParameters<N: Node> {
-    Named(Vec<(QualifiedName, Option<Transform<N>>)>), // parameter name, default value
+    Named(Vec<(QualifiedName, Option<Transform<N>>)>), // parameter name, default value (None if the parameter is required)
     Positional(Vec<QualifiedName>),
 }
 #[derive(Clone, Debug)]
@@ -50,6 +59,9 @@ pub(crate) fn invoke<
     qn: &QualifiedName,
     a: &ActualParameters<N>,
 ) -> Result<Sequence<N>, Error> {
+    if let (Some(t), ActualParameters::Positional(args)) = (is_constructor(ctxt, qn), a) {
+        return constructor(ctxt, stctxt, &t, args);
+    }
     let mut qnr = qn.clone();
     qnr.resolve(ctxt.namespaces_ref())?;
     match ctxt.callables.get(&qnr) {
@@ -57,6 +69,7 @@ pub(crate) fn invoke<
             match &t.parameters {
                 FormalParameters::Named(v) => {
                     let mut newctxt = ctxt.clone();
+                    newctxt.depth = ctxt.deeper(stctxt)?;
                     // Put the actual parameters in a HashMap for easy access
                     let mut actuals = HashMap::new();
                     if let ActualParameters::Named(av) = a {
@@ -75,40 +88,122 @@ pub(crate) fn invoke<
                                 Ok(())
                             }
                             None => {
-                                // Use default value
+                                // Use default value. A parameter without a default value is required.
                                 if let Some(d) = dflt {
-                                    newctxt.var_push(name.to_string(), ctxt.dispatch(stctxt, d)?)
+                                    newctxt.var_push(name.to_string(), ctxt.dispatch(stctxt, d)?);
+                                    Ok(())
                                 } else {
-                                    newctxt.var_push(name.to_string(), vec![])
+                                    Err(missing_parameter(name))
                                 }
-                                Ok(())
                             }
                         }
                     })?;
                     newctxt.dispatch(stctxt, &t.body)
                 }
-                FormalParameters::Positional(v) => {
+                FormalParameters::Positional(_) => {
                     if let ActualParameters::Positional(av) = a {
-                        // Make sure number of parameters are equal, then set up variables by position
-                        if v.len() == av.len() {
-                            let mut newctxt = ctxt.clone();
-                            v.iter().zip(av.iter()).try_for_each(|(qn, t)| {
-                                newctxt.var_push(qn.to_string(), ctxt.dispatch(stctxt, t)?);
-                                Ok(())
-                            })?;
-                            newctxt.dispatch(stctxt, &t.body)
-                        } else {
-                            Err(Error::new(ErrorKind::TypeError, "argument mismatch"))
-                        }
+                        let args = av.iter().try_fold(vec![], |mut acc, t| {
+                            acc.push(ctxt.dispatch(stctxt, t)?);
+                            Ok::<_, Error>(acc)
+                        })?;
+                        call_function(ctxt, stctxt, &qnr, args)
                     } else {
                         Err(Error::new(ErrorKind::TypeError, "argument mismatch"))
                     }
                 }
             }
         }
-        None => Err(Error::new(
-            ErrorKind::Unknown,
-            format!("unknown callable \"{}\"", qn),
-        )),
+        None => match a {
+            ActualParameters::Positional(av)
+                if stctxt.functions.keys().any(|(name, _)| *name == qnr) =>
+            {
+                let args = av.iter().try_fold(vec![], |mut acc, t| {
+                    acc.push(ctxt.dispatch(stctxt, t)?);
+                    Ok::<_, Error>(acc)
+                })?;
+                call_function(ctxt, stctxt, &qnr, args)
+            }
+            _ => Err(Error::new(
+                ErrorKind::Unknown,
+                format!("unknown callable \"{}\"", qn.eqname()),
+            )),
+        },
     }
 }
+
+/// Call a function with positional parameters, where the arguments have already been evaluated.
+/// The number of arguments must be the same as the number of parameters.
+/// If there is no stylesheet function with the name, then the extension function with the name and arity is called.
+pub(crate) fn call_function<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    qn: &QualifiedName,
+    args: Vec<Sequence<N>>,
+) -> Result<Sequence<N>, Error> {
+    match ctxt.callables.get(qn) {
+        Some(Callable {
+            body,
+            parameters: FormalParameters::Positional(v),
+            result_type,
+        }) => {
+            if args.len() < v.len() {
+                // Every parameter of a function is required
+                Err(missing_parameter(&v[args.len()]))
+            } else if v.len() == args.len() {
+                let mut newctxt = ctxt.clone();
+                newctxt.depth = ctxt.deeper(stctxt)?;
+                // Tunnel parameters are not passed through a function call
+                newctxt.tunnel.clear();
+                v.iter()
+                    .zip(args)
+                    .for_each(|(p, a)| newctxt.var_push(p.to_string(), a));
+                let result = newctxt.dispatch(stctxt, body)?;
+                match result_type {
+                    Some(t) => coerce(result, t).map_err(|e| match e.kind {
+                        ErrorKind::StaticData | ErrorKind::NotImplemented => e,
+                        _ => Error::new_with_code(
+                            ErrorKind::TypeError,
+                            format!("result of function \"{}\": {}", qn.eqname(), e.message),
+                            Some(QualifiedName::new(None, None, "XTTE0780")),
+                        ),
+                    }),
+                    None => Ok(result),
+                }
+            } else {
+                Err(Error::new_with_code(
+                    ErrorKind::TypeError,
+                    format!(
+                        "function \"{}\" has {} parameters, but {} arguments were given",
+                        qn.eqname(),
+                        v.len(),
+                        args.len()
+                    ),
+                    Some(QualifiedName::new(None, None, "XPST0017")),
+                ))
+            }
+        }
+        _ => match stctxt.functions.get(&(qn.clone(), args.len())) {
+            // An extension function
+            Some(f) => f(args.as_slice()),
+            None => Err(Error::new_with_code(
+                ErrorKind::Unknown,
+                format!("unknown function \"{}\"", qn.eqname()),
+                Some(QualifiedName::new(None, None, "XPST0017")),
+            )),
+        },
+    }
+}
+
+/// The error for a required parameter that has not been supplied by the caller.
+pub(crate) fn missing_parameter(name: &QualifiedName) -> Error {
+    Error::new_with_code(
+        ErrorKind::DynamicAbsent,
+        format!("required parameter \"{}\" was not supplied", name),
+        Some(QualifiedName::new(None, None, "XTDE0700")),
+    )
+}
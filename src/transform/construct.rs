@@ -1,18 +1,21 @@
 //! These functions construct nodes, possibly destined for the result document.
 
-use crate::item::{Node, NodeType, Sequence, SequenceTrait};
+use crate::item::{Map, Node, NodeType, Sequence, SequenceTrait};
+use crate::output::comment_content;
+use crate::parser::common::{is_ncnamechar, is_ncnamestartchar};
 use crate::qname::QualifiedName;
 use crate::transform::context::{Context, StaticContext};
 use crate::transform::Transform;
 use crate::value::Value;
 use crate::xdmerror::{Error, ErrorKind};
 use crate::Item;
+use std::collections::HashMap;
 use std::rc::Rc;
 use url::Url;
 
 /// An empty sequence.
 pub(crate) fn empty<N: Node>(_ctxt: &Context<N>) -> Result<Sequence<N>, Error> {
-    Ok(Sequence::new())
+    Ok(Sequence::empty())
 }
 
 /// Creates a singleton sequence with the given value
@@ -22,6 +25,7 @@ pub(crate) fn literal<N: Node>(_ctxt: &Context<N>, val: &Item<N>) -> Result<Sequ
 
 /// Creates a singleton sequence with a new element node.
 /// The transform is evaluated to create the content of the element.
+/// If inherit is true then child elements are given the namespace nodes of the new element.
 pub(crate) fn literal_element<
     N: Node,
     F: FnMut(&str) -> Result<(), Error>,
@@ -32,6 +36,7 @@ pub(crate) fn literal_element<
     stctxt: &mut StaticContext<N, F, G, H>,
     qn: &QualifiedName,
     c: &Transform<N>,
+    inherit: &bool,
 ) -> Result<Sequence<N>, Error> {
     if ctxt.rd.is_none() {
         return Err(Error::new(
@@ -42,26 +47,17 @@ pub(crate) fn literal_element<
     let r = ctxt.rd.clone().unwrap();
 
     let mut e = r.new_element(qn.clone())?;
-    ctxt.dispatch(stctxt, c)?.iter().try_for_each(|i| {
-        // Item could be a Node or text
-        match i {
-            Item::Node(t) => match t.node_type() {
-                NodeType::Attribute => e.add_attribute(t.clone()),
-                _ => e.push(t.deep_copy()?),
-            },
-            _ => {
-                // Add the Value as a text node
-                let n = r.new_text(Rc::new(Value::from(i.to_string())))?;
-                e.push(n)
-            }
-        }
-    })?;
+    let content = normalize_content(&r, ctxt.dispatch(stctxt, c)?)?;
+    add_content(ctxt, stctxt, &r, &mut e, content, *inherit)?;
     Ok(vec![Item::Node(e)])
 }
 
 /// Creates a singleton sequence with a new element node.
 /// The name is interpreted as an AVT to determine the element name.
+/// If a namespace URI is given then it determines the namespace of the element,
+/// otherwise the prefix of the name is resolved using the given namespace declarations.
 /// The transform is evaluated to create the content of the element.
+/// If inherit is true then child elements are given the namespace nodes of the new element.
 pub(crate) fn element<
     N: Node,
     F: FnMut(&str) -> Result<(), Error>,
@@ -72,6 +68,9 @@ pub(crate) fn element<
     stctxt: &mut StaticContext<N, F, G, H>,
     qn: &Transform<N>,
     c: &Transform<N>,
+    inherit: &bool,
+    nsuri: &Option<Box<Transform<N>>>,
+    namespaces: &Vec<HashMap<String, String>>,
 ) -> Result<Sequence<N>, Error> {
     if ctxt.rd.is_none() {
         return Err(Error::new(
@@ -81,28 +80,206 @@ pub(crate) fn element<
     }
     let r = ctxt.rd.clone().unwrap();
 
-    let qnavt = QualifiedName::try_from(ctxt.dispatch(stctxt, qn)?.to_string().as_str())?;
+    let qnavt = computed_name(ctxt, stctxt, qn, nsuri, namespaces, true)?;
     let mut e = r.new_element(qnavt)?;
-    ctxt.dispatch(stctxt, c)?.iter().try_for_each(|i| {
+    let content = normalize_content(&r, ctxt.dispatch(stctxt, c)?)?;
+    add_content(ctxt, stctxt, &r, &mut e, content, *inherit)?;
+    Ok(vec![Item::Node(e)])
+}
+
+/// Normalise a sequence that is to become the content of a node.
+/// See [XSLT 5.7.1](https://www.w3.org/TR/xslt-30/#constructing-complex-content).
+/// Adjacent atomic values are joined, separated by a single space, to make a text node.
+/// A document node is replaced by its children.
+/// Zero-length text nodes are removed, and adjacent text nodes are merged.
+/// New text nodes are created in the given document.
+pub(crate) fn normalize_content<N: Node>(
+    r: &N,
+    content: Sequence<N>,
+) -> Result<Sequence<N>, Error> {
+    // Join adjacent atomic values, and replace document nodes with their children
+    let mut items: Vec<Item<N>> = vec![];
+    let mut values: Vec<String> = vec![];
+    let flush = |values: &mut Vec<String>, items: &mut Vec<Item<N>>| -> Result<(), Error> {
+        if !values.is_empty() {
+            items.push(Item::Node(
+                r.new_text(Rc::new(Value::from(values.join(" "))))?,
+            ));
+            values.clear();
+        }
+        Ok(())
+    };
+    for i in content {
+        match i {
+            Item::Value(v) => values.push(v.to_string()),
+            Item::Node(n) if n.node_type() == NodeType::Document => {
+                flush(&mut values, &mut items)?;
+                n.child_iter().for_each(|c| items.push(Item::Node(c)))
+            }
+            _ => {
+                flush(&mut values, &mut items)?;
+                items.push(i)
+            }
+        }
+    }
+    flush(&mut values, &mut items)?;
+    // Remove empty text nodes, and merge adjacent text nodes
+    let mut result: Sequence<N> = vec![];
+    let mut text: Vec<Item<N>> = vec![];
+    let merge = |text: &mut Vec<Item<N>>, result: &mut Sequence<N>| -> Result<(), Error> {
+        match text.len() {
+            0 => {}
+            1 => result.push(text[0].clone()),
+            _ => result.push(Item::Node(r.new_text(Rc::new(Value::from(
+                text.iter().map(|t| t.to_string()).collect::<String>(),
+            )))?)),
+        }
+        text.clear();
+        Ok(())
+    };
+    for i in items {
+        match &i {
+            Item::Node(n) if n.node_type() == NodeType::Text => {
+                if !n.to_string().is_empty() {
+                    text.push(i)
+                }
+            }
+            _ => {
+                merge(&mut text, &mut result)?;
+                result.push(i)
+            }
+        }
+    }
+    merge(&mut text, &mut result)?;
+    Ok(result)
+}
+
+/// The content of a sequence constructor, normalised as for the content of a node (see [normalize_content]).
+/// This is the value of a variable or parameter that is defined by its content.
+/// If there is no result document then the sequence is not changed.
+pub(crate) fn complex_content<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    c: &Transform<N>,
+) -> Result<Sequence<N>, Error> {
+    let content = ctxt.dispatch(stctxt, c)?;
+    match &ctxt.rd {
+        Some(r) => normalize_content(r, content),
+        None => Ok(content),
+    }
+}
+
+/// Copies a sequence into a document of another tree implementation, appending the copies to the document's children.
+/// Atomic values become text nodes, and a document node is replaced by its children.
+pub(crate) fn copy_into<N: Node, R: Node>(doc: &mut R, content: Sequence<N>) -> Result<(), Error> {
+    let d = doc.clone();
+    content.iter().try_for_each(|i| match i {
+        Item::Node(n) => copy_node_into(&d, doc, n),
+        Item::Value(v) => doc.push(d.new_text(v.clone())?),
+        _ => Err(Error::new(
+            ErrorKind::TypeError,
+            "only nodes and atomic values can be copied into a result document",
+        )),
+    })
+}
+
+fn copy_node_into<N: Node, R: Node>(doc: &R, parent: &mut R, n: &N) -> Result<(), Error> {
+    match n.node_type() {
+        NodeType::Document => n
+            .child_iter()
+            .try_for_each(|c| copy_node_into(doc, parent, &c)),
+        NodeType::Element => {
+            let mut e = doc.new_element(n.name())?;
+            n.namespace_iter().try_for_each(|ns| {
+                let prefix = ns.name().get_localname();
+                e.add_namespace(doc.new_namespace(
+                    ns.value().to_string(),
+                    (!prefix.is_empty()).then_some(prefix),
+                )?)
+            })?;
+            n.attribute_iter()
+                .try_for_each(|a| e.add_attribute(doc.new_attribute(a.name(), a.value())?))?;
+            parent.push(e.clone())?;
+            n.child_iter()
+                .try_for_each(|c| copy_node_into(doc, &mut e, &c))
+        }
+        NodeType::Attribute => parent.add_attribute(doc.new_attribute(n.name(), n.value())?),
+        NodeType::Text => parent.push(doc.new_text(n.value())?),
+        NodeType::Comment => parent.push(doc.new_comment(n.value())?),
+        NodeType::ProcessingInstruction => {
+            parent.push(doc.new_processing_instruction(n.name(), n.value())?)
+        }
+        _ => Ok(()),
+    }
+}
+
+// Add the content of a newly constructed element.
+// Adding an attribute or namespace after a child node is a recoverable error (XTDE0410); the node is ignored.
+// An attribute or namespace that belongs to another element is copied, rather than moved.
+fn add_content<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    r: &N,
+    e: &mut N,
+    content: Sequence<N>,
+    inherit: bool,
+) -> Result<(), Error> {
+    let mut has_children = false;
+    content.iter().try_for_each(|i| {
         // Item could be a Node or text
         match i {
             Item::Node(t) => match t.node_type() {
-                NodeType::Attribute => e.add_attribute(t.clone()),
-                _ => e.push(t.deep_copy()?),
+                NodeType::Attribute | NodeType::Namespace if has_children => ctxt.recover(
+                    stctxt,
+                    Error::new_with_code(
+                        ErrorKind::TypeError,
+                        format!(
+                            "node \"{}\" added to element \"{}\" after its children",
+                            t.name(),
+                            e.name()
+                        ),
+                        Some(QualifiedName::new(None, None, "XTDE0410")),
+                    ),
+                ),
+                NodeType::Attribute => e.add_attribute(detached(t)?),
+                NodeType::Namespace => e.add_namespace(detached(t)?),
+                NodeType::Element => {
+                    has_children = true;
+                    let c = t.deep_copy()?;
+                    if inherit {
+                        add_namespaces(&c, e.namespace_iter())?
+                    }
+                    e.push(c)
+                }
+                _ => {
+                    has_children = true;
+                    e.push(t.deep_copy()?)
+                }
             },
             _ => {
                 // Add the Value as a text node
+                has_children = true;
                 let n = r.new_text(Rc::new(Value::from(i.to_string())))?;
                 e.push(n)
             }
         }
-    })?;
-    Ok(vec![Item::Node(e)])
+    })
 }
 
 /// Creates a new text node.
 /// The transform is evaluated to create the value of the text node.
 /// Special characters are escaped, unless disabled.
+/// If the context has no result document then a string value is created instead.
 pub(crate) fn literal_text<
     N: Node,
     F: FnMut(&str) -> Result<(), Error>,
@@ -114,31 +291,68 @@ pub(crate) fn literal_text<
     t: &Transform<N>,
     b: &bool,
 ) -> Result<Sequence<N>, Error> {
-    if ctxt.rd.is_none() {
-        return Err(Error::new(
-            ErrorKind::Unknown,
-            String::from("context has no result document"),
-        ));
-    }
-
     let v = ctxt.dispatch(stctxt, t)?.to_string();
-    if *b {
-        Ok(vec![Item::Node(
-            ctxt.rd.clone().unwrap().new_text(Rc::new(Value::from(v)))?,
-        )])
+    let text = if *b {
+        v
     } else {
-        Ok(vec![Item::Node(
-            ctxt.rd.clone().unwrap().new_text(Rc::new(Value::from(
-                v.replace('&', "&amp;")
-                    .replace('>', "&gt;")
-                    .replace('<', "&lt;")
-                    .replace('\'', "&apos;")
-                    .replace('\"', "&quot;"),
-            )))?,
-        )])
+        v.replace('&', "&amp;")
+            .replace('>', "&gt;")
+            .replace('<', "&lt;")
+            .replace('\'', "&apos;")
+            .replace('\"', "&quot;")
+    };
+    match &ctxt.rd {
+        Some(r) => Ok(vec![Item::Node(r.new_text(Rc::new(Value::from(text)))?)]),
+        // Without a result document, the text is a string value
+        None => Ok(vec![Item::Value(Rc::new(Value::from(text)))]),
     }
 }
 
+/// Creates a singleton sequence with the string value of the selected items.
+/// If first is true then only the first item is used (XSLT 1.0),
+/// otherwise the string values of all items are joined using the separator.
+/// Zero-length text nodes are ignored and adjacent text nodes are merged before joining, see XSLT v3.0 5.7.2.
+pub(crate) fn value_of<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    t: &Transform<N>,
+    s: &Transform<N>,
+    first: &bool,
+) -> Result<Sequence<N>, Error> {
+    let seq = ctxt.dispatch(stctxt, t)?;
+    let v = if *first {
+        seq.first().map_or(String::new(), |i| i.to_string())
+    } else {
+        let sep = ctxt.dispatch(stctxt, s)?.to_string();
+        let mut parts: Vec<String> = vec![];
+        let mut text = false;
+        seq.iter().for_each(|i| match i {
+            Item::Node(n) if n.node_type() == NodeType::Text => {
+                let v = n.to_string();
+                match parts.last_mut() {
+                    _ if v.is_empty() => {}
+                    Some(last) if text => last.push_str(v.as_str()),
+                    _ => {
+                        parts.push(v);
+                        text = true
+                    }
+                }
+            }
+            _ => {
+                parts.push(i.to_string());
+                text = false
+            }
+        });
+        parts.join(sep.as_str())
+    };
+    Ok(vec![Item::Value(Rc::new(Value::from(v)))])
+}
+
 /// Creates a singleton sequence with a new attribute node.
 /// The transform is evaluated to create the value of the attribute.
 /// TODO: AVT for attribute name
@@ -167,6 +381,94 @@ pub(crate) fn literal_attribute<
     Ok(vec![Item::Node(a)])
 }
 
+/// Creates a singleton sequence with a new attribute node.
+/// The name is interpreted as an AVT to determine the attribute name.
+/// If a namespace URI is given then it determines the namespace of the attribute,
+/// otherwise the prefix of the name is resolved using the given namespace declarations.
+/// The transform is evaluated to create the value of the attribute.
+pub(crate) fn attribute<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    qn: &Transform<N>,
+    t: &Transform<N>,
+    nsuri: &Option<Box<Transform<N>>>,
+    namespaces: &Vec<HashMap<String, String>>,
+) -> Result<Sequence<N>, Error> {
+    if ctxt.rd.is_none() {
+        return Err(Error::new(
+            ErrorKind::Unknown,
+            String::from("context has no result document"),
+        ));
+    }
+
+    let qnavt = computed_name(ctxt, stctxt, qn, nsuri, namespaces, false)?;
+    let a = ctxt.rd.clone().unwrap().new_attribute(
+        qnavt,
+        Rc::new(Value::from(ctxt.dispatch(stctxt, t)?.to_string())),
+    )?;
+    Ok(vec![Item::Node(a)])
+}
+
+// Determine the name of a constructed element or attribute.
+// The name must be a lexical QName (XTDE0820).
+// If a namespace URI is given then it is used and the prefix is not resolved.
+// Otherwise a prefix must be declared (XTDE0830), and an unprefixed element name is in the default namespace.
+fn computed_name<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    qn: &Transform<N>,
+    nsuri: &Option<Box<Transform<N>>>,
+    namespaces: &Vec<HashMap<String, String>>,
+    use_default: bool,
+) -> Result<QualifiedName, Error> {
+    let name = ctxt.dispatch(stctxt, qn)?.to_string();
+    let name = name.trim();
+    let lexical = match QualifiedName::try_from(name) {
+        Ok(q) if q.get_nsuri_ref().is_none() => q,
+        _ => {
+            return Err(Error::new_with_code(
+                ErrorKind::TypeError,
+                format!("\"{}\" is not a valid QName", name),
+                Some(QualifiedName::new(None, None, "XTDE0820")),
+            ))
+        }
+    };
+    if let Some(u) = nsuri {
+        let uri = ctxt.dispatch(stctxt, u)?.to_string();
+        return Ok(if uri.is_empty() {
+            QualifiedName::new(None, None, lexical.get_localname())
+        } else {
+            QualifiedName::new(Some(uri), lexical.get_prefix(), lexical.get_localname())
+        });
+    }
+    match lexical.get_prefix() {
+        Some(p) => QualifiedName::try_from((name, namespaces)).map_err(|_| {
+            Error::new_with_code(
+                ErrorKind::TypeError,
+                format!("prefix \"{}\" is not declared", p),
+                Some(QualifiedName::new(None, None, "XTDE0830")),
+            )
+        }),
+        None if use_default => Ok(namespaces
+            .iter()
+            .find_map(|h| h.get(""))
+            .map_or(lexical.clone(), |d| {
+                QualifiedName::new(Some(d.clone()), None, lexical.get_localname())
+            })),
+        None => Ok(lexical),
+    }
+}
+
 /// Creates a singleton sequence with a new comment node.
 /// The transform is evaluated to create the value of the comment.
 pub(crate) fn literal_comment<
@@ -186,11 +488,13 @@ pub(crate) fn literal_comment<
         ));
     }
 
+    // A comment must not contain "--" or end with "-", so a space is inserted
+    let v = comment_content(ctxt.dispatch(stctxt, t)?.to_string().as_str());
     let a = ctxt
         .rd
         .clone()
         .unwrap()
-        .new_comment(Rc::new(Value::from(ctxt.dispatch(stctxt, t)?.to_string())))?;
+        .new_comment(Rc::new(Value::from(v)))?;
     Ok(vec![Item::Node(a)])
 }
 
@@ -214,13 +518,58 @@ pub(crate) fn literal_processing_instruction<
         ));
     }
 
+    // The content of a PI must not contain "?>", so a space is inserted (XSLT 11.6).
+    // This was a recoverable error in XSLT 1.0, but it is not an error in later versions.
+    let v = ctxt.dispatch(stctxt, t)?.to_string().replace("?>", "? >");
+    // The target must be an NCName, other than "xml" (XTDE0890)
+    let target = ctxt.dispatch(stctxt, name)?.to_string();
+    let target = target.trim();
+    let mut chars = target.chars();
+    if !chars.next().is_some_and(|c| is_ncnamestartchar(&c))
+        || !chars.all(|c| is_ncnamechar(&c))
+        || target.eq_ignore_ascii_case("xml")
+    {
+        return Err(Error::new_with_code(
+            ErrorKind::TypeError,
+            format!("\"{}\" is not a valid processing instruction name", target),
+            Some(QualifiedName::new(None, None, "XTDE0890")),
+        ));
+    }
     let pi = ctxt.rd.clone().unwrap().new_processing_instruction(
-        QualifiedName::new(None, None, ctxt.dispatch(stctxt, name)?.to_string()),
-        Rc::new(Value::from(ctxt.dispatch(stctxt, t)?.to_string())),
+        QualifiedName::new(None, None, target),
+        Rc::new(Value::from(v)),
     )?;
     Ok(vec![Item::Node(pi)])
 }
 
+/// Creates a singleton sequence with a new namespace node.
+/// The transforms are evaluated to create the prefix and URI of the namespace.
+pub(crate) fn literal_namespace<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    p: &Transform<N>,
+    u: &Transform<N>,
+) -> Result<Sequence<N>, Error> {
+    if ctxt.rd.is_none() {
+        return Err(Error::new(
+            ErrorKind::Unknown,
+            String::from("context has no result document"),
+        ));
+    }
+
+    let prefix = ctxt.dispatch(stctxt, p)?.to_string();
+    let ns = ctxt.rd.clone().unwrap().new_namespace(
+        ctxt.dispatch(stctxt, u)?.to_string(),
+        (!prefix.is_empty()).then_some(prefix),
+    )?;
+    Ok(vec![Item::Node(ns)])
+}
+
 /// Set an attribute on the context item, which must be an element-type node.
 /// (TODO: use an expression to select the element)
 /// If the element does not have an attribute with the given name, create it.
@@ -281,7 +630,7 @@ pub(crate) fn set_attribute<
             ))
         }
     }
-    Ok(vec![])
+    Ok(Sequence::empty())
 }
 
 /// Construct a [Sequence] of items
@@ -301,6 +650,82 @@ pub(crate) fn make_sequence<
         Ok(acc)
     })
 }
+
+/// Construct a map. See XPath 3.1, 3.11.1.1.
+/// Each key must be a single atomic value (XPTY0004), and no two keys may be the same (XQDY0137).
+pub(crate) fn map_constructor<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    entries: &[(Transform<N>, Transform<N>)],
+) -> Result<Sequence<N>, Error> {
+    let mut m = Map::new();
+    for (k, v) in entries {
+        let key = match ctxt.dispatch(stctxt, k)?.as_slice() {
+            [i] => i.atomize()?,
+            _ => {
+                return Err(Error::new_with_code(
+                    ErrorKind::TypeError,
+                    "the key of a map entry must be a single atomic value",
+                    Some(QualifiedName::new(None, None, "XPTY0004")),
+                ))
+            }
+        };
+        if m.contains_key(&key) {
+            return Err(Error::new_with_code(
+                ErrorKind::DynamicAbsent,
+                format!("duplicate key \"{}\" in map constructor", key),
+                Some(QualifiedName::new(None, None, "XQDY0137")),
+            ));
+        }
+        let value = ctxt.dispatch(stctxt, v)?;
+        m.insert(key, value);
+    }
+    Ok(vec![Item::Map(Rc::new(m))])
+}
+
+/// Look up entries in maps. See XPath 3.1, 3.11.3.
+/// The first argument selects the maps, and the atomized value of the second argument gives the keys.
+/// The result is the values of the entries with those keys, in order. It is a type error (XPTY0004) if an item is not a map.
+pub(crate) fn lookup<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    base: &Transform<N>,
+    keys: &Transform<N>,
+) -> Result<Sequence<N>, Error> {
+    let maps = ctxt.dispatch(stctxt, base)?;
+    let keys = ctxt
+        .dispatch(stctxt, keys)?
+        .iter()
+        .map(|k| k.atomize())
+        .collect::<Result<Vec<Rc<Value>>, Error>>()?;
+    let mut result = vec![];
+    for i in &maps {
+        match i {
+            Item::Map(m) => keys
+                .iter()
+                .filter_map(|k| m.get(k))
+                .for_each(|v| result.extend(v.iter().cloned())),
+            _ => {
+                return Err(Error::new_with_code(
+                    ErrorKind::TypeError,
+                    "lookup requires a map",
+                    Some(QualifiedName::new(None, None, "XPTY0004")),
+                ))
+            }
+        }
+    }
+    Ok(result)
+}
 /// Shallow copy of an item.
 /// The first argument selects the items to be copied.
 /// The second argument creates the content of the target item.
@@ -314,6 +739,7 @@ pub(crate) fn copy<
     stctxt: &mut StaticContext<N, F, G, H>,
     s: &Transform<N>,
     c: &Transform<N>,
+    cns: &bool,
 ) -> Result<Sequence<N>, Error> {
     let sel = ctxt.dispatch(stctxt, s)?;
     let mut result: Sequence<N> = Vec::new();
@@ -322,21 +748,18 @@ pub(crate) fn copy<
         result.push(cp.clone());
         match cp {
             Item::Node(mut im) => {
-                for j in ctxt.dispatch(stctxt, c)? {
-                    match &j {
-                        Item::Value(v) => im.push(im.new_text(v.clone())?)?,
-                        Item::Node(n) => match n.node_type() {
-                            NodeType::Attribute => im.add_attribute(n.clone())?,
-                            _ => im.push(n.clone())?,
-                        },
-                        _ => {
-                            return Err(Error::new(
-                                ErrorKind::NotImplemented,
-                                String::from("not yet implemented"),
-                            ))
-                        }
+                if let Item::Node(src) = &k {
+                    if *cns && src.node_type() == NodeType::Element {
+                        copy_namespaces(src, &im)?
                     }
                 }
+                let content = ctxt.dispatch(stctxt, c)?;
+                let content = match &ctxt.rd {
+                    Some(r) => normalize_content(r, content)?,
+                    None => content,
+                };
+                let r = im.clone();
+                add_content(ctxt, stctxt, &r, &mut im, content, false)?
             }
             _ => {}
         }
@@ -355,11 +778,118 @@ pub(crate) fn deep_copy<
     ctxt: &Context<N>,
     stctxt: &mut StaticContext<N, F, G, H>,
     s: &Transform<N>,
+    cns: &bool,
 ) -> Result<Sequence<N>, Error> {
-    let sel = ctxt.dispatch(stctxt, s)?;
-    let mut result: Sequence<N> = Vec::new();
-    for k in sel {
-        result.push(k.deep_copy()?);
+    ctxt.dispatch(stctxt, s)?
+        .iter()
+        .map(|k| copy_item(k, *cns))
+        .collect()
+}
+
+/// Deep copy of an item, where the result may flow directly into the serialised output.
+/// If the context only serialises its result, then a selected node is not copied,
+/// unless it is an element that would acquire namespaces from its ancestors.
+/// Otherwise, the result is kept and so the nodes are copied, as for [deep_copy] when in-scope namespaces are copied.
+pub(crate) fn direct_copy<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    s: &Transform<N>,
+) -> Result<Sequence<N>, Error> {
+    ctxt.dispatch(stctxt, s)?
+        .into_iter()
+        .map(|k| match &k {
+            Item::Node(n) if ctxt.direct_output && !inherits_namespaces(n) => Ok(k),
+            _ => copy_item(&k, true),
+        })
+        .collect()
+}
+
+// Deep copy an item. If cns is true then a copied element has the namespaces that are in scope for the source element.
+fn copy_item<N: Node>(k: &Item<N>, cns: bool) -> Result<Item<N>, Error> {
+    let cp = k.deep_copy()?;
+    if let (Item::Node(src), Item::Node(dest)) = (k, &cp) {
+        if src.node_type() == NodeType::Element {
+            if cns {
+                // The copied element also has the namespaces that are in scope from its ancestors
+                copy_namespaces(src, dest)?
+            } else {
+                // Only the namespaces used by names will be declared
+                let mut nsnodes = vec![];
+                dest.namespace_iter().for_each(|n| nsnodes.push(n));
+                dest.descend_iter()
+                    .for_each(|d| d.namespace_iter().for_each(|n| nsnodes.push(n)));
+                nsnodes.iter_mut().try_for_each(|n| n.pop())?
+            }
+        }
     }
-    Ok(result)
+    Ok(cp)
+}
+
+// Does an element have namespaces in scope from its ancestors that it does not declare itself?
+fn inherits_namespaces<N: Node>(n: &N) -> bool {
+    n.node_type() == NodeType::Element
+        && n.ancestor_iter()
+            .filter(|a| a.node_type() == NodeType::Element)
+            .flat_map(|a| a.namespace_iter())
+            .any(|a| !n.namespace_iter().any(|m| m.name() == a.name()))
+}
+
+// An attribute or namespace node that belongs to an element is copied, so that it is not removed from that element.
+fn detached<N: Node>(n: &N) -> Result<N, Error> {
+    if n.parent()
+        .is_some_and(|p| p.node_type() == NodeType::Element)
+    {
+        n.deep_copy()
+    } else {
+        Ok(n.clone())
+    }
+}
+
+// Add copies of the in-scope namespaces of the source element to the destination element,
+// i.e. the namespace nodes of the source element and its ancestors.
+fn copy_namespaces<N: Node>(src: &N, dest: &N) -> Result<(), Error> {
+    // The closest declaration of a prefix is the one that is in scope
+    add_namespaces(
+        dest,
+        Some(src.clone())
+            .into_iter()
+            .chain(src.ancestor_iter())
+            .filter(|a| a.node_type() == NodeType::Element)
+            .flat_map(|a| a.namespace_iter()),
+    )
+}
+
+// The prefix that is declared by a namespace node, or the empty string for the default namespace.
+// A tree that does not support namespace nodes uses an xmlns pseudo-attribute instead.
+fn declared_prefix<N: Node>(n: &N) -> String {
+    let qn = n.name();
+    if n.node_type() == NodeType::Attribute
+        && qn.get_prefix().is_none()
+        && qn.get_localname() == "xmlns"
+    {
+        String::new()
+    } else {
+        qn.get_localname()
+    }
+}
+
+// Add copies of the given namespace nodes to an element,
+// unless the element already has a namespace node with the same prefix.
+fn add_namespaces<N: Node>(dest: &N, mut nsnodes: impl Iterator<Item = N>) -> Result<(), Error> {
+    nsnodes.try_for_each(|n| {
+        let prefix = declared_prefix(&n);
+        if dest.namespace_iter().any(|d| declared_prefix(&d) == prefix) {
+            Ok(())
+        } else {
+            dest.add_namespace(dest.new_namespace(
+                n.value().to_string(),
+                (!prefix.is_empty()).then_some(prefix),
+            )?)
+        }
+    })
 }
@@ -10,13 +10,13 @@ A [Context] is used to evaluate a [Transform]. The evaluate method matches the c
 
  */
 
-use crate::item::{Node, Sequence};
+use crate::item::{Node, NodeType, Sequence};
 use crate::output::OutputDefinition;
 #[allow(unused_imports)]
 use crate::pattern::Pattern;
 use crate::qname::QualifiedName;
 use crate::transform::booleans::*;
-use crate::transform::callable::{invoke, Callable};
+use crate::transform::callable::{call_function, invoke, Callable, FormalParameters};
 use crate::transform::construct::*;
 use crate::transform::controlflow::*;
 use crate::transform::datetime::*;
@@ -29,17 +29,74 @@ use crate::transform::navigate::*;
 use crate::transform::numbers::*;
 use crate::transform::strings::*;
 use crate::transform::template::{apply_imports, apply_templates, next_match, Template};
-use crate::transform::variables::{declare_variable, reference_variable};
-use crate::transform::Transform;
-use crate::xdmerror::Error;
+use crate::transform::types::{cast, castable, instance_of};
+use crate::transform::variables::{
+    declare_variable, reference_variable, tunnel, tunnel_parameter, Globals,
+};
+use crate::transform::whitespace::strip_whitespace;
+use crate::transform::{NodeTest, Transform};
+use crate::xdmerror::{Error, Location};
 use crate::{ErrorKind, Item, SequenceTrait, Value};
+use std::cell::RefCell;
 use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::rc::Rc;
 use url::Url;
 
+/// The default limit on the depth of template and function invocation. See [Context::max_depth].
+/// This suits the stack of a thread with the default size, even in an unoptimised build.
+pub const DEFAULT_MAX_DEPTH: usize = 100;
+/// The limit on the depth of template and function invocation when secure processing is enabled.
+/// See [StaticContextBuilder::secure_processing].
+pub const SECURE_MAX_DEPTH: usize = 100;
+
+pub use crate::transform::strings::{
+    Collation, CODEPOINT_COLLATION, HTML_ASCII_CASE_INSENSITIVE_COLLATION,
+};
+
 //pub type Message = FnMut(&str) -> Result<(), Error>;
 
+/// Candidate templates for nodes of a given shape, i.e. mode, node type and node name.
+/// The cache is shared by all contexts derived from the same context.
+pub(crate) type TemplateCache<N> =
+    Rc<RefCell<HashMap<(Option<QualifiedName>, NodeType, QualifiedName), Vec<Rc<Template<N>>>>>>;
+
+/// How recoverable dynamic errors are handled.
+/// Recoverable errors are those that the XSLT specification allows a processor to recover from,
+/// such as a conflict between templates of equal priority, or an attribute added to an element after its children.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ErrorRecovery {
+    /// Signal the error. This is the default.
+    #[default]
+    Strict,
+    /// Take the recovery action and report the error through the message callback.
+    Recover,
+    /// Take the recovery action without reporting the error.
+    RecoverSilently,
+}
+
+/// A description of a template, as returned by [Context::dump_static_info].
+#[derive(Clone, Debug, PartialEq)]
+pub struct TemplateInfo {
+    pub mode: Option<QualifiedName>,
+    /// The match pattern, as written in the stylesheet if that is known.
+    pub pattern: String,
+    pub priority: Option<f64>,
+    /// Where the template is declared in the stylesheet, if that is known.
+    pub location: Option<String>,
+}
+
+/// A description of the static parts of a compiled stylesheet, as returned by [Context::dump_static_info].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StaticInfo {
+    /// All templates with a match pattern, including the built-in templates, in the order they were added to the context.
+    pub templates: Vec<TemplateInfo>,
+    pub named_templates: Vec<QualifiedName>,
+    pub functions: Vec<QualifiedName>,
+    pub variables: Vec<String>,
+    pub keys: Vec<String>,
+}
+
 /// The transformation context. This is the dynamic context.
 /// The static parts of the context are in a separate structure.
 /// Contexts are immutable, but frequently are cloned to provide a new context.
@@ -50,15 +107,27 @@ pub struct Context<N: Node> {
     pub(crate) i: usize, // The index to the item that is the current context item
     pub(crate) previous_context: Option<Item<N>>, // The "current" XPath item, which is really the context item for the invoking context. See XSLT 20.4.1.
     pub(crate) depth: usize,                      // Depth of evaluation
-    pub(crate) rd: Option<N>,                     // Result document
+    pub(crate) max_depth: usize, // Maximum depth of template and function invocation
+    pub(crate) rd: Option<N>,    // Result document
+    // The context position and size, when the context item was selected from a sequence by xsl:apply-templates or xsl:for-each.
+    // Each level of nesting evaluates in its own context, so an outer position is unaffected by an inner instruction.
+    pub(crate) focus: Option<(usize, usize)>,
     // There is no distinction between built-in and user-defined templates
     // Built-in templates have no priority and no document order
     pub(crate) templates: Vec<Rc<Template<N>>>,
     pub(crate) current_templates: Vec<Rc<Template<N>>>,
+    // Templates that may match a node, keyed by the node's shape. None if the cache is disabled.
+    pub(crate) template_cache: Option<TemplateCache<N>>,
     // Named templates and functions
     pub(crate) callables: HashMap<QualifiedName, Callable<N>>,
     // Variables, with scoping
     pub(crate) vars: HashMap<String, Vec<Sequence<N>>>,
+    // Tunnel parameters. These are passed on to every template that is invoked.
+    pub(crate) tunnel: HashMap<QualifiedName, Sequence<N>>,
+    // Global variables and parameters, and the global context item in which they are evaluated
+    pub(crate) globals: Globals<N>,
+    // Whether this is the context for evaluating a global variable. The context position and size are then absent.
+    pub(crate) global: bool,
     // Grouping
     pub(crate) current_grouping_key: Option<Rc<Value>>,
     pub(crate) current_group: Sequence<N>,
@@ -68,12 +137,25 @@ pub struct Context<N: Node> {
     pub(crate) keys: HashMap<String, Vec<(Pattern<N>, Transform<N>)>>,
     // The calculated values of keys.
     pub(crate) key_values: HashMap<String, HashMap<String, Vec<N>>>,
+    // Whitespace stripping for source documents, as declared by xsl:strip-space and xsl:preserve-space
+    pub(crate) strip_space: Vec<NodeTest>,
+    pub(crate) preserve_space: Vec<NodeTest>,
     // Output control
     pub(crate) od: OutputDefinition,
+    // Named output definitions, for secondary result documents
+    pub(crate) outputs: HashMap<QualifiedName, OutputDefinition>,
     pub(crate) base_url: Option<Url>,
     // Namespace resolution. If any transforms contain a QName that needs to be resolved to an EQName,
     // then these prefix -> URI mappings are used. These are usually derived from the stylesheet document.
     pub(crate) namespaces: Vec<HashMap<String, String>>,
+    // What to do when a recoverable error occurs
+    pub(crate) recovery: ErrorRecovery,
+    // Collations registered by the application, in addition to the builtin collations, keyed by URI
+    pub(crate) collations: HashMap<String, Collation>,
+    // The URI of the default collation. If not set, then the codepoint collation is the default.
+    pub(crate) default_collation: Option<String>,
+    // Whether the result of the evaluation is only serialised. Nodes selected by xsl:copy-of need not then be copied.
+    pub(crate) direct_output: bool,
 }
 
 impl<N: Node> Context<N> {
@@ -81,26 +163,41 @@ impl<N: Node> Context<N> {
         Context {
             cur: Sequence::new(),
             i: 0,
+            focus: None,
             previous_context: None,
             depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
             rd: None,
             templates: vec![],
             current_templates: vec![],
+            template_cache: Some(TemplateCache::default()),
             callables: HashMap::new(),
             vars: HashMap::new(),
+            tunnel: HashMap::new(),
+            globals: Globals::new(),
+            global: false,
             current_grouping_key: None,
             current_group: Sequence::new(),
             keys: HashMap::new(),
             key_values: HashMap::new(),
+            strip_space: vec![],
+            preserve_space: vec![],
             od: OutputDefinition::new(),
+            outputs: HashMap::new(),
             base_url: None,
             namespaces: vec![],
+            recovery: ErrorRecovery::default(),
+            collations: HashMap::new(),
+            default_collation: None,
+            direct_output: false,
         }
     }
     /// Sets the context item.
     pub fn context(&mut self, s: Sequence<N>, i: usize) {
         self.cur = s;
         self.i = i;
+        self.focus = None;
+        self.global = false;
     }
     /// Sets the XML Namespaces.
     pub fn namespaces(&mut self, ns: Vec<HashMap<String, String>>) {
@@ -110,6 +207,67 @@ impl<N: Node> Context<N> {
     pub fn namespaces_ref(&self) -> &Vec<HashMap<String, String>> {
         &self.namespaces
     }
+    /// Sets the limit on the depth of template and function invocation.
+    /// Each xsl:apply-templates, xsl:call-template or function call adds one level.
+    /// Exceeding the limit is an error (XRLM0001), rather than overflowing the stack.
+    /// The default is [DEFAULT_MAX_DEPTH]. An identity transformation of a deeply nested document,
+    /// or a recursive template that processes a long sequence, needs one level for each node or item;
+    /// a higher limit may then be set, provided that the thread's stack is large enough.
+    pub fn max_depth(&mut self, d: usize) {
+        self.max_depth = d;
+    }
+    /// The depth for a new level of template or function invocation.
+    /// Secure processing imposes a lower limit, if the context's own limit is higher.
+    pub(crate) fn deeper<
+        F: FnMut(&str) -> Result<(), Error>,
+        G: FnMut(&str) -> Result<N, Error>,
+        H: FnMut(&Url) -> Result<String, Error>,
+    >(
+        &self,
+        stctxt: &StaticContext<N, F, G, H>,
+    ) -> Result<usize, Error> {
+        let max = if stctxt.secure {
+            self.max_depth.min(SECURE_MAX_DEPTH)
+        } else {
+            self.max_depth
+        };
+        if self.depth < max {
+            Ok(self.depth + 1)
+        } else {
+            Err(Error::new_with_code(
+                ErrorKind::LimitExceeded,
+                format!(
+                    "maximum depth of template or function invocation ({}) exceeded at {}",
+                    max,
+                    location(self.cur.get(self.i))
+                ),
+                Some(QualifiedName::new(None, None, "XRLM0001")),
+            ))
+        }
+    }
+    /// Make a collation available, identified by its URI.
+    pub fn declare_collation(&mut self, uri: impl Into<String>, c: Collation) {
+        self.collations.insert(uri.into(), c);
+    }
+    /// Sets the default collation, by its URI. The collation must be a builtin collation, or one that has been declared.
+    pub fn default_collation(&mut self, uri: impl Into<String>) {
+        self.default_collation = Some(uri.into())
+    }
+    /// Find the collation with the given URI, or the default collation if no URI is given.
+    pub(crate) fn resolve_collation(&self, uri: Option<&str>) -> Result<Collation, Error> {
+        let u = uri
+            .or(self.default_collation.as_deref())
+            .unwrap_or(CODEPOINT_COLLATION);
+        self.collations
+            .get(u)
+            .cloned()
+            .or_else(|| builtin_collation(u))
+            .ok_or(Error::new_with_code(
+                ErrorKind::DynamicAbsent,
+                format!("unsupported collation \"{}\"", u),
+                Some(QualifiedName::new(None, None, "FOCH0002")),
+            ))
+    }
     /// Sets the "current" item.
     pub fn previous_context(&mut self, i: Item<N>) {
         self.previous_context = Some(i);
@@ -118,6 +276,51 @@ impl<N: Node> Context<N> {
     pub fn result_document(&mut self, rd: N) {
         self.rd = Some(rd);
     }
+    /// Enable or disable caching of the templates that may match nodes with the same type and name.
+    /// The cache is enabled by default.
+    pub fn template_cache(&mut self, enable: bool) {
+        self.template_cache = enable.then(TemplateCache::default);
+    }
+    /// Sets how recoverable dynamic errors are handled. The default is to signal the error.
+    pub fn error_recovery(&mut self, r: ErrorRecovery) {
+        self.recovery = r;
+    }
+    /// Handle a recoverable dynamic error.
+    /// In strict mode the error is returned. Otherwise the caller takes the recovery action,
+    /// and the error is reported through the message callback, along with the location of the context item.
+    pub(crate) fn recover<
+        F: FnMut(&str) -> Result<(), Error>,
+        G: FnMut(&str) -> Result<N, Error>,
+        H: FnMut(&Url) -> Result<String, Error>,
+    >(
+        &self,
+        stctxt: &mut StaticContext<N, F, G, H>,
+        e: Error,
+    ) -> Result<(), Error> {
+        self.recover_at(stctxt, self.cur.get(self.i), e)
+    }
+    // Handle a recoverable error, reporting the location of the given item
+    fn recover_at<
+        F: FnMut(&str) -> Result<(), Error>,
+        G: FnMut(&str) -> Result<N, Error>,
+        H: FnMut(&Url) -> Result<String, Error>,
+    >(
+        &self,
+        stctxt: &mut StaticContext<N, F, G, H>,
+        i: Option<&Item<N>>,
+        e: Error,
+    ) -> Result<(), Error> {
+        match self.recovery {
+            ErrorRecovery::Strict => Err(e),
+            ErrorRecovery::Recover => {
+                if let Some(f) = &mut stctxt.message {
+                    f(format!("recoverable error at {}: {}", location(i), e.message).as_str())?
+                }
+                Ok(())
+            }
+            ErrorRecovery::RecoverSilently => Ok(()),
+        }
+    }
     /// Declare a key
     pub fn declare_key(&mut self, name: String, m: Pattern<N>, u: Transform<N>) {
         if let Some(v) = self.keys.get_mut(&name) {
@@ -144,6 +347,38 @@ impl<N: Node> Context<N> {
     ) -> Result<(), Error> {
         populate_key_values(self, stctxt, sd)
     }
+    /// Declare which elements have whitespace-only text nodes stripped from source documents.
+    /// These are the name tests of xsl:strip-space and xsl:preserve-space.
+    pub fn whitespace_stripping(&mut self, strip: Vec<NodeTest>, preserve: Vec<NodeTest>) {
+        self.strip_space = strip;
+        self.preserve_space = preserve;
+    }
+    /// Prepare a source document for the transformation. The [Node] argument must be a document node.
+    /// If whitespace is to be stripped, then the document is copied and whitespace is stripped from the copy,
+    /// so that the caller's tree is unchanged.
+    /// The (stripped) document becomes the context item and key values are calculated for it,
+    /// so that templates, positions and keys all see the same tree.
+    /// The document that is used for the transformation is returned.
+    pub fn source_document<
+        F: FnMut(&str) -> Result<(), Error>,
+        G: FnMut(&str) -> Result<N, Error>,
+        H: FnMut(&Url) -> Result<String, Error>,
+    >(
+        &mut self,
+        stctxt: &mut StaticContext<N, F, G, H>,
+        sd: N,
+    ) -> Result<N, Error> {
+        let doc = if self.strip_space.is_empty() {
+            sd
+        } else {
+            let cp = sd.deep_copy()?;
+            strip_whitespace(cp.clone(), false, &self.strip_space, &self.preserve_space)?;
+            cp
+        };
+        self.context(vec![Item::Node(doc.clone())], 0);
+        populate_key_values(self, stctxt, doc.clone())?;
+        Ok(doc)
+    }
     pub fn dump_key_values(&self) {
         self.key_values.iter().for_each(|(k, v)| {
             println!("key \"{}\":", k);
@@ -179,6 +414,21 @@ impl<N: Node> Context<N> {
         })
     }
 
+    /// Declare a global variable, i.e. a top-level xsl:variable.
+    /// The value is evaluated when the variable is first referenced.
+    pub fn declare_global_variable(&mut self, name: impl Into<String>, value: Transform<N>) {
+        Rc::make_mut(&mut self.globals.decls).insert(name.into(), (value, false));
+    }
+    /// Declare a global parameter, i.e. a top-level xsl:param, with its default value.
+    /// The default is only evaluated if the application does not supply a value, see [Context::global_parameter].
+    pub fn declare_global_parameter(&mut self, name: impl Into<String>, default: Transform<N>) {
+        Rc::make_mut(&mut self.globals.decls).insert(name.into(), (default, true));
+    }
+    /// Supply the value of a global parameter.
+    pub fn global_parameter(&mut self, name: impl Into<String>, value: Sequence<N>) {
+        Rc::make_mut(&mut self.globals.params).insert(name.into(), value);
+    }
+
     /// Callable components: named templates and user-defined functions
     pub fn callable_push(&mut self, qn: QualifiedName, c: Callable<N>) {
         self.callables.insert(qn, c);
@@ -241,6 +491,13 @@ impl<N: Node> Context<N> {
         &self,
         stctxt: &mut StaticContext<N, F, G, H>,
     ) -> Result<Sequence<N>, Error> {
+        if self.rd.is_none() {
+            if let Some(f) = &mut stctxt.document_factory {
+                let mut c = self.clone();
+                c.rd = Some(f()?);
+                return c.evaluate(stctxt);
+            }
+        }
         if self.cur.is_empty() {
             Ok(Sequence::new())
         } else {
@@ -252,44 +509,190 @@ impl<N: Node> Context<N> {
                     ))
                 },
                 |i| {
+                    // Global variables are evaluated with the document node of the principal source document as the context item.
+                    // Their values are computed afresh for each source document.
+                    let mut c = self.clone();
+                    c.globals = self.globals.for_item(Some(match i {
+                        Item::Node(n) => Item::Node(n.owner_document()),
+                        _ => i.clone(),
+                    }));
                     // There may be 0, 1, or more matching templates.
-                    // If there are more than one with the same priority and import level,
-                    // then take the one with the higher document order.
-                    let templates = self.find_templates(stctxt, i, &None)?;
-                    match templates.len() {
-                        0 => Err(Error::new(
+                    let templates = c.find_templates(stctxt, i, &None)?;
+                    if templates.is_empty() {
+                        Err(Error::new(
                             ErrorKind::DynamicAbsent,
                             String::from("no matching template"),
-                        )),
-                        1 => self.dispatch(stctxt, &templates[0].body),
-                        _ => {
-                            if templates[0].priority == templates[1].priority
-                                && templates[0].import.len() == templates[1].import.len()
-                            {
-                                let mut candidates: Vec<Rc<Template<N>>> = templates
-                                    .iter()
-                                    .take_while(|t| {
-                                        t.priority == templates[0].priority
-                                            && t.import.len() == templates[0].import.len()
-                                    })
-                                    .cloned()
-                                    .collect();
-                                candidates.sort_unstable_by(|a, b| {
-                                    a.document_order.map_or(Ordering::Greater, |v| {
-                                        b.document_order.map_or(Ordering::Less, |u| v.cmp(&u))
-                                    })
-                                });
-                                self.dispatch(stctxt, &candidates.last().unwrap().body)
-                            } else {
-                                self.dispatch(stctxt, &templates[0].body)
-                            }
-                        }
+                        ))
+                    } else {
+                        let t = c.select_template(stctxt, i, &templates)?;
+                        c.dispatch(stctxt, &t.body)
                     }
                 },
             )
         }
     }
 
+    /// Evaluate as for [Context::evaluate], and copy the result into the given document, which is returned.
+    /// The document may be of a different tree implementation to the source document and the stylesheet,
+    /// for example a smite source document may produce an intmuttree result.
+    /// The result is first constructed in the context's result document, or else in a document from the document factory.
+    pub fn evaluate_into<
+        R: Node,
+        F: FnMut(&str) -> Result<(), Error>,
+        G: FnMut(&str) -> Result<N, Error>,
+        H: FnMut(&Url) -> Result<String, Error>,
+    >(
+        &self,
+        stctxt: &mut StaticContext<N, F, G, H>,
+        mut doc: R,
+    ) -> Result<R, Error> {
+        let mut c = self.clone();
+        if c.rd.is_none() {
+            if let Some(f) = &mut stctxt.document_factory {
+                c.rd = Some(f()?);
+            }
+        }
+        let content = c.evaluate(stctxt)?;
+        let content = match &c.rd {
+            Some(r) => normalize_content(r, content)?,
+            None => content,
+        };
+        copy_into(&mut doc, content)?;
+        Ok(doc)
+    }
+
+    /// Evaluate as for [Context::evaluate], and serialize the result using the output definition of the stylesheet.
+    /// Since the result is only serialized, the nodes selected by an xsl:copy-of instruction at the top level of a template
+    /// are written directly from the source tree, without first being copied.
+    pub fn evaluate_to_string<
+        F: FnMut(&str) -> Result<(), Error>,
+        G: FnMut(&str) -> Result<N, Error>,
+        H: FnMut(&Url) -> Result<String, Error>,
+    >(
+        &self,
+        stctxt: &mut StaticContext<N, F, G, H>,
+    ) -> Result<String, Error> {
+        let mut c = self.clone();
+        c.direct_output = true;
+        c.evaluate(stctxt)?.serialize(&self.od)
+    }
+
+    /// Call a stylesheet function, i.e. one defined by xsl:function, with the given arguments.
+    /// No source document is required, so a compiled stylesheet may be used as a library of functions.
+    /// Since there is no source document, global variables are evaluated without a context item.
+    /// The number of arguments must match the arity of the function.
+    pub fn call_function<
+        F: FnMut(&str) -> Result<(), Error>,
+        G: FnMut(&str) -> Result<N, Error>,
+        H: FnMut(&Url) -> Result<String, Error>,
+    >(
+        &self,
+        stctxt: &mut StaticContext<N, F, G, H>,
+        qn: &QualifiedName,
+        args: Vec<Sequence<N>>,
+    ) -> Result<Sequence<N>, Error> {
+        call_function(self, stctxt, qn, args)
+    }
+
+    /// Describe the compiled templates, functions, variables and keys, for debugging and introspection.
+    /// The names in each list are sorted.
+    pub fn dump_static_info(&self) -> StaticInfo {
+        let mut named_templates = vec![];
+        let mut functions = vec![];
+        self.callables
+            .iter()
+            .for_each(|(qn, c)| match c.parameters {
+                FormalParameters::Named(_) => named_templates.push(qn.clone()),
+                FormalParameters::Positional(_) => functions.push(qn.clone()),
+            });
+        named_templates.sort();
+        functions.sort();
+        let mut variables: Vec<String> = self
+            .vars
+            .keys()
+            .chain(self.globals.decls.keys())
+            .cloned()
+            .collect();
+        variables.sort();
+        let mut keys: Vec<String> = self.keys.keys().cloned().collect();
+        keys.sort();
+        StaticInfo {
+            templates: self
+                .templates
+                .iter()
+                .map(|t| TemplateInfo {
+                    mode: t.mode.clone(),
+                    pattern: t
+                        .match_string
+                        .clone()
+                        .unwrap_or_else(|| format!("{:?}", t.pattern)),
+                    priority: t.priority,
+                    location: t.location.clone(),
+                })
+                .collect(),
+            named_templates,
+            functions,
+            variables,
+            keys,
+        }
+    }
+
+    /// Choose the template to evaluate from a list of matching templates, as returned by find_templates.
+    /// If there are two or more templates with the same priority and import level,
+    /// then this is a recoverable error and the template that is last in document order is chosen.
+    pub(crate) fn select_template<
+        F: FnMut(&str) -> Result<(), Error>,
+        G: FnMut(&str) -> Result<N, Error>,
+        H: FnMut(&Url) -> Result<String, Error>,
+    >(
+        &self,
+        stctxt: &mut StaticContext<N, F, G, H>,
+        i: &Item<N>,
+        templates: &[Rc<Template<N>>],
+    ) -> Result<Rc<Template<N>>, Error> {
+        if templates.len() > 1
+            && templates[0].priority == templates[1].priority
+            && templates[0].import.len() == templates[1].import.len()
+        {
+            let mut candidates: Vec<Rc<Template<N>>> = templates
+                .iter()
+                .take_while(|t| {
+                    t.priority == templates[0].priority
+                        && t.import.len() == templates[0].import.len()
+                })
+                .cloned()
+                .collect();
+            candidates.sort_unstable_by(|a, b| {
+                a.document_order.map_or(Ordering::Greater, |v| {
+                    b.document_order.map_or(Ordering::Less, |u| v.cmp(&u))
+                })
+            });
+            // Only templates in the stylesheet can conflict; built-in templates have no document order
+            if candidates
+                .iter()
+                .filter(|t| t.document_order.is_some())
+                .count()
+                > 1
+            {
+                self.recover_at(
+                    stctxt,
+                    Some(i),
+                    Error::new_with_code(
+                        ErrorKind::DynamicAbsent,
+                        format!(
+                            "{} templates with the same priority match the item",
+                            candidates.len()
+                        ),
+                        Some(QualifiedName::new(None, None, "XTDE0540")),
+                    ),
+                )?
+            }
+            Ok(candidates.last().unwrap().clone())
+        } else {
+            Ok(templates[0].clone())
+        }
+    }
+
     /// Find a template with a matching [Pattern] in the given mode.
     pub fn find_templates<
         F: FnMut(&str) -> Result<(), Error>,
@@ -301,17 +704,33 @@ impl<N: Node> Context<N> {
         i: &Item<N>,
         m: &Option<QualifiedName>,
     ) -> Result<Vec<Rc<Template<N>>>, Error> {
-        let mut candidates =
-            self.templates
+        // Only the templates whose pattern may match a node of this type and name need to be tested.
+        let possible: Vec<Rc<Template<N>>> = match (&self.template_cache, i) {
+            (Some(cache), Item::Node(n)) => {
+                let key = (m.clone(), n.node_type(), n.name());
+                let cached = cache.borrow().get(&key).cloned();
+                cached.unwrap_or_else(|| {
+                    let c: Vec<Rc<Template<N>>> = self
+                        .templates
+                        .iter()
+                        .filter(|t| t.mode == *m && t.pattern.terminal_match(i))
+                        .cloned()
+                        .collect();
+                    cache.borrow_mut().insert(key, c.clone());
+                    c
+                })
+            }
+            _ => self
+                .templates
                 .iter()
                 .filter(|t| t.mode == *m)
-                .try_fold(vec![], |mut cand, t| {
-                    let e = t.pattern.matches(self, stctxt, i);
-                    if e {
-                        cand.push(t.clone())
-                    }
-                    Ok(cand)
-                })?;
+                .cloned()
+                .collect(),
+        };
+        let mut candidates: Vec<Rc<Template<N>>> = possible
+            .into_iter()
+            .filter(|t| t.pattern.matches(self, stctxt, i))
+            .collect();
         if !candidates.is_empty() {
             // Find the template(s) with the lowest priority.
 
@@ -367,6 +786,23 @@ impl<N: Node> Context<N> {
         stctxt: &mut StaticContext<N, F, G, H>,
         t: &Transform<N>,
     ) -> Result<Sequence<N>, Error> {
+        // Direct output only reaches the instructions whose result is, in turn, the result of a template.
+        // The result of any other transformation is kept, or used to construct something else,
+        // so the nodes selected by xsl:copy-of within it must be copied.
+        if self.direct_output
+            && !matches!(
+                t,
+                Transform::SequenceItems(_)
+                    | Transform::ApplyTemplates(..)
+                    | Transform::ApplyImports
+                    | Transform::NextMatch
+                    | Transform::DirectCopy(_)
+            )
+        {
+            let mut c = self.clone();
+            c.direct_output = false;
+            return c.dispatch(stctxt, t);
+        }
         match t {
             Transform::Root => root(self),
             Transform::ContextItem => context(self),
@@ -376,18 +812,23 @@ impl<N: Node> Context<N> {
             Transform::Filter(t) => filter(self, stctxt, t),
             Transform::Empty => empty(self),
             Transform::Literal(v) => literal(self, v),
-            Transform::LiteralElement(qn, t) => literal_element(self, stctxt, qn, t),
-            Transform::Element(qn, t) => element(self, stctxt, qn, t),
+            Transform::LiteralElement(qn, t, i) => literal_element(self, stctxt, qn, t, i),
+            Transform::Element(qn, t, i, u, ns) => element(self, stctxt, qn, t, i, u, ns),
             Transform::LiteralText(t, b) => literal_text(self, stctxt, t, b),
+            Transform::ComplexContent(c) => complex_content(self, stctxt, c),
+            Transform::ValueOf(t, s, b) => value_of(self, stctxt, t, s, b),
             Transform::LiteralAttribute(qn, t) => literal_attribute(self, stctxt, qn, t),
+            Transform::Attribute(qn, t, u, ns) => attribute(self, stctxt, qn, t, u, ns),
             Transform::LiteralComment(t) => literal_comment(self, stctxt, t),
             Transform::LiteralProcessingInstruction(n, t) => {
                 literal_processing_instruction(self, stctxt, n, t)
             }
+            Transform::LiteralNamespace(p, u) => literal_namespace(self, stctxt, p, u),
             Transform::SetAttribute(qn, v) => set_attribute(self, stctxt, qn, v),
             Transform::SequenceItems(v) => make_sequence(self, stctxt, v),
-            Transform::Copy(f, t) => copy(self, stctxt, f, t),
-            Transform::DeepCopy(d) => deep_copy(self, stctxt, d),
+            Transform::Copy(f, t, n) => copy(self, stctxt, f, t, n),
+            Transform::DeepCopy(d, n) => deep_copy(self, stctxt, d, n),
+            Transform::DirectCopy(d) => direct_copy(self, stctxt, d),
             Transform::Or(v) => tr_or(self, stctxt, v),
             Transform::And(v) => tr_and(self, stctxt, v),
             Transform::Union(b) => union(self, stctxt, b),
@@ -396,19 +837,34 @@ impl<N: Node> Context<N> {
             Transform::Concat(v) => tr_concat(self, stctxt, v),
             Transform::Range(s, e) => tr_range(self, stctxt, s, e),
             Transform::Arithmetic(v) => arithmetic(self, stctxt, v),
+            Transform::MapConstructor(e) => map_constructor(self, stctxt, e),
+            Transform::Lookup(m, k) => lookup(self, stctxt, m, k),
             Transform::Loop(v, b) => tr_loop(self, stctxt, v, b),
+            Transform::Quantified(e, v, t) => quantified(self, stctxt, e, v, t),
             Transform::Switch(c, o) => switch(self, stctxt, c, o),
             Transform::ForEach(g, s, b, o) => for_each(self, stctxt, g, s, b, o),
+            Transform::PerformSort(s, o) => perform_sort(self, stctxt, s, o),
+            Transform::Iterate(s, p, b, c) => iterate(self, stctxt, s, p, b, c),
+            Transform::NextIteration(p) => next_iteration(self, stctxt, p),
+            Transform::Break(b) => tr_break(self, stctxt, b),
             Transform::ApplyTemplates(s, m, o) => apply_templates(self, stctxt, s, m, o),
             Transform::ApplyImports => apply_imports(self, stctxt),
             Transform::NextMatch => next_match(self, stctxt),
             Transform::VariableDeclaration(n, v, f) => {
                 declare_variable(self, stctxt, n.clone(), v, f)
             }
-            Transform::VariableReference(n) => reference_variable(self, n),
+            Transform::VariableReference(n) => reference_variable(self, stctxt, n),
+            Transform::Tunnel(p, t) => tunnel(self, stctxt, p, t),
+            Transform::TunnelParameter(n, d) => tunnel_parameter(self, stctxt, n, d),
             Transform::Position => position(self),
             Transform::Last => last(self),
             Transform::Count(s) => tr_count(self, stctxt, s),
+            Transform::Exists(s) => tr_exists(self, stctxt, s),
+            Transform::IsEmpty(s) => tr_empty(self, stctxt, s),
+            Transform::DistinctValues(s, c) => distinct_values(self, stctxt, s, c),
+            Transform::IndexOf(s, t, c) => index_of(self, stctxt, s, t, c),
+            Transform::InsertBefore(t, p, i) => insert_before(self, stctxt, t, p, i),
+            Transform::Remove(t, p) => remove(self, stctxt, t, p),
             Transform::LocalName(s) => local_name(self, stctxt, s),
             Transform::Name(s) => name(self, stctxt, s),
             Transform::String(s) => string(self, stctxt, s),
@@ -419,14 +875,23 @@ impl<N: Node> Context<N> {
             Transform::SubstringBefore(s, t) => substring_before(self, stctxt, s, t),
             Transform::SubstringAfter(s, t) => substring_after(self, stctxt, s, t),
             Transform::NormalizeSpace(s) => normalize_space(self, stctxt, s),
+            Transform::ContainsToken(s, t, c) => contains_token(self, stctxt, s, t, c),
+            Transform::Tokenize(s, p, f) => tokenize(self, stctxt, s, p, f),
+            Transform::StringJoin(s, t) => string_join(self, stctxt, s, t),
             Transform::Translate(s, m, t) => translate(self, stctxt, s, m, t),
             Transform::GenerateId(s) => generate_id(self, stctxt, s),
+            Transform::RootOf(s) => root_of(self, stctxt, s),
+            Transform::Path(s) => path(self, stctxt, s),
+            Transform::Nilled(s) => nilled(self, stctxt, s),
             Transform::Boolean(b) => boolean(self, stctxt, b),
             Transform::Not(b) => not(self, stctxt, b),
             Transform::True => tr_true(self),
             Transform::False => tr_false(self),
             Transform::Number(n) => number(self, stctxt, n),
             Transform::Sum(s) => sum(self, stctxt, s),
+            Transform::Avg(s) => avg(self, stctxt, s),
+            Transform::Max(s, c) => max(self, stctxt, s, c),
+            Transform::Min(s, c) => min(self, stctxt, s, c),
             Transform::Floor(n) => floor(self, stctxt, n),
             Transform::Ceiling(n) => ceiling(self, stctxt, n),
             Transform::Round(n, p) => round(self, stctxt, n, p),
@@ -441,7 +906,9 @@ impl<N: Node> Context<N> {
             Transform::FormatDate(t, p, l, c, q) => format_date(self, stctxt, t, p, l, c, q),
             Transform::FormatTime(t, p, l, c, q) => format_time(self, stctxt, t, p, l, c, q),
             Transform::FormatNumber(v, p, d) => format_number(self, stctxt, v, p, d),
+            Transform::ParseIetfDate(s) => parse_ietf_date(self, stctxt, s),
             Transform::FormatInteger(i, s) => format_integer(self, stctxt, i, s),
+            Transform::FormatIntegers(i, s, o) => format_integers(self, stctxt, i, s, o),
             Transform::GenerateIntegers(start_at, select, n) => {
                 generate_integers(self, stctxt, start_at, select, n)
             }
@@ -449,8 +916,22 @@ impl<N: Node> Context<N> {
             Transform::SystemProperty(p) => system_property(self, stctxt, p),
             Transform::AvailableSystemProperties => available_system_properties(),
             Transform::Document(uris, base) => document(self, stctxt, uris, base),
+            Transform::ParseXml(s) => parse_xml(self, stctxt, s),
+            Transform::Serialize(s, p) => serialize(self, stctxt, s, p),
+            Transform::NestedTransform(o) => nested_transform(self, stctxt, o),
+            Transform::Collection(uri) => collection(self, stctxt, uri),
+            Transform::UriCollection(uri) => uri_collection(self, stctxt, uri),
             Transform::Invoke(qn, a) => invoke(self, stctxt, qn, a),
-            Transform::Message(b, s, e, t) => message(self, stctxt, b, s, e, t),
+            Transform::Message(b, s, e, t, l) => message(self, stctxt, b, s, e, t, l),
+            Transform::ResultDocument(h, o, p, c) => result_document(self, stctxt, h, o, p, c),
+            Transform::SourceDocument(h, s, b) => source_document(self, stctxt, h, *s, b),
+            Transform::ExtensionInstruction(qn, a, fb) => {
+                extension_instruction(self, stctxt, qn, a, fb)
+            }
+            Transform::Trace(v, l) => trace(self, stctxt, v, l),
+            Transform::InstanceOf(s, t) => instance_of(self, stctxt, s, t),
+            Transform::Cast(s, t, o) => cast(self, stctxt, s, t, *o),
+            Transform::Castable(s, t, o) => castable(self, stctxt, s, t, *o),
             Transform::Error(k, m) => tr_error(self, k, m),
             Transform::NotImplemented(s) => not_implemented(self, s),
             _ => Err(Error::new(
@@ -466,24 +947,44 @@ impl<N: Node> From<Sequence<N>> for Context<N> {
         Context {
             cur: value,
             i: 0,
+            focus: None,
             previous_context: None,
             depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
             rd: None,
             templates: vec![],
             current_templates: vec![],
+            template_cache: Some(TemplateCache::default()),
             callables: HashMap::new(),
             vars: HashMap::new(),
+            tunnel: HashMap::new(),
+            globals: Globals::new(),
+            global: false,
             keys: HashMap::new(),
             key_values: HashMap::new(),
             current_grouping_key: None,
             current_group: Sequence::new(),
             od: OutputDefinition::new(),
+            outputs: HashMap::new(),
             base_url: None,
             namespaces: vec![],
+            recovery: ErrorRecovery::default(),
+            collations: HashMap::new(),
+            default_collation: None,
+            direct_output: false,
         }
     }
 }
 
+// The path to an item, for error reporting
+fn location<N: Node>(i: Option<&Item<N>>) -> String {
+    match i {
+        Some(Item::Node(n)) => n.path(),
+        Some(i) => format!("item \"{}\"", i.to_string()),
+        None => String::from("no context item"),
+    }
+}
+
 /// Builder for a [Context]
 pub struct ContextBuilder<N: Node>(Context<N>);
 
@@ -493,12 +994,20 @@ impl<N: Node> ContextBuilder<N> {
     }
     pub fn context(mut self, s: Sequence<N>) -> Self {
         self.0.cur = s;
+        self.0.focus = None;
+        self.0.global = false;
         self
     }
     pub fn index(mut self, i: usize) -> Self {
         self.0.i = i;
         self
     }
+    /// Set the context position (starting at 1) and size, for a context item that was selected from a sequence.
+    /// This must follow setting the context, since setting the context resets the position.
+    pub fn position(mut self, p: usize, size: usize) -> Self {
+        self.0.focus = Some((p, size));
+        self
+    }
     pub fn previous_context(mut self, i: Option<Item<N>>) -> Self {
         self.0.previous_context = i;
         self
@@ -521,12 +1030,24 @@ impl<N: Node> ContextBuilder<N> {
     }
     pub fn template(mut self, t: Template<N>) -> Self {
         self.0.templates.push(Rc::new(t));
-        self
+        self.reset_template_cache()
     }
     pub fn template_all(mut self, v: Vec<Template<N>>) -> Self {
         for t in v {
             self.0.templates.push(Rc::new(t))
         }
+        self.reset_template_cache()
+    }
+    /// Enable or disable caching of the templates that may match nodes with the same type and name.
+    pub fn template_cache(mut self, enable: bool) -> Self {
+        self.0.template_cache(enable);
+        self
+    }
+    // Adding a template invalidates any cached candidates
+    fn reset_template_cache(mut self) -> Self {
+        if self.0.template_cache.is_some() {
+            self.0.template_cache = Some(TemplateCache::default())
+        }
         self
     }
     pub fn current_templates(mut self, c: Vec<Rc<Template<N>>>) -> Self {
@@ -545,6 +1066,10 @@ impl<N: Node> ContextBuilder<N> {
         self.0.od = od;
         self
     }
+    pub fn named_output_definition(mut self, qn: QualifiedName, od: OutputDefinition) -> Self {
+        self.0.outputs.insert(qn, od);
+        self
+    }
     pub fn base_url(mut self, b: Url) -> Self {
         self.0.base_url = Some(b);
         self
@@ -553,10 +1078,26 @@ impl<N: Node> ContextBuilder<N> {
         self.0.namespaces = ns;
         self
     }
+    pub fn max_depth(mut self, d: usize) -> Self {
+        self.0.max_depth = d;
+        self
+    }
+    pub fn collation(mut self, uri: impl Into<String>, c: Collation) -> Self {
+        self.0.collations.insert(uri.into(), c);
+        self
+    }
+    pub fn default_collation(mut self, uri: impl Into<String>) -> Self {
+        self.0.default_collation = Some(uri.into());
+        self
+    }
     pub fn callable(mut self, qn: QualifiedName, c: Callable<N>) -> Self {
         self.0.callables.insert(qn, c);
         self
     }
+    pub fn error_recovery(mut self, r: ErrorRecovery) -> Self {
+        self.0.recovery = r;
+        self
+    }
     pub fn build(self) -> Context<N> {
         self.0
     }
@@ -583,10 +1124,67 @@ where
     H: FnMut(&Url) -> Result<String, Error>, // Fetches the data from a URL
 {
     pub(crate) message: Option<F>,
+    pub(crate) message_handler: Option<MessageHandler>,
     pub(crate) parser: Option<G>,
     pub(crate) fetcher: Option<H>,
+    pub(crate) result_document: Option<ResultDocumentHandler<N>>,
+    pub(crate) document_factory: Option<DocumentFactory<N>>,
+    pub(crate) iteration: Option<IterationControl<N>>,
+    pub(crate) collection: Option<CollectionResolver<N>>,
+    // Collections that have been resolved, so that the same nodes are returned each time. The key is None for the default collection.
+    pub(crate) collections: HashMap<Option<String>, Sequence<N>>,
+    pub(crate) uri_collection: Option<UriCollectionResolver>,
+    // Extension functions, keyed by name and arity
+    pub(crate) functions: HashMap<(QualifiedName, usize), ExtensionFunction<N>>,
+    // Handlers for extension instructions, keyed by the name of the element
+    pub(crate) extension_elements: HashMap<QualifiedName, ExtensionElement<N>>,
+    // Stylesheets that have been compiled by the transform function, keyed by the stylesheet node.
+    pub(crate) stylesheets: Vec<(N, Context<N>)>,
+    pub(crate) secure: bool,
+}
+
+/// The details of a message emitted by the transformation, i.e. xsl:message.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MessageInfo {
+    /// The serialised content of the message
+    pub message: String,
+    /// Whether the message terminates the transformation
+    pub terminate: bool,
+    /// The error code of the message, if any
+    pub code: Option<QualifiedName>,
+    /// Where the instruction occurs in the stylesheet, if it is known
+    pub location: Option<Location>,
 }
 
+/// A callback that receives the details of each message.
+pub type MessageHandler = Box<dyn FnMut(&MessageInfo) -> Result<(), Error>>;
+
+/// A callback that receives a secondary result document.
+/// The arguments are the URI of the result document, its content, and the effective output definition.
+pub type ResultDocumentHandler<N> =
+    Box<dyn FnMut(&str, Sequence<N>, &OutputDefinition) -> Result<(), Error>>;
+
+/// A callback that creates a new, empty document, in which result trees are constructed.
+pub type DocumentFactory<N> = Box<dyn FnMut() -> Result<N, Error>>;
+
+/// A callback that resolves the URI of a collection to its documents or nodes.
+/// The argument is None for the default collection.
+pub type CollectionResolver<N> = Box<dyn FnMut(Option<&str>) -> Result<Sequence<N>, Error>>;
+
+/// A callback that resolves the URI of a collection to the URIs of its resources, i.e. fn:uri-collection.
+/// The argument is None for the default collection.
+pub type UriCollectionResolver = Box<dyn FnMut(Option<&str>) -> Result<Vec<String>, Error>>;
+
+/// A function, implemented in Rust, that may be called from a stylesheet or XPath expression.
+/// The arguments are evaluated before the function is called.
+pub type ExtensionFunction<N> = Box<dyn Fn(&[Sequence<N>]) -> Result<Sequence<N>, Error>>;
+
+/// A handler for an extension instruction, implemented in Rust.
+/// The first argument is the result document, which is used to create nodes.
+/// The second argument is the attributes of the instruction, with their attribute value templates evaluated.
+pub type ExtensionElement<N> =
+    Box<dyn Fn(&N, &[(QualifiedName, String)]) -> Result<Sequence<N>, Error>>;
+
 impl<N: Node, F, G, H> StaticContext<N, F, G, H>
 where
     F: FnMut(&str) -> Result<(), Error>,
@@ -596,8 +1194,34 @@ where
     pub fn new() -> Self {
         StaticContext {
             message: None,
+            message_handler: None,
             parser: None,
             fetcher: None,
+            result_document: None,
+            document_factory: None,
+            iteration: None,
+            collection: None,
+            collections: HashMap::new(),
+            uri_collection: None,
+            functions: HashMap::new(),
+            extension_elements: HashMap::new(),
+            stylesheets: vec![],
+            secure: false,
+        }
+    }
+    /// Signal an error if secure processing is enabled, since the operation accesses resources outside of the transformation.
+    pub(crate) fn check_secure(&self, operation: &str) -> Result<(), Error> {
+        if self.secure {
+            Err(Error::new_with_code(
+                ErrorKind::Forbidden,
+                format!(
+                    "{} is not permitted when secure processing is enabled",
+                    operation
+                ),
+                Some(QualifiedName::new(None, None, "XRSP0001")),
+            ))
+        } else {
+            Ok(())
         }
     }
 }
@@ -623,9 +1247,11 @@ where
 ///        None,
 ///        Box::new(Transform::Empty),
 ///        Box::new(Transform::Empty),
+///        None,
 ///    ),
 ///    Transform::Literal(Item::Value(Rc::new(Value::from("element content")))),
-///   ]))
+///   ])),
+///   true,
 /// );
 /// let mut context = ContextBuilder::new()
 ///    .result_document(Rc::new(Node::new()))
@@ -660,6 +1286,15 @@ where
         self.0.message = Some(f);
         self
     }
+    /// Set up a callback that receives the details of each message, including where it was emitted.
+    /// This is in addition to the callback given by [StaticContextBuilder::message].
+    pub fn message_handler<M>(mut self, m: M) -> Self
+    where
+        M: FnMut(&MessageInfo) -> Result<(), Error> + 'static,
+    {
+        self.0.message_handler = Some(Box::new(m));
+        self
+    }
     pub fn parser(mut self, p: G) -> Self {
         self.0.parser = Some(p);
         self
@@ -668,6 +1303,103 @@ where
         self.0.fetcher = Some(f);
         self
     }
+    /// Set up a callback for secondary result documents, i.e. xsl:result-document.
+    pub fn result_document<R>(mut self, r: R) -> Self
+    where
+        R: FnMut(&str, Sequence<N>, &OutputDefinition) -> Result<(), Error> + 'static,
+    {
+        self.0.result_document = Some(Box::new(r));
+        self
+    }
+    /// Set up a callback that receives each secondary result document, as for [StaticContextBuilder::result_document],
+    /// but constructed in a tree of another implementation, R.
+    /// The document factory creates a new, empty document of that implementation, and the content of the result document is copied into it.
+    pub fn result_document_in<R, D, C>(self, mut factory: D, mut callback: C) -> Self
+    where
+        R: Node,
+        D: FnMut() -> Result<R, Error> + 'static,
+        C: FnMut(&str, R, &OutputDefinition) -> Result<(), Error> + 'static,
+    {
+        self.result_document(move |uri, content: Sequence<N>, od| {
+            let mut doc = factory()?;
+            copy_into(&mut doc, content)?;
+            callback(uri, doc, od)
+        })
+    }
+    /// Set up a callback that creates a new, empty document.
+    /// This allows the result to be constructed in a tree other than the source tree, for example a tree that is created on demand.
+    /// The document is of the same implementation as the source tree; see [Context::evaluate_into] and
+    /// [StaticContextBuilder::result_document_in] for a result in another implementation.
+    /// Each secondary result document (xsl:result-document) is constructed in its own new document,
+    /// and a context that has no result document uses a new document for the primary result.
+    pub fn document_factory<D>(mut self, d: D) -> Self
+    where
+        D: FnMut() -> Result<N, Error> + 'static,
+    {
+        self.0.document_factory = Some(Box::new(d));
+        self
+    }
+    /// Set up a callback that resolves the URI of a collection, i.e. fn:collection.
+    /// The resolver is called once for each collection; subsequent calls return the same nodes.
+    /// Without a resolver, no collection is available (FODC0002).
+    /// [crate::externals::directory_collection] is a resolver that reads the files in a directory.
+    pub fn collection<C>(mut self, c: C) -> Self
+    where
+        C: FnMut(Option<&str>) -> Result<Sequence<N>, Error> + 'static,
+    {
+        self.0.collection = Some(Box::new(c));
+        self
+    }
+    /// Set up a callback that resolves the URI of a collection to the URIs of its resources, i.e. fn:uri-collection.
+    /// Without a resolver, no collection is available (FODC0002).
+    /// [crate::externals::directory_uri_collection] is a resolver that lists the files in a directory.
+    pub fn uri_collection<C>(mut self, c: C) -> Self
+    where
+        C: FnMut(Option<&str>) -> Result<Vec<String>, Error> + 'static,
+    {
+        self.0.uri_collection = Some(Box::new(c));
+        self
+    }
+    /// Register an extension function, with the given name and number of parameters.
+    /// The name must be in a namespace, so the function is called using a prefix that is bound to that namespace,
+    /// e.g. ext:lookup("key") where the ext prefix is declared in the stylesheet.
+    /// A stylesheet function with the same name takes precedence.
+    /// It is an error (XTSE0740) if the name is not in a namespace, since it could then hide a builtin function.
+    pub fn function(
+        mut self,
+        name: QualifiedName,
+        arity: usize,
+        f: ExtensionFunction<N>,
+    ) -> Result<Self, Error> {
+        if name.get_nsuri_ref().is_none() {
+            return Err(Error::new_with_code(
+                ErrorKind::StaticData,
+                format!("extension function \"{}\" must be in a namespace", name),
+                Some(QualifiedName::new(None, None, "XTSE0740")),
+            ));
+        }
+        self.0.functions.insert((name, arity), f);
+        Ok(self)
+    }
+    /// Register a handler for an extension instruction.
+    /// The name must be in a namespace that the stylesheet declares as an extension namespace,
+    /// using the extension-element-prefixes attribute, e.g. ext:doSomething where the ext prefix is an extension element prefix.
+    /// If no handler is registered for an extension instruction then its xsl:fallback children are evaluated.
+    pub fn extension_element(mut self, name: QualifiedName, e: ExtensionElement<N>) -> Self {
+        self.0.extension_elements.insert(name, e);
+        self
+    }
+    /// Enable or disable secure processing. It is disabled by default.
+    /// When enabled, the document, collection, uri-collection and parse-xml functions, xsl:source-document, xsl:result-document,
+    /// and the stylesheet-text option of the transform function raise an error (code XRSP0001),
+    /// even if the fetcher, parser, collection and result document callbacks have been set up.
+    /// Since these are the only operations that call the parser, no document is parsed, and no entities are expanded, during the transformation.
+    /// The depth of template and function invocation is also limited to [SECURE_MAX_DEPTH].
+    /// The stylesheet cannot change this setting.
+    pub fn secure_processing(mut self, s: bool) -> Self {
+        self.0.secure = s;
+        self
+    }
     pub fn build(self) -> StaticContext<N, F, G, H> {
         self.0
     }
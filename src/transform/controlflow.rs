@@ -1,11 +1,14 @@
 //! These functions are for features that control program flow.
 
-use std::collections::HashMap;
+use std::ops::ControlFlow;
 use std::rc::Rc;
 use url::Url;
 
-use crate::item::{Node, Sequence, SequenceTrait};
+use crate::item::{Item, Node, Sequence, SequenceTrait};
+use crate::qname::QualifiedName;
 use crate::transform::context::{Context, ContextBuilder, StaticContext};
+use crate::transform::navigate::{first_items, visit_items};
+use crate::transform::strings::{collation_argument, DistinctItems};
 use crate::transform::{do_sort, Grouping, Order, Transform};
 use crate::value::{Operator, Value};
 use crate::xdmerror::{Error, ErrorKind};
@@ -24,7 +27,7 @@ pub(crate) fn tr_loop<
     b: &Transform<N>,
 ) -> Result<Sequence<N>, Error> {
     if v.is_empty() {
-        return Ok(vec![]);
+        return Ok(Sequence::empty());
     }
     // This implementation only supports one variable
 
@@ -41,7 +44,58 @@ pub(crate) fn tr_loop<
     Ok(result)
 }
 
+/// Evaluate a quantified expression, i.e. "some" or "every".
+/// The variables are bound to each combination of items in turn, and the test is evaluated for each.
+/// Evaluation stops at the first decisive test, and the sequences are only evaluated as far as is necessary.
+pub(crate) fn quantified<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    every: &bool,
+    v: &[(String, Transform<N>)],
+    t: &Transform<N>,
+) -> Result<Sequence<N>, Error> {
+    let decided = quantified_bindings(ctxt, stctxt, *every, v, t)?.is_break();
+    Ok(vec![Item::Value(Rc::new(Value::from(decided != *every)))])
+}
+
+// Bind the first variable to each item of its sequence, then the remaining variables.
+// Breaks when a test is true for "some", or false for "every".
+fn quantified_bindings<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    every: bool,
+    v: &[(String, Transform<N>)],
+    t: &Transform<N>,
+) -> Result<ControlFlow<()>, Error> {
+    match v.split_first() {
+        None => {
+            if first_items(ctxt, stctxt, t, 2)?.effective_boolean_value()? != every {
+                Ok(ControlFlow::Break(()))
+            } else {
+                Ok(ControlFlow::Continue(()))
+            }
+        }
+        Some(((name, s), rest)) => visit_items(ctxt, stctxt, s, &mut |stctxt, i| {
+            let lctxt = ContextBuilder::from(ctxt)
+                .variable(name.clone(), vec![i.clone()])
+                .build();
+            quantified_bindings(&lctxt, stctxt, every, rest, t)
+        }),
+    }
+}
+
 /// Choose a sequence to return.
+/// The clause of the first condition that is true is evaluated, otherwise the default clause is evaluated.
 pub(crate) fn switch<
     N: Node,
     F: FnMut(&str) -> Result<(), Error>,
@@ -53,15 +107,13 @@ pub(crate) fn switch<
     v: &Vec<(Transform<N>, Transform<N>)>,
     o: &Transform<N>,
 ) -> Result<Sequence<N>, Error> {
-    let mut candidate = ctxt.dispatch(stctxt, o)?;
+    // The conditions are evaluated in order. Once one is true, no later condition or clause is evaluated.
     for (t, w) in v {
-        let r = ctxt.dispatch(stctxt, t)?;
-        if r.to_bool() {
-            candidate = ctxt.dispatch(stctxt, w)?;
-            break;
+        if first_items(ctxt, stctxt, t, 2)?.to_bool() {
+            return ctxt.dispatch(stctxt, w);
         }
     }
-    Ok(candidate)
+    ctxt.dispatch(stctxt, o)
 }
 
 /// Evaluate a combinator for each item.
@@ -76,16 +128,18 @@ pub fn for_each<
     g: &Option<Grouping<N>>,
     s: &Transform<N>,
     body: &Transform<N>,
-    o: &Vec<(Order, Transform<N>)>,
+    o: &Vec<(Order<N>, Transform<N>)>,
 ) -> Result<Sequence<N>, Error> {
     match g {
         None => {
             let mut result: Sequence<N> = Vec::new();
             let mut seq = ctxt.dispatch(stctxt, s)?;
             do_sort(&mut seq, o, ctxt, stctxt)?;
-            for i in seq {
+            let size = seq.len();
+            for (p, i) in seq.into_iter().enumerate() {
                 let mut v = ContextBuilder::from(ctxt)
                     .context(vec![i.clone()])
+                    .position(p + 1, size)
                     .previous_context(Some(i))
                     .build()
                     .dispatch(stctxt, body)?;
@@ -93,13 +147,30 @@ pub fn for_each<
             }
             Ok(result)
         }
-        Some(Grouping::By(b)) => group_by(ctxt, stctxt, b, s, body, o),
+        Some(Grouping::By(b, c)) => group_by(ctxt, stctxt, b, c, s, body, o),
         Some(Grouping::Adjacent(a)) => group_adjacent(ctxt, stctxt, a, s, body, o),
         Some(Grouping::StartingWith(v)) => group_starting_with(ctxt, stctxt, v, s, body, o),
         Some(Grouping::EndingWith(v)) => group_ending_with(ctxt, stctxt, v, s, body, o),
     }
 }
 
+/// Sort the selected items. The result is the sorted sequence, rather than the result of evaluating a body for each item.
+pub fn perform_sort<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    s: &Transform<N>,
+    o: &Vec<(Order<N>, Transform<N>)>,
+) -> Result<Sequence<N>, Error> {
+    let mut seq = ctxt.dispatch(stctxt, s)?;
+    do_sort(&mut seq, o, ctxt, stctxt)?;
+    Ok(seq)
+}
+
 /// Evaluate a combinator for each group of items.
 fn group_by<
     N: Node,
@@ -110,14 +181,18 @@ fn group_by<
     ctxt: &Context<N>,
     stctxt: &mut StaticContext<N, F, G, H>,
     by: &Vec<Transform<N>>,
+    c: &Option<Box<Transform<N>>>,
     s: &Transform<N>,
     body: &Transform<N>,
-    o: &Vec<(Order, Transform<N>)>,
+    o: &Vec<(Order<N>, Transform<N>)>,
 ) -> Result<Sequence<N>, Error> {
-    // Each 'by' expression is evaluated to a string key and stored in the hashmap
+    // Each 'by' expression is evaluated to a key value.
+    // String keys are the same if they are equal in the collation, other keys are the same if their values are equal.
+    // Groups are kept in order of first appearance.
     // TODO: this implementation is only supporting a single key
     let t = by[0].clone();
-    let mut groups = HashMap::new();
+    let mut distinct = DistinctItems::new(collation_argument(ctxt, stctxt, c)?);
+    let mut groups: Vec<(Item<N>, Sequence<N>)> = vec![];
     ctxt.dispatch(stctxt, s)?.iter().try_for_each(|i| {
         // There may be multiple keys returned.
         // For each one, add this item into the group for that key
@@ -127,12 +202,19 @@ fn group_by<
             .build()
             .dispatch(stctxt, &t)?
             .iter()
-            .for_each(|k| {
-                let e: &mut Sequence<N> = groups.entry(k.to_string()).or_default();
-                e.push(i.clone());
-            });
-        Ok(())
+            .try_for_each(|k| {
+                let k = Item::Value(k.atomize()?);
+                match distinct.find_or_insert(&k) {
+                    Ok(g) => groups[g].1.push(i.clone()),
+                    Err(_) => groups.push((k, vec![i.clone()])),
+                }
+                Ok::<(), Error>(())
+            })
     })?;
+    let groups: Vec<(String, Sequence<N>)> = groups
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect();
 
     if !o.is_empty() {
         // Build a vector of the groups, and then sort the vector
@@ -153,7 +235,7 @@ fn group_by<
             // TODO: support all data types
             key_seq.to_string()
         });
-        if o[0].0 == Order::Descending {
+        if o[0].0.is_descending(ctxt, stctxt)? {
             gr_vec.reverse();
         }
         // Now evaluate the body for each group
@@ -194,14 +276,14 @@ fn group_adjacent<
     adj: &Vec<Transform<N>>,
     s: &Transform<N>,
     body: &Transform<N>,
-    o: &Vec<(Order, Transform<N>)>,
+    o: &Vec<(Order<N>, Transform<N>)>,
 ) -> Result<Sequence<N>, Error> {
     // TODO: this implementation is only supporting a single key
     let t = adj[0].clone();
     let mut groups = Vec::new();
     let sel = ctxt.dispatch(stctxt, s)?;
     if sel.is_empty() {
-        return Ok(vec![]);
+        return Ok(Sequence::empty());
     } else {
         let mut curgrp = vec![sel[0].clone()];
         let mut curkey = ContextBuilder::from(ctxt)
@@ -261,7 +343,7 @@ fn group_adjacent<
             // TODO: support all data types
             key_seq.to_string()
         });
-        if o[0].0 == Order::Descending {
+        if o[0].0.is_descending(ctxt, stctxt)? {
             gr_vec.reverse();
         }
         // Now evaluate the body for each group
@@ -302,7 +384,7 @@ fn group_starting_with<
     _pat: &Vec<Transform<N>>,
     _s: &Transform<N>,
     _body: &Transform<N>,
-    _o: &Vec<(Order, Transform<N>)>,
+    _o: &Vec<(Order<N>, Transform<N>)>,
 ) -> Result<Sequence<N>, Error> {
     Err(Error::new(
         ErrorKind::NotImplemented,
@@ -322,10 +404,115 @@ pub fn group_ending_with<
     _pat: &Vec<Transform<N>>,
     _s: &Transform<N>,
     _body: &Transform<N>,
-    _o: &Vec<(Order, Transform<N>)>,
+    _o: &Vec<(Order<N>, Transform<N>)>,
 ) -> Result<Sequence<N>, Error> {
     Err(Error::new(
         ErrorKind::NotImplemented,
         String::from("not implemented"),
     ))
 }
+
+/// Signals from the body of an iteration to the enclosing [Transform::Iterate].
+pub(crate) enum IterationControl<N: Node> {
+    /// Continue with the next item, with new values for the named parameters.
+    Next(Vec<(String, Sequence<N>)>),
+    /// Stop the iteration.
+    Break,
+}
+
+/// Evaluate a combinator for each item, carrying parameters forward from one iteration to the next.
+/// The iteration does not recurse, so the stack does not grow with the length of the sequence.
+pub(crate) fn iterate<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    s: &Transform<N>,
+    params: &[(QualifiedName, Transform<N>)],
+    body: &Transform<N>,
+    completion: &Transform<N>,
+) -> Result<Sequence<N>, Error> {
+    let mut values = params.iter().try_fold(vec![], |mut acc, (qn, t)| {
+        acc.push((qn.to_string(), ctxt.dispatch(stctxt, t)?));
+        Ok::<_, Error>(acc)
+    })?;
+    let mut result: Sequence<N> = Vec::new();
+    for i in ctxt.dispatch(stctxt, s)? {
+        let lctxt = values
+            .iter()
+            .fold(
+                ContextBuilder::from(ctxt)
+                    .context(vec![i.clone()])
+                    .previous_context(Some(i)),
+                |b, (n, v)| b.variable(n.clone(), v.clone()),
+            )
+            .build();
+        // An enclosing iteration's signal is restored once this body has been evaluated
+        let outer = stctxt.iteration.take();
+        let mut v = lctxt.dispatch(stctxt, body)?;
+        result.append(&mut v);
+        match std::mem::replace(&mut stctxt.iteration, outer) {
+            Some(IterationControl::Break) => return Ok(result),
+            Some(IterationControl::Next(nv)) => {
+                for (n, v) in nv {
+                    match values.iter_mut().find(|(m, _)| *m == n) {
+                        Some(p) => p.1 = v,
+                        None => {
+                            return Err(Error::new_with_code(
+                                ErrorKind::StaticAbsent,
+                                format!("\"{}\" is not a parameter of the iteration", n),
+                                Some(QualifiedName::new(None, None, "XTSE3130")),
+                            ))
+                        }
+                    }
+                }
+            }
+            None => {}
+        }
+    }
+    let mut v = values
+        .into_iter()
+        .fold(ContextBuilder::from(ctxt), |b, (n, v)| b.variable(n, v))
+        .build()
+        .dispatch(stctxt, completion)?;
+    result.append(&mut v);
+    Ok(result)
+}
+
+/// Continue with the next iteration, with new values for the parameters.
+pub(crate) fn next_iteration<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    params: &[(QualifiedName, Transform<N>)],
+) -> Result<Sequence<N>, Error> {
+    let values = params.iter().try_fold(vec![], |mut acc, (qn, t)| {
+        acc.push((qn.to_string(), ctxt.dispatch(stctxt, t)?));
+        Ok::<_, Error>(acc)
+    })?;
+    stctxt.iteration = Some(IterationControl::Next(values));
+    Ok(Sequence::empty())
+}
+
+/// Stop the iteration. The result of the transformation is added to the result of the iteration.
+pub(crate) fn tr_break<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    b: &Transform<N>,
+) -> Result<Sequence<N>, Error> {
+    let result = ctxt.dispatch(stctxt, b)?;
+    stctxt.iteration = Some(IterationControl::Break);
+    Ok(result)
+}
@@ -3,11 +3,12 @@
 use std::rc::Rc;
 
 #[allow(unused_imports)]
-use chrono::{DateTime, Datelike, FixedOffset, Local, Timelike};
+use chrono::{DateTime, Datelike, FixedOffset, Local, NaiveDate, NaiveTime, Timelike};
 use url::Url;
 
 use crate::item::{Item, Node, Sequence, SequenceTrait};
 use crate::parser::datetime::parse as picture_parse;
+use crate::qname::QualifiedName;
 use crate::transform::context::{Context, StaticContext};
 use crate::transform::Transform;
 use crate::value::Value;
@@ -49,7 +50,7 @@ pub fn format_date_time<
     let dt = ctxt.dispatch(stctxt, value)?;
     let pic = picture_parse::<N>(&ctxt.dispatch(stctxt, picture)?.to_string())?;
     match dt.len() {
-        0 => Ok(vec![]), // Empty value returns empty sequence
+        0 => Ok(Sequence::empty()), // Empty value returns empty sequence
         1 => {
             match &dt[0] {
                 Item::Value(d) => match **d {
@@ -105,7 +106,7 @@ pub fn format_date<
     let dt = ctxt.dispatch(stctxt, value)?;
     let pic = picture_parse::<N>(&ctxt.dispatch(stctxt, picture)?.to_string())?;
     match dt.len() {
-        0 => Ok(vec![]), // Empty value returns empty sequence
+        0 => Ok(Sequence::empty()), // Empty value returns empty sequence
         1 => {
             match &dt[0] {
                 Item::Value(d) => match **d {
@@ -162,7 +163,7 @@ pub fn format_time<
     let dt = ctxt.dispatch(stctxt, value)?;
     let pic = picture_parse::<N>(&ctxt.dispatch(stctxt, picture)?.to_string())?;
     match dt.len() {
-        0 => Ok(vec![]), // Empty value returns empty sequence
+        0 => Ok(Sequence::empty()), // Empty value returns empty sequence
         1 => {
             match &dt[0] {
                 Item::Value(d) => match **d {
@@ -199,3 +200,262 @@ pub fn format_time<
         )),
     }
 }
+
+/// XPath parse-ietf-date function. See XPath Functions 3.1, 9.8.4.
+/// The date may be in the format of RFC 1123, RFC 850, or the C asctime function.
+/// A two-digit year is in the 20th century, and a date without a timezone is in UTC.
+/// As with other dateTime values, the result is converted to the local timezone.
+pub fn parse_ietf_date<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    value: &Transform<N>,
+) -> Result<Sequence<N>, Error> {
+    let v = ctxt.dispatch(stctxt, value)?;
+    match v.len() {
+        0 => Ok(Sequence::empty()),
+        1 => {
+            let s = v.to_string();
+            let dt = IetfDate::new(s.as_str()).parse().ok_or_else(|| {
+                Error::new_with_code(
+                    ErrorKind::TypeError,
+                    format!("\"{}\" is not a valid IETF date", s),
+                    Some(QualifiedName::new(None, None, "FORG0010")),
+                )
+            })?;
+            Ok(vec![Item::Value(Rc::new(Value::DateTime(
+                dt.with_timezone(&Local),
+            )))])
+        }
+        _ => Err(Error::new(
+            ErrorKind::TypeError,
+            String::from("not a singleton sequence"),
+        )),
+    }
+}
+
+const DAY_NAMES: [&str; 14] = [
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+    "Sunday",
+    "Mon",
+    "Tue",
+    "Wed",
+    "Thu",
+    "Fri",
+    "Sat",
+    "Sun",
+];
+const MONTH_NAMES: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+// Timezone names and their offset from UTC in hours. Longer names come first, so that UTC is not read as UT.
+const TZ_NAMES: [(&str, i32); 11] = [
+    ("UTC", 0),
+    ("UT", 0),
+    ("GMT", 0),
+    ("EST", -5),
+    ("EDT", -4),
+    ("CST", -6),
+    ("CDT", -5),
+    ("MST", -7),
+    ("MDT", -6),
+    ("PST", -8),
+    ("PDT", -7),
+];
+
+// A scanner for the grammar in XPath Functions 3.1, 9.8.4.3.
+// Each method consumes its production and returns None if the input does not match.
+struct IetfDate<'a> {
+    rest: &'a str,
+}
+
+impl<'a> IetfDate<'a> {
+    fn new(input: &'a str) -> Self {
+        IetfDate { rest: input }
+    }
+    // input ::= S? (dayname ","? S)? ((datespec S time) | asctime) S?
+    fn parse(mut self) -> Option<DateTime<FixedOffset>> {
+        self.space();
+        if self.name(&DAY_NAMES).is_some() {
+            self.literal(",");
+            self.required_space()?;
+        }
+        let (day, month, year, (time, offset)) = match self.name(&MONTH_NAMES) {
+            // asctime ::= monthname dsep daynum S time S year
+            Some(month) => {
+                self.dsep()?;
+                let day = self.digits(1, 2)?;
+                self.required_space()?;
+                let time = self.time()?;
+                self.required_space()?;
+                (day, month, self.year()?, time)
+            }
+            // datespec ::= daynum dsep monthname dsep year
+            None => {
+                let day = self.digits(1, 2)?;
+                self.dsep()?;
+                let month = self.name(&MONTH_NAMES)?;
+                self.dsep()?;
+                let year = self.year()?;
+                self.required_space()?;
+                (day, month, year, self.time()?)
+            }
+        };
+        self.space();
+        if !self.rest.is_empty() {
+            return None;
+        }
+        NaiveDate::from_ymd_opt(year as i32, month as u32 + 1, day)?
+            .and_time(time)
+            .and_local_timezone(offset)
+            .single()
+    }
+    // time ::= hours ":" minutes (":" seconds)? (S? timezone)?
+    fn time(&mut self) -> Option<(NaiveTime, FixedOffset)> {
+        let hours = self.digits(1, 2)?;
+        self.literal(":").then_some(())?;
+        let minutes = self.digits(2, 2)?;
+        let (seconds, nanos) = if self.literal(":") {
+            let seconds = self.digits(2, 2)?;
+            let nanos = if self.literal(".") {
+                let fraction = self.take_while(|c| c.is_ascii_digit());
+                if fraction.is_empty() {
+                    return None;
+                }
+                format!("{:0<9}", fraction.get(..9).unwrap_or(fraction))
+                    .parse::<u32>()
+                    .ok()?
+            } else {
+                0
+            };
+            (seconds, nanos)
+        } else {
+            (0, 0)
+        };
+        let time = NaiveTime::from_hms_nano_opt(hours, minutes, seconds, nanos)?;
+        // The timezone is optional, so if there isn't one then the space before it is not consumed
+        let before = self.rest;
+        self.space();
+        match self.timezone() {
+            Some(offset) => Some((time, offset)),
+            None => {
+                self.rest = before;
+                Some((time, FixedOffset::east_opt(0)?))
+            }
+        }
+    }
+    // timezone ::= tzname | tzoffset (S? "(" S? tzname S? ")")?
+    fn timezone(&mut self) -> Option<FixedOffset> {
+        if let Some(i) = self.name(&TZ_NAMES.map(|(n, _)| n)) {
+            return FixedOffset::east_opt(TZ_NAMES[i].1 * 3600);
+        }
+        // tzoffset ::= ("+"|"-") tzhours ":"? tzminutes?
+        let sign = if self.literal("+") {
+            1
+        } else if self.literal("-") {
+            -1
+        } else {
+            return None;
+        };
+        let hours = self.digits(1, 2)?;
+        if hours > 14 {
+            return None;
+        }
+        let colon = self.literal(":");
+        let minutes = match self.digits(2, 2) {
+            Some(m) if m < 60 => m,
+            Some(_) => return None,
+            None if colon => return None,
+            None => 0,
+        };
+        // The name of the timezone may follow the offset, in parentheses; it is ignored
+        let before = self.rest;
+        self.space();
+        if self.literal("(") {
+            self.space();
+            self.name(&TZ_NAMES.map(|(n, _)| n))?;
+            self.space();
+            self.literal(")").then_some(())?;
+        } else {
+            self.rest = before;
+        }
+        FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60) as i32)
+    }
+    // year ::= digit digit (digit digit)?
+    fn year(&mut self) -> Option<u32> {
+        let start = self.rest;
+        let year = self.digits(2, 4)?;
+        match start.len() - self.rest.len() {
+            2 => Some(1900 + year),
+            4 => Some(year),
+            _ => None,
+        }
+    }
+    // dsep ::= S | (S? "-" S?)
+    fn dsep(&mut self) -> Option<()> {
+        let space = self.space();
+        if self.literal("-") {
+            self.space();
+            Some(())
+        } else {
+            space.then_some(())
+        }
+    }
+    // One of a list of names, ignoring case. Returns the index of the name.
+    fn name(&mut self, names: &[&str]) -> Option<usize> {
+        let i = names.iter().position(|n| {
+            self.rest
+                .get(..n.len())
+                .is_some_and(|p| p.eq_ignore_ascii_case(n))
+        })?;
+        self.rest = &self.rest[names[i].len()..];
+        Some(i)
+    }
+    fn digits(&mut self, min: usize, max: usize) -> Option<u32> {
+        let n = self
+            .rest
+            .chars()
+            .take(max)
+            .take_while(|c| c.is_ascii_digit())
+            .count();
+        if n < min {
+            return None;
+        }
+        let (d, rest) = self.rest.split_at(n);
+        self.rest = rest;
+        d.parse().ok()
+    }
+    fn literal(&mut self, l: &str) -> bool {
+        match self.rest.strip_prefix(l) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> &'a str {
+        let n = self.rest.find(|c| !f(c)).unwrap_or(self.rest.len());
+        let (t, rest) = self.rest.split_at(n);
+        self.rest = rest;
+        t
+    }
+    // S ::= (x09 | x0A | x0D | x20)+
+    fn space(&mut self) -> bool {
+        !self
+            .take_while(|c| matches!(c, '\t' | '\n' | '\r' | ' '))
+            .is_empty()
+    }
+    fn required_space(&mut self) -> Option<()> {
+        self.space().then_some(())
+    }
+}
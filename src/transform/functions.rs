@@ -4,24 +4,44 @@ use pkg_version::*;
 use std::rc::Rc;
 use url::Url;
 
-use crate::item::{Item, Node, Sequence};
+use crate::item::{not_singleton, Item, Map, Node, NodeType, Sequence};
+use crate::output::OutputDefinition;
 use crate::qname::QualifiedName;
-use crate::transform::context::{Context, StaticContext};
+use crate::transform::callable::ActualParameters;
+use crate::transform::context::{Context, ContextBuilder, StaticContext};
+use crate::transform::navigate::first_items;
+use crate::transform::strings::{collation_argument, DistinctItems};
 use crate::transform::Transform;
 use crate::value::Value;
 use crate::xdmerror::{Error, ErrorKind};
+use crate::xslt::compile_nested;
 use crate::SequenceTrait;
 
 /// XPath position function.
 pub fn position<N: Node>(ctxt: &Context<N>) -> Result<Sequence<N>, Error> {
-    Ok(vec![Item::Value(Rc::new(Value::from(ctxt.i as i64 + 1)))])
+    if ctxt.global {
+        return Err(absent_focus("position"));
+    }
+    let p = ctxt.focus.map_or(ctxt.i + 1, |(p, _)| p);
+    Ok(vec![Item::Value(Rc::new(Value::from(p as i64)))])
 }
 
 /// XPath last function.
 pub fn last<N: Node>(ctxt: &Context<N>) -> Result<Sequence<N>, Error> {
-    Ok(vec![Item::Value(Rc::new(Value::from(
-        ctxt.cur.len() as i64
-    )))])
+    if ctxt.global {
+        return Err(absent_focus("size"));
+    }
+    let size = ctxt.focus.map_or(ctxt.cur.len(), |(_, s)| s);
+    Ok(vec![Item::Value(Rc::new(Value::from(size as i64)))])
+}
+
+// The context position and size are absent when a global variable is evaluated. See XSLT 9.5.
+fn absent_focus(what: &str) -> Error {
+    Error::new_with_code(
+        ErrorKind::DynamicAbsent,
+        format!("the context {} is absent in a global variable", what),
+        Some(QualifiedName::new(None, None, "XPDY0002")),
+    )
 }
 
 /// XPath count function.
@@ -40,6 +60,139 @@ pub fn tr_count<
     )))])
 }
 
+/// XPath exists function. The sequence is only evaluated as far as its first item.
+pub fn tr_exists<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    s: &Transform<N>,
+) -> Result<Sequence<N>, Error> {
+    Ok(vec![Item::Value(Rc::new(Value::from(
+        !first_items(ctxt, stctxt, s, 1)?.is_empty(),
+    )))])
+}
+
+/// XPath empty function. The sequence is only evaluated as far as its first item.
+pub fn tr_empty<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    s: &Transform<N>,
+) -> Result<Sequence<N>, Error> {
+    Ok(vec![Item::Value(Rc::new(Value::from(
+        first_items(ctxt, stctxt, s, 1)?.is_empty(),
+    )))])
+}
+
+/// XPath distinct-values function. Items are atomized, and the first of each set of equal values is retained.
+/// Strings are compared using the given collation.
+pub fn distinct_values<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    s: &Transform<N>,
+    c: &Option<Box<Transform<N>>>,
+) -> Result<Sequence<N>, Error> {
+    let mut distinct = DistinctItems::new(collation_argument(ctxt, stctxt, c)?);
+    let mut result: Sequence<N> = vec![];
+    for i in ctxt.dispatch(stctxt, s)? {
+        let v = Item::Value(i.atomize()?);
+        if distinct.find_or_insert(&v).is_err() {
+            result.push(v)
+        }
+    }
+    Ok(result)
+}
+
+/// XPath index-of function. Returns the positions, starting at 1, of the items in the sequence that are equal to the search value.
+/// Strings are compared using the given collation.
+pub fn index_of<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    s: &Transform<N>,
+    t: &Transform<N>,
+    c: &Option<Box<Transform<N>>>,
+) -> Result<Sequence<N>, Error> {
+    let collation = collation_argument(ctxt, stctxt, c)?;
+    let search = ctxt.dispatch(stctxt, t)?;
+    if search.len() != 1 {
+        return Err(Error::new(
+            ErrorKind::TypeError,
+            String::from("search value must be a singleton"),
+        ));
+    }
+    let search: Item<N> = Item::Value(search[0].atomize()?);
+    ctxt.dispatch(stctxt, s)?
+        .iter()
+        .enumerate()
+        .try_fold(vec![], |mut result, (p, i)| {
+            if collation.same_item(&Item::Value(i.atomize()?), &search) {
+                result.push(Item::Value(Rc::new(Value::from(p as i64 + 1))))
+            }
+            Ok(result)
+        })
+}
+
+/// XPath insert-before function. The items are inserted before the item at the given position, starting at 1.
+/// A position less than 1 inserts at the start of the sequence, and a position greater than its length inserts at the end.
+pub fn insert_before<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    t: &Transform<N>,
+    p: &Transform<N>,
+    i: &Transform<N>,
+) -> Result<Sequence<N>, Error> {
+    let mut result = ctxt.dispatch(stctxt, t)?;
+    let position = ctxt.dispatch(stctxt, p)?.to_int()?;
+    let inserts = ctxt.dispatch(stctxt, i)?;
+    let index = position.clamp(1, result.len() as i64 + 1) as usize - 1;
+    result.splice(index..index, inserts);
+    Ok(result)
+}
+
+/// XPath remove function. The item at the given position, starting at 1, is removed.
+/// If there is no item at that position then the sequence is unchanged.
+pub fn remove<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    t: &Transform<N>,
+    p: &Transform<N>,
+) -> Result<Sequence<N>, Error> {
+    let mut result = ctxt.dispatch(stctxt, t)?;
+    let position = ctxt.dispatch(stctxt, p)?.to_int()?;
+    if (1..=result.len() as i64).contains(&position) {
+        result.remove(position as usize - 1);
+    }
+    Ok(result)
+}
+
 /// XPath generate-id function.
 pub fn generate_id<
     N: Node,
@@ -58,12 +211,7 @@ pub fn generate_id<
             match seq.len() {
                 0 => return Ok(vec![Item::Value(Rc::new(Value::from("")))]),
                 1 => seq[0].clone(),
-                _ => {
-                    return Err(Error::new(
-                        ErrorKind::TypeError,
-                        String::from("not a singleton sequence"),
-                    ))
-                }
+                _ => return Err(not_singleton(&seq)),
             }
         }
     };
@@ -132,7 +280,7 @@ pub fn system_property<
             (Some(XSLTNS), "xsd-version") => Ok(vec![Item::Value(Rc::new(Value::from(1.1)))]),
             _ => Err(Error::new(
                 ErrorKind::Unknown,
-                format!("unknown property \"{}\"", qn),
+                format!("unknown property \"{}\"", qn.eqname()),
             )),
         }
     } else {
@@ -228,17 +376,23 @@ pub fn document<
     uris: &Box<Transform<N>>,
     _base: &Option<Box<Transform<N>>>,
 ) -> Result<Sequence<N>, Error> {
+    stctxt.check_secure("the document function")?;
     let u_list = ctxt.dispatch(stctxt, uris)?;
     if let Some(h) = &mut stctxt.fetcher {
         if let Some(g) = &mut stctxt.parser {
             u_list.iter().try_fold(vec![], |mut acc, u| {
                 // TODO: resolve relative URI against base URI
-                let url = Url::parse(u.to_string().as_str())
+                let mut url = Url::parse(u.to_string().as_str())
                     .map_err(|_| Error::new(ErrorKind::TypeError, "unable to parse URL"))?;
+                // A fragment identifier selects the element with that ID in the document
+                let fragment = url.fragment().map(String::from);
+                url.set_fragment(None);
                 let docdata = h(&url)?;
-                //let x = g(docdata.as_str())?;
-                //acc.push(Item::Node(x));
-                acc.push(Item::Node(g(docdata.as_str())?));
+                let doc = g(docdata.as_str())?;
+                match fragment {
+                    Some(id) => acc.extend(element_with_id(&doc, id.as_str()).map(Item::Node)),
+                    None => acc.push(Item::Node(doc)),
+                }
                 Ok(acc)
             })
         } else {
@@ -255,6 +409,385 @@ pub fn document<
     }
 }
 
+// Find the element in a document that has the given ID.
+// Without a DTD or schema, an ID is the value of an xml:id attribute.
+fn element_with_id<N: Node>(doc: &N, id: &str) -> Option<N> {
+    let xmlid = QualifiedName::new(
+        Some(String::from("http://www.w3.org/XML/1998/namespace")),
+        None,
+        "id",
+    );
+    doc.descend_iter().find(|n| {
+        n.node_type() == NodeType::Element
+            && n.get_attribute_node(&xmlid)
+                .is_some_and(|a| a.to_string().trim() == id)
+    })
+}
+
+/// XPath parse-xml function.
+/// The string is parsed using the parser of the static context, and the result is the document node.
+/// The string must be a well-formed document, so it must have exactly one top-level element.
+/// Since the parser may expand entities declared in the string, the function is not permitted when secure processing is enabled.
+pub fn parse_xml<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    s: &Transform<N>,
+) -> Result<Sequence<N>, Error> {
+    stctxt.check_secure("the parse-xml function")?;
+    let arg = ctxt.dispatch(stctxt, s)?;
+    if arg.is_empty() {
+        return Ok(Sequence::empty());
+    }
+    if let Some(g) = &mut stctxt.parser {
+        let doc = g(arg.to_string().as_str()).map_err(|e| {
+            Error::new_with_code(
+                e.kind,
+                format!("string is not a well-formed document: {}", e.message),
+                Some(QualifiedName::new(None, None, "FODC0006")),
+            )
+        })?;
+        if doc.child_iter().filter(|c| c.is_element()).count() == 1 {
+            Ok(vec![Item::Node(doc)])
+        } else {
+            Err(Error::new_with_code(
+                ErrorKind::TypeError,
+                "string is not a well-formed document: it must have exactly one top-level element",
+                Some(QualifiedName::new(None, None, "FODC0006")),
+            ))
+        }
+    } else {
+        Err(Error::new(
+            ErrorKind::StaticAbsent,
+            "function to parse document not supplied",
+        ))
+    }
+}
+
+// The namespace of serialization parameters
+const SERIALIZATION_NS: &str = "http://www.w3.org/2010/xslt-xquery-serialization";
+
+/// XPath serialize function.
+/// The parameters are given by an output:serialization-parameters element.
+/// The supported parameters are method, indent and omit-xml-declaration; other parameters are ignored.
+/// The methods are xml, html, xhtml, text, json and adaptive. See [SequenceTrait::serialize].
+pub fn serialize<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    s: &Transform<N>,
+    p: &Option<Box<Transform<N>>>,
+) -> Result<Sequence<N>, Error> {
+    let seq = ctxt.dispatch(stctxt, s)?;
+    let mut od = OutputDefinition::new();
+    let params = match p {
+        Some(t) => ctxt.dispatch(stctxt, t)?,
+        None => vec![],
+    };
+    match params.len() {
+        0 => {}
+        1 => serialization_parameters(&params[0], &mut od)?,
+        _ => return Err(not_singleton(&params)),
+    }
+    let serialized = seq.serialize(&od)?;
+    let result = match od.get_method().as_deref() {
+        None | Some("xml") | Some("xhtml") | Some("html") if !od.get_omit_xml_declaration() => {
+            format!(
+                "<?xml version=\"1.0\" encoding=\"{}\"?>{}",
+                od.get_encoding().unwrap_or_else(|| String::from("UTF-8")),
+                serialized
+            )
+        }
+        _ => serialized,
+    };
+    Ok(vec![Item::Value(Rc::new(Value::from(result)))])
+}
+
+/// XPath transform function. A stylesheet is compiled and evaluated.
+/// The argument is a map of options, and the result is a map whose "output" entry is the primary result.
+/// The supported options are stylesheet-node, stylesheet-text, source-node, initial-template and stylesheet-params.
+/// Since there are no QName values, the name of the initial template, and the keys of the stylesheet-params map, are strings giving local names.
+/// A stylesheet node is compiled once, and the compiled stylesheet is reused whenever the same node is supplied.
+/// The nested transformation is one level deeper than the caller, and is subject to the same limits.
+pub fn nested_transform<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    o: &Transform<N>,
+) -> Result<Sequence<N>, Error> {
+    let options = match ctxt.dispatch(stctxt, o)?.as_slice() {
+        [Item::Map(m)] => m.clone(),
+        _ => return Err(invalid_transform_options("the options must be a map")),
+    };
+    let mut style = None;
+    let mut text = None;
+    let mut source = None;
+    let mut initial = None;
+    let mut params = vec![];
+    for (k, v) in options.iter() {
+        match (k.to_string().as_str(), v.as_slice()) {
+            ("stylesheet-node", [Item::Node(n)]) => style = Some(n.clone()),
+            ("stylesheet-text", [Item::Value(t)]) => text = Some(t.to_string()),
+            ("source-node", [Item::Node(n)]) => source = Some(n.clone()),
+            ("initial-template", [Item::Value(t)]) => initial = Some(t.to_string()),
+            ("stylesheet-params", [Item::Map(m)]) => {
+                params = m.iter().map(|(p, v)| (p.to_string(), v.clone())).collect()
+            }
+            (k, _) => {
+                return Err(invalid_transform_options(
+                    format!(
+                        "option \"{}\" is not supported, or its value is not valid",
+                        k
+                    )
+                    .as_str(),
+                ))
+            }
+        }
+    }
+
+    let mut compiled = match (style, text) {
+        (Some(s), None) => {
+            let cached = stctxt
+                .stylesheets
+                .iter()
+                .find(|(n, _)| n.is_same(&s))
+                .map(|(_, c)| c.clone());
+            match cached {
+                Some(c) => c,
+                None => {
+                    let c = compile_nested(&s)?;
+                    stctxt.stylesheets.push((s, c.clone()));
+                    c
+                }
+            }
+        }
+        (None, Some(t)) => {
+            stctxt.check_secure("the stylesheet-text option of the transform function")?;
+            match &mut stctxt.parser {
+                Some(g) => compile_nested(&g(t.as_str())?)?,
+                None => {
+                    return Err(Error::new(
+                        ErrorKind::StaticAbsent,
+                        "function to parse document not supplied",
+                    ))
+                }
+            }
+        }
+        _ => {
+            return Err(invalid_transform_options(
+                "exactly one of stylesheet-node and stylesheet-text must be supplied",
+            ))
+        }
+    };
+    compiled.max_depth = ctxt.max_depth;
+    params
+        .into_iter()
+        .for_each(|(name, value)| compiled.global_parameter(name, value));
+    let mut inner = ContextBuilder::from(&compiled)
+        .depth(ctxt.deeper(stctxt)?)
+        .build();
+    if let Some(rd) = &ctxt.rd {
+        inner.result_document(rd.clone())
+    }
+    let output = match (initial, source) {
+        (Some(name), s) => {
+            // Global variables are evaluated with the document node of the source document as the context item.
+            inner.globals = inner
+                .globals
+                .for_item(s.as_ref().map(|n| Item::Node(n.owner_document())));
+            ContextBuilder::from(&inner)
+                .context(s.into_iter().map(Item::Node).collect())
+                .build()
+                .dispatch(
+                    stctxt,
+                    &Transform::Invoke(
+                        QualifiedName::new(None, None, name),
+                        ActualParameters::Named(vec![]),
+                    ),
+                )
+        }
+        (None, Some(s)) => {
+            inner.context(vec![Item::Node(s)], 0);
+            inner.evaluate(stctxt)
+        }
+        (None, None) => Err(invalid_transform_options(
+            "either source-node or initial-template must be supplied",
+        )),
+    }?;
+    let mut result = Map::new();
+    result.insert(Rc::new(Value::from("output")), output);
+    Ok(vec![Item::Map(Rc::new(result))])
+}
+
+fn invalid_transform_options(msg: &str) -> Error {
+    Error::new_with_code(
+        ErrorKind::TypeError,
+        format!("invalid options for transform: {}", msg),
+        Some(QualifiedName::new(None, None, "FOXT0002")),
+    )
+}
+
+// Set the output definition from an output:serialization-parameters element.
+// Each parameter is a child element with a value attribute.
+fn serialization_parameters<N: Node>(i: &Item<N>, od: &mut OutputDefinition) -> Result<(), Error> {
+    let invalid = |m: String| {
+        Error::new_with_code(
+            ErrorKind::TypeError,
+            m,
+            Some(QualifiedName::new(None, None, "SEPM0017")),
+        )
+    };
+    let e = match i {
+        Item::Node(n)
+            if n.is_element()
+                && n.name().get_nsuri_ref() == Some(SERIALIZATION_NS)
+                && n.name().get_localname() == "serialization-parameters" =>
+        {
+            n
+        }
+        _ => {
+            return Err(invalid(String::from(
+                "serialization parameters must be an output:serialization-parameters element",
+            )))
+        }
+    };
+    e.child_iter()
+        .filter(|c| c.is_element() && c.name().get_nsuri_ref() == Some(SERIALIZATION_NS))
+        .try_for_each(|c| {
+            let name = c.name().get_localname();
+            let value = c
+                .get_attribute(&QualifiedName::new(None, None, "value"))
+                .to_string();
+            let yes_or_no = || match value.trim() {
+                "yes" | "true" | "1" => Ok(true),
+                "no" | "false" | "0" => Ok(false),
+                v => Err(invalid(format!(
+                    "invalid value \"{}\" for serialization parameter \"{}\"",
+                    v, name
+                ))),
+            };
+            match name.as_str() {
+                "method" => od.set_method(Some(value.trim().to_string())),
+                "indent" => od.set_indent(yes_or_no()?),
+                "omit-xml-declaration" => od.set_omit_xml_declaration(yes_or_no()?),
+                _ => {}
+            }
+            Ok(())
+        })
+}
+
+/// XPath collection function.
+/// The argument is the URI of the collection. If it is absent, or the empty sequence, then the default collection is returned.
+/// A collection is resolved by the collection resolver of the static context, and the result is kept so that the same nodes are returned for the same URI.
+/// A relative URI is resolved against the base URL. If there is no resolver, then no collection is available (FODC0002).
+/// See [crate::externals::directory_collection] for a resolver that reads the files in a directory.
+pub fn collection<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    uri: &Option<Box<Transform<N>>>,
+) -> Result<Sequence<N>, Error> {
+    stctxt.check_secure("the collection function")?;
+    let uri = collection_uri(ctxt, stctxt, uri)?;
+    if let Some(seq) = stctxt.collections.get(&uri) {
+        return Ok(seq.clone());
+    }
+    let seq = match &mut stctxt.collection {
+        Some(r) => r(uri.as_deref())?,
+        None => return Err(no_collection(&uri)),
+    };
+    stctxt.collections.insert(uri, seq.clone());
+    Ok(seq)
+}
+
+/// XPath uri-collection function.
+/// The argument is the URI of the collection. If it is absent, or the empty sequence, then the URIs of the default collection are returned.
+/// The URIs are given by the URI collection resolver of the static context.
+/// If there is no resolver, then no collection is available (FODC0002).
+/// See [crate::externals::directory_uri_collection] for a resolver that lists the files in a directory.
+pub fn uri_collection<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    uri: &Option<Box<Transform<N>>>,
+) -> Result<Sequence<N>, Error> {
+    stctxt.check_secure("the uri-collection function")?;
+    let uri = collection_uri(ctxt, stctxt, uri)?;
+    let uris = match &mut stctxt.uri_collection {
+        Some(r) => r(uri.as_deref())?,
+        None => return Err(no_collection(&uri)),
+    };
+    Ok(uris
+        .into_iter()
+        .map(|u| Item::Value(Rc::new(Value::from(u))))
+        .collect())
+}
+
+// The URI of a collection. The empty sequence, or no argument, selects the default collection.
+// A relative URI is resolved against the base URL, if there is one.
+fn collection_uri<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    uri: &Option<Box<Transform<N>>>,
+) -> Result<Option<String>, Error> {
+    match uri {
+        Some(u) => {
+            let s = ctxt.dispatch(stctxt, u)?.to_string();
+            let s = s.trim();
+            if s.is_empty() {
+                return Ok(None);
+            }
+            match &ctxt.base_url {
+                Some(b) => b.join(s).map(|u| Some(u.to_string())).map_err(|_| {
+                    Error::new_with_code(
+                        ErrorKind::TypeError,
+                        format!("invalid collection URI \"{}\"", s),
+                        Some(QualifiedName::new(None, None, "FODC0004")),
+                    )
+                }),
+                None => Ok(Some(s.to_string())),
+            }
+        }
+        None => Ok(None),
+    }
+}
+
+fn no_collection(uri: &Option<String>) -> Error {
+    Error::new_with_code(
+        ErrorKind::DynamicAbsent,
+        uri.as_ref()
+            .map_or(String::from("there is no default collection"), |u| {
+                format!("collection \"{}\" is not available", u)
+            }),
+        Some(QualifiedName::new(None, None, "FODC0002")),
+    )
+}
+
 pub(crate) fn tr_error<N: Node>(
     _ctxt: &Context<N>,
     kind: &ErrorKind,
@@ -24,6 +24,8 @@ pub(crate) fn populate_key_values<
     stctxt: &mut StaticContext<N, F, G, H>,
     sd: N,
 ) -> Result<(), Error> {
+    // Global variables that are referenced by the use expressions have the source document as the global context item
+    let globals = ctxt.globals.for_item(Some(Item::Node(sd.owner_document())));
     // We have to visit N nodes to compute K keys.
     // In a typical scenario, N >> K so we want to perform a single pass over the nodes.
     for n in sd.owner_document().descend_iter() {
@@ -32,9 +34,10 @@ pub(crate) fn populate_key_values<
         for (name, d) in &ctxt.keys {
             for (m, u) in d {
                 if m.matches(ctxt, stctxt, &Item::Node(n.clone())) {
-                    let newctxt = ContextBuilder::from(&*ctxt)
+                    let mut newctxt = ContextBuilder::from(&*ctxt)
                         .context(vec![Item::Node(n.clone())])
                         .build();
+                    newctxt.globals = globals.clone();
                     let values = newctxt.dispatch(stctxt, u)?;
                     // Each item in values is a value for this key
                     values.iter().for_each(|v| {
@@ -5,6 +5,7 @@ use url::Url;
 
 use crate::item::{Item, Node, Sequence, SequenceTrait};
 use crate::transform::context::{Context, StaticContext};
+use crate::transform::navigate::{document_order, first_items};
 use crate::transform::Transform;
 use crate::value::{Operator, Value};
 use crate::xdmerror::{Error, ErrorKind};
@@ -26,7 +27,7 @@ pub(crate) fn tr_or<
     loop {
         match v.get(i) {
             Some(a) => {
-                if ctxt.dispatch(stctxt, a)?.to_bool() {
+                if first_items(ctxt, stctxt, a, 2)?.to_bool() {
                     b = true;
                     break;
                 }
@@ -55,7 +56,7 @@ pub(crate) fn tr_and<
     loop {
         match v.get(i) {
             Some(a) => {
-                if !ctxt.dispatch(stctxt, a)?.to_bool() {
+                if !first_items(ctxt, stctxt, a, 2)?.to_bool() {
                     b = false;
                     break;
                 }
@@ -133,7 +134,7 @@ pub(crate) fn value_comparison<
 }
 
 /// Each function in the supplied vector is evaluated, and the resulting sequences are combined into a single sequence.
-/// TODO: eliminate duplicates
+/// The result is in document order, with duplicates eliminated.
 pub(crate) fn union<
     N: Node,
     F: FnMut(&str) -> Result<(), Error>,
@@ -149,5 +150,5 @@ pub(crate) fn union<
         let mut c = ctxt.dispatch(stctxt, b)?;
         result.append(&mut c)
     }
-    Ok(result)
+    Ok(document_order(result))
 }
@@ -1,10 +1,12 @@
 //! Miscellaneous support functions.
 
-use crate::item::{Node, Sequence, SequenceTrait};
+use crate::item::{Item, Node, Sequence, SequenceTrait};
 use crate::qname::QualifiedName;
-use crate::transform::context::{Context, StaticContext};
+use crate::transform::burst::burst_document;
+use crate::transform::construct::normalize_content;
+use crate::transform::context::{Context, ContextBuilder, MessageInfo, StaticContext};
 use crate::transform::Transform;
-use crate::xdmerror::Error;
+use crate::xdmerror::{Error, Location};
 use crate::ErrorKind;
 use url::Url;
 
@@ -21,7 +23,8 @@ pub fn current<N: Node>(ctxt: &Context<N>) -> Result<Sequence<N>, Error> {
 }
 
 /// Emits a message from the stylesheet.
-/// The transform is evaluated to create the content of the message.
+/// The transform is evaluated to create the content of the message, which is then serialised.
+/// The message is passed to the message callback, and its details, including its location, to the message handler.
 pub(crate) fn message<
     N: Node,
     F: FnMut(&str) -> Result<(), Error>,
@@ -32,26 +35,238 @@ pub(crate) fn message<
     stctxt: &mut StaticContext<N, F, G, H>,
     body: &Transform<N>,
     _sel: &Option<Box<Transform<N>>>, // select expression, an alternative to body
-    _e: &Transform<N>,                // error code
+    e: &Transform<N>,                 // error code
     t: &Transform<N>,                 // terminate
+    loc: &Option<Location>,
 ) -> Result<Sequence<N>, Error> {
-    let msg = ctxt.dispatch(stctxt, body)?.to_string();
+    let msg = ctxt.dispatch(stctxt, body)?.to_xml();
+    let terminate = match ctxt.dispatch(stctxt, t)?.to_string().trim() {
+        "yes" | "true" | "1" => true,
+        "" | "no" | "false" | "0" => false,
+        v => {
+            return Err(Error::new_with_code(
+                ErrorKind::DynamicAbsent,
+                format!("invalid value \"{}\" for terminate", v),
+                Some(QualifiedName::new(None, None, "XTDE0030")),
+            ))
+        }
+    };
+    // An error code that is not a valid EQName is ignored, and the default code is used
+    let ec = ctxt.dispatch(stctxt, e)?.to_string();
+    let code = QualifiedName::try_from((ec.trim(), &ctxt.namespaces))
+        .ok()
+        .filter(|_| !ec.trim().is_empty())
+        .unwrap_or(QualifiedName::new(
+            Some(String::from("http://www.w3.org/2005/xqt-errors")),
+            None,
+            String::from("XTMM9000"),
+        ));
     if let Some(f) = &mut stctxt.message {
         f(msg.as_str())?
     }
-    match ctxt.dispatch(stctxt, t)?.to_string().trim() {
-        "yes" => {
-            // TODO: return error code
-            Err(Error {
-                kind: ErrorKind::Terminated,
-                message: msg,
-                code: Some(QualifiedName::new(
-                    Some(String::from("http://www.w3.org/2005/xqt-errors")),
-                    None,
-                    String::from("XTMM9000"),
-                )),
-            })
-        }
-        _ => Ok(vec![]),
+    if let Some(h) = &mut stctxt.message_handler {
+        h(&MessageInfo {
+            message: msg.clone(),
+            terminate,
+            code: Some(code.clone()),
+            location: loc.clone(),
+        })?
+    }
+    if terminate {
+        Err(Error {
+            kind: ErrorKind::Terminated,
+            message: msg,
+            code: Some(code),
+            location: loc.clone().map(Box::new),
+        })
+    } else {
+        Ok(Sequence::empty())
+    }
+}
+
+/// Creates a secondary result document.
+/// The content and the effective output definition are passed to the result document callback.
+pub(crate) fn result_document<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    href: &Transform<N>,
+    format: &Option<Box<Transform<N>>>,
+    params: &[(String, Transform<N>)],
+    body: &Transform<N>,
+) -> Result<Sequence<N>, Error> {
+    stctxt.check_secure("xsl:result-document")?;
+    let uri = ctxt.dispatch(stctxt, href)?.to_string();
+    let mut od = match format {
+        Some(f) => {
+            let name = ctxt.dispatch(stctxt, f)?.to_string();
+            let qn = QualifiedName::try_from((name.as_str(), &ctxt.namespaces))?;
+            ctxt.outputs.get(&qn).cloned().ok_or(Error::new_with_code(
+                ErrorKind::DynamicAbsent,
+                format!("no output definition named \"{}\"", qn.eqname()),
+                Some(QualifiedName::new(None, None, "XTDE1460")),
+            ))?
+        }
+        None => ctxt.od.clone(),
+    };
+    for (p, t) in params {
+        let v = ctxt.dispatch(stctxt, t)?.to_string();
+        match p.as_str() {
+            "indent" => od.set_indent(matches!(v.trim(), "yes" | "true" | "1")),
+            "method" => od.set_method(Some(v)),
+            "encoding" => od.set_encoding(Some(v)),
+            "normalization-form" => od.set_normalization_form(Some(v))?,
+            "byte-order-mark" => od.set_byte_order_mark(matches!(v.trim(), "yes" | "true" | "1")),
+            _ => {}
+        }
+    }
+    // Each secondary result is constructed in its own document, if a document factory has been set up
+    let rd = match &mut stctxt.document_factory {
+        Some(f) => Some(f()?),
+        None => None,
+    };
+    let content = match rd {
+        Some(r) => {
+            let content = ContextBuilder::from(ctxt)
+                .previous_context(ctxt.previous_context.clone())
+                .result_document(r.clone())
+                .build()
+                .dispatch(stctxt, body)?;
+            normalize_content(&r, content)?
+        }
+        None => {
+            let content = ctxt.dispatch(stctxt, body)?;
+            match &ctxt.rd {
+                Some(r) => normalize_content(r, content)?,
+                None => content,
+            }
+        }
+    };
+    if let Some(f) = &mut stctxt.result_document {
+        f(uri.as_str(), content, &od)?
+    }
+    Ok(Sequence::empty())
+}
+
+/// Loads a secondary source document, using the fetcher and parser of the static context,
+/// and evaluates the body with the document node as the context item.
+/// A document that is requested to be streamed is processed in burst mode, if the body allows it
+/// (see [burst_document]); otherwise it is fully materialized, and a message says so.
+pub(crate) fn source_document<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    href: &Transform<N>,
+    streamable: bool,
+    body: &Transform<N>,
+) -> Result<Sequence<N>, Error> {
+    stctxt.check_secure("xsl:source-document")?;
+    let uri = ctxt.dispatch(stctxt, href)?.to_string();
+    let url = Url::parse(uri.as_str()).map_err(|_| {
+        Error::new_with_code(
+            ErrorKind::TypeError,
+            format!("unable to parse URL \"{}\"", uri),
+            Some(QualifiedName::new(None, None, "FODC0005")),
+        )
+    })?;
+    let docdata = match &mut stctxt.fetcher {
+        Some(h) => h(&url)?,
+        None => {
+            return Err(Error::new(
+                ErrorKind::StaticAbsent,
+                "function to resolve URI not supplied",
+            ))
+        }
+    };
+    if streamable {
+        if let Some(result) = burst_document(ctxt, stctxt, uri.as_str(), docdata.as_str(), body)? {
+            return Ok(result);
+        }
+    }
+    let doc = match &mut stctxt.parser {
+        Some(g) => g(docdata.as_str())?,
+        None => {
+            return Err(Error::new(
+                ErrorKind::StaticAbsent,
+                "function to parse document not supplied",
+            ))
+        }
+    };
+    ContextBuilder::from(ctxt)
+        .context(vec![Item::Node(doc.clone())])
+        .position(1, 1)
+        .previous_context(Some(Item::Node(doc)))
+        .build()
+        .dispatch(stctxt, body)
+}
+
+/// Evaluates an extension instruction, using the handler that is registered in the static context.
+/// The attributes are evaluated and passed to the handler, along with the result document.
+/// If there is no handler for the instruction then the fallback is evaluated instead.
+pub(crate) fn extension_instruction<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    qn: &QualifiedName,
+    attributes: &[(QualifiedName, Transform<N>)],
+    fallback: &Option<Box<Transform<N>>>,
+) -> Result<Sequence<N>, Error> {
+    if !stctxt.extension_elements.contains_key(qn) {
+        return match fallback {
+            Some(f) => ctxt.dispatch(stctxt, f),
+            None => Err(Error::new_with_code(
+                ErrorKind::DynamicAbsent,
+                format!("extension instruction {} is not available", qn),
+                Some(QualifiedName::new(None, None, "XTDE1450")),
+            )),
+        };
+    }
+    let r = ctxt.rd.clone().ok_or(Error::new(
+        ErrorKind::Unknown,
+        String::from("context has no result document"),
+    ))?;
+    let values = attributes
+        .iter()
+        .map(|(a, t)| Ok((a.clone(), ctxt.dispatch(stctxt, t)?.to_string())))
+        .collect::<Result<Vec<_>, Error>>()?;
+    let content = (stctxt.extension_elements[qn])(&r, values.as_slice())?;
+    normalize_content(&r, content)
+}
+
+/// XPath trace function.
+/// The value is returned unchanged, and the label and a description of the value (see [SequenceTrait::describe]) are emitted as a message.
+pub(crate) fn trace<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    value: &Transform<N>,
+    label: &Transform<N>,
+) -> Result<Sequence<N>, Error> {
+    let v = ctxt.dispatch(stctxt, value)?;
+    let l = ctxt.dispatch(stctxt, label)?.to_string();
+    let msg = if l.is_empty() {
+        v.describe()
+    } else {
+        format!("{}: {}", l, v.describe())
+    };
+    if let Some(f) = &mut stctxt.message {
+        f(msg.as_str())?
     }
+    Ok(v)
 }
@@ -38,6 +38,7 @@ assert_eq!(sequence.to_string(), "2")
 */
 
 pub(crate) mod booleans;
+mod burst;
 pub mod callable;
 pub(crate) mod construct;
 pub mod context;
@@ -52,7 +53,9 @@ pub(crate) mod navigate;
 pub mod numbers;
 pub(crate) mod strings;
 pub mod template;
+pub(crate) mod types;
 pub(crate) mod variables;
+pub(crate) mod whitespace;
 
 #[allow(unused_imports)]
 use crate::item::Sequence;
@@ -60,11 +63,12 @@ use crate::item::{Item, Node, NodeType, SequenceTrait};
 use crate::qname::QualifiedName;
 use crate::transform::callable::ActualParameters;
 use crate::transform::context::{Context, ContextBuilder, StaticContext};
-use crate::transform::numbers::Numbering;
+use crate::transform::numbers::{NumberFormat, Numbering};
 use crate::value::Operator;
 #[allow(unused_imports)]
 use crate::value::Value;
-use crate::xdmerror::{Error, ErrorKind};
+use crate::xdmerror::{Error, ErrorKind, Location};
+use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::fmt;
 use std::fmt::{Debug, Formatter};
@@ -97,26 +101,59 @@ pub enum Transform<N: Node> {
     /// A literal, atomic value.
     Literal(Item<N>),
     /// A literal element. Consists of the element name and content.
-    LiteralElement(QualifiedName, Box<Transform<N>>),
+    /// The flag determines whether child elements inherit the namespaces of the new element.
+    LiteralElement(QualifiedName, Box<Transform<N>>, bool),
     /// A constructed element. Consists of the name and content.
-    Element(Box<Transform<N>>, Box<Transform<N>>),
+    /// The flag determines whether child elements inherit the namespaces of the new element.
+    /// The name is resolved using the optional namespace URI, otherwise using the namespace declarations that are in scope.
+    Element(
+        Box<Transform<N>>,
+        Box<Transform<N>>,
+        bool,
+        Option<Box<Transform<N>>>,
+        Vec<HashMap<String, String>>,
+    ),
     /// A literal text node. Consists of the value of the node. Second argument gives whether to disable output escaping.
+    /// Unless output escaping is disabled, markup characters are escaped when the node is created, regardless of the output method.
     LiteralText(Box<Transform<N>>, bool),
+    /// The content of a sequence constructor, normalised as for the content of a node.
+    /// This is the value of a variable or parameter that is defined by its content.
+    ComplexContent(Box<Transform<N>>),
+    /// The string value of a sequence, as for xsl:value-of. Consists of the selected items and the separator.
+    /// If the flag is true then only the first item is used, as in XSLT 1.0.
+    ValueOf(Box<Transform<N>>, Box<Transform<N>>, bool),
     /// A literal attribute. Consists of the attribute name and value.
     /// NB. The value may be produced by an Attribute Value Template, so must be dynamic.
     LiteralAttribute(QualifiedName, Box<Transform<N>>),
+    /// A constructed attribute. Consists of the name and value.
+    /// The name is resolved using the optional namespace URI, otherwise using the namespace declarations that are in scope.
+    Attribute(
+        Box<Transform<N>>,
+        Box<Transform<N>>,
+        Option<Box<Transform<N>>>,
+        Vec<HashMap<String, String>>,
+    ),
     /// A literal comment. Consists of the value.
     LiteralComment(Box<Transform<N>>),
     /// A literal processing instruction. Consists of the name and value.
     LiteralProcessingInstruction(Box<Transform<N>>, Box<Transform<N>>),
+    /// A literal namespace node. Consists of the prefix and the namespace URI.
+    /// An empty prefix is the default namespace.
+    LiteralNamespace(Box<Transform<N>>, Box<Transform<N>>),
     /// Produce a [Sequence]. Each element in the vector becomes one, or more, item in the sequence.
     SequenceItems(Vec<Transform<N>>),
 
     /// A shallow copy of an item. Consists of the selector of the item to be copied,
-    /// and the content of the target.
-    Copy(Box<Transform<N>>, Box<Transform<N>>),
+    /// the content of the target, and whether the in-scope namespaces of an element are copied.
+    Copy(Box<Transform<N>>, Box<Transform<N>>, bool),
     /// A deep copy of an item. That is, it copies an item including its descendants.
-    DeepCopy(Box<Transform<N>>),
+    /// The flag determines whether the in-scope namespaces of elements are copied.
+    DeepCopy(Box<Transform<N>>, bool),
+    /// A deep copy whose result may flow directly into the serialised output.
+    /// If the context only serialises its result then the selected nodes need not be copied,
+    /// except for an element that would acquire namespaces from its ancestors.
+    /// Otherwise, or when the result is kept by a variable, parameter or function, this is the same as [Transform::DeepCopy] with in-scope namespaces copied.
+    DirectCopy(Box<Transform<N>>),
 
     /// Logical OR. Each element of the outer vector is an operand.
     Or(Vec<Transform<N>>),
@@ -137,9 +174,16 @@ pub enum Transform<N: Node> {
     Range(Box<Transform<N>>, Box<Transform<N>>),
     /// Perform arithmetic operations
     Arithmetic(Vec<ArithmeticOperand<N>>),
+    /// Construct a map. Each element of the vector is the key and value of an entry.
+    MapConstructor(Vec<(Transform<N>, Transform<N>)>),
+    /// Look up entries in maps. Consists of the maps and the keys.
+    Lookup(Box<Transform<N>>, Box<Transform<N>>),
 
     /// A repeating transformation. Consists of variable declarations and the loop body.
     Loop(Vec<(String, Transform<N>)>, Box<Transform<N>>),
+    /// A quantified expression. Consists of whether every item must satisfy the test (otherwise some item must),
+    /// the variable declarations, and the test.
+    Quantified(bool, Vec<(String, Transform<N>)>, Box<Transform<N>>),
     /// A branching transformation. Consists of (test, body) clauses and an otherwise clause.
     Switch(Vec<(Transform<N>, Transform<N>)>, Box<Transform<N>>),
 
@@ -148,14 +192,30 @@ pub enum Transform<N: Node> {
         Option<Grouping<N>>,
         Box<Transform<N>>,
         Box<Transform<N>>,
-        Vec<(Order, Transform<N>)>,
+        Vec<(Order<N>, Transform<N>)>,
+    ),
+    /// Sort a sequence. Consists of the selector and the sort keys.
+    /// The result is the sorted sequence itself, so nodes retain their identity.
+    PerformSort(Box<Transform<N>>, Vec<(Order<N>, Transform<N>)>),
+    /// Evaluate a transformation for each selected item, carrying parameters from one iteration to the next.
+    /// Consists of the selector, the parameters with their initial values, the body, and the transformation to evaluate on completion.
+    Iterate(
+        Box<Transform<N>>,
+        Vec<(QualifiedName, Transform<N>)>,
+        Box<Transform<N>>,
+        Box<Transform<N>>,
     ),
+    /// Continue with the next iteration of the enclosing [Transform::Iterate].
+    /// Consists of new values for the iteration parameters.
+    NextIteration(Vec<(QualifiedName, Transform<N>)>),
+    /// Stop the enclosing [Transform::Iterate]. The result of the transformation is added to the result of the iteration.
+    Break(Box<Transform<N>>),
     /// Find a template that matches an item and evaluate its body with the item as the context.
     /// Consists of the selector for items to be matched, the mode, and sort keys.
     ApplyTemplates(
         Box<Transform<N>>,
         Option<QualifiedName>,
-        Vec<(Order, Transform<N>)>,
+        Vec<(Order<N>, Transform<N>)>,
     ),
     /// Find templates at the next import level and evaluate its body.
     ApplyImports,
@@ -174,6 +234,13 @@ pub enum Transform<N: Node> {
     /// Reference a variable.
     /// The result is the value stored for that variable in the current context and current scope.
     VariableReference(String),
+    /// Pass tunnel parameters to the templates that are invoked, directly or indirectly, by a transformation.
+    /// Consists of the names and values of the parameters, and the transformation.
+    Tunnel(Vec<(QualifiedName, Transform<N>)>, Box<Transform<N>>),
+    /// The value of a tunnel parameter of a template.
+    /// Consists of the name of the parameter and its default value, which is used when the parameter has not been passed.
+    /// A required parameter has no default value.
+    TunnelParameter(QualifiedName, Option<Box<Transform<N>>>),
 
     /// Set the value of an attribute. The context item must be an element-type node.
     /// Consists of the name of the attribute and its value. The [Sequence] produced will be cast to a [Value].
@@ -183,6 +250,22 @@ pub enum Transform<N: Node> {
     Position,
     Last,
     Count(Box<Transform<N>>),
+    /// XPath exists function.
+    Exists(Box<Transform<N>>),
+    /// XPath empty function.
+    IsEmpty(Box<Transform<N>>),
+    /// Remove duplicate values from a sequence. Consists of the sequence and an optional collation.
+    DistinctValues(Box<Transform<N>>, Option<Box<Transform<N>>>),
+    /// Find the positions of a value in a sequence. Consists of the sequence, the search value and an optional collation.
+    IndexOf(
+        Box<Transform<N>>,
+        Box<Transform<N>>,
+        Option<Box<Transform<N>>>,
+    ),
+    /// Insert items into a sequence. Consists of the target sequence, the position, and the items to insert.
+    InsertBefore(Box<Transform<N>>, Box<Transform<N>>, Box<Transform<N>>),
+    /// Remove an item from a sequence. Consists of the target sequence and the position of the item.
+    Remove(Box<Transform<N>>, Box<Transform<N>>),
     LocalName(Option<Box<Transform<N>>>),
     Name(Option<Box<Transform<N>>>),
     String(Box<Transform<N>>),
@@ -197,14 +280,42 @@ pub enum Transform<N: Node> {
     SubstringBefore(Box<Transform<N>>, Box<Transform<N>>),
     SubstringAfter(Box<Transform<N>>, Box<Transform<N>>),
     NormalizeSpace(Option<Box<Transform<N>>>),
+    /// Test whether a whitespace-separated token is present.
+    /// Consists of the input strings, the token and an optional collation.
+    ContainsToken(
+        Box<Transform<N>>,
+        Box<Transform<N>>,
+        Option<Box<Transform<N>>>,
+    ),
+    /// Split a string into tokens. Consists of the input string, the separator pattern and flags.
+    /// If there is no pattern then the string is split on whitespace.
+    Tokenize(
+        Box<Transform<N>>,
+        Option<Box<Transform<N>>>,
+        Option<Box<Transform<N>>>,
+    ),
+    /// Join the string values of a sequence. Consists of the sequence and the separator.
+    /// If there is no separator then the empty string is used.
+    StringJoin(Box<Transform<N>>, Option<Box<Transform<N>>>),
     Translate(Box<Transform<N>>, Box<Transform<N>>, Box<Transform<N>>),
     GenerateId(Option<Box<Transform<N>>>),
+    /// The root of the tree containing the given node, or the context item if there is no argument.
+    RootOf(Option<Box<Transform<N>>>),
+    /// The canonical path to the given node, or the context item if there is no argument.
+    Path(Option<Box<Transform<N>>>),
+    /// Whether the given element, or the context item, is nilled, i.e. has xsi:nil="true".
+    Nilled(Option<Box<Transform<N>>>),
     Boolean(Box<Transform<N>>),
     Not(Box<Transform<N>>),
     True,
     False,
     Number(Box<Transform<N>>),
     Sum(Box<Transform<N>>),
+    Avg(Box<Transform<N>>),
+    /// The greatest item in a sequence. Consists of the sequence and an optional collation for comparing strings.
+    Max(Box<Transform<N>>, Option<Box<Transform<N>>>),
+    /// The least item in a sequence. Consists of the sequence and an optional collation for comparing strings.
+    Min(Box<Transform<N>>, Option<Box<Transform<N>>>),
     Floor(Box<Transform<N>>),
     Ceiling(Box<Transform<N>>),
     Round(Box<Transform<N>>, Option<Box<Transform<N>>>),
@@ -237,12 +348,20 @@ pub enum Transform<N: Node> {
         Box<Transform<N>>,
         Option<Box<Transform<N>>>,
     ),
+    /// Parse a date in one of the formats used by IETF protocols, such as HTTP.
+    ParseIetfDate(Box<Transform<N>>),
     /// Convert a number to a string.
     /// This is one half of the functionality of xsl:number, as well as format-integer().
     /// See XSLT 12.4.
     /// First argument is the integer to be formatted.
     /// Second argument is the format specification.
     FormatInteger(Box<Transform<N>>, Box<Transform<N>>),
+    /// Convert a list of numbers to a string, with the formatting options of xsl:number.
+    /// See XSLT 12.4.
+    /// First argument is the integers to be formatted.
+    /// Second argument is the format specification.
+    /// Third argument is the grouping and ordinal options.
+    FormatIntegers(Box<Transform<N>>, Box<Transform<N>>, Box<NumberFormat<N>>),
     /// Generate a sequence of integers. This is one half of the functionality of xsl:number.
     /// First argument is the start-at specification.
     /// Second argument is the select expression.
@@ -264,20 +383,64 @@ pub enum Transform<N: Node> {
     AvailableSystemProperties,
     /// Read an external document
     Document(Box<Transform<N>>, Option<Box<Transform<N>>>),
+    /// Read a collection of documents. The argument is the URI of the collection; without it, the default collection.
+    Collection(Option<Box<Transform<N>>>),
+    /// The URIs of the resources in a collection. The argument is the URI of the collection; without it, the default collection.
+    UriCollection(Option<Box<Transform<N>>>),
+    /// Parse a string as an XML document
+    ParseXml(Box<Transform<N>>),
+    /// Serialize a sequence as a string. The optional argument is an output:serialization-parameters element.
+    Serialize(Box<Transform<N>>, Option<Box<Transform<N>>>),
+    /// Compile and evaluate a stylesheet, i.e. the XPath transform function. The argument is the map of options.
+    NestedTransform(Box<Transform<N>>),
 
     /// Invoke a callable component. Consists of a name, an actual argument list.
     Invoke(QualifiedName, ActualParameters<N>),
 
-    /// Emit a message. Consists of a select expression, a terminate attribute, an error-code, and a body.
+    /// Emit a message. Consists of a body, a select expression, an error-code, a terminate attribute,
+    /// and the location of the instruction in the stylesheet.
     Message(
         Box<Transform<N>>,
         Option<Box<Transform<N>>>,
         Box<Transform<N>>,
         Box<Transform<N>>,
+        Option<Location>,
     ),
 
+    /// Create a secondary result document. Consists of the URI of the document, the name of its output definition,
+    /// serialization parameters that override the output definition, and the content of the document.
+    ResultDocument(
+        Box<Transform<N>>,
+        Option<Box<Transform<N>>>,
+        Vec<(String, Transform<N>)>,
+        Box<Transform<N>>,
+    ),
+
+    /// Load a secondary source document and evaluate a sequence constructor with the document as the context item.
+    /// Consists of the URI of the document, whether streaming was requested, and the sequence constructor.
+    SourceDocument(Box<Transform<N>>, bool, Box<Transform<N>>),
+
+    /// An extension instruction. Consists of the name of the element, its attributes as attribute value templates,
+    /// and the xsl:fallback sequence constructor, if any.
+    ExtensionInstruction(
+        QualifiedName,
+        Vec<(QualifiedName, Transform<N>)>,
+        Option<Box<Transform<N>>>,
+    ),
+
+    /// Emit a diagnostic and return a value unchanged. Consists of the value and a label.
+    Trace(Box<Transform<N>>, Box<Transform<N>>),
+
+    /// Does a sequence match a sequence type? Consists of the sequence and the type.
+    InstanceOf(Box<Transform<N>>, SequenceType),
+    /// Cast an atomic value to an atomic type. Consists of the value, the name of the type,
+    /// and whether the empty sequence is allowed.
+    Cast(Box<Transform<N>>, QualifiedName, bool),
+    /// Can an atomic value be cast to an atomic type? The arguments are the same as for Cast.
+    Castable(Box<Transform<N>>, QualifiedName, bool),
+
     /// For things that are not yet implemented, such as:
-    /// Union, IntersectExcept, InstanceOf, Treat, Castable, Cast, Arrow, Unary, SimpleMap, Is, Before, After.
+    /// Union, IntersectExcept, Treat, Arrow, Unary, SimpleMap, Is, Before, After.
     NotImplemented(String),
 
     /// Error condition.
@@ -303,16 +466,23 @@ impl<N: Node> Debug for Transform<N> {
             Transform::Filter(_) => write!(f, "Filter"),
             Transform::Empty => write!(f, "Empty"),
             Transform::Literal(_) => write!(f, "literal value"),
-            Transform::LiteralElement(qn, _) => write!(f, "literal element named \"{}\"", qn),
-            Transform::Element(_, _) => write!(f, "constructed element"),
+            Transform::LiteralElement(qn, _, _) => {
+                write!(f, "literal element named \"{}\"", qn)
+            }
+            Transform::Element(_, _, _, _, _) => write!(f, "constructed element"),
             Transform::LiteralText(_, b) => write!(f, "literal text (disable escaping {})", b),
+            Transform::ComplexContent(_) => write!(f, "complex content"),
+            Transform::ValueOf(_, _, b) => write!(f, "value of (first item only {})", b),
             Transform::LiteralAttribute(qn, _) => write!(f, "literal attribute named \"{}\"", qn),
+            Transform::Attribute(_, _, _, _) => write!(f, "constructed attribute"),
             Transform::LiteralComment(_) => write!(f, "literal comment"),
             Transform::LiteralProcessingInstruction(_, _) => {
                 write!(f, "literal processing-instruction")
             }
-            Transform::Copy(_, _) => write!(f, "shallow copy"),
-            Transform::DeepCopy(_) => write!(f, "deep copy"),
+            Transform::LiteralNamespace(_, _) => write!(f, "literal namespace"),
+            Transform::Copy(_, _, _) => write!(f, "shallow copy"),
+            Transform::DeepCopy(_, _) => write!(f, "deep copy"),
+            Transform::DirectCopy(_) => write!(f, "direct copy"),
             Transform::GeneralComparison(o, v, u) => {
                 write!(f, "general comparison {} of {:?} and {:?}", o, v, u)
             }
@@ -322,11 +492,18 @@ impl<N: Node> Debug for Transform<N> {
             Transform::Concat(o) => write!(f, "Concatenate {} operands", o.len()),
             Transform::Range(_, _) => write!(f, "range"),
             Transform::Arithmetic(o) => write!(f, "Arithmetic {} operands", o.len()),
+            Transform::MapConstructor(e) => write!(f, "map of {} entries", e.len()),
+            Transform::Lookup(m, k) => write!(f, "lookup {:?} in {:?}", k, m),
             Transform::And(o) => write!(f, "AND {} operands", o.len()),
             Transform::Or(o) => write!(f, "OR {} operands", o.len()),
             Transform::Loop(_, _) => write!(f, "loop"),
+            Transform::Quantified(e, _, _) => write!(f, "{}", if *e { "every" } else { "some" }),
             Transform::Switch(c, _) => write!(f, "switch {} clauses", c.len()),
             Transform::ForEach(_g, _, _, o) => write!(f, "for-each ({} sort keys)", o.len()),
+            Transform::PerformSort(_, o) => write!(f, "perform-sort ({} sort keys)", o.len()),
+            Transform::Iterate(_, p, _, _) => write!(f, "iterate ({} parameters)", p.len()),
+            Transform::NextIteration(p) => write!(f, "next-iteration ({} parameters)", p.len()),
+            Transform::Break(_) => write!(f, "break"),
             Transform::Union(v) => write!(f, "union of {} operands", v.len()),
             Transform::ApplyTemplates(_, m, o) => {
                 write!(f, "Apply templates (mode {:?}, {} sort keys)", m, o.len())
@@ -335,11 +512,21 @@ impl<N: Node> Debug for Transform<N> {
             Transform::ApplyImports => write!(f, "Apply imports"),
             Transform::NextMatch => write!(f, "next-match"),
             Transform::VariableDeclaration(n, _, _) => write!(f, "declare variable \"{}\"", n),
+            Transform::Tunnel(p, t) => write!(f, "tunnel {} parameters to {:?}", p.len(), t),
+            Transform::TunnelParameter(n, _) => write!(f, "tunnel parameter \"{}\"", n),
             Transform::VariableReference(n) => write!(f, "reference variable \"{}\"", n),
             Transform::SetAttribute(n, _) => write!(f, "set attribute named \"{}\"", n),
             Transform::Position => write!(f, "position"),
             Transform::Last => write!(f, "last"),
             Transform::Count(_s) => write!(f, "count()"),
+            Transform::Exists(_s) => write!(f, "exists()"),
+            Transform::IsEmpty(_s) => write!(f, "empty()"),
+            Transform::DistinctValues(s, _) => write!(f, "distinct-values({:?})", s),
+            Transform::IndexOf(s, t, _) => write!(f, "index-of({:?}, {:?})", s, t),
+            Transform::InsertBefore(t, p, i) => {
+                write!(f, "insert-before({:?}, {:?}, {:?})", t, p, i)
+            }
+            Transform::Remove(t, p) => write!(f, "remove({:?}, {:?})", t, p),
             Transform::Name(_n) => write!(f, "name()"),
             Transform::LocalName(_n) => write!(f, "local-name()"),
             Transform::String(s) => write!(f, "string({:?})", s),
@@ -350,14 +537,23 @@ impl<N: Node> Debug for Transform<N> {
             Transform::SubstringBefore(s, t) => write!(f, "substring-before({:?}, {:?})", s, t),
             Transform::SubstringAfter(s, t) => write!(f, "substring-after({:?}, {:?})", s, t),
             Transform::NormalizeSpace(_s) => write!(f, "normalize-space()"),
+            Transform::ContainsToken(s, t, _) => write!(f, "contains-token({:?}, {:?})", s, t),
+            Transform::Tokenize(s, p, _) => write!(f, "tokenize({:?}, {:?})", s, p),
+            Transform::StringJoin(s, t) => write!(f, "string-join({:?}, {:?})", s, t),
             Transform::Translate(s, t, u) => write!(f, "translate({:?}, {:?}, {:?})", s, t, u),
             Transform::GenerateId(_) => write!(f, "generate-id()"),
+            Transform::RootOf(_) => write!(f, "root()"),
+            Transform::Path(_) => write!(f, "path()"),
+            Transform::Nilled(_) => write!(f, "nilled()"),
             Transform::Boolean(b) => write!(f, "boolean({:?})", b),
             Transform::Not(b) => write!(f, "not({:?})", b),
             Transform::True => write!(f, "true"),
             Transform::False => write!(f, "false"),
             Transform::Number(n) => write!(f, "number({:?})", n),
             Transform::Sum(n) => write!(f, "sum({:?})", n),
+            Transform::Avg(n) => write!(f, "avg({:?})", n),
+            Transform::Max(n, _) => write!(f, "max({:?})", n),
+            Transform::Min(n, _) => write!(f, "min({:?})", n),
             Transform::Floor(n) => write!(f, "floor({:?})", n),
             Transform::Ceiling(n) => write!(f, "ceiling({:?})", n),
             Transform::Round(n, _p) => write!(f, "round({:?},...)", n),
@@ -370,7 +566,9 @@ impl<N: Node> Debug for Transform<N> {
             Transform::FormatDate(p, q, _, _, _) => write!(f, "format-date({:?}, {:?}, ...)", p, q),
             Transform::FormatTime(p, q, _, _, _) => write!(f, "format-time({:?}, {:?}, ...)", p, q),
             Transform::FormatNumber(v, p, _) => write!(f, "format-number({:?}, {:?})", v, p),
+            Transform::ParseIetfDate(s) => write!(f, "parse-ietf-date({:?})", s),
             Transform::FormatInteger(i, s) => write!(f, "format-integer({:?}, {:?})", i, s),
+            Transform::FormatIntegers(i, s, _) => write!(f, "format-integers({:?}, {:?})", i, s),
             Transform::GenerateIntegers(_start_at, _select, _n) => write!(f, "generate-integers"),
             Transform::CurrentGroup => write!(f, "current-group"),
             Transform::CurrentGroupingKey => write!(f, "current-grouping-key"),
@@ -378,8 +576,22 @@ impl<N: Node> Debug for Transform<N> {
             Transform::SystemProperty(p) => write!(f, "system-properties({:?})", p),
             Transform::AvailableSystemProperties => write!(f, "available-system-properties"),
             Transform::Document(uris, _) => write!(f, "document({:?})", uris),
+            Transform::Collection(uri) => write!(f, "collection({:?})", uri),
+            Transform::UriCollection(uri) => write!(f, "uri-collection({:?})", uri),
+            Transform::ParseXml(s) => write!(f, "parse-xml({:?})", s),
+            Transform::Serialize(s, _) => write!(f, "serialize({:?})", s),
+            Transform::NestedTransform(_) => write!(f, "transform"),
             Transform::Invoke(qn, _a) => write!(f, "invoke \"{}\"", qn),
-            Transform::Message(_, _, _, _) => write!(f, "message"),
+            Transform::Message(_, _, _, _, _) => write!(f, "message"),
+            Transform::ResultDocument(_, _, _, _) => write!(f, "result-document"),
+            Transform::SourceDocument(h, _, _) => write!(f, "source-document({:?})", h),
+            Transform::ExtensionInstruction(qn, _, _) => {
+                write!(f, "extension-instruction {}", qn)
+            }
+            Transform::Trace(v, l) => write!(f, "trace({:?}, {:?})", v, l),
+            Transform::InstanceOf(s, t) => write!(f, "{:?} instance of {}", s, t),
+            Transform::Cast(s, t, _) => write!(f, "{:?} cast as {}", s, t),
+            Transform::Castable(s, t, _) => write!(f, "{:?} castable as {}", s, t),
             Transform::NotImplemented(s) => write!(f, "Not implemented: \"{}\"", s),
             Transform::Error(k, s) => write!(f, "Error: {} \"{}\"", k, s),
         }
@@ -387,10 +599,39 @@ impl<N: Node> Debug for Transform<N> {
 }
 
 /// The sort order
-#[derive(Clone, PartialEq, Debug)]
-pub enum Order {
+#[derive(Clone, Debug)]
+pub enum Order<N: Node> {
     Ascending,
     Descending,
+    /// The order is given by an attribute value template, which must evaluate to "ascending" or "descending".
+    Computed(Box<Transform<N>>),
+}
+
+impl<N: Node> Order<N> {
+    /// Whether the order is descending. A computed order is evaluated in the given context.
+    pub(crate) fn is_descending<
+        F: FnMut(&str) -> Result<(), Error>,
+        G: FnMut(&str) -> Result<N, Error>,
+        H: FnMut(&Url) -> Result<String, Error>,
+    >(
+        &self,
+        ctxt: &Context<N>,
+        stctxt: &mut StaticContext<N, F, G, H>,
+    ) -> Result<bool, Error> {
+        match self {
+            Order::Ascending => Ok(false),
+            Order::Descending => Ok(true),
+            Order::Computed(t) => match ctxt.dispatch(stctxt, t)?.to_string().trim() {
+                "ascending" => Ok(false),
+                "descending" => Ok(true),
+                o => Err(Error::new_with_code(
+                    ErrorKind::DynamicAbsent,
+                    format!("invalid value \"{}\" for order", o),
+                    Some(QualifiedName::new(None, None, "XTDE0030")),
+                )),
+            },
+        }
+    }
 }
 
 /// Performing sorting of a [Sequence] using the given sort keys.
@@ -401,26 +642,30 @@ pub(crate) fn do_sort<
     H: FnMut(&Url) -> Result<String, Error>,
 >(
     seq: &mut Sequence<N>,
-    o: &Vec<(Order, Transform<N>)>,
+    o: &Vec<(Order<N>, Transform<N>)>,
     ctxt: &Context<N>,
     stctxt: &mut StaticContext<N, F, G, H>,
 ) -> Result<(), Error> {
     // Optionally sort the select sequence
     // TODO: multiple sort keys
     if !o.is_empty() {
-        seq.sort_by_cached_key(|k| {
-            // TODO: Don't panic
-            let key_seq = ContextBuilder::from(ctxt)
-                .context(vec![k.clone()])
-                .build()
-                .dispatch(stctxt, &o[0].1)
-                .expect("unable to determine key value");
-            // Assume string data type for now
-            // TODO: support number data type
-            // TODO: support all data types
-            key_seq.to_string()
-        });
-        if o[0].0 == Order::Descending {
+        // Assume string data type for now
+        // TODO: support number data type
+        // TODO: support all data types
+        let mut keyed = seq
+            .drain(..)
+            .map(|k| {
+                let key_seq = ContextBuilder::from(ctxt)
+                    .context(vec![k.clone()])
+                    .build()
+                    .dispatch(stctxt, &o[0].1)?;
+                Ok((key_seq.to_string(), k))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let collation = ctxt.resolve_collation(None)?;
+        keyed.sort_by(|(a, _), (b, _)| collation.compare(a, b));
+        seq.extend(keyed.into_iter().map(|(_, k)| k));
+        if o[0].0.is_descending(ctxt, stctxt)? {
             seq.reverse();
         }
     }
@@ -432,7 +677,8 @@ pub(crate) fn do_sort<
 /// A None value for the option means that the collection is not to be grouped.
 #[derive(Clone, Debug)]
 pub enum Grouping<N: Node> {
-    By(Vec<Transform<N>>),
+    /// Group by key value. Consists of the key expressions and an optional collation for comparing string keys.
+    By(Vec<Transform<N>>, Option<Box<Transform<N>>>),
     StartingWith(Vec<Transform<N>>),
     EndingWith(Vec<Transform<N>>),
     Adjacent(Vec<Transform<N>>),
@@ -441,7 +687,7 @@ pub enum Grouping<N: Node> {
 impl<N: Node> Grouping<N> {
     fn to_string(&self) -> String {
         match self {
-            Grouping::By(_) => "group-by".to_string(),
+            Grouping::By(_, _) => "group-by".to_string(),
             Grouping::Adjacent(_) => "group-adjacent".to_string(),
             Grouping::StartingWith(_) => "group-starting-with".to_string(),
             Grouping::EndingWith(_) => "group-ending-with".to_string(),
@@ -474,33 +720,43 @@ impl NodeMatch {
     pub fn matches<N: Node>(&self, n: &N) -> bool {
         match &self.nodetest {
             NodeTest::Name(t) => {
-                match n.node_type() {
-                    NodeType::Element | NodeType::Attribute => {
-                        // TODO: namespaces
-                        match &t.name {
-                            Some(a) => match a {
-                                WildcardOrName::Wildcard => true,
-                                WildcardOrName::Name(s) => *s == n.name().get_localname(),
-                            },
-                            None => false,
-                        }
+                // A name test only matches nodes of the principal node kind of the axis
+                if n.node_type() == self.axis.principal_node_kind() {
+                    // TODO: namespaces
+                    match &t.name {
+                        Some(a) => match a {
+                            WildcardOrName::Wildcard => true,
+                            WildcardOrName::Name(s) => *s == n.name().get_localname(),
+                        },
+                        None => false,
                     }
-                    _ => false,
+                } else {
+                    false
                 }
             }
             NodeTest::Kind(k) => {
                 match k {
                     KindTest::Document => matches!(n.node_type(), NodeType::Document),
                     KindTest::Element => matches!(n.node_type(), NodeType::Element),
-                    KindTest::PI => matches!(n.node_type(), NodeType::ProcessingInstruction),
+                    KindTest::Attribute => matches!(n.node_type(), NodeType::Attribute),
+                    KindTest::PI(None) => {
+                        matches!(n.node_type(), NodeType::ProcessingInstruction)
+                    }
+                    KindTest::PI(Some(t)) => {
+                        matches!(n.node_type(), NodeType::ProcessingInstruction)
+                            && n.name().get_localname() == *t
+                    }
                     KindTest::Comment => matches!(n.node_type(), NodeType::Comment),
                     KindTest::Text => matches!(n.node_type(), NodeType::Text),
-                    //Note: This one is matching not NodeType::Document
-                    KindTest::Any => !matches!(n.node_type(), NodeType::Document),
-                    KindTest::Attribute
-                    | KindTest::SchemaElement
-                    | KindTest::SchemaAttribute
-                    | KindTest::Namespace => false, // TODO: not yet implemented
+                    // The Document is only matched on the self and descendant-or-self axes.
+                    // This stops a pattern such as "node()" from matching the Document.
+                    KindTest::Any => {
+                        !matches!(n.node_type(), NodeType::Document)
+                            || matches!(self.axis, Axis::SelfAxis | Axis::DescendantOrSelf)
+                    }
+                    KindTest::SchemaElement | KindTest::SchemaAttribute | KindTest::Namespace => {
+                        false
+                    } // TODO: not yet implemented
                 }
             }
         }
@@ -601,14 +857,15 @@ impl TryFrom<&str> for NodeTest {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub enum KindTest {
     Document,
     Element,
     Attribute,
     SchemaElement,
     SchemaAttribute,
-    PI,
+    /// A processing instruction, optionally with the given target
+    PI(Option<String>),
     Comment,
     Text,
     Namespace,
@@ -623,7 +880,8 @@ impl fmt::Display for KindTest {
             KindTest::Attribute => write!(f, "attribute"),
             KindTest::SchemaElement => write!(f, "schema element"),
             KindTest::SchemaAttribute => write!(f, "schema attribute"),
-            KindTest::PI => write!(f, "processing instruction"),
+            KindTest::PI(None) => write!(f, "processing instruction"),
+            KindTest::PI(Some(t)) => write!(f, "processing instruction \"{}\"", t),
             KindTest::Comment => write!(f, "comment"),
             KindTest::Text => write!(f, "text"),
             KindTest::Namespace => write!(f, "namespace"),
@@ -646,8 +904,11 @@ impl KindTest {
                     (KindTest::Attribute, _) => false,
                     (KindTest::SchemaElement, _) => false, // not supported
                     (KindTest::SchemaAttribute, _) => false, // not supported
-                    (KindTest::PI, NodeType::ProcessingInstruction) => true,
-                    (KindTest::PI, _) => false,
+                    (KindTest::PI(None), NodeType::ProcessingInstruction) => true,
+                    (KindTest::PI(Some(t)), NodeType::ProcessingInstruction) => {
+                        n.name().get_localname() == *t
+                    }
+                    (KindTest::PI(_), _) => false,
                     (KindTest::Comment, NodeType::Comment) => true,
                     (KindTest::Comment, _) => false,
                     (KindTest::Text, NodeType::Text) => true,
@@ -666,7 +927,7 @@ impl KindTest {
             KindTest::Attribute => "AttributeTest",
             KindTest::SchemaElement => "SchemaElementTest",
             KindTest::SchemaAttribute => "SchemaAttributeTest",
-            KindTest::PI => "PITest",
+            KindTest::PI(_) => "PITest",
             KindTest::Comment => "CommentTest",
             KindTest::Text => "TextTest",
             KindTest::Namespace => "NamespaceNodeTest",
@@ -675,6 +936,101 @@ impl KindTest {
     }
 }
 
+/// A sequence type, such as xs:integer+ or element()*. See XPath 2.5.4.
+#[derive(Clone, Debug)]
+pub enum SequenceType {
+    /// empty-sequence()
+    Empty,
+    Items(ItemType, Occurrence),
+}
+
+impl SequenceType {
+    /// Does a sequence match the sequence type?
+    pub fn matches<N: Node>(&self, s: &Sequence<N>) -> bool {
+        match self {
+            SequenceType::Empty => s.is_empty(),
+            SequenceType::Items(t, o) => o.allows(s.len()) && s.iter().all(|i| t.matches(i)),
+        }
+    }
+}
+
+impl fmt::Display for SequenceType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SequenceType::Empty => write!(f, "empty-sequence()"),
+            SequenceType::Items(t, o) => write!(f, "{}{}", t, o),
+        }
+    }
+}
+
+/// The type of an item in a sequence type.
+#[derive(Clone, Debug)]
+pub enum ItemType {
+    /// item()
+    Item,
+    /// An atomic type, such as xs:integer
+    Atomic(QualifiedName),
+    /// A node type, such as element()
+    Kind(KindTest),
+}
+
+impl ItemType {
+    /// Does an item match the item type?
+    pub fn matches<N: Node>(&self, i: &Item<N>) -> bool {
+        match (self, i) {
+            (ItemType::Item, _) => true,
+            (ItemType::Atomic(t), Item::Value(v)) => v.instance_of(t),
+            (ItemType::Atomic(_), _) => false,
+            (ItemType::Kind(k), _) => k.matches(i),
+        }
+    }
+}
+
+impl fmt::Display for ItemType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ItemType::Item => write!(f, "item()"),
+            ItemType::Atomic(t) => write!(f, "{}", t),
+            ItemType::Kind(k) => write!(f, "{}()", k),
+        }
+    }
+}
+
+/// The number of items allowed by a sequence type.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Occurrence {
+    One,
+    /// ?
+    ZeroOrOne,
+    /// *
+    ZeroOrMore,
+    /// +
+    OneOrMore,
+}
+
+impl Occurrence {
+    /// Is a sequence of this length allowed?
+    pub fn allows(&self, n: usize) -> bool {
+        match self {
+            Occurrence::One => n == 1,
+            Occurrence::ZeroOrOne => n <= 1,
+            Occurrence::ZeroOrMore => true,
+            Occurrence::OneOrMore => n >= 1,
+        }
+    }
+}
+
+impl fmt::Display for Occurrence {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Occurrence::One => Ok(()),
+            Occurrence::ZeroOrOne => write!(f, "?"),
+            Occurrence::ZeroOrMore => write!(f, "*"),
+            Occurrence::OneOrMore => write!(f, "+"),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct NameTest {
     pub ns: Option<WildcardOrName>,
@@ -790,6 +1146,17 @@ pub enum Axis {
     Unknown,
 }
 
+impl Axis {
+    /// The principal node kind of the axis. A name test only matches nodes of this kind.
+    pub fn principal_node_kind(&self) -> NodeType {
+        match self {
+            Axis::Attribute | Axis::SelfAttribute => NodeType::Attribute,
+            Axis::Namespace | Axis::SelfNamespace => NodeType::Namespace,
+            _ => NodeType::Element,
+        }
+    }
+}
+
 impl From<&str> for Axis {
     fn from(s: &str) -> Self {
         match s {
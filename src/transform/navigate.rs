@@ -1,10 +1,16 @@
 //! Navigation routines
 
-use crate::item::{Node, NodeType, Sequence, SequenceTrait};
+use crate::item::{not_singleton, Node, NodeType, Sequence, SequenceTrait};
+use crate::qname::QualifiedName;
 use crate::transform::context::{Context, ContextBuilder, StaticContext};
 use crate::transform::{Axis, NodeMatch, Transform};
+use crate::value::Value;
 use crate::xdmerror::{Error, ErrorKind};
 use crate::Item;
+use std::cmp::Ordering;
+use std::iter::once;
+use std::ops::ControlFlow;
+use std::rc::Rc;
 use url::Url;
 
 /// The root node of the context item.
@@ -23,7 +29,7 @@ pub(crate) fn root<N: Node>(ctxt: &Context<N>) -> Result<Sequence<N>, Error> {
                 _ => n
                     .ancestor_iter()
                     .last()
-                    .map_or(Ok(vec![]), |m| Ok(vec![Item::Node(m)])),
+                    .map_or(Ok(Sequence::empty()), |m| Ok(vec![Item::Node(m)])),
             },
             _ => Err(Error::new(
                 ErrorKind::ContextNotNode,
@@ -33,12 +39,195 @@ pub(crate) fn root<N: Node>(ctxt: &Context<N>) -> Result<Sequence<N>, Error> {
     }
 }
 
+/// XPath root function.
+/// The root of the tree containing the given node, or the context item.
+pub(crate) fn root_of<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    s: &Option<Box<Transform<N>>>,
+) -> Result<Sequence<N>, Error> {
+    let i = match s {
+        None => ctxt.cur.get(ctxt.i).cloned().ok_or(Error::new(
+            ErrorKind::DynamicAbsent,
+            String::from("no context"),
+        ))?,
+        Some(t) => {
+            let seq = ctxt.dispatch(stctxt, t)?;
+            match seq.len() {
+                0 => return Ok(Sequence::empty()),
+                1 => seq[0].clone(),
+                _ => return Err(not_singleton(&seq)),
+            }
+        }
+    };
+    match i {
+        Item::Node(n) => Ok(vec![Item::Node(n.ancestor_iter().last().unwrap_or(n))]),
+        _ => Err(Error::new(
+            ErrorKind::ContextNotNode,
+            String::from("not a node"),
+        )),
+    }
+}
+
+/// XPath path function.
+/// The canonical path to the given node, or the context item, using "Q{uri}name[position]" steps.
+pub(crate) fn path<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    s: &Option<Box<Transform<N>>>,
+) -> Result<Sequence<N>, Error> {
+    let i = match s {
+        None => ctxt.cur.get(ctxt.i).cloned().ok_or(Error::new(
+            ErrorKind::DynamicAbsent,
+            String::from("no context"),
+        ))?,
+        Some(t) => {
+            let seq = ctxt.dispatch(stctxt, t)?;
+            match seq.len() {
+                0 => return Ok(Sequence::empty()),
+                1 => seq[0].clone(),
+                _ => return Err(not_singleton(&seq)),
+            }
+        }
+    };
+    match i {
+        Item::Node(n) => {
+            let mut nodes: Vec<N> = Some(n.clone())
+                .into_iter()
+                .chain(n.ancestor_iter())
+                .collect();
+            nodes.reverse();
+            let steps: Vec<String> = nodes
+                .iter()
+                .filter(|m| m.node_type() != NodeType::Document)
+                .map(path_step)
+                .collect();
+            let result = match (nodes[0].node_type(), steps.is_empty()) {
+                (NodeType::Document, true) => String::from("/"),
+                (NodeType::Document, false) => format!("/{}", steps.join("/")),
+                // The tree is not rooted at a document node
+                (_, _) => {
+                    let mut r = format!("Q{{{}}}root()", FNNS);
+                    steps.iter().skip(1).for_each(|t| {
+                        r.push('/');
+                        r.push_str(t)
+                    });
+                    r
+                }
+            };
+            Ok(vec![Item::Value(Rc::new(Value::from(result)))])
+        }
+        _ => Err(Error::new(
+            ErrorKind::ContextNotNode,
+            String::from("not a node"),
+        )),
+    }
+}
+
+/// XPath nilled function.
+/// Whether the given element, or the context item, has an xsi:nil attribute with the value true.
+/// Returns the empty sequence for nodes other than elements.
+pub(crate) fn nilled<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    s: &Option<Box<Transform<N>>>,
+) -> Result<Sequence<N>, Error> {
+    let i = match s {
+        None => ctxt.cur.get(ctxt.i).cloned().ok_or(Error::new(
+            ErrorKind::DynamicAbsent,
+            String::from("no context"),
+        ))?,
+        Some(t) => {
+            let seq = ctxt.dispatch(stctxt, t)?;
+            match seq.len() {
+                0 => return Ok(Sequence::empty()),
+                1 => seq[0].clone(),
+                _ => return Err(not_singleton(&seq)),
+            }
+        }
+    };
+    match i {
+        Item::Node(n) if n.node_type() == NodeType::Element => {
+            let nil = n.get_attribute(&QualifiedName::new(Some(String::from(XSINS)), None, "nil"));
+            Ok(vec![Item::Value(Rc::new(Value::from(matches!(
+                nil.to_string().trim(),
+                "true" | "1"
+            ))))])
+        }
+        Item::Node(_) => Ok(Sequence::empty()),
+        _ => Err(Error::new(
+            ErrorKind::ContextNotNode,
+            String::from("not a node"),
+        )),
+    }
+}
+
+const FNNS: &str = "http://www.w3.org/2005/xpath-functions";
+const XSINS: &str = "http://www.w3.org/2001/XMLSchema-instance";
+
+// A step in the canonical path of a node. See [path].
+fn path_step<N: Node>(n: &N) -> String {
+    // The position of the node amongst its siblings of the same kind and name
+    let position = || {
+        n.parent().map_or(1, |p| {
+            p.child_iter()
+                .filter(|c| c.node_type() == n.node_type() && c.name() == n.name())
+                .position(|c| c.is_same(n))
+                .map_or(1, |i| i + 1)
+        })
+    };
+    let name = n.name();
+    match n.node_type() {
+        NodeType::Element => format!(
+            "Q{{{}}}{}[{}]",
+            name.get_nsuri().unwrap_or_default(),
+            name.get_localname(),
+            position()
+        ),
+        NodeType::Attribute => match name.get_nsuri() {
+            Some(u) => format!("@Q{{{}}}{}", u, name.get_localname()),
+            None => format!("@{}", name.get_localname()),
+        },
+        NodeType::Text => format!("text()[{}]", position()),
+        NodeType::Comment => format!("comment()[{}]", position()),
+        NodeType::ProcessingInstruction => format!(
+            "processing-instruction({})[{}]",
+            name.get_localname(),
+            position()
+        ),
+        NodeType::Namespace => {
+            if name.get_localname().is_empty() {
+                format!("namespace::*[Q{{{}}}local-name()=\"\"]", FNNS)
+            } else {
+                format!("namespace::{}", name.get_localname())
+            }
+        }
+        NodeType::Document | NodeType::Reference | NodeType::Unknown => String::new(),
+    }
+}
+
 /// The context item.
 pub(crate) fn context<N: Node>(ctxt: &Context<N>) -> Result<Sequence<N>, Error> {
     ctxt.cur.get(ctxt.i).map_or(
-        Err(Error::new(
+        Err(Error::new_with_code(
             ErrorKind::DynamicAbsent,
             String::from("no context"),
+            Some(QualifiedName::new(None, None, "XPDY0002")),
         )),
         |i| Ok(vec![i.clone()]),
     )
@@ -88,7 +277,11 @@ pub(crate) fn compose<
             break;
         }
     }
-    Ok(context)
+    // A step already returns its nodes in document order without duplicates
+    match steps.last() {
+        Some(Transform::Step(_)) => Ok(context),
+        _ => Ok(document_order(context)),
+    }
     //    steps.iter().try_fold(ctxt.cur.clone(), |seq, t| {
     //        ContextBuilder::from(ctxt)
     //            .current(seq)
@@ -278,21 +471,30 @@ pub(crate) fn step<N: Node>(ctxt: &Context<N>, nm: &NodeMatch) -> Result<Sequenc
             )),
         }
     }) {
-        Ok(mut r) => {
-            // Sort in document order
-            r.sort_unstable_by(|a, b| {
-                get_node_unchecked(a).cmp_document_order(get_node_unchecked(b))
-            });
-            // Eliminate duplicates
-            r.dedup_by(|a, b| {
-                get_node(a).map_or(false, |aa| get_node(b).map_or(false, |bb| aa.is_same(bb)))
-            });
-            Ok(r)
-        }
+        Ok(r) => Ok(document_order(r)),
         Err(err) => Err(err),
     }
 }
 
+/// Put a sequence of nodes into document order, removing duplicates.
+/// If the sequence contains any item that is not a node then it is returned unchanged.
+/// The sequence is only sorted if it is not already in strict document order.
+pub(crate) fn document_order<N: Node>(mut r: Sequence<N>) -> Sequence<N> {
+    if r.len() < 2 || !r.iter().all(|i| matches!(i, Item::Node(_))) {
+        return r;
+    }
+    if r.windows(2).all(|w| {
+        get_node_unchecked(&w[0]).cmp_document_order(get_node_unchecked(&w[1])) == Ordering::Less
+    }) {
+        return r;
+    }
+    // Sort in document order
+    r.sort_unstable_by(|a, b| get_node_unchecked(a).cmp_document_order(get_node_unchecked(b)));
+    // Eliminate duplicates
+    r.dedup_by(|a, b| get_node(a).is_ok_and(|aa| get_node(b).is_ok_and(|bb| aa.is_same(bb))));
+    r
+}
+
 fn get_node_unchecked<N: Node>(i: &Item<N>) -> &N {
     match i {
         Item::Node(n) => n,
@@ -318,15 +520,244 @@ pub(crate) fn filter<
     predicate: &Transform<N>,
 ) -> Result<Sequence<N>, Error> {
     ctxt.cur.iter().try_fold(vec![], |mut acc, i| {
-        if ContextBuilder::from(ctxt)
-            .context(vec![i.clone()])
-            .previous_context(ctxt.previous_context.clone())
-            .build()
-            .dispatch(stctxt, predicate)?
-            .to_bool()
-        {
+        if satisfies(ctxt, stctxt, predicate, i, &ctxt.previous_context)? {
             acc.push(i.clone())
         }
         Ok(acc)
     })
 }
+
+// Evaluate a predicate for a single item.
+// Only as much of the predicate's value is evaluated as is needed to find its boolean value.
+fn satisfies<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    predicate: &Transform<N>,
+    i: &Item<N>,
+    current: &Option<Item<N>>,
+) -> Result<bool, Error> {
+    let pctxt = ContextBuilder::from(ctxt)
+        .context(vec![i.clone()])
+        .previous_context(current.clone())
+        .build();
+    Ok(first_items(&pctxt, stctxt, predicate, 2)?.to_bool())
+}
+
+/// A visitor of the items of a sequence. The visitor may stop the evaluation of the sequence by returning [ControlFlow::Break].
+pub(crate) type Visitor<'a, N, F, G, H> =
+    dyn FnMut(&mut StaticContext<N, F, G, H>, &Item<N>) -> Result<ControlFlow<()>, Error> + 'a;
+
+/// Evaluate the first n items of a transformation, or fewer if the sequence is shorter.
+/// The items are not necessarily the first in document order, so this is suitable for finding whether a sequence is empty,
+/// or its effective boolean value.
+pub(crate) fn first_items<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    t: &Transform<N>,
+    n: usize,
+) -> Result<Sequence<N>, Error> {
+    let mut result = vec![];
+    if n > 0 {
+        visit_items(ctxt, stctxt, t, &mut |_, i| {
+            result.push(i.clone());
+            Ok(if result.len() < n {
+                ControlFlow::Continue(())
+            } else {
+                ControlFlow::Break(())
+            })
+        })?;
+    }
+    Ok(result)
+}
+
+/// Evaluate a transformation, passing each item of the result to the visitor until the visitor breaks.
+/// A path of steps and filters is evaluated lazily, one node at a time,
+/// so that only as much of the tree is traversed as is needed to produce the items that are visited.
+/// In that case the items are not necessarily visited in document order, and duplicates are not removed.
+/// Any other transformation is evaluated in full.
+pub(crate) fn visit_items<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    t: &Transform<N>,
+    v: &mut Visitor<N, F, G, H>,
+) -> Result<ControlFlow<()>, Error> {
+    match t {
+        Transform::Step(_) | Transform::Filter(_) => visit_all(
+            ctxt,
+            stctxt,
+            &[t],
+            ctxt.cur.iter().cloned(),
+            &ctxt.previous_context,
+            v,
+        ),
+        Transform::Compose(steps) => {
+            let mut flat = vec![];
+            flatten_steps(steps, &mut flat);
+            // Only the first step may produce a sequence that is not a path of steps and filters,
+            // since any other step depends on the whole of its context.
+            match flat.split_first() {
+                Some((first, rest))
+                    if rest
+                        .iter()
+                        .all(|s| matches!(s, Transform::Step(_) | Transform::Filter(_))) =>
+                {
+                    // See compose for how the current item is set
+                    let current = if ctxt.previous_context.is_none() {
+                        ctxt.cur.get(ctxt.i).cloned()
+                    } else {
+                        ctxt.previous_context.clone()
+                    };
+                    match first {
+                        Transform::Step(_) | Transform::Filter(_) => {
+                            visit_all(ctxt, stctxt, &flat, ctxt.cur.iter().cloned(), &current, v)
+                        }
+                        _ => {
+                            let seq = ContextBuilder::from(ctxt)
+                                .context(ctxt.cur.clone())
+                                .previous_context(current.clone())
+                                .build()
+                                .dispatch(stctxt, first)?;
+                            visit_all(ctxt, stctxt, rest, seq.into_iter(), &current, v)
+                        }
+                    }
+                }
+                None => visit_all(ctxt, stctxt, &[], ctxt.cur.iter().cloned(), &None, v),
+                _ => visit_all(
+                    ctxt,
+                    stctxt,
+                    &[],
+                    ctxt.dispatch(stctxt, t)?.into_iter(),
+                    &None,
+                    v,
+                ),
+            }
+        }
+        _ => visit_all(
+            ctxt,
+            stctxt,
+            &[],
+            ctxt.dispatch(stctxt, t)?.into_iter(),
+            &None,
+            v,
+        ),
+    }
+}
+
+// Nested compositions are equivalent to a single path of steps
+fn flatten_steps<'a, N: Node>(steps: &'a [Transform<N>], flat: &mut Vec<&'a Transform<N>>) {
+    steps.iter().for_each(|s| match s {
+        Transform::Compose(t) => flatten_steps(t, flat),
+        _ => flat.push(s),
+    })
+}
+
+// Evaluate the remaining steps of a path for each of the items, stopping as soon as the visitor breaks.
+fn visit_all<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    steps: &[&Transform<N>],
+    items: impl Iterator<Item = Item<N>>,
+    current: &Option<Item<N>>,
+    v: &mut Visitor<N, F, G, H>,
+) -> Result<ControlFlow<()>, Error> {
+    for i in items {
+        if visit_steps(ctxt, stctxt, steps, &i, current, v)?.is_break() {
+            return Ok(ControlFlow::Break(()));
+        }
+    }
+    Ok(ControlFlow::Continue(()))
+}
+
+// Evaluate the steps of a path for a single item.
+// The axes that may contain many nodes are traversed lazily.
+fn visit_steps<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    steps: &[&Transform<N>],
+    i: &Item<N>,
+    current: &Option<Item<N>>,
+    v: &mut Visitor<N, F, G, H>,
+) -> Result<ControlFlow<()>, Error> {
+    let Some((t, rest)) = steps.split_first() else {
+        return v(stctxt, i);
+    };
+    match (t, i) {
+        (Transform::Filter(p), _) => {
+            if satisfies(ctxt, stctxt, p, i, current)? {
+                visit_steps(ctxt, stctxt, rest, i, current, v)
+            } else {
+                Ok(ControlFlow::Continue(()))
+            }
+        }
+        (Transform::Step(nm), Item::Node(n)) => {
+            let matching = |c: &N| nm.matches(c);
+            match nm.axis {
+                Axis::Child => {
+                    let nodes = n.child_iter().filter(matching).map(Item::Node);
+                    visit_all(ctxt, stctxt, rest, nodes, current, v)
+                }
+                Axis::Descendant => {
+                    let nodes = n.descend_iter().filter(matching).map(Item::Node);
+                    visit_all(ctxt, stctxt, rest, nodes, current, v)
+                }
+                Axis::DescendantOrSelf => {
+                    let nodes = once(n.clone())
+                        .chain(n.descend_iter())
+                        .filter(matching)
+                        .map(Item::Node);
+                    visit_all(ctxt, stctxt, rest, nodes, current, v)
+                }
+                Axis::DescendantOrSelfOrRoot => {
+                    let nodes = once(n.owner_document())
+                        .chain(once(n.clone()).chain(n.descend_iter()).filter(matching))
+                        .map(Item::Node);
+                    visit_all(ctxt, stctxt, rest, nodes, current, v)
+                }
+                _ => {
+                    let sctxt = ContextBuilder::from(ctxt).context(vec![i.clone()]).build();
+                    visit_all(
+                        ctxt,
+                        stctxt,
+                        rest,
+                        step(&sctxt, nm)?.into_iter(),
+                        current,
+                        v,
+                    )
+                }
+            }
+        }
+        (Transform::Step(_), _) => Err(Error::new(
+            ErrorKind::Unknown,
+            String::from("context item is not a node"),
+        )),
+        _ => Err(Error::new(
+            ErrorKind::Unknown,
+            String::from("not a step in a path"),
+        )),
+    }
+}
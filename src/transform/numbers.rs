@@ -1,5 +1,6 @@
 //! These functions are for features defined in XPath Functions 1.0 and 2.0.
 
+use std::cmp::Ordering;
 use std::rc::Rc;
 use url::Url;
 
@@ -7,10 +8,11 @@ use english_numbers::{convert, Formatting};
 use formato::Formato;
 use italian_numbers::roman_converter;
 
-use crate::item::{Item, Node, NodeType, Sequence, SequenceTrait};
+use crate::item::{not_singleton, Item, Node, NodeType, Sequence, SequenceTrait};
 use crate::pattern::{PathBuilder, Pattern};
 use crate::qname::QualifiedName;
 use crate::transform::context::{Context, StaticContext};
+use crate::transform::strings::collation_argument;
 use crate::transform::{
     ArithmeticOperand, ArithmeticOperator, Axis, KindTest, NameTest, NodeTest, Transform,
     WildcardOrName,
@@ -53,11 +55,11 @@ pub fn generate_integers<
     select: &Transform<N>,
     num: &Numbering<N>,
 ) -> Result<Sequence<N>, Error> {
-    // This implements "single" level. "multiple" and "any" are TODO
-    if num.level != Level::Single {
+    // This implements "single" and "multiple" levels. "any" is TODO
+    if num.level == Level::Any {
         return Err(Error::new(
             ErrorKind::NotImplemented,
-            "only single level is implemented",
+            "only single and multiple levels are implemented",
         ));
     }
 
@@ -96,6 +98,41 @@ pub fn generate_integers<
                         }
                     }));
 
+            if num.level == Level::Multiple {
+                // let $F := $S/ancestor-or-self::node()[matches-from(.)][1]
+                // When there is no from pattern specified then use the root node
+                let chain: Vec<N> = std::iter::once(m.clone())
+                    .chain(m.ancestor_iter())
+                    .collect();
+                let f = num
+                    .from
+                    .as_ref()
+                    .and_then(|fr| {
+                        chain
+                            .iter()
+                            .position(|i| fr.matches(ctxt, stctxt, &Item::Node(i.clone())))
+                    })
+                    .unwrap_or(chain.len() - 1);
+                // let $AF := $S/ancestor-or-self::node()[matches-count(.)][ancestor-or-self::node()[. is $F]]
+                // for $af in $AF return 1 + count($af/preceding-sibling::node()[matches-count(.)])
+                let af: Vec<N> = chain[..=f]
+                    .iter()
+                    .rev()
+                    .filter(|i| count_pat.matches(ctxt, stctxt, &Item::Node((*i).clone())))
+                    .cloned()
+                    .collect();
+                return Ok(af
+                    .iter()
+                    .map(|a| {
+                        let preceding = a
+                            .prev_iter()
+                            .filter(|i| count_pat.matches(ctxt, stctxt, &Item::Node(i.clone())))
+                            .count();
+                        Item::Value(Rc::new(Value::from(1 + preceding)))
+                    })
+                    .collect());
+            }
+
             // let a = $S/ancestor-or-self::node()[matches-count(.)][1]
             // TODO: Don't Panic
             let a = if count_pat.matches(ctxt, stctxt, &Item::Node(m.clone())) {
@@ -107,7 +144,7 @@ pub fn generate_integers<
                     .collect()
             };
             if a.is_empty() {
-                return Ok(vec![]);
+                return Ok(Sequence::empty());
             }
             // let f = $S/ancestor-or-self::node()[matches-from(.)][1]
             // TODO: Don't Panic
@@ -127,7 +164,7 @@ pub fn generate_integers<
                 vec![m.owner_document().clone()]
             };
             if f.is_empty() {
-                return Ok(vec![]);
+                return Ok(Sequence::empty());
             }
             // let af = $a[ancestor-or-self::node()[. is $f]]
             let af_test: Vec<N> = if a[0].is_same(&f[0]) {
@@ -137,7 +174,7 @@ pub fn generate_integers<
             };
             let af = if af_test.is_empty() { vec![] } else { a };
             if af.is_empty() {
-                return Ok(vec![]);
+                return Ok(Sequence::empty());
             }
             // 1 + count($af/preceding-sibling::node()[matches-count(.)])
             let result: Vec<N> = af[0]
@@ -185,14 +222,11 @@ pub fn number<
                 }
             }
         }
-        _ => Err(Error::new(
-            ErrorKind::TypeError,
-            String::from("not a singleton sequence"),
-        )),
+        _ => Err(not_singleton(&n)),
     }
 }
 
-/// XPath sum function.
+/// XPath sum function. It is an error (FORG0006) if an item is not a number.
 pub fn sum<
     N: Node,
     F: FnMut(&str) -> Result<(), Error>,
@@ -204,13 +238,165 @@ pub fn sum<
     s: &Transform<N>,
 ) -> Result<Sequence<N>, Error> {
     Ok(vec![Item::Value(Rc::new(Value::Double(
-        ctxt.dispatch(stctxt, s)?.iter().fold(0.0, |mut acc, i| {
-            acc += i.to_double();
-            acc
-        }),
+        ctxt.dispatch(stctxt, s)?
+            .iter()
+            .try_fold(0.0, |acc, i| Ok::<f64, Error>(acc + aggregate_number(i)?))?,
     )))])
 }
 
+/// XPath avg function. The average of an empty sequence is the empty sequence.
+/// It is an error (FORG0006) if an item is not a number.
+pub fn avg<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    s: &Transform<N>,
+) -> Result<Sequence<N>, Error> {
+    let seq = ctxt.dispatch(stctxt, s)?;
+    if seq.is_empty() {
+        return Ok(Sequence::empty());
+    }
+    let total = seq
+        .iter()
+        .try_fold(0.0, |acc, i| Ok::<f64, Error>(acc + aggregate_number(i)?))?;
+    Ok(vec![Item::Value(Rc::new(Value::Double(
+        total / seq.len() as f64,
+    )))])
+}
+
+// The numeric value of an item that is aggregated by sum, avg, max or min.
+// A double or float is used as it is, even if it is NaN.
+// Other values, and nodes, are converted from their string value, which must be a number.
+fn aggregate_number<N: Node>(i: &Item<N>) -> Result<f64, Error> {
+    match i {
+        Item::Value(v) => match **v {
+            Value::Double(d) => return Ok(d),
+            Value::Float(f) => return Ok(f as f64),
+            _ => {}
+        },
+        Item::Node(_) => {}
+        Item::Function(_) | Item::Map(_) => {
+            return Err(Error::new_with_code(
+                ErrorKind::TypeError,
+                "a function item cannot be aggregated",
+                Some(QualifiedName::new(None, None, "FORG0006")),
+            ))
+        }
+    }
+    let s = i.to_string();
+    s.trim().parse::<f64>().map_err(|_| {
+        Error::new_with_code(
+            ErrorKind::TypeError,
+            format!("\"{}\" is not a number", s),
+            Some(QualifiedName::new(None, None, "FORG0006")),
+        )
+    })
+}
+
+/// XPath max function. Strings are compared using the collation, if given, or the default collation.
+pub fn max<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    s: &Transform<N>,
+    c: &Option<Box<Transform<N>>>,
+) -> Result<Sequence<N>, Error> {
+    extremum(ctxt, stctxt, s, c, Ordering::Greater)
+}
+
+/// XPath min function. Strings are compared using the collation, if given, or the default collation.
+pub fn min<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    s: &Transform<N>,
+    c: &Option<Box<Transform<N>>>,
+) -> Result<Sequence<N>, Error> {
+    extremum(ctxt, stctxt, s, c, Ordering::Less)
+}
+
+// Find the item that compares as "which" to all other items, i.e. the greatest or least.
+// If the items are all strings then they are compared using the collation, otherwise they are compared as numbers.
+// Nodes are atomized as untyped values, so they are compared as numbers.
+fn extremum<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    s: &Transform<N>,
+    c: &Option<Box<Transform<N>>>,
+    which: Ordering,
+) -> Result<Sequence<N>, Error> {
+    let seq = ctxt.dispatch(stctxt, s)?;
+    let is_string = |i: &Item<N>| match i {
+        Item::Value(v) => matches!(**v, Value::String(_) | Value::NormalizedString(_)),
+        _ => false,
+    };
+    if seq.is_empty() {
+        Ok(Sequence::empty())
+    } else if seq.iter().all(is_string) {
+        let collation = collation_argument(ctxt, stctxt, c)?;
+        Ok(seq
+            .into_iter()
+            .reduce(|a, b| {
+                if collation.compare(&b.to_string(), &a.to_string()) == which {
+                    b
+                } else {
+                    a
+                }
+            })
+            .into_iter()
+            .collect())
+    } else if seq.iter().any(is_string) {
+        Err(Error::new_with_code(
+            ErrorKind::TypeError,
+            "cannot compare strings and other values",
+            Some(QualifiedName::new(None, None, "FORG0006")),
+        ))
+    } else {
+        let numbers = seq
+            .into_iter()
+            .map(|i| {
+                let d = aggregate_number(&i)?;
+                Ok(match i {
+                    Item::Node(_) => (d, Item::Value(Rc::new(Value::Double(d)))),
+                    _ => (d, i),
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        if numbers.iter().any(|(d, _)| d.is_nan()) {
+            return Ok(vec![Item::Value(Rc::new(Value::Double(f64::NAN)))]);
+        }
+        Ok(numbers
+            .into_iter()
+            .reduce(|a, b| {
+                if b.0.partial_cmp(&a.0) == Some(which) {
+                    b
+                } else {
+                    a
+                }
+            })
+            .map(|(_, i)| i)
+            .into_iter()
+            .collect())
+    }
+}
+
 /// XPath floor function.
 pub fn floor<
     N: Node,
@@ -227,10 +413,7 @@ pub fn floor<
         1 => Ok(vec![Item::Value(Rc::new(Value::Double(
             n[0].to_double().floor(),
         )))]),
-        _ => Err(Error::new(
-            ErrorKind::TypeError,
-            String::from("not a singleton sequence"),
-        )),
+        _ => Err(not_singleton(&n)),
     }
 }
 
@@ -250,10 +433,7 @@ pub fn ceiling<
         1 => Ok(vec![Item::Value(Rc::new(Value::Double(
             n[0].to_double().ceil(),
         )))]),
-        _ => Err(Error::new(
-            ErrorKind::TypeError,
-            String::from("not a singleton sequence"),
-        )),
+        _ => Err(not_singleton(&n)),
     }
 }
 
@@ -278,10 +458,8 @@ pub fn round<
                     ((n[0].to_double() * (10.0_f64).powi(m[0].to_int().unwrap() as i32)).round())
                         * (10.0_f64).powi(-m[0].to_int().unwrap() as i32),
                 )))]),
-                _ => Err(Error::new(
-                    ErrorKind::TypeError,
-                    String::from("not a singleton sequence"),
-                )),
+                (1, _) => Err(not_singleton(&m)),
+                _ => Err(not_singleton(&n)),
             }
         }
         None => {
@@ -291,10 +469,7 @@ pub fn round<
                 1 => Ok(vec![Item::Value(Rc::new(Value::Double(
                     n[0].to_double().round(),
                 )))]),
-                _ => Err(Error::new(
-                    ErrorKind::TypeError,
-                    String::from("not a singleton sequence"),
-                )),
+                _ => Err(not_singleton(&n)),
             }
         }
     }
@@ -316,7 +491,7 @@ pub(crate) fn tr_range<
     let e = ctxt.dispatch(stctxt, end)?;
     if s.is_empty() || e.is_empty() {
         // Empty sequence is the result
-        return Ok(vec![]);
+        return Ok(Sequence::empty());
     }
     if s.len() != 1 || e.len() != 1 {
         return Err(Error::new(
@@ -328,7 +503,7 @@ pub(crate) fn tr_range<
     let j = e[0].to_int()?;
     if i > j {
         // empty sequence result
-        Ok(vec![])
+        Ok(Sequence::empty())
     } else if i == j {
         let mut seq = Sequence::new();
         seq.push_value(&Rc::new(Value::Integer(i)));
@@ -415,10 +590,7 @@ pub fn format_number<
                 }
             }
         }
-        _ => Err(Error::new(
-            ErrorKind::TypeError,
-            String::from("not a singleton sequence"),
-        )),
+        _ => Err(not_singleton(&n)),
     }
 }
 
@@ -433,171 +605,256 @@ pub fn format_integer<
     stctxt: &mut StaticContext<N, F, G, H>,
     num: &Transform<N>,
     picture: &Transform<N>,
+) -> Result<Sequence<N>, Error> {
+    format_integers(ctxt, stctxt, num, picture, &NumberFormat::default())
+}
+
+/// XSLT xsl:number, with grouping and ordinal options.
+pub fn format_integers<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    num: &Transform<N>,
+    picture: &Transform<N>,
+    nf: &NumberFormat<N>,
 ) -> Result<Sequence<N>, Error> {
     let p = ctxt.dispatch(stctxt, picture)?.to_string();
-    let numbers = ctxt.dispatch(stctxt, num)?;
-    let mut nit = numbers.iter();
-
-    let mut result = String::new();
-
-    // Interpret the picture string.
-    // Most of the tokens are one character, except for 'Ww'.
-    let mut pit = p.chars().peekable();
-    loop {
-        let c = pit.next();
-        if let Some(d) = c {
-            if d.is_alphanumeric() {
-                match d {
-                    '0' => {
-                        // 01, 02, 03, 04, ...
-                        // length specification
-                        // TODO: non-arabic-roman numerals
-                        let mut token = String::from(d);
-                        loop {
-                            if let Some(p) = pit.peek() {
-                                if p.eq(&'0') {
-                                    pit.next();
-                                    token.push('0');
-                                } else if p.eq(&'1') {
-                                    pit.next();
-                                    token.push('1');
-                                } else {
-                                    break;
-                                }
-                            } else {
-                                break;
-                            }
-                        }
-                        if let Some(num) = nit.next() {
-                            result.push_str(
-                                format!("{:0>1$}", num.to_int()?.to_string(), token.len()).as_str(),
-                            );
-                        } else {
-                            break;
-                        }
-                    }
-                    '1' => {
-                        // 1, 2, 3, ...
-                        if let Some(num) = nit.next() {
-                            result.push_str(num.to_int()?.to_string().as_str())
-                        } else {
-                            break;
-                        }
-                    }
-                    'A' => {
-                        // A, B, C, ..., AA, BB, CC, ...
-                    }
-                    'a' => {
-                        // a, b, c, ..., aa, bb, cc, ...
-                    }
-                    'i' => {
-                        // i, ii, iii, iv, v, vi, ...
-                        if let Some(num) = nit.next() {
-                            result.push_str(
-                                roman_converter(u16::try_from(num.to_int()?).map_err(|e| {
-                                    Error::new(ErrorKind::ParseError, e.to_string())
-                                })?)
-                                .map_err(|e| Error::new(ErrorKind::ParseError, e))?
-                                .to_lowercase()
-                                .as_str(),
-                            )
-                        } else {
-                            break;
-                        }
-                    }
-                    'I' => {
-                        // I, II, III, IV, V, VI, ...
-                        if let Some(num) = nit.next() {
-                            result.push_str(
-                                roman_converter(u16::try_from(num.to_int()?).map_err(|e| {
-                                    Error::new(ErrorKind::ParseError, e.to_string())
-                                })?)
-                                .map_err(|e| Error::new(ErrorKind::ParseError, e))?
-                                .as_str(),
-                            )
-                        } else {
-                            break;
-                        }
-                    }
-                    'w' => {
-                        // one, two, three, ...
-                        if let Some(num) = nit.next() {
-                            result.push_str(
-                                convert(
-                                    num.to_int()?,
-                                    Formatting {
-                                        title_case: false,
-                                        spaces: true,
-                                        conjunctions: false,
-                                        commas: false,
-                                        dashes: false,
-                                    },
-                                )
-                                .to_string()
-                                .as_str(),
-                            )
-                        } else {
-                            break;
-                        }
-                    }
-                    'W' => {
-                        // 'Ww'
-                        if let Some('w') = pit.peek() {
-                            // One, Two, Three, ...
-                            pit.next();
-                            if let Some(num) = nit.next() {
-                                result.push_str(
-                                    convert(
-                                        num.to_int()?,
-                                        Formatting {
-                                            title_case: true,
-                                            spaces: true,
-                                            conjunctions: false,
-                                            commas: false,
-                                            dashes: false,
-                                        },
-                                    )
-                                    .to_string()
-                                    .as_str(),
-                                )
-                            } else {
-                                break;
-                            }
-                        } else {
-                            // ONE, TWO, THREE, ...
-                            if let Some(num) = nit.next() {
-                                result.push_str(
-                                    convert(
-                                        num.to_int()?,
-                                        Formatting {
-                                            title_case: false,
-                                            spaces: true,
-                                            conjunctions: false,
-                                            commas: false,
-                                            dashes: false,
-                                        },
-                                    )
-                                    .to_string()
-                                    .to_uppercase()
-                                    .as_str(),
-                                )
-                            } else {
-                                break;
-                            }
-                        }
-                    }
-                    // TODO: non-English words
-                    // Use french-numbers crate
-                    // Use italian-numbers crate
-                    _ => {}
-                }
-            } else {
-                result.push(d)
+    let opts = nf.resolve(ctxt, stctxt)?;
+    let numbers = ctxt
+        .dispatch(stctxt, num)?
+        .iter()
+        .map(|n| n.to_int())
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    // Interpret the format string.
+    // It is a sequence of alternating separator and format tokens. A format token is a run of alphanumeric characters.
+    // A leading separator is a prefix, and a trailing separator is a suffix.
+    let mut prefix = String::new();
+    let mut tokens: Vec<(String, String)> = vec![]; // (preceding separator, format token)
+    let mut suffix = String::new();
+    p.chars().for_each(|c| {
+        if c.is_alphanumeric() {
+            if !suffix.is_empty() || tokens.is_empty() {
+                tokens.push((std::mem::take(&mut suffix), String::new()))
             }
+            tokens.last_mut().unwrap().1.push(c)
+        } else if tokens.is_empty() {
+            prefix.push(c)
         } else {
-            break;
+            suffix.push(c)
         }
+    });
+    if tokens.is_empty() {
+        tokens.push((String::new(), String::from("1")))
     }
 
+    let mut result = prefix;
+    numbers.iter().enumerate().try_for_each(|(i, n)| {
+        // When there are more numbers than format tokens, the last token (and its separator) is reused
+        let (sep, token) = &tokens[i.min(tokens.len() - 1)];
+        if i > 0 {
+            if tokens.len() == 1 {
+                result.push('.')
+            } else {
+                result.push_str(sep)
+            }
+        }
+        result.push_str(format_token(*n, token, &opts)?.as_str());
+        Ok::<(), Error>(())
+    })?;
+    result.push_str(suffix.as_str());
+
     Ok(vec![Item::Value(Rc::new(Value::from(result)))])
 }
+
+/// Options for formatting numbers. See XSLT 12.4.
+/// The options are attribute value templates, so they are evaluated each time a number is formatted.
+#[derive(Clone, Debug)]
+pub struct NumberFormat<N: Node> {
+    grouping: Option<(Transform<N>, Transform<N>)>,
+    ordinal: Option<Transform<N>>,
+}
+impl<N: Node> NumberFormat<N> {
+    /// Grouping is the separator and the number of digits in each group. Ordinal numbering gives "1st", "2nd", and so on.
+    pub fn new(
+        grouping: Option<(Transform<N>, Transform<N>)>,
+        ordinal: Option<Transform<N>>,
+    ) -> Self {
+        NumberFormat { grouping, ordinal }
+    }
+    // Evaluate the options in the dynamic context.
+    fn resolve<
+        F: FnMut(&str) -> Result<(), Error>,
+        G: FnMut(&str) -> Result<N, Error>,
+        H: FnMut(&Url) -> Result<String, Error>,
+    >(
+        &self,
+        ctxt: &Context<N>,
+        stctxt: &mut StaticContext<N, F, G, H>,
+    ) -> Result<FormatOptions, Error> {
+        let grouping = match &self.grouping {
+            Some((sep, size)) => {
+                let sz = ctxt.dispatch(stctxt, size)?.to_string();
+                Some((
+                    ctxt.dispatch(stctxt, sep)?.to_string(),
+                    sz.trim().parse::<usize>().map_err(|_| {
+                        Error::new_with_code(
+                            ErrorKind::DynamicAbsent,
+                            format!("invalid value \"{}\" for grouping-size", sz),
+                            Some(QualifiedName::new(None, None, "XTDE0030")),
+                        )
+                    })?,
+                ))
+            }
+            None => None,
+        };
+        // Any value other than "no" (or absent) requests ordinal numbering
+        let ordinal = match &self.ordinal {
+            Some(o) => !matches!(
+                ctxt.dispatch(stctxt, o)?.to_string().trim(),
+                "" | "no" | "false" | "0"
+            ),
+            None => false,
+        };
+        Ok(FormatOptions { grouping, ordinal })
+    }
+}
+impl<N: Node> Default for NumberFormat<N> {
+    fn default() -> Self {
+        NumberFormat {
+            grouping: None,
+            ordinal: None,
+        }
+    }
+}
+
+// The evaluated options for formatting numbers.
+struct FormatOptions {
+    grouping: Option<(String, usize)>,
+    ordinal: bool,
+}
+
+// Format a single number using a format token.
+// Unsupported tokens, and numbers that cannot be represented by a token, use decimal digits.
+fn format_token(n: i64, token: &str, nf: &FormatOptions) -> Result<String, Error> {
+    let words = |title_case| {
+        convert(
+            n,
+            Formatting {
+                title_case,
+                spaces: true,
+                conjunctions: false,
+                commas: false,
+                dashes: false,
+            },
+        )
+        .to_string()
+    };
+    let roman = || {
+        u16::try_from(n)
+            .ok()
+            .and_then(|m| roman_converter(m).ok())
+            .map(|r| r.to_string())
+    };
+    match token {
+        "a" | "A" if n > 0 => {
+            // a, b, c, ..., z, aa, ab, ...
+            let base = if token == "a" { b'a' } else { b'A' };
+            let mut letters = vec![];
+            let mut m = n;
+            while m > 0 {
+                m -= 1;
+                letters.push((base + (m % 26) as u8) as char);
+                m /= 26;
+            }
+            Ok(letters.iter().rev().collect())
+        }
+        "i" if roman().is_some() => Ok(roman().unwrap().to_lowercase()),
+        "I" if roman().is_some() => Ok(roman().unwrap()),
+        "w" if nf.ordinal => Ok(ordinal_words(words(false))),
+        "w" => Ok(words(false)),
+        "Ww" if nf.ordinal => Ok(ordinal_words(words(true))),
+        "Ww" => Ok(words(true)),
+        "W" if nf.ordinal => Ok(ordinal_words(words(false)).to_uppercase()),
+        "W" => Ok(words(false).to_uppercase()),
+        _ => {
+            // Decimal digits. The number of digits in the token is the minimum width, e.g. "001".
+            let width = if token.chars().all(|c| c.is_ascii_digit()) {
+                token.len()
+            } else {
+                1
+            };
+            let mut digits = format!("{:0>1$}", n.unsigned_abs(), width);
+            if let Some((sep, size)) = &nf.grouping {
+                if *size > 0 {
+                    let len = digits.chars().count();
+                    digits = digits
+                        .chars()
+                        .enumerate()
+                        .fold(String::new(), |mut acc, (i, c)| {
+                            if i > 0 && (len - i) % size == 0 {
+                                acc.push_str(sep)
+                            }
+                            acc.push(c);
+                            acc
+                        })
+                }
+            }
+            if n < 0 {
+                digits.insert(0, '-')
+            }
+            if nf.ordinal {
+                digits.push_str(ordinal_suffix(n))
+            }
+            Ok(digits)
+        }
+    }
+}
+
+// The English ordinal suffix for a number: "st", "nd", "rd" or "th".
+fn ordinal_suffix(n: i64) -> &'static str {
+    let n = n.unsigned_abs();
+    match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    }
+}
+
+// Convert a number in English words to its ordinal form by changing the last word, e.g. "twenty one" to "twenty first".
+fn ordinal_words(s: String) -> String {
+    let (head, last) = match s.rfind(' ') {
+        Some(i) => s.split_at(i + 1),
+        None => ("", s.as_str()),
+    };
+    let title_case = last.chars().next().is_some_and(|c| c.is_uppercase());
+    let lower = last.to_lowercase();
+    let ordinal = match lower.as_str() {
+        "one" => String::from("first"),
+        "two" => String::from("second"),
+        "three" => String::from("third"),
+        "five" => String::from("fifth"),
+        "eight" => String::from("eighth"),
+        "nine" => String::from("ninth"),
+        "twelve" => String::from("twelfth"),
+        l if l.ends_with('y') => format!("{}ieth", &l[..l.len() - 1]),
+        l => format!("{}th", l),
+    };
+    let mut result = String::from(head);
+    if title_case {
+        let mut c = ordinal.chars();
+        if let Some(f) = c.next() {
+            result.extend(f.to_uppercase());
+            result.push_str(c.as_str());
+        }
+    } else {
+        result.push_str(ordinal.as_str())
+    }
+    result
+}
@@ -1,14 +1,19 @@
 //! These functions are for features defined in XPath Functions 1.0 and 2.0.
 
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
 use std::rc::Rc;
 
+use regex::{Regex, RegexBuilder};
 use unicode_segmentation::UnicodeSegmentation;
 use url::Url;
 
 use crate::item::{Item, Node, Sequence, SequenceTrait};
+use crate::qname::QualifiedName;
 use crate::transform::context::{Context, StaticContext};
 use crate::transform::Transform;
-use crate::value::Value;
+use crate::value::{Operator, Value, XSNS};
 use crate::xdmerror::{Error, ErrorKind};
 
 /// XPath local-name function.
@@ -118,9 +123,12 @@ pub fn string<
     stctxt: &mut StaticContext<N, F, G, H>,
     s: &Transform<N>,
 ) -> Result<Sequence<N>, Error> {
-    Ok(vec![Item::Value(Rc::new(Value::from(
-        ctxt.dispatch(stctxt, s)?.to_string(),
-    )))])
+    let v = ctxt
+        .dispatch(stctxt, s)?
+        .iter()
+        .map(|i| i.string_value())
+        .collect::<Result<String, Error>>()?;
+    Ok(vec![Item::Value(Rc::new(Value::from(v)))])
 }
 
 /// XPath starts-with function.
@@ -247,7 +255,7 @@ pub fn substring_before<
                 }
             }
         }
-        None => Ok(vec![]),
+        None => Ok(Sequence::empty()),
     }
 }
 
@@ -280,7 +288,7 @@ pub fn substring_after<
                 }
             }
         }
-        None => Ok(vec![]),
+        None => Ok(Sequence::empty()),
     }
 }
 
@@ -308,11 +316,302 @@ pub fn normalize_space<
     // intersperse is the right iterator to use, but it is only available in nightly at the moment
     s.map(|u| {
         vec![Item::Value(Rc::new(Value::from(
-            u.split_whitespace().collect::<Vec<&str>>().join(" "),
+            whitespace_tokens(u.as_str())
+                .collect::<Vec<&str>>()
+                .join(" "),
         )))]
     })
 }
 
+/// Split a string on runs of XML whitespace. Leading and trailing whitespace does not produce empty tokens.
+pub(crate) fn whitespace_tokens(s: &str) -> impl Iterator<Item = &str> {
+    s.split(['\u{20}', '\u{9}', '\u{d}', '\u{a}'])
+        .filter(|t| !t.is_empty())
+}
+
+/// The Unicode codepoint collation. This is the default collation.
+pub const CODEPOINT_COLLATION: &str = "http://www.w3.org/2005/xpath-functions/collation/codepoint";
+/// The HTML ASCII case-insensitive collation, see XPath Functions 3.1 5.3.5.
+pub const HTML_ASCII_CASE_INSENSITIVE_COLLATION: &str =
+    "http://www.w3.org/2005/xpath-functions/collation/html-ascii-case-insensitive";
+
+type CompareFn = dyn Fn(&str, &str) -> Ordering;
+type KeyFn = dyn Fn(&str) -> String;
+
+/// A collation determines how strings are compared.
+#[derive(Clone)]
+pub struct Collation {
+    compare: Rc<CompareFn>,
+    key: Option<Rc<KeyFn>>,
+}
+
+impl Collation {
+    pub fn new(f: impl Fn(&str, &str) -> Ordering + 'static) -> Self {
+        Collation {
+            compare: Rc::new(f),
+            key: None,
+        }
+    }
+    /// A collation that also gives a collation key for each string.
+    /// Two strings must be equal in the collation if, and only if, their keys are equal.
+    /// Grouping and distinct values use the key to find equal strings in constant time.
+    /// Without a key, each string is compared with every distinct string found so far.
+    pub fn with_key(
+        f: impl Fn(&str, &str) -> Ordering + 'static,
+        k: impl Fn(&str) -> String + 'static,
+    ) -> Self {
+        Collation {
+            compare: Rc::new(f),
+            key: Some(Rc::new(k)),
+        }
+    }
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        (self.compare)(a, b)
+    }
+    /// Whether two items are the same, for the purposes of grouping and distinct values.
+    /// Strings, including the string value of nodes, are compared using this collation.
+    /// Other values, such as numbers, are compared by value. A string is never the same as a non-string value.
+    pub(crate) fn same_item<N: Node>(&self, a: &Item<N>, b: &Item<N>) -> bool {
+        match (is_string(a), is_string(b)) {
+            (true, true) => self.compare(&a.to_string(), &b.to_string()) == Ordering::Equal,
+            (false, false) => a.compare(b, Operator::Equal).unwrap_or(false),
+            _ => false,
+        }
+    }
+    // The key of an item, such that two items are the same if, and only if, their keys are equal.
+    // A string has no key if the collation does not provide one.
+    fn item_key<N: Node>(&self, i: &Item<N>) -> Option<ItemKey> {
+        match i {
+            Item::Value(v) if !is_string(i) => Some(match &**v {
+                Value::Boolean(b) => ItemKey::Boolean(*b),
+                v if v.instance_of(&QualifiedName::new(
+                    Some(String::from(XSNS)),
+                    None,
+                    "numeric",
+                )) =>
+                {
+                    // Integers are kept exact, so that large integers are not confused.
+                    // All NaNs are the same, and so are positive and negative zero.
+                    let d = v.to_f64();
+                    match v.to_integer() {
+                        Some(n) => ItemKey::Integer(n),
+                        None if d.fract() == 0.0 && d.abs() < 1e38 => ItemKey::Integer(d as i128),
+                        None if d.is_nan() => ItemKey::Number(f64::NAN.to_bits()),
+                        None => ItemKey::Number((d + 0.0).to_bits()),
+                    }
+                }
+                v => ItemKey::Other(v.value_type(), v.to_string()),
+            }),
+            _ => self
+                .key
+                .as_ref()
+                .map(|k| ItemKey::String(k(&i.to_string()))),
+        }
+    }
+}
+
+fn is_string<N: Node>(i: &Item<N>) -> bool {
+    match i {
+        Item::Node(_) => true,
+        Item::Value(v) => matches!(
+            **v,
+            Value::String(_) | Value::NormalizedString(_) | Value::UntypedAtomic(_)
+        ),
+        Item::Function(_) | Item::Map(_) => false,
+    }
+}
+
+#[derive(PartialEq, Eq, Hash)]
+enum ItemKey {
+    String(String),
+    Boolean(bool),
+    Integer(i128),
+    Number(u64),
+    Other(&'static str, String),
+}
+
+/// The distinct items found so far, for grouping and distinct values.
+/// Items are the same if they are the same in the collation, see [Collation::same_item].
+pub(crate) struct DistinctItems<N: Node> {
+    collation: Collation,
+    keys: HashMap<ItemKey, usize>,
+    // Items that have no key, with their index
+    unkeyed: Vec<(Item<N>, usize)>,
+    count: usize,
+}
+
+impl<N: Node> DistinctItems<N> {
+    pub(crate) fn new(collation: Collation) -> Self {
+        DistinctItems {
+            collation,
+            keys: HashMap::new(),
+            unkeyed: vec![],
+            count: 0,
+        }
+    }
+    /// Find the index of the distinct item that is the same as the given item.
+    /// If there is none, then the item is added and Err gives its index.
+    pub(crate) fn find_or_insert(&mut self, i: &Item<N>) -> Result<usize, usize> {
+        let next = self.count;
+        let found = match self.collation.item_key(i) {
+            Some(k) => *self.keys.entry(k).or_insert(next),
+            None => {
+                let n = self
+                    .unkeyed
+                    .iter()
+                    .find(|(u, _)| self.collation.same_item(u, i))
+                    .map_or(next, |(_, n)| *n);
+                if n == next {
+                    self.unkeyed.push((i.clone(), next))
+                }
+                n
+            }
+        };
+        if found == next {
+            self.count += 1;
+            Err(next)
+        } else {
+            Ok(found)
+        }
+    }
+}
+
+impl fmt::Debug for Collation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("collation")
+    }
+}
+
+/// The collations that are always available.
+pub(crate) fn builtin_collation(uri: &str) -> Option<Collation> {
+    match uri {
+        CODEPOINT_COLLATION => Some(Collation::with_key(|a, b| a.cmp(b), String::from)),
+        HTML_ASCII_CASE_INSENSITIVE_COLLATION => Some(Collation::with_key(
+            |a, b| a.to_ascii_lowercase().cmp(&b.to_ascii_lowercase()),
+            |a| a.to_ascii_lowercase(),
+        )),
+        _ => None,
+    }
+}
+
+/// Find the collation given by an optional collation argument, or the default collation if there is no argument.
+pub(crate) fn collation_argument<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    c: &Option<Box<Transform<N>>>,
+) -> Result<Collation, Error> {
+    match c {
+        Some(coll) => {
+            let u = ctxt.dispatch(stctxt, coll)?.to_string();
+            ctxt.resolve_collation(Some(u.as_str()))
+        }
+        None => ctxt.resolve_collation(None),
+    }
+}
+
+/// XPath contains-token function.
+pub fn contains_token<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    s: &Transform<N>,
+    t: &Transform<N>,
+    c: &Option<Box<Transform<N>>>,
+) -> Result<Sequence<N>, Error> {
+    let collation = collation_argument(ctxt, stctxt, c)?;
+    // s is the sequence of strings to search, t is the token to search for
+    let token = ctxt.dispatch(stctxt, t)?.to_string();
+    let token = token.trim_matches(['\u{20}', '\u{9}', '\u{d}', '\u{a}']);
+    let found = !token.is_empty()
+        && ctxt.dispatch(stctxt, s)?.iter().any(|i| {
+            whitespace_tokens(i.to_string().as_str())
+                .any(|u| collation.compare(u, token) == Ordering::Equal)
+        });
+    Ok(vec![Item::Value(Rc::new(Value::from(found)))])
+}
+
+/// XPath tokenize function.
+pub fn tokenize<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    s: &Transform<N>,
+    p: &Option<Box<Transform<N>>>,
+    f: &Option<Box<Transform<N>>>,
+) -> Result<Sequence<N>, Error> {
+    let input = ctxt.dispatch(stctxt, s)?.to_string();
+    let to_item = |t: &str| Item::Value(Rc::new(Value::from(t)));
+    match p {
+        None => Ok(whitespace_tokens(input.as_str()).map(to_item).collect()),
+        Some(pat) => {
+            let pattern = ctxt.dispatch(stctxt, pat)?.to_string();
+            let flags = match f {
+                Some(g) => ctxt.dispatch(stctxt, g)?.to_string(),
+                None => String::new(),
+            };
+            let re = regex_with_flags(pattern.as_str(), flags.as_str())?;
+            if re.is_match("") {
+                return Err(Error::new_with_code(
+                    ErrorKind::TypeError,
+                    String::from("pattern matches a zero-length string"),
+                    Some(QualifiedName::new(None, None, "FORX0003")),
+                ));
+            }
+            if input.is_empty() {
+                Ok(Sequence::empty())
+            } else {
+                Ok(re.split(input.as_str()).map(to_item).collect())
+            }
+        }
+    }
+}
+
+// Compile a regular expression, using XPath regular expression flags.
+fn regex_with_flags(pattern: &str, flags: &str) -> Result<Regex, Error> {
+    let p = if flags.contains('q') {
+        regex::escape(pattern)
+    } else {
+        pattern.to_string()
+    };
+    let mut b = RegexBuilder::new(p.as_str());
+    for c in flags.chars() {
+        match c {
+            's' => b.dot_matches_new_line(true),
+            'm' => b.multi_line(true),
+            'i' => b.case_insensitive(true),
+            'x' => b.ignore_whitespace(true),
+            'q' => &mut b,
+            _ => {
+                return Err(Error::new_with_code(
+                    ErrorKind::TypeError,
+                    format!("invalid regular expression flag \"{}\"", c),
+                    Some(QualifiedName::new(None, None, "FORX0001")),
+                ))
+            }
+        };
+    }
+    b.build().map_err(|e| {
+        Error::new_with_code(
+            ErrorKind::ParseError,
+            format!("invalid regular expression: {}", e),
+            Some(QualifiedName::new(None, None, "FORX0002")),
+        )
+    })
+}
+
 /// XPath translate function.
 pub fn translate<
     N: Node,
@@ -363,6 +662,31 @@ pub fn translate<
     Ok(vec![Item::Value(Rc::new(Value::from(result)))])
 }
 
+/// XPath string-join function. The string values of the items in the sequence are joined using the separator.
+pub fn string_join<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    s: &Transform<N>,
+    t: &Option<Box<Transform<N>>>,
+) -> Result<Sequence<N>, Error> {
+    let separator = match t {
+        Some(u) => ctxt.dispatch(stctxt, u)?.to_string(),
+        None => String::new(),
+    };
+    let result = ctxt
+        .dispatch(stctxt, s)?
+        .iter()
+        .map(|i| i.to_string())
+        .collect::<Vec<String>>()
+        .join(separator.as_str());
+    Ok(vec![Item::Value(Rc::new(Value::from(result)))])
+}
+
 /// XPath concat function. All arguments are concatenated into a single string value.
 pub(crate) fn tr_concat<
     N: Node,
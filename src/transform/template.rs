@@ -9,7 +9,7 @@ use crate::qname::QualifiedName;
 use crate::transform::context::{Context, ContextBuilder, StaticContext};
 use crate::transform::{do_sort, Order, Transform};
 use crate::xdmerror::Error;
-use crate::{Node, Pattern, Sequence};
+use crate::{Item, Node, Pattern, Sequence, SequenceTrait};
 
 #[derive(Clone)]
 pub struct Template<N: Node> {
@@ -19,6 +19,9 @@ pub struct Template<N: Node> {
     pub(crate) import: Vec<usize>,
     pub(crate) document_order: Option<usize>,
     pub(crate) mode: Option<QualifiedName>,
+    // Where the template came from, for debugging: the match pattern as written, and its location in the stylesheet
+    pub(crate) match_string: Option<String>,
+    pub(crate) location: Option<String>,
 }
 
 impl<N: Node> Template<N> {
@@ -37,8 +40,17 @@ impl<N: Node> Template<N> {
             import,
             document_order,
             mode,
+            match_string: None,
+            location: None,
         }
     }
+    /// Record the match pattern, as written, and the location of the template in the stylesheet.
+    /// These are only used to describe the template; see [Context::dump_static_info].
+    pub fn source(mut self, match_string: impl Into<String>, location: impl Into<String>) -> Self {
+        self.match_string = Some(match_string.into());
+        self.location = Some(location.into());
+        self
+    }
 }
 
 /// Two templates are equal if they have the same priority, import precedence, and mode.
@@ -99,49 +111,51 @@ pub(crate) fn apply_templates<
     stctxt: &mut StaticContext<N, F, G, H>,
     s: &Transform<N>,
     m: &Option<QualifiedName>,
-    o: &Vec<(Order, Transform<N>)>, // sort keys
+    o: &Vec<(Order<N>, Transform<N>)>, // sort keys
 ) -> Result<Sequence<N>, Error> {
     // s is the select expression. Evaluate it, and then iterate over its items.
     // Each iteration becomes an item in the result sequence.
     let mut seq = ctxt.dispatch(stctxt, s)?;
     do_sort(&mut seq, o, ctxt, stctxt)?;
-    seq.iter().try_fold(vec![], |mut result, i| {
-        let templates = ctxt.find_templates(stctxt, i, m)?;
-        // If there are two or more templates with the same priority and import level, then take the one that has the higher document order
-        let matching = if templates.len() > 1 {
-            if templates[0].priority == templates[1].priority
-                && templates[0].import.len() == templates[1].import.len()
-            {
-                let mut candidates: Vec<Rc<Template<N>>> = templates
-                    .iter()
-                    .take_while(|t| {
-                        t.priority == templates[0].priority
-                            && t.import.len() == templates[0].import.len()
-                    })
-                    .cloned()
-                    .collect();
-                candidates.sort_unstable_by(|a, b| {
-                    a.document_order.map_or(Ordering::Greater, |v| {
-                        b.document_order.map_or(Ordering::Less, |u| v.cmp(&u))
-                    })
-                });
-                candidates.last().unwrap().clone()
-            } else {
-                templates[0].clone()
-            }
-        } else {
-            templates[0].clone()
-        };
-        // Create a new context using the current templates, then evaluate the highest priority and highest import precedence
-        let mut u = ContextBuilder::from(ctxt)
-            .context(vec![i.clone()])
-            .previous_context(Some(i.clone()))
-            .current_templates(templates)
-            .build()
-            .dispatch(stctxt, &matching.body)?;
-        result.append(&mut u);
-        Ok(result)
-    })
+    let depth = ctxt.deeper(stctxt)?;
+    let size = seq.len();
+    seq.iter()
+        .enumerate()
+        .try_fold(vec![], |mut result, (p, i)| {
+            let mut u = apply_template_to(ctxt, stctxt, i, p + 1, size, m, depth)?;
+            result.append(&mut u);
+            Ok(result)
+        })
+}
+
+/// Apply the matching template to one item of a selection.
+/// The item is at position p of a selection of the given size, and the templates are evaluated at the given depth.
+pub(crate) fn apply_template_to<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    i: &Item<N>,
+    p: usize,
+    size: usize,
+    m: &Option<QualifiedName>,
+    depth: usize,
+) -> Result<Sequence<N>, Error> {
+    let templates = ctxt.find_templates(stctxt, i, m)?;
+    // If there are two or more templates with the same priority and import level, then take the one that has the higher document order
+    let matching = ctxt.select_template(stctxt, i, &templates)?;
+    // Create a new context using the current templates, then evaluate the highest priority and highest import precedence
+    ContextBuilder::from(ctxt)
+        .context(vec![i.clone()])
+        .position(p, size)
+        .previous_context(Some(i.clone()))
+        .current_templates(templates)
+        .depth(depth)
+        .build()
+        .dispatch(stctxt, &matching.body)
 }
 
 /// Apply template with a higher import precedence.
@@ -171,7 +185,7 @@ pub(crate) fn apply_imports<
             .build()
             .dispatch(stctxt, &next[0].body)
     } else {
-        Ok(vec![])
+        Ok(Sequence::empty())
     }
 }
 
@@ -191,6 +205,6 @@ pub(crate) fn next_match<
             .build()
             .dispatch(stctxt, &ctxt.current_templates[1].body)
     } else {
-        Ok(vec![])
+        Ok(Sequence::empty())
     }
 }
@@ -0,0 +1,231 @@
+//! Sequence types: instance of, cast as, castable as, and the constructor functions.
+
+use crate::item::{Item, Node, Sequence};
+use crate::qname::QualifiedName;
+use crate::transform::context::{Context, StaticContext};
+use crate::transform::{ItemType, SequenceType, Transform};
+use crate::value::{Value, XSNS};
+use crate::xdmerror::{Error, ErrorKind};
+use std::collections::HashMap;
+use std::rc::Rc;
+use url::Url;
+
+/// XPath instance of expression.
+pub(crate) fn instance_of<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    s: &Transform<N>,
+    t: &SequenceType,
+) -> Result<Sequence<N>, Error> {
+    let seq = ctxt.dispatch(stctxt, s)?;
+    let t = resolve_sequence_type(t, ctxt.namespaces_ref())?;
+    Ok(vec![Item::Value(Rc::new(Value::Boolean(t.matches(&seq))))])
+}
+
+/// XPath cast as expression.
+/// The value is atomized; if it is a node then its string value is cast as an untyped atomic value.
+pub(crate) fn cast<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    s: &Transform<N>,
+    t: &QualifiedName,
+    optional: bool,
+) -> Result<Sequence<N>, Error> {
+    let seq = ctxt.dispatch(stctxt, s)?;
+    cast_sequence(&seq, &type_name(ctxt, t)?, optional)
+}
+
+/// XPath castable as expression.
+/// Static errors, such as an unknown type, are still reported.
+pub(crate) fn castable<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    s: &Transform<N>,
+    t: &QualifiedName,
+    optional: bool,
+) -> Result<Sequence<N>, Error> {
+    let seq = ctxt.dispatch(stctxt, s)?;
+    let b = match cast_sequence(&seq, &type_name(ctxt, t)?, optional) {
+        Ok(_) => true,
+        Err(e) if matches!(e.kind, ErrorKind::StaticData | ErrorKind::NotImplemented) => {
+            return Err(e)
+        }
+        Err(_) => false,
+    };
+    Ok(vec![Item::Value(Rc::new(Value::Boolean(b)))])
+}
+
+/// A constructor function, such as xs:integer("5"). This is the same as casting the argument,
+/// except that the empty sequence is allowed.
+pub(crate) fn constructor<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    t: &QualifiedName,
+    args: &[Transform<N>],
+) -> Result<Sequence<N>, Error> {
+    match args {
+        [a] => cast_sequence(&ctxt.dispatch(stctxt, a)?, t, true),
+        _ => Err(Error::new_with_code(
+            ErrorKind::StaticBadFunction,
+            format!(
+                "constructor function {} takes one argument, not {}",
+                t,
+                args.len()
+            ),
+            Some(QualifiedName::new(None, None, "XPST0017")),
+        )),
+    }
+}
+
+fn cast_sequence<N: Node>(
+    seq: &Sequence<N>,
+    t: &QualifiedName,
+    optional: bool,
+) -> Result<Sequence<N>, Error> {
+    match seq.as_slice() {
+        [] if optional => Ok(vec![]),
+        [i] => {
+            let v = match i {
+                Item::Value(v) => v.cast_to(t)?,
+                _ => Value::UntypedAtomic(i.to_string()).cast_to(t)?,
+            };
+            Ok(vec![Item::Value(Rc::new(v))])
+        }
+        _ => Err(Error::new_with_code(
+            ErrorKind::TypeError,
+            format!("cannot cast a sequence of {} items to {}", seq.len(), t),
+            Some(QualifiedName::new(None, None, "XPTY0004")),
+        )),
+    }
+}
+
+/// Convert a sequence to a sequence type, using the function conversion rules.
+/// If the item type is atomic then nodes are atomized, untyped atomic values are cast to the type,
+/// and numeric values are promoted. The names of atomic types must already be resolved.
+pub(crate) fn coerce<N: Node>(seq: Sequence<N>, t: &SequenceType) -> Result<Sequence<N>, Error> {
+    let result = match t {
+        SequenceType::Items(ItemType::Atomic(qn), _) => seq
+            .into_iter()
+            .map(|i| {
+                let v = match i {
+                    Item::Value(v) => v,
+                    _ => Rc::new(Value::UntypedAtomic(i.to_string())),
+                };
+                if v.instance_of(qn) {
+                    Ok(Item::Value(v))
+                } else if matches!(*v, Value::UntypedAtomic(_)) || promotes(&v, qn) {
+                    v.cast_to(qn).map(|c| Item::Value(Rc::new(c)))
+                } else {
+                    Err(Error::new_with_code(
+                        ErrorKind::TypeError,
+                        format!(
+                            "a value of type {} does not match the required type {}",
+                            v.type_name(),
+                            t
+                        ),
+                        Some(QualifiedName::new(None, None, "XPTY0004")),
+                    ))
+                }
+            })
+            .collect::<Result<Sequence<N>, Error>>()?,
+        _ => seq,
+    };
+    if t.matches(&result) {
+        Ok(result)
+    } else {
+        Err(Error::new_with_code(
+            ErrorKind::TypeError,
+            format!(
+                "a sequence of {} items does not match the required type {}",
+                result.len(),
+                t
+            ),
+            Some(QualifiedName::new(None, None, "XPTY0004")),
+        ))
+    }
+}
+
+// Numeric type promotion: a decimal (including an integer) may be promoted to a float or a double,
+// and a float may be promoted to a double.
+fn promotes(v: &Value, t: &QualifiedName) -> bool {
+    match t.get_localname().as_str() {
+        "double" => v.instance_of(&xs_name("decimal")) || v.instance_of(&xs_name("float")),
+        "float" => v.instance_of(&xs_name("decimal")),
+        _ => false,
+    }
+}
+
+fn xs_name(local: &str) -> QualifiedName {
+    QualifiedName::new(Some(String::from(XSNS)), Some(String::from("xs")), local)
+}
+
+/// Resolve the names of atomic types in a sequence type.
+pub(crate) fn resolve_sequence_type(
+    t: &SequenceType,
+    namespaces: &Vec<HashMap<String, String>>,
+) -> Result<SequenceType, Error> {
+    match t {
+        SequenceType::Items(ItemType::Atomic(qn), o) => Ok(SequenceType::Items(
+            ItemType::Atomic(resolve_type_name(qn, namespaces)?),
+            *o,
+        )),
+        _ => Ok(t.clone()),
+    }
+}
+
+fn type_name<N: Node>(ctxt: &Context<N>, t: &QualifiedName) -> Result<QualifiedName, Error> {
+    resolve_type_name(t, ctxt.namespaces_ref())
+}
+
+/// Resolve the name of a type. The xs prefix is bound to the XML Schema namespace, unless it is declared otherwise.
+pub(crate) fn resolve_type_name(
+    t: &QualifiedName,
+    namespaces: &Vec<HashMap<String, String>>,
+) -> Result<QualifiedName, Error> {
+    if t.get_nsuri_ref().is_some() {
+        return Ok(t.clone());
+    }
+    let mut qn = t.clone();
+    match qn.resolve(namespaces) {
+        Ok(_) => Ok(qn),
+        Err(_) if t.get_prefix().as_deref() == Some("xs") => Ok(QualifiedName::new(
+            Some(String::from(XSNS)),
+            t.get_prefix(),
+            t.get_localname(),
+        )),
+        Err(e) => Err(e),
+    }
+}
+
+/// Is the name, resolved in the context, the name of a constructor function?
+pub(crate) fn is_constructor<N: Node>(
+    ctxt: &Context<N>,
+    qn: &QualifiedName,
+) -> Option<QualifiedName> {
+    if qn.get_prefix().is_none() && qn.get_nsuri_ref().is_none() {
+        return None;
+    }
+    type_name(ctxt, qn)
+        .ok()
+        .filter(|t| t.get_nsuri_ref() == Some(XSNS))
+}
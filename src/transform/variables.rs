@@ -1,11 +1,55 @@
 //! Support for variables.
 
-use crate::item::{Node, Sequence};
+use crate::item::{Item, Node, Sequence};
+use crate::qname::QualifiedName;
+use crate::transform::callable::missing_parameter;
 use crate::transform::context::{Context, ContextBuilder, StaticContext};
 use crate::transform::Transform;
 use crate::xdmerror::{Error, ErrorKind};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
 use url::Url;
 
+/// The global variables and parameters of a stylesheet.
+/// A global variable is evaluated when it is first referenced, and its value is kept for the rest of the transformation.
+/// This means that globals may be declared in any order, so long as they do not depend on themselves. See XSLT 9.5 and 9.9.
+#[derive(Clone, Debug)]
+pub(crate) struct Globals<N: Node> {
+    // The declarations. Each is the expression for the value of the variable, or the default value of the parameter,
+    // and whether it is a parameter.
+    pub(crate) decls: Rc<HashMap<String, (Transform<N>, bool)>>,
+    // The values of global parameters that have been supplied by the application
+    pub(crate) params: Rc<HashMap<String, Sequence<N>>>,
+    // The global context item. This is the document node of the principal source document,
+    // and is absent if the transformation does not have a source document.
+    pub(crate) item: Option<Item<N>>,
+    // The values that have been computed so far. None marks a variable whose value is being computed.
+    // The values are shared by all contexts derived from the same context.
+    values: Rc<RefCell<HashMap<String, Option<Sequence<N>>>>>,
+}
+
+impl<N: Node> Globals<N> {
+    pub(crate) fn new() -> Self {
+        Globals {
+            decls: Rc::new(HashMap::new()),
+            params: Rc::new(HashMap::new()),
+            item: None,
+            values: Rc::new(RefCell::new(HashMap::new())),
+        }
+    }
+    /// The same globals, evaluated with a different global context item.
+    /// No values have been computed for the new context item.
+    pub(crate) fn for_item(&self, item: Option<Item<N>>) -> Self {
+        Globals {
+            decls: self.decls.clone(),
+            params: self.params.clone(),
+            item,
+            values: Rc::new(RefCell::new(HashMap::new())),
+        }
+    }
+}
+
 /// Declare a variable in a new scope and then evaluate the given transformation.
 /// Returns the result of the transformation.
 pub fn declare_variable<
@@ -25,7 +69,17 @@ pub fn declare_variable<
         .build()
         .dispatch(stctxt, f)
 }
-pub fn reference_variable<N: Node>(ctxt: &Context<N>, name: &String) -> Result<Sequence<N>, Error> {
+/// Get the value of a variable. A local variable hides a global variable with the same name.
+pub fn reference_variable<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    name: &String,
+) -> Result<Sequence<N>, Error> {
     match ctxt.vars.get(name) {
         Some(u) => match u.last() {
             Some(t) => Ok(t.clone()),
@@ -34,9 +88,112 @@ pub fn reference_variable<N: Node>(ctxt: &Context<N>, name: &String) -> Result<S
                 format!("variable \"{}\" is no longer in scope", name),
             )),
         },
-        None => Err(Error::new(
-            ErrorKind::Unknown,
-            format!("unknown variable \"{}\"", name),
-        )),
+        None => global_variable(ctxt, stctxt, name),
     }
 }
+
+/// Evaluate a transformation with tunnel parameters.
+/// The parameters are passed on to every template that is invoked by the transformation,
+/// and to the templates that those templates invoke, and so on. See XSLT 10.1.3.
+pub fn tunnel<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    params: &[(QualifiedName, Transform<N>)],
+    t: &Transform<N>,
+) -> Result<Sequence<N>, Error> {
+    let mut newctxt = ctxt.clone();
+    params.iter().try_for_each(|(name, value)| {
+        newctxt
+            .tunnel
+            .insert(name.clone(), ctxt.dispatch(stctxt, value)?);
+        Ok::<(), Error>(())
+    })?;
+    newctxt.dispatch(stctxt, t)
+}
+
+/// Get the value of a tunnel parameter of a template.
+/// If the parameter has not been passed by an invoking template, then its default value is used.
+/// It is an error if a required parameter has not been passed.
+pub fn tunnel_parameter<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    name: &QualifiedName,
+    dflt: &Option<Box<Transform<N>>>,
+) -> Result<Sequence<N>, Error> {
+    match (ctxt.tunnel.get(name), dflt) {
+        (Some(v), _) => Ok(v.clone()),
+        (None, Some(d)) => ctxt.dispatch(stctxt, d),
+        (None, None) => Err(missing_parameter(name)),
+    }
+}
+
+// Get the value of a global variable or parameter, evaluating it if this is the first reference.
+fn global_variable<
+    N: Node,
+    F: FnMut(&str) -> Result<(), Error>,
+    G: FnMut(&str) -> Result<N, Error>,
+    H: FnMut(&Url) -> Result<String, Error>,
+>(
+    ctxt: &Context<N>,
+    stctxt: &mut StaticContext<N, F, G, H>,
+    name: &str,
+) -> Result<Sequence<N>, Error> {
+    let globals = &ctxt.globals;
+    let (value, param) = globals
+        .decls
+        .get(name)
+        .ok_or_else(|| Error::new(ErrorKind::Unknown, format!("unknown variable \"{}\"", name)))?;
+    if *param {
+        if let Some(v) = globals.params.get(name) {
+            return Ok(v.clone());
+        }
+    }
+    match globals.values.borrow().get(name) {
+        Some(Some(v)) => return Ok(v.clone()),
+        Some(None) => {
+            return Err(Error::new_with_code(
+                ErrorKind::DynamicAbsent,
+                format!("circular reference to global variable \"{}\"", name),
+                Some(QualifiedName::new(None, None, "XTDE0640")),
+            ))
+        }
+        None => {}
+    }
+    globals.values.borrow_mut().insert(name.to_string(), None);
+    let result = global_context(ctxt).dispatch(stctxt, value);
+    match &result {
+        Ok(v) => globals
+            .values
+            .borrow_mut()
+            .insert(name.to_string(), Some(v.clone())),
+        Err(_) => globals.values.borrow_mut().remove(name),
+    };
+    result
+}
+
+// The context for evaluating a global variable.
+// The context item is the global context item, and the context position and size are absent.
+// Local variables, the current template and the current group are not in scope.
+fn global_context<N: Node>(ctxt: &Context<N>) -> Context<N> {
+    let item = ctxt.globals.item.clone();
+    let mut g = ContextBuilder::from(ctxt)
+        .context(item.clone().into_iter().collect())
+        .previous_context(item)
+        .variables(HashMap::new())
+        .current_templates(vec![])
+        .current_group(vec![])
+        .build();
+    g.current_grouping_key = None;
+    g.global = true;
+    g
+}
@@ -0,0 +1,158 @@
+//! Whitespace stripping.
+//!
+//! Whitespace-only text nodes are removed from a tree under the direction of xsl:strip-space and xsl:preserve-space.
+//! This is used both for stylesheet modules and for source documents.
+
+use crate::item::{Node, NodeType};
+use crate::qname::QualifiedName;
+use crate::transform::{KindTest, NodeTest, WildcardOrName};
+use crate::xdmerror::Error;
+
+/// Strip whitespace nodes from a XDM tree.
+/// See [XSLT 4.3](https://www.w3.org/TR/2017/REC-xslt-30-20170608/#stylesheet-stripping).
+/// The [Node] argument must be the document node of the tree.
+pub fn strip_whitespace<N: Node>(
+    t: N,
+    cpi: bool, // strip comments and PIs?
+    strip: &Vec<NodeTest>,
+    preserve: &Vec<NodeTest>,
+) -> Result<(), Error> {
+    t.child_iter().try_for_each(|n| {
+        strip_whitespace_node(n, cpi, strip, preserve, true)?;
+        Ok(())
+    })?;
+    Ok(())
+}
+
+// TODO: the rules for stripping/preserving are a lot more complex
+// TODO: Return Result so that errors can be propagated
+fn strip_whitespace_node<N: Node>(
+    mut n: N,
+    cpi: bool, // strip comments and PIs?
+    strip: &Vec<NodeTest>,
+    preserve: &Vec<NodeTest>,
+    keep: bool,
+) -> Result<(), Error> {
+    match n.node_type() {
+        NodeType::Comment | NodeType::ProcessingInstruction => {
+            if cpi {
+                n.pop()?;
+                // TODO: Merge text nodes that are now adjacent
+            }
+        }
+        NodeType::Element => {
+            // Determine if this element toggles the strip/preserve setting
+            // Match a strip NodeTest or a preserve NodeTest
+            // The 'strength' of the match determines which setting wins
+            let mut ss = -1.0;
+            let mut ps = -1.0;
+            strip.iter().for_each(|t| match t {
+                NodeTest::Kind(KindTest::Any) | NodeTest::Kind(KindTest::Element) => ss = -0.5,
+                NodeTest::Name(nt) => match (nt.ns.as_ref(), nt.name.as_ref()) {
+                    (None, Some(WildcardOrName::Wildcard)) => {
+                        ss = -0.25;
+                    }
+                    (None, Some(WildcardOrName::Name(name))) => {
+                        match (n.name().get_nsuri(), n.name().get_localname()) {
+                            (Some(_), _) => {}
+                            (None, ename) => {
+                                if *name == ename {
+                                    ss = 0.5;
+                                }
+                            }
+                        }
+                    }
+                    (Some(WildcardOrName::Name(ns)), Some(WildcardOrName::Name(name))) => {
+                        match (n.name().get_nsuri(), n.name().get_localname()) {
+                            (Some(ens), ename) => {
+                                if *ns == ens && *name == ename {
+                                    ss = 0.5;
+                                }
+                            }
+                            (None, ename) => {
+                                if *name == ename {
+                                    ss = 0.5;
+                                }
+                            }
+                        }
+                    }
+                    (Some(WildcardOrName::Wildcard), Some(WildcardOrName::Name(_))) => {
+                        ss = -0.25;
+                    }
+                    (Some(WildcardOrName::Name(_)), Some(WildcardOrName::Wildcard)) => {
+                        ss = -0.25;
+                    }
+                    (Some(WildcardOrName::Wildcard), Some(WildcardOrName::Wildcard)) => {
+                        ss = -0.5;
+                    }
+                    _ => {}
+                },
+                _ => {}
+            });
+            preserve.iter().for_each(|t| match t {
+                NodeTest::Kind(KindTest::Any) | NodeTest::Kind(KindTest::Element) => ps = -0.5,
+                NodeTest::Name(nt) => match (nt.ns.as_ref(), nt.name.as_ref()) {
+                    (None, Some(WildcardOrName::Name(name))) => {
+                        match (n.name().get_nsuri(), n.name().get_localname()) {
+                            (Some(_), _) => {}
+                            (None, ename) => {
+                                if *name == ename {
+                                    ps = 0.5;
+                                }
+                            }
+                        }
+                    }
+                    (Some(WildcardOrName::Name(ns)), Some(WildcardOrName::Name(name))) => {
+                        match (n.name().get_nsuri(), n.name().get_localname()) {
+                            (Some(ens), ename) => {
+                                if *ns == ens && *name == ename {
+                                    ps = 0.5;
+                                }
+                            }
+                            (None, ename) => {
+                                if *name == ename {
+                                    ps = 0.5;
+                                }
+                            }
+                        }
+                    }
+                    (Some(WildcardOrName::Wildcard), Some(WildcardOrName::Name(_))) => {
+                        ps = -0.25;
+                    }
+                    (Some(WildcardOrName::Name(_)), Some(WildcardOrName::Wildcard)) => {
+                        ps = -0.25;
+                    }
+                    (Some(WildcardOrName::Wildcard), Some(WildcardOrName::Wildcard)) => {
+                        ps = -0.5;
+                    }
+                    _ => {}
+                },
+                _ => {}
+            });
+            // xml:space='preserve' keeps white space, regardless of the strip and preserve settings
+            let space = n.get_attribute(&QualifiedName::new(
+                Some(String::from("http://www.w3.org/XML/1998/namespace")),
+                None,
+                "space",
+            ));
+            let keep_children = if space.to_string() == "preserve" {
+                true
+            } else if ss > -1.0 {
+                ps >= ss
+            } else if ps > -1.0 {
+                true
+            } else {
+                keep
+            };
+            n.child_iter()
+                .try_for_each(|m| strip_whitespace_node(m, cpi, strip, preserve, keep_children))?
+        }
+        NodeType::Text => {
+            if n.to_string().trim().is_empty() && !keep {
+                n.pop()?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
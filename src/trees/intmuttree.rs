@@ -9,11 +9,9 @@ To create a tree, use [NodeBuilder](crate::trees::intmuttree::NodeBuilder) to ma
 NB. The Item module's Node trait is implemented for Rc\<intmuttree::Node\>. For convenience, this is defined as the type [RNode](crate::trees::intmuttree::RNode).
 
 ```rust
-use std::rc::Rc;
 use xrust::trees::intmuttree::{Document, NodeBuilder, RNode};
 use xrust::item::{Node, NodeType};
 use xrust::qname::QualifiedName;
-use xrust::value::Value;
 use xrust::xdmerror::Error;
 
 pub(crate) type ExtDTDresolver = fn(Option<String>, String) -> Result<String, Error>;
@@ -31,7 +29,7 @@ doc.push(top.clone())
 
 top.push(
     NodeBuilder::new(NodeType::Text)
-    .value(Rc::new(Value::from("content of the element")))
+    .value("content of the element")
     .build()
 ).expect("unable to append child node");
 
@@ -40,9 +38,9 @@ assert_eq!(doc.to_xml(), "<Top-Level>content of the element</Top-Level>")
 
 use crate::externals::URLResolver;
 use crate::item::{Node as ItemNode, NodeType};
-use crate::output::OutputDefinition;
-use crate::parser::xml::parse;
-use crate::parser::ParserConfig;
+use crate::output::{comment_content, OutputDefinition};
+use crate::parser::xml::parse_with_options;
+use crate::parser::ParseOptions;
 use crate::qname::QualifiedName;
 use crate::value::Value;
 use crate::xdmerror::*;
@@ -172,11 +170,15 @@ impl Document {
 impl TryFrom<(String, Option<URLResolver>, Option<String>)> for Document {
     type Error = Error;
     fn try_from(s: (String, Option<URLResolver>, Option<String>)) -> Result<Self, Self::Error> {
-        let mut pc = ParserConfig::new();
-        pc.ext_dtd_resolver = s.1;
-        pc.docloc = s.2;
+        let mut options = ParseOptions::new();
+        if let Some(r) = s.1 {
+            options = options.resolver(r)
+        }
+        if let Some(l) = s.2 {
+            options = options.docloc(l)
+        }
         let doc = NodeBuilder::new(NodeType::Document).build();
-        parse(doc.clone(), s.0.as_str(), Some(pc))?;
+        parse_with_options(doc.clone(), s.0.as_str(), options)?;
         let result = DocumentBuilder::new().content(vec![doc]).build();
         Ok(result)
     }
@@ -184,11 +186,15 @@ impl TryFrom<(String, Option<URLResolver>, Option<String>)> for Document {
 impl TryFrom<(&str, Option<URLResolver>, Option<String>)> for Document {
     type Error = Error;
     fn try_from(s: (&str, Option<URLResolver>, Option<String>)) -> Result<Self, Self::Error> {
-        let mut pc = ParserConfig::new();
-        pc.ext_dtd_resolver = s.1;
-        pc.docloc = s.2;
+        let mut options = ParseOptions::new();
+        if let Some(r) = s.1 {
+            options = options.resolver(r)
+        }
+        if let Some(l) = s.2 {
+            options = options.docloc(l)
+        }
         let doc = NodeBuilder::new(NodeType::Document).build();
-        parse(doc.clone(), s.0, Some(pc))?;
+        parse_with_options(doc.clone(), s.0, options)?;
         let result = DocumentBuilder::new().content(vec![doc]).build();
         Ok(result)
     }
@@ -337,7 +343,7 @@ impl ItemNode for RNode {
     }
 
     fn get_id(&self) -> String {
-        format!("{:p}", &**self as *const Node)
+        format!("n{:x}", &**self as *const Node as usize)
     }
 
     fn to_string(&self) -> String {
@@ -358,11 +364,11 @@ impl ItemNode for RNode {
     }
     /// Serialise as XML
     fn to_xml(&self) -> String {
-        to_xml_int(self, &OutputDefinition::new(), vec![], 0)
+        to_xml_int(self, &OutputDefinition::new(), vec![], 2)
     }
     /// Serialise the node as XML, with options such as indentation.
     fn to_xml_with_options(&self, od: &OutputDefinition) -> String {
-        to_xml_int(self, od, vec![], 0)
+        to_xml_int(self, od, vec![], 2)
     }
 
     fn is_same(&self, other: &Self) -> bool {
@@ -430,44 +436,54 @@ impl ItemNode for RNode {
     fn attribute_iter(&self) -> Self::NodeIterator {
         Box::new(Attributes::new(self))
     }
+    /// Namespace nodes are not supported, so the namespace declarations are the xmlns pseudo-attributes created by new_namespace.
+    fn namespace_iter(&self) -> Self::NodeIterator {
+        Box::new(
+            self.attribute_iter()
+                .filter(|a| xmlns_prefix(&a.name()).is_some()),
+        )
+    }
     fn get_attribute(&self, a: &QualifiedName) -> Rc<Value> {
         self.attributes
             .borrow()
             .get(a)
-            .map_or(Rc::new(Value::from("")), |v| {
-                v.value.as_ref().unwrap().clone()
-            })
+            .map_or(Rc::new(Value::from(String::new())), |v| v.value())
     }
     fn get_attribute_node(&self, a: &QualifiedName) -> Option<RNode> {
-        self.attributes
-            .borrow()
-            .get(a)
-            .map_or(None, |v| Some(v.clone()))
+        self.attributes.borrow().get(a).cloned()
     }
 
     fn new_element(&self, qn: QualifiedName) -> Result<Self, Error> {
         Ok(NodeBuilder::new(NodeType::Element).name(qn).build())
     }
     fn new_text(&self, v: Rc<Value>) -> Result<Self, Error> {
-        Ok(NodeBuilder::new(NodeType::Text).value(v).build())
+        Ok(NodeBuilder::new(NodeType::Text).shared_value(v).build())
     }
     fn new_attribute(&self, qn: QualifiedName, v: Rc<Value>) -> Result<Self, Error> {
         Ok(NodeBuilder::new(NodeType::Attribute)
             .name(qn)
-            .value(v)
+            .shared_value(v)
             .build())
     }
     fn new_comment(&self, v: Rc<Value>) -> Result<Self, Error> {
-        Ok(NodeBuilder::new(NodeType::Comment).value(v).build())
+        Ok(NodeBuilder::new(NodeType::Comment).shared_value(v).build())
     }
     fn new_processing_instruction(&self, qn: QualifiedName, v: Rc<Value>) -> Result<Self, Error> {
         Ok(NodeBuilder::new(NodeType::ProcessingInstruction)
             .name(qn)
-            .value(v)
+            .shared_value(v)
             .build())
     }
-    fn new_namespace(&self, _ns: String, _prefix: Option<String>) -> Result<Self, Error> {
-        Err(Error::new(ErrorKind::NotImplemented, "not supported"))
+    /// Namespace nodes are not supported, so a namespace declaration is an xmlns pseudo-attribute.
+    fn new_namespace(&self, ns: String, prefix: Option<String>) -> Result<Self, Error> {
+        let qn = match prefix {
+            Some(p) => QualifiedName::new(None, Some(String::from("xmlns")), p),
+            None => QualifiedName::new(None, None, String::from("xmlns")),
+        };
+        Ok(NodeBuilder::new(NodeType::Attribute)
+            .name(qn)
+            .value(ns)
+            .build())
     }
 
     /// Append a node to the child list
@@ -491,6 +507,12 @@ impl ItemNode for RNode {
                 String::from("unable to insert before: node is an orphan"),
             )
         })?;
+        if self.node_type == NodeType::Attribute {
+            // This includes namespace declarations, which are attributes
+            parent.attributes.borrow_mut().remove(&self.name());
+            *self.parent.borrow_mut() = None;
+            return Ok(());
+        }
         let idx = find_index(&parent, self)?;
         parent.children.borrow_mut().remove(idx);
         Ok(())
@@ -545,26 +567,23 @@ impl ItemNode for RNode {
     fn shallow_copy(&self) -> Result<Self, Error> {
         Ok(NodeBuilder::new(self.node_type())
             .name(self.name())
-            .value(self.value())
+            .shared_value(self.value())
             .build())
     }
 
     /// Deep copy the node. Returned node is unattached.
     fn deep_copy(&self) -> Result<Self, Error> {
-        let mut result = NodeBuilder::new(self.node_type())
-            .name(self.name())
-            .value(self.value())
-            .build();
-
-        self.attribute_iter().try_for_each(|a| {
-            result.add_attribute(a.deep_copy()?)?;
-            Ok::<(), Error>(())
-        })?;
-
-        self.child_iter().try_for_each(|c| {
-            result.push(c.deep_copy()?)?;
-            Ok::<(), Error>(())
-        })?;
+        // The descendants are copied using an explicit stack, rather than recursion
+        let result = copy_with_attributes(self)?;
+        let mut stack = vec![(self.clone(), result.clone())];
+        while let Some((src, mut dest)) = stack.pop() {
+            src.child_iter().try_for_each(|c| {
+                let cp = copy_with_attributes(&c)?;
+                dest.push(cp.clone())?;
+                stack.push((c, cp));
+                Ok::<(), Error>(())
+            })?;
+        }
 
         Ok(result)
     }
@@ -579,14 +598,14 @@ impl ItemNode for RNode {
                 }
                 let result = NodeBuilder::new(self.node_type())
                     .name(self.name())
-                    .value(v)
+                    .shared_value(v)
                     .build();
                 Ok(result)
             }
             _ => {
                 let mut result = NodeBuilder::new(self.node_type())
                     .name(self.name())
-                    .value(self.value())
+                    .shared_value(self.value())
                     .build();
 
                 self.attribute_iter().try_for_each(|a| {
@@ -613,8 +632,15 @@ impl ItemNode for RNode {
         ))
     }
 
-    fn add_namespace(&self, _ns: Self) -> Result<(), Error> {
-        todo!()
+    /// Add a namespace declaration, i.e. an xmlns pseudo-attribute created by new_namespace.
+    fn add_namespace(&self, ns: Self) -> Result<(), Error> {
+        match ns.node_type {
+            NodeType::Attribute if xmlns_prefix(&ns.name()).is_some() => self.add_attribute(ns),
+            _ => Err(Error::new(
+                ErrorKind::TypeError,
+                String::from("not a namespace declaration"),
+            )),
+        }
     }
 }
 
@@ -651,166 +677,248 @@ fn doc_order(n: &RNode) -> Vec<usize> {
             }
         }
         NodeType::Attribute => {
-            // Namespace nodes are first, then attributes
-            let mut a = doc_order(&n.parent().unwrap());
+            // Namespace nodes are first, then attributes, which are ordered amongst themselves by name
+            let p = n.parent().unwrap();
+            let idx = n.name.borrow().as_ref().map_or(0, |qn| {
+                p.attributes.borrow().keys().filter(|k| *k < qn).count()
+            });
+            let mut a = doc_order(&p);
             a.push(2);
+            a.push(idx);
             a
         }
         _ => vec![0],
     }
 }
 
+// Copy a node, along with its attributes, but not its children
+fn copy_with_attributes(n: &RNode) -> Result<RNode, Error> {
+    let result = n.shallow_copy()?;
+    n.attribute_iter().try_for_each(|a| {
+        result.add_attribute(a.shallow_copy()?)?;
+        Ok::<(), Error>(())
+    })?;
+    Ok(result)
+}
+
+// A step in the serialisation of a tree.
+// The tree is serialised using an explicit stack, so that deeply nested documents do not overflow the call stack.
+enum XmlStep {
+    // Serialise a node. "ns" is the list of XML Namespaces that have been declared in an ancestor: (URI, prefix).
+    // "indent" is the current level of identation, which is that of the children of an element.
+    Node(RNode, Rc<Vec<(String, Option<String>)>>, usize),
+    // Literal output, such as the spacing between the children of an element.
+    Literal(String),
+    // The end tag of an element.
+    End(String, bool, usize),
+}
+
 // This handles the XML serialisation of the document.
-// "ns" is the list of XML Namespaces that have been declared in an ancestor: (URI, prefix).
-// "indent" is the current level of identation.
 fn to_xml_int(
     node: &RNode,
     od: &OutputDefinition,
     ns: Vec<(String, Option<String>)>,
     indent: usize,
 ) -> String {
-    match node.node_type {
-        NodeType::Document => node
-            .children
-            .borrow()
-            .iter()
-            .fold(String::new(), |mut result, c| {
-                result.push_str(to_xml_int(c, od, ns.clone(), indent + 2).as_str());
-                result
-            }),
-        NodeType::Element => {
-            let mut result = String::from("<");
-            // Elements must have a name, so unpack it
-            let qn = node.name.borrow().as_ref().unwrap().clone();
-            result.push_str(qn.to_string().as_str());
-
-            // Check if any XML Namespaces need to be declared
-            // newns is a vector of (prefix, namespace URI) pairs
-            let mut declared = ns.clone();
-            let mut newns: Vec<(String, Option<String>)> = vec![];
-            // First, the element itself
-            namespace_check(&qn, &declared).iter().for_each(|m| {
-                newns.push(m.clone());
-                declared.push(m.clone())
-            });
-            // Next, it's attributes
-            node.attributes.borrow().iter().for_each(|(k, _)| {
-                namespace_check(k, &declared).iter().for_each(|m| {
-                    newns.push(m.clone());
-                    declared.push(m.clone())
-                })
-            });
-            // Finally, it's child elements
-            node.child_iter()
-                .filter(|c| c.node_type == NodeType::Element)
-                .for_each(|c| {
-                    c.name.borrow().as_ref().map(|d| {
-                        namespace_check(d, &declared).iter().for_each(|m| {
-                            newns.push(m.clone());
-                            declared.push(m.clone())
+    let mut result = String::new();
+    let mut stack = vec![XmlStep::Node(node.clone(), Rc::new(ns), indent)];
+    while let Some(step) = stack.pop() {
+        match step {
+            XmlStep::Node(n, ns, indent) => match n.node_type {
+                NodeType::Document => {
+                    // If the indent option is enabled, then each top-level node is on its own line.
+                    n.children
+                        .borrow()
+                        .iter()
+                        .enumerate()
+                        .rev()
+                        .for_each(|(i, c)| {
+                            stack.push(XmlStep::Node(c.clone(), ns.clone(), indent));
+                            if od.get_indent() && i > 0 {
+                                stack.push(XmlStep::Literal(String::from("\n")))
+                            }
                         })
-                    });
-                });
-            newns.iter().for_each(|(u, p)| {
-                result.push_str(" xmlns");
-                if let Some(q) = p {
-                    result.push(':');
-                    result.push_str(q.as_str());
                 }
-                result.push_str("='");
-                result.push_str(u);
-                result.push('\'');
-            });
-
-            node.attributes
-                .borrow()
-                .iter()
-                .for_each(|(k, v)| result.push_str(format!(" {}='{}'", k, v.value()).as_str()));
-            result.push('>');
-
-            // Content of the element.
-            // If the indent option is enabled, then if no child is a text node then add spacing.
-            let do_indent: bool = od
-                .get_indent()
-                .then(|| {
-                    node.child_iter().fold(true, |mut acc, c| {
-                        if acc && c.node_type == NodeType::Text {
-                            acc = false
+                NodeType::Element => {
+                    let (name, declared) = start_tag(&n, &ns, od, &mut result);
+
+                    // Content of the element.
+                    // If the indent option is enabled, then if there are children and no child is a text node then add spacing.
+                    // Comments and processing instructions are then on their own lines, but stay inline in mixed content.
+                    let do_indent: bool = od.get_indent()
+                        && !n.children.borrow().is_empty()
+                        && n.children
+                            .borrow()
+                            .iter()
+                            .all(|c| c.node_type != NodeType::Text);
+
+                    stack.push(XmlStep::End(name, do_indent, indent));
+                    let declared = Rc::new(declared);
+                    n.children.borrow().iter().rev().for_each(|c| {
+                        stack.push(XmlStep::Node(c.clone(), declared.clone(), indent + 2));
+                        if do_indent {
+                            let mut spacing = String::from("\n");
+                            (0..indent).for_each(|_| spacing.push(' '));
+                            stack.push(XmlStep::Literal(spacing))
                         }
-                        acc
-                    })
-                })
-                .map_or(false, |b| b);
-
-            node.children.borrow().iter().for_each(|c| {
-                if do_indent {
+                    });
+                }
+                NodeType::Text => result.push_str(n.value().to_string().as_str()),
+                NodeType::Comment => {
+                    result.push_str("<!--");
+                    let s = n.value.as_ref().map_or("".to_string(), |n| n.to_string());
+                    result.push_str(comment_content(s.as_str()).as_str());
+                    result.push_str("-->");
+                }
+                NodeType::ProcessingInstruction => {
+                    result.push_str("<?");
+                    let s = n
+                        .name
+                        .borrow()
+                        .as_ref()
+                        .map_or("".to_string(), |n| n.to_string());
+                    result.push_str(s.as_str());
+                    result.push(' ');
+                    let t = n.value.clone().map_or("".to_string(), |n| n.to_string());
+                    result.push_str(t.as_str());
+                    result.push_str("?>");
+                }
+                // An attribute that is not part of an element, i.e. at the top level of the result, is its string value
+                NodeType::Attribute => result.push_str(n.value().to_string().as_str()),
+                _ => {}
+            },
+            XmlStep::Literal(l) => result.push_str(l.as_str()),
+            XmlStep::End(name, do_indent, indent) => {
+                if do_indent && indent > 1 {
                     result.push('\n');
-                    (0..indent).for_each(|_| result.push(' '))
+                    (0..(indent - 2)).for_each(|_| result.push(' '))
                 }
-                result.push_str(to_xml_int(c, od, newns.clone(), indent + 2).as_str())
-            });
-            if do_indent && indent > 1 {
-                result.push('\n');
-                (0..(indent - 2)).for_each(|_| result.push(' '))
+                result.push_str("</");
+                result.push_str(name.as_str());
+                result.push('>');
             }
-            result.push_str("</");
-            result.push_str(
-                node.name
-                    .borrow()
-                    .as_ref()
-                    .map_or(String::new(), |n| n.to_string())
-                    .as_str(),
-            );
-            result.push('>');
-            result
-        }
-        NodeType::Text => node.value().to_string(),
-        NodeType::Comment => {
-            let mut result = String::from("<!--");
-            let s = node
-                .value
-                .as_ref()
-                .map_or("".to_string(), |n| n.to_string());
-            result.push_str(s.as_str());
-            result.push_str("-->");
-            result
-        }
-        NodeType::ProcessingInstruction => {
-            let mut result = String::from("<?");
-            let s = node
-                .name
-                .borrow()
-                .as_ref()
-                .map_or("".to_string(), |n| n.to_string());
-            result.push_str(s.as_str());
-            result.push(' ');
-            let t = node.value.clone().map_or("".to_string(), |n| n.to_string());
-            result.push_str(t.as_str());
-            result.push_str("?>");
-            result
         }
-        _ => String::new(),
     }
+    od.normalize(result)
 }
 
-// Checks if this node's name is in a namespace that has already been declared.
-// Returns a namespace to be declared if required, (URI, prefix).
-fn namespace_check(
-    qn: &QualifiedName,
-    ns: &Vec<(String, Option<String>)>,
-) -> Option<(String, Option<String>)> {
-    let mut result = None;
-    if let Some(qnuri) = qn.get_nsuri_ref() {
-        // Has this namespace already been declared?
-        if ns.iter().find(|(u, _)| u == qnuri).is_some() {
-            // Namespace has been declared, but with the same prefix?
-            // TODO: see forest.rs for example implementation
-        } else {
-            // Namespace has not been declared, so this element must declare it
-            result = Some((qnuri.to_string(), qn.get_prefix()))
+// Serialise the start tag of an element, including any XML Namespace declarations that are needed.
+// Returns the element's serialised name and the XML Namespaces that are in scope for its content.
+fn start_tag(
+    node: &RNode,
+    ns: &[(String, Option<String>)],
+    od: &OutputDefinition,
+    result: &mut String,
+) -> (String, Vec<(String, Option<String>)>) {
+    result.push('<');
+    // Elements must have a name, so unpack it
+    let qn = node.name.borrow().as_ref().unwrap().clone();
+
+    // Check if any XML Namespaces need to be declared
+    // newns is a vector of (namespace URI, prefix) pairs
+    let mut declared = ns.to_vec();
+    let mut newns: Vec<(String, Option<String>)> = vec![];
+    // First, any namespace declarations that have been added as xmlns pseudo-attributes.
+    // These are declared in order of namespace URI, then prefix, as for the namespace nodes of a smite tree.
+    let mut xmlns: Vec<(String, Option<String>)> = node
+        .attributes
+        .borrow()
+        .iter()
+        .filter_map(|(k, v)| xmlns_prefix(k).map(|p| (v.value().to_string(), p)))
+        .collect();
+    xmlns.sort();
+    // When regenerating prefixes, a declaration of a prefix that is bound to a different URI is dropped.
+    // Names in that namespace are given an invented prefix instead.
+    let regenerate = od.get_regenerate_prefixes();
+    xmlns.into_iter().for_each(|(u, p)| {
+        let collides = p.is_some() && in_scope(&declared, &p).is_some_and(|v| !v.is_empty());
+        if in_scope(&declared, &p).unwrap_or("") != u && !(regenerate && collides) {
+            declare((u, p), &mut declared, &mut newns)
+        }
+    });
+    // Next, the element itself
+    let (name, m) = qn.serialize_in(&declared, false, od);
+    if let Some(m) = m {
+        declare(m, &mut declared, &mut newns)
+    }
+    result.push_str(name.as_str());
+    // Next, it's attributes.
+    // An attribute in a namespace must have a prefix, so one may have to be invented.
+    // The attributes are taken in order of name, so that invented prefixes are the same every time.
+    let mut atts: Vec<(String, String)> = vec![];
+    let mut attributes: Vec<(QualifiedName, RNode)> = node
+        .attributes
+        .borrow()
+        .iter()
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+    attributes.sort_by(|(a, _), (b, _)| a.cmp(b));
+    attributes.iter().for_each(|(k, v)| {
+        if xmlns_prefix(k).is_some() {
+            return;
+        }
+        let (name, m) = k.serialize_in(&declared, true, od);
+        if let Some(m) = m {
+            declare(m, &mut declared, &mut newns)
+        }
+        atts.push((name, v.value().to_string()))
+    });
+    // Finally, it's child elements that have a prefix that is not yet declared
+    node.child_iter()
+        .filter(|c| c.node_type == NodeType::Element)
+        .for_each(|c| {
+            if let Some(d) = c.name.borrow().as_ref() {
+                if d.get_prefix().is_some() && in_scope(&declared, &d.get_prefix()).is_none() {
+                    if let (_, Some(m)) = d.serialize_in(&declared, false, od) {
+                        declare(m, &mut declared, &mut newns)
+                    }
+                }
+            }
+        });
+    newns.iter().for_each(|(u, p)| {
+        result.push_str(" xmlns");
+        if let Some(q) = p {
+            result.push(':');
+            result.push_str(q.as_str());
         }
+        result.push_str("='");
+        result.push_str(u);
+        result.push('\'');
+    });
+
+    atts.iter()
+        .for_each(|(k, v)| result.push_str(format!(" {}='{}'", k, v).as_str()));
+    result.push('>');
+
+    (name, declared)
+}
+
+// The namespace URI currently bound to a prefix (None is the default namespace).
+fn in_scope<'a>(ns: &'a [(String, Option<String>)], prefix: &Option<String>) -> Option<&'a str> {
+    ns.iter()
+        .rev()
+        .find(|(_, p)| p == prefix)
+        .map(|(u, _)| u.as_str())
+}
+
+// Record a namespace declaration for an element.
+fn declare(
+    m: (String, Option<String>),
+    declared: &mut Vec<(String, Option<String>)>,
+    newns: &mut Vec<(String, Option<String>)>,
+) {
+    newns.retain(|(_, p)| *p != m.1);
+    newns.push(m.clone());
+    declared.push(m);
+}
+
+// If this is the name of a namespace declaration pseudo-attribute, return the prefix being declared.
+fn xmlns_prefix(qn: &QualifiedName) -> Option<Option<String>> {
+    match qn.get_prefix().as_deref() {
+        Some("xmlns") => Some(Some(qn.get_localname())),
+        None if qn.get_nsuri_ref().is_none() && qn.get_localname() == "xmlns" => Some(None),
+        _ => None,
     }
-    result
 }
 
 // Find the position of this node in the parent's child list.
@@ -894,45 +1002,25 @@ impl Iterator for Ancestors {
     }
 }
 
-// This implementation eagerly constructs a list of nodes
-// to traverse.
-// An alternative would be to lazily traverse the descendants.
-// Also, rewrite this iterator in terms of child_iter.
+// The stack holds the nodes that are yet to be visited, with the next node on top.
 pub struct Descendants {
-    v: Vec<RNode>,
-    cur: usize,
+    stack: Vec<RNode>,
 }
 impl Descendants {
     fn new(n: &RNode) -> Self {
+        // Traverse lazily in document order, using an explicit stack rather than recursion
         Descendants {
-            v: n.children.borrow().iter().fold(vec![], |mut acc, c| {
-                let mut d = descendant_add(c);
-                acc.append(&mut d);
-                acc
-            }),
-            cur: 0,
+            stack: n.children.borrow().iter().rev().cloned().collect(),
         }
     }
 }
-fn descendant_add(n: &RNode) -> Vec<RNode> {
-    let mut result = vec![n.clone()];
-    n.children.borrow().iter().for_each(|c| {
-        let mut l = descendant_add(c);
-        result.append(&mut l);
-    });
-    result
-}
 impl Iterator for Descendants {
     type Item = RNode;
 
     fn next(&mut self) -> Option<RNode> {
-        match self.v.get(self.cur) {
-            Some(n) => {
-                self.cur += 1;
-                Some(n.clone())
-            }
-            None => None,
-        }
+        let n = self.stack.pop()?;
+        self.stack.extend(n.children.borrow().iter().rev().cloned());
+        Some(n)
     }
 }
 
@@ -1011,7 +1099,12 @@ impl NodeBuilder {
         *self.0.name.borrow_mut() = Some(qn);
         self
     }
-    pub fn value(mut self, v: Rc<Value>) -> Self {
+    pub fn value(mut self, v: impl Into<Value>) -> Self {
+        self.0.value = Some(Rc::new(v.into()));
+        self
+    }
+    /// Set the value of the node to a value that is shared with another node.
+    pub fn shared_value(mut self, v: Rc<Value>) -> Self {
         self.0.value = Some(v);
         self
     }
@@ -1174,6 +1267,9 @@ pub enum DTDDecl {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::parser::xml::parse;
+
+    const XML_NAMESPACE: &str = "http://www.w3.org/XML/1998/namespace";
 
     #[test]
     fn new_push() {
@@ -1277,12 +1373,8 @@ mod tests {
                 .name(QualifiedName::new(None, None, String::from("Level1")))
                 .build();
             child.push(l1.clone()).expect("unable to append child");
-            l1.push(
-                NodeBuilder::new(NodeType::Text)
-                    .value(Rc::new(Value::from(i)))
-                    .build(),
-            )
-            .expect("unable to append child");
+            l1.push(NodeBuilder::new(NodeType::Text).value(i).build())
+                .expect("unable to append child");
         });
         assert_eq!(root.to_xml(), "<Test><Level1>1</Level1><Level1>2</Level1><Level1>3</Level1><Level1>4</Level1><Level1>5</Level1></Test>")
     }
@@ -1299,12 +1391,8 @@ mod tests {
                 .name(QualifiedName::new(None, None, String::from("Level1")))
                 .build();
             child.push(l1.clone()).expect("unable to append child");
-            l1.push(
-                NodeBuilder::new(NodeType::Text)
-                    .value(Rc::new(Value::from(i)))
-                    .build(),
-            )
-            .expect("unable to append child");
+            l1.push(NodeBuilder::new(NodeType::Text).value(i).build())
+                .expect("unable to append child");
         });
         child
             .child_iter()
@@ -1319,26 +1407,55 @@ mod tests {
     }
 
     #[test]
-    fn deep_copy() {
+    fn namespace_declarations() {
         let mut root = NodeBuilder::new(NodeType::Document).build();
-        let mut child = NodeBuilder::new(NodeType::Element)
+        let child = NodeBuilder::new(NodeType::Element)
             .name(QualifiedName::new(None, None, String::from("Test")))
             .build();
         root.push(child.clone()).expect("unable to append child");
+        child
+            .add_namespace(
+                child
+                    .new_namespace(String::from("urn:a"), Some(String::from("a")))
+                    .expect("unable to create namespace declaration"),
+            )
+            .expect("unable to add namespace declaration");
         child
             .add_attribute(
                 NodeBuilder::new(NodeType::Attribute)
                     .name(QualifiedName::new(None, None, String::from("id")))
-                    .value(Rc::new(Value::from("foo")))
+                    .value("foo")
                     .build(),
             )
             .expect("unable to add attribute");
+        let ns: Vec<RNode> = child.namespace_iter().collect();
+        assert_eq!(ns.len(), 1);
+        assert_eq!(ns[0].to_string(), "urn:a");
+        ns[0]
+            .clone()
+            .pop()
+            .expect("unable to remove namespace declaration");
+        assert_eq!(child.namespace_iter().count(), 0);
+        assert_eq!(root.to_xml(), "<Test id='foo'></Test>")
+    }
+
+    #[test]
+    fn deep_copy() {
+        let mut root = NodeBuilder::new(NodeType::Document).build();
+        let mut child = NodeBuilder::new(NodeType::Element)
+            .name(QualifiedName::new(None, None, String::from("Test")))
+            .build();
+        root.push(child.clone()).expect("unable to append child");
         child
-            .push(
-                NodeBuilder::new(NodeType::Text)
-                    .value(Rc::new(Value::from("1234")))
+            .add_attribute(
+                NodeBuilder::new(NodeType::Attribute)
+                    .name(QualifiedName::new(None, None, String::from("id")))
+                    .value("foo")
                     .build(),
             )
+            .expect("unable to add attribute");
+        child
+            .push(NodeBuilder::new(NodeType::Text).value("1234").build())
             .expect("unable to add text node");
 
         assert_eq!(
@@ -1362,16 +1479,12 @@ mod tests {
             .add_attribute(
                 NodeBuilder::new(NodeType::Attribute)
                     .name(QualifiedName::new(None, None, String::from("id")))
-                    .value(Rc::new(Value::from("foo")))
+                    .value("foo")
                     .build(),
             )
             .expect("unable to add attribute");
         child
-            .push(
-                NodeBuilder::new(NodeType::Text)
-                    .value(Rc::new(Value::from("1234")))
-                    .build(),
-            )
+            .push(NodeBuilder::new(NodeType::Text).value("1234").build())
             .expect("unable to add text node");
 
         assert_eq!(
@@ -1395,7 +1508,7 @@ mod tests {
             .add_attribute(
                 NodeBuilder::new(NodeType::Attribute)
                     .name(QualifiedName::new(None, None, String::from("id")))
-                    .value(Rc::new(Value::from("foo")))
+                    .value("foo")
                     .build(),
             )
             .expect("unable to add attribute");
@@ -1407,12 +1520,8 @@ mod tests {
             ))
             .expect("unable to create element");
         child.push(l1.clone()).expect("unable to add node");
-        l1.push(
-            NodeBuilder::new(NodeType::Text)
-                .value(Rc::new(Value::from("1234")))
-                .build(),
-        )
-        .expect("unable to add text node");
+        l1.push(NodeBuilder::new(NodeType::Text).value("1234").build())
+            .expect("unable to add text node");
 
         let mut od = OutputDefinition::new();
         od.set_indent(true);
@@ -1423,4 +1532,155 @@ mod tests {
 </eg:Test>"#
         )
     }
+
+    fn identity_reparse(src: &str) -> (String, RNode) {
+        let srcdoc = NodeBuilder::new(NodeType::Document).build();
+        parse(srcdoc.clone(), src, None).expect("unable to parse source");
+        let styledoc = NodeBuilder::new(NodeType::Document).build();
+        let (styledoc, stylens) = crate::parser::xml::parse_with_ns(
+            styledoc,
+            r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='/'><xsl:apply-templates/></xsl:template>
+  <xsl:template match='child::*'><xsl:copy><xsl:apply-templates select='attribute::*'/><xsl:apply-templates/></xsl:copy></xsl:template>
+  <xsl:template match='attribute::*'><xsl:copy/></xsl:template>
+  <xsl:template match='child::text()'><xsl:copy/></xsl:template>
+</xsl:stylesheet>"#,
+            None,
+        )
+        .expect("unable to parse stylesheet");
+        let mut stctxt = crate::transform::context::StaticContextBuilder::new()
+            .message(|_| Ok(()))
+            .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+            .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+            .build();
+        let mut ctxt = crate::xslt::from_document(
+            styledoc,
+            stylens,
+            None,
+            |_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")),
+            |_| Ok(String::new()),
+        )
+        .expect("unable to compile stylesheet");
+        ctxt.context(vec![crate::item::Item::Node(srcdoc)], 0);
+        ctxt.result_document(NodeBuilder::new(NodeType::Document).build());
+        let xml = crate::item::SequenceTrait::to_xml(
+            &ctxt.evaluate(&mut stctxt).expect("evaluation failed"),
+        );
+        let resultdoc = NodeBuilder::new(NodeType::Document).build();
+        parse(resultdoc.clone(), xml.as_str(), None).expect("unable to reparse result");
+        (xml, resultdoc)
+    }
+
+    #[test]
+    fn identity_xml_namespace_attribute() {
+        let (xml, doc) = identity_reparse("<Test xml:lang='en'>content</Test>");
+        assert_eq!(xml, "<Test xml:lang='en'>content</Test>");
+        let top = doc.child_iter().next().expect("no document element");
+        assert_eq!(
+            top.get_attribute(&QualifiedName::new(
+                Some(String::from(XML_NAMESPACE)),
+                None,
+                String::from("lang")
+            ))
+            .to_string(),
+            "en"
+        )
+    }
+
+    #[test]
+    fn identity_custom_namespace_attribute() {
+        let (xml, doc) = identity_reparse(
+            "<Test xmlns:eg='http://example.org/'><eg:Level1 eg:id='one'>content</eg:Level1></Test>",
+        );
+        let l1 = doc
+            .child_iter()
+            .next()
+            .and_then(|t| t.child_iter().next())
+            .expect("no child element");
+        assert_eq!(
+            xml,
+            "<Test xmlns:eg='http://example.org/'><eg:Level1 eg:id='one'>content</eg:Level1></Test>"
+        );
+        assert_eq!(l1.name().get_nsuri_ref(), Some("http://example.org/"));
+        assert_eq!(
+            l1.get_attribute(&QualifiedName::new(
+                Some(String::from("http://example.org/")),
+                None,
+                String::from("id")
+            ))
+            .to_string(),
+            "one"
+        )
+    }
+
+    #[test]
+    fn to_xml_invented_prefix() {
+        let mut root = NodeBuilder::new(NodeType::Document).build();
+        let child = NodeBuilder::new(NodeType::Element)
+            .name(QualifiedName::new(None, None, String::from("Test")))
+            .build();
+        root.push(child.clone()).expect("unable to append child");
+        child
+            .add_attribute(
+                NodeBuilder::new(NodeType::Attribute)
+                    .name(QualifiedName::new(
+                        Some(String::from("http://test.org/")),
+                        None,
+                        String::from("id"),
+                    ))
+                    .value("foo")
+                    .build(),
+            )
+            .expect("unable to add attribute");
+        child
+            .add_attribute(
+                NodeBuilder::new(NodeType::Attribute)
+                    .name(QualifiedName::new(
+                        None,
+                        Some(String::from("xmlns")),
+                        String::from("ns0"),
+                    ))
+                    .value("http://test.org/")
+                    .build(),
+            )
+            .expect("unable to add attribute");
+        assert_eq!(
+            root.to_xml(),
+            "<Test xmlns:ns0='http://test.org/' ns0:id='foo'></Test>"
+        )
+    }
+
+    #[test]
+    fn to_xml_regenerate_prefixes() {
+        let mut root = NodeBuilder::new(NodeType::Document).build();
+        let mut outer = NodeBuilder::new(NodeType::Element)
+            .name(QualifiedName::new(
+                Some(String::from("urn:a")),
+                Some(String::from("p")),
+                String::from("Outer"),
+            ))
+            .build();
+        root.push(outer.clone()).expect("unable to append child");
+        outer
+            .push(
+                NodeBuilder::new(NodeType::Element)
+                    .name(QualifiedName::new(
+                        Some(String::from("urn:b")),
+                        Some(String::from("p")),
+                        String::from("Inner"),
+                    ))
+                    .build(),
+            )
+            .expect("unable to append child");
+        assert_eq!(
+            root.to_xml(),
+            "<p:Outer xmlns:p='urn:a'><p:Inner xmlns:p='urn:b'></p:Inner></p:Outer>"
+        );
+        let mut od = OutputDefinition::new();
+        od.set_regenerate_prefixes(true);
+        assert_eq!(
+            root.to_xml_with_options(&od),
+            "<p:Outer xmlns:p='urn:a'><ns0:Inner xmlns:ns0='urn:b'></ns0:Inner></p:Outer>"
+        )
+    }
 }
@@ -74,6 +74,9 @@ impl Node for Nullo {
     fn attribute_iter(&self) -> Self::NodeIterator {
         Box::new(NulloIter::new())
     }
+    fn namespace_iter(&self) -> Self::NodeIterator {
+        Box::new(NulloIter::new())
+    }
     fn get_attribute(&self, _: &QualifiedName) -> Rc<Value> {
         Rc::new(Value::from(""))
     }
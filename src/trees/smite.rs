@@ -44,13 +44,13 @@ assert_eq!(doc.to_xml(), "<Top-Level>content of the element</Top-Level>")
 */
 
 use crate::item::{Node as ItemNode, NodeType};
-use crate::output::OutputDefinition;
+use crate::output::{comment_content, OutputDefinition};
 use crate::qname::QualifiedName;
 use crate::value::Value;
 use crate::xdmerror::*;
 use crate::xmldecl::{XMLDecl, XMLDeclBuilder};
 use regex::Regex;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::cmp::Ordering;
 use std::collections::hash_map::IntoIter;
 use std::collections::HashMap;
@@ -73,6 +73,7 @@ enum NodeInner {
         RefCell<HashMap<Rc<QualifiedName>, RNode>>, // attributes
         RefCell<Vec<RNode>>, // children
         RefCell<HashMap<Option<String>, RNode>>, // namespaces
+        Cell<Option<(usize, usize)>>, // location in the parsed text
     ),
     Text(RefCell<Weak<Node>>, Rc<Value>),
     Attribute(RefCell<Weak<Node>>, Rc<QualifiedName>, Rc<Value>),
@@ -84,12 +85,23 @@ enum NodeInner {
         String,
     ), //URI
 }
-pub struct Node(NodeInner);
+/// A node in a tree.
+///
+/// Document and Element nodes cache their string-value the first time it is computed.
+/// Adding or removing a child node (push, insert_before, pop) clears the cache of the parent node and all of its ancestors.
+/// Text, attribute and other leaf values are immutable, so no other change can make a cached string-value stale.
+pub struct Node(NodeInner, RefCell<Option<String>>);
+
+impl From<NodeInner> for Node {
+    fn from(n: NodeInner) -> Self {
+        Node(n, RefCell::new(None))
+    }
+}
 
 impl Node {
     /// Only documents are created new. All other types of nodes are created using new_* methods.
     pub fn new() -> Self {
-        Node(NodeInner::Document(
+        Node::from(NodeInner::Document(
             RefCell::new(None),
             RefCell::new(vec![]),
             RefCell::new(vec![]),
@@ -97,7 +109,7 @@ impl Node {
     }
     pub fn set_nsuri(&mut self, uri: String) -> Result<(), Error> {
         match &self.0 {
-            NodeInner::Element(p, qn, att, c, ns) => {
+            NodeInner::Element(p, qn, att, c, ns, loc) => {
                 self.0 = NodeInner::Element(
                     p.clone(),
                     Rc::new(QualifiedName::new(
@@ -108,6 +120,7 @@ impl Node {
                     att.clone(),
                     c.clone(),
                     ns.clone(),
+                    loc.clone(),
                 );
                 Ok(())
             }
@@ -137,8 +150,8 @@ impl PartialEq for Node {
                 // TODO: use a method that terminates early on non-equality
             }
             (
-                NodeInner::Element(_, name, atts, c, _),
-                NodeInner::Element(_, o_name, o_atts, d, _),
+                NodeInner::Element(_, name, atts, c, _, _),
+                NodeInner::Element(_, o_name, o_atts, d, _, _),
             ) => {
                 if name == o_name {
                     // Attributes must match
@@ -202,7 +215,7 @@ impl ItemNode for RNode {
     fn node_type(&self) -> NodeType {
         match &self.0 {
             NodeInner::Document(_, _, _) => NodeType::Document,
-            NodeInner::Element(_, _, _, _, _) => NodeType::Element,
+            NodeInner::Element(_, _, _, _, _, _) => NodeType::Element,
             NodeInner::Attribute(_, _, _) => NodeType::Attribute,
             NodeInner::Text(_, _) => NodeType::Text,
             NodeInner::Comment(_, _) => NodeType::Comment,
@@ -212,12 +225,16 @@ impl ItemNode for RNode {
     }
     fn name(&self) -> QualifiedName {
         match &self.0 {
-            NodeInner::Element(_, qn, _, _, _)
+            NodeInner::Element(_, qn, _, _, _, _)
             | NodeInner::ProcessingInstruction(_, qn, _)
             | NodeInner::Attribute(_, qn, _) => {
                 let r: QualifiedName = (*qn.clone()).clone();
                 r
             }
+            // The name of a namespace node is its prefix
+            NodeInner::Namespace(_, p, _) => {
+                QualifiedName::new(None, None, p.clone().unwrap_or_default())
+            }
             _ => QualifiedName::new(None, None, String::from("")),
         }
     }
@@ -227,21 +244,41 @@ impl ItemNode for RNode {
             | NodeInner::Comment(_, v)
             | NodeInner::ProcessingInstruction(_, _, v)
             | NodeInner::Attribute(_, _, v) => v.clone(),
+            NodeInner::Namespace(_, _, uri) => Rc::new(Value::from(uri.clone())),
             _ => Rc::new(Value::from(String::from(""))),
         }
     }
 
     fn get_id(&self) -> String {
-        format!("{:#p}", &(self).0 as *const NodeInner)
+        format!("n{:x}", &self.0 as *const NodeInner as usize)
+    }
+    fn location(&self) -> Option<(usize, usize)> {
+        match &self.0 {
+            NodeInner::Element(_, _, _, _, _, loc) => loc.get(),
+            _ => None,
+        }
+    }
+    fn set_location(&self, l: (usize, usize)) {
+        if let NodeInner::Element(_, _, _, _, _, loc) = &self.0 {
+            loc.set(Some(l))
+        }
     }
 
     fn to_string(&self) -> String {
         match &self.0 {
-            NodeInner::Document(_, c, _) | NodeInner::Element(_, _, _, c, _) => {
-                c.borrow().iter().fold(String::new(), |mut acc, n| {
-                    acc.push_str(n.to_string().as_str());
-                    acc
-                })
+            NodeInner::Document(_, _, _) | NodeInner::Element(_, _, _, _, _, _) => {
+                if let Some(v) = self.1.borrow().as_ref() {
+                    return v.clone();
+                }
+                let v = self
+                    .descend_iter()
+                    .filter(|c| c.node_type() == NodeType::Text)
+                    .fold(String::new(), |mut acc, c| {
+                        acc.push_str(c.to_string().as_str());
+                        acc
+                    });
+                *self.1.borrow_mut() = Some(v.clone());
+                v
             }
             NodeInner::Attribute(_, _, v)
             | NodeInner::Text(_, v)
@@ -251,10 +288,10 @@ impl ItemNode for RNode {
         }
     }
     fn to_xml(&self) -> String {
-        to_xml_int(self, &OutputDefinition::new(), vec![], 0)
+        to_xml_int(self, &OutputDefinition::new(), vec![], 2)
     }
     fn to_xml_with_options(&self, od: &OutputDefinition) -> std::string::String {
-        to_xml_int(self, od, vec![], 0)
+        to_xml_int(self, od, vec![], 2)
     }
     fn is_same(&self, other: &Self) -> bool {
         Rc::ptr_eq(self, other)
@@ -311,9 +348,12 @@ impl ItemNode for RNode {
     fn attribute_iter(&self) -> Self::NodeIterator {
         Box::new(Attributes::new(self))
     }
+    fn namespace_iter(&self) -> Self::NodeIterator {
+        Box::new(Namespaces::new(self))
+    }
     fn get_attribute(&self, a: &QualifiedName) -> Rc<Value> {
         match &self.0 {
-            NodeInner::Element(_, _, att, _, _) => att
+            NodeInner::Element(_, _, att, _, _, _) => att
                 .borrow()
                 .get(a)
                 .map_or(Rc::new(Value::from(String::new())), |v| v.value()),
@@ -322,23 +362,24 @@ impl ItemNode for RNode {
     }
     fn get_attribute_node(&self, a: &QualifiedName) -> Option<Self> {
         match &self.0 {
-            NodeInner::Element(_, _, att, _, _) => att.borrow().get(a).cloned(),
+            NodeInner::Element(_, _, att, _, _, _) => att.borrow().get(a).cloned(),
             _ => None,
         }
     }
     fn new_element(&self, qn: QualifiedName) -> Result<Self, Error> {
-        let child = Rc::new(Node(NodeInner::Element(
+        let child = Rc::new(Node::from(NodeInner::Element(
             RefCell::new(Rc::downgrade(&self.owner_document())),
             Rc::new(qn),
             RefCell::new(HashMap::new()),
             RefCell::new(vec![]),
             RefCell::new(HashMap::new()),
+            Cell::new(None),
         )));
         unattached(self, child.clone());
         Ok(child)
     }
     fn new_namespace(&self, ns: String, prefix: Option<String>) -> Result<Self, Error> {
-        let ns_node = Rc::new(Node(NodeInner::Namespace(
+        let ns_node = Rc::new(Node::from(NodeInner::Namespace(
             RefCell::new(Rc::downgrade(&self.owner_document())),
             prefix,
             ns,
@@ -347,7 +388,7 @@ impl ItemNode for RNode {
         Ok(ns_node)
     }
     fn new_text(&self, v: Rc<Value>) -> Result<Self, Error> {
-        let child = Rc::new(Node(NodeInner::Text(
+        let child = Rc::new(Node::from(NodeInner::Text(
             RefCell::new(Rc::downgrade(&self.owner_document())),
             v,
         )));
@@ -355,7 +396,7 @@ impl ItemNode for RNode {
         Ok(child)
     }
     fn new_attribute(&self, qn: QualifiedName, v: Rc<Value>) -> Result<Self, Error> {
-        let att = Rc::new(Node(NodeInner::Attribute(
+        let att = Rc::new(Node::from(NodeInner::Attribute(
             RefCell::new(Rc::downgrade(self)),
             Rc::new(qn.clone()),
             v,
@@ -364,7 +405,7 @@ impl ItemNode for RNode {
         Ok(att)
     }
     fn new_comment(&self, v: Rc<Value>) -> Result<Self, Error> {
-        let child = Rc::new(Node(NodeInner::Comment(
+        let child = Rc::new(Node::from(NodeInner::Comment(
             RefCell::new(Rc::downgrade(&self.owner_document())),
             v,
         )));
@@ -372,7 +413,7 @@ impl ItemNode for RNode {
         Ok(child)
     }
     fn new_processing_instruction(&self, qn: QualifiedName, v: Rc<Value>) -> Result<Self, Error> {
-        let child = Rc::new(Node(NodeInner::ProcessingInstruction(
+        let child = Rc::new(Node::from(NodeInner::ProcessingInstruction(
             RefCell::new(Rc::downgrade(&self.owner_document())),
             Rc::new(qn.clone()),
             v,
@@ -412,10 +453,12 @@ impl ItemNode for RNode {
             }
             NodeInner::Attribute(parent, qn, _) => {
                 // Remove this node from the attribute hashmap
-                match Weak::upgrade(&parent.borrow()) {
+                // The parent must not remain borrowed, since this node will be re-parented.
+                let parent = Weak::upgrade(&parent.borrow());
+                match parent {
                     Some(p) => {
                         match &p.0 {
-                            NodeInner::Element(_, _, att, _, _) => {
+                            NodeInner::Element(_, _, att, _, _, _) => {
                                 att.borrow_mut().remove(qn).ok_or(Error::new(
                                     ErrorKind::DynamicAbsent,
                                     String::from("unable to find attribute"),
@@ -442,10 +485,12 @@ impl ItemNode for RNode {
             }
             NodeInner::Namespace(parent, prefix, _) => {
                 // Remove this node from the attribute hashmap
-                match Weak::upgrade(&parent.borrow()) {
+                // The parent must not remain borrowed, since this node will be re-parented.
+                let parent = Weak::upgrade(&parent.borrow());
+                match parent {
                     Some(p) => {
                         match &p.0 {
-                            NodeInner::Element(_, _, _, _, namespaces) => {
+                            NodeInner::Element(_, _, _, _, namespaces, _) => {
                                 namespaces.borrow_mut().remove(prefix).ok_or(Error::new(
                                     ErrorKind::DynamicAbsent,
                                     String::from("unable to find namespace"),
@@ -470,7 +515,7 @@ impl ItemNode for RNode {
                     }
                 }
             }
-            NodeInner::Element(parent, _, _, _, _)
+            NodeInner::Element(parent, _, _, _, _, _)
             | NodeInner::Text(parent, _)
             | NodeInner::Comment(parent, _)
             | NodeInner::ProcessingInstruction(parent, _, _) => {
@@ -484,13 +529,21 @@ impl ItemNode for RNode {
                     ));
                 };
                 match &p.0 {
-                    NodeInner::Element(_, _, _, c, _) => {
+                    NodeInner::Element(_, _, _, c, _, _) => {
                         let idx = find_index(&p, self)?;
                         c.borrow_mut().remove(idx);
+                        invalidate(&p);
                         let doc = self.owner_document();
                         unattached(&doc, self.clone())
                     }
-                    NodeInner::Document(_, _, _) => {} // node was in the unattached list
+                    NodeInner::Document(_, c, _) => {
+                        // Either the node is a child of the document, or it is in the unattached list
+                        if let Ok(idx) = find_index(&p, self) {
+                            c.borrow_mut().remove(idx);
+                            invalidate(&p);
+                            unattached(&p, self.clone())
+                        }
+                    }
                     _ => {
                         return Err(Error::new(
                             ErrorKind::TypeError,
@@ -511,7 +564,7 @@ impl ItemNode for RNode {
         }
 
         match &self.0 {
-            NodeInner::Element(_, _, patt, _, _) => {
+            NodeInner::Element(_, _, patt, _, _, _) => {
                 // Firstly, make sure the node is removed from its old parent
                 let mut m = att.clone();
                 m.pop()?;
@@ -544,7 +597,7 @@ impl ItemNode for RNode {
         }
 
         match &self.0 {
-            NodeInner::Element(_, _, _, _, n) => {
+            NodeInner::Element(_, _, _, _, n, _) => {
                 // Firstly, make sure the node is removed from its old parent
                 let mut m = ns.clone();
                 m.pop()?;
@@ -553,8 +606,8 @@ impl ItemNode for RNode {
                 detach(ns.clone());
                 // Now add to this parent
                 // TODO: deal with same name being redefined
-                if let NodeInner::Namespace(_, _, _) = &ns.0 {
-                    let _ = n.borrow_mut().insert(ns.name().get_prefix(), ns.clone());
+                if let NodeInner::Namespace(_, p, _) = &ns.0 {
+                    let _ = n.borrow_mut().insert(p.clone(), ns.clone());
                 }
                 make_parent(ns, self.clone());
                 Ok(())
@@ -579,7 +632,7 @@ impl ItemNode for RNode {
         detach(n.clone());
         // Now insert into parent's child list
         match &self.0 {
-            NodeInner::Element(p, _, _, _, _)
+            NodeInner::Element(p, _, _, _, _, _)
             | NodeInner::Text(p, _)
             | NodeInner::Comment(p, _)
             | NodeInner::ProcessingInstruction(p, _, _) => {
@@ -587,8 +640,9 @@ impl ItemNode for RNode {
                 let idx = find_index(&parent, self)?;
                 match &parent.0 {
                     NodeInner::Document(_, children, _)
-                    | NodeInner::Element(_, _, _, children, _) => {
+                    | NodeInner::Element(_, _, _, children, _, _) => {
                         children.borrow_mut().insert(idx, n.clone());
+                        invalidate(&parent);
                         make_parent(n, parent.clone())
                     }
                     _ => {
@@ -611,39 +665,44 @@ impl ItemNode for RNode {
     fn shallow_copy(&self) -> Result<Self, Error> {
         // All new nodes are parentless, i.e. they are unattached to the tree
         match &self.0 {
-            NodeInner::Document(x, _, _) => Ok(Rc::new(Node(NodeInner::Document(
+            NodeInner::Document(x, _, _) => Ok(Rc::new(Node::from(NodeInner::Document(
                 x.clone(),
                 RefCell::new(vec![]),
                 RefCell::new(vec![]),
             )))),
-            NodeInner::Element(p, qn, _, _, _) => {
-                let new = Rc::new(Node(NodeInner::Element(
+            NodeInner::Element(p, qn, _, _, _, loc) => {
+                let new = Rc::new(Node::from(NodeInner::Element(
                     p.clone(),
                     qn.clone(),
                     RefCell::new(HashMap::new()),
                     RefCell::new(vec![]),
                     RefCell::new(HashMap::new()),
+                    loc.clone(),
                 )));
                 unattached(self, new.clone());
                 Ok(new)
             }
-            NodeInner::Attribute(p, qn, v) => Ok(Rc::new(Node(NodeInner::Attribute(
-                p.clone(),
-                qn.clone(),
-                v.clone(),
-            )))),
+            NodeInner::Attribute(p, qn, v) => {
+                let new = Rc::new(Node::from(NodeInner::Attribute(
+                    p.clone(),
+                    qn.clone(),
+                    v.clone(),
+                )));
+                unattached(&self.owner_document(), new.clone());
+                Ok(new)
+            }
             NodeInner::Text(p, v) => {
-                let new = Rc::new(Node(NodeInner::Text(p.clone(), v.clone())));
+                let new = Rc::new(Node::from(NodeInner::Text(p.clone(), v.clone())));
                 unattached(&self.parent().unwrap(), new.clone());
                 Ok(new)
             }
             NodeInner::Comment(p, v) => {
-                let new = Rc::new(Node(NodeInner::Comment(p.clone(), v.clone())));
+                let new = Rc::new(Node::from(NodeInner::Comment(p.clone(), v.clone())));
                 unattached(&self.parent().unwrap(), new.clone());
                 Ok(new)
             }
             NodeInner::ProcessingInstruction(p, qn, v) => {
-                let new = Rc::new(Node(NodeInner::ProcessingInstruction(
+                let new = Rc::new(Node::from(NodeInner::ProcessingInstruction(
                     p.clone(),
                     qn.clone(),
                     v.clone(),
@@ -652,7 +711,7 @@ impl ItemNode for RNode {
                 Ok(new)
             }
             NodeInner::Namespace(p, pre, uri) => {
-                let new = Rc::new(Node(NodeInner::Namespace(
+                let new = Rc::new(Node::from(NodeInner::Namespace(
                     p.clone(),
                     pre.clone(),
                     uri.clone(),
@@ -663,22 +722,24 @@ impl ItemNode for RNode {
         }
     }
     fn deep_copy(&self) -> Result<Self, Error> {
-        let mut new = self.shallow_copy()?;
-        self.attribute_iter().try_for_each(|a| {
-            new.add_attribute(a.deep_copy()?)?;
-            Ok(())
-        })?;
-        self.child_iter().try_for_each(|c| {
-            new.push(c.deep_copy()?)?;
-            Ok(())
-        })?;
+        // The descendants are copied using an explicit stack, rather than recursion
+        let new = copy_with_properties(self)?;
+        let mut stack = vec![(self.clone(), new.clone())];
+        while let Some((src, mut dest)) = stack.pop() {
+            src.child_iter().try_for_each(|c| {
+                let cp = copy_with_properties(&c)?;
+                dest.push(cp.clone())?;
+                stack.push((c, cp));
+                Ok(())
+            })?;
+        }
         Ok(new)
     }
     fn get_canonical(&self) -> Result<Self, Error> {
         match &self.0 {
             NodeInner::Document(_, e, _) => {
                 let mut result = self.shallow_copy()?;
-                for n in e.borrow_mut().iter() {
+                for n in e.borrow().iter() {
                     if let Ok(rn) = n.get_canonical() {
                         result.push(rn)?
                     }
@@ -711,19 +772,23 @@ impl ItemNode for RNode {
                 Ok(d.new_text(w)?)
             }
             NodeInner::Attribute(_, _, _) => self.shallow_copy(),
-            NodeInner::Element(_, _, _, _, _) => {
+            NodeInner::Element(_, _, _, _, _, _) => {
                 let mut result = self.shallow_copy()?;
 
                 let d = result.owner_document();
                 self.attribute_iter().try_for_each(|a| {
                     //Replace any number of spaces with a single space.
-                    let re = Regex::new(r"\s+").unwrap();
+                    //Other white space characters can only have come from character references, so they are kept.
+                    let re = Regex::new(r" +").unwrap();
                     result.add_attribute(
                         d.new_attribute(
                             a.name(),
                             Rc::new(Value::String(
-                                re.replace_all(a.clone().value().to_string().trim(), " ")
-                                    .to_string(),
+                                re.replace_all(
+                                    a.clone().value().to_string().trim_matches(' '),
+                                    " ",
+                                )
+                                .to_string(),
                             )),
                         )?,
                     )?;
@@ -770,7 +835,7 @@ impl Debug for Node {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match &self.0 {
             NodeInner::Document(_, _, _) => write!(f, "document"),
-            NodeInner::Element(_, qn, ats, _, _) => {
+            NodeInner::Element(_, qn, ats, _, _, _) => {
                 let attrs = ats.borrow();
                 write!(
                     f,
@@ -814,7 +879,7 @@ fn unattached(d: &RNode, n: RNode) {
             u.borrow_mut().push(n.clone());
             make_parent(n, d.clone())
         }
-        NodeInner::Element(_, _, _, _, _) => {
+        NodeInner::Element(_, _, _, _, _, _) => {
             let doc = d.owner_document();
             if let NodeInner::Document(_, _, u) = &doc.0 {
                 u.borrow_mut().push(n.clone());
@@ -829,11 +894,12 @@ fn unattached(d: &RNode, n: RNode) {
 // Make the parent of the node be the given new parent
 fn make_parent(n: RNode, b: RNode) {
     match &n.0 {
-        NodeInner::Element(p, _, _, _, _)
+        NodeInner::Element(p, _, _, _, _, _)
         | NodeInner::Attribute(p, _, _)
         | NodeInner::Text(p, _)
         | NodeInner::Comment(p, _)
-        | NodeInner::ProcessingInstruction(p, _, _) => *p.borrow_mut() = Rc::downgrade(&b),
+        | NodeInner::ProcessingInstruction(p, _, _)
+        | NodeInner::Namespace(p, _, _) => *p.borrow_mut() = Rc::downgrade(&b),
         _ => panic!("unable to change parent"),
     }
 }
@@ -841,11 +907,12 @@ fn make_parent(n: RNode, b: RNode) {
 // This is in preparation for it being added to the tree.
 fn detach(n: RNode) {
     match &n.0 {
-        NodeInner::Element(p, _, _, _, _)
+        NodeInner::Element(p, _, _, _, _, _)
         | NodeInner::Attribute(p, _, _)
         | NodeInner::Text(p, _)
         | NodeInner::Comment(p, _)
-        | NodeInner::ProcessingInstruction(p, _, _) => {
+        | NodeInner::ProcessingInstruction(p, _, _)
+        | NodeInner::Namespace(p, _, _) => {
             let doc = Weak::upgrade(&p.borrow()).unwrap();
             match &doc.0 {
                 NodeInner::Document(_, _, u) => {
@@ -861,6 +928,13 @@ fn detach(n: RNode) {
     }
 }
 
+// Clear the cached string-value of a node and all of its ancestors.
+// This must be called whenever the child list of a node changes.
+fn invalidate(n: &RNode) {
+    *n.1.borrow_mut() = None;
+    n.ancestor_iter().for_each(|a| *a.1.borrow_mut() = None)
+}
+
 fn push_node(parent: &RNode, child: RNode) -> Result<(), Error> {
     if child.node_type() == NodeType::Attribute || child.node_type() == NodeType::Document {
         return Err(Error::new(
@@ -872,7 +946,7 @@ fn push_node(parent: &RNode, child: RNode) -> Result<(), Error> {
         NodeInner::Document(_, c, _) => {
             c.borrow_mut().push(child.clone());
         }
-        NodeInner::Element(_, _, _, c, _) => {
+        NodeInner::Element(_, _, _, c, _, _) => {
             c.borrow_mut().push(child.clone());
         }
         _ => {
@@ -882,6 +956,7 @@ fn push_node(parent: &RNode, child: RNode) -> Result<(), Error> {
             ))
         }
     }
+    invalidate(parent);
     make_parent(child, parent.clone());
     Ok(())
 }
@@ -890,24 +965,34 @@ fn push_node(parent: &RNode, child: RNode) -> Result<(), Error> {
 fn doc_order(n: &RNode) -> Vec<usize> {
     match &n.0 {
         NodeInner::Document(_, _, _) => vec![1usize],
-        NodeInner::Attribute(_, _, _) => {
+        // Namespaces come before attributes, and attributes come before the children of their element
+        NodeInner::Namespace(_, _, _) => {
             let mut a = doc_order(&n.parent().unwrap());
-            a.push(2);
+            a.push(1);
             a
         }
-        NodeInner::Namespace(_, _, _) => {
-            let mut a = doc_order(&n.parent().unwrap());
+        // Attributes are ordered amongst themselves by name, so that their order is stable
+        NodeInner::Attribute(_, qn, _) => {
+            let p = n.parent().unwrap();
+            let idx = match &p.0 {
+                NodeInner::Element(_, _, att, _, _, _) => {
+                    att.borrow().keys().filter(|k| *k < qn).count()
+                }
+                _ => 0,
+            };
+            let mut a = doc_order(&p);
             a.push(2);
+            a.push(idx);
             a
         }
-        NodeInner::Element(p, _, _, _, _)
+        NodeInner::Element(p, _, _, _, _, _)
         | NodeInner::Text(p, _)
         | NodeInner::Comment(p, _)
         | NodeInner::ProcessingInstruction(p, _, _) => match Weak::upgrade(&p.borrow()) {
             Some(q) => {
                 let idx = find_index(&q, n).expect("unable to locate node in parent");
                 let mut a = doc_order(&q);
-                a.push(idx + 2);
+                a.push(idx + 3);
                 a
             }
             None => vec![1usize],
@@ -918,7 +1003,7 @@ fn doc_order(n: &RNode) -> Vec<usize> {
 // Find the position of this node in the parent's child list.
 fn find_index(parent: &RNode, child: &RNode) -> Result<usize, Error> {
     let idx = match &parent.0 {
-        NodeInner::Document(_, c, _) | NodeInner::Element(_, _, _, c, _) => {
+        NodeInner::Document(_, c, _) | NodeInner::Element(_, _, _, c, _, _) => {
             c.borrow().iter().enumerate().fold(None, |mut acc, (i, v)| {
                 if Rc::ptr_eq(child, v) {
                     acc = Some(i)
@@ -940,134 +1025,212 @@ fn find_index(parent: &RNode, child: &RNode) -> Result<usize, Error> {
     ))
 }
 
+// Copy a node, along with its attributes and namespaces, but not its children
+fn copy_with_properties(n: &RNode) -> Result<RNode, Error> {
+    let mut new = n.shallow_copy()?;
+    n.attribute_iter().try_for_each(|a| {
+        new.add_attribute(a.shallow_copy()?)?;
+        Ok(())
+    })?;
+    n.namespace_iter().try_for_each(|ns| {
+        new.add_namespace(ns.shallow_copy()?)?;
+        Ok(())
+    })?;
+    Ok(new)
+}
+
+// A step in the serialisation of a tree.
+// The tree is serialised using an explicit stack, so that deeply nested documents do not overflow the call stack.
+enum XmlStep {
+    // Serialise a node. "ns" is the list of XML Namespaces that have been declared in an ancestor: (URI, prefix).
+    // "indent" is the current level of identation, which is that of the children of an element.
+    Node(RNode, Rc<Vec<(String, Option<String>)>>, usize),
+    // Literal output, such as the spacing between the children of an element.
+    Literal(String),
+    // The end tag of an element.
+    End(String, bool, usize),
+}
+
 // This handles the XML serialisation of the document.
-// "ns" is the list of XML Namespaces that have been declared in an ancestor: (URI, prefix).
-// "indent" is the current level of identation.
 fn to_xml_int(
     node: &RNode,
     od: &OutputDefinition,
     ns: Vec<(String, Option<String>)>,
     indent: usize,
 ) -> String {
-    match &node.0 {
-        NodeInner::Document(_, _, _) => node.child_iter().fold(String::new(), |mut result, c| {
-            result.push_str(to_xml_int(&c, od, ns.clone(), indent + 2).as_str());
-            result
-        }),
-        NodeInner::Element(_, qn, _, _, _) => {
-            let mut result = String::from("<");
-            result.push_str(qn.to_string().as_str());
-
-            // Check if any XML Namespaces need to be declared
-            // newns is a vector of (prefix, namespace URI) pairs
-            let mut declared = ns.clone();
-            let mut newns: Vec<(String, Option<String>)> = vec![];
-            // First, the element itself
-            namespace_check(qn, &declared).iter().for_each(|m| {
-                newns.push(m.clone());
-                declared.push(m.clone())
-            });
-            // Next, it's attributes
-            node.attribute_iter().for_each(|a| {
-                namespace_check(&a.name(), &declared).iter().for_each(|m| {
-                    newns.push(m.clone());
-                    declared.push(m.clone())
-                })
-            });
-            // Finally, it's child elements
-            node.child_iter()
-                .filter(|c| c.node_type() == NodeType::Element)
-                .for_each(|c| {
-                    namespace_check(&c.name(), &declared).iter().for_each(|m| {
-                        newns.push(m.clone());
-                        declared.push(m.clone())
+    let mut result = String::new();
+    let mut stack = vec![XmlStep::Node(node.clone(), Rc::new(ns), indent)];
+    while let Some(step) = stack.pop() {
+        match step {
+            XmlStep::Node(n, ns, indent) => match &n.0 {
+                NodeInner::Document(_, c, _) => {
+                    // If the indent option is enabled, then each top-level node is on its own line.
+                    // A document type declaration, if any, precedes the document element.
+                    c.borrow().iter().enumerate().rev().for_each(|(i, c)| {
+                        stack.push(XmlStep::Node(c.clone(), ns.clone(), indent));
+                        if let Some(d) = (c.node_type() == NodeType::Element)
+                            .then(|| od.doctype_declaration(c.name().to_string().as_str()))
+                            .flatten()
+                        {
+                            stack.push(XmlStep::Literal(d))
+                        }
+                        if od.get_indent() && i > 0 {
+                            stack.push(XmlStep::Literal(String::from("\n")))
+                        }
                     })
-                });
-            newns.iter().for_each(|(u, p)| {
-                result.push_str(" xmlns");
-                if let Some(q) = p {
-                    result.push(':');
-                    result.push_str(q.as_str());
                 }
-                result.push_str("='");
-                result.push_str(u);
-                result.push('\'');
-            });
+                NodeInner::Element(_, _, _, c, _, _) => {
+                    let (name, declared) = start_tag(&n, &ns, od, &mut result);
 
-            node.attribute_iter().for_each(|a| {
-                result.push_str(
-                    format!(" {}='{}'", a.name().to_string().as_str(), a.value()).as_str(),
-                )
-            });
-            result.push('>');
+                    // Content of the element.
+                    // If the indent option is enabled, then if there are children and no child is a text node then add spacing.
+                    // Comments and processing instructions are then on their own lines, but stay inline in mixed content.
+                    let do_indent: bool = od.get_indent()
+                        && !c.borrow().is_empty()
+                        && c.borrow().iter().all(|c| c.node_type() != NodeType::Text);
 
-            // Content of the element.
-            // If the indent option is enabled, then if no child is a text node then add spacing.
-            let do_indent: bool = od
-                .get_indent()
-                .then(|| {
-                    node.child_iter().fold(true, |mut acc, c| {
-                        if acc && c.node_type() == NodeType::Text {
-                            acc = false
+                    stack.push(XmlStep::End(name, do_indent, indent));
+                    let declared = Rc::new(declared);
+                    c.borrow().iter().rev().for_each(|c| {
+                        stack.push(XmlStep::Node(c.clone(), declared.clone(), indent + 2));
+                        if do_indent {
+                            let mut spacing = String::from("\n");
+                            spacing.push_str(od.get_indent_string().repeat(indent / 2).as_str());
+                            stack.push(XmlStep::Literal(spacing))
                         }
-                        acc
-                    })
-                })
-                .map_or(false, |b| b);
-
-            node.child_iter().for_each(|c| {
-                if do_indent {
+                    });
+                }
+                NodeInner::Text(_, v) => {
+                    result.push_str(od.map_characters(v.to_string().as_str()).as_str())
+                }
+                NodeInner::Comment(_, v) => {
+                    result.push_str("<!--");
+                    result.push_str(comment_content(v.to_string().as_str()).as_str());
+                    result.push_str("-->");
+                }
+                NodeInner::ProcessingInstruction(_, qn, v) => {
+                    result.push_str("<?");
+                    result.push_str(qn.to_string().as_str());
+                    result.push(' ');
+                    result.push_str(v.to_string().as_str());
+                    result.push_str("?>");
+                }
+                // An attribute that is not part of an element, i.e. at the top level of the result, is its string value
+                NodeInner::Attribute(_, _, v) => result.push_str(v.to_string().as_str()),
+                _ => {}
+            },
+            XmlStep::Literal(l) => result.push_str(l.as_str()),
+            XmlStep::End(name, do_indent, indent) => {
+                if do_indent && indent > 1 {
                     result.push('\n');
-                    (0..indent).for_each(|_| result.push(' '))
+                    result.push_str(od.get_indent_string().repeat(indent / 2 - 1).as_str())
                 }
-                result.push_str(to_xml_int(&c, od, newns.clone(), indent + 2).as_str())
-            });
-            if do_indent && indent > 1 {
-                result.push('\n');
-                (0..(indent - 2)).for_each(|_| result.push(' '))
+                result.push_str("</");
+                result.push_str(name.as_str());
+                result.push('>');
             }
-            result.push_str("</");
-            result.push_str(qn.to_string().as_str());
-            result.push('>');
-            result
-        }
-        NodeInner::Text(_, v) => v.to_string(),
-        NodeInner::Comment(_, v) => {
-            let mut result = String::from("<!--");
-            result.push_str(v.to_string().as_str());
-            result.push_str("-->");
-            result
         }
-        NodeInner::ProcessingInstruction(_, qn, v) => {
-            let mut result = String::from("<?");
-            result.push_str(qn.to_string().as_str());
-            result.push(' ');
-            result.push_str(v.to_string().as_str());
-            result.push_str("?>");
-            result
-        }
-        _ => String::new(),
     }
+    od.normalize(result)
 }
 
-// Checks if this node's name is in a namespace that has already been declared.
-// Returns a namespace to be declared if required, (URI, prefix).
-fn namespace_check(
-    qn: &QualifiedName,
-    ns: &Vec<(String, Option<String>)>,
-) -> Option<(String, Option<String>)> {
-    let mut result = None;
-    if let Some(qnuri) = qn.get_nsuri_ref() {
-        // Has this namespace already been declared?
-        if ns.iter().any(|(u, _)| u == qnuri) {
-            // Namespace has been declared, but with the same prefix?
-            // TODO: see forest.rs for example implementation
-        } else {
-            // Namespace has not been declared, so this element must declare it
-            result = Some((qnuri.to_string(), qn.get_prefix()))
+// Serialise the start tag of an element, including any XML Namespace declarations that are needed.
+// Returns the element's serialised name and the XML Namespaces that are in scope for its content.
+fn start_tag(
+    node: &RNode,
+    ns: &[(String, Option<String>)],
+    od: &OutputDefinition,
+    result: &mut String,
+) -> (String, Vec<(String, Option<String>)>) {
+    result.push('<');
+
+    // Check if any XML Namespaces need to be declared
+    // newns is a vector of (namespace URI, prefix) pairs
+    let mut declared = ns.to_vec();
+    let mut newns: Vec<(String, Option<String>)> = vec![];
+    // First, the element's namespace nodes
+    let mut nsnodes: Vec<(String, Option<String>)> = node
+        .namespace_iter()
+        .filter_map(|n| match &n.0 {
+            NodeInner::Namespace(_, p, u) => Some((u.clone(), p.clone())),
+            _ => None,
+        })
+        .collect();
+    nsnodes.sort();
+    // When regenerating prefixes, a namespace node whose prefix is bound to a different URI is not declared.
+    // Names in that namespace are given an invented prefix instead.
+    let regenerate = od.get_regenerate_prefixes();
+    // A namespace that is already in scope, from an ancestor, is not declared again.
+    nsnodes.into_iter().for_each(|m| {
+        if in_scope(&declared, &m.1).unwrap_or("") != m.0
+            && !(regenerate && collides(&declared, &m))
+        {
+            newns.push(m.clone());
+            declared.push(m)
         }
+    });
+    // Next, the element itself
+    let (name, m) = node.name().serialize_in(&declared, false, od);
+    if let Some(m) = m {
+        newns.push(m.clone());
+        declared.push(m)
     }
-    result
+    result.push_str(name.as_str());
+    // Then, it's attributes.
+    // These are in document order, so that any prefixes that are invented are the same every time.
+    let mut attributes: Vec<RNode> = node.attribute_iter().collect();
+    attributes.sort_by_key(|a| a.name());
+    let atts: Vec<(String, String)> = attributes
+        .iter()
+        .map(|a| {
+            let (aname, m) = a.name().serialize_in(&declared, true, od);
+            if let Some(m) = m {
+                newns.push(m.clone());
+                declared.push(m)
+            }
+            (aname, od.map_characters(a.value().to_string().as_str()))
+        })
+        .collect();
+    // Finally, it's child elements.
+    // Only prefixes that are not yet bound are declared here; the default namespace is left to the child.
+    node.child_iter()
+        .filter(|c| c.node_type() == NodeType::Element)
+        .for_each(|c| {
+            if let (_, Some(m)) = c.name().serialize_in(&declared, false, od) {
+                if m.1.is_some() && declared.iter().all(|(_, p)| *p != m.1) {
+                    newns.push(m.clone());
+                    declared.push(m)
+                }
+            }
+        });
+    newns.iter().for_each(|(u, p)| {
+        result.push_str(" xmlns");
+        if let Some(q) = p {
+            result.push(':');
+            result.push_str(q.as_str());
+        }
+        result.push_str("='");
+        result.push_str(u);
+        result.push('\'');
+    });
+
+    atts.iter()
+        .for_each(|(k, v)| result.push_str(format!(" {}='{}'", k, v).as_str()));
+    result.push('>');
+    (name, declared)
+}
+
+// Is the prefix of a namespace declaration already bound to a different URI?
+fn collides(ns: &[(String, Option<String>)], m: &(String, Option<String>)) -> bool {
+    m.1.is_some() && in_scope(ns, &m.1).is_some_and(|u| !u.is_empty() && u != m.0)
+}
+
+// The namespace URI that a prefix is bound to, where a later declaration overrides an earlier one.
+fn in_scope<'a>(ns: &'a [(String, Option<String>)], prefix: &Option<String>) -> Option<&'a str> {
+    ns.iter()
+        .rev()
+        .find(|(_, p)| p == prefix)
+        .map(|(u, _)| u.as_str())
 }
 
 pub struct Children {
@@ -1077,7 +1240,7 @@ pub struct Children {
 impl Children {
     fn new(n: &RNode) -> Self {
         match &n.0 {
-            NodeInner::Document(_, c, _) | NodeInner::Element(_, _, _, c, _) => Children {
+            NodeInner::Document(_, c, _) | NodeInner::Element(_, _, _, c, _, _) => Children {
                 v: c.borrow().clone(),
                 i: 0,
             },
@@ -1115,7 +1278,7 @@ impl Iterator for Ancestors {
     fn next(&mut self) -> Option<RNode> {
         let parent = match &self.cur.0 {
             NodeInner::Document(_, _, _) => None,
-            NodeInner::Element(p, _, _, _, _)
+            NodeInner::Element(p, _, _, _, _, _)
             | NodeInner::Attribute(p, _, _)
             | NodeInner::Text(p, _)
             | NodeInner::Comment(p, _)
@@ -1129,43 +1292,27 @@ impl Iterator for Ancestors {
     }
 }
 
-// This implementation eagerly constructs a list of nodes to traverse.
-// A better approach would be to lazily traverse the descendants.
+// The descendants are traversed lazily in document order, using an explicit stack rather than recursion.
+// The stack holds the nodes that are yet to be visited, with the next node on top.
 pub struct Descendants {
-    v: Vec<RNode>,
-    cur: usize,
+    stack: Vec<RNode>,
 }
 impl Descendants {
     fn new(n: &RNode) -> Self {
-        Descendants {
-            v: n.child_iter().fold(vec![], |mut acc, c| {
-                let mut d = descendant_add(&c);
-                acc.append(&mut d);
-                acc
-            }),
-            cur: 0,
-        }
+        let mut stack: Vec<RNode> = n.child_iter().collect();
+        stack.reverse();
+        Descendants { stack }
     }
 }
-fn descendant_add(n: &RNode) -> Vec<RNode> {
-    let mut result = vec![n.clone()];
-    n.child_iter().for_each(|c| {
-        let mut l = descendant_add(&c);
-        result.append(&mut l);
-    });
-    result
-}
 impl Iterator for Descendants {
     type Item = RNode;
 
     fn next(&mut self) -> Option<RNode> {
-        match self.v.get(self.cur) {
-            Some(n) => {
-                self.cur += 1;
-                Some(n.clone())
-            }
-            None => None,
-        }
+        let n = self.stack.pop()?;
+        let mut children: Vec<RNode> = n.child_iter().collect();
+        children.reverse();
+        self.stack.append(&mut children);
+        Some(n)
     }
 }
 
@@ -1199,7 +1346,7 @@ impl Iterator for Siblings {
             } else {
                 self.1 + self.2 as usize
             };
-            if let NodeInner::Element(_, _, _, children, _) = &self.0 .0 {
+            if let NodeInner::Element(_, _, _, children, _, _) = &self.0 .0 {
                 match children.borrow().get(newidx) {
                     Some(n) => {
                         self.1 = newidx;
@@ -1219,7 +1366,7 @@ pub struct Attributes {
 }
 impl Attributes {
     fn new(n: &RNode) -> Self {
-        if let NodeInner::Element(_, _, attributes, _, _) = &n.0 {
+        if let NodeInner::Element(_, _, attributes, _, _, _) = &n.0 {
             let b = attributes.borrow();
             Attributes {
                 it: Some(b.clone().into_iter()),
@@ -1238,6 +1385,30 @@ impl Iterator for Attributes {
     }
 }
 
+pub struct Namespaces {
+    it: Option<IntoIter<Option<String>, RNode>>,
+}
+impl Namespaces {
+    fn new(n: &RNode) -> Self {
+        if let NodeInner::Element(_, _, _, _, namespaces, _) = &n.0 {
+            let b = namespaces.borrow();
+            Namespaces {
+                it: Some(b.clone().into_iter()),
+            }
+        } else {
+            // Other types of nodes don't have namespaces, so always return None
+            Namespaces { it: None }
+        }
+    }
+}
+impl Iterator for Namespaces {
+    type Item = RNode;
+
+    fn next(&mut self) -> Option<RNode> {
+        self.it.as_mut().and_then(|i| i.next().map(|(_, n)| n))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
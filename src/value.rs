@@ -2,9 +2,10 @@
 //!
 //! An atomic value that is an item in a sequence.
 
+use crate::output::json_string;
 use crate::qname::QualifiedName;
 use crate::xdmerror::{Error, ErrorKind};
-use chrono::{DateTime, Local, NaiveDate};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
 use core::fmt;
 use rust_decimal::prelude::ToPrimitive;
 use rust_decimal::Decimal;
@@ -13,6 +14,10 @@ use rust_decimal_macros::dec;
 use std::cmp::Ordering;
 use std::convert::TryFrom;
 use std::fmt::Formatter;
+use std::str::FromStr;
+
+/// The XML Schema namespace. The names of the atomic types are in this namespace.
+pub const XSNS: &str = "http://www.w3.org/2001/XMLSchema";
 
 /// Comparison operators for values
 #[derive(Copy, Clone, Debug)]
@@ -93,7 +98,7 @@ pub enum Value {
     /// all atomic values (no lists or unions)
     AnyAtomicType,
     /// untyped atomic value
-    UntypedAtomic,
+    UntypedAtomic(String),
     Duration,
     Time(DateTime<Local>), // Ignore the date part. Perhaps use Instant instead?
     Decimal(Decimal),
@@ -152,6 +157,8 @@ impl fmt::Display for Value {
         let result = match self {
             Value::String(s) => s.to_string(),
             Value::NormalizedString(s) => s.0.to_string(),
+            Value::UntypedAtomic(s) => s.to_string(),
+            Value::Boolean(b) => b.to_string(),
             Value::Decimal(d) => d.to_string(),
             Value::Float(f) => f.to_string(),
             Value::Double(d) => d.to_string(),
@@ -168,8 +175,8 @@ impl fmt::Display for Value {
             Value::NonNegativeInteger(i) => i.0.to_string(),
             Value::PositiveInteger(i) => i.0.to_string(),
             Value::NegativeInteger(i) => i.0.to_string(),
-            Value::Time(t) => t.format("%H:%M:%S.%f").to_string(),
-            Value::DateTime(dt) => dt.format("%Y-%m-%dT%H:%M:%S%z").to_string(),
+            Value::Time(t) => t.format("%H:%M:%S%.f").to_string(),
+            Value::DateTime(dt) => dt.format("%Y-%m-%dT%H:%M:%S%.f%:z").to_string(),
             Value::Date(d) => d.format("%Y-%m-%d").to_string(),
             Value::QName(q) => q.to_string(),
             _ => "".to_string(),
@@ -188,6 +195,7 @@ impl Value {
                 !t.is_empty()
             }
             Value::NormalizedString(s) => !s.0.is_empty(),
+            Value::UntypedAtomic(s) => !s.is_empty(),
             Value::Double(n) => *n != 0.0,
             Value::Integer(i) => *i != 0,
             Value::Int(i) => *i != 0,
@@ -229,7 +237,7 @@ impl Value {
             Value::ENTITIES => "ENTITIES",
             Value::Numeric => "Numeric",
             Value::AnyAtomicType => "AnyAtomicType",
-            Value::UntypedAtomic => "UntypedAtomic",
+            Value::UntypedAtomic(_) => "UntypedAtomic",
             Value::Duration => "Duration",
             Value::Time(_) => "Time",
             Value::Decimal(_) => "Decimal",
@@ -265,6 +273,279 @@ impl Value {
             Value::QName(_) => "QName",
         }
     }
+    /// The name of the value's type, in the XML Schema namespace.
+    /// This is the most specific type of the value, so 5 is an xs:integer, 5.0 is an xs:decimal and "5" is an xs:string.
+    pub fn type_name(&self) -> QualifiedName {
+        QualifiedName::new(
+            Some(String::from(XSNS)),
+            Some(String::from("xs")),
+            self.type_local_name(),
+        )
+    }
+    fn type_local_name(&self) -> &'static str {
+        match &self {
+            Value::AnyType => "anyType",
+            Value::Untyped => "untyped",
+            Value::AnySimpleType => "anySimpleType",
+            Value::IDREFS => "IDREFS",
+            Value::NMTOKENS => "NMTOKENS",
+            Value::ENTITIES => "ENTITIES",
+            Value::Numeric => "numeric",
+            Value::AnyAtomicType => "anyAtomicType",
+            Value::UntypedAtomic(_) => "untypedAtomic",
+            Value::Duration => "duration",
+            Value::Time(_) => "time",
+            Value::Decimal(_) => "decimal",
+            Value::Float(_) => "float",
+            Value::Double(_) => "double",
+            Value::Integer(_) => "integer",
+            Value::NonPositiveInteger(_) => "nonPositiveInteger",
+            Value::NegativeInteger(_) => "negativeInteger",
+            Value::Long(_) => "long",
+            Value::Int(_) => "int",
+            Value::Short(_) => "short",
+            Value::Byte(_) => "byte",
+            Value::NonNegativeInteger(_) => "nonNegativeInteger",
+            Value::UnsignedLong(_) => "unsignedLong",
+            Value::UnsignedInt(_) => "unsignedInt",
+            Value::UnsignedShort(_) => "unsignedShort",
+            Value::UnsignedByte(_) => "unsignedByte",
+            Value::PositiveInteger(_) => "positiveInteger",
+            Value::DateTime(_) => "dateTime",
+            Value::DateTimeStamp => "dateTimeStamp",
+            Value::Date(_) => "date",
+            Value::String(_) => "string",
+            Value::NormalizedString(_) => "normalizedString",
+            Value::Token => "token",
+            Value::Language => "language",
+            Value::NMTOKEN => "NMTOKEN",
+            Value::Name => "Name",
+            Value::NCName => "NCName",
+            Value::ID => "ID",
+            Value::IDREF => "IDREF",
+            Value::ENTITY => "ENTITY",
+            Value::Boolean(_) => "boolean",
+            Value::QName(_) => "QName",
+        }
+    }
+    /// Is the value an instance of the given type? That is, is the type of the value the same as, or derived from, the given type.
+    /// For example, an xs:int is an instance of xs:integer and xs:decimal, but not xs:double or xs:string.
+    pub fn instance_of(&self, t: &QualifiedName) -> bool {
+        if t.get_nsuri_ref() != Some(XSNS) {
+            return false;
+        }
+        let target = t.get_localname();
+        if target == "numeric" {
+            return ["decimal", "float", "double"].iter().any(|n| {
+                self.instance_of(&QualifiedName::new(Some(String::from(XSNS)), None, *n))
+            });
+        }
+        let mut current = Some(self.type_local_name());
+        while let Some(c) = current {
+            if c == target {
+                return true;
+            }
+            current = base_type(c);
+        }
+        false
+    }
+    /// The value as it is serialized by the adaptive output method. See Serialization 3.1, 10.
+    /// Strings are quoted, booleans are true() or false(), doubles use exponential notation,
+    /// and values of other non-numeric types are written as a constructor function, e.g. xs:date("2024-01-01").
+    pub fn to_adaptive(&self) -> String {
+        let xs = |t: &str| QualifiedName::new(Some(String::from(XSNS)), None, t);
+        match self {
+            Value::Boolean(b) => format!("{}()", b),
+            Value::Double(d) if d.is_nan() => String::from("NaN"),
+            Value::Double(d) if d.is_infinite() => {
+                String::from(if *d > 0.0 { "INF" } else { "-INF" })
+            }
+            Value::Double(d) => {
+                let e = format!("{:e}", d);
+                match e.split_once('e') {
+                    Some((m, x)) if !m.contains('.') => format!("{}.0e{}", m, x),
+                    _ => e,
+                }
+            }
+            _ if self.instance_of(&xs("string"))
+                || self.instance_of(&xs("untypedAtomic"))
+                || self.instance_of(&xs("anyURI")) =>
+            {
+                format!("\"{}\"", self.to_string().replace('"', "\"\""))
+            }
+            _ if self.instance_of(&xs("decimal")) => self.to_string(),
+            _ => format!("{}(\"{}\")", self.type_name(), self.to_string()),
+        }
+    }
+    /// The value as JSON, for the json output method. See Serialization 3.1, 11.
+    /// Numbers and booleans are JSON numbers and booleans; all other values are JSON strings.
+    /// It is an error (SERE0020) if a number is infinite or NaN.
+    pub fn to_json_value(&self) -> Result<String, Error> {
+        let xs = |t: &str| QualifiedName::new(Some(String::from(XSNS)), None, t);
+        match self {
+            Value::Boolean(b) => Ok(b.to_string()),
+            Value::Double(d) if !d.is_finite() => Err(not_json(self)),
+            Value::Float(f) if !f.is_finite() => Err(not_json(self)),
+            _ if self.instance_of(&xs("numeric")) => Ok(self.to_string()),
+            _ => Ok(json_string(self.to_string().as_str())),
+        }
+    }
+    /// Cast the value to the given atomic type. See XPath Functions 19, Casting.
+    /// A string or untyped value is cast using the lexical form of the target type.
+    /// Errors are:
+    /// * XPST0051 if the type is not an atomic type, XPST0080 if it is an abstract type,
+    /// * XPTY0004 if values of this type cannot be cast to the target type,
+    /// * FORG0001 if the value is not valid for the target type,
+    /// * FOCA0001, FOCA0002 or FOCA0003 if a number cannot be represented in the target type.
+    pub fn cast_to(&self, t: &QualifiedName) -> Result<Value, Error> {
+        if t.get_nsuri_ref() != Some(XSNS) {
+            return Err(cast_error(
+                ErrorKind::StaticData,
+                format!("{} is not an atomic type", t),
+                "XPST0051",
+            ));
+        }
+        let target = t.get_localname();
+        match target.as_str() {
+            "anyType" | "untyped" | "anySimpleType" | "anyAtomicType" | "numeric" | "NOTATION" => {
+                return Err(cast_error(
+                    ErrorKind::StaticData,
+                    format!("cannot cast to the abstract type xs:{}", target),
+                    "XPST0080",
+                ))
+            }
+            "string" | "normalizedString" | "untypedAtomic" | "boolean" | "decimal" | "float"
+            | "double" | "date" | "dateTime" | "time" | "QName" => {}
+            t if is_integer_type(t) => {}
+            "token" | "language" | "NMTOKEN" | "Name" | "NCName" | "ID" | "IDREF" | "ENTITY"
+            | "IDREFS" | "NMTOKENS" | "ENTITIES" | "duration" | "yearMonthDuration"
+            | "dayTimeDuration" | "dateTimeStamp" | "gYearMonth" | "gYear" | "gMonthDay"
+            | "gDay" | "gMonth" | "hexBinary" | "base64Binary" | "anyURI" => {
+                return Err(Error::new(
+                    ErrorKind::NotImplemented,
+                    format!("casting to xs:{} is not supported", target),
+                ))
+            }
+            _ => {
+                return Err(cast_error(
+                    ErrorKind::StaticData,
+                    format!("xs:{} is not an atomic type", target),
+                    "XPST0051",
+                ))
+            }
+        }
+        match self {
+            Value::String(_) | Value::NormalizedString(_) | Value::UntypedAtomic(_) => {
+                from_lexical(self.to_string().as_str(), target.as_str())
+            }
+            _ => self.cast_typed(target.as_str()),
+        }
+    }
+    // Cast a value that is not a string to one of the supported atomic types.
+    fn cast_typed(&self, target: &str) -> Result<Value, Error> {
+        let not_allowed = || {
+            cast_error(
+                ErrorKind::TypeError,
+                format!("cannot cast xs:{} to xs:{}", self.type_local_name(), target),
+                "XPTY0004",
+            )
+        };
+        let number = self.instance_of(&QualifiedName::new(
+            Some(String::from(XSNS)),
+            None,
+            "numeric",
+        ));
+        match (target, self) {
+            ("string", _) => Ok(Value::String(self.to_string())),
+            ("untypedAtomic", _) => Ok(Value::UntypedAtomic(self.to_string())),
+            ("normalizedString", _) => {
+                Ok(Value::NormalizedString(NormalizedString(self.to_string())))
+            }
+            ("boolean", Value::Boolean(b)) => Ok(Value::Boolean(*b)),
+            ("boolean", _) if number => {
+                let d = self.to_f64();
+                Ok(Value::Boolean(d != 0.0 && !d.is_nan()))
+            }
+            ("decimal", Value::Boolean(b)) => Ok(Value::Decimal(Decimal::from(*b as i64))),
+            ("decimal", Value::Decimal(d)) => Ok(Value::Decimal(*d)),
+            ("decimal", Value::Float(_) | Value::Double(_)) => {
+                let d = finite(self.to_f64())?;
+                Decimal::from_f64_retain(d)
+                    .map(|d| Value::Decimal(d.normalize()))
+                    .ok_or_else(|| {
+                        cast_error(
+                            ErrorKind::TypeError,
+                            format!("{} is too large for xs:decimal", d),
+                            "FOCA0001",
+                        )
+                    })
+            }
+            ("decimal", _) if number => self
+                .to_integer()
+                .and_then(|i| i64::try_from(i).ok())
+                .map(|i| Value::Decimal(Decimal::from(i)))
+                .ok_or_else(not_allowed),
+            ("double", Value::Boolean(b)) => Ok(Value::Double(*b as i64 as f64)),
+            ("double", _) if number => Ok(Value::Double(self.to_f64())),
+            ("float", Value::Boolean(b)) => Ok(Value::Float(*b as i64 as f32)),
+            ("float", _) if number => Ok(Value::Float(self.to_f64() as f32)),
+            (t, Value::Boolean(b)) if is_integer_type(t) => integer_value(*b as i128, t),
+            (t, Value::Decimal(d)) if is_integer_type(t) => d
+                .trunc()
+                .to_i128()
+                .ok_or_else(|| too_large(d.to_string()))
+                .and_then(|i| integer_value(i, t)),
+            (t, Value::Float(_) | Value::Double(_)) if is_integer_type(t) => {
+                let d = finite(self.to_f64())?.trunc();
+                if d.abs() < 1e38 {
+                    integer_value(d as i128, t)
+                } else {
+                    Err(too_large(d.to_string()))
+                }
+            }
+            (t, _) if is_integer_type(t) && number => {
+                integer_value(self.to_integer().ok_or_else(not_allowed)?, t)
+            }
+            ("date", Value::Date(d)) => Ok(Value::Date(*d)),
+            ("date", Value::DateTime(dt)) => Ok(Value::Date(dt.date_naive())),
+            ("dateTime", Value::DateTime(dt)) => Ok(Value::DateTime(*dt)),
+            ("dateTime", Value::Date(d)) => d
+                .and_hms_opt(0, 0, 0)
+                .and_then(|dt| Local.from_local_datetime(&dt).earliest())
+                .map(Value::DateTime)
+                .ok_or_else(not_allowed),
+            ("time", Value::Time(t) | Value::DateTime(t)) => Ok(Value::Time(*t)),
+            ("QName", Value::QName(q)) => Ok(Value::QName(q.clone())),
+            _ => Err(not_allowed()),
+        }
+    }
+    // The value of a number of an integer type.
+    pub(crate) fn to_integer(&self) -> Option<i128> {
+        match self {
+            Value::Integer(i) | Value::Long(i) => Some(*i as i128),
+            Value::Int(i) => Some(*i as i128),
+            Value::Short(i) => Some(*i as i128),
+            Value::Byte(i) => Some(*i as i128),
+            Value::UnsignedLong(i) => Some(*i as i128),
+            Value::UnsignedInt(i) => Some(*i as i128),
+            Value::UnsignedShort(i) => Some(*i as i128),
+            Value::UnsignedByte(i) => Some(*i as i128),
+            Value::NonPositiveInteger(i) => Some(i.0 as i128),
+            Value::NegativeInteger(i) => Some(i.0 as i128),
+            Value::NonNegativeInteger(i) => Some(i.0 as i128),
+            Value::PositiveInteger(i) => Some(i.0 as i128),
+            _ => None,
+        }
+    }
+    // The value of a number as a double.
+    pub(crate) fn to_f64(&self) -> f64 {
+        match self {
+            Value::Double(d) => *d,
+            Value::Float(f) => *f as f64,
+            Value::Decimal(d) => d.to_f64().unwrap_or(f64::NAN),
+            _ => self.to_integer().map_or(f64::NAN, |i| i as f64),
+        }
+    }
     pub fn compare(&self, other: &Value, op: Operator) -> Result<bool, Error> {
         match &self {
             Value::Boolean(b) => {
@@ -353,6 +634,253 @@ impl Value {
     }
 }
 
+// The type that a built-in atomic type is derived from.
+fn base_type(t: &str) -> Option<&'static str> {
+    match t {
+        "untypedAtomic" | "string" | "boolean" | "decimal" | "float" | "double" | "duration"
+        | "dateTime" | "time" | "date" | "QName" => Some("anyAtomicType"),
+        "integer" => Some("decimal"),
+        "nonPositiveInteger" | "long" | "nonNegativeInteger" => Some("integer"),
+        "negativeInteger" => Some("nonPositiveInteger"),
+        "int" => Some("long"),
+        "short" => Some("int"),
+        "byte" => Some("short"),
+        "unsignedLong" | "positiveInteger" => Some("nonNegativeInteger"),
+        "unsignedInt" => Some("unsignedLong"),
+        "unsignedShort" => Some("unsignedInt"),
+        "unsignedByte" => Some("unsignedShort"),
+        "dateTimeStamp" => Some("dateTime"),
+        "normalizedString" => Some("string"),
+        "token" => Some("normalizedString"),
+        "language" | "NMTOKEN" | "Name" => Some("token"),
+        "NCName" => Some("Name"),
+        "ID" | "IDREF" | "ENTITY" => Some("NCName"),
+        "anyAtomicType" | "IDREFS" | "NMTOKENS" | "ENTITIES" => Some("anySimpleType"),
+        "anySimpleType" | "untyped" => Some("anyType"),
+        _ => None,
+    }
+}
+
+fn is_integer_type(t: &str) -> bool {
+    matches!(
+        t,
+        "integer"
+            | "nonPositiveInteger"
+            | "negativeInteger"
+            | "long"
+            | "int"
+            | "short"
+            | "byte"
+            | "nonNegativeInteger"
+            | "unsignedLong"
+            | "unsignedInt"
+            | "unsignedShort"
+            | "unsignedByte"
+            | "positiveInteger"
+    )
+}
+
+fn cast_error(kind: ErrorKind, message: String, code: &str) -> Error {
+    Error::new_with_code(kind, message, Some(QualifiedName::new(None, None, code)))
+}
+
+fn not_json(v: &Value) -> Error {
+    Error::new_with_code(
+        ErrorKind::TypeError,
+        format!("{} cannot be serialized as a JSON number", v),
+        Some(QualifiedName::new(None, None, "SERE0020")),
+    )
+}
+
+fn invalid(s: &str, t: &str) -> Error {
+    cast_error(
+        ErrorKind::TypeError,
+        format!("\"{}\" is not a valid xs:{}", s, t),
+        "FORG0001",
+    )
+}
+
+fn too_large(s: String) -> Error {
+    cast_error(
+        ErrorKind::TypeError,
+        format!("{} is too large for xs:integer", s),
+        "FOCA0003",
+    )
+}
+
+// NaN and infinity cannot be cast to xs:decimal or xs:integer
+fn finite(d: f64) -> Result<f64, Error> {
+    if d.is_finite() {
+        Ok(d)
+    } else {
+        Err(cast_error(
+            ErrorKind::TypeError,
+            format!(
+                "{} cannot be cast to a decimal or integer",
+                Value::Double(d)
+            ),
+            "FOCA0002",
+        ))
+    }
+}
+
+// An integer of one of the integer types. Values outside the range of the type are invalid.
+fn integer_value(i: i128, t: &str) -> Result<Value, Error> {
+    let out_of_range = |_| invalid(i.to_string().as_str(), t);
+    match t {
+        "integer" => i64::try_from(i)
+            .map(Value::Integer)
+            .map_err(|_| too_large(i.to_string())),
+        "long" => i64::try_from(i).map(Value::Long).map_err(out_of_range),
+        "int" => i32::try_from(i).map(Value::Int).map_err(out_of_range),
+        "short" => i16::try_from(i).map(Value::Short).map_err(out_of_range),
+        "byte" => i8::try_from(i).map(Value::Byte).map_err(out_of_range),
+        "unsignedLong" => u64::try_from(i)
+            .map(Value::UnsignedLong)
+            .map_err(out_of_range),
+        "unsignedInt" => u32::try_from(i)
+            .map(Value::UnsignedInt)
+            .map_err(out_of_range),
+        "unsignedShort" => u16::try_from(i)
+            .map(Value::UnsignedShort)
+            .map_err(out_of_range),
+        "unsignedByte" => u8::try_from(i)
+            .map(Value::UnsignedByte)
+            .map_err(out_of_range),
+        _ => {
+            let j = i64::try_from(i).map_err(|_| too_large(i.to_string()))?;
+            let v = match t {
+                "nonPositiveInteger" => {
+                    NonPositiveInteger::try_from(j).map(Value::NonPositiveInteger)
+                }
+                "negativeInteger" => NegativeInteger::try_from(j).map(Value::NegativeInteger),
+                "nonNegativeInteger" => {
+                    NonNegativeInteger::try_from(j).map(Value::NonNegativeInteger)
+                }
+                _ => PositiveInteger::try_from(j).map(Value::PositiveInteger),
+            };
+            v.map_err(|_| invalid(i.to_string().as_str(), t))
+        }
+    }
+}
+
+// Cast a string to one of the supported atomic types, using the lexical form of the type.
+// Whitespace is collapsed for all types except the string types.
+fn from_lexical(s: &str, t: &str) -> Result<Value, Error> {
+    let w = s.trim_matches(|c| matches!(c, ' ' | '\t' | '\n' | '\r'));
+    match t {
+        "string" => Ok(Value::String(s.to_string())),
+        "untypedAtomic" => Ok(Value::UntypedAtomic(s.to_string())),
+        "normalizedString" => Ok(Value::NormalizedString(NormalizedString(
+            s.replace(['\t', '\n', '\r'], " "),
+        ))),
+        "boolean" => match w {
+            "true" | "1" => Ok(Value::Boolean(true)),
+            "false" | "0" => Ok(Value::Boolean(false)),
+            _ => Err(invalid(s, t)),
+        },
+        "decimal" => {
+            if is_decimal_lexical(w) {
+                Decimal::from_str(w.strip_prefix('+').unwrap_or(w))
+                    .map(|d| Value::Decimal(d.normalize()))
+                    .map_err(|_| {
+                        cast_error(
+                            ErrorKind::TypeError,
+                            format!("{} is too large for xs:decimal", w),
+                            "FOCA0001",
+                        )
+                    })
+            } else {
+                Err(invalid(s, t))
+            }
+        }
+        "double" | "float" => {
+            let d = match w {
+                "INF" | "+INF" => f64::INFINITY,
+                "-INF" => f64::NEG_INFINITY,
+                "NaN" => f64::NAN,
+                _ => {
+                    let (m, e) = w.split_once(['e', 'E']).unwrap_or((w, "0"));
+                    if is_decimal_lexical(m) && is_integer_lexical(e) {
+                        w.parse::<f64>().map_err(|_| invalid(s, t))?
+                    } else {
+                        return Err(invalid(s, t));
+                    }
+                }
+            };
+            if t == "double" {
+                Ok(Value::Double(d))
+            } else {
+                Ok(Value::Float(d as f32))
+            }
+        }
+        "date" => NaiveDate::parse_from_str(without_timezone(w), "%Y-%m-%d")
+            .map(Value::Date)
+            .map_err(|_| invalid(s, t)),
+        "dateTime" => DateTime::parse_from_rfc3339(w)
+            .map(|dt| dt.with_timezone(&Local))
+            .ok()
+            .or_else(|| {
+                NaiveDateTime::parse_from_str(w, "%Y-%m-%dT%H:%M:%S%.f")
+                    .ok()
+                    .and_then(|dt| Local.from_local_datetime(&dt).earliest())
+            })
+            .map(Value::DateTime)
+            .ok_or_else(|| invalid(s, t)),
+        "time" => NaiveTime::parse_from_str(without_timezone(w), "%H:%M:%S%.f")
+            .ok()
+            .and_then(|tm| {
+                Local
+                    .from_local_datetime(&Local::now().date_naive().and_time(tm))
+                    .earliest()
+            })
+            .map(Value::Time)
+            .ok_or_else(|| invalid(s, t)),
+        _ if is_integer_type(t) => {
+            if is_integer_lexical(w) {
+                integer_value(w.parse::<i128>().map_err(|_| too_large(w.to_string()))?, t)
+            } else {
+                Err(invalid(s, t))
+            }
+        }
+        _ => Err(cast_error(
+            ErrorKind::TypeError,
+            format!("cannot cast a string to xs:{}", t),
+            "XPTY0004",
+        )),
+    }
+}
+
+// An optional sign followed by digits
+fn is_integer_lexical(s: &str) -> bool {
+    let d = s.strip_prefix(['+', '-']).unwrap_or(s);
+    !d.is_empty() && d.chars().all(|c| c.is_ascii_digit())
+}
+
+// An optional sign followed by digits, with an optional decimal point
+fn is_decimal_lexical(s: &str) -> bool {
+    let d = s.strip_prefix(['+', '-']).unwrap_or(s);
+    let (whole, fraction) = d.split_once('.').unwrap_or((d, ""));
+    !(whole.is_empty() && fraction.is_empty())
+        && whole.chars().all(|c| c.is_ascii_digit())
+        && fraction.chars().all(|c| c.is_ascii_digit())
+}
+
+// The timezone of a date or time is not kept
+fn without_timezone(s: &str) -> &str {
+    if let Some(t) = s.strip_suffix('Z') {
+        t
+    } else if s.len() > 6
+        && s.is_char_boundary(s.len() - 6)
+        && s[s.len() - 6..].starts_with(['+', '-'])
+        && s[s.len() - 3..].starts_with(':')
+    {
+        &s[..s.len() - 6]
+    } else {
+        s
+    }
+}
+
 impl PartialEq for Value {
     fn eq(&self, other: &Value) -> bool {
         match self {
@@ -903,4 +1431,31 @@ mod tests {
     fn op_after() {
         assert_eq!(Operator::After.to_string(), ">>")
     }
+
+    fn xs(t: &str) -> QualifiedName {
+        QualifiedName::new(Some(String::from(XSNS)), Some(String::from("xs")), t)
+    }
+    #[test]
+    fn type_name_specific() {
+        assert_eq!(Value::from(5_i64).type_name().to_string(), "xs:integer");
+        assert_eq!(Value::from(dec!(5.0)).type_name().to_string(), "xs:decimal");
+        assert_eq!(Value::from("5").type_name().to_string(), "xs:string")
+    }
+    #[test]
+    fn instance_of_base_type() {
+        assert!(Value::UnsignedByte(5).instance_of(&xs("nonNegativeInteger")));
+        assert!(Value::UnsignedByte(5).instance_of(&xs("numeric")));
+        assert!(!Value::UnsignedByte(5).instance_of(&xs("byte")))
+    }
+    #[test]
+    fn cast_string_to_double() {
+        assert_eq!(
+            Value::from("-INF")
+                .cast_to(&xs("double"))
+                .expect("cast failed")
+                .to_f64(),
+            f64::NEG_INFINITY
+        );
+        assert!(Value::from("inf").cast_to(&xs("double")).is_err())
+    }
 }
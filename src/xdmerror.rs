@@ -35,6 +35,10 @@ pub enum ErrorKind {
     NotImplemented,
     ParseError,
     Unknown,
+    /// A processing limit, such as the depth of template invocation, has been reached
+    LimitExceeded,
+    /// An operation is not permitted, such as accessing external resources during secure processing
+    Forbidden,
 }
 impl ErrorKind {
     /// String representation of error
@@ -52,6 +56,8 @@ impl ErrorKind {
             ErrorKind::NotNodes => "path expression is not a sequence of nodes",
             ErrorKind::ContextNotNode => "context item is not a node for an axis step",
             ErrorKind::Terminated => "application has voluntarily terminated processing",
+            ErrorKind::LimitExceeded => "a processing limit has been exceeded",
+            ErrorKind::Forbidden => "operation not permitted",
             ErrorKind::NotImplemented => "not implemented",
             ErrorKind::Unknown => "unknown",
             ErrorKind::ParseError => "XML Parse error",
@@ -65,12 +71,34 @@ impl fmt::Display for ErrorKind {
     }
 }
 
+/// Where a construct occurs in the text it was parsed from, such as an instruction in a stylesheet module.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Location {
+    /// The URI of the module, if it is known
+    pub module: Option<String>,
+    /// The line number, starting at 1
+    pub line: usize,
+    /// The column number, starting at 1
+    pub column: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.module {
+            Some(m) => write!(f, "{}:{}:{}", m, self.line, self.column),
+            None => write!(f, "{}:{}", self.line, self.column),
+        }
+    }
+}
+
 /// An error returned by an XPath, XQuery or XSLT function/method
 #[derive(Clone)]
 pub struct Error {
     pub kind: ErrorKind,
     pub message: String,
     pub code: Option<QualifiedName>,
+    /// Where the error was raised, if it is known
+    pub location: Option<Box<Location>>,
 }
 
 impl std::error::Error for Error {}
@@ -81,6 +109,7 @@ impl Error {
             kind,
             message: message.into(),
             code: None,
+            location: None,
         }
     }
     pub fn new_with_code(
@@ -92,6 +121,7 @@ impl Error {
             kind,
             message: message.into(),
             code,
+            location: None,
         }
     }
 }
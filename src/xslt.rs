@@ -68,19 +68,22 @@ let seq = ctxt.evaluate(&mut static_context)
 assert_eq!(seq.to_xml(), "<html><head><title>XSLT in Rust</title></head><body><p>A simple document.</p></body></html>")
  */
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 
 use crate::item::{Item, Node, NodeType, Sequence};
 use crate::output::*;
 use crate::parser::avt::parse as parse_avt;
-use crate::parser::xpath::parse;
+use crate::parser::xpath::{parse, parse_sequence_type};
 use crate::pattern::Pattern;
 use crate::qname::*;
 use crate::transform::callable::{ActualParameters, Callable, FormalParameters};
 use crate::transform::context::{Context, ContextBuilder};
-use crate::transform::numbers::{Level, Numbering};
+use crate::transform::numbers::{Level, NumberFormat, Numbering};
+use crate::transform::strings::builtin_collation;
 use crate::transform::template::Template;
+use crate::transform::types::resolve_sequence_type;
+pub use crate::transform::whitespace::strip_whitespace;
 use crate::transform::{
     Axis, Grouping, KindTest, NameTest, NodeMatch, NodeTest, Order, Transform, WildcardOrName,
 };
@@ -114,6 +117,35 @@ pub trait XSLT: Node {
     //    }
 }
 
+/// Compiles an already parsed stylesheet into a transformation [Context].
+/// This is [from_document] for a stylesheet that does not include or import other modules,
+/// so no closures are needed to fetch and parse them.
+/// An xsl:include or xsl:import element is an error.
+/// NB. Due to whitespace stripping, this is destructive of the stylesheet.
+pub fn from_tree<N: Node>(
+    styledoc: N,
+    stylens: Vec<HashMap<String, String>>,
+    base: Option<Url>,
+) -> Result<Context<N>, Error> {
+    from_document(
+        styledoc,
+        stylens,
+        base,
+        |_| {
+            Err(Error::new(
+                ErrorKind::NotImplemented,
+                "unable to parse stylesheet module",
+            ))
+        },
+        |u| {
+            Err(Error::new(
+                ErrorKind::NotImplemented,
+                format!("unable to fetch stylesheet module \"{}\"", u),
+            ))
+        },
+    )
+}
+
 /// Compiles a [Node] into a transformation [Context].
 /// NB. Due to whitespace stripping, this is destructive of the stylesheet.
 /// The argument f is a closure that parses a string to a [Node].
@@ -131,6 +163,9 @@ where
     F: Fn(&str) -> Result<N, Error>,
     G: Fn(&Url) -> Result<String, Error>,
 {
+    // Strip whitespace, comments and processing instructions from the stylesheet
+    strip_stylesheet(styledoc.clone())?;
+
     // Check that this is a valid XSLT stylesheet
     // There must be a single element as a child of the root node, and it must be named xsl:stylesheet or xsl:transform
     let mut rnit = styledoc.child_iter();
@@ -162,92 +197,25 @@ where
         ));
     }
 
-    // TODO: check version attribute
-
-    // Strip whitespace from the stylesheet
-    strip_whitespace(
-        styledoc.clone(),
-        true,
-        &vec![NodeTest::Name(NameTest {
-            ns: None,
-            prefix: None,
-            name: Some(WildcardOrName::Wildcard),
-        })],
-        &vec![NodeTest::Name(NameTest {
-            ns: Some(WildcardOrName::Name(XSLTNS.to_string())),
-            prefix: Some("xsl".to_string()),
-            name: Some(WildcardOrName::Name("text".to_string())),
-        })],
-    )?;
-
-    // Setup the serialization of the primary result document
-    let mut od = OutputDefinition::new();
-    if let Some(c) = stylenode.child_iter().find(|c| {
-        !(c.is_element()
-            && c.name().get_nsuri_ref() == Some(XSLTNS)
-            && c.name().get_localname() == "output")
-    }) {
-        let b: bool = matches!(
-            c.get_attribute(&QualifiedName::new(None, None, "indent".to_string()))
-                .to_string()
-                .as_str(),
-            "yes" | "true" | "1"
-        );
-
-        od.set_indent(b);
-    };
+    module_defaults(&stylenode)?;
 
     // Iterate over children, looking for includes
     // * resolve href
     // * fetch document
     // * parse XML
     // * replace xsl:include element with content
-    stylenode
-        .child_iter()
-        .filter(|c| {
-            c.is_element()
-                && c.name().get_nsuri_ref() == Some(XSLTNS)
-                && c.name().get_localname() == "include"
-        })
-        .try_for_each(|mut c| {
-            let h = c.get_attribute(&QualifiedName::new(None, None, "href".to_string()));
-            let url = match base.clone().map_or_else(
-                || Url::parse(h.to_string().as_str()),
-                |full| full.join(h.to_string().as_str()),
-            ) {
-                Ok(u) => u,
-                Err(_) => {
-                    return Result::Err(Error::new(
-                        ErrorKind::Unknown,
-                        format!(
-                            "unable to parse href URL \"{}\" baseurl \"{}\"",
-                            h,
-                            base.clone()
-                                .map_or(String::from("--no base--"), |b| b.to_string())
-                        ),
-                    ));
-                }
-            };
-            let xml = g(&url)?;
-            let module = f(xml.as_str().trim())?;
-            // TODO: check that the module is a valid XSLT stylesheet, etc
-            // Copy each top-level element of the module to the main stylesheet,
-            // inserting before the xsl:include node
-            let moddoc = module.first_child().unwrap();
-            moddoc.child_iter().try_for_each(|mc| {
-                c.insert_before(mc)?;
-                Ok::<(), Error>(())
-            })?;
-            // Remove the xsl:include element node
-            c.pop()?;
-            Ok(())
-        })?;
+    // Included modules may themselves include modules.
+    // The absolute URL of a module identifies it, so a module is only included once.
+    let mut active: Vec<Url> = base.iter().cloned().collect();
+    let mut loaded = HashSet::new();
+    include_modules(&stylenode, &base, &f, &g, &mut active, &mut loaded)?;
 
     // Iterate over children, looking for imports
     // * resolve href
     // * fetch document
     // * parse XML
     // * replace xsl:import element with content
+    let mut imported = HashSet::new();
     stylenode
         .child_iter()
         .filter(|c| {
@@ -255,32 +223,45 @@ where
                 && c.name().get_nsuri_ref() == Some(XSLTNS)
                 && c.name().get_localname() == "import"
         })
+        .collect::<Vec<_>>()
+        .into_iter()
         .try_for_each(|mut c| {
-            let h = c.get_attribute(&QualifiedName::new(None, None, "href".to_string()));
-            let url = match base.clone().map_or_else(
-                || Url::parse(h.to_string().as_str()),
-                |full| full.join(h.to_string().as_str()),
-            ) {
-                Ok(u) => u,
-                Err(_) => {
-                    return Result::Err(Error::new(
-                        ErrorKind::Unknown,
-                        format!(
-                            "unable to parse href URL \"{}\" baseurl \"{}\"",
-                            h,
-                            base.clone()
-                                .map_or(String::from("--no base--"), |b| b.to_string())
-                        ),
-                    ));
-                }
-            };
+            let url = module_url(&c, &base)?;
+            if active.contains(&url) {
+                return Err(Error::new_with_code(
+                    ErrorKind::StaticData,
+                    format!(
+                        "stylesheet module imports itself: {}",
+                        module_cycle(&active, &url)
+                    ),
+                    Some(QualifiedName::new(None, None, "XTSE0210")),
+                ));
+            }
+            if !imported.insert(url.clone()) {
+                // The module has already been imported
+                c.pop()?;
+                return Ok(());
+            }
             let xml = g(&url)?;
             let module = f(xml.as_str().trim())?;
             // TODO: check that the module is a valid XSLT stylesheet, etc
+            strip_stylesheet(module.clone())?;
+            let moddoc = module_element(&module, &url)?;
+            module_defaults(&moddoc)?;
+            // The imported module's own includes are at the precedence of the imported module
+            active.push(url.clone());
+            include_modules(
+                &moddoc,
+                &Some(url.clone()),
+                &f,
+                &g,
+                &mut active,
+                &mut HashSet::new(),
+            )?;
+            active.pop();
+            mark_module(&moddoc, &url)?;
             // Copy each top-level element of the module to the main stylesheet,
             // inserting before the xsl:include node
-            // TODO: Don't Panic
-            let moddoc = module.first_child().unwrap();
             moddoc.child_iter().try_for_each(|mc| {
                 if mc.node_type() == NodeType::Element {
                     // Add the import precedence attribute
@@ -306,6 +287,36 @@ where
             Ok::<(), Error>(())
         })?;
 
+    // The remaining top-level elements are from the principal stylesheet module
+    if let Some(b) = &base {
+        mark_module(&stylenode, b)?
+    }
+
+    // Check the attributes and the placement of every XSLT element, so that a typo is not silently ignored
+    validate_elements(&stylenode)?;
+
+    // Setup the serialization of the primary result document,
+    // and the named output definitions for secondary result documents
+    let mut od = OutputDefinition::new();
+    let mut outputs = HashMap::new();
+    stylenode
+        .child_iter()
+        .filter(|c| {
+            c.is_element()
+                && c.name().get_nsuri_ref() == Some(XSLTNS)
+                && c.name().get_localname() == "output"
+        })
+        .try_for_each(|c| {
+            let o = output_definition(&c)?;
+            match o.get_name() {
+                None => od = o,
+                Some(qn) => {
+                    outputs.insert(qn, o);
+                }
+            }
+            Ok::<(), Error>(())
+        })?;
+
     // Find named attribute sets
 
     // Store for named attribute sets
@@ -344,6 +355,40 @@ where
             Ok(())
         })?;
 
+    // Every template must have a match pattern, a name, or both.
+    // A template without a match pattern is never invoked by xsl:apply-templates, so it cannot have a mode or priority.
+    stylenode
+        .child_iter()
+        .filter(|c| {
+            c.is_element()
+                && c.name().get_nsuri_ref() == Some(XSLTNS)
+                && c.name().get_localname() == "template"
+        })
+        .try_for_each(|c| {
+            let has = |a: &str| {
+                c.get_attribute_node(&QualifiedName::new(None, None, a))
+                    .is_some()
+            };
+            let msg = if has("match") {
+                return Ok(());
+            } else if !has("name") {
+                "xsl:template must have a match attribute, a name attribute, or both"
+            } else if has("mode") {
+                "xsl:template without a match attribute must not have a mode attribute"
+            } else if has("priority") {
+                "xsl:template without a match attribute must not have a priority attribute"
+            } else {
+                return Ok(());
+            };
+            let mut e = Error::new_with_code(
+                ErrorKind::StaticData,
+                msg,
+                Some(QualifiedName::new(None, None, "XTSE0500")),
+            );
+            e.location = instruction_location(&c).map(Box::new);
+            Err(e)
+        })?;
+
     // Iterate over children, looking for templates
     // * compile match pattern
     // * compile content into sequence constructor
@@ -356,20 +401,25 @@ where
                 && c.name().get_nsuri_ref() == Some(XSLTNS)
                 && c.name().get_localname() == "template"
         })
-        .filter(|c| {
+        .enumerate()
+        .filter(|(_, c)| {
             !c.get_attribute(&QualifiedName::new(None, None, "match"))
                 .to_string()
                 .is_empty()
         })
-        .try_for_each(|c| {
+        .try_for_each(|(i, c)| {
             let m = c.get_attribute(&QualifiedName::new(None, None, "match"));
             let pat = Pattern::try_from(m.to_string())?;
             let mut body = vec![];
             let mode = c.get_attribute_node(&QualifiedName::new(None, None, "mode"));
-            c.child_iter().try_for_each(|d| {
-                body.push(to_transform(d, &stylens, &attr_sets)?);
-                Ok::<(), Error>(())
-            })?;
+            c.child_iter()
+                .filter(|d| !is_tunnel_param(d))
+                .try_for_each(|d| {
+                    body.push(direct_output(to_transform(d, &stylens, &attr_sets)?));
+                    Ok::<(), Error>(())
+                })?;
+            let body =
+                declare_tunnel_params(&c, Transform::SequenceItems(body), &stylens, &attr_sets)?;
             //sc.static_analysis(&mut pat);
             //sc.static_analysis(&mut body);
             // Determine the priority of the template
@@ -387,8 +437,9 @@ where
                             let ((t, nt), q) = s.clone().t.unwrap();
                             // If "/" then -0.5
                             match (t, nt) {
-                                (Axis::SelfAttribute, _) => -0.5,
                                 (Axis::SelfAxis, Axis::Parent)
+                                | (Axis::SelfAttribute, Axis::Parent)
+                                | (Axis::SelfNamespace, Axis::Parent)
                                 | (Axis::SelfAxis, Axis::Ancestor)
                                 | (Axis::SelfAxis, Axis::AncestorOrSelf) => match q {
                                     NodeTest::Name(nm) => match nm.name {
@@ -416,17 +467,21 @@ where
             if im.to_string() != "" {
                 import = im.to_int()? as usize
             }
-            templates.push(Template::new(
-                pat,
-                Transform::SequenceItems(body),
-                Some(prio),
-                vec![import],
-                None,
-                mode.map(|n| {
-                    QualifiedName::try_from((n.to_string().as_str(), &stylens))
-                        .expect("unable to resolve qualified name")
-                }), // TODO: don't panic
-            ));
+            templates.push(
+                Template::new(
+                    pat,
+                    body,
+                    Some(prio),
+                    vec![import],
+                    Some(templates.len()),
+                    mode.map(|n| QualifiedName::try_from((n.to_string().as_str(), &stylens)))
+                        .transpose()?,
+                )
+                .source(
+                    m.to_string(),
+                    format!("/{}/xsl:template[{}]", stylenode.name(), i + 1),
+                ),
+            );
             Ok::<(), Error>(())
         })?;
 
@@ -449,57 +504,114 @@ where
             Ok(())
         })?;
 
-    let mut newctxt = ContextBuilder::new()
+    // The default collation is the first URI in the list that is recognised
+    let dc = stylenode
+        .get_attribute(&QualifiedName::new(None, None, "default-collation"))
+        .to_string();
+    let default_collation = dc
+        .split_whitespace()
+        .find(|u| builtin_collation(u).is_some())
+        .map(String::from);
+    if !dc.trim().is_empty() && default_collation.is_none() {
+        return Err(Error::new_with_code(
+            ErrorKind::StaticData,
+            format!("no recognised collation in default-collation \"{}\"", dc),
+            Some(QualifiedName::new(None, None, "XTSE0125")),
+        ));
+    }
+
+    let builder = ContextBuilder::new()
         // Define the builtin templates
         // See XSLT 6.7. This implements text-only-copy.
         // TODO: Support deep-copy, shallow-copy, deep-skin, shallow-skip and fail
         // This matches "/" and processes the root element
-        .template(Template::new(
-            Pattern::try_from("/")?,
-            Transform::ApplyTemplates(
-                Box::new(Transform::Step(NodeMatch::new(
-                    Axis::Child,
-                    NodeTest::Kind(KindTest::Any),
-                ))),
+        .template(
+            Template::new(
+                Pattern::try_from("/")?,
+                Transform::ApplyTemplates(
+                    Box::new(Transform::Step(NodeMatch::new(
+                        Axis::Child,
+                        NodeTest::Kind(KindTest::Any),
+                    ))),
+                    None,
+                    vec![],
+                ),
                 None,
-                vec![],
-            ),
-            None,
-            vec![0],
-            None,
-            None,
-        ))
+                vec![0],
+                None,
+                None,
+            )
+            .source("/", "built-in"),
+        )
         // This matches "*" and applies templates to all children
-        .template(Template::new(
-            Pattern::try_from("child::*")?,
-            Transform::ApplyTemplates(
-                Box::new(Transform::Step(NodeMatch::new(
-                    Axis::Child,
-                    NodeTest::Kind(KindTest::Any),
-                ))),
+        .template(
+            Template::new(
+                Pattern::try_from("child::*")?,
+                Transform::ApplyTemplates(
+                    Box::new(Transform::Step(NodeMatch::new(
+                        Axis::Child,
+                        NodeTest::Kind(KindTest::Any),
+                    ))),
+                    None,
+                    vec![],
+                ),
                 None,
-                vec![],
-            ),
-            None,
-            vec![0],
-            None,
-            None,
-        ))
+                vec![0],
+                None,
+                None,
+            )
+            .source("child::*", "built-in"),
+        )
         // This matches "text()" and copies content
-        .template(Template::new(
-            Pattern::try_from("child::text()")?,
-            Transform::ContextItem,
-            None,
-            vec![0],
-            None,
-            None,
-        ))
+        .template(
+            Template::new(
+                Pattern::try_from("child::text()")?,
+                Transform::ContextItem,
+                None,
+                vec![0],
+                None,
+                None,
+            )
+            .source("child::text()", "built-in"),
+        )
+        // This matches attributes and outputs their string value
+        .template(
+            Template::new(
+                Pattern::try_from("attribute::node()")?,
+                Transform::String(Box::new(Transform::ContextItem)),
+                None,
+                vec![0],
+                None,
+                None,
+            )
+            .source("attribute::node()", "built-in"),
+        )
+        // This matches namespace nodes and does nothing
+        .template(
+            Template::new(
+                Pattern::try_from("namespace::node()")?,
+                Transform::Empty,
+                None,
+                vec![0],
+                None,
+                None,
+            )
+            .source("namespace::node()", "built-in"),
+        )
         .template_all(templates)
-        .output_definition(od)
+        .output_definition(od);
+    let mut newctxt = outputs
+        .into_iter()
+        .fold(builder, |b, (qn, o)| b.named_output_definition(qn, o))
         .namespaces(stylens.clone())
         .build();
     keys.iter()
         .for_each(|(name, m, u)| newctxt.declare_key(name.to_string(), m.clone(), u.clone()));
+    let (ss, ps) = space_declarations(&stylenode)?;
+    newctxt.whitespace_stripping(ss, ps);
+    if let Some(u) = default_collation {
+        newctxt.default_collation(u)
+    }
 
     // Add named templates
     stylenode
@@ -525,6 +637,7 @@ where
                     c.is_element()
                         && c.name().get_nsuri_ref() == Some(XSLTNS)
                         && c.name().get_localname() == "param"
+                        && !is_tunnel_param(c)
                 })
                 .try_for_each(|c| {
                     let p_name = c.get_attribute(&QualifiedName::new(None, None, "name"));
@@ -534,27 +647,17 @@ where
                             "name attribute is missing",
                         ))
                     } else {
-                        let sel = c.get_attribute(&QualifiedName::new(None, None, "select"));
-                        if sel.to_string().is_empty() {
-                            // xsl:param content is the sequence constructor
-                            let mut body = vec![];
-                            c.child_iter().try_for_each(|d| {
-                                body.push(to_transform(d, &stylens, &attr_sets)?);
-                                Ok(())
-                            })?;
-                            params.push((
-                                QualifiedName::new(None, None, p_name.to_string()),
-                                Some(Transform::SequenceItems(body)),
-                            ));
-                            Ok(())
-                        } else {
-                            // select attribute value is an expression
-                            params.push((
-                                QualifiedName::new(None, None, p_name.to_string()),
-                                Some(parse::<N>(&sel.to_string())?),
-                            ));
-                            Ok(())
-                        }
+                        // The default value is only evaluated when the caller does not supply a value.
+                        // A required parameter has no default value.
+                        params.push((
+                            QualifiedName::new(None, None, p_name.to_string()),
+                            if yes_or_no(&c, QualifiedName::new(None, None, "required"), false)? {
+                                None
+                            } else {
+                                Some(materialize(&c, variable_value(&c, &stylens, &attr_sets)?))
+                            },
+                        ));
+                        Ok(())
                     }
                 })?;
             // Content is the template body
@@ -572,13 +675,62 @@ where
             newctxt.callable_push(
                 QualifiedName::new(None, None, name.to_string()),
                 Callable::new(
-                    Transform::SequenceItems(body),
+                    declare_tunnel_params(
+                        &c,
+                        Transform::SequenceItems(body),
+                        &stylens,
+                        &attr_sets,
+                    )?,
                     FormalParameters::Named(params),
                 ),
             );
             Ok(())
         })?;
 
+    // Add global variables and parameters.
+    // These are evaluated when they are first referenced, so they may be declared in any order.
+    // Imported declarations come first, so a declaration in the importing module overrides them.
+    // Two declarations with the same name and the same import precedence are an error. See XSLT 9.5.
+    let mut declared: HashMap<String, String> = HashMap::new();
+    stylenode
+        .child_iter()
+        .filter(|c| c.is_element() && c.name().get_nsuri_ref() == Some(XSLTNS))
+        .try_for_each(|c| {
+            let param = match c.name().get_localname().as_str() {
+                "variable" => false,
+                "param" => true,
+                _ => return Ok(()),
+            };
+            let name = c.get_attribute(&QualifiedName::new(None, None, "name"));
+            if name.to_string().is_empty() {
+                return Err(Error::new(
+                    ErrorKind::StaticAbsent,
+                    "name attribute is missing",
+                ));
+            }
+            let import = c
+                .get_attribute(&QualifiedName::new(
+                    Some(String::from("http://github.com/ballsteve/xrust")),
+                    None,
+                    String::from("import"),
+                ))
+                .to_string();
+            if declared.insert(name.to_string(), import.clone()) == Some(import) {
+                return Err(Error::new_with_code(
+                    ErrorKind::StaticAbsent,
+                    format!("global variable \"{}\" is declared more than once", name),
+                    Some(QualifiedName::new(None, None, "XTSE0630")),
+                ));
+            }
+            let value = materialize(&c, variable_value(&c, &stylens, &attr_sets)?);
+            if param {
+                newctxt.declare_global_parameter(name.to_string(), value)
+            } else {
+                newctxt.declare_global_variable(name.to_string(), value)
+            }
+            Ok(())
+        })?;
+
     // Add functions
     stylenode
         .child_iter()
@@ -616,8 +768,22 @@ where
                             ErrorKind::StaticAbsent,
                             "name attribute is missing",
                         ))
+                    } else if !c
+                        .get_attribute(&QualifiedName::new(None, None, "select"))
+                        .to_string()
+                        .is_empty()
+                        || has_content(&c)
+                    {
+                        // Function parameters do not have a default value. See XSLT 10.3.2.
+                        Err(Error::new_with_code(
+                            ErrorKind::StaticAbsent,
+                            format!(
+                                "parameter \"{}\" of a function must not have a default value",
+                                p_name
+                            ),
+                            Some(QualifiedName::new(None, None, "XTSE0760")),
+                        ))
                     } else {
-                        // TODO: validate that xsl:param elements do not specify a default value. See XSLT 10.3.2.
                         params.push(QualifiedName::new(None, None, p_name.to_string()));
                         Ok(())
                     }
@@ -634,12 +800,21 @@ where
                     body.push(to_transform(d, &stylens, &attr_sets)?);
                     Ok::<(), Error>(())
                 })?;
+            let callable = Callable::new(
+                Transform::SequenceItems(body),
+                FormalParameters::Positional(params),
+            );
+            let as_type = c.get_attribute(&QualifiedName::new(None, None, "as"));
             newctxt.callable_push(
                 eqname,
-                Callable::new(
-                    Transform::SequenceItems(body),
-                    FormalParameters::Positional(params),
-                ),
+                if as_type.to_string().is_empty() {
+                    callable
+                } else {
+                    callable.result_type(resolve_sequence_type(
+                        &parse_sequence_type::<N>(as_type.to_string().as_str())?,
+                        &vec![in_scope_namespaces(&c)],
+                    )?)
+                },
             );
             Ok(())
         })?;
@@ -652,11 +827,17 @@ fn to_transform<N: Node>(
     n: N,
     ns: &Vec<HashMap<String, String>>,
     attr_sets: &HashMap<QualifiedName, Vec<Transform<N>>>,
+) -> Result<Transform<N>, Error> {
+    compile_node(n.clone(), ns, attr_sets).map_err(|e| locate_syntax_error(e, &n))
+}
+
+fn compile_node<N: Node>(
+    n: N,
+    ns: &Vec<HashMap<String, String>>,
+    attr_sets: &HashMap<QualifiedName, Vec<Transform<N>>>,
 ) -> Result<Transform<N>, Error> {
     match n.node_type() {
-        NodeType::Text => Ok(Transform::Literal(Item::Value(Rc::new(Value::String(
-            n.to_string(),
-        ))))),
+        NodeType::Text => Ok(literal_text(n.to_string(), true)),
         NodeType::Element => {
             match (n.name().get_nsuri_ref(), n.name().get_localname().as_str()) {
                 (Some(XSLTNS), "text") => {
@@ -665,85 +846,59 @@ fn to_transform<N: Node>(
                         None,
                         "disable-output-escaping".to_string(),
                     ));
-                    if !doe.to_string().is_empty() {
-                        match &doe.to_string()[..] {
-                            "yes" => Ok(Transform::Literal(Item::Value(Rc::new(Value::String(
-                                n.to_string(),
-                            ))))),
-                            "no" => {
-                                let text = n
-                                    .to_string()
-                                    .replace('&', "&amp;")
-                                    .replace('>', "&gt;")
-                                    .replace('<', "&lt;")
-                                    .replace('\'', "&apos;")
-                                    .replace('\"', "&quot;");
-                                Ok(Transform::Literal(Item::Value(Rc::new(Value::from(text)))))
-                            }
-                            _ => Err(Error::new(
-                                ErrorKind::TypeError,
-                                "disable-output-escaping only accepts values yes or no."
-                                    .to_string(),
-                            )),
-                        }
-                    } else {
-                        let text = n
-                            .to_string()
-                            .replace('&', "&amp;")
-                            .replace('>', "&gt;")
-                            .replace('<', "&lt;")
-                            .replace('\'', "&apos;")
-                            .replace('\"', "&quot;");
-                        Ok(Transform::Literal(Item::Value(Rc::new(Value::from(text)))))
+                    match &doe.to_string()[..] {
+                        "yes" => Ok(literal_text(n.to_string(), true)),
+                        "no" | "" => Ok(literal_text(n.to_string(), false)),
+                        _ => Err(Error::new(
+                            ErrorKind::TypeError,
+                            "disable-output-escaping only accepts values yes or no.".to_string(),
+                        )),
                     }
                 }
                 (Some(XSLTNS), "value-of") => {
                     let sel =
                         n.get_attribute(&QualifiedName::new(None, None, "select".to_string()));
+                    let sep = n
+                        .get_attribute_node(&QualifiedName::new(None, None, "separator"))
+                        .map_or(
+                            Ok(Transform::Literal(Item::Value(Rc::new(Value::from(" "))))),
+                            |s| parse_avt(s.to_string().as_str()),
+                        )?;
+                    let v = Transform::ValueOf(
+                        Box::new(parse::<N>(&sel.to_string())?),
+                        Box::new(sep),
+                        xslt_version(&n) < 2.0,
+                    );
                     let doe = n.get_attribute(&QualifiedName::new(
                         None,
                         None,
                         "disable-output-escaping".to_string(),
                     ));
-                    if !doe.to_string().is_empty() {
-                        match &doe.to_string()[..] {
-                            "yes" => Ok(Transform::LiteralText(
-                                Box::new(parse::<N>(&sel.to_string())?),
-                                true,
-                            )),
-                            "no" => Ok(Transform::LiteralText(
-                                Box::new(parse::<N>(&sel.to_string())?),
-                                false,
-                            )),
-                            _ => Err(Error::new(
-                                ErrorKind::TypeError,
-                                "disable-output-escaping only accepts values yes or no."
-                                    .to_string(),
-                            )),
-                        }
-                    } else {
-                        Ok(Transform::LiteralText(
-                            Box::new(parse::<N>(&sel.to_string())?),
-                            false,
-                        ))
+                    match &doe.to_string()[..] {
+                        "yes" => Ok(Transform::LiteralText(Box::new(v), true)),
+                        "no" | "" => Ok(Transform::LiteralText(Box::new(v), false)),
+                        _ => Err(Error::new(
+                            ErrorKind::TypeError,
+                            "disable-output-escaping only accepts values yes or no.".to_string(),
+                        )),
                     }
                 }
                 (Some(XSLTNS), "apply-templates") => {
                     let sel = n.get_attribute(&QualifiedName::new(None, None, "select"));
                     let m = n.get_attribute_node(&QualifiedName::new(None, None, "mode"));
                     let sort_keys = get_sort_keys(&n)?;
-                    if !sel.to_string().is_empty() {
-                        Ok(Transform::ApplyTemplates(
+                    let at = if !sel.to_string().is_empty() {
+                        Transform::ApplyTemplates(
                             Box::new(parse::<N>(&sel.to_string())?),
                             m.map(|s| {
                                 QualifiedName::try_from((s.to_string().as_str(), ns))
                                     .expect("unable to resolve qualified name")
                             }),
                             sort_keys,
-                        )) // TODO: don't panic
+                        ) // TODO: don't panic
                     } else {
                         // If there is no select attribute, then default is "child::node()"
-                        Ok(Transform::ApplyTemplates(
+                        Transform::ApplyTemplates(
                             Box::new(Transform::Step(NodeMatch::new(
                                 Axis::Child,
                                 NodeTest::Kind(KindTest::Any),
@@ -753,8 +908,9 @@ fn to_transform<N: Node>(
                                     .expect("unable to resolve qualified name")
                             }),
                             sort_keys,
-                        )) // TODO: don't panic
-                    }
+                        ) // TODO: don't panic
+                    };
+                    pass_tunnel_params(&n, at, ns, attr_sets)
                 }
                 (Some(XSLTNS), "apply-imports") => Ok(Transform::ApplyImports),
                 (Some(XSLTNS), "sequence") => {
@@ -870,17 +1026,12 @@ fn to_transform<N: Node>(
                 (Some(XSLTNS), "for-each") => {
                     let s = n.get_attribute(&QualifiedName::new(None, None, "select".to_string()));
                     if !s.to_string().is_empty() {
+                        let (ord, body) = sort_keys_and_body(&n, ns, attr_sets)?;
                         Ok(Transform::ForEach(
                             None,
                             Box::new(parse::<N>(&s.to_string())?),
-                            Box::new(Transform::SequenceItems(n.child_iter().try_fold(
-                                vec![],
-                                |mut body, e| {
-                                    body.push(to_transform(e, ns, attr_sets)?);
-                                    Ok(body)
-                                },
-                            )?)),
-                            get_sort_keys(&n)?,
+                            Box::new(body),
+                            ord,
                         ))
                     } else {
                         Result::Err(Error::new(
@@ -889,8 +1040,34 @@ fn to_transform<N: Node>(
                         ))
                     }
                 }
+                (Some(XSLTNS), "perform-sort") => {
+                    // The sequence to sort is given either by the select attribute or by the content
+                    let (ord, body) = sort_keys_and_body(&n, ns, attr_sets)?;
+                    if ord.is_empty() {
+                        return Err(Error::new_with_code(
+                            ErrorKind::StaticAbsent,
+                            "perform-sort must have at least one sort key",
+                            Some(QualifiedName::new(None, None, "XTSE0010")),
+                        ));
+                    }
+                    let s = n.get_attribute(&QualifiedName::new(None, None, "select"));
+                    if s.to_string().is_empty() {
+                        Ok(Transform::PerformSort(Box::new(body), ord))
+                    } else if n.child_iter().any(|c| !is_sort(&c) && is_significant(&c)) {
+                        Err(Error::new_with_code(
+                            ErrorKind::StaticAbsent,
+                            "perform-sort has both a select attribute and content",
+                            Some(QualifiedName::new(None, None, "XTSE1040")),
+                        ))
+                    } else {
+                        Ok(Transform::PerformSort(
+                            Box::new(parse::<N>(&s.to_string())?),
+                            ord,
+                        ))
+                    }
+                }
                 (Some(XSLTNS), "for-each-group") => {
-                    let ord = get_sort_keys(&n)?;
+                    let (ord, body) = sort_keys_and_body(&n, ns, attr_sets)?;
                     let s = n.get_attribute(&QualifiedName::new(None, None, "select".to_string()));
                     if !s.to_string().is_empty() {
                         match (
@@ -924,27 +1101,29 @@ fn to_transform<N: Node>(
                             .as_str(),
                         ) {
                             (by, "", "", "") => Ok(Transform::ForEach(
-                                Some(Grouping::By(vec![parse::<N>(by)?])),
+                                Some(Grouping::By(
+                                    vec![parse::<N>(by)?],
+                                    n.get_attribute_node(&QualifiedName::new(
+                                        None,
+                                        None,
+                                        "collation",
+                                    ))
+                                    .map_or(
+                                        Ok(None),
+                                        |c| {
+                                            parse_avt(c.to_string().as_str())
+                                                .map(|t| Some(Box::new(t)))
+                                        },
+                                    )?,
+                                )),
                                 Box::new(parse::<N>(&s.to_string())?),
-                                Box::new(Transform::SequenceItems(n.child_iter().try_fold(
-                                    vec![],
-                                    |mut body, e| {
-                                        body.push(to_transform(e, ns, attr_sets)?);
-                                        Ok(body)
-                                    },
-                                )?)),
+                                Box::new(body),
                                 ord,
                             )),
                             ("", adj, "", "") => Ok(Transform::ForEach(
                                 Some(Grouping::Adjacent(vec![parse::<N>(adj)?])),
                                 Box::new(parse::<N>(&s.to_string())?),
-                                Box::new(Transform::SequenceItems(n.child_iter().try_fold(
-                                    vec![],
-                                    |mut body, e| {
-                                        body.push(to_transform(e, ns, attr_sets)?);
-                                        Ok(body)
-                                    },
-                                )?)),
+                                Box::new(body),
                                 ord,
                             )),
                             // TODO: group-starting-with and group-ending-with
@@ -993,88 +1172,165 @@ fn to_transform<N: Node>(
                             attrs.append(&mut content);
                             Transform::SequenceItems(attrs)
                         }),
+                        yes_or_no(&n, QualifiedName::new(None, None, "copy-namespaces"), true)?,
                     ))
                 }
                 (Some(XSLTNS), "copy-of") => {
                     let s = n.get_attribute(&QualifiedName::new(None, None, "select".to_string()));
                     if !s.to_string().is_empty() {
-                        Ok(Transform::DeepCopy(Box::new(parse::<N>(&s.to_string())?)))
+                        Ok(Transform::DeepCopy(
+                            Box::new(parse::<N>(&s.to_string())?),
+                            yes_or_no(&n, QualifiedName::new(None, None, "copy-namespaces"), true)?,
+                        ))
                     } else {
-                        Ok(Transform::DeepCopy(Box::new(Transform::ContextItem)))
+                        Ok(Transform::DeepCopy(
+                            Box::new(Transform::ContextItem),
+                            yes_or_no(&n, QualifiedName::new(None, None, "copy-namespaces"), true)?,
+                        ))
                     }
                 }
-                (Some(XSLTNS), "call-template") => {
-                    let name = n.get_attribute(&QualifiedName::new(None, None, "name"));
-                    if !name.to_string().is_empty() {
-                        // Iterate over the xsl:with-param elements to get the actual parameters
-                        // TODO: validate that the children are only xsl:with-param elements
-                        let mut ap = vec![];
-                        n.child_iter()
-                            .filter(|c| {
-                                c.is_element()
-                                    && c.name().get_nsuri_ref() == Some(XSLTNS)
-                                    && c.name().get_localname() == "with-param"
-                            })
-                            .try_for_each(|c| {
-                                let wp_name =
+                (Some(XSLTNS), "iterate") => {
+                    let s = n.get_attribute(&QualifiedName::new(None, None, "select"));
+                    if s.to_string().is_empty() {
+                        return Err(Error::new(
+                            ErrorKind::StaticAbsent,
+                            "select attribute missing",
+                        ));
+                    }
+                    let mut params = vec![];
+                    let mut body = vec![];
+                    let mut completion = Transform::Empty;
+                    n.child_iter().try_for_each(|c| {
+                        match (
+                            c.node_type(),
+                            c.name().get_nsuri_ref(),
+                            c.name().get_localname().as_str(),
+                        ) {
+                            (NodeType::Element, Some(XSLTNS), "param") => {
+                                let p_name =
                                     c.get_attribute(&QualifiedName::new(None, None, "name"));
-                                if !wp_name.to_string().is_empty() {
-                                    let sel =
-                                        c.get_attribute(&QualifiedName::new(None, None, "select"));
-                                    if sel.to_string().is_empty() {
-                                        // xsl:with-param content is the sequence constructor
-                                        let mut body = vec![];
-                                        c.child_iter().try_for_each(|d| {
-                                            body.push(to_transform(d, ns, attr_sets)?);
-                                            Ok(())
-                                        })?;
-                                        ap.push((
-                                            QualifiedName::new(None, None, wp_name.to_string()),
-                                            Transform::SequenceItems(body),
-                                        ));
-                                        Ok(())
-                                    } else {
-                                        // select attribute value is an expression
-                                        ap.push((
-                                            QualifiedName::new(None, None, wp_name.to_string()),
-                                            parse::<N>(&sel.to_string())?,
-                                        ));
-                                        Ok(())
-                                    }
-                                } else {
-                                    Err(Error::new(
+                                if p_name.to_string().is_empty() {
+                                    return Err(Error::new(
                                         ErrorKind::StaticAbsent,
-                                        "missing name attribute",
-                                    ))
+                                        "name attribute is missing",
+                                    ));
                                 }
-                            })?;
-                        Ok(Transform::Invoke(
-                            QualifiedName::new(None, None, name.to_string()),
-                            ActualParameters::Named(ap),
-                        ))
-                    } else {
-                        Err(Error::new(
-                            ErrorKind::StaticAbsent,
-                            "name attribute missing",
-                        ))
-                    }
+                                params.push((
+                                    QualifiedName::new(None, None, p_name.to_string()),
+                                    variable_value(&c, ns, attr_sets)?,
+                                ))
+                            }
+                            (NodeType::Element, Some(XSLTNS), "on-completion") => {
+                                completion = select_or_content(&c, ns, attr_sets)?
+                            }
+                            _ => body.push(to_transform(c, ns, attr_sets)?),
+                        }
+                        Ok(())
+                    })?;
+                    Ok(Transform::Iterate(
+                        Box::new(parse::<N>(&s.to_string())?),
+                        params,
+                        Box::new(Transform::SequenceItems(body)),
+                        Box::new(completion),
+                    ))
                 }
-                (Some(XSLTNS), "element") => {
-                    let m = n.get_attribute(&QualifiedName::new(None, None, "name".to_string()));
-                    if m.to_string().is_empty() {
-                        return Err(Error::new(ErrorKind::TypeError, "missing name attribute"));
+                (Some(XSLTNS), "next-iteration") => Ok(Transform::NextIteration(with_params(
+                    &n, false, ns, attr_sets,
+                )?)),
+                (Some(XSLTNS), "break") => Ok(Transform::Break(Box::new(select_or_content(
+                    &n, ns, attr_sets,
+                )?))),
+                (Some(XSLTNS), "result-document") => {
+                    // All of the attributes are attribute value templates
+                    let avt = |name: &str| {
+                        n.get_attribute_node(&QualifiedName::new(None, None, name))
+                            .map_or(Ok(None), |a| parse_avt(a.to_string().as_str()).map(Some))
+                    };
+                    let mut params = vec![];
+                    for p in [
+                        "method",
+                        "indent",
+                        "encoding",
+                        "normalization-form",
+                        "byte-order-mark",
+                    ] {
+                        if let Some(t) = avt(p)? {
+                            params.push((p.to_string(), t))
+                        }
                     }
-                    let mut content = n.child_iter().try_fold(vec![], |mut body, e| {
-                        body.push(to_transform(e, ns, attr_sets)?);
-                        Ok(body)
-                    })?;
-                    // Process @xsl:use-attribute-sets
-                    let use_atts = n.get_attribute(&QualifiedName::new(
-                        Some(XSLTNS.to_string()),
-                        None,
-                        "use-attribute-sets",
-                    ));
-                    let mut attrs = vec![];
+                    Ok(Transform::ResultDocument(
+                        Box::new(
+                            avt("href")?.unwrap_or(Transform::Literal(Item::Value(Rc::new(
+                                Value::from(""),
+                            )))),
+                        ),
+                        avt("format")?.map(Box::new),
+                        params,
+                        Box::new(Transform::SequenceItems(n.child_iter().try_fold(
+                            vec![],
+                            |mut body, e| {
+                                body.push(to_transform(e, ns, attr_sets)?);
+                                Ok::<_, Error>(body)
+                            },
+                        )?)),
+                    ))
+                }
+                (Some(XSLTNS), "source-document") => {
+                    let href = n
+                        .get_attribute_node(&QualifiedName::new(None, None, "href"))
+                        .ok_or(Error::new_with_code(
+                            ErrorKind::StaticAbsent,
+                            "xsl:source-document must have an href attribute",
+                            Some(QualifiedName::new(None, None, "XTSE0010")),
+                        ))?;
+                    Ok(Transform::SourceDocument(
+                        Box::new(parse_avt(href.to_string().as_str())?),
+                        yes_or_no(&n, QualifiedName::new(None, None, "streamable"), false)?,
+                        Box::new(Transform::SequenceItems(n.child_iter().try_fold(
+                            vec![],
+                            |mut body, e| {
+                                body.push(to_transform(e, ns, attr_sets)?);
+                                Ok::<_, Error>(body)
+                            },
+                        )?)),
+                    ))
+                }
+                (Some(XSLTNS), "call-template") => {
+                    let name = n.get_attribute(&QualifiedName::new(None, None, "name"));
+                    if !name.to_string().is_empty() {
+                        // TODO: validate that the children are only xsl:with-param elements
+                        pass_tunnel_params(
+                            &n,
+                            Transform::Invoke(
+                                QualifiedName::new(None, None, name.to_string()),
+                                ActualParameters::Named(with_params(&n, false, ns, attr_sets)?),
+                            ),
+                            ns,
+                            attr_sets,
+                        )
+                    } else {
+                        Err(Error::new(
+                            ErrorKind::StaticAbsent,
+                            "name attribute missing",
+                        ))
+                    }
+                }
+                (Some(XSLTNS), "element") => {
+                    let m = n.get_attribute(&QualifiedName::new(None, None, "name".to_string()));
+                    if m.to_string().is_empty() {
+                        return Err(Error::new(ErrorKind::TypeError, "missing name attribute"));
+                    }
+                    let mut content = n.child_iter().try_fold(vec![], |mut body, e| {
+                        body.push(to_transform(e, ns, attr_sets)?);
+                        Ok(body)
+                    })?;
+                    // Process @xsl:use-attribute-sets
+                    let use_atts = n.get_attribute(&QualifiedName::new(
+                        Some(XSLTNS.to_string()),
+                        None,
+                        "use-attribute-sets",
+                    ));
+                    let mut attrs = vec![];
                     use_atts.to_string().split_whitespace().try_for_each(|a| {
                         let eqa = QualifiedName::try_from((a, ns))?;
                         attr_sets
@@ -1094,20 +1350,23 @@ fn to_transform<N: Node>(
                             attrs.append(&mut content);
                             Transform::SequenceItems(attrs)
                         }),
+                        yes_or_no(
+                            &n,
+                            QualifiedName::new(None, None, "inherit-namespaces"),
+                            true,
+                        )?,
+                        namespace_avt(&n)?,
+                        vec![in_scope_namespaces(&n)],
                     ))
                 }
                 (Some(XSLTNS), "attribute") => {
                     let m = n.get_attribute(&QualifiedName::new(None, None, "name".to_string()));
                     if !m.to_string().is_empty() {
-                        Ok(Transform::LiteralAttribute(
-                            QualifiedName::new(None, None, m.to_string()),
-                            Box::new(Transform::SequenceItems(n.child_iter().try_fold(
-                                vec![],
-                                |mut body, e| {
-                                    body.push(to_transform(e, ns, attr_sets)?);
-                                    Ok(body)
-                                },
-                            )?)),
+                        Ok(Transform::Attribute(
+                            Box::new(parse_avt(m.to_string().as_str())?),
+                            Box::new(simple_content(&n, ns, attr_sets, true)?),
+                            namespace_avt(&n)?,
+                            vec![in_scope_namespaces(&n)],
                         ))
                     } else {
                         Err(Error::new(
@@ -1117,10 +1376,7 @@ fn to_transform<N: Node>(
                     }
                 }
                 (Some(XSLTNS), "comment") => Ok(Transform::LiteralComment(Box::new(
-                    Transform::SequenceItems(n.child_iter().try_fold(vec![], |mut body, e| {
-                        body.push(to_transform(e, ns, attr_sets)?);
-                        Ok(body)
-                    })?),
+                    simple_content(&n, ns, attr_sets, false)?,
                 ))),
                 (Some(XSLTNS), "processing-instruction") => {
                     let m = n.get_attribute(&QualifiedName::new(None, None, "name".to_string()));
@@ -1132,18 +1388,26 @@ fn to_transform<N: Node>(
                     }
                     Ok(Transform::LiteralProcessingInstruction(
                         Box::new(parse_avt(m.to_string().as_str())?),
-                        Box::new(Transform::SequenceItems(n.child_iter().try_fold(
-                            vec![],
-                            |mut body, e| {
-                                body.push(to_transform(e, ns, attr_sets)?);
-                                Ok(body)
-                            },
-                        )?)),
+                        Box::new(simple_content(&n, ns, attr_sets, false)?),
                     ))
                 }
+                (Some(XSLTNS), "namespace") => {
+                    let m = n.get_attribute_node(&QualifiedName::new(None, None, "name"));
+                    match m {
+                        Some(m) => Ok(Transform::LiteralNamespace(
+                            Box::new(parse_avt(m.to_string().as_str())?),
+                            Box::new(simple_content(&n, ns, attr_sets, false)?),
+                        )),
+                        None => Err(Error::new(
+                            ErrorKind::TypeError,
+                            "missing name attribute".to_string(),
+                        )),
+                    }
+                }
                 (Some(XSLTNS), "message") => {
                     let t =
                         n.get_attribute(&QualifiedName::new(None, None, "terminate".to_string()));
+                    let ec = n.get_attribute(&QualifiedName::new(None, None, "error-code"));
                     Ok(Transform::Message(
                         Box::new(Transform::SequenceItems(n.child_iter().try_fold(
                             vec![],
@@ -1153,31 +1417,70 @@ fn to_transform<N: Node>(
                             },
                         )?)),
                         None,
-                        Box::new(Transform::Empty),
+                        // error-code is an attribute value template
+                        Box::new(if ec.to_string().is_empty() {
+                            Transform::Empty
+                        } else {
+                            parse_avt(ec.to_string().as_str())?
+                        }),
+                        // terminate is an attribute value template, and defaults to "no"
                         Box::new(if t.to_string().is_empty() {
                             Transform::False
                         } else {
-                            Transform::Literal(Item::Value(Rc::new(Value::from(t.to_string()))))
+                            parse_avt(t.to_string().as_str())?
                         }),
+                        instruction_location(&n),
                     ))
                 }
                 (Some(XSLTNS), "number") => {
                     let value = n.get_attribute(&QualifiedName::new(None, None, "value"));
                     let sel = n.get_attribute(&QualifiedName::new(None, None, "select"));
-                    let level = n.get_attribute(&QualifiedName::new(None, None, "level"));
-                    if level.to_string() != "" && level.to_string() != "single" {
-                        return Err(Error::new(
-                            ErrorKind::NotImplemented,
-                            "only single level numbering is supported",
-                        ));
-                    }
+                    let level = match n
+                        .get_attribute(&QualifiedName::new(None, None, "level"))
+                        .to_string()
+                        .trim()
+                    {
+                        "" | "single" => Level::Single,
+                        "multiple" => Level::Multiple,
+                        "any" => Level::Any,
+                        l => {
+                            return Err(Error::new_with_code(
+                                ErrorKind::StaticData,
+                                format!("invalid value \"{}\" for level", l),
+                                Some(QualifiedName::new(None, None, "XTSE0020")),
+                            ))
+                        }
+                    };
                     let count = n.get_attribute(&QualifiedName::new(None, None, "count"));
                     let from = n.get_attribute(&QualifiedName::new(None, None, "from"));
                     let format = n.get_attribute(&QualifiedName::new(None, None, "format"));
-                    // TODO: lang, letter-value, ordinal, start-at, grouping-separator, grouping-size
+                    // Grouping requires both the separator and the size.
+                    // The format, grouping and ordinal attributes are attribute value templates, validated when evaluated.
+                    let gsep =
+                        n.get_attribute_node(&QualifiedName::new(None, None, "grouping-separator"));
+                    let gsize =
+                        n.get_attribute_node(&QualifiedName::new(None, None, "grouping-size"));
+                    let grouping = match (gsep, gsize) {
+                        (Some(sep), Some(size)) => Some((
+                            parse_avt(sep.to_string().as_str())?,
+                            parse_avt(size.to_string().as_str())?,
+                        )),
+                        _ => None,
+                    };
+                    let ordinal = n
+                        .get_attribute_node(&QualifiedName::new(None, None, "ordinal"))
+                        .map(|o| parse_avt(o.to_string().as_str()))
+                        .transpose()?;
+                    let format = if format.to_string().is_empty() {
+                        Transform::Literal(Item::Value(Rc::new(Value::from("1"))))
+                    } else {
+                        parse_avt(format.to_string().as_str())?
+                    };
+                    let nf = Box::new(NumberFormat::new(grouping, ordinal));
+                    // TODO: lang, letter-value, start-at
                     if value.to_string().is_empty() {
                         // Compute place marker
-                        Ok(Transform::FormatInteger(
+                        Ok(Transform::FormatIntegers(
                             Box::new(Transform::GenerateIntegers(
                                 Box::new(Transform::Empty), // start-at (TODO)
                                 Box::new(if sel.to_string().is_empty() {
@@ -1186,7 +1489,7 @@ fn to_transform<N: Node>(
                                     parse::<N>(&sel.to_string())?
                                 }), // select
                                 Box::new(Numbering::new(
-                                    Level::Single, // TODO: parse level attribute value
+                                    level,
                                     if count.to_string().is_empty() {
                                         None
                                     } else {
@@ -1199,25 +1502,15 @@ fn to_transform<N: Node>(
                                     },
                                 )),
                             )),
-                            Box::new(Transform::Literal(Item::Value(
-                                if format.to_string().is_empty() {
-                                    Rc::new(Value::from("1"))
-                                } else {
-                                    format
-                                },
-                            ))),
+                            Box::new(format),
+                            nf,
                         ))
                     } else {
                         // Place marker is supplied
-                        Ok(Transform::FormatInteger(
+                        Ok(Transform::FormatIntegers(
                             Box::new(parse::<N>(&value.to_string())?),
-                            Box::new(Transform::Literal(Item::Value(
-                                if format.to_string().is_empty() {
-                                    Rc::new(Value::from("1"))
-                                } else {
-                                    format
-                                },
-                            ))),
+                            Box::new(format),
+                            nf,
                         ))
                     }
                 }
@@ -1228,6 +1521,9 @@ fn to_transform<N: Node>(
                     "unsupported XSL element \"{}\"",
                     u
                 ))),
+                (Some(u), _) if extension_namespaces(&n)?.iter().any(|e| e == u) => {
+                    extension_instruction(&n, ns, attr_sets)
+                }
                 (u, a) => {
                     // Process @xsl:use-attribute-sets
                     let use_atts = n.get_attribute(&QualifiedName::new(
@@ -1245,7 +1541,16 @@ fn to_transform<N: Node>(
                             .for_each(|a| attrs.append(&mut a.clone()));
                         Ok(())
                     })?;
-                    let mut content = vec![];
+                    // Namespaces in scope in the stylesheet are copied to the result
+                    let mut content: Vec<Transform<N>> = result_namespaces(&n)?
+                        .into_iter()
+                        .map(|(p, u)| {
+                            Transform::LiteralNamespace(
+                                Box::new(Transform::Literal(Item::Value(Rc::new(Value::from(p))))),
+                                Box::new(Transform::Literal(Item::Value(Rc::new(Value::from(u))))),
+                            )
+                        })
+                        .collect();
                     // Copy attributes to the result, except for XSLT directives
                     n.attribute_iter()
                         .filter(|e| e.name().get_nsuri_ref() != Some(XSLTNS))
@@ -1270,10 +1575,21 @@ fn to_transform<N: Node>(
                             attrs.append(&mut content);
                             Transform::SequenceItems(attrs)
                         }),
+                        yes_or_no(
+                            &n,
+                            QualifiedName::new(
+                                Some(XSLTNS.to_string()),
+                                None,
+                                "inherit-namespaces",
+                            ),
+                            true,
+                        )?,
                     ))
                 }
             }
         }
+        // Comments and processing instructions in a sequence constructor are ignored
+        NodeType::Comment | NodeType::ProcessingInstruction => Ok(Transform::Empty),
         NodeType::Attribute => {
             // Get value as a Value
             Ok(Transform::LiteralAttribute(
@@ -1292,18 +1608,1307 @@ fn to_transform<N: Node>(
     }
 }
 
-fn get_sort_keys<N: Node>(n: &N) -> Result<Vec<(Order, Transform<N>)>, Error> {
+/// Replace the xsl:include elements of a stylesheet module with the content of the included modules.
+/// Included modules may themselves include other modules.
+/// The chain of modules currently being included is used to detect a module that includes itself.
+/// A module that has already been loaded is not included again.
+fn include_modules<N: Node, F, G>(
+    module: &N,
+    base: &Option<Url>,
+    f: &F,
+    g: &G,
+    active: &mut Vec<Url>,
+    loaded: &mut HashSet<Url>,
+) -> Result<(), Error>
+where
+    F: Fn(&str) -> Result<N, Error>,
+    G: Fn(&Url) -> Result<String, Error>,
+{
+    module
+        .child_iter()
+        .filter(|c| {
+            c.is_element()
+                && c.name().get_nsuri_ref() == Some(XSLTNS)
+                && c.name().get_localname() == "include"
+        })
+        .collect::<Vec<_>>()
+        .into_iter()
+        .try_for_each(|mut c| {
+            let url = module_url(&c, base)?;
+            if active.contains(&url) {
+                return Err(Error::new_with_code(
+                    ErrorKind::StaticData,
+                    format!(
+                        "stylesheet module includes itself: {}",
+                        module_cycle(active, &url)
+                    ),
+                    Some(QualifiedName::new(None, None, "XTSE0180")),
+                ));
+            }
+            if loaded.insert(url.clone()) {
+                let xml = g(&url)?;
+                let included = f(xml.as_str().trim())?;
+                strip_stylesheet(included.clone())?;
+                // TODO: check that the module is a valid XSLT stylesheet, etc
+                let moddoc = module_element(&included, &url)?;
+                module_defaults(&moddoc)?;
+                active.push(url.clone());
+                include_modules(&moddoc, &Some(url.clone()), f, g, active, loaded)?;
+                active.pop();
+                mark_module(&moddoc, &url)?;
+                // Copy each top-level element of the module to the including module,
+                // inserting before the xsl:include node
+                moddoc.child_iter().try_for_each(|mc| {
+                    c.insert_before(mc)?;
+                    Ok::<(), Error>(())
+                })?;
+            }
+            // Remove the xsl:include element node
+            c.pop()?;
+            Ok(())
+        })
+}
+
+// Records the URI of a stylesheet module on its top-level elements, so that the location of instructions can be reported once modules have been combined.
+// Elements that come from an included module are already marked.
+fn mark_module<N: Node>(module: &N, url: &Url) -> Result<(), Error> {
+    let qn = module_qname();
+    module
+        .child_iter()
+        .filter(|c| c.is_element() && c.get_attribute_node(&qn).is_none())
+        .try_for_each(|c| {
+            c.add_attribute(c.new_attribute(qn.clone(), Rc::new(Value::from(url.to_string())))?)
+        })
+}
+
+fn module_qname() -> QualifiedName {
+    QualifiedName::new(
+        Some(String::from("http://github.com/ballsteve/xrust")),
+        None,
+        String::from("module"),
+    )
+}
+
+/// Where an instruction occurs in the stylesheet.
+/// This is only known if the stylesheet was parsed by a parser that records positions.
+fn instruction_location<N: Node>(n: &N) -> Option<Location> {
+    let qn = module_qname();
+    n.location().map(|(line, column)| Location {
+        module: n
+            .ancestor_iter()
+            .find_map(|a| a.get_attribute_node(&qn))
+            .map(|m| m.to_string()),
+        line,
+        column,
+    })
+}
+
+/// The standard attributes, which are allowed on every XSLT element. See XSLT 3.5.
+const STANDARD_ATTRIBUTES: &[&str] = &[
+    "default-collation",
+    "default-mode",
+    "default-validation",
+    "exclude-result-prefixes",
+    "expand-text",
+    "extension-element-prefixes",
+    "use-when",
+    "version",
+    "xpath-default-namespace",
+];
+
+const SERIALIZATION_ATTRIBUTES: &[&str] = &[
+    "allow-duplicate-names",
+    "build-tree",
+    "byte-order-mark",
+    "cdata-section-elements",
+    "doctype-public",
+    "doctype-system",
+    "encoding",
+    "escape-uri-attributes",
+    "html-version",
+    "include-content-type",
+    "indent",
+    "item-separator",
+    "json-node-output-method",
+    "media-type",
+    "method",
+    "normalization-form",
+    "omit-xml-declaration",
+    "parameter-document",
+    "standalone",
+    "suppress-indentation",
+    "undeclare-prefixes",
+    "use-character-maps",
+];
+
+/// The attributes that are allowed on an XSLT element, other than the standard attributes and the serialization parameters.
+/// The result is None if there is no such XSLT element.
+fn element_attributes(name: &str) -> Option<&'static [&'static str]> {
+    let attributes: &[&str] = match name {
+        "accept" | "expose" => &["component", "names", "visibility"],
+        "accumulator" => &["name", "initial-value", "as", "streamable"],
+        "accumulator-rule" => &["match", "phase", "select"],
+        "analyze-string" => &["select", "regex", "flags"],
+        "apply-imports"
+        | "choose"
+        | "fallback"
+        | "fork"
+        | "map"
+        | "matching-substring"
+        | "merge"
+        | "merge-action"
+        | "next-iteration"
+        | "next-match"
+        | "non-matching-substring"
+        | "otherwise"
+        | "override"
+        | "where-populated" => &[],
+        "apply-templates" => &["select", "mode"],
+        "assert" => &["test", "select", "error-code"],
+        "attribute" => &[
+            "name",
+            "namespace",
+            "select",
+            "separator",
+            "type",
+            "validation",
+        ],
+        "attribute-set" => &["name", "use-attribute-sets", "visibility", "streamable"],
+        "break" | "comment" | "for-each" | "iterate" | "on-completion" | "on-empty"
+        | "on-non-empty" | "perform-sort" | "sequence" => &["select"],
+        "call-template" => &["name"],
+        "catch" => &["errors", "select"],
+        "character-map" => &["name", "use-character-maps"],
+        "context-item" | "global-context-item" => &["as", "use"],
+        "copy" => &[
+            "select",
+            "copy-namespaces",
+            "inherit-namespaces",
+            "use-attribute-sets",
+            "type",
+            "validation",
+        ],
+        "copy-of" => &[
+            "select",
+            "copy-accumulators",
+            "copy-namespaces",
+            "type",
+            "validation",
+        ],
+        "decimal-format" => &[
+            "name",
+            "decimal-separator",
+            "grouping-separator",
+            "infinity",
+            "minus-sign",
+            "exponent-separator",
+            "NaN",
+            "percent",
+            "per-mille",
+            "zero-digit",
+            "digit",
+            "pattern-separator",
+        ],
+        "document" => &["validation", "type"],
+        "element" => &[
+            "name",
+            "namespace",
+            "inherit-namespaces",
+            "use-attribute-sets",
+            "type",
+            "validation",
+        ],
+        "evaluate" => &[
+            "xpath",
+            "as",
+            "base-uri",
+            "with-params",
+            "context-item",
+            "namespace-context",
+            "schema-aware",
+        ],
+        "for-each-group" => &[
+            "select",
+            "group-by",
+            "group-adjacent",
+            "group-starting-with",
+            "group-ending-with",
+            "composite",
+            "collation",
+        ],
+        "function" => &[
+            "name",
+            "as",
+            "visibility",
+            "streamability",
+            "override-extension-function",
+            "override",
+            "new-each-time",
+            "cache",
+        ],
+        "if" | "when" => &["test"],
+        "import" | "include" => &["href"],
+        "import-schema" => &["namespace", "schema-location"],
+        "key" => &["name", "match", "use", "composite", "collation"],
+        "map-entry" => &["key", "select"],
+        "merge-key" => &[
+            "select",
+            "lang",
+            "order",
+            "collation",
+            "case-order",
+            "data-type",
+        ],
+        "merge-source" => &[
+            "name",
+            "for-each-item",
+            "for-each-source",
+            "select",
+            "streamable",
+            "use-accumulators",
+            "sort-before-merge",
+            "validation",
+            "type",
+        ],
+        "message" => &["select", "terminate", "error-code"],
+        "mode" => &[
+            "name",
+            "streamable",
+            "use-accumulators",
+            "on-no-match",
+            "on-multiple-match",
+            "warning-on-no-match",
+            "warning-on-multiple-match",
+            "typed",
+            "visibility",
+        ],
+        "namespace" | "processing-instruction" => &["name", "select"],
+        "namespace-alias" => &["stylesheet-prefix", "result-prefix"],
+        "number" => &[
+            "value",
+            "select",
+            "level",
+            "count",
+            "from",
+            "format",
+            "lang",
+            "letter-value",
+            "ordinal",
+            "start-at",
+            "grouping-separator",
+            "grouping-size",
+        ],
+        "output" => &["name"],
+        "output-character" => &["character", "string"],
+        "package" => &[
+            "id",
+            "name",
+            "package-version",
+            "input-type-annotations",
+            "declared-modes",
+        ],
+        "param" => &[
+            "name", "select", "as", "required", "implicit", "tunnel", "static",
+        ],
+        "preserve-space" | "strip-space" => &["elements"],
+        "result-document" => &["format", "href", "validation", "type", "output-version"],
+        "sort" => &[
+            "select",
+            "lang",
+            "order",
+            "collation",
+            "stable",
+            "case-order",
+            "data-type",
+        ],
+        "source-document" => &[
+            "href",
+            "streamable",
+            "use-accumulators",
+            "validation",
+            "type",
+        ],
+        "stylesheet" | "transform" => &["id", "input-type-annotations"],
+        "template" => &["match", "name", "priority", "mode", "as", "visibility"],
+        "text" => &["disable-output-escaping"],
+        "try" => &["select", "rollback-output"],
+        "use-package" => &["name", "package-version"],
+        "value-of" => &["select", "separator", "disable-output-escaping"],
+        "variable" => &["name", "select", "as", "static", "visibility"],
+        "with-param" => &["name", "select", "as", "tunnel"],
+        _ => return None,
+    };
+    Some(attributes)
+}
+
+/// Declarations, which may only appear at the top level of a stylesheet module.
+/// xsl:param and xsl:variable may appear both at the top level and in a sequence constructor.
+fn is_declaration(name: &str) -> bool {
+    matches!(
+        name,
+        "accumulator"
+            | "attribute-set"
+            | "character-map"
+            | "decimal-format"
+            | "function"
+            | "global-context-item"
+            | "import"
+            | "import-schema"
+            | "include"
+            | "key"
+            | "mode"
+            | "namespace-alias"
+            | "output"
+            | "preserve-space"
+            | "strip-space"
+            | "template"
+            | "use-package"
+    )
+}
+
+/// The XSLT elements that may be the parent of an element that only appears in a particular context.
+fn allowed_parents(name: &str) -> Option<&'static [&'static str]> {
+    let parents: &[&str] = match name {
+        "when" | "otherwise" => &["choose"],
+        "with-param" => &[
+            "apply-templates",
+            "call-template",
+            "apply-imports",
+            "next-match",
+            "next-iteration",
+            "evaluate",
+        ],
+        "sort" => &[
+            "apply-templates",
+            "for-each",
+            "for-each-group",
+            "perform-sort",
+        ],
+        "matching-substring" | "non-matching-substring" => &["analyze-string"],
+        "catch" => &["try"],
+        "on-completion" => &["iterate"],
+        "context-item" => &["template"],
+        "accumulator-rule" => &["accumulator"],
+        "output-character" => &["character-map"],
+        "merge-source" | "merge-action" => &["merge"],
+        "merge-key" => &["merge-source"],
+        "accept" | "override" => &["use-package"],
+        _ => return None,
+    };
+    Some(parents)
+}
+
+/// Check that each XSLT element in the stylesheet is a known element, has only the attributes that are allowed for it,
+/// and appears where it is allowed. Attributes in a namespace, such as extension attributes, are not checked.
+fn validate_elements<N: Node>(stylenode: &N) -> Result<(), Error> {
+    Some(stylenode.clone())
+        .into_iter()
+        .chain(stylenode.descend_iter())
+        .filter(|e| e.is_element() && e.name().get_nsuri_ref() == Some(XSLTNS))
+        .try_for_each(|e| {
+            validate_element(&e, stylenode).map_err(|mut err| {
+                err.location = instruction_location(&e).map(Box::new);
+                err
+            })
+        })
+}
+
+fn validate_element<N: Node>(e: &N, stylenode: &N) -> Result<(), Error> {
+    let name = e.name().get_localname();
+    let placement = |msg: String| {
+        Err(Error::new_with_code(
+            ErrorKind::StaticData,
+            msg,
+            Some(QualifiedName::new(None, None, "XTSE0010")),
+        ))
+    };
+    let allowed = match element_attributes(name.as_str()) {
+        Some(a) => a,
+        None => {
+            return placement(format!(
+                "unknown XSLT element xsl:{}{}",
+                name,
+                suggestion(&name, KNOWN_ELEMENTS)
+            ))
+        }
+    };
+
+    // Attributes
+    let serialization = matches!(name.as_str(), "output" | "result-document");
+    if let Some(a) = e.attribute_iter().find(|a| {
+        let an = a.name();
+        let local = an.get_localname();
+        an.get_nsuri_ref().is_none()
+            && an.get_prefix().is_none()
+            && local != "xmlns"
+            && !allowed.contains(&local.as_str())
+            && !STANDARD_ATTRIBUTES.contains(&local.as_str())
+            && !(serialization && SERIALIZATION_ATTRIBUTES.contains(&local.as_str()))
+    }) {
+        let local = a.name().get_localname();
+        let extra: &[&str] = if serialization {
+            SERIALIZATION_ATTRIBUTES
+        } else {
+            &[]
+        };
+        let candidates: Vec<&str> = allowed
+            .iter()
+            .chain(STANDARD_ATTRIBUTES)
+            .chain(extra)
+            .copied()
+            .collect();
+        return Err(Error::new_with_code(
+            ErrorKind::StaticData,
+            format!(
+                "attribute \"{}\" is not allowed on xsl:{}{}",
+                local,
+                name,
+                suggestion(&local, &candidates)
+            ),
+            Some(QualifiedName::new(None, None, "XTSE0090")),
+        ));
+    }
+
+    // Placement
+    if e.is_same(stylenode) {
+        return Ok(());
+    }
+    let parent = e.parent();
+    let top_level = parent.as_ref().is_some_and(|p| p.is_same(stylenode));
+    let parent_name = parent
+        .as_ref()
+        .filter(|p| p.is_element() && p.name().get_nsuri_ref() == Some(XSLTNS))
+        .map(|p| p.name().get_localname());
+    let variable = matches!(name.as_str(), "param" | "variable");
+    if matches!(name.as_str(), "stylesheet" | "transform" | "package") {
+        return placement(format!("xsl:{} must be the document element", name));
+    }
+    if top_level && !is_declaration(&name) && !variable {
+        return placement(format!("xsl:{} is not allowed at the top level", name));
+    }
+    if !top_level && is_declaration(&name) {
+        return placement(format!("xsl:{} is only allowed at the top level", name));
+    }
+    if let Some(ps) = allowed_parents(&name) {
+        if !parent_name
+            .as_ref()
+            .is_some_and(|p| ps.contains(&p.as_str()))
+        {
+            return placement(format!(
+                "xsl:{} must be a child of {}",
+                name,
+                ps.iter()
+                    .map(|p| format!("xsl:{}", p))
+                    .collect::<Vec<_>>()
+                    .join(" or ")
+            ));
+        }
+    }
+    if name == "param" && !top_level {
+        let p = parent_name.unwrap_or_default();
+        if !matches!(p.as_str(), "template" | "function" | "iterate") {
+            return placement(String::from(
+                "xsl:param must be a child of xsl:template, xsl:function, xsl:iterate, or a top-level element",
+            ));
+        }
+        // Parameters come before the rest of the content
+        if e.prev_iter().any(|s| {
+            !(s.is_element()
+                && s.name().get_nsuri_ref() == Some(XSLTNS)
+                && matches!(s.name().get_localname().as_str(), "param" | "context-item"))
+        }) {
+            return placement(format!(
+                "xsl:param must come before the other children of xsl:{}",
+                p
+            ));
+        }
+    }
+    Ok(())
+}
+
+const KNOWN_ELEMENTS: &[&str] = &[
+    "accept",
+    "accumulator",
+    "accumulator-rule",
+    "analyze-string",
+    "apply-imports",
+    "apply-templates",
+    "assert",
+    "attribute",
+    "attribute-set",
+    "break",
+    "call-template",
+    "catch",
+    "character-map",
+    "choose",
+    "comment",
+    "context-item",
+    "copy",
+    "copy-of",
+    "decimal-format",
+    "document",
+    "element",
+    "evaluate",
+    "expose",
+    "fallback",
+    "for-each",
+    "for-each-group",
+    "fork",
+    "function",
+    "global-context-item",
+    "if",
+    "import",
+    "import-schema",
+    "include",
+    "iterate",
+    "key",
+    "map",
+    "map-entry",
+    "matching-substring",
+    "merge",
+    "merge-action",
+    "merge-key",
+    "merge-source",
+    "message",
+    "mode",
+    "namespace",
+    "namespace-alias",
+    "next-iteration",
+    "next-match",
+    "non-matching-substring",
+    "number",
+    "on-completion",
+    "on-empty",
+    "on-non-empty",
+    "otherwise",
+    "output",
+    "output-character",
+    "override",
+    "package",
+    "param",
+    "perform-sort",
+    "preserve-space",
+    "processing-instruction",
+    "result-document",
+    "sequence",
+    "sort",
+    "source-document",
+    "strip-space",
+    "stylesheet",
+    "template",
+    "text",
+    "transform",
+    "try",
+    "use-package",
+    "value-of",
+    "variable",
+    "when",
+    "where-populated",
+    "with-param",
+];
+
+/// Suggest the closest of the candidates to a misspelt word, if there is one that is close enough.
+fn suggestion(word: &str, candidates: &[&str]) -> String {
+    candidates
+        .iter()
+        .map(|c| (edit_distance(word, c), c))
+        .filter(|(d, _)| *d <= 2 && *d < word.chars().count())
+        .min_by_key(|(d, _)| *d)
+        .map_or(String::new(), |(_, c)| format!("; did you mean \"{}\"?", c))
+}
+
+/// The Levenshtein distance between two strings, where a transposition of adjacent characters counts as one edit.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    (0..=a.len()).for_each(|i| d[i][0] = i);
+    (0..=b.len()).for_each(|j| d[0][j] = j);
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1)
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+/// Compile a stylesheet for the XPath transform function.
+/// The stylesheet is copied first, since compiling it strips whitespace.
+/// Prefixes in attribute values are resolved using the namespace declarations of the document element.
+pub(crate) fn compile_nested<N: Node>(styledoc: &N) -> Result<Context<N>, Error> {
+    let doc = styledoc.deep_copy()?;
+    let ns = doc
+        .child_iter()
+        .find(|c| c.is_element())
+        .map_or(vec![], |e| vec![in_scope_namespaces(&e)]);
+    from_tree(doc, ns, None)
+}
+
+/// A syntax error in an XPath expression is reported at the element in the stylesheet that has the expression in an attribute.
+/// The error message gives the position within the expression.
+fn locate_syntax_error<N: Node>(mut e: Error, n: &N) -> Error {
+    if e.location.is_none()
+        && e.code
+            .as_ref()
+            .is_some_and(|c| c.get_localname() == "XPST0003")
+    {
+        e.location = instruction_location(n).map(Box::new)
+    }
+    e
+}
+
+/// Resolve the href of an xsl:include or xsl:import element to an absolute URL.
+fn module_url<N: Node>(n: &N, base: &Option<Url>) -> Result<Url, Error> {
+    let h = n.get_attribute(&QualifiedName::new(None, None, "href".to_string()));
+    base.clone()
+        .map_or_else(
+            || Url::parse(h.to_string().as_str()),
+            |full| full.join(h.to_string().as_str()),
+        )
+        .map_err(|_| {
+            Error::new(
+                ErrorKind::Unknown,
+                format!(
+                    "unable to parse href URL \"{}\" baseurl \"{}\"",
+                    h,
+                    base.clone()
+                        .map_or(String::from("--no base--"), |b| b.to_string())
+                ),
+            )
+        })
+}
+
+/// The document element of a stylesheet module.
+fn module_element<N: Node>(module: &N, url: &Url) -> Result<N, Error> {
+    module.first_child().ok_or(Error::new(
+        ErrorKind::TypeError,
+        format!(
+            "stylesheet module \"{}\" does not have document element",
+            url
+        ),
+    ))
+}
+
+/// Apply the default-mode and default-validation attributes of a stylesheet module.
+/// This must be done before the module's includes are merged, since the defaults only apply to the module itself.
+/// Templates and xsl:apply-templates instructions that have no mode, or the mode "#default", are given the default mode.
+/// The mode "#unnamed" is the unnamed mode.
+fn module_defaults<N: Node>(module: &N) -> Result<(), Error> {
+    let validation = module
+        .get_attribute(&QualifiedName::new(None, None, "default-validation"))
+        .to_string();
+    if !matches!(validation.trim(), "" | "strip" | "preserve") {
+        return Err(Error::new_with_code(
+            ErrorKind::StaticData,
+            format!("invalid value \"{}\" for default-validation", validation),
+            Some(QualifiedName::new(None, None, "XTSE0020")),
+        ));
+    }
+
+    let dm = module
+        .get_attribute(&QualifiedName::new(None, None, "default-mode"))
+        .to_string();
+    let default_mode = match dm.trim() {
+        "" | "#unnamed" => None,
+        m => Some(m.to_string()),
+    };
+    let mode_qn = QualifiedName::new(None, None, "mode");
+    let targets: Vec<N> = module
+        .descend_iter()
+        .filter(|d| {
+            d.is_element()
+                && d.name().get_nsuri_ref() == Some(XSLTNS)
+                && (d.name().get_localname() == "apply-templates"
+                    || (d.name().get_localname() == "template"
+                        && d.parent().is_some_and(|p| p.is_same(module))
+                        && !d
+                            .get_attribute(&QualifiedName::new(None, None, "match"))
+                            .to_string()
+                            .is_empty()))
+        })
+        .collect();
+    targets.iter().try_for_each(|t| {
+        let mode = t.get_attribute_node(&mode_qn);
+        match (mode, &default_mode) {
+            (Some(mut m), _) if m.to_string().trim() == "#unnamed" => m.pop(),
+            (Some(mut m), None) if m.to_string().trim() == "#default" => m.pop(),
+            (Some(m), Some(d)) if m.to_string().trim() == "#default" => {
+                t.add_attribute(t.new_attribute(mode_qn.clone(), Rc::new(Value::from(d.clone())))?)
+            }
+            (None, Some(d)) => {
+                t.add_attribute(t.new_attribute(mode_qn.clone(), Rc::new(Value::from(d.clone())))?)
+            }
+            _ => Ok(()),
+        }
+    })
+}
+
+/// Describe the chain of modules that leads back to a module.
+fn module_cycle(active: &[Url], url: &Url) -> String {
+    active
+        .iter()
+        .skip_while(|u| *u != url)
+        .chain(std::iter::once(url))
+        .map(|u| u.to_string())
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+/// The serialization parameters of an xsl:output element.
+/// The stylesheet compiler uses this for the xsl:output elements of a stylesheet,
+/// and it may also be used to build an [OutputDefinition] from an xsl:output element that has been parsed separately.
+pub fn output_definition<N: Node>(n: &N) -> Result<OutputDefinition, Error> {
+    let attr = |a: &str| {
+        n.get_attribute(&QualifiedName::new(None, None, a))
+            .to_string()
+    };
+    let ns = vec![in_scope_namespaces(n)];
+    let mut b = OutputDefinitionBuilder::new()
+        .indent(yes_or_no(
+            n,
+            QualifiedName::new(None, None, "indent"),
+            false,
+        )?)
+        .byte_order_mark(yes_or_no(
+            n,
+            QualifiedName::new(None, None, "byte-order-mark"),
+            false,
+        )?)
+        .omit_xml_declaration(yes_or_no(
+            n,
+            QualifiedName::new(None, None, "omit-xml-declaration"),
+            true,
+        )?);
+    let name = attr("name");
+    if !name.is_empty() {
+        b = b.name(QualifiedName::try_from((name.as_str(), &ns))?)
+    }
+    let method = attr("method");
+    if !method.is_empty() {
+        b = b.method(&method)?
+    }
+    let encoding = attr("encoding");
+    if !encoding.is_empty() {
+        b = b.encoding(&encoding)
+    }
+    let nf = attr("normalization-form");
+    if !nf.is_empty() {
+        b = b.normalization_form(&nf)?
+    }
+    let public = attr("doctype-public");
+    if !public.is_empty() {
+        b = b.doctype_public(&public)
+    }
+    let system = attr("doctype-system");
+    if !system.is_empty() {
+        b = b.doctype_system(&system)
+    }
+    for e in attr("cdata-section-elements").split_whitespace() {
+        b = b.cdata_section_element(QualifiedName::try_from((e, &ns))?)
+    }
+    Ok(b.build())
+}
+
+/// Compile the xsl:with-param children of an element to actual parameters.
+/// Either the tunnel parameters or the other parameters are compiled.
+fn with_params<N: Node>(
+    n: &N,
+    tunnel: bool,
+    ns: &Vec<HashMap<String, String>>,
+    attr_sets: &HashMap<QualifiedName, Vec<Transform<N>>>,
+) -> Result<Vec<(QualifiedName, Transform<N>)>, Error> {
+    n.child_iter()
+        .filter(|c| {
+            c.is_element()
+                && c.name().get_nsuri_ref() == Some(XSLTNS)
+                && c.name().get_localname() == "with-param"
+        })
+        .map(|c| {
+            Ok((
+                yes_or_no(&c, QualifiedName::new(None, None, "tunnel"), false)?,
+                c,
+            ))
+        })
+        .collect::<Result<Vec<_>, Error>>()?
+        .into_iter()
+        .filter(|(t, _)| *t == tunnel)
+        .map(|(_, c)| {
+            let wp_name = c.get_attribute(&QualifiedName::new(None, None, "name"));
+            if wp_name.to_string().is_empty() {
+                Err(Error::new(
+                    ErrorKind::StaticAbsent,
+                    "missing name attribute",
+                ))
+            } else {
+                Ok((
+                    QualifiedName::new(None, None, wp_name.to_string()),
+                    materialize(&c, select_or_content(&c, ns, attr_sets)?),
+                ))
+            }
+        })
+        .collect()
+}
+
+/// Pass the tunnel parameters given by the xsl:with-param children of an instruction to the templates it invokes.
+fn pass_tunnel_params<N: Node>(
+    n: &N,
+    t: Transform<N>,
+    ns: &Vec<HashMap<String, String>>,
+    attr_sets: &HashMap<QualifiedName, Vec<Transform<N>>>,
+) -> Result<Transform<N>, Error> {
+    let params = with_params(n, true, ns, attr_sets)?;
+    if params.is_empty() {
+        Ok(t)
+    } else {
+        Ok(Transform::Tunnel(params, Box::new(t)))
+    }
+}
+
+/// Whether an element in a template is the declaration of a tunnel parameter.
+fn is_tunnel_param<N: Node>(c: &N) -> bool {
+    c.is_element()
+        && c.name().get_nsuri_ref() == Some(XSLTNS)
+        && c.name().get_localname() == "param"
+        && yes_or_no(c, QualifiedName::new(None, None, "tunnel"), false).unwrap_or(false)
+}
+
+/// Declare the tunnel parameters of a template, which are then in scope for the template body.
+/// The value of a tunnel parameter is the value passed by an invoking template, or else its default value.
+/// A required tunnel parameter has no default value.
+fn declare_tunnel_params<N: Node>(
+    template: &N,
+    body: Transform<N>,
+    ns: &Vec<HashMap<String, String>>,
+    attr_sets: &HashMap<QualifiedName, Vec<Transform<N>>>,
+) -> Result<Transform<N>, Error> {
+    template
+        .child_iter()
+        .filter(is_tunnel_param)
+        .try_fold(body, |b, c| {
+            let p_name = c.get_attribute(&QualifiedName::new(None, None, "name"));
+            if p_name.to_string().is_empty() {
+                return Err(Error::new(
+                    ErrorKind::StaticAbsent,
+                    "name attribute is missing",
+                ));
+            }
+            let qn = QualifiedName::new(None, None, p_name.to_string());
+            let dflt = if yes_or_no(&c, QualifiedName::new(None, None, "required"), false)? {
+                None
+            } else {
+                Some(Box::new(materialize(
+                    &c,
+                    variable_value(&c, ns, attr_sets)?,
+                )))
+            };
+            Ok(Transform::VariableDeclaration(
+                qn.to_string(),
+                Box::new(Transform::TunnelParameter(qn, dflt)),
+                Box::new(b),
+            ))
+        })
+}
+
+/// A text node in a sequence constructor, either literal text or xsl:text.
+/// Unless output escaping is disabled, the value is escaped when the text node is created.
+/// Literal text is not escaped, since it is also the content of comments, processing instructions and messages.
+/// Escaping does not wait for serialisation, so it does not depend on the output method:
+/// to produce unescaped characters with a non-XML output method, such as text, disable output escaping.
+fn literal_text<N: Node>(s: String, doe: bool) -> Transform<N> {
+    Transform::LiteralText(
+        Box::new(Transform::Literal(Item::Value(Rc::new(Value::from(s))))),
+        doe,
+    )
+}
+
+/// The value of a variable or parameter that is defined by its content, rather than a select attribute.
+/// Unless the type is declared, the content is normalised as for the content of a node.
+fn materialize<N: Node>(n: &N, t: Transform<N>) -> Transform<N> {
+    if n.get_attribute(&QualifiedName::new(None, None, "select"))
+        .to_string()
+        .is_empty()
+        && n.get_attribute(&QualifiedName::new(None, None, "as"))
+            .to_string()
+            .is_empty()
+    {
+        Transform::ComplexContent(Box::new(t))
+    } else {
+        t
+    }
+}
+
+/// An xsl:copy-of instruction at the top level of a template body produces part of the result of the template.
+/// When that result is only serialised (see [Context::evaluate_to_string]), the selected nodes may be written without first being copied.
+/// This does not apply when in-scope namespaces are not copied, since the namespaces of the nodes must then be changed.
+fn direct_output<N: Node>(t: Transform<N>) -> Transform<N> {
+    match t {
+        Transform::DeepCopy(s, true) => Transform::DirectCopy(s),
+        _ => t,
+    }
+}
+
+/// Compile the value of an element that has either a select attribute or a sequence constructor.
+fn select_or_content<N: Node>(
+    n: &N,
+    ns: &Vec<HashMap<String, String>>,
+    attr_sets: &HashMap<QualifiedName, Vec<Transform<N>>>,
+) -> Result<Transform<N>, Error> {
+    let sel = n.get_attribute(&QualifiedName::new(None, None, "select"));
+    if sel.to_string().is_empty() {
+        // content is the sequence constructor
+        Ok(Transform::SequenceItems(n.child_iter().try_fold(
+            vec![],
+            |mut body, d| {
+                body.push(to_transform(d, ns, attr_sets)?);
+                Ok::<_, Error>(body)
+            },
+        )?))
+    } else {
+        // select attribute value is an expression
+        parse::<N>(&sel.to_string())
+    }
+}
+
+/// Compile the value of an xsl:variable or xsl:param element.
+/// The value is given either by the select attribute or by the content, but not both. See XSLT 9.3.
+fn variable_value<N: Node>(
+    n: &N,
+    ns: &Vec<HashMap<String, String>>,
+    attr_sets: &HashMap<QualifiedName, Vec<Transform<N>>>,
+) -> Result<Transform<N>, Error> {
+    if !n
+        .get_attribute(&QualifiedName::new(None, None, "select"))
+        .to_string()
+        .is_empty()
+        && has_content(n)
+    {
+        return Err(Error::new_with_code(
+            ErrorKind::StaticAbsent,
+            format!(
+                "variable \"{}\" has both a select attribute and content",
+                n.get_attribute(&QualifiedName::new(None, None, "name"))
+            ),
+            Some(QualifiedName::new(None, None, "XTSE0620")),
+        ));
+    }
+    select_or_content(n, ns, attr_sets)
+}
+
+// Whether an element in the stylesheet has a sequence constructor.
+fn has_content<N: Node>(n: &N) -> bool {
+    n.child_iter().any(|c| is_significant(&c))
+}
+
+// Comments, processing instructions and whitespace-only text nodes are not significant in a sequence constructor.
+fn is_significant<N: Node>(c: &N) -> bool {
+    match c.node_type() {
+        NodeType::Element => true,
+        NodeType::Text => !c.to_string().trim().is_empty(),
+        _ => false,
+    }
+}
+
+// The XSLT version in effect for an element in the stylesheet.
+// This is given by the version attribute of the closest XSLT element, or the xsl:version attribute of a literal result element.
+// If no version is declared then the version implemented by the processor is assumed.
+fn xslt_version<N: Node>(n: &N) -> f64 {
+    Some(n.clone())
+        .into_iter()
+        .chain(n.ancestor_iter())
+        .filter(|a| a.node_type() == NodeType::Element)
+        .find_map(|a| {
+            let v = if a.name().get_nsuri_ref() == Some(XSLTNS) {
+                a.get_attribute(&QualifiedName::new(None, None, "version"))
+            } else {
+                a.get_attribute(&QualifiedName::new(
+                    Some(XSLTNS.to_string()),
+                    None,
+                    "version",
+                ))
+            };
+            v.to_string().trim().parse::<f64>().ok()
+        })
+        .unwrap_or(3.0)
+}
+
+// The value of a yes-or-no attribute, such as copy-namespaces. If the attribute is not present then the default is used.
+fn yes_or_no<N: Node>(n: &N, attr: QualifiedName, default: bool) -> Result<bool, Error> {
+    match n.get_attribute(&attr).to_string().trim() {
+        "" => Ok(default),
+        "yes" | "true" | "1" => Ok(true),
+        "no" | "false" | "0" => Ok(false),
+        _ => Err(Error::new(
+            ErrorKind::TypeError,
+            format!("{} only accepts values yes or no.", attr),
+        )),
+    }
+}
+
+// The namespace declarations that are in scope for an element in the stylesheet, as a map from prefix to URI.
+// The default namespace has an empty prefix. The xml prefix is always in scope.
+fn in_scope_namespaces<N: Node>(n: &N) -> HashMap<String, String> {
+    let mut result = HashMap::from([(
+        "xml".to_string(),
+        "http://www.w3.org/XML/1998/namespace".to_string(),
+    )]);
+    // The closest declaration of a prefix is the one that is in scope
+    Some(n.clone())
+        .into_iter()
+        .chain(n.ancestor_iter())
+        .filter(|a| a.node_type() == NodeType::Element)
+        .flat_map(|a| a.namespace_iter())
+        .for_each(|ns| {
+            result
+                .entry(ns.name().get_localname())
+                .or_insert_with(|| ns.value().to_string());
+        });
+    result
+}
+
+// The value of an instruction that constructs simple content: xsl:attribute, xsl:comment, xsl:processing-instruction or xsl:namespace.
+// The value is given either by the select attribute or by the content of the instruction. See XSLT v3.0 5.7.2.
+// The items are joined using the separator, if the instruction allows one (only xsl:attribute does),
+// which defaults to a single space for a select expression and to a zero-length string for the content.
+fn simple_content<N: Node>(
+    n: &N,
+    ns: &Vec<HashMap<String, String>>,
+    attr_sets: &HashMap<QualifiedName, Vec<Transform<N>>>,
+    separator: bool,
+) -> Result<Transform<N>, Error> {
+    let sel = n.get_attribute_node(&QualifiedName::new(None, None, "select"));
+    let sep = if separator {
+        n.get_attribute_node(&QualifiedName::new(None, None, "separator"))
+    } else {
+        None
+    };
+    let body = match &sel {
+        Some(s) => parse::<N>(&s.to_string())?,
+        None => Transform::SequenceItems(n.child_iter().try_fold(vec![], |mut body, e| {
+            body.push(to_transform(e, ns, attr_sets)?);
+            Ok(body)
+        })?),
+    };
+    if sel.is_none() && sep.is_none() {
+        // The string values of the content are concatenated
+        return Ok(body);
+    }
+    let sep = sep.map_or_else(
+        || {
+            Ok(Transform::Literal(Item::Value(Rc::new(Value::from(
+                if sel.is_some() { " " } else { "" },
+            )))))
+        },
+        |s| parse_avt(s.to_string().as_str()),
+    )?;
+    Ok(Transform::ValueOf(Box::new(body), Box::new(sep), false))
+}
+
+// The namespace attribute of xsl:element and xsl:attribute, which is an AVT.
+fn namespace_avt<N: Node>(n: &N) -> Result<Option<Box<Transform<N>>>, Error> {
+    let u = n.get_attribute_node(&QualifiedName::new(None, None, "namespace"));
+    u.map_or(Ok(None), |u| {
+        Ok(Some(Box::new(parse_avt(u.to_string().as_str())?)))
+    })
+}
+
+// The namespaces that are in scope for an element in the stylesheet, except for the XSLT namespace
+// and any namespaces excluded by exclude-result-prefixes.
+// These are copied to a literal result element. Returns (prefix, URI) pairs.
+fn result_namespaces<N: Node>(n: &N) -> Result<Vec<(String, String)>, Error> {
+    let mut result: Vec<(String, String)> = in_scope_namespaces(n)
+        .into_iter()
+        .filter(|(p, _)| p != "xml")
+        .collect();
+    let mut excluded: Vec<String> = vec![XSLTNS.to_string()];
+    let elements: Vec<N> = Some(n.clone())
+        .into_iter()
+        .chain(n.ancestor_iter())
+        .filter(|a| a.node_type() == NodeType::Element)
+        .collect();
+    // Prefixes may be excluded by the xsl:stylesheet element,
+    // or by an xsl:exclude-result-prefixes attribute on a literal result element.
+    elements.iter().enumerate().try_for_each(|(i, e)| {
+        let erp = if e.name().get_nsuri_ref() == Some(XSLTNS) {
+            e.get_attribute(&QualifiedName::new(None, None, "exclude-result-prefixes"))
+        } else {
+            e.get_attribute(&QualifiedName::new(
+                Some(XSLTNS.to_string()),
+                None,
+                "exclude-result-prefixes",
+            ))
+        };
+        // The namespaces that are in scope for the element with the attribute.
+        // For a given prefix, only the closest declaration is in scope.
+        let in_scope = |prefix: Option<&str>| {
+            elements[i..]
+                .iter()
+                .flat_map(|a| a.namespace_iter())
+                .filter(|ns| prefix.is_none_or(|p| ns.name().get_localname() == p))
+                .take(if prefix.is_some() { 1 } else { usize::MAX })
+                .map(|ns| ns.value().to_string())
+                .collect::<Vec<String>>()
+        };
+        erp.to_string().split_whitespace().try_for_each(|p| {
+            match p {
+                "#all" => excluded.append(&mut in_scope(None)),
+                "#default" => excluded.append(&mut in_scope(Some(""))),
+                _ => {
+                    let mut u = in_scope(Some(p));
+                    if u.is_empty() {
+                        return Err(Error::new_with_code(
+                            ErrorKind::StaticAbsent,
+                            format!("excluded prefix \"{}\" is not declared", p),
+                            Some(QualifiedName::new(None, None, "XTSE0808")),
+                        ));
+                    }
+                    excluded.append(&mut u)
+                }
+            }
+            Ok(())
+        })
+    })?;
+    excluded.append(&mut extension_namespaces(n)?);
+    result.retain(|(_, u)| !excluded.contains(u));
+    result.sort();
+    Ok(result)
+}
+
+// The namespaces that are extension namespaces for an element in the stylesheet.
+// They are declared by the xsl:stylesheet element, or by an xsl:extension-element-prefixes attribute
+// on a literal result element or extension instruction, or on one of its ancestors. See XSLT v3.0 10.1.
+fn extension_namespaces<N: Node>(n: &N) -> Result<Vec<String>, Error> {
+    Some(n.clone())
+        .into_iter()
+        .chain(n.ancestor_iter())
+        .filter(|a| a.node_type() == NodeType::Element)
+        .try_fold(vec![], |mut acc, e| {
+            let eep = if e.name().get_nsuri_ref() == Some(XSLTNS) {
+                e.get_attribute(&QualifiedName::new(
+                    None,
+                    None,
+                    "extension-element-prefixes",
+                ))
+            } else {
+                e.get_attribute(&QualifiedName::new(
+                    Some(XSLTNS.to_string()),
+                    None,
+                    "extension-element-prefixes",
+                ))
+            };
+            let in_scope = in_scope_namespaces(&e);
+            eep.to_string().split_whitespace().try_for_each(|p| {
+                let prefix = if p == "#default" { "" } else { p };
+                match in_scope.get(prefix) {
+                    Some(u) => {
+                        acc.push(u.clone());
+                        Ok(())
+                    }
+                    None => Err(Error::new_with_code(
+                        ErrorKind::StaticAbsent,
+                        format!("extension element prefix \"{}\" is not declared", p),
+                        Some(QualifiedName::new(None, None, "XTSE1430")),
+                    )),
+                }
+            })?;
+            Ok(acc)
+        })
+}
+
+// An element in an extension namespace is an extension instruction. Its attributes are attribute value templates,
+// and its xsl:fallback children are evaluated if there is no handler for the instruction.
+fn extension_instruction<N: Node>(
+    n: &N,
+    ns: &Vec<HashMap<String, String>>,
+    attr_sets: &HashMap<QualifiedName, Vec<Transform<N>>>,
+) -> Result<Transform<N>, Error> {
+    let attributes = n
+        .attribute_iter()
+        .filter(|a| a.name().get_nsuri_ref() != Some(XSLTNS))
+        .map(|a| Ok((a.name(), parse_avt(a.value().to_string().as_str())?)))
+        .collect::<Result<Vec<_>, Error>>()?;
+    let fallback = n
+        .child_iter()
+        .filter(|c| {
+            c.node_type() == NodeType::Element
+                && c.name() == QualifiedName::new(Some(XSLTNS.to_string()), None, "fallback")
+        })
+        .map(|f| {
+            f.child_iter()
+                .map(|c| to_transform(c, ns, attr_sets))
+                .collect::<Result<Vec<_>, Error>>()
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    Ok(Transform::ExtensionInstruction(
+        n.name(),
+        attributes,
+        if fallback.is_empty() {
+            None
+        } else {
+            Some(Box::new(Transform::SequenceItems(
+                fallback.into_iter().flatten().collect(),
+            )))
+        },
+    ))
+}
+
+fn is_sort<N: Node>(n: &N) -> bool {
+    n.node_type() == NodeType::Element
+        && n.name() == QualifiedName::new(Some(XSLTNS.to_string()), None, "sort")
+}
+
+fn sort_key<N: Node>(n: &N) -> Result<(Order<N>, Transform<N>), Error> {
+    // order is an attribute value template. A fixed value is checked now, a computed value when sorting.
+    let o = n.get_attribute(&QualifiedName::new(None, None, "order"));
+    let ord = match o.to_string().trim() {
+        "" | "ascending" => Order::Ascending,
+        "descending" => Order::Descending,
+        v if v.contains('{') => Order::Computed(Box::new(parse_avt(v)?)),
+        v => {
+            return Err(Error::new_with_code(
+                ErrorKind::StaticData,
+                format!("invalid value \"{}\" for order", v),
+                Some(QualifiedName::new(None, None, "XTSE0020")),
+            ))
+        }
+    };
+    let sortsel = n.get_attribute(&QualifiedName::new(None, None, "select"));
+    Ok((ord, parse::<N>(&sortsel.to_string())?))
+}
+
+type SortKeys<N> = Vec<(Order<N>, Transform<N>)>;
+
+/// The xsl:sort children of an instruction give its sort keys, and the remaining children are its body.
+fn sort_keys_and_body<N: Node>(
+    n: &N,
+    ns: &Vec<HashMap<String, String>>,
+    attr_sets: &HashMap<QualifiedName, Vec<Transform<N>>>,
+) -> Result<(SortKeys<N>, Transform<N>), Error> {
+    let (sorts, body): (Vec<N>, Vec<N>) = n.child_iter().partition(is_sort);
+    Ok((
+        sorts
+            .iter()
+            .map(sort_key)
+            .collect::<Result<Vec<_>, Error>>()?,
+        Transform::SequenceItems(
+            body.into_iter()
+                .map(|e| to_transform(e, ns, attr_sets))
+                .collect::<Result<Vec<_>, Error>>()?,
+        ),
+    ))
+}
+
+fn get_sort_keys<N: Node>(n: &N) -> Result<Vec<(Order<N>, Transform<N>)>, Error> {
     n.child_iter()
         .try_fold(vec![], |mut acc, c| match c.node_type() {
             NodeType::Element => {
-                if c.name() == QualifiedName::new(Some(XSLTNS.to_string()), None, "sort") {
-                    let ordval = c.get_attribute(&QualifiedName::new(None, None, "order"));
-                    let ord = match ordval.to_string().as_str() {
-                        "descending" => Order::Descending,
-                        _ => Order::Ascending,
-                    };
-                    let sortsel = c.get_attribute(&QualifiedName::new(None, None, "select"));
-                    acc.push((ord, parse::<N>(&sortsel.to_string())?));
+                if is_sort(&c) {
+                    acc.push(sort_key(&c)?);
                     Ok(acc)
                 } else {
                     Err(Error::new(
@@ -1335,204 +2940,97 @@ fn get_sort_keys<N: Node>(n: &N) -> Result<Vec<(Order, Transform<N>)>, Error> {
         })
 }
 
-/// Strip whitespace nodes from a XDM tree.
-/// See [XSLT 4.3](https://www.w3.org/TR/2017/REC-xslt-30-20170608/#stylesheet-stripping).
-/// The [Node] argument must be the document node of the tree.
-pub fn strip_whitespace<N: Node>(
-    t: N,
-    cpi: bool, // strip comments and PIs?
-    strip: &Vec<NodeTest>,
-    preserve: &Vec<NodeTest>,
-) -> Result<(), Error> {
-    t.child_iter().try_for_each(|n| {
-        strip_whitespace_node(n, cpi, strip, preserve, true)?;
-        Ok(())
-    })?;
-    Ok(())
+/// Strip whitespace-only text nodes, comments and processing instructions from a stylesheet module.
+/// White space is kept in xsl:text elements, and wherever xml:space='preserve' is in effect.
+fn strip_stylesheet<N: Node>(styledoc: N) -> Result<(), Error> {
+    strip_comments(&styledoc)?;
+    strip_whitespace(
+        styledoc,
+        true,
+        &vec![NodeTest::Name(NameTest {
+            ns: None,
+            prefix: None,
+            name: Some(WildcardOrName::Wildcard),
+        })],
+        &vec![NodeTest::Name(NameTest {
+            ns: Some(WildcardOrName::Name(XSLTNS.to_string())),
+            prefix: Some("xsl".to_string()),
+            name: Some(WildcardOrName::Name("text".to_string())),
+        })],
+    )
+}
+
+/// Remove comments and processing instructions from a stylesheet module, and then merge adjacent text nodes.
+/// This is done before whitespace is stripped, so that a comment does not split a text node
+/// (see [XSLT 4.2](https://www.w3.org/TR/2017/REC-xslt-30-20170608/#stylesheet-stripping)).
+fn strip_comments<N: Node>(styledoc: &N) -> Result<(), Error> {
+    let (mut cpi, parents): (Vec<N>, Vec<N>) = styledoc
+        .descend_iter()
+        .filter(|d| {
+            matches!(
+                d.node_type(),
+                NodeType::Comment | NodeType::ProcessingInstruction | NodeType::Element
+            )
+        })
+        .partition(|d| d.node_type() != NodeType::Element);
+    cpi.iter_mut().try_for_each(|c| c.pop())?;
+    parents.iter().try_for_each(|p| {
+        let children: Vec<N> = p.child_iter().collect();
+        children
+            .chunk_by(|a, b| a.node_type() == NodeType::Text && b.node_type() == NodeType::Text)
+            .filter(|run| run.len() > 1)
+            .try_for_each(|run| {
+                let mut first = run[0].clone();
+                let text = run.iter().map(|t| t.to_string()).collect::<String>();
+                first.insert_before(p.new_text(Rc::new(Value::from(text)))?)?;
+                run.iter().try_for_each(|t| t.clone().pop())
+            })
+    })
 }
 
 /// Strip whitespace nodes from a XDM tree.
 /// This function operates under the direction of the xsl:strip-space and xsl:preserve-space directives in a XSLT stylesheet.
+/// NB. This is destructive of the source document. [Context::source_document] strips a copy of the document instead.
 pub fn strip_source_document<N: Node>(src: N, style: N) -> Result<(), Error> {
-    // Find strip-space element, if any, and use it to construct a vector of NodeTests.
-    // Ditto for preserve-space.
     let mut ss: Vec<NodeTest> = vec![];
     let mut ps: Vec<NodeTest> = vec![];
     style.child_iter().try_for_each(|n| {
         // n should be the xsl:stylesheet element
-        n.child_iter().try_for_each(|m| {
-            match (
-                m.node_type(),
-                m.name().get_nsuri_ref(),
-                m.name().get_localname().as_str(),
-            ) {
-                (NodeType::Element, Some(XSLTNS), "strip-space") => {
-                    let v =
-                        m.get_attribute(&QualifiedName::new(None, None, "elements".to_string()));
-                    if !v.to_string().is_empty() {
-                        v.to_string().split_whitespace().try_for_each(|t| {
-                            ss.push(NodeTest::try_from(t)?);
-                            Ok::<(), Error>(())
-                        })?
-                    } else {
-                        return Result::Err(Error::new(
-                            ErrorKind::Unknown,
-                            String::from("missing elements attribute"),
-                        ));
-                    }
-                }
-                (NodeType::Element, Some(XSLTNS), "preserve-space") => {
-                    let v =
-                        m.get_attribute(&QualifiedName::new(None, None, "elements".to_string()));
-                    if !v.to_string().is_empty() {
-                        v.to_string().split_whitespace().try_for_each(|t| {
-                            ps.push(NodeTest::try_from(t)?);
-                            Ok::<(), Error>(())
-                        })?
-                    } else {
-                        return Result::Err(Error::new(
-                            ErrorKind::Unknown,
-                            String::from("missing elements attribute"),
-                        ));
-                    }
-                }
-                _ => {}
-            }
-            Ok::<(), Error>(())
-        })?;
+        let (s, p) = space_declarations(&n)?;
+        ss.extend(s);
+        ps.extend(p);
         Ok::<(), Error>(())
     })?;
 
     strip_whitespace(src, false, &ss, &ps)
 }
 
-// TODO: the rules for stripping/preserving are a lot more complex
-// TODO: Return Result so that errors can be propagated
-fn strip_whitespace_node<N: Node>(
-    mut n: N,
-    cpi: bool, // strip comments and PIs?
-    strip: &Vec<NodeTest>,
-    preserve: &Vec<NodeTest>,
-    keep: bool,
-) -> Result<(), Error> {
-    match n.node_type() {
-        NodeType::Comment | NodeType::ProcessingInstruction => {
-            if cpi {
-                n.pop()?;
-                // TODO: Merge text nodes that are now adjacent
-            }
-        }
-        NodeType::Element => {
-            // Determine if this element toggles the strip/preserve setting
-            // Match a strip NodeTest or a preserve NodeTest
-            // The 'strength' of the match determines which setting wins
-            let mut ss = -1.0;
-            let mut ps = -1.0;
-            strip.iter().for_each(|t| match t {
-                NodeTest::Kind(KindTest::Any) | NodeTest::Kind(KindTest::Element) => ss = -0.5,
-                NodeTest::Name(nt) => match (nt.ns.as_ref(), nt.name.as_ref()) {
-                    (None, Some(WildcardOrName::Wildcard)) => {
-                        ss = -0.25;
-                    }
-                    (None, Some(WildcardOrName::Name(name))) => {
-                        match (n.name().get_nsuri(), n.name().get_localname()) {
-                            (Some(_), _) => {}
-                            (None, ename) => {
-                                if *name == ename {
-                                    ss = 0.5;
-                                }
-                            }
-                        }
-                    }
-                    (Some(WildcardOrName::Name(ns)), Some(WildcardOrName::Name(name))) => {
-                        match (n.name().get_nsuri(), n.name().get_localname()) {
-                            (Some(ens), ename) => {
-                                if *ns == ens && *name == ename {
-                                    ss = 0.5;
-                                }
-                            }
-                            (None, ename) => {
-                                if *name == ename {
-                                    ss = 0.5;
-                                }
-                            }
-                        }
-                    }
-                    (Some(WildcardOrName::Wildcard), Some(WildcardOrName::Name(_))) => {
-                        ss = -0.25;
-                    }
-                    (Some(WildcardOrName::Name(_)), Some(WildcardOrName::Wildcard)) => {
-                        ss = -0.25;
-                    }
-                    (Some(WildcardOrName::Wildcard), Some(WildcardOrName::Wildcard)) => {
-                        ss = -0.5;
-                    }
-                    _ => {}
-                },
-                _ => {}
-            });
-            preserve.iter().for_each(|t| match t {
-                NodeTest::Kind(KindTest::Any) | NodeTest::Kind(KindTest::Element) => ps = -0.5,
-                NodeTest::Name(nt) => match (nt.ns.as_ref(), nt.name.as_ref()) {
-                    (None, Some(WildcardOrName::Name(name))) => {
-                        match (n.name().get_nsuri(), n.name().get_localname()) {
-                            (Some(_), _) => {}
-                            (None, ename) => {
-                                if *name == ename {
-                                    ps = 0.5;
-                                }
-                            }
-                        }
-                    }
-                    (Some(WildcardOrName::Name(ns)), Some(WildcardOrName::Name(name))) => {
-                        match (n.name().get_nsuri(), n.name().get_localname()) {
-                            (Some(ens), ename) => {
-                                if *ns == ens && *name == ename {
-                                    ps = 0.5;
-                                }
-                            }
-                            (None, ename) => {
-                                if *name == ename {
-                                    ps = 0.5;
-                                }
-                            }
-                        }
-                    }
-                    (Some(WildcardOrName::Wildcard), Some(WildcardOrName::Name(_))) => {
-                        ps = -0.25;
-                    }
-                    (Some(WildcardOrName::Name(_)), Some(WildcardOrName::Wildcard)) => {
-                        ps = -0.25;
-                    }
-                    (Some(WildcardOrName::Wildcard), Some(WildcardOrName::Wildcard)) => {
-                        ps = -0.5;
-                    }
-                    _ => {}
-                },
-                _ => {}
-            });
-            n.child_iter().try_for_each(|m| {
-                strip_whitespace_node(
-                    m,
-                    cpi,
-                    strip,
-                    preserve,
-                    if ss > -1.0 {
-                        ps >= ss
-                    } else if ps > -1.0 {
-                        true
-                    } else {
-                        keep
-                    },
-                )
-            })?
-        }
-        NodeType::Text => {
-            if n.to_string().trim().is_empty() && !keep {
-                n.pop()?;
-            }
+/// Find the xsl:strip-space and xsl:preserve-space declarations of a stylesheet,
+/// and construct a vector of NodeTests for each.
+fn space_declarations<N: Node>(stylenode: &N) -> Result<(Vec<NodeTest>, Vec<NodeTest>), Error> {
+    let mut ss: Vec<NodeTest> = vec![];
+    let mut ps: Vec<NodeTest> = vec![];
+    stylenode.child_iter().try_for_each(|m| {
+        let tests = match (
+            m.node_type(),
+            m.name().get_nsuri_ref(),
+            m.name().get_localname().as_str(),
+        ) {
+            (NodeType::Element, Some(XSLTNS), "strip-space") => &mut ss,
+            (NodeType::Element, Some(XSLTNS), "preserve-space") => &mut ps,
+            _ => return Ok(()),
+        };
+        let v = m.get_attribute(&QualifiedName::new(None, None, "elements".to_string()));
+        if v.to_string().is_empty() {
+            return Result::Err(Error::new(
+                ErrorKind::Unknown,
+                String::from("missing elements attribute"),
+            ));
         }
-        _ => {}
-    }
-    Ok(())
+        v.to_string().split_whitespace().try_for_each(|t| {
+            tests.push(NodeTest::try_from(t)?);
+            Ok::<(), Error>(())
+        })
+    })?;
+    Ok((ss, ps))
 }
@@ -0,0 +1,197 @@
+// A node type for tests that counts the nodes that are visited, and the copies that are made.
+// It wraps a smite node, and every node that is produced by navigating the tree is counted.
+// Every shallow or deep copy of a node is also counted.
+// All of the nodes of a tree share the same counters.
+
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use xrust::item::{Node, NodeType};
+use xrust::output::OutputDefinition;
+use xrust::parser::xml::{parse as xmlparse, parse_with_ns};
+use xrust::qname::QualifiedName;
+use xrust::trees::smite::{Node as SmiteNode, RNode};
+use xrust::value::Value;
+use xrust::xdmerror::Error;
+use xrust::xmldecl::XMLDecl;
+
+#[derive(Clone, Debug, Default)]
+pub struct Counters {
+    visited: Cell<usize>,
+    copied: Cell<usize>,
+}
+
+#[derive(Clone, Debug)]
+pub struct CNode(RNode, Rc<Counters>);
+
+impl PartialEq for CNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl CNode {
+    fn wrap(&self, n: RNode) -> CNode {
+        self.1.visited.set(self.1.visited.get() + 1);
+        CNode(n, self.1.clone())
+    }
+    fn copy(&self, n: RNode) -> CNode {
+        self.1.copied.set(self.1.copied.get() + 1);
+        CNode(n, self.1.clone())
+    }
+    fn wrap_iter(&self, it: Box<dyn Iterator<Item = RNode>>) -> Box<dyn Iterator<Item = CNode>> {
+        let c = self.clone();
+        Box::new(it.map(move |n| c.wrap(n)))
+    }
+}
+
+impl Node for CNode {
+    type NodeIterator = Box<dyn Iterator<Item = CNode>>;
+
+    fn node_type(&self) -> NodeType {
+        self.0.node_type()
+    }
+    fn name(&self) -> QualifiedName {
+        self.0.name()
+    }
+    fn value(&self) -> Rc<Value> {
+        self.0.value()
+    }
+    fn get_id(&self) -> String {
+        self.0.get_id()
+    }
+    fn to_string(&self) -> String {
+        Node::to_string(&self.0)
+    }
+    fn to_xml(&self) -> String {
+        self.0.to_xml()
+    }
+    fn to_xml_with_options(&self, od: &OutputDefinition) -> String {
+        self.0.to_xml_with_options(od)
+    }
+    fn is_same(&self, other: &Self) -> bool {
+        self.0.is_same(&other.0)
+    }
+    fn document_order(&self) -> Vec<usize> {
+        self.0.document_order()
+    }
+    fn cmp_document_order(&self, other: &Self) -> Ordering {
+        self.0.cmp_document_order(&other.0)
+    }
+    fn child_iter(&self) -> Self::NodeIterator {
+        self.wrap_iter(self.0.child_iter())
+    }
+    fn ancestor_iter(&self) -> Self::NodeIterator {
+        self.wrap_iter(self.0.ancestor_iter())
+    }
+    fn owner_document(&self) -> Self {
+        self.wrap(self.0.owner_document())
+    }
+    fn descend_iter(&self) -> Self::NodeIterator {
+        self.wrap_iter(self.0.descend_iter())
+    }
+    fn next_iter(&self) -> Self::NodeIterator {
+        self.wrap_iter(self.0.next_iter())
+    }
+    fn prev_iter(&self) -> Self::NodeIterator {
+        self.wrap_iter(self.0.prev_iter())
+    }
+    fn attribute_iter(&self) -> Self::NodeIterator {
+        self.wrap_iter(self.0.attribute_iter())
+    }
+    fn namespace_iter(&self) -> Self::NodeIterator {
+        self.wrap_iter(self.0.namespace_iter())
+    }
+    fn get_attribute(&self, a: &QualifiedName) -> Rc<Value> {
+        self.0.get_attribute(a)
+    }
+    fn get_attribute_node(&self, a: &QualifiedName) -> Option<Self> {
+        self.0.get_attribute_node(a).map(|n| self.wrap(n))
+    }
+    fn new_element(&self, qn: QualifiedName) -> Result<Self, Error> {
+        Ok(CNode(self.0.new_element(qn)?, self.1.clone()))
+    }
+    fn new_text(&self, v: Rc<Value>) -> Result<Self, Error> {
+        Ok(CNode(self.0.new_text(v)?, self.1.clone()))
+    }
+    fn new_attribute(&self, qn: QualifiedName, v: Rc<Value>) -> Result<Self, Error> {
+        Ok(CNode(self.0.new_attribute(qn, v)?, self.1.clone()))
+    }
+    fn new_comment(&self, v: Rc<Value>) -> Result<Self, Error> {
+        Ok(CNode(self.0.new_comment(v)?, self.1.clone()))
+    }
+    fn new_processing_instruction(&self, qn: QualifiedName, v: Rc<Value>) -> Result<Self, Error> {
+        Ok(CNode(
+            self.0.new_processing_instruction(qn, v)?,
+            self.1.clone(),
+        ))
+    }
+    fn new_namespace(&self, ns: String, prefix: Option<String>) -> Result<Self, Error> {
+        Ok(CNode(self.0.new_namespace(ns, prefix)?, self.1.clone()))
+    }
+    fn push(&mut self, n: Self) -> Result<(), Error> {
+        self.0.push(n.0)
+    }
+    fn pop(&mut self) -> Result<(), Error> {
+        self.0.pop()
+    }
+    fn insert_before(&mut self, n: Self) -> Result<(), Error> {
+        self.0.insert_before(n.0)
+    }
+    fn add_attribute(&self, att: Self) -> Result<(), Error> {
+        self.0.add_attribute(att.0)
+    }
+    fn shallow_copy(&self) -> Result<Self, Error> {
+        Ok(self.copy(self.0.shallow_copy()?))
+    }
+    fn deep_copy(&self) -> Result<Self, Error> {
+        Ok(self.copy(self.0.deep_copy()?))
+    }
+    fn get_canonical(&self) -> Result<Self, Error> {
+        Ok(CNode(self.0.get_canonical()?, self.1.clone()))
+    }
+    fn xmldecl(&self) -> XMLDecl {
+        self.0.xmldecl()
+    }
+    fn set_xmldecl(&mut self, d: XMLDecl) -> Result<(), Error> {
+        self.0.set_xmldecl(d)
+    }
+    fn add_namespace(&self, ns: Self) -> Result<(), Error> {
+        self.0.add_namespace(ns.0)
+    }
+}
+
+pub fn make_empty_doc() -> CNode {
+    CNode(Rc::new(SmiteNode::new()), Rc::new(Counters::default()))
+}
+
+#[allow(dead_code)]
+pub fn make_doc() -> Result<CNode, Error> {
+    Ok(make_empty_doc())
+}
+
+#[allow(dead_code)]
+pub fn make_from_str(s: &str) -> Result<CNode, Error> {
+    let doc = make_empty_doc();
+    xmlparse(doc.clone(), s, None)?;
+    Ok(doc)
+}
+
+#[allow(dead_code)]
+pub fn make_from_str_with_ns(s: &str) -> Result<(CNode, Vec<HashMap<String, String>>), Error> {
+    parse_with_ns(make_empty_doc(), s, None)
+}
+
+// The number of nodes that have been visited in the tree
+#[allow(dead_code)]
+pub fn visited(n: &CNode) -> usize {
+    n.1.visited.get()
+}
+
+// The number of copies that have been made of nodes in the tree
+#[allow(dead_code)]
+pub fn copied(n: &CNode) -> usize {
+    n.1.copied.get()
+}
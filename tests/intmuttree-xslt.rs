@@ -0,0 +1,27 @@
+mod intmuttree;
+mod smite;
+#[allow(dead_code)]
+mod xsltgeneric;
+
+// XSLT tests that construct their results in an intmuttree
+
+#[test]
+fn xslt_document_factory_intmuttree() {
+    xsltgeneric::generic_document_factory(
+        intmuttree::make_from_str,
+        intmuttree::make_from_str_with_ns,
+        intmuttree::make_empty_doc,
+    )
+    .expect("test failed")
+}
+// A smite source document and stylesheet, with an intmuttree result
+#[test]
+fn xslt_result_tree_intmuttree() {
+    xsltgeneric::generic_result_tree(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+        intmuttree::make_empty_doc,
+    )
+    .expect("test failed")
+}
@@ -0,0 +1,26 @@
+// Support functions for intmuttree tests
+
+use std::collections::HashMap;
+
+use xrust::item::NodeType;
+use xrust::parser::xml::{parse as xmlparse, parse_with_ns};
+use xrust::trees::intmuttree::{NodeBuilder, RNode};
+use xrust::xdmerror::Error;
+
+#[allow(dead_code)]
+pub fn make_empty_doc() -> Result<RNode, Error> {
+    Ok(NodeBuilder::new(NodeType::Document).build())
+}
+
+#[allow(dead_code)]
+pub fn make_from_str(s: &str) -> Result<RNode, Error> {
+    let doc = NodeBuilder::new(NodeType::Document).build();
+    xmlparse(doc.clone(), s, None)?;
+    Ok(doc)
+}
+
+#[allow(dead_code)]
+pub fn make_from_str_with_ns(s: &str) -> Result<(RNode, Vec<HashMap<String, String>>), Error> {
+    let doc = NodeBuilder::new(NodeType::Document).build();
+    parse_with_ns(doc, s, None)
+}
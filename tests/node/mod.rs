@@ -1,7 +1,8 @@
 //! Generic node tests
 
 use std::rc::Rc;
-use xrust::item::{Node, NodeType};
+use xrust::item::{Item, Node, NodeType, Sequence, SequenceTrait};
+use xrust::output::OutputDefinition;
 use xrust::qname::QualifiedName;
 use xrust::value::Value;
 use xrust::xdmerror::Error;
@@ -39,3 +40,291 @@ where
         None => panic!("unable to find attribute \"role\""),
     }
 }
+
+pub fn path<N: Node, G>(make_doc: G) -> Result<(), Error>
+where
+    G: Fn() -> N,
+{
+    let mut sd = make_doc();
+    let mut t = sd.new_element(QualifiedName::new(None, None, String::from("Test")))?;
+    sd.push(t.clone())?;
+    let mut l1_1 = sd.new_element(QualifiedName::new(None, None, String::from("Level1")))?;
+    t.push(l1_1.clone())?;
+    l1_1.push(sd.new_text(Rc::new(Value::from("one")))?)?;
+    let mut l1_2 = sd.new_element(QualifiedName::new(None, None, String::from("Level1")))?;
+    t.push(l1_2.clone())?;
+    let l2 = sd.new_element(QualifiedName::new(None, None, String::from("Level2")))?;
+    t.push(l2.clone())?;
+    let a = sd.new_attribute(
+        QualifiedName::new(None, None, String::from("id")),
+        Rc::new(Value::from("two")),
+    )?;
+    l1_2.add_attribute(a.clone())?;
+    let c = sd.new_comment(Rc::new(Value::from("comment")))?;
+    l1_2.push(c.clone())?;
+
+    assert_eq!(sd.path(), "/");
+    assert_eq!(t.path(), "/Test");
+    assert_eq!(l1_1.path(), "/Test/Level1[1]");
+    assert_eq!(l1_2.path(), "/Test/Level1[2]");
+    assert_eq!(l2.path(), "/Test/Level2");
+    assert_eq!(a.path(), "/Test/Level1[2]/@id");
+    assert_eq!(c.path(), "/Test/Level1[2]/comment()");
+    Ok(())
+}
+
+pub fn text_content_len<N: Node, G>(make_doc: G) -> Result<(), Error>
+where
+    G: Fn() -> N,
+{
+    let mut sd = make_doc();
+    let mut t = sd.new_element(QualifiedName::new(None, None, String::from("Test")))?;
+    sd.push(t.clone())?;
+    let mut l1 = sd.new_element(QualifiedName::new(None, None, String::from("Level1")))?;
+    t.push(l1.clone())?;
+    let tx = sd.new_text(Rc::new(Value::from("ünïcödé")))?;
+    l1.push(tx.clone())?;
+    let c = sd.new_comment(Rc::new(Value::from("not counted")))?;
+    l1.push(c.clone())?;
+    t.push(sd.new_text(Rc::new(Value::from(" and 42 €")))?)?;
+    let a = sd.new_attribute(
+        QualifiedName::new(None, None, String::from("id")),
+        Rc::new(Value::from("ßeta")),
+    )?;
+    t.add_attribute(a.clone())?;
+
+    for n in [&sd, &t, &l1, &tx, &c, &a] {
+        assert_eq!(n.text_content_len(), n.to_string().chars().count())
+    }
+    assert_eq!(t.text_content_len(), 16);
+    Ok(())
+}
+
+pub fn indent_comment<N: Node, G>(make_doc: G) -> Result<(), Error>
+where
+    G: Fn() -> N,
+{
+    let mut sd = make_doc();
+    sd.push(sd.new_comment(Rc::new(Value::from("before")))?)?;
+    let mut t = sd.new_element(QualifiedName::new(None, None, String::from("Test")))?;
+    sd.push(t.clone())?;
+    t.push(sd.new_element(QualifiedName::new(None, None, String::from("Level1")))?)?;
+    t.push(sd.new_comment(Rc::new(Value::from("between")))?)?;
+    t.push(sd.new_processing_instruction(
+        QualifiedName::new(None, None, String::from("pi")),
+        Rc::new(Value::from("also between")),
+    )?)?;
+    let mut l1 = sd.new_element(QualifiedName::new(None, None, String::from("Level1")))?;
+    t.push(l1.clone())?;
+    // Mixed content is not indented
+    l1.push(sd.new_text(Rc::new(Value::from("one")))?)?;
+    l1.push(sd.new_comment(Rc::new(Value::from("inline")))?)?;
+    l1.push(sd.new_text(Rc::new(Value::from("two")))?)?;
+
+    let mut od = OutputDefinition::new();
+    od.set_indent(true);
+    assert_eq!(
+        sd.to_xml_with_options(&od),
+        "<!--before-->
+<Test>
+  <Level1></Level1>
+  <!--between-->
+  <?pi also between?>
+  <Level1>one<!--inline-->two</Level1>
+</Test>"
+    );
+    Ok(())
+}
+
+pub fn uri_no_prefix<N: Node, G>(make_doc: G) -> Result<(), Error>
+where
+    G: Fn() -> N,
+{
+    let mut sd = make_doc();
+    let mut t = sd.new_element(QualifiedName::new(
+        Some(String::from("urn:test")),
+        None,
+        String::from("Test"),
+    ))?;
+    sd.push(t.clone())?;
+    // An attribute needs a prefix to be in a namespace
+    t.add_attribute(sd.new_attribute(
+        QualifiedName::new(Some(String::from("urn:test")), None, String::from("att")),
+        Rc::new(Value::from("value")),
+    )?)?;
+    // A stale prefix that is bound to a different namespace
+    let mut c = sd.new_element(QualifiedName::new(
+        Some(String::from("urn:other")),
+        Some(String::from("ns0")),
+        String::from("Child"),
+    ))?;
+    t.push(c.clone())?;
+    c.push(sd.new_element(QualifiedName::new(None, None, String::from("Plain")))?)?;
+    assert_eq!(
+        sd.to_xml(),
+        "<Test xmlns='urn:test' xmlns:ns0='urn:test' ns0:att='value'><ns0:Child xmlns:ns0='urn:other'><Plain xmlns=''></Plain></ns0:Child></Test>"
+    );
+    assert_eq!(t.name().eqname(), "Q{urn:test}Test");
+    Ok(())
+}
+
+pub fn preferred_prefix<N: Node, G>(make_doc: G) -> Result<(), Error>
+where
+    G: Fn() -> N,
+{
+    const SVG: &str = "http://www.w3.org/2000/svg";
+    // None of the names have a prefix, so the serializer has to choose them
+    let build = || -> Result<N, Error> {
+        let mut sd = make_doc();
+        let mut t = sd.new_element(QualifiedName::new(
+            Some(String::from("urn:a")),
+            None,
+            String::from("Test"),
+        ))?;
+        sd.push(t.clone())?;
+        t.add_attribute(sd.new_attribute(
+            QualifiedName::new(Some(String::from("urn:c")), None, String::from("y")),
+            Rc::new(Value::from("2")),
+        )?)?;
+        t.add_attribute(sd.new_attribute(
+            QualifiedName::new(Some(String::from("urn:b")), None, String::from("x")),
+            Rc::new(Value::from("1")),
+        )?)?;
+        t.push(sd.new_element(QualifiedName::new(
+            Some(String::from(SVG)),
+            None,
+            String::from("svg"),
+        ))?)?;
+        Ok(sd)
+    };
+    let od = OutputDefinition::new();
+    let mut preferred = OutputDefinition::new();
+    preferred.set_preferred_prefix(SVG, "svg");
+    // The preferred prefix for a namespace is not used if it is already bound to a different namespace
+    preferred.set_preferred_prefix("urn:c", "ns0");
+    // Invented prefixes are the same every time the tree is serialized
+    for _ in 0..10 {
+        let sd = build()?;
+        assert_eq!(
+            sd.to_xml_with_options(&od),
+            format!("<Test xmlns='urn:a' xmlns:ns0='urn:b' xmlns:ns1='urn:c' ns0:x='1' ns1:y='2'><svg xmlns='{}'></svg></Test>", SVG)
+        );
+        assert_eq!(
+            sd.to_xml_with_options(&preferred),
+            format!("<Test xmlns='urn:a' xmlns:ns0='urn:b' xmlns:ns1='urn:c' xmlns:svg='{}' ns0:x='1' ns1:y='2'><svg:svg></svg:svg></Test>", SVG)
+        );
+    }
+    Ok(())
+}
+
+pub fn deep_tree<N: Node, G>(make_doc: G) -> Result<(), Error>
+where
+    G: Fn() -> N,
+{
+    // A document with elements nested 10,000 deep
+    let sd = make_doc();
+    let mut parent = sd.clone();
+    for _ in 0..10000 {
+        let e = sd.new_element(QualifiedName::new(None, None, String::from("d")))?;
+        parent.push(e.clone())?;
+        parent = e;
+    }
+    parent.push(sd.new_text(Rc::new(Value::from("deep")))?)?;
+    // Serialization, string-value and copying do not recurse on the stack
+    let xml = sd.to_xml();
+    assert!(xml.starts_with("<d><d><d>"));
+    assert!(xml.contains("<d>deep</d>"));
+    assert!(xml.ends_with("</d></d></d>"));
+    assert_eq!(xml.len(), 10000 * 7 + 4);
+    assert_eq!(sd.to_string(), "deep");
+    assert_eq!(sd.descend_iter().count(), 10001);
+    let cp = sd.first_child().unwrap().deep_copy()?;
+    assert_eq!(cp.to_string(), "deep");
+    assert_eq!(cp.descend_iter().count(), 10000);
+    Ok(())
+}
+
+pub fn string_value_cache<N: Node, G>(make_doc: G) -> Result<(), Error>
+where
+    G: Fn() -> N,
+{
+    // The string-value, computed afresh from the descendant text nodes
+    fn fresh<N: Node>(n: &N) -> String {
+        n.descend_iter()
+            .filter(|c| c.node_type() == NodeType::Text)
+            .map(|c| c.to_string())
+            .collect()
+    }
+    let mut sd = make_doc();
+    let mut t = sd.new_element(QualifiedName::new(None, None, String::from("Test")))?;
+    sd.push(t.clone())?;
+    let mut a = sd.new_element(QualifiedName::new(None, None, String::from("a")))?;
+    t.push(a.clone())?;
+    a.push(sd.new_text(Rc::new(Value::from("one")))?)?;
+    assert_eq!(t.to_string(), "one");
+    assert_eq!(sd.to_string(), "one");
+
+    // Appending a child to a descendant
+    let mut b = sd.new_element(QualifiedName::new(None, None, String::from("b")))?;
+    a.push(b.clone())?;
+    b.push(sd.new_text(Rc::new(Value::from("two")))?)?;
+    assert_eq!(sd.to_string(), fresh(&sd));
+    assert_eq!(t.to_string(), "onetwo");
+    assert_eq!(a.to_string(), fresh(&a));
+
+    // Inserting before a descendant
+    let z = sd.new_text(Rc::new(Value::from("zero")))?;
+    a.first_child().unwrap().insert_before(z)?;
+    assert_eq!(t.to_string(), "zeroonetwo");
+    assert_eq!(sd.to_string(), fresh(&sd));
+
+    // Removing a descendant
+    b.pop()?;
+    assert_eq!(t.to_string(), "zeroone");
+    assert_eq!(sd.to_string(), fresh(&sd));
+    assert_eq!(b.to_string(), "two");
+
+    // Moving a subtree from one parent to another
+    let mut c = sd.new_element(QualifiedName::new(None, None, String::from("c")))?;
+    t.push(c.clone())?;
+    assert_eq!(c.to_string(), "");
+    c.push(a.clone())?;
+    assert_eq!(c.to_string(), "zeroone");
+    assert_eq!(t.to_string(), fresh(&t));
+    Ok(())
+}
+
+pub fn describe<N: Node, G>(make_doc: G) -> Result<(), Error>
+where
+    G: Fn() -> N,
+{
+    let mut sd = make_doc();
+    let mut t = sd.new_element(QualifiedName::new(None, None, String::from("Test")))?;
+    sd.push(t.clone())?;
+    let mut l1 = sd.new_element(QualifiedName::new(None, None, String::from("Level1")))?;
+    t.push(l1.clone())?;
+    l1.push(sd.new_text(Rc::new(Value::from("one")))?)?;
+    let mut l2 = sd.new_element(QualifiedName::new(None, None, String::from("Level1")))?;
+    t.push(l2.clone())?;
+    l2.push(sd.new_text(Rc::new(Value::from(
+        "two, which is a rather long value that will be truncated",
+    )))?)?;
+    let s: Sequence<N> = vec![
+        Item::Node(sd.clone()),
+        Item::Node(l1),
+        Item::Node(l2),
+        Item::Value(Rc::new(Value::from(42_i64))),
+        Item::Value(Rc::new(Value::from("a string"))),
+    ];
+    assert_eq!(
+        s.describe(),
+        r#"[0] Document / "onetwo, which is a rather long v"...
+[1] Element Level1 /Test/Level1[1] "one"
+[2] Element Level1 /Test/Level1[2] "two, which is a rather long valu"...
+[3] Integer "42"
+[4] String "a string""#
+    );
+    assert_eq!(s[4].describe_with_length(3), r#"String "a s"..."#);
+    assert_eq!(Sequence::<N>::new().describe(), "empty sequence");
+    Ok(())
+}
@@ -394,3 +394,105 @@ where
     );
     Ok(())
 }
+pub fn pattern_sel_attribute_parent<N: Node, G>(make_empty_doc: G) -> Result<(), Error>
+where
+    G: Fn() -> N,
+{
+    let p: Pattern<N> =
+        Pattern::try_from("@id[parent::item]").expect("unable to parse \"@id[parent::item]\"");
+
+    // Setup a source document
+    let mut sd = make_empty_doc();
+    let mut t = sd
+        .new_element(QualifiedName::new(None, None, String::from("Test")))
+        .expect("unable to create element");
+    sd.push(t.clone()).expect("unable to append child");
+    let item = sd
+        .new_element(QualifiedName::new(None, None, String::from("item")))
+        .expect("unable to create element");
+    t.push(item.clone()).expect("unable to append child");
+    let item_id = sd
+        .new_attribute(
+            QualifiedName::new(None, None, String::from("id")),
+            Rc::new(Value::from("first")),
+        )
+        .expect("unable to create attribute");
+    item.add_attribute(item_id.clone())
+        .expect("unable to add attribute");
+    let other = sd
+        .new_element(QualifiedName::new(None, None, String::from("other")))
+        .expect("unable to create element");
+    t.push(other.clone()).expect("unable to append child");
+    let other_id = sd
+        .new_attribute(
+            QualifiedName::new(None, None, String::from("id")),
+            Rc::new(Value::from("second")),
+        )
+        .expect("unable to create attribute");
+    other
+        .add_attribute(other_id.clone())
+        .expect("unable to add attribute");
+
+    let mut stctxt = StaticContextBuilder::new()
+        .message(|_| Ok(()))
+        .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .build();
+
+    assert!(item_id.parent().is_some_and(|n| n.is_same(&item)));
+    assert!(item_id
+        .ancestor_iter()
+        .nth(1)
+        .is_some_and(|n| n.is_same(&t)));
+    assert_eq!(
+        p.matches(&Context::new(), &mut stctxt, &Rc::new(Item::Node(item_id))),
+        true
+    );
+    assert_eq!(
+        p.matches(&Context::new(), &mut stctxt, &Rc::new(Item::Node(other_id))),
+        false
+    );
+    Ok(())
+}
+
+pub fn pattern_sel_position<N: Node, G>(make_empty_doc: G) -> Result<(), Error>
+where
+    G: Fn() -> N,
+{
+    let p: Pattern<N> = Pattern::try_from("item[2]").expect("unable to parse \"item[2]\"");
+    let l: Pattern<N> =
+        Pattern::try_from("item[last()]").expect("unable to parse \"item[last()]\"");
+
+    // Setup a source document. The position of an item is counted amongst its sibling items only.
+    let mut sd = make_empty_doc();
+    let mut t = sd
+        .new_element(QualifiedName::new(None, None, String::from("Test")))
+        .expect("unable to create element");
+    sd.push(t.clone()).expect("unable to append child");
+    let mut items = vec![];
+    for name in ["item", "other", "item", "item"] {
+        let e = sd
+            .new_element(QualifiedName::new(None, None, String::from(name)))
+            .expect("unable to create element");
+        t.push(e.clone()).expect("unable to append child");
+        items.push(e)
+    }
+
+    let mut stctxt = StaticContextBuilder::new()
+        .message(|_| Ok(()))
+        .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .build();
+
+    let matched: Vec<bool> = items
+        .iter()
+        .map(|n| p.matches(&Context::new(), &mut stctxt, &Item::Node(n.clone())))
+        .collect();
+    assert_eq!(matched, vec![false, false, true, false]);
+    let matched: Vec<bool> = items
+        .iter()
+        .map(|n| l.matches(&Context::new(), &mut stctxt, &Item::Node(n.clone())))
+        .collect();
+    assert_eq!(matched, vec![false, false, false, true]);
+    Ok(())
+}
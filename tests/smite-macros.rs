@@ -17,3 +17,35 @@ item_node_tests!(smite::make_empty_doc, smite::make_doc, smite::make_sd_raw);
 fn node_get_attr_node() {
     node::get_attr_node::<RNode, _>(smite::make_empty_doc).expect("test failed")
 }
+#[test]
+fn node_path() {
+    node::path::<RNode, _>(smite::make_empty_doc).expect("test failed")
+}
+#[test]
+fn node_text_content_len() {
+    node::text_content_len::<RNode, _>(smite::make_empty_doc).expect("test failed")
+}
+#[test]
+fn node_indent_comment() {
+    node::indent_comment::<RNode, _>(smite::make_empty_doc).expect("test failed")
+}
+#[test]
+fn node_uri_no_prefix() {
+    node::uri_no_prefix::<RNode, _>(smite::make_empty_doc).expect("test failed")
+}
+#[test]
+fn node_preferred_prefix() {
+    node::preferred_prefix::<RNode, _>(smite::make_empty_doc).expect("test failed")
+}
+#[test]
+fn node_deep_tree() {
+    node::deep_tree::<RNode, _>(smite::make_empty_doc).expect("test failed")
+}
+#[test]
+fn node_string_value_cache() {
+    node::string_value_cache::<RNode, _>(smite::make_empty_doc).expect("test failed")
+}
+#[test]
+fn node_describe() {
+    node::describe::<RNode, _>(smite::make_empty_doc).expect("test failed")
+}
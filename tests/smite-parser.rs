@@ -0,0 +1,156 @@
+use std::rc::Rc;
+use xrust::item::{Node, NodeType};
+use xrust::output::OutputDefinition;
+use xrust::parser::xml::{parse, parse_with_ns_options, parse_with_options};
+use xrust::parser::ParseOptions;
+use xrust::qname::QualifiedName;
+use xrust::trees::intmuttree::NodeBuilder;
+use xrust::trees::smite::Node as SmiteNode;
+use xrust::xdmerror::{Error, ErrorKind};
+
+const NESTED: &str = "<!DOCTYPE Test [
+<!ENTITY one 'one'>
+<!ENTITY two '&one;&one;'>
+]><Test>&two;</Test>";
+
+#[test]
+fn parse_default_options() {
+    let doc = parse_with_options(Rc::new(SmiteNode::new()), NESTED, ParseOptions::new())
+        .expect("unable to parse");
+    assert_eq!(doc.to_xml(), "<Test>oneone</Test>")
+}
+
+#[test]
+fn parse_entity_depth() {
+    parse_with_options(
+        Rc::new(SmiteNode::new()),
+        NESTED,
+        ParseOptions::new().entity_depth(2),
+    )
+    .expect_err("nested entities were expanded beyond the limit");
+}
+
+#[test]
+fn parse_resolver() {
+    let options = ParseOptions::new()
+        .docloc("file:///base/")
+        .resolver(|loc, uri| {
+            if loc.as_deref() == Some("file:///base/") && uri == "ext.xml" {
+                Ok(String::from("external"))
+            } else {
+                Err(Error::new(ErrorKind::Unknown, "unexpected resource"))
+            }
+        });
+    let (doc, _) = parse_with_ns_options(
+        Rc::new(SmiteNode::new()),
+        "<!DOCTYPE Test [<!ENTITY ext SYSTEM 'ext.xml'>]><Test>&ext;</Test>",
+        options,
+    )
+    .expect("unable to parse");
+    assert_eq!(doc.to_xml(), "<Test>external</Test>")
+}
+
+#[test]
+fn parse_attribute_normalization() {
+    let doc = parse_with_options(
+        Rc::new(SmiteNode::new()),
+        "<Test lf='one\ntwo' crlf='one\r\ntwo' tab='one\ttwo' ref='one&#10;two&#9;three'/>",
+        ParseOptions::new(),
+    )
+    .expect("unable to parse");
+    let t = doc.child_iter().next().expect("no document element");
+    let att = |n: &str| {
+        t.get_attribute(&QualifiedName::new(None, None, n))
+            .to_string()
+    };
+    // Literal white space is normalized to a single space
+    assert_eq!(att("lf"), "one two");
+    assert_eq!(att("crlf"), "one two");
+    assert_eq!(att("tab"), "one two");
+    // White space from a character reference is preserved
+    assert_eq!(att("ref"), "one\ntwo\tthree");
+}
+
+#[test]
+fn parse_strip_comments() {
+    let src =
+        "<?start?><!--before--><Test>one<!--c-->two<?pi x?>three<a><!--c--></a></Test><!--after-->";
+    let kept = parse_with_options(Rc::new(SmiteNode::new()), src, ParseOptions::new())
+        .expect("unable to parse");
+    assert_eq!(
+        kept.to_xml(),
+        "<?start ?><!--before--><Test>one<!--c-->two<?pi x?>three<a><!--c--></a></Test><!--after-->"
+    );
+    let doc = parse_with_options(
+        Rc::new(SmiteNode::new()),
+        src,
+        ParseOptions::new().strip_comments(true),
+    )
+    .expect("unable to parse");
+    assert_eq!(
+        doc.to_xml(),
+        "<?start ?><Test>onetwo<?pi x?>three<a></a></Test>"
+    );
+    let doc = parse_with_options(
+        Rc::new(SmiteNode::new()),
+        src,
+        ParseOptions::new()
+            .strip_comments(true)
+            .strip_processing_instructions(true),
+    )
+    .expect("unable to parse");
+    assert_eq!(doc.to_xml(), "<Test>onetwothree<a></a></Test>");
+    // Text either side of a comment is a single text node
+    let t = doc.child_iter().next().expect("no document element");
+    assert_eq!(t.child_iter().count(), 2);
+}
+
+#[test]
+fn parse_element_location() {
+    let src = "<Test>\n  <a/><b>x</b>\n\t<ü/><c/></Test>";
+    // Locations are only recorded when asked for
+    let doc = parse_with_options(Rc::new(SmiteNode::new()), src, ParseOptions::new())
+        .expect("unable to parse");
+    let t = doc.child_iter().next().expect("no document element");
+    assert_eq!(t.location(), None);
+    let doc = parse_with_options(
+        Rc::new(SmiteNode::new()),
+        src,
+        ParseOptions::new().locations(true),
+    )
+    .expect("unable to parse");
+    let t = doc.child_iter().next().expect("no document element");
+    assert_eq!(t.location(), Some((1, 1)));
+    let locs: Vec<Option<(usize, usize)>> = t
+        .child_iter()
+        .filter(|c| c.is_element())
+        .map(|c| c.location())
+        .collect();
+    assert_eq!(
+        locs,
+        vec![Some((2, 3)), Some((2, 7)), Some((3, 2)), Some((3, 6))]
+    );
+    // Only elements have a location
+    assert_eq!(t.first_child().and_then(|c| c.location()), None);
+}
+
+#[test]
+fn parse_serialize_both_trees() {
+    // Each element has at most one attribute, since the order of attributes is not defined
+    let src = "<Test xmlns='urn:default' xmlns:eg='urn:eg' xmlns:a='urn:a'><!-- a comment --><?pi some data?><eg:Level1 eg:id='one'>text</eg:Level1><Level2 attr='two'><Empty/></Level2>tail</Test>";
+    let smite = Rc::new(SmiteNode::new());
+    parse(smite.clone(), src, None).expect("unable to parse into smite tree");
+    let intmut = NodeBuilder::new(NodeType::Document).build();
+    parse(intmut.clone(), src, None).expect("unable to parse into intmuttree tree");
+    assert!(smite
+        .to_xml()
+        .starts_with("<Test xmlns:a='urn:a' xmlns='urn:default' xmlns:eg='urn:eg'><!--"));
+    assert_eq!(intmut.to_xml(), smite.to_xml());
+    let mut od = OutputDefinition::new();
+    od.set_indent(true);
+    assert_eq!(
+        intmut.to_xml_with_options(&od),
+        smite.to_xml_with_options(&od)
+    );
+    assert_eq!(intmut.to_json(), smite.to_json());
+}
@@ -47,3 +47,12 @@ fn pattern_sel_text_kind_1_pos() {
     patterngeneric::pattern_sel_text_kind_1_pos::<RNode, _>(smite::make_empty_doc)
         .expect("test failed")
 }
+#[test]
+fn pattern_sel_attribute_parent() {
+    patterngeneric::pattern_sel_attribute_parent::<RNode, _>(smite::make_empty_doc)
+        .expect("test failed")
+}
+#[test]
+fn pattern_sel_position() {
+    patterngeneric::pattern_sel_position::<RNode, _>(smite::make_empty_doc).expect("test failed")
+}
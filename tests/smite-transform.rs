@@ -11,6 +11,11 @@ fn tr_empty() {
         .expect("test failed")
 }
 #[test]
+fn sequence_empty() {
+    transformgeneric::generic_sequence_empty::<RNode, _, _>(smite::make_empty_doc, smite::make_sd)
+        .expect("test failed")
+}
+#[test]
 fn tr_singleton_literal() {
     transformgeneric::generic_tr_singleton_literal::<RNode, _, _>(
         smite::make_empty_doc,
@@ -2,6 +2,7 @@
 
 use xrust::trees::smite::RNode;
 
+mod counted;
 mod smite;
 mod xpathgeneric;
 
@@ -40,6 +41,14 @@ fn xpath_step_wild_1() {
         .expect("test failed")
 }
 #[test]
+fn xpath_step_attribute_parent() {
+    xpathgeneric::generic_step_attribute_parent::<RNode, _, _>(
+        smite::make_empty_doc,
+        smite::make_sd,
+    )
+    .expect("test failed")
+}
+#[test]
 fn xpath_step_parent_1() {
     xpathgeneric::generic_step_parent_1::<RNode, _, _>(smite::make_empty_doc, smite::make_sd)
         .expect("test failed")
@@ -70,6 +79,14 @@ fn xpath_path_1_neg() {
         .expect("test failed")
 }
 #[test]
+fn xpath_syntax_error_position() {
+    xpathgeneric::generic_syntax_error_position::<RNode, _, _>(
+        smite::make_empty_doc,
+        smite::make_sd,
+    )
+    .expect("test failed")
+}
+#[test]
 fn xpath_path_3() {
     xpathgeneric::generic_path_3::<RNode, _, _>(smite::make_empty_doc, smite::make_sd)
         .expect("test failed")
@@ -85,11 +102,34 @@ fn xpath_generate_id() {
         .expect("test failed")
 }
 #[test]
+fn xpath_fncall_root() {
+    xpathgeneric::generic_fncall_root::<RNode, _, _>(smite::make_empty_doc, smite::make_sd)
+        .expect("test failed")
+}
+#[test]
 fn xpath_union() {
     xpathgeneric::generic_union::<RNode, _, _>(smite::make_empty_doc, smite::make_sd)
         .expect("test failed")
 }
 #[test]
+fn xpath_path_document_order() {
+    xpathgeneric::generic_path_document_order::<RNode, _, _>(smite::make_empty_doc, smite::make_sd)
+        .expect("test failed")
+}
+#[test]
+fn xpath_self_axis_kinds() {
+    xpathgeneric::generic_self_axis_kinds::<RNode, _, _>(smite::make_empty_doc, smite::make_sd)
+        .expect("test failed")
+}
+#[test]
+fn xpath_descendant_or_self_axis_kinds() {
+    xpathgeneric::generic_descendant_or_self_axis_kinds::<RNode, _, _>(
+        smite::make_empty_doc,
+        smite::make_sd,
+    )
+    .expect("test failed")
+}
+#[test]
 fn xpath_intersectexcept() {
     xpathgeneric::generic_intersectexcept::<RNode, _, _>(smite::make_empty_doc, smite::make_sd)
         .expect("test failed")
@@ -115,6 +155,11 @@ fn xpath_cast() {
         .expect("test failed")
 }
 #[test]
+fn xpath_parse_ietf_date() {
+    xpathgeneric::generic_parse_ietf_date::<RNode, _, _>(smite::make_empty_doc, smite::make_sd)
+        .expect("test failed")
+}
+#[test]
 fn xpath_arrow() {
     xpathgeneric::generic_arrow::<RNode, _, _>(smite::make_empty_doc, smite::make_sd)
         .expect("test failed")
@@ -185,6 +230,29 @@ fn xpath_context_item() {
         .expect("test failed")
 }
 #[test]
+fn xpath_nested_sequence() {
+    xpathgeneric::generic_nested_sequence::<RNode, _, _>(smite::make_empty_doc, smite::make_sd)
+        .expect("test failed")
+}
+#[test]
+fn xpath_empty_sequence_compare() {
+    xpathgeneric::generic_empty_sequence_compare::<RNode, _, _>(
+        smite::make_empty_doc,
+        smite::make_sd,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xpath_sequence_argument() {
+    xpathgeneric::generic_sequence_argument::<RNode, _, _>(smite::make_empty_doc, smite::make_sd)
+        .expect("test failed")
+}
+#[test]
+fn xpath_node_sequence() {
+    xpathgeneric::generic_node_sequence::<RNode, _, _>(smite::make_empty_doc, smite::make_sd)
+        .expect("test failed")
+}
+#[test]
 fn xpath_parens_singleton() {
     xpathgeneric::generic_parens_singleton::<RNode, _, _>(smite::make_empty_doc, smite::make_sd)
         .expect("test failed")
@@ -331,6 +399,70 @@ fn xpath_fncall_normalizespace() {
     .expect("test failed")
 }
 #[test]
+fn xpath_fncall_containstoken() {
+    xpathgeneric::generic_fncall_containstoken::<RNode, _, _>(smite::make_empty_doc, smite::make_sd)
+        .expect("test failed")
+}
+#[test]
+fn xpath_fncall_tokenize_ws() {
+    xpathgeneric::generic_fncall_tokenize_ws::<RNode, _, _>(smite::make_empty_doc, smite::make_sd)
+        .expect("test failed")
+}
+#[test]
+fn xpath_fncall_tokenize_spaces() {
+    xpathgeneric::generic_fncall_tokenize_spaces::<RNode, _, _>(
+        smite::make_empty_doc,
+        smite::make_sd,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xpath_fncall_tokenize_errors() {
+    xpathgeneric::generic_fncall_tokenize_errors::<RNode, _, _>(
+        smite::make_empty_doc,
+        smite::make_sd,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xpath_fncall_string_join() {
+    xpathgeneric::generic_fncall_string_join::<RNode, _, _>(smite::make_empty_doc, smite::make_sd)
+        .expect("test failed")
+}
+#[test]
+fn xpath_fncall_distinct_values() {
+    xpathgeneric::generic_fncall_distinct_values::<RNode, _, _>(
+        smite::make_empty_doc,
+        smite::make_sd,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xpath_fncall_index_of() {
+    xpathgeneric::generic_fncall_index_of::<RNode, _, _>(smite::make_empty_doc, smite::make_sd)
+        .expect("test failed")
+}
+#[test]
+fn xpath_fncall_insert_before() {
+    xpathgeneric::generic_fncall_insert_before::<RNode, _, _>(smite::make_empty_doc, smite::make_sd)
+        .expect("test failed")
+}
+#[test]
+fn xpath_fncall_remove() {
+    xpathgeneric::generic_fncall_remove::<RNode, _, _>(smite::make_empty_doc, smite::make_sd)
+        .expect("test failed")
+}
+#[test]
+fn xpath_fncall_max_min() {
+    xpathgeneric::generic_fncall_max_min::<RNode, _, _>(smite::make_empty_doc, smite::make_sd)
+        .expect("test failed")
+}
+#[test]
+fn xpath_fncall_path() {
+    xpathgeneric::generic_fncall_path::<RNode, _, _>(smite::make_empty_doc, smite::make_sd)
+        .expect("test failed")
+}
+#[test]
 fn xpath_fncall_translate() {
     xpathgeneric::generic_fncall_translate::<RNode, _, _>(smite::make_empty_doc, smite::make_sd)
         .expect("test failed")
@@ -381,6 +513,14 @@ fn xpath_fncall_sum() {
         .expect("test failed")
 }
 #[test]
+fn xpath_fncall_sum_non_numeric() {
+    xpathgeneric::generic_fncall_sum_non_numeric::<RNode, _, _>(
+        smite::make_empty_doc,
+        smite::make_sd,
+    )
+    .expect("test failed")
+}
+#[test]
 fn xpath_fncall_floor() {
     xpathgeneric::generic_fncall_floor::<RNode, _, _>(smite::make_empty_doc, smite::make_sd)
         .expect("test failed")
@@ -477,3 +617,16 @@ fn xpath_document_1() {
     )
     .expect("test failed")
 }
+#[test]
+fn xpath_quantified() {
+    xpathgeneric::generic_quantified::<RNode, _, _>(smite::make_empty_doc, smite::make_sd)
+        .expect("test failed")
+}
+#[test]
+fn xpath_early_exit() {
+    xpathgeneric::generic_early_exit::<counted::CNode, _, _>(
+        counted::make_empty_doc,
+        counted::visited,
+    )
+    .expect("test failed")
+}
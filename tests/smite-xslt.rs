@@ -1,3 +1,4 @@
+mod counted;
 mod smite;
 mod xsltgeneric;
 
@@ -40,6 +41,24 @@ fn xslt_value_of_2() {
     .expect("test failed")
 }
 #[test]
+fn xslt_text_disable_output_escaping() {
+    xsltgeneric::generic_text_disable_output_escaping(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_contains_token_class() {
+    xsltgeneric::generic_contains_token_class(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
 fn xslt_literal_element() {
     xsltgeneric::generic_literal_element(
         smite::make_from_str,
@@ -76,6 +95,33 @@ fn xslt_apply_templates_2() {
     .expect("test failed")
 }
 #[test]
+fn xslt_apply_templates_attributes() {
+    xsltgeneric::generic_apply_templates_attributes(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_apply_templates_position() {
+    xsltgeneric::generic_apply_templates_position(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_apply_templates_same_shape() {
+    xsltgeneric::generic_apply_templates_same_shape(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
 fn xslt_apply_templates_mode() {
     xsltgeneric::generic_apply_templates_mode(
         smite::make_from_str,
@@ -85,6 +131,15 @@ fn xslt_apply_templates_mode() {
     .expect("test failed")
 }
 #[test]
+fn xslt_generate_id_modes() {
+    xsltgeneric::generic_generate_id_modes(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
 fn xslt_apply_templates_sort() {
     xsltgeneric::generic_apply_templates_sort(
         smite::make_from_str,
@@ -94,6 +149,33 @@ fn xslt_apply_templates_sort() {
     .expect("test failed")
 }
 #[test]
+fn xslt_sort_order_avt() {
+    xsltgeneric::generic_sort_order_avt(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_perform_sort() {
+    xsltgeneric::generic_perform_sort(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_perform_sort_invalid() {
+    xsltgeneric::generic_perform_sort_invalid(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
 fn xslt_comment() {
     xsltgeneric::generic_comment(
         smite::make_from_str,
@@ -103,6 +185,24 @@ fn xslt_comment() {
     .expect("test failed")
 }
 #[test]
+fn xslt_comment_hyphens() {
+    xsltgeneric::generic_comment_hyphens(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_simple_content() {
+    xsltgeneric::generic_simple_content(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
 fn xslt_pi() {
     xsltgeneric::generic_pi(
         smite::make_from_str,
@@ -112,6 +212,15 @@ fn xslt_pi() {
     .expect("test failed")
 }
 #[test]
+fn xslt_pi_avt() {
+    xsltgeneric::generic_pi_avt(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
 fn xslt_message_1() {
     xsltgeneric::generic_message_1(
         smite::make_from_str,
@@ -130,6 +239,123 @@ fn xslt_message_term() {
     .expect("test failed")
 }
 #[test]
+fn xslt_message_term_earlier() {
+    xsltgeneric::generic_message_term_earlier(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_message_term_avt() {
+    xsltgeneric::generic_message_term_avt(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_message_element() {
+    xsltgeneric::generic_message_element(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_trace() {
+    xsltgeneric::generic_trace(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_recoverable_errors() {
+    xsltgeneric::generic_recoverable_errors(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_copy_namespaces() {
+    xsltgeneric::generic_copy_namespaces(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_inherit_namespaces() {
+    xsltgeneric::generic_inherit_namespaces(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_exclude_result_prefixes() {
+    xsltgeneric::generic_exclude_result_prefixes(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_computed_names() {
+    xsltgeneric::generic_computed_names(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_value_of_version() {
+    xsltgeneric::generic_value_of_version(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_value_of_first_node() {
+    xsltgeneric::generic_value_of_first_node(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_iterate_break() {
+    xsltgeneric::generic_iterate_break(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_iterate_completion() {
+    xsltgeneric::generic_iterate_completion(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
 fn xslt_issue_58() {
     xsltgeneric::generic_issue_58(
         smite::make_from_str,
@@ -139,6 +365,33 @@ fn xslt_issue_58() {
     .expect("test failed")
 }
 #[test]
+fn xslt_preserve_prefixes() {
+    xsltgeneric::generic_preserve_prefixes(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_output_definition_builder() {
+    xsltgeneric::generic_output_definition_builder(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_nested_namespaces() {
+    xsltgeneric::generic_nested_namespaces(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
 fn xslt_callable_named_1() {
     xsltgeneric::generic_callable_named_1(
         smite::make_from_str,
@@ -148,6 +401,42 @@ fn xslt_callable_named_1() {
     .expect("test failed")
 }
 #[test]
+fn xslt_param_select_default() {
+    xsltgeneric::generic_param_select_default(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_tunnel_param_default() {
+    xsltgeneric::generic_tunnel_param_default(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_param_required() {
+    xsltgeneric::generic_param_required(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_param_default_invalid() {
+    xsltgeneric::generic_param_default_invalid(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
 fn xslt_callable_posn_1() {
     xsltgeneric::generic_callable_posn_1(
         smite::make_from_str,
@@ -167,8 +456,8 @@ fn xslt_include() {
     .expect("test failed")
 }
 #[test]
-fn xslt_current() {
-    xsltgeneric::generic_current(
+fn xslt_include_cycle() {
+    xsltgeneric::generic_include_cycle(
         smite::make_from_str,
         smite::make_from_str_with_ns,
         smite::make_sd_cooked,
@@ -176,8 +465,8 @@ fn xslt_current() {
     .expect("test failed")
 }
 #[test]
-fn xslt_key_1() {
-    xsltgeneric::generic_key_1(
+fn xslt_include_diamond() {
+    xsltgeneric::generic_include_diamond(
         smite::make_from_str,
         smite::make_from_str_with_ns,
         smite::make_sd_cooked,
@@ -185,8 +474,8 @@ fn xslt_key_1() {
     .expect("test failed")
 }
 #[test]
-fn xslt_document_1() {
-    xsltgeneric::generic_document_1(
+fn xslt_result_document() {
+    xsltgeneric::generic_result_document(
         smite::make_from_str,
         smite::make_from_str_with_ns,
         smite::make_sd_cooked,
@@ -194,8 +483,8 @@ fn xslt_document_1() {
     .expect("test failed")
 }
 #[test]
-fn xslt_number_1() {
-    xsltgeneric::generic_number_1(
+fn xslt_result_document_unknown_format() {
+    xsltgeneric::generic_result_document_unknown_format(
         smite::make_from_str,
         smite::make_from_str_with_ns,
         smite::make_sd_cooked,
@@ -203,8 +492,8 @@ fn xslt_number_1() {
     .expect("test failed")
 }
 #[test]
-fn xslt_attr_set_1() {
-    xsltgeneric::attr_set_1(
+fn xslt_document_factory() {
+    xsltgeneric::generic_document_factory(
         smite::make_from_str,
         smite::make_from_str_with_ns,
         smite::make_sd_cooked,
@@ -212,8 +501,8 @@ fn xslt_attr_set_1() {
     .expect("test failed")
 }
 #[test]
-fn xslt_attr_set_2() {
-    xsltgeneric::attr_set_2(
+fn xslt_from_tree() {
+    xsltgeneric::generic_from_tree(
         smite::make_from_str,
         smite::make_from_str_with_ns,
         smite::make_sd_cooked,
@@ -221,8 +510,451 @@ fn xslt_attr_set_2() {
     .expect("test failed")
 }
 #[test]
-fn xslt_attr_set_3() {
-    xsltgeneric::attr_set_3(
+fn xslt_call_function() {
+    xsltgeneric::generic_call_function(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_function_as() {
+    xsltgeneric::generic_function_as(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_extension_function() {
+    xsltgeneric::generic_extension_function(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_extension_element() {
+    xsltgeneric::generic_extension_element(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_global_variables() {
+    xsltgeneric::generic_global_variables(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_global_circular() {
+    xsltgeneric::generic_global_circular(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_global_duplicate() {
+    xsltgeneric::generic_global_duplicate(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_template_match_or_name() {
+    xsltgeneric::generic_template_match_or_name(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_unknown_attributes() {
+    xsltgeneric::generic_unknown_attributes(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_global_context_item() {
+    xsltgeneric::generic_global_context_item(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_current() {
+    xsltgeneric::generic_current(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_key_1() {
+    xsltgeneric::generic_key_1(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_strip_space_key() {
+    xsltgeneric::generic_strip_space_key(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_document_1() {
+    xsltgeneric::generic_document_1(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_document_fragment() {
+    xsltgeneric::generic_document_fragment(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_source_document() {
+    xsltgeneric::generic_source_document(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_source_document_burst() {
+    xsltgeneric::generic_source_document_burst(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_parse_xml() {
+    xsltgeneric::generic_parse_xml(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_serialize() {
+    xsltgeneric::generic_serialize(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_serialize_adaptive_json() {
+    xsltgeneric::generic_serialize_adaptive_json(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_transform_function() {
+    xsltgeneric::generic_transform_function(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_number_1() {
+    xsltgeneric::generic_number_1(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_attr_set_1() {
+    xsltgeneric::attr_set_1(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_attr_set_2() {
+    xsltgeneric::attr_set_2(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_attr_set_3() {
+    xsltgeneric::attr_set_3(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_unresolved_prefix() {
+    xsltgeneric::generic_unresolved_prefix(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_dump_static_info() {
+    xsltgeneric::generic_dump_static_info(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_indented_stylesheet() {
+    xsltgeneric::generic_indented_stylesheet(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_default_collation() {
+    xsltgeneric::generic_default_collation(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_default_collation_unknown() {
+    xsltgeneric::generic_default_collation_unknown(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_deep_tree() {
+    xsltgeneric::generic_deep_tree(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_normalization_form() {
+    xsltgeneric::generic_normalization_form(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_number_multiple() {
+    xsltgeneric::generic_number_multiple(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_number_grouping() {
+    xsltgeneric::generic_number_grouping(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_number_ordinal() {
+    xsltgeneric::generic_number_ordinal(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_byte_order_mark() {
+    xsltgeneric::generic_byte_order_mark(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_default_mode() {
+    xsltgeneric::generic_default_mode(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_default_validation_invalid() {
+    xsltgeneric::generic_default_validation_invalid(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_stylesheet_comments() {
+    xsltgeneric::generic_stylesheet_comments(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+        smite::make_from_str_stripped,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_copy_of_direct() {
+    xsltgeneric::generic_copy_of_direct(
+        counted::make_from_str,
+        counted::make_from_str_with_ns,
+        counted::make_doc,
+        counted::copied,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_complex_content() {
+    xsltgeneric::generic_complex_content(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_choose_short_circuit() {
+    xsltgeneric::generic_choose_short_circuit(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_group_by_collation() {
+    xsltgeneric::generic_group_by_collation(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_message_location() {
+    xsltgeneric::generic_message_location(
+        smite::make_from_str,
+        smite::make_from_str_with_locations,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_syntax_error_location() {
+    xsltgeneric::generic_syntax_error_location(
+        smite::make_from_str,
+        smite::make_from_str_with_locations,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_secure_processing() {
+    xsltgeneric::generic_secure_processing(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_collection() {
+    xsltgeneric::generic_collection(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_uri_collection() {
+    xsltgeneric::generic_uri_collection(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_nilled() {
+    xsltgeneric::generic_nilled(
+        smite::make_from_str,
+        smite::make_from_str_with_ns,
+        smite::make_sd_cooked,
+    )
+    .expect("test failed")
+}
+#[test]
+fn xslt_copy_of_attributes() {
+    xsltgeneric::generic_copy_of_attributes(
         smite::make_from_str,
         smite::make_from_str_with_ns,
         smite::make_sd_cooked,
@@ -4,7 +4,8 @@ use std::collections::HashMap;
 use std::rc::Rc;
 
 use xrust::item::{Item, Node};
-use xrust::parser::xml::{parse as xmlparse, parse_with_ns};
+use xrust::parser::xml::{parse as xmlparse, parse_with_ns, parse_with_ns_options};
+use xrust::parser::ParseOptions;
 use xrust::qname::QualifiedName;
 use xrust::trees::smite::{Node as SmiteNode, RNode};
 use xrust::value::Value;
@@ -54,9 +55,31 @@ pub fn make_from_str(s: &str) -> Result<RNode, Error> {
     Ok(doc)
 }
 
+/// Parse a document, leaving out comments and processing instructions.
+#[allow(dead_code)]
+pub fn make_from_str_stripped(s: &str) -> Result<(RNode, Vec<HashMap<String, String>>), Error> {
+    let doc = Rc::new(SmiteNode::new());
+    parse_with_ns_options(
+        doc,
+        s,
+        ParseOptions::new()
+            .strip_comments(true)
+            .strip_processing_instructions(true),
+    )
+}
+
 #[allow(dead_code)]
 pub fn make_from_str_with_ns(s: &str) -> Result<(RNode, Vec<HashMap<String, String>>), Error> {
     let doc = Rc::new(SmiteNode::new());
     let r = parse_with_ns(doc.clone(), s, None)?;
     Ok(r)
 }
+
+/// Parse a stylesheet, recording where each element occurs.
+#[allow(dead_code)]
+pub fn make_from_str_with_locations(
+    s: &str,
+) -> Result<(RNode, Vec<HashMap<String, String>>), Error> {
+    let doc = Rc::new(SmiteNode::new());
+    parse_with_ns_options(doc, s, ParseOptions::new().locations(true))
+}
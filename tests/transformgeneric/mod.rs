@@ -2,7 +2,7 @@
 
 use chrono::{Datelike, Local, Timelike};
 use std::rc::Rc;
-use xrust::item::{Item, Node, SequenceTrait};
+use xrust::item::{Item, Node, Sequence, SequenceTrait};
 use xrust::pattern::Pattern;
 use xrust::qname::QualifiedName;
 use xrust::transform::callable::{ActualParameters, Callable, FormalParameters};
@@ -33,6 +33,19 @@ where
     assert_eq!(seq.len(), 0);
     Ok(())
 }
+pub fn generic_sequence_empty<N: Node, G, H>(_: G, _: H) -> Result<(), Error>
+where
+    G: Fn() -> N,
+    H: Fn() -> Item<N>,
+{
+    let seq: Sequence<N> = Sequence::empty();
+    assert!(seq.is_empty());
+    // The effective boolean value of an empty sequence is false, and its string value is the empty string
+    assert!(!seq.to_bool());
+    assert!(!seq.effective_boolean_value()?);
+    assert_eq!(seq.to_string(), "");
+    Ok(())
+}
 pub fn generic_tr_singleton_literal<N: Node, G, H>(_: G, _: H) -> Result<(), Error>
 where
     G: Fn() -> N,
@@ -60,6 +73,7 @@ where
         Box::new(Transform::Literal(Item::<N>::Value(Rc::new(Value::from(
             "content",
         ))))),
+        true,
     );
     let mydoc = make_empty_doc();
     let mut stctxt = StaticContextBuilder::new()
@@ -88,7 +102,9 @@ where
             Box::new(Transform::Literal(Item::<N>::Value(Rc::new(Value::from(
                 "content",
             ))))),
+            true,
         )),
+        true,
     );
     let mydoc = make_empty_doc();
     let mut stctxt = StaticContextBuilder::new()
@@ -114,6 +130,9 @@ where
         Box::new(Transform::Literal(Item::<N>::Value(Rc::new(Value::from(
             "content",
         ))))),
+        true,
+        None,
+        vec![],
     );
     let mydoc = make_empty_doc();
     let mut stctxt = StaticContextBuilder::new()
@@ -189,6 +208,7 @@ where
             ),
             Transform::Literal(Item::<N>::Value(Rc::new(Value::from("content")))),
         ])),
+        true,
     );
     let mydoc = make_empty_doc();
     let mut stctxt = StaticContextBuilder::new()
@@ -215,6 +235,7 @@ where
             ))))),
             Transform::Literal(Item::<N>::Value(Rc::new(Value::from("content")))),
         ])),
+        true,
     );
     let mydoc = make_empty_doc();
     let mut stctxt = StaticContextBuilder::new()
@@ -246,6 +267,7 @@ where
             ),
             Transform::Literal(Item::<N>::Value(Rc::new(Value::from("content")))),
         ])),
+        true,
     );
     let mydoc = make_empty_doc();
     let mut stctxt = StaticContextBuilder::new()
@@ -339,9 +361,11 @@ where
                 None,
                 Box::new(Transform::Empty),
                 Box::new(Transform::Empty),
+                None,
             ),
             Transform::Literal(Item::<N>::Value(Rc::new(Value::from("content")))),
         ])),
+        true,
     );
     let mydoc = make_empty_doc();
     let ctxt = ContextBuilder::new().result_document(mydoc).build();
@@ -375,6 +399,7 @@ where
                 None,
                 Box::new(Transform::Empty),
                 Box::new(Transform::Empty),
+                None,
             ),
             Transform::Literal(Item::<N>::Value(Rc::new(Value::from("content")))),
             Transform::Message(
@@ -384,8 +409,10 @@ where
                 None,
                 Box::new(Transform::Empty),
                 Box::new(Transform::Empty),
+                None,
             ),
         ])),
+        true,
     );
     let mydoc = make_empty_doc();
     let ctxt = ContextBuilder::new().result_document(mydoc).build();
@@ -423,9 +450,11 @@ where
                 Box::new(Transform::Literal(Item::<N>::Value(Rc::new(Value::from(
                     "yes",
                 ))))),
+                None,
             ),
             Transform::Literal(Item::<N>::Value(Rc::new(Value::from("content")))),
         ])),
+        true,
     );
     let mydoc = make_empty_doc();
     let ctxt = ContextBuilder::new().result_document(mydoc).build();
@@ -491,6 +520,7 @@ where
             "this is the original",
         ))))),
         Box::new(Transform::<N>::Empty),
+        true,
     );
     let mut stctxt = StaticContextBuilder::new()
         .message(|_| Ok(()))
@@ -513,6 +543,7 @@ where
     let x = Transform::Copy(
         Box::new(Transform::ContextItem),
         Box::new(Transform::<N>::Empty),
+        true,
     );
     let mut stctxt = StaticContextBuilder::new()
         .message(|_| Ok(()))
@@ -553,6 +584,7 @@ where
         Box::new(Transform::Literal(Item::<N>::Value(Rc::new(Value::from(
             "this is the copy",
         ))))),
+        true,
     );
 
     let mydoc = make_empty_doc();
@@ -631,7 +663,7 @@ where
     )
     .expect("unable to add text node");
 
-    let x = Transform::DeepCopy(Box::new(Transform::ContextItem));
+    let x = Transform::DeepCopy(Box::new(Transform::ContextItem), true);
 
     let mut stctxt = StaticContextBuilder::new()
         .message(|_| Ok(()))
@@ -2668,13 +2700,16 @@ where
 {
     // xsl:for-each-group select="1 to 50" group-by=". mod 10" body == xsl:text "group current-grouping-key size count(current-group)"
     let x = Transform::ForEach(
-        Some(Grouping::By(vec![Transform::Arithmetic(vec![
-            ArithmeticOperand::new(ArithmeticOperator::Noop, Transform::ContextItem),
-            ArithmeticOperand::new(
-                ArithmeticOperator::Modulo,
-                Transform::Literal(Item::<N>::Value(Rc::new(Value::from(10)))),
-            ),
-        ])])),
+        Some(Grouping::By(
+            vec![Transform::Arithmetic(vec![
+                ArithmeticOperand::new(ArithmeticOperator::Noop, Transform::ContextItem),
+                ArithmeticOperand::new(
+                    ArithmeticOperator::Modulo,
+                    Transform::Literal(Item::<N>::Value(Rc::new(Value::from(10)))),
+                ),
+            ])],
+            None,
+        )),
         Box::new(Transform::Range(
             Box::new(Transform::Literal(Item::<N>::Value(Rc::new(Value::from(
                 1,
@@ -2686,11 +2721,22 @@ where
         Box::new(Transform::LiteralElement(
             QualifiedName::new(None, None, String::from("group")),
             Box::new(Transform::SequenceItems(vec![
-                Transform::Literal(Item::Value(Rc::new(Value::from("key ")))),
+                Transform::LiteralText(
+                    Box::new(Transform::Literal(Item::Value(Rc::new(Value::from(
+                        "key ",
+                    ))))),
+                    true,
+                ),
                 Transform::CurrentGroupingKey,
-                Transform::Literal(Item::Value(Rc::new(Value::from(" #members ")))),
+                Transform::LiteralText(
+                    Box::new(Transform::Literal(Item::Value(Rc::new(Value::from(
+                        " #members ",
+                    ))))),
+                    true,
+                ),
                 Transform::Count(Box::new(Transform::CurrentGroup)),
             ])),
+            true,
         )),
         vec![],
     );
@@ -2720,13 +2766,16 @@ where
     // body == xsl:text "group current-grouping-key size count(current-group)"
     // sort == current-grouping-key()
     let x = Transform::ForEach(
-        Some(Grouping::By(vec![Transform::Arithmetic(vec![
-            ArithmeticOperand::new(ArithmeticOperator::Noop, Transform::ContextItem),
-            ArithmeticOperand::new(
-                ArithmeticOperator::Modulo,
-                Transform::Literal(Item::<N>::Value(Rc::new(Value::from(10)))),
-            ),
-        ])])),
+        Some(Grouping::By(
+            vec![Transform::Arithmetic(vec![
+                ArithmeticOperand::new(ArithmeticOperator::Noop, Transform::ContextItem),
+                ArithmeticOperand::new(
+                    ArithmeticOperator::Modulo,
+                    Transform::Literal(Item::<N>::Value(Rc::new(Value::from(10)))),
+                ),
+            ])],
+            None,
+        )),
         Box::new(Transform::Range(
             Box::new(Transform::Literal(Item::<N>::Value(Rc::new(Value::from(
                 1,
@@ -2738,11 +2787,22 @@ where
         Box::new(Transform::LiteralElement(
             QualifiedName::new(None, None, String::from("group")),
             Box::new(Transform::SequenceItems(vec![
-                Transform::Literal(Item::Value(Rc::new(Value::from("key ")))),
+                Transform::LiteralText(
+                    Box::new(Transform::Literal(Item::Value(Rc::new(Value::from(
+                        "key ",
+                    ))))),
+                    true,
+                ),
                 Transform::CurrentGroupingKey,
-                Transform::Literal(Item::Value(Rc::new(Value::from(" #members ")))),
+                Transform::LiteralText(
+                    Box::new(Transform::Literal(Item::Value(Rc::new(Value::from(
+                        " #members ",
+                    ))))),
+                    true,
+                ),
                 Transform::Count(Box::new(Transform::CurrentGroup)),
             ])),
+            true,
         )),
         vec![(Order::Ascending, Transform::CurrentGroupingKey)],
     );
@@ -2791,11 +2851,22 @@ where
         Box::new(Transform::LiteralElement(
             QualifiedName::new(None, None, String::from("group")),
             Box::new(Transform::SequenceItems(vec![
-                Transform::Literal(Item::Value(Rc::new(Value::from("key ")))),
+                Transform::LiteralText(
+                    Box::new(Transform::Literal(Item::Value(Rc::new(Value::from(
+                        "key ",
+                    ))))),
+                    true,
+                ),
                 Transform::CurrentGroupingKey,
-                Transform::Literal(Item::Value(Rc::new(Value::from(" #members ")))),
+                Transform::LiteralText(
+                    Box::new(Transform::Literal(Item::Value(Rc::new(Value::from(
+                        " #members ",
+                    ))))),
+                    true,
+                ),
                 Transform::Count(Box::new(Transform::CurrentGroup)),
             ])),
+            true,
         )),
         vec![],
     );
@@ -2835,11 +2906,22 @@ where
         Box::new(Transform::LiteralElement(
             QualifiedName::new(None, None, String::from("group")),
             Box::new(Transform::SequenceItems(vec![
-                Transform::Literal(Item::Value(Rc::new(Value::from("key ")))),
+                Transform::LiteralText(
+                    Box::new(Transform::Literal(Item::Value(Rc::new(Value::from(
+                        "key ",
+                    ))))),
+                    true,
+                ),
                 Transform::CurrentGroupingKey,
-                Transform::Literal(Item::Value(Rc::new(Value::from(" #members ")))),
+                Transform::LiteralText(
+                    Box::new(Transform::Literal(Item::Value(Rc::new(Value::from(
+                        " #members ",
+                    ))))),
+                    true,
+                ),
                 Transform::Count(Box::new(Transform::CurrentGroup)),
             ])),
+            true,
         )),
         vec![(Order::Ascending, Transform::CurrentGroupingKey)],
     );
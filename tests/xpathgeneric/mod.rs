@@ -186,6 +186,33 @@ where
     }
 }
 
+pub fn generic_syntax_error_position<N: Node, G, H>(_: G, _: H) -> Result<(), Error>
+where
+    G: Fn() -> N,
+    H: Fn() -> Item<N>,
+{
+    let e = parse::<N>("child::[1]").expect_err("parsed an axis step without a node test");
+    assert_eq!(e.kind, ErrorKind::StaticSyntax);
+    assert_eq!(
+        e.code.as_ref().map(|c| c.to_string()),
+        Some(String::from("XPST0003"))
+    );
+    assert!(e.message.contains("offset 7"));
+    assert!(e.message.contains("expected one of"));
+    assert!(e.message.ends_with("child::[1]\n       ^"));
+
+    // An unterminated string literal is reported at the end of the expression
+    let f = parse::<N>("'abc").expect_err("parsed an unterminated string literal");
+    assert_eq!(
+        f.code.as_ref().map(|c| c.to_string()),
+        Some(String::from("XPST0003"))
+    );
+    assert!(f.message.contains("offset 4"));
+    assert!(f.message.contains("\"'\""));
+    assert!(f.message.ends_with("'abc\n    ^"));
+    Ok(())
+}
+
 pub fn generic_path_3<N: Node, G, H>(make_empty_doc: G, make_doc: H) -> Result<(), Error>
 where
     G: Fn() -> N,
@@ -415,6 +442,51 @@ where
     Ok(())
 }
 
+pub fn generic_step_attribute_parent<N: Node, G, H>(
+    make_empty_doc: G,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn() -> N,
+    H: Fn() -> Item<N>,
+{
+    // The parent of an attribute is its element, and the attribute's ancestors are the element's ancestors
+    let s: Sequence<N> = dispatch_rig(
+        "/child::a/child::b/child::a/attribute::id/parent::*/parent::*/attribute::id",
+        &make_empty_doc,
+        &make_doc,
+    )?;
+    assert_eq!(s.to_string(), "b1b6");
+    let s: Sequence<N> = dispatch_rig(
+        "count(/child::a/child::b/child::a/attribute::id/ancestor::*)",
+        &make_empty_doc,
+        &make_doc,
+    )?;
+    assert_eq!(s.to_string(), "4");
+    // Attributes are not children
+    let s: Sequence<N> =
+        dispatch_rig("count(/child::a/child::node())", &make_empty_doc, &make_doc)?;
+    assert_eq!(s.to_string(), "2");
+    // An attribute comes after its element and before the element's children
+    let s: Sequence<N> = dispatch_rig(
+        "/child::a/child::b/child::a | /child::a/child::b/attribute::id | /child::a/child::b",
+        &make_empty_doc,
+        &make_doc,
+    )?;
+    let names: Vec<String> = s
+        .iter()
+        .map(|i| match i {
+            Item::Node(n) if n.node_type() == NodeType::Attribute => n.value().to_string(),
+            Item::Node(n) => n
+                .get_attribute(&QualifiedName::new(None, None, "id"))
+                .to_string(),
+            _ => panic!("not a node"),
+        })
+        .collect();
+    assert_eq!(names, vec!["b1", "b1", "a2", "a3", "b6", "b6", "a4", "a5"]);
+    Ok(())
+}
+
 pub fn generic_step_parent_1<N: Node, G, H>(make_empty_doc: G, make_doc: H) -> Result<(), Error>
 where
     G: Fn() -> N,
@@ -522,6 +594,43 @@ where
     }
 }
 
+pub fn generic_fncall_root<N: Node, G, H>(make_empty_doc: G, make_doc: H) -> Result<(), Error>
+where
+    G: Fn() -> N,
+    H: Fn() -> Item<N>,
+{
+    let s: Sequence<N> = dispatch_rig(
+        "root(child::a/child::b/child::a/child::b[attribute::id eq 'b3'])",
+        make_empty_doc,
+        make_doc,
+    )?;
+    assert_eq!(s.len(), 1);
+    match &s[0] {
+        Item::Node(n) => {
+            assert_eq!(n.node_type(), NodeType::Document);
+            Ok(())
+        }
+        _ => Err(Error::new(ErrorKind::Unknown, "not a node")),
+    }
+}
+
+pub fn generic_fncall_path<N: Node, G, H>(make_empty_doc: G, make_doc: H) -> Result<(), Error>
+where
+    G: Fn() -> N,
+    H: Fn() -> Item<N>,
+{
+    let s: Sequence<N> = dispatch_rig(
+        "path(child::a/child::b/child::a/child::b[attribute::id eq 'b3']/attribute::id)",
+        &make_empty_doc,
+        &make_doc,
+    )?;
+    assert_eq!(s.len(), 1);
+    assert_eq!(s.to_string(), "/Q{}a[1]/Q{}b[1]/Q{}a[1]/Q{}b[2]/@id");
+    let t: Sequence<N> = dispatch_rig("path()", &make_empty_doc, &make_doc)?;
+    assert_eq!(t.to_string(), "/");
+    Ok(())
+}
+
 pub fn generic_xpath_context_item<N: Node, G, H>(make_empty_doc: G, _: H) -> Result<(), Error>
 where
     G: Fn() -> N,
@@ -793,6 +902,57 @@ where
     Ok(())
 }
 
+pub fn generic_nested_sequence<N: Node, G, H>(_: G, _: H) -> Result<(), Error>
+where
+    G: Fn() -> N,
+    H: Fn() -> Item<N>,
+{
+    let s: Sequence<N> = no_src_no_result("count(((1, 2), (3)))")?;
+    assert_eq!(s.len(), 1);
+    assert_eq!(s[0].to_int().unwrap(), 3);
+    let s: Sequence<N> = no_src_no_result("( (), (1, ( 2, 3 )) )")?;
+    assert_eq!(s.len(), 3);
+    assert_eq!(s[2].to_int().unwrap(), 3);
+    Ok(())
+}
+
+pub fn generic_empty_sequence_compare<N: Node, G, H>(_: G, _: H) -> Result<(), Error>
+where
+    G: Fn() -> N,
+    H: Fn() -> Item<N>,
+{
+    let s: Sequence<N> = no_src_no_result("( ) = 1")?;
+    assert_eq!(s.len(), 1);
+    assert!(!s[0].to_bool());
+    Ok(())
+}
+
+pub fn generic_sequence_argument<N: Node, G, H>(_: G, _: H) -> Result<(), Error>
+where
+    G: Fn() -> N,
+    H: Fn() -> Item<N>,
+{
+    let s: Sequence<N> = no_src_no_result("sum( (1, 2, 3) )")?;
+    assert_eq!(s.len(), 1);
+    assert_eq!(s[0].to_int().unwrap(), 6);
+    Ok(())
+}
+
+pub fn generic_node_sequence<N: Node, G, H>(make_empty_doc: G, make_doc: H) -> Result<(), Error>
+where
+    G: Fn() -> N,
+    H: Fn() -> Item<N>,
+{
+    let s: Sequence<N> = dispatch_rig(
+        "count((child::a, child::a/child::b))",
+        make_empty_doc,
+        make_doc,
+    )?;
+    assert_eq!(s.len(), 1);
+    assert_eq!(s[0].to_int().unwrap(), 3);
+    Ok(())
+}
+
 pub fn generic_fncall_string<N: Node, G, H>(_: G, _: H) -> Result<(), Error>
 where
     G: Fn() -> N,
@@ -987,6 +1147,179 @@ where
     Ok(())
 }
 
+pub fn generic_fncall_containstoken<N: Node, G, H>(_: G, _: H) -> Result<(), Error>
+where
+    G: Fn() -> N,
+    H: Fn() -> Item<N>,
+{
+    let s: Sequence<N> =
+        no_src_no_result("contains-token(('a', '  note   important '), ' important ')")?;
+    assert_eq!(s.len(), 1);
+    assert!(s[0].to_bool());
+    let s: Sequence<N> = no_src_no_result("contains-token('notes important', 'note')")?;
+    assert!(!s[0].to_bool());
+    Ok(())
+}
+
+pub fn generic_fncall_tokenize_ws<N: Node, G, H>(_: G, _: H) -> Result<(), Error>
+where
+    G: Fn() -> N,
+    H: Fn() -> Item<N>,
+{
+    let s: Sequence<N> = no_src_no_result("tokenize('	a  b\nc 	')")?;
+    assert_eq!(s.len(), 3);
+    assert_eq!(s[0].to_string(), "a");
+    assert_eq!(s[1].to_string(), "b");
+    assert_eq!(s[2].to_string(), "c");
+    Ok(())
+}
+
+pub fn generic_fncall_tokenize_spaces<N: Node, G, H>(_: G, _: H) -> Result<(), Error>
+where
+    G: Fn() -> N,
+    H: Fn() -> Item<N>,
+{
+    let s: Sequence<N> = no_src_no_result("tokenize('  a  b c ')")?;
+    assert_eq!(s.len(), 3);
+    assert_eq!(s[0].to_string(), "a");
+    assert_eq!(s[1].to_string(), "b");
+    assert_eq!(s[2].to_string(), "c");
+    let t: Sequence<N> = no_src_no_result("tokenize('   ')")?;
+    assert_eq!(t.len(), 0);
+    let u: Sequence<N> = no_src_no_result("tokenize(())")?;
+    assert_eq!(u.len(), 0);
+    Ok(())
+}
+
+pub fn generic_fncall_tokenize_errors<N: Node, G, H>(_: G, _: H) -> Result<(), Error>
+where
+    G: Fn() -> N,
+    H: Fn() -> Item<N>,
+{
+    for (expr, code) in [
+        ("tokenize('a(b', '(')", "FORX0002"),
+        ("tokenize('a b', ' ', 'z')", "FORX0001"),
+        ("tokenize('a b', 'c*')", "FORX0003"),
+    ] {
+        let e = no_src_no_result::<N>(expr).expect_err(expr);
+        assert_eq!(
+            e.code.as_ref().map(|c| c.to_string()),
+            Some(String::from(code)),
+            "{}",
+            expr
+        );
+    }
+    Ok(())
+}
+
+pub fn generic_fncall_string_join<N: Node, G, H>(_: G, _: H) -> Result<(), Error>
+where
+    G: Fn() -> N,
+    H: Fn() -> Item<N>,
+{
+    let s: Sequence<N> = no_src_no_result("string-join(('a','b','c'))")?;
+    assert_eq!(s.len(), 1);
+    assert_eq!(s.to_string(), "abc");
+    let t: Sequence<N> = no_src_no_result("string-join(('a','b','c'), ', ')")?;
+    assert_eq!(t.to_string(), "a, b, c");
+    Ok(())
+}
+
+pub fn generic_fncall_distinct_values<N: Node, G, H>(_: G, _: H) -> Result<(), Error>
+where
+    G: Fn() -> N,
+    H: Fn() -> Item<N>,
+{
+    let s: Sequence<N> =
+        no_src_no_result("distinct-values(('Apple', 'apple', 'APPLE', 1, 1.0, 2))")?;
+    assert_eq!(s.len(), 5);
+    assert_eq!(s.to_string(), "AppleappleAPPLE12");
+    let t: Sequence<N> = no_src_no_result("distinct-values(('Apple', 'apple', 'APPLE', 1, 1.0, 2), 'http://www.w3.org/2005/xpath-functions/collation/html-ascii-case-insensitive')")?;
+    assert_eq!(t.len(), 3);
+    assert_eq!(t[0].to_string(), "Apple");
+    // Large integers are not confused, a boolean is not the same as a number, and all NaNs are the same
+    let u: Sequence<N> = no_src_no_result(
+        "distinct-values((9007199254740992, 9007199254740993, true(), 1, 1e0, number('x'), number('y')))",
+    )?;
+    assert_eq!(u.len(), 5);
+    Ok(())
+}
+
+pub fn generic_fncall_index_of<N: Node, G, H>(_: G, _: H) -> Result<(), Error>
+where
+    G: Fn() -> N,
+    H: Fn() -> Item<N>,
+{
+    let s: Sequence<N> = no_src_no_result("index-of(('Apple', 'apple', 'APPLE'), 'apple')")?;
+    assert_eq!(s.len(), 1);
+    assert_eq!(s[0].to_int()?, 2);
+    let t: Sequence<N> = no_src_no_result("index-of(('Apple', 'apple', 'APPLE'), 'apple', 'http://www.w3.org/2005/xpath-functions/collation/html-ascii-case-insensitive')")?;
+    assert_eq!(t.len(), 3);
+    assert_eq!(t[2].to_int()?, 3);
+    let u: Sequence<N> = no_src_no_result("index-of((10, 20, 10), 10)")?;
+    assert_eq!(u.len(), 2);
+    assert_eq!(u[1].to_int()?, 3);
+    Ok(())
+}
+
+pub fn generic_fncall_insert_before<N: Node, G, H>(_: G, _: H) -> Result<(), Error>
+where
+    G: Fn() -> N,
+    H: Fn() -> Item<N>,
+{
+    let s: Sequence<N> = no_src_no_result("insert-before(('a', 'b', 'c'), 2, ('x', 'y'))")?;
+    assert_eq!(s.len(), 5);
+    assert_eq!(s.to_string(), "axybc");
+    // Positions outside the sequence are clamped to its start or end
+    let t: Sequence<N> = no_src_no_result("insert-before(('a', 'b'), 0, 'x')")?;
+    assert_eq!(t.to_string(), "xab");
+    let u: Sequence<N> = no_src_no_result("insert-before(('a', 'b'), 5, 'x')")?;
+    assert_eq!(u.to_string(), "abx");
+    let v: Sequence<N> = no_src_no_result("insert-before((), 1, 'x')")?;
+    assert_eq!(v.to_string(), "x");
+    Ok(())
+}
+
+pub fn generic_fncall_remove<N: Node, G, H>(_: G, _: H) -> Result<(), Error>
+where
+    G: Fn() -> N,
+    H: Fn() -> Item<N>,
+{
+    let s: Sequence<N> = no_src_no_result("remove(('a', 'b', 'c'), 1)")?;
+    assert_eq!(s.len(), 2);
+    assert_eq!(s.to_string(), "bc");
+    // A position outside the sequence leaves it unchanged
+    let t: Sequence<N> = no_src_no_result("remove(('a', 'b', 'c'), 0)")?;
+    assert_eq!(t.to_string(), "abc");
+    let u: Sequence<N> = no_src_no_result("remove(('a', 'b', 'c'), 4)")?;
+    assert_eq!(u.to_string(), "abc");
+    let v: Sequence<N> = no_src_no_result("remove((), 1)")?;
+    assert_eq!(v.len(), 0);
+    Ok(())
+}
+
+pub fn generic_fncall_max_min<N: Node, G, H>(_: G, _: H) -> Result<(), Error>
+where
+    G: Fn() -> N,
+    H: Fn() -> Item<N>,
+{
+    let s: Sequence<N> = no_src_no_result("max(('apple', 'Banana'))")?;
+    assert_eq!(s.to_string(), "apple");
+    let t: Sequence<N> = no_src_no_result("max(('apple', 'Banana'), 'http://www.w3.org/2005/xpath-functions/collation/html-ascii-case-insensitive')")?;
+    assert_eq!(t.to_string(), "Banana");
+    let u: Sequence<N> = no_src_no_result("min(('apple', 'Banana'))")?;
+    assert_eq!(u.to_string(), "Banana");
+    let v: Sequence<N> = no_src_no_result("min(('apple', 'Banana'), 'http://www.w3.org/2005/xpath-functions/collation/html-ascii-case-insensitive')")?;
+    assert_eq!(v.to_string(), "apple");
+    let w: Sequence<N> = no_src_no_result("max((1, 3.5, 2))")?;
+    assert_eq!(w.to_string(), "3.5");
+    let x: Sequence<N> = no_src_no_result("min((3, 1, 2))")?;
+    assert_eq!(x[0].to_int()?, 1);
+    let y: Sequence<N> = no_src_no_result("max(())")?;
+    assert_eq!(y.len(), 0);
+    Ok(())
+}
+
 pub fn generic_fncall_translate<N: Node, G, H>(_: G, _: H) -> Result<(), Error>
 where
     G: Fn() -> N,
@@ -1146,6 +1479,34 @@ where
     Ok(())
 }
 
+pub fn generic_fncall_sum_non_numeric<N: Node, G, H>(_: G, _: H) -> Result<(), Error>
+where
+    G: Fn() -> N,
+    H: Fn() -> Item<N>,
+{
+    // A value that is not a number is a type error, rather than giving NaN
+    for expr in [
+        "sum((1, 'two', 3))",
+        "avg((1, 'two', 3))",
+        "max((1, 'two', 3))",
+        "min((1, true()))",
+    ] {
+        let e = no_src_no_result::<N>(expr).expect_err(expr);
+        assert_eq!(e.kind, ErrorKind::TypeError);
+        assert_eq!(
+            e.code.as_ref().map(|c| c.to_string()),
+            Some(String::from("FORG0006")),
+            "{}",
+            expr
+        );
+    }
+    let s: Sequence<N> = no_src_no_result("avg((1, 2, '6'))")?;
+    assert_eq!(s.to_string(), "3");
+    let t: Sequence<N> = no_src_no_result("avg(())")?;
+    assert_eq!(t.len(), 0);
+    Ok(())
+}
+
 pub fn generic_fncall_floor<N: Node, G, H>(_: G, _: H) -> Result<(), Error>
 where
     G: Fn() -> N,
@@ -1512,6 +1873,101 @@ where
     Ok(())
 }
 
+// A haystack of more than 100,000 elements with a needle near the start.
+fn make_haystack<N: Node>(mut doc: N) -> Result<N, Error> {
+    let element = |name: &str| doc.new_element(QualifiedName::new(None, None, name));
+    let mut root = element("haystack")?;
+    let mut sections = vec![];
+    for s in 0..10 {
+        let mut section = element("section")?;
+        if s == 0 {
+            section.push(element("needle")?)?;
+        }
+        for _ in 0..100 {
+            let mut hay = element("hay")?;
+            for _ in 0..100 {
+                hay.push(element("straw")?)?;
+            }
+            section.push(hay)?;
+        }
+        sections.push(section);
+    }
+    for s in sections {
+        root.push(s)?;
+    }
+    doc.push(root)?;
+    Ok(doc)
+}
+
+// Existence, effective boolean values and quantified expressions stop as soon as the result is known,
+// so only a small part of the haystack is visited.
+pub fn generic_early_exit<N: Node, G, C>(make_empty_doc: G, visited: C) -> Result<(), Error>
+where
+    G: Fn() -> N,
+    C: Fn(&N) -> usize,
+{
+    let doc = make_haystack(make_empty_doc())?;
+    let mut stctxt = StaticContextBuilder::new()
+        .message(|_| Ok(()))
+        .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .build();
+    let ctxt = ContextBuilder::new()
+        .context(vec![Item::Node(doc.clone())])
+        .build();
+    let mut evaluate = |e: &str| -> Result<(String, usize), Error> {
+        let before = visited(&doc);
+        let result = ctxt.dispatch(&mut stctxt, &parse(e)?)?;
+        Ok((result.to_string(), visited(&doc) - before))
+    };
+    for (e, expected) in [
+        ("exists(//needle)", "true"),
+        ("empty(//needle)", "false"),
+        ("boolean(//needle)", "true"),
+        ("if (//needle) then 'found' else 'lost'", "found"),
+        ("exists(//section[child::needle])", "true"),
+        ("some $n in //* satisfies name($n) = 'needle'", "true"),
+        ("every $n in //* satisfies name($n) = 'haystack'", "false"),
+    ] {
+        let (result, count) = evaluate(e)?;
+        assert_eq!(result, expected, "evaluating {}", e);
+        assert!(count < 100, "evaluating {} visited {} nodes", e, count);
+    }
+    // Counting the needles must visit the whole haystack
+    let (result, count) = evaluate("count(//needle)")?;
+    assert_eq!(result, "1");
+    assert!(count > 100000, "counting visited {} nodes", count);
+    Ok(())
+}
+
+pub fn generic_quantified<N: Node, G, H>(make_empty_doc: G, make_doc: H) -> Result<(), Error>
+where
+    G: Fn() -> N,
+    H: Fn() -> Item<N>,
+{
+    for (e, expected) in [
+        ("some $x in (1, 2, 3) satisfies $x > 2", "true"),
+        ("some $x in (1, 2, 3) satisfies $x > 3", "false"),
+        ("some $x in () satisfies $x > 3", "false"),
+        ("every $x in (1, 2, 3) satisfies $x > 0", "true"),
+        ("every $x in (1, 2, 3) satisfies $x > 1", "false"),
+        ("every $x in () satisfies $x > 3", "true"),
+        ("some $x in (1, 2), $y in (2, 3) satisfies $x = $y", "true"),
+        (
+            "every $x in (1, 2), $y in (2, 3) satisfies $x < $y",
+            "false",
+        ),
+        ("exists(())", "false"),
+        ("empty(())", "true"),
+        ("exists(child::a)", "true"),
+        ("empty(child::a/child::nothing)", "true"),
+    ] {
+        let result: Sequence<N> = dispatch_rig(e, &make_empty_doc, &make_doc)?;
+        assert_eq!(result.to_string(), expected, "evaluating {}", e);
+    }
+    Ok(())
+}
+
 fn unimplemented_rig<N: Node, G, H>(
     e: impl AsRef<str>,
     make_empty_doc: G,
@@ -1538,7 +1994,187 @@ where
     G: Fn() -> N,
     H: Fn() -> Item<N>,
 {
-    unimplemented_rig("'a' | 'b'", make_empty_doc, make_doc)
+    let result: Sequence<N> = dispatch_rig(
+        "descendant::b[attribute::id eq 'b3'] | descendant::b[attribute::id eq 'b1'] union descendant::b[attribute::id eq 'b3']",
+        make_empty_doc,
+        make_doc,
+    )?;
+    assert_eq!(result.len(), 2);
+    assert_eq!(result[0].to_xml().starts_with("<b id='b1'>"), true);
+    assert_eq!(result[1].to_xml(), "<b id='b3'></b>");
+    Ok(())
+}
+pub fn generic_path_document_order<N: Node, G, H>(make_empty_doc: G, _: H) -> Result<(), Error>
+where
+    G: Fn() -> N,
+    H: Fn() -> Item<N>,
+{
+    let mut sd = make_empty_doc();
+    let mut top = sd
+        .new_element(QualifiedName::new(None, None, String::from("Top")))
+        .expect("unable to create element");
+    sd.push(top.clone()).expect("unable to add node");
+    for i in 1..=5 {
+        let mut c = sd
+            .new_element(QualifiedName::new(None, None, String::from("Child")))
+            .expect("unable to create element");
+        c.push(
+            sd.new_text(Rc::new(Value::from(i)))
+                .expect("unable to create text"),
+        )
+        .expect("unable to add node");
+        top.push(c).expect("unable to add node");
+    }
+    let mut stctxt = StaticContextBuilder::new()
+        .message(|_| Ok(()))
+        .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .build();
+    let seq = ContextBuilder::new()
+        .context(vec![Item::Node(sd)])
+        .result_document(make_empty_doc())
+        .build()
+        .dispatch(
+            &mut stctxt,
+            &parse("child::Top/child::Child/preceding-sibling::*/following-sibling::*")?,
+        )?;
+    // Every Child except the first is a following sibling of some preceding sibling,
+    // and each must appear only once, in document order
+    assert_eq!(seq.len(), 4);
+    assert_eq!(seq.to_string(), "2345");
+    Ok(())
+}
+// <Test id='t'><Level1 a='1'>one<?target data?><?other x?></Level1><Level2>two<!--c--></Level2></Test>
+fn axis_fixture<N: Node>(mut sd: N) -> Result<N, Error> {
+    let mut t = sd.new_element(QualifiedName::new(None, None, "Test"))?;
+    sd.push(t.clone())?;
+    t.add_attribute(sd.new_attribute(
+        QualifiedName::new(None, None, "id"),
+        Rc::new(Value::from("t")),
+    )?)?;
+    let mut l1 = sd.new_element(QualifiedName::new(None, None, "Level1"))?;
+    t.push(l1.clone())?;
+    l1.add_attribute(sd.new_attribute(
+        QualifiedName::new(None, None, "a"),
+        Rc::new(Value::from("1")),
+    )?)?;
+    l1.push(sd.new_text(Rc::new(Value::from("one")))?)?;
+    l1.push(sd.new_processing_instruction(
+        QualifiedName::new(None, None, "target"),
+        Rc::new(Value::from("data")),
+    )?)?;
+    l1.push(sd.new_processing_instruction(
+        QualifiedName::new(None, None, "other"),
+        Rc::new(Value::from("x")),
+    )?)?;
+    let mut l2 = sd.new_element(QualifiedName::new(None, None, "Level2"))?;
+    t.push(l2.clone())?;
+    l2.push(sd.new_text(Rc::new(Value::from("two")))?)?;
+    l2.push(sd.new_comment(Rc::new(Value::from("c")))?)?;
+    Ok(sd)
+}
+pub fn generic_self_axis_kinds<N: Node, G, H>(make_empty_doc: G, _: H) -> Result<(), Error>
+where
+    G: Fn() -> N,
+    H: Fn() -> Item<N>,
+{
+    let sd = axis_fixture(make_empty_doc())?;
+    let mut stctxt = StaticContextBuilder::new()
+        .message(|_| Ok(()))
+        .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .build();
+    let ctxt = ContextBuilder::new().context(vec![Item::Node(sd)]).build();
+    // (expression, number of nodes selected)
+    vec![
+        ("self::node()", 1),
+        ("self::document-node()", 1),
+        ("self::element()", 0),
+        ("child::Test/child::*/self::node()[self::Level1 or self::Level2]", 2),
+        ("child::Test/child::*/self::Level2", 1),
+        // Attribute context items: the principal node kind of self:: is element
+        ("child::Test/attribute::id/self::node()", 1),
+        ("child::Test/attribute::id/self::attribute()", 1),
+        ("child::Test/attribute::id/self::id", 0),
+        ("child::Test/attribute::id/self::*", 0),
+        ("child::Test/attribute::id/self::text()", 0),
+        // Text context items
+        ("child::Test/child::Level1/child::text()/self::node()", 1),
+        ("child::Test/child::Level1/child::text()/self::text()", 1),
+        ("child::Test/child::Level1/child::text()/self::Level1", 0),
+        ("child::Test/child::Level1/child::text()/self::element()", 0),
+        // Other kinds of context items
+        ("child::Test/child::Level2/child::comment()/self::comment()", 1),
+        ("child::Test/child::Level2/child::comment()/self::text()", 0),
+        (
+            "child::Test/child::Level1/child::processing-instruction()/self::processing-instruction('target')",
+            1,
+        ),
+        // The principal node kind of attribute:: is attribute
+        ("child::Test/attribute::*", 1),
+        ("child::Test/attribute::id", 1),
+        ("child::Test/attribute::attribute()", 1),
+        ("child::Test/attribute::node()", 1),
+        ("child::Test/attribute::element()", 0),
+    ]
+    .iter()
+    .try_for_each(|(e, c)| {
+        let seq = ctxt.dispatch(&mut stctxt, &parse(e)?)?;
+        assert_eq!(seq.len(), *c, "expression \"{}\"", e);
+        Ok(())
+    })
+}
+pub fn generic_descendant_or_self_axis_kinds<N: Node, G, H>(
+    make_empty_doc: G,
+    _: H,
+) -> Result<(), Error>
+where
+    G: Fn() -> N,
+    H: Fn() -> Item<N>,
+{
+    let sd = axis_fixture(make_empty_doc())?;
+    let mut stctxt = StaticContextBuilder::new()
+        .message(|_| Ok(()))
+        .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .build();
+    let ctxt = ContextBuilder::new().context(vec![Item::Node(sd)]).build();
+    // (expression, number of nodes selected)
+    vec![
+        // The document, three elements, two text nodes, two PIs and a comment. Attributes are not descendants.
+        ("descendant-or-self::node()", 9),
+        ("descendant-or-self::document-node()", 1),
+        ("descendant-or-self::element()", 3),
+        ("descendant-or-self::*", 3),
+        ("descendant-or-self::text()", 2),
+        ("descendant-or-self::comment()", 1),
+        ("descendant-or-self::processing-instruction()", 2),
+        ("descendant-or-self::processing-instruction('target')", 1),
+        ("descendant-or-self::processing-instruction(other)", 1),
+        ("descendant-or-self::processing-instruction('missing')", 0),
+        ("descendant-or-self::attribute()", 0),
+        ("descendant-or-self::id", 0),
+        // The context node is included exactly once
+        ("child::Test/descendant-or-self::Test", 1),
+        ("child::Test/descendant-or-self::node()", 8),
+        ("child::Test/child::Level1/descendant-or-self::node()", 4),
+        (
+            "child::Test/child::Level1/child::text()/descendant-or-self::node()",
+            1,
+        ),
+        (
+            "child::Test/child::Level1/child::text()/descendant-or-self::text()",
+            1,
+        ),
+        ("child::Test/attribute::id/descendant-or-self::node()", 1),
+        ("child::Test/attribute::id/descendant-or-self::id", 0),
+    ]
+    .iter()
+    .try_for_each(|(e, c)| {
+        let seq = ctxt.dispatch(&mut stctxt, &parse(e)?)?;
+        assert_eq!(seq.len(), *c, "expression \"{}\"", e);
+        Ok(())
+    })
 }
 pub fn generic_intersectexcept<N: Node, G, H>(make_empty_doc: G, make_doc: H) -> Result<(), Error>
 where
@@ -1552,7 +2188,37 @@ where
     G: Fn() -> N,
     H: Fn() -> Item<N>,
 {
-    unimplemented_rig("'a' instance of empty-sequence()", make_empty_doc, make_doc)
+    vec![
+        // 5, 5.0 and "5" have different types
+        ("5 instance of xs:integer", "true"),
+        ("5.0 instance of xs:integer", "false"),
+        ("'5' instance of xs:integer", "false"),
+        ("'5' instance of xs:string", "true"),
+        ("xs:decimal('5.0') instance of xs:decimal", "true"),
+        ("xs:decimal('5.0') instance of xs:integer", "false"),
+        // A derived type is an instance of its base types
+        ("5 instance of xs:decimal", "true"),
+        ("xs:byte(5) instance of xs:short", "true"),
+        ("xs:byte(5) instance of xs:unsignedByte", "false"),
+        ("xs:integer('5') instance of xs:integer", "true"),
+        ("xs:untypedAtomic('5') instance of xs:string", "false"),
+        ("5 instance of xs:anyAtomicType", "true"),
+        ("5 instance of item()", "true"),
+        // Occurrence indicators
+        ("(1, 2) instance of xs:integer", "false"),
+        ("(1, 2) instance of xs:integer+", "true"),
+        ("() instance of xs:integer?", "true"),
+        ("() instance of xs:integer+", "false"),
+        ("() instance of empty-sequence()", "true"),
+        ("child::* instance of element()", "true"),
+        ("child::* instance of xs:string", "false"),
+    ]
+    .iter()
+    .try_for_each(|(e, expected)| {
+        let result = dispatch_rig(e, &make_empty_doc, &make_doc)?;
+        assert_eq!(result.to_string(), *expected, "evaluating {}", e);
+        Ok(())
+    })
 }
 pub fn generic_treat<N: Node, G, H>(make_empty_doc: G, make_doc: H) -> Result<(), Error>
 where
@@ -1566,14 +2232,165 @@ where
     G: Fn() -> N,
     H: Fn() -> Item<N>,
 {
-    unimplemented_rig("'a' castable as type?", make_empty_doc, make_doc)
+    vec![
+        ("'5' castable as xs:integer", "true"),
+        ("'five' castable as xs:integer", "false"),
+        ("300 castable as xs:byte", "false"),
+        ("() castable as xs:integer", "false"),
+        ("() castable as xs:integer?", "true"),
+    ]
+    .iter()
+    .try_for_each(|(e, expected)| {
+        let result = dispatch_rig(e, &make_empty_doc, &make_doc)?;
+        assert_eq!(result.to_string(), *expected, "evaluating {}", e);
+        Ok(())
+    })?;
+    // An unknown type is a static error, not a failed cast
+    let e = dispatch_rig("'5' castable as xs:foo", make_empty_doc, make_doc)
+        .expect_err("unknown type was castable");
+    assert_eq!(
+        e.code.map(|c| c.to_string()),
+        Some(String::from("XPST0051"))
+    );
+    Ok(())
 }
 pub fn generic_cast<N: Node, G, H>(make_empty_doc: G, make_doc: H) -> Result<(), Error>
 where
     G: Fn() -> N,
     H: Fn() -> Item<N>,
 {
-    unimplemented_rig("'a' cast as type?", make_empty_doc, make_doc)
+    vec![
+        ("' 12 ' cast as xs:integer", "12"),
+        ("'3.50' cast as xs:decimal", "3.5"),
+        ("xs:decimal('3.7') cast as xs:integer", "3"),
+        ("xs:decimal('-3.7') cast as xs:integer", "-3"),
+        ("'1e3' cast as xs:double", "1000"),
+        ("1 cast as xs:boolean", "true"),
+        ("xs:double('0') cast as xs:boolean", "false"),
+        ("'true' cast as xs:boolean", "true"),
+        ("xs:boolean('1') cast as xs:integer", "1"),
+        ("'2024-02-29' cast as xs:date", "2024-02-29"),
+        (
+            "xs:dateTime('2024-03-01T10:30:00') cast as xs:date",
+            "2024-03-01",
+        ),
+        ("xs:unsignedByte(255)", "255"),
+        ("12 cast as xs:string", "12"),
+        ("() cast as xs:integer?", ""),
+        ("xs:integer(())", ""),
+    ]
+    .iter()
+    .try_for_each(|(e, expected)| {
+        let result = dispatch_rig(e, &make_empty_doc, &make_doc)?;
+        assert_eq!(result.to_string(), *expected, "evaluating {}", e);
+        Ok(())
+    })?;
+    // The value remembers the type it was cast to
+    let result = dispatch_rig("'12' cast as xs:short", &make_empty_doc, &make_doc)?;
+    assert_eq!(result.len(), 1);
+    match &result[0] {
+        Item::Value(v) => assert_eq!(v.type_name().to_string(), "xs:short"),
+        _ => panic!("not a value"),
+    }
+    vec![
+        ("'yes' cast as xs:boolean", "FORG0001"),
+        ("'abc' cast as xs:integer", "FORG0001"),
+        ("'2023-02-29' cast as xs:date", "FORG0001"),
+        ("300 cast as xs:byte", "FORG0001"),
+        ("xs:unsignedByte(-1)", "FORG0001"),
+        ("xs:integer(xs:double('INF'))", "FOCA0002"),
+        ("xs:integer(xs:double('1e30'))", "FOCA0003"),
+        ("xs:date('2024-01-01') cast as xs:integer", "XPTY0004"),
+        ("() cast as xs:integer", "XPTY0004"),
+        ("(1, 2) cast as xs:integer", "XPTY0004"),
+        ("'5' cast as xs:anyAtomicType", "XPST0080"),
+        ("'5' cast as xs:foo", "XPST0051"),
+    ]
+    .iter()
+    .try_for_each(|(e, code)| {
+        let err = dispatch_rig(e, &make_empty_doc, &make_doc)
+            .expect_err(format!("evaluating {} succeeded", e).as_str());
+        assert_eq!(
+            err.code.map(|c| c.to_string()),
+            Some(code.to_string()),
+            "evaluating {}",
+            e
+        );
+        Ok(())
+    })
+}
+pub fn generic_parse_ietf_date<N: Node, G, H>(make_empty_doc: G, make_doc: H) -> Result<(), Error>
+where
+    G: Fn() -> N,
+    H: Fn() -> Item<N>,
+{
+    // Each date is formatted with the same picture as the equivalent xs:dateTime,
+    // so that the comparison does not depend on the local timezone
+    let picture = "'[Y]-[M]-[D]T[H]:[m]:[s].[f][z]'";
+    vec![
+        ("Wed, 06 Jun 1994 07:29:35 GMT", "1994-06-06T07:29:35Z"),
+        ("Wed, 6 Jun 94 07:29:35 GMT", "1994-06-06T07:29:35Z"),
+        ("Wed Jun 06 11:54:45 EST 2013", "2013-06-06T11:54:45-05:00"),
+        ("Sunday, 06-Nov-94 08:49:37 GMT", "1994-11-06T08:49:37Z"),
+        ("Wed, 6 Jun 94 07:29:35 +0500", "1994-06-06T07:29:35+05:00"),
+        ("  Sun Nov  6 08:49:37 1994 ", "1994-11-06T08:49:37Z"),
+        ("6 jun 94 7:29 -05:00 (EST)", "1994-06-06T07:29:00-05:00"),
+        ("Mon, 20 Mar 2023 10:00:01.5 UTC", "2023-03-20T10:00:01.5Z"),
+    ]
+    .iter()
+    .try_for_each(|(ietf, iso)| {
+        let result = dispatch_rig(
+            format!("format-date-time(parse-ietf-date('{}'), {})", ietf, picture),
+            &make_empty_doc,
+            &make_doc,
+        )?;
+        let expected = dispatch_rig(
+            format!("format-date-time(xs:dateTime('{}'), {})", iso, picture),
+            &make_empty_doc,
+            &make_doc,
+        )?;
+        assert_eq!(result.to_string(), expected.to_string(), "parsing {}", ietf);
+        Ok(())
+    })?;
+    assert_eq!(
+        dispatch_rig(
+            "parse-ietf-date('Wed, 06 Jun 1994 07:29:35 GMT') instance of xs:dateTime",
+            &make_empty_doc,
+            &make_doc
+        )?
+        .to_string(),
+        "true"
+    );
+    assert_eq!(
+        dispatch_rig("parse-ietf-date(())", &make_empty_doc, &make_doc)?.len(),
+        0
+    );
+    vec![
+        "",
+        "Wed, 1994-06-06 07:29:35",
+        "Wed, 06 Jun 994 07:29:35 GMT",
+        "Wed,06 Jun 1994 07:29:35 GMT",
+        "Wed, 06 Jun 1994 07:29:61 GMT",
+        "Wed, 31 Jun 1994 07:29:35 GMT",
+        "Wed, 06 Jun 1994 07:29:35 +1500",
+        "Wed, 06 Jun 1994 07:29:35 GMT extra",
+    ]
+    .iter()
+    .try_for_each(|ietf| {
+        let err = dispatch_rig(
+            format!("parse-ietf-date('{}')", ietf),
+            &make_empty_doc,
+            &make_doc,
+        )
+        .expect_err(format!("parsing \"{}\" succeeded", ietf).as_str());
+        assert_eq!(
+            err.code.map(|c| c.to_string()),
+            Some(String::from("FORG0010")),
+            "parsing {}",
+            ietf
+        );
+        Ok(())
+    })
 }
 pub fn generic_arrow<N: Node, G, H>(make_empty_doc: G, make_doc: H) -> Result<(), Error>
 where
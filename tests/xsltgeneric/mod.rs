@@ -1,12 +1,21 @@
 //! Tests for XSLT defined generically
 
 use pkg_version::{pkg_version_major, pkg_version_minor, pkg_version_patch};
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::rc::Rc;
 use url::Url;
-use xrust::item::{Item, Node, Sequence, SequenceTrait};
-use xrust::transform::context::StaticContextBuilder;
-use xrust::xdmerror::{Error, ErrorKind};
-use xrust::xslt::from_document;
+use xrust::externals::{directory_collection, directory_uri_collection};
+use xrust::item::{Item, Node, NodeType, Sequence, SequenceTrait};
+use xrust::output::{OutputDefinition, OutputDefinitionBuilder};
+use xrust::qname::QualifiedName;
+use xrust::testutils::xml_equal::assert_xml_equal;
+use xrust::transform::context::{
+    ContextBuilder, ErrorRecovery, MessageInfo, StaticContextBuilder, DEFAULT_MAX_DEPTH,
+};
+use xrust::value::Value;
+use xrust::xdmerror::{Error, ErrorKind, Location};
+use xrust::xslt::{from_document, from_tree, output_definition};
 
 fn test_rig<N: Node, G, H, J>(
     src: impl AsRef<str>,
@@ -27,13 +36,7 @@ where
         .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
         .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
         .build();
-    let mut ctxt = from_document(
-        styledoc,
-        stylens,
-        None,
-        |s| parse_from_str(s),
-        |_| Ok(String::new()),
-    )?;
+    let mut ctxt = from_tree(styledoc, stylens, None)?;
     ctxt.context(vec![Item::Node(srcdoc.clone())], 0);
     ctxt.result_document(make_doc()?);
     ctxt.populate_key_values(&mut stctxt, srcdoc.clone())?;
@@ -63,15 +66,77 @@ where
         .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
         .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
         .build();
-    let mut ctxt = from_document(
-        styledoc,
-        stylens,
-        None,
-        |s| parse_from_str(s),
-        |_| Ok(String::new()),
-    )?;
+    let mut ctxt = from_tree(styledoc, stylens, None)?;
+    ctxt.context(vec![Item::Node(srcdoc.clone())], 0);
+    ctxt.result_document(make_doc()?);
+    let seq = ctxt.evaluate(&mut stctxt)?;
+    Ok((seq, msgs))
+}
+
+// Returns the primary result, and the URI, serialised content, and output definition of each secondary result document.
+#[allow(clippy::type_complexity)]
+fn test_result_document_rig<N: Node, G, H, J>(
+    src: impl AsRef<str>,
+    style: impl AsRef<str>,
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(Sequence<N>, Vec<(String, String, OutputDefinition)>), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let srcdoc = parse_from_str(src.as_ref())?;
+    let (styledoc, stylens) = parse_from_str_with_ns(style.as_ref())?;
+    let docs = Rc::new(RefCell::new(vec![]));
+    let d = docs.clone();
+    let mut stctxt = StaticContextBuilder::new()
+        .message(|_| Ok(()))
+        .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .result_document(move |uri, content: Sequence<N>, od| {
+            d.borrow_mut()
+                .push((uri.to_string(), content.to_xml_with_options(od), od.clone()));
+            Ok(())
+        })
+        .build();
+    let mut ctxt = from_tree(styledoc, stylens, None)?;
+    ctxt.context(vec![Item::Node(srcdoc.clone())], 0);
+    ctxt.result_document(make_doc()?);
+    let seq = ctxt.evaluate(&mut stctxt)?;
+    let result = docs.borrow().clone();
+    Ok((seq, result))
+}
+
+fn test_recovery_rig<N: Node, G, H, J>(
+    src: impl AsRef<str>,
+    style: impl AsRef<str>,
+    recovery: ErrorRecovery,
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(Sequence<N>, Vec<String>), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let srcdoc = parse_from_str(src.as_ref())?;
+    let (styledoc, stylens) = parse_from_str_with_ns(style.as_ref())?;
+    let mut msgs: Vec<String> = vec![];
+    let mut stctxt = StaticContextBuilder::new()
+        .message(|m| {
+            msgs.push(String::from(m));
+            Ok(())
+        })
+        .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .build();
+    let mut ctxt = from_tree(styledoc, stylens, None)?;
     ctxt.context(vec![Item::Node(srcdoc.clone())], 0);
     ctxt.result_document(make_doc()?);
+    ctxt.error_recovery(recovery);
     let seq = ctxt.evaluate(&mut stctxt)?;
     Ok((seq, msgs))
 }
@@ -107,6 +172,50 @@ where
         ))
     }
 }
+pub fn generic_indented_stylesheet<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    // Indentation between instructions is stripped, but xsl:text and xml:space='preserve' keep their white space
+    let result = test_rig(
+        "<Test><Level1>one</Level1><Level1>two</Level1></Test>",
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+
+    <xsl:template match='/'>
+        <Result>
+            <xsl:apply-templates/>
+        </Result>
+    </xsl:template>
+
+    <xsl:template match='child::Test'>
+        <xsl:apply-templates/>
+        <Kept xml:space='preserve'>  </Kept>
+    </xsl:template>
+
+    <xsl:template match='child::Level1'>
+        <Item>
+            <xsl:value-of select='.'/>
+        </Item>
+        <xsl:text> </xsl:text>
+    </xsl:template>
+
+</xsl:stylesheet>"#,
+        parse_from_str,
+        parse_from_str_with_ns,
+        make_doc,
+    )?;
+    assert_eq!(
+        result.to_xml(),
+        "<Result><Item>one</Item> <Item>two</Item> <Kept xml:space='preserve'>  </Kept></Result>"
+    );
+    Ok(())
+}
 
 pub fn generic_sys_prop<N: Node, G, H, J>(
     parse_from_str: G,
@@ -217,6 +326,54 @@ where
     }
 }
 
+pub fn generic_text_disable_output_escaping<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let result = test_rig(
+        "<Test>one</Test>",
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='child::Test'><p><xsl:value-of select='.'/><xsl:text disable-output-escaping="yes">&lt;br/&gt;</xsl:text><xsl:text>&lt;br/&gt;</xsl:text></p></xsl:template>
+</xsl:stylesheet>"#,
+        parse_from_str,
+        parse_from_str_with_ns,
+        make_doc,
+    )?;
+    // Only the text that disables output escaping is raw markup
+    assert_eq!(result.to_xml(), "<p>one<br/>&lt;br/&gt;</p>");
+    Ok(())
+}
+
+pub fn generic_contains_token_class<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let result = test_rig(
+        "<Test><p class=' note  important '>one</p><p class='notes'>two</p><p class='note'>three</p></Test>",
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='/'><xsl:apply-templates select='child::Test/child::p[contains-token(attribute::class, "note")]'/></xsl:template>
+  <xsl:template match='child::p'><xsl:value-of select='.'/></xsl:template>
+</xsl:stylesheet>"#,
+        parse_from_str,
+        parse_from_str_with_ns,
+        make_doc,
+    )?;
+    assert_eq!(result.to_string(), "onethree");
+    Ok(())
+}
+
 pub fn generic_literal_element<N: Node, G, H, J>(
     parse_from_str: G,
     parse_from_str_with_ns: J,
@@ -350,6 +507,62 @@ where
     }
 }
 
+pub fn generic_apply_templates_position<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    // The outer numbering resumes after the nested apply-templates
+    let result = test_rig(
+        "<Test><Level1><Level2>a</Level2><Level2>b</Level2></Level1><Level1><Level2>c</Level2></Level1><Level1/></Test>",
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='/'><xsl:apply-templates/></xsl:template>
+  <xsl:template match='child::Test'><xsl:apply-templates select='child::Level1'/></xsl:template>
+  <xsl:template match='child::Level1'>[<xsl:value-of select='position()'/>/<xsl:value-of select='last()'/>:<xsl:apply-templates select='child::Level2'/>:<xsl:value-of select='position()'/>]</xsl:template>
+  <xsl:template match='child::Level2'><xsl:for-each select='child::text()'>(<xsl:value-of select='position()'/>)</xsl:for-each><xsl:value-of select='position()'/>/<xsl:value-of select='last()'/></xsl:template>
+</xsl:stylesheet>"#,
+        parse_from_str,
+        parse_from_str_with_ns,
+        make_doc,
+    )?;
+    assert_eq!(
+        result.to_string(),
+        "[1/3:(1)1/2(1)2/2:1][2/3:(1)1/1:2][3/3::3]"
+    );
+    Ok(())
+}
+
+pub fn generic_apply_templates_same_shape<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    // Elements with the same name are matched by different templates, depending on their parent
+    let result = test_rig(
+        "<Test><one><item>1</item></one><two><item>2</item></two><one><item>3</item></one></Test>",
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='/'><xsl:apply-templates select='child::Test/child::*/child::item'/></xsl:template>
+  <xsl:template match='child::one/child::item'>one<xsl:value-of select='.'/></xsl:template>
+  <xsl:template match='child::two/child::item'>two<xsl:value-of select='.'/></xsl:template>
+</xsl:stylesheet>"#,
+        parse_from_str,
+        parse_from_str_with_ns,
+        make_doc,
+    )?;
+    assert_eq!(result.to_string(), "one1two2one3");
+    Ok(())
+}
+
 pub fn generic_apply_templates_mode<N: Node, G, H, J>(
     parse_from_str: G,
     parse_from_str_with_ns: J,
@@ -386,6 +599,47 @@ where
     }
 }
 
+pub fn generic_generate_id_modes<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let result = test_rig(
+        "<Test><Level1 id='a'>a</Level1><Level1>b</Level1></Test>",
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='child::Test'><xsl:apply-templates select='child::Level1'/>|<xsl:apply-templates select='child::Level1' mode='other'/>|<xsl:for-each select='child::Level1'><xsl:value-of select='generate-id(.)'/>;<xsl:value-of select='generate-id(@id)'/>;</xsl:for-each></xsl:template>
+  <xsl:template match='child::Level1'><xsl:value-of select='generate-id()'/>;<xsl:value-of select='generate-id(@id)'/>;</xsl:template>
+  <xsl:template match='child::Level1' mode='other'><xsl:value-of select='generate-id(current())'/>;<xsl:apply-templates select='@id' mode='other'/>;</xsl:template>
+  <xsl:template match='@id' mode='other'><xsl:value-of select='generate-id()'/></xsl:template>
+</xsl:stylesheet>"#,
+        parse_from_str,
+        parse_from_str_with_ns,
+        make_doc,
+    )?
+    .to_string();
+    // Each pass gives the same identifiers for the same nodes
+    let passes: Vec<&str> = result.split('|').collect();
+    assert_eq!(passes.len(), 3);
+    assert_eq!(passes[0], passes[1]);
+    assert_eq!(passes[0], passes[2]);
+    // Different nodes have different identifiers, and each identifier is an alphanumeric name
+    let ids: Vec<&str> = passes[0].split(';').filter(|id| !id.is_empty()).collect();
+    assert_eq!(ids.len(), 3);
+    assert_ne!(ids[0], ids[1]);
+    assert_ne!(ids[0], ids[2]);
+    assert_ne!(ids[1], ids[2]);
+    ids.iter().for_each(|id| {
+        assert!(id.starts_with(|c: char| c.is_ascii_alphabetic()));
+        assert!(id.chars().all(|c| c.is_ascii_alphanumeric()));
+    });
+    Ok(())
+}
+
 pub fn generic_apply_templates_sort<N: Node, G, H, J>(
     parse_from_str: G,
     parse_from_str_with_ns: J,
@@ -423,6 +677,152 @@ where
     }
 }
 
+pub fn generic_sort_order_avt<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let srcdoc =
+        parse_from_str("<Test><Level1>b</Level1><Level1>c</Level1><Level1>a</Level1></Test>")?;
+    let (styledoc, stylens) = parse_from_str_with_ns(
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:param name='dir'/>
+  <xsl:template match='/'><xsl:apply-templates/></xsl:template>
+  <xsl:template match='child::Test'><xsl:apply-templates select='child::Level1'><xsl:sort select='.' order='{$dir}'/></xsl:apply-templates></xsl:template>
+  <xsl:template match='child::Level1'><xsl:number format='{$fmt}' grouping-separator='{$sep}' grouping-size='2'/><xsl:value-of select='.'/></xsl:template>
+</xsl:stylesheet>"#,
+    )?;
+    // The stylesheet is compiled once, and each run supplies different parameter values
+    let compiled = from_tree(styledoc, stylens, None)?;
+    let transform = |dir: &str| {
+        let mut stctxt = StaticContextBuilder::new()
+            .message(|_| Ok(()))
+            .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+            .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+            .build();
+        let param = |v: &str| vec![Item::Value(Rc::new(Value::from(v)))];
+        let mut ctxt = ContextBuilder::from(&compiled)
+            .variable(String::from("dir"), param(dir))
+            .variable(String::from("fmt"), param("(001)"))
+            .variable(String::from("sep"), param(":"))
+            .build();
+        ctxt.context(vec![Item::Node(srcdoc.clone())], 0);
+        ctxt.result_document(make_doc()?);
+        ctxt.evaluate(&mut stctxt)
+    };
+
+    assert_eq!(transform("ascending")?.to_string(), "(0:03)a(0:01)b(0:02)c");
+    assert_eq!(
+        transform("descending")?.to_string(),
+        "(0:02)c(0:01)b(0:03)a"
+    );
+    match transform("sideways") {
+        Ok(_) => panic!("invalid sort order was accepted"),
+        Err(e) => assert_eq!(
+            e.code.map(|c| c.to_string()),
+            Some(String::from("XTDE0030"))
+        ),
+    }
+    Ok(())
+}
+
+pub fn generic_perform_sort<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    // The sorted sequence is kept in a variable and used later.
+    // The union with the original nodes has no duplicates, so the sorted nodes are not copies.
+    let result = test_rig(
+        "<Test><one>blue</one><two>yellow</two><three>green</three></Test>",
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:variable name='sorted' as='element()*'>
+    <xsl:perform-sort select='child::Test/child::*'><xsl:sort select='.'/></xsl:perform-sort>
+  </xsl:variable>
+  <xsl:variable name='reversed' as='element()*'>
+    <xsl:perform-sort><xsl:sort select='.' order='descending'/><xsl:sequence select='child::Test/child::*'/></xsl:perform-sort>
+  </xsl:variable>
+  <xsl:template match='/'>
+    <xsl:value-of select='count($sorted)'/>
+    <xsl:text>;</xsl:text>
+    <xsl:for-each select='$sorted'><xsl:value-of select='position()'/>:<xsl:value-of select='local-name()'/>,</xsl:for-each>
+    <xsl:text>;</xsl:text>
+    <xsl:for-each select='$reversed'><xsl:value-of select='local-name()'/>,</xsl:for-each>
+    <xsl:text>;</xsl:text>
+    <xsl:value-of select='count($sorted | child::Test/child::*)'/>
+    <xsl:text>;</xsl:text>
+    <xsl:for-each select='$sorted'><xsl:value-of select='generate-id()'/>,</xsl:for-each>
+    <xsl:text>;</xsl:text>
+    <xsl:for-each select='child::Test/child::*'><xsl:value-of select='generate-id()'/>,</xsl:for-each>
+  </xsl:template>
+</xsl:stylesheet>"#,
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?
+    .to_string();
+    let parts: Vec<&str> = result.split(';').collect();
+    assert_eq!(parts.len(), 6);
+    assert_eq!(parts[0], "3");
+    assert_eq!(parts[1], "1:one,2:three,3:two,");
+    assert_eq!(parts[2], "two,three,one,");
+    assert_eq!(parts[3], "3");
+    // The sorted nodes have the same identifiers as the nodes in the source document
+    let sorted: Vec<&str> = parts[4].split(',').collect();
+    let original: Vec<&str> = parts[5].split(',').collect();
+    assert_eq!(sorted[0], original[0]);
+    assert_eq!(sorted[1], original[2]);
+    assert_eq!(sorted[2], original[1]);
+    Ok(())
+}
+
+pub fn generic_perform_sort_invalid<N: Node, G, H, J>(
+    _: G,
+    parse_from_str_with_ns: J,
+    _: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let compile = |body: &str| -> Result<(), Error> {
+        let (styledoc, stylens) = parse_from_str_with_ns(
+            format!(
+                "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'><xsl:template match='/'>{}</xsl:template></xsl:stylesheet>",
+                body
+            )
+            .as_str(),
+        )?;
+        from_tree(styledoc, stylens, None).map(|_| ())
+    };
+    let code = |r: Result<(), Error>| r.err().and_then(|e| e.code).map(|c| c.to_string());
+
+    // The select attribute and content are mutually exclusive
+    assert_eq!(
+        code(compile(
+            "<xsl:perform-sort select='child::*'><xsl:sort select='.'/><xsl:sequence select='.'/></xsl:perform-sort>"
+        )),
+        Some(String::from("XTSE1040"))
+    );
+    // At least one sort key is required
+    assert_eq!(
+        code(compile("<xsl:perform-sort select='child::*'/>")),
+        Some(String::from("XTSE0010"))
+    );
+    Ok(())
+}
+
 pub fn generic_comment<N: Node, G, H, J>(
     parse_from_str: G,
     parse_from_str_with_ns: J,
@@ -453,7 +853,7 @@ where
     }
 }
 
-pub fn generic_pi<N: Node, G, H, J>(
+pub fn generic_comment_hyphens<N: Node, G, H, J>(
     parse_from_str: G,
     parse_from_str_with_ns: J,
     make_doc: H,
@@ -464,25 +864,140 @@ where
     J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
 {
     let result = test_rig(
-        "<Test>one<Level1/>two<Level1/>three<Level1/>four<Level1/></Test>",
+        "<Test>one<Level1>a--b---c-</Level1>two</Test>",
         r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
-  <xsl:template match='/'><xsl:apply-templates/></xsl:template>
   <xsl:template match='child::Test'><xsl:apply-templates/></xsl:template>
-  <xsl:template match='child::Level1'><xsl:processing-instruction name='piL1'>this is a level 1 element</xsl:processing-instruction></xsl:template>
+  <xsl:template match='child::Level1'><xsl:comment><xsl:value-of select='.'/></xsl:comment></xsl:template>
   <xsl:template match='child::text()'><xsl:sequence select='.'/></xsl:template>
 </xsl:stylesheet>"#,
         parse_from_str,
         parse_from_str_with_ns,
         make_doc,
     )?;
-    if result.to_xml() == "one<?piL1 this is a level 1 element?>two<?piL1 this is a level 1 element?>three<?piL1 this is a level 1 element?>four<?piL1 this is a level 1 element?>" {
-        Ok(())
+    // A space is inserted after each hyphen that is followed by a hyphen or ends the comment
+    assert_eq!(result.to_xml(), "one<!--a- -b- - -c- -->two");
+    Ok(())
+}
+
+pub fn generic_simple_content<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let result = test_rig(
+        "<Test><a id='a'/><a id='b'/><a id='c'/></Test>",
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='child::Test'><out><a><xsl:attribute name='ids' select='child::a/attribute::id'/></a><b><xsl:attribute name='n' select='(1, 2, 3.5)'/></b><c><xsl:attribute name='n' select='(1, 2, 3.5)' separator=','/></c><d><xsl:attribute name='n' separator=', '><xsl:sequence select='(1, 2)'/></xsl:attribute></d><xsl:comment select='(1, 2)'/><xsl:processing-instruction name='pi' select='child::a/attribute::id'/></out></xsl:template>
+</xsl:stylesheet>"#,
+        parse_from_str,
+        parse_from_str_with_ns,
+        make_doc,
+    )?;
+    // Selected items are joined with a single space, unless a separator is given
+    assert_eq!(
+        result.to_xml(),
+        "<out><a ids='a b c'></a><b n='1 2 3.5'></b><c n='1,2,3.5'></c><d n='1, 2'></d><!--1 2--><?pi a b c?></out>"
+    );
+    Ok(())
+}
+
+pub fn generic_pi<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let result = test_rig(
+        "<Test>one<Level1/>two<Level1/>three<Level1/>four<Level1/></Test>",
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='/'><xsl:apply-templates/></xsl:template>
+  <xsl:template match='child::Test'><xsl:apply-templates/></xsl:template>
+  <xsl:template match='child::Level1'><xsl:processing-instruction name='piL1'>this is a level 1 element</xsl:processing-instruction></xsl:template>
+  <xsl:template match='child::text()'><xsl:sequence select='.'/></xsl:template>
+</xsl:stylesheet>"#,
+        parse_from_str,
+        parse_from_str_with_ns,
+        make_doc,
+    )?;
+    if result.to_xml() == "one<?piL1 this is a level 1 element?>two<?piL1 this is a level 1 element?>three<?piL1 this is a level 1 element?>four<?piL1 this is a level 1 element?>" {
+        Ok(())
     } else {
         Err(Error::new(ErrorKind::Unknown,
                        format!("got result \"{}\", expected \"one<?piL1 this is a level 1 element?>two<?piL1 this is a level 1 element?>three<?piL1 this is a level 1 element?>four<?piL1 this is a level 1 element?>\"", result.to_string())))
     }
 }
 
+pub fn generic_pi_avt<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let style = |target: &str| {
+        format!(
+            "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='child::Test'><xsl:call-template name='mkpi'><xsl:with-param name='target' select=\"{}\"/></xsl:call-template></xsl:template>
+  <xsl:template name='mkpi'><xsl:param name='target'/><xsl:processing-instruction name='{{$target}}-pi'>content</xsl:processing-instruction></xsl:template>
+</xsl:stylesheet>",
+            target
+        )
+    };
+    let result = test_rig(
+        "<Test/>",
+        style("local-name()"),
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    assert_eq!(result.to_xml(), "<?Test-pi content?>");
+    // The computed name must be an NCName, but not "xml"
+    for t in ["'1st'", "'a:b'", "'x m'"] {
+        match test_rig(
+            "<Test/>",
+            style(t),
+            &parse_from_str,
+            &parse_from_str_with_ns,
+            &make_doc,
+        ) {
+            Ok(r) => panic!("invalid name was accepted, got \"{}\"", r.to_xml()),
+            Err(e) => assert_eq!(
+                e.code.map(|c| c.to_string()),
+                Some(String::from("XTDE0890"))
+            ),
+        }
+    }
+    let style_xml = "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='child::Test'><xsl:processing-instruction name='{\"XML\"}'/></xsl:template>
+</xsl:stylesheet>";
+    match test_rig(
+        "<Test/>",
+        style_xml,
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    ) {
+        Ok(r) => panic!("invalid name was accepted, got \"{}\"", r.to_xml()),
+        Err(e) => assert_eq!(
+            e.code.map(|c| c.to_string()),
+            Some(String::from("XTDE0890"))
+        ),
+    }
+    Ok(())
+}
+
 pub fn generic_current<N: Node, G, H, J>(
     parse_from_str: G,
     parse_from_str_with_ns: J,
@@ -553,6 +1068,65 @@ where
     }
 }
 
+pub fn generic_strip_space_key<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let transform = |src: &N| -> Result<String, Error> {
+        let (styledoc, stylens) = parse_from_str_with_ns(
+            r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:strip-space elements='*'/>
+  <xsl:key name='types' match='child::item' use='@type'/>
+  <xsl:template match='child::Test'>
+    <xsl:value-of select='count(child::node())'/>
+    <xsl:text>;</xsl:text>
+    <xsl:for-each select='key("types", "a")'>
+      <xsl:value-of select='count(preceding-sibling::node())'/>
+      <xsl:text>,</xsl:text>
+    </xsl:for-each>
+    <xsl:text>;</xsl:text>
+    <xsl:apply-templates select='child::node()'/>
+  </xsl:template>
+  <xsl:template match='child::item'><xsl:value-of select='position()'/></xsl:template>
+  <xsl:template match='child::text()'>t</xsl:template>
+</xsl:stylesheet>"#,
+        )?;
+        let mut stctxt = StaticContextBuilder::new()
+            .message(|_| Ok(()))
+            .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+            .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+            .build();
+        let mut ctxt = from_tree(styledoc, stylens, None)?;
+        ctxt.result_document(make_doc()?);
+        ctxt.source_document(&mut stctxt, src.clone())?;
+        Ok(ctxt.evaluate(&mut stctxt)?.to_string())
+    };
+    let indented = parse_from_str(
+        "<Test>
+  <item type='a'>one</item>
+  <item type='b'>two</item>
+  <item type='a'>three</item>
+</Test>",
+    )?;
+    let stripped = parse_from_str(
+        "<Test><item type='a'>one</item><item type='b'>two</item><item type='a'>three</item></Test>",
+    )?;
+    assert_eq!(transform(&indented)?, "3;0,2,;123");
+    assert_eq!(transform(&stripped)?, "3;0,2,;123");
+    // The whitespace is stripped from a copy, so the caller's document is unchanged
+    assert_eq!(
+        indented.first_child().map_or(0, |t| t.child_iter().count()),
+        7
+    );
+    Ok(())
+}
+
 // Although we have the source and stylesheet in files,
 // they are inlined here to avoid dependency on I/O libraries
 pub fn generic_issue_58<N: Node, G, H, J>(
@@ -614,6 +1188,166 @@ where
     }
 }
 
+// The prefixes of the stylesheet and the source are preserved,
+// even where the same prefix is bound to different namespaces.
+// Regenerating prefixes invents a new prefix for the colliding namespace instead.
+pub fn generic_preserve_prefixes<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let result = test_rig(
+        "<dat:doc xmlns:dat='urn:src'><dat:title>one</dat:title></dat:doc>",
+        r#"<xsl:stylesheet version="1.0"
+	xmlns:dat="urn:dat"
+	xmlns:int="urn:int"
+	xmlns:xsl="http://www.w3.org/1999/XSL/Transform">
+    <xsl:template match="/"><dat:pack><int:head><xsl:copy-of select="/*"/></int:head></dat:pack></xsl:template>
+</xsl:stylesheet>
+"#,
+        parse_from_str,
+        parse_from_str_with_ns,
+        make_doc,
+    )?;
+    assert_eq!(
+        result.to_xml(),
+        "<dat:pack xmlns:dat='urn:dat' xmlns:int='urn:int'><int:head><dat:doc xmlns:dat='urn:src'><dat:title>one</dat:title></dat:doc></int:head></dat:pack>"
+    );
+    let mut od = OutputDefinition::new();
+    od.set_regenerate_prefixes(true);
+    assert_eq!(
+        result.to_xml_with_options(&od),
+        "<dat:pack xmlns:dat='urn:dat' xmlns:int='urn:int'><int:head xmlns:ns0='urn:src'><ns0:doc><ns0:title>one</ns0:title></ns0:doc></int:head></dat:pack>"
+    );
+    Ok(())
+}
+
+pub fn generic_output_definition_builder<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let result = test_rig(
+        "<Test/>",
+        r#"<xsl:stylesheet xmlns:xsl="http://www.w3.org/1999/XSL/Transform">
+    <xsl:template match="/"><doc><a x="café">café</a><b/></doc></xsl:template>
+</xsl:stylesheet>
+"#,
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+
+    // The same result serialized with two definitions
+    let compact = OutputDefinitionBuilder::new().method("xml")?.build();
+    assert_eq!(
+        result.to_xml_with_options(&compact),
+        "<doc><a x='café'>café</a><b></b></doc>"
+    );
+    let indented = OutputDefinitionBuilder::new()
+        .method("xml")?
+        .indent(true)
+        .indent_string("\t")?
+        .character_map('é', "&#233;")
+        .build();
+    assert_eq!(
+        result.to_xml_with_options(&indented),
+        "<doc>\n\t<a x='caf&#233;'>caf&#233;</a>\n\t<b></b>\n</doc>"
+    );
+
+    // Invalid values are rejected
+    let code = |e: Error| e.code.map(|c| c.to_string());
+    assert_eq!(
+        OutputDefinitionBuilder::new()
+            .method("xhtml5")
+            .err()
+            .and_then(code),
+        Some(String::from("SEPM0016"))
+    );
+    assert_eq!(
+        OutputDefinitionBuilder::new()
+            .indent_string("--")
+            .err()
+            .and_then(code),
+        Some(String::from("SEPM0016"))
+    );
+    // The defaults are the same as for a new definition
+    assert_eq!(
+        OutputDefinitionBuilder::default().build(),
+        OutputDefinition::new()
+    );
+
+    // An xsl:output element gives the same definition as the builder
+    let (styledoc, _) = parse_from_str_with_ns(
+        r#"<xsl:stylesheet xmlns:xsl="http://www.w3.org/1999/XSL/Transform" xmlns:eg="urn:eg">
+    <xsl:output method="xml" indent="yes" omit-xml-declaration="no" doctype-system="doc.dtd" cdata-section-elements="eg:code script"/>
+</xsl:stylesheet>"#,
+    )?;
+    let output = styledoc
+        .child_iter()
+        .flat_map(|s| s.child_iter())
+        .find(|c| c.node_type() == NodeType::Element)
+        .expect("no xsl:output element");
+    assert_eq!(
+        output_definition(&output)?,
+        OutputDefinitionBuilder::new()
+            .method("xml")?
+            .indent(true)
+            .omit_xml_declaration(false)
+            .doctype_system("doc.dtd")
+            .cdata_section_element(QualifiedName::new(
+                Some(String::from("urn:eg")),
+                None,
+                "code"
+            ))
+            .cdata_section_element(QualifiedName::new(None, None, "script"))
+            .build()
+    );
+    Ok(())
+}
+
+// Nested elements in the same namespace are declared once, on the outermost element.
+pub fn generic_nested_namespaces<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let result = test_rig(
+        "<Test/>",
+        r#"<xsl:stylesheet version="1.0"
+	xmlns="urn:x"
+	xmlns:int="urn:int"
+	xmlns:xsl="http://www.w3.org/1999/XSL/Transform"
+	exclude-result-prefixes="int">
+    <xsl:template match="/"><a><int:b><c><int:d>text</int:d></c></int:b><xsl:element name="e" namespace="urn:e"><xsl:element name="f" namespace="urn:e"/></xsl:element></a></xsl:template>
+</xsl:stylesheet>
+"#,
+        parse_from_str,
+        parse_from_str_with_ns,
+        make_doc,
+    )?;
+    assert_eq!(
+        result.to_xml(),
+        "<a xmlns='urn:x' xmlns:int='urn:int'><int:b><c><int:d>text</int:d></c></int:b><e xmlns='urn:e'><f></f></e></a>"
+    );
+    Ok(())
+}
+
 pub fn generic_message_1<N: Node, G, H, J>(
     parse_from_str: G,
     parse_from_str_with_ns: J,
@@ -704,7 +1438,7 @@ where
         )),
     }
 }
-pub fn generic_callable_named_1<N: Node, G, H, J>(
+pub fn generic_message_term_earlier<N: Node, G, H, J>(
     parse_from_str: G,
     parse_from_str_with_ns: J,
     make_doc: H,
@@ -714,39 +1448,37 @@ where
     H: Fn() -> Result<N, Error>,
     J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
 {
-    let result = test_rig(
-        "<Test><one>blue</one><two>yellow</two><three>green</three><four>blue</four></Test>",
+    // Messages are delivered as they are emitted, so they are not lost when a later message terminates the transformation
+    let srcdoc = parse_from_str("<Test><Level1>one</Level1><Level1>two</Level1><Level2/></Test>")?;
+    let (styledoc, stylens) = parse_from_str_with_ns(
         r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
   <xsl:template match='/'><xsl:apply-templates/></xsl:template>
-  <xsl:template match='child::Test'>
-    <xsl:call-template name='my_template'>
-      <xsl:with-param name='my_param' select='count(child::*)'/>
-    </xsl:call-template>
-  </xsl:template>
-  <xsl:template name='my_template'>
-    <xsl:param name='my_param'>default value</xsl:param>
-    <xsl:text>There are </xsl:text>
-    <xsl:sequence select='$my_param'/>
-    <xsl:text> child elements</xsl:text>
-  </xsl:template>
+  <xsl:template match='child::Test'><xsl:apply-templates/></xsl:template>
+  <xsl:template match='child::Level1'><xsl:message>level 1 <xsl:value-of select='.'/></xsl:message></xsl:template>
+  <xsl:template match='child::Level2'><xsl:message terminate='yes'>level 2</xsl:message></xsl:template>
 </xsl:stylesheet>"#,
-        parse_from_str,
-        parse_from_str_with_ns,
-        make_doc,
     )?;
-    if result.to_string() == "There are 4 child elements" {
-        Ok(())
-    } else {
-        Err(Error::new(
-            ErrorKind::Unknown,
-            format!(
-                "got result \"{}\", expected \"There are 4 child elements\"",
-                result.to_string()
-            ),
-        ))
-    }
+    let mut msgs: Vec<String> = vec![];
+    let mut stctxt = StaticContextBuilder::new()
+        .message(|m| {
+            msgs.push(String::from(m));
+            Ok(())
+        })
+        .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .build();
+    let mut ctxt = from_tree(styledoc, stylens, None)?;
+    ctxt.context(vec![Item::Node(srcdoc.clone())], 0);
+    ctxt.result_document(make_doc()?);
+    let e = ctxt
+        .evaluate(&mut stctxt)
+        .expect_err("evaluation succeeded when it should have been terminated");
+    assert_eq!(e.kind, ErrorKind::Terminated);
+    drop(stctxt);
+    assert_eq!(msgs, vec!["level 1 one", "level 1 two", "level 2"]);
+    Ok(())
 }
-pub fn generic_callable_posn_1<N: Node, G, H, J>(
+pub fn generic_message_term_avt<N: Node, G, H, J>(
     parse_from_str: G,
     parse_from_str_with_ns: J,
     make_doc: H,
@@ -756,90 +1488,3561 @@ where
     H: Fn() -> Result<N, Error>,
     J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
 {
-    let result = test_rig(
-        "<Test><one>blue</one><two>yellow</two><three>green</three><four>blue</four></Test>",
-        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform' xmlns:eg='http://example.org/'>
+    // terminate is computed for each message, so only the "stop" element ends the transformation
+    let srcdoc = parse_from_str(
+        "<Test><Level1>go</Level1><Level1>stop</Level1><Level1>go again</Level1></Test>",
+    )?;
+    let (styledoc, stylens) = parse_from_str_with_ns(
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
   <xsl:template match='/'><xsl:apply-templates/></xsl:template>
-  <xsl:template match='child::Test'>
-    <xsl:sequence select='eg:my_func(count(child::*))'/>
-  </xsl:template>
-  <xsl:function name='eg:my_func'>
-    <xsl:param name='my_param'/>
-    <xsl:text>There are </xsl:text>
-    <xsl:sequence select='$my_param'/>
-    <xsl:text> child elements</xsl:text>
-  </xsl:function>
+  <xsl:template match='child::Test'><xsl:apply-templates/></xsl:template>
+  <xsl:template match='child::Level1'><xsl:message terminate="{if (. eq 'stop') then 'yes' else 'no'}"><xsl:value-of select='.'/></xsl:message></xsl:template>
 </xsl:stylesheet>"#,
-        parse_from_str,
-        parse_from_str_with_ns,
-        make_doc,
     )?;
-    if result.to_string() == "There are 4 child elements" {
-        Ok(())
-    } else {
-        Err(Error::new(
-            ErrorKind::Unknown,
-            format!(
-                "got result \"{}\", expected \"There are 4 child elements\"",
-                result.to_string()
-            ),
-        ))
-    }
-}
-
-pub fn generic_include<N: Node, G, H, J>(
-    parse_from_str: G,
-    parse_from_str_with_ns: J,
-    make_doc: H,
-) -> Result<(), Error>
-where
-    G: Fn(&str) -> Result<N, Error>,
-    H: Fn() -> Result<N, Error>,
-    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+    let mut msgs: Vec<String> = vec![];
+    let mut stctxt = StaticContextBuilder::new()
+        .message(|m| {
+            msgs.push(String::from(m));
+            Ok(())
+        })
+        .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .build();
+    let mut ctxt = from_tree(styledoc, stylens, None)?;
+    ctxt.context(vec![Item::Node(srcdoc.clone())], 0);
+    ctxt.result_document(make_doc()?);
+    let e = ctxt
+        .evaluate(&mut stctxt)
+        .expect_err("evaluation succeeded when it should have been terminated");
+    assert_eq!(e.kind, ErrorKind::Terminated);
+    drop(stctxt);
+    assert_eq!(msgs, vec!["go", "stop"]);
+    Ok(())
+}
+pub fn generic_message_element<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let (result, msgs) = test_msg_rig(
+        "<Test><Level1>one</Level1></Test>",
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='/'><xsl:apply-templates/></xsl:template>
+  <xsl:template match='child::Test'><xsl:apply-templates/></xsl:template>
+  <xsl:template match='child::Level1'><xsl:message>found <Found><xsl:sequence select='.'/></Found></xsl:message><L/></xsl:template>
+</xsl:stylesheet>"#,
+        parse_from_str,
+        parse_from_str_with_ns,
+        make_doc,
+    )?;
+    assert_eq!(result.to_xml(), "<L></L>");
+    assert_eq!(msgs, vec!["found <Found><Level1>one</Level1></Found>"]);
+    Ok(())
+}
+pub fn generic_trace<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let (result, msgs) = test_msg_rig(
+        "<Test><Level1>one</Level1><Level1>two</Level1></Test>",
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='/'><xsl:apply-templates select='child::Test/child::Level1'/></xsl:template>
+  <xsl:template match='child::Level1'><L><xsl:value-of select='trace(., "level 1")'/></L></xsl:template>
+</xsl:stylesheet>"#,
+        parse_from_str,
+        parse_from_str_with_ns,
+        make_doc,
+    )?;
+    assert_xml_equal(&result, "<L>one</L><L>two</L>");
+    assert_eq!(
+        msgs,
+        vec![
+            "level 1: [0] Element Level1 /Test/Level1[1] \"one\"",
+            "level 1: [0] Element Level1 /Test/Level1[2] \"two\""
+        ]
+    );
+    Ok(())
+}
+
+const FAULTY_STYLE: &str = r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='/'><xsl:apply-templates select='child::Test/child::Level1'/></xsl:template>
+  <xsl:template match='child::Level1'><L>first</L></xsl:template>
+  <xsl:template match='child::Level1'><L>text<xsl:attribute name='late'>x</xsl:attribute><xsl:processing-instruction name='pi'>a?>b</xsl:processing-instruction></L></xsl:template>
+</xsl:stylesheet>"#;
+
+pub fn generic_recoverable_errors<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let src = "<Test><Level1>one</Level1></Test>";
+    // Strict mode, the default, signals the first error: the templates conflict
+    match test_rig(
+        src,
+        FAULTY_STYLE,
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    ) {
+        Ok(_) => panic!("conflicting templates were not reported"),
+        Err(e) => assert_eq!(
+            e.code.map(|c| c.to_string()),
+            Some(String::from("XTDE0540"))
+        ),
+    }
+    assert!(test_recovery_rig(
+        src,
+        FAULTY_STYLE,
+        ErrorRecovery::Strict,
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )
+    .is_err());
+    // Recover mode produces output and reports each error
+    let (result, msgs) = test_recovery_rig(
+        src,
+        FAULTY_STYLE,
+        ErrorRecovery::Recover,
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    // The space inserted into the processing instruction is not an error
+    assert_eq!(result.to_xml(), "<L>text<?pi a? >b?></L>");
+    assert_eq!(msgs.len(), 2);
+    assert!(msgs
+        .iter()
+        .all(|m| m.starts_with("recoverable error at /Test/Level1: ")));
+    // Silent recovery produces the same output without reporting
+    let (result, msgs) = test_recovery_rig(
+        src,
+        FAULTY_STYLE,
+        ErrorRecovery::RecoverSilently,
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    assert_eq!(result.to_xml(), "<L>text<?pi a? >b?></L>");
+    assert!(msgs.is_empty());
+    Ok(())
+}
+
+pub fn generic_copy_namespaces<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let src = "<Test xmlns:a='urn:a' xmlns:unused='urn:unused'><a:Sub><a:Inner>text</a:Inner></a:Sub></Test>";
+    let style = |copy_ns: &str| {
+        format!(
+            r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='/'><Out><xsl:copy-of select='child::Test/child::*' {copy_ns}/></Out><Shallow><xsl:apply-templates select='child::Test/child::*'/></Shallow></xsl:template>
+  <xsl:template match='child::*'><xsl:copy {copy_ns}/></xsl:template>
+</xsl:stylesheet>"#
+        )
+    };
+    // The unused namespace is in scope for the copied subtree, so it is copied by default
+    let result = test_rig(
+        src,
+        style(""),
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    assert_eq!(
+        result.to_xml(),
+        "<Out xmlns:a='urn:a'><a:Sub xmlns:unused='urn:unused'><a:Inner>text</a:Inner></a:Sub></Out><Shallow xmlns:a='urn:a'><a:Sub xmlns:unused='urn:unused'></a:Sub></Shallow>"
+    );
+    // Only the namespaces that are used are declared
+    let result = test_rig(
+        src,
+        style("copy-namespaces='no'"),
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    assert_eq!(
+        result.to_xml(),
+        "<Out xmlns:a='urn:a'><a:Sub><a:Inner>text</a:Inner></a:Sub></Out><Shallow xmlns:a='urn:a'><a:Sub></a:Sub></Shallow>"
+    );
+    Ok(())
+}
+
+pub fn generic_inherit_namespaces<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let style = |inherit: &str| {
+        format!(
+            r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='/'><p:outer xmlns:p='urn:p' xmlns:q='urn:q' xsl:inherit-namespaces='{inherit}'><xsl:element name='inner'/></p:outer><xsl:element name='outer' inherit-namespaces='{inherit}'><r:inner xmlns:r='urn:r'/></xsl:element></xsl:template>
+</xsl:stylesheet>"#
+        )
+    };
+    // Serialize the children of the result elements on their own, so that only their namespaces are declared
+    let children = |inherit: &str| -> Result<Vec<String>, Error> {
+        let result = test_rig(
+            "<Test/>",
+            style(inherit),
+            &parse_from_str,
+            &parse_from_str_with_ns,
+            &make_doc,
+        )?;
+        Ok(result
+            .iter()
+            .filter_map(|i| match i {
+                Item::Node(n) => n.child_iter().next().map(|c| c.to_xml()),
+                _ => None,
+            })
+            .collect())
+    };
+    // By default children inherit the namespaces in scope for their parent
+    assert_eq!(
+        children("yes")?,
+        vec![
+            "<inner xmlns:p='urn:p' xmlns:q='urn:q'></inner>",
+            "<r:inner xmlns:r='urn:r'></r:inner>"
+        ]
+    );
+    assert_eq!(
+        children("no")?,
+        vec!["<inner></inner>", "<r:inner xmlns:r='urn:r'></r:inner>"]
+    );
+    Ok(())
+}
+
+pub fn generic_exclude_result_prefixes<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let style = |exclude: &str| {
+        format!(
+            r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform' xmlns:eg='http://example.org/' xmlns:keep='urn:keep' {exclude}>
+  <xsl:template match='/'><Out><Inner/></Out></xsl:template>
+</xsl:stylesheet>"#
+        )
+    };
+    let result = test_rig(
+        "<Test/>",
+        style(""),
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    assert_eq!(
+        result.to_xml(),
+        "<Out xmlns:eg='http://example.org/' xmlns:keep='urn:keep'><Inner></Inner></Out>"
+    );
+    let result = test_rig(
+        "<Test/>",
+        style("exclude-result-prefixes='eg'"),
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    assert_eq!(
+        result.to_xml(),
+        "<Out xmlns:keep='urn:keep'><Inner></Inner></Out>"
+    );
+    let result = test_rig(
+        "<Test/>",
+        style("exclude-result-prefixes='#all'"),
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    assert_xml_equal(&result, "<Out><Inner/></Out>");
+    // Excluding an undeclared prefix is a static error
+    assert!(test_rig(
+        "<Test/>",
+        style("exclude-result-prefixes='undeclared'"),
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )
+    .is_err());
+    Ok(())
+}
+
+pub fn generic_computed_names<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let style = |body: &str| {
+        format!(
+            r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform' xmlns:eg='http://example.org/'>
+  <xsl:template match='/'>{body}</xsl:template>
+</xsl:stylesheet>"#
+        )
+    };
+    // The prefix is only declared on xsl:stylesheet
+    let result = test_rig(
+        "<Test/>",
+        style("<xsl:element name='eg:{local-name(child::*)}'><xsl:attribute name='{\"eg:att\"}'>v</xsl:attribute></xsl:element>"),
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    match &result[0] {
+        Item::Node(n) => {
+            assert_eq!(n.name().get_nsuri_ref(), Some("http://example.org/"));
+            assert_eq!(n.name().get_localname(), "Test");
+            let a = n.attribute_iter().next().expect("no attribute");
+            assert_eq!(a.name().get_nsuri_ref(), Some("http://example.org/"));
+            assert_eq!(a.name().get_localname(), "att");
+        }
+        _ => panic!("result is not a node"),
+    }
+    // The namespace attribute overrides resolution of the prefix
+    let result = test_rig(
+        "<Test/>",
+        style("<xsl:element name='undeclared:{local-name(child::*)}' namespace='urn:{\"ns\"}'/>"),
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    match &result[0] {
+        Item::Node(n) => assert_eq!(n.name().get_nsuri_ref(), Some("urn:ns")),
+        _ => panic!("result is not a node"),
+    }
+    // Not a valid QName
+    let e = test_rig(
+        "<Test/>",
+        style("<xsl:element name='{\"1abc\"}'/>"),
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )
+    .expect_err("invalid name was accepted");
+    assert_eq!(e.code.map(|c| c.to_string()), Some("XTDE0820".to_string()));
+    // The prefix is not declared
+    let e = test_rig(
+        "<Test/>",
+        style("<Out><xsl:attribute name='undeclared:att'>v</xsl:attribute></Out>"),
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )
+    .expect_err("undeclared prefix was accepted");
+    assert_eq!(e.code.map(|c| c.to_string()), Some("XTDE0830".to_string()));
+    Ok(())
+}
+
+pub fn generic_value_of_version<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let style = |version: &str| {
+        format!(
+            r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform' version='{version}'>
+  <xsl:template match='child::Test'><xsl:value-of select='child::Level1'/></xsl:template>
+</xsl:stylesheet>"#
+        )
+    };
+    let src = "<Test><Level1>one</Level1><Level1>two</Level1><Level1>three</Level1></Test>";
+    // XSLT 1.0 uses the string value of the first node
+    let result = test_rig(
+        src,
+        style("1.0"),
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    assert_eq!(result.to_string(), "one");
+    // XSLT 2.0 and later use all of the nodes, separated by a space
+    let result = test_rig(
+        src,
+        style("2.0"),
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    assert_eq!(result.to_string(), "one two three");
+    Ok(())
+}
+
+pub fn generic_value_of_first_node<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    // The version may also be given by an instruction or a literal result element
+    let result = test_rig(
+        "<Test><Level1>one</Level1><Level2>two</Level2><Level3>three</Level3></Test>",
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform' version='2.0'>
+  <xsl:template match='child::Test'><xsl:value-of select='child::*' version='1.0'/>|<Out xsl:version='1.0'><xsl:value-of select='child::*'/></Out>|<xsl:value-of select='child::*'/>|<xsl:value-of select='child::Missing' version='1.0'/></xsl:template>
+</xsl:stylesheet>"#,
+        parse_from_str,
+        parse_from_str_with_ns,
+        make_doc,
+    )?;
+    assert_eq!(result.to_xml(), "one|<Out>one</Out>|one two three|");
+    Ok(())
+}
+
+pub fn generic_iterate_break<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    // A running total over a long sequence, stopping once the total exceeds a threshold
+    let result = test_rig(
+        "<Test/>",
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform' version='3.0'>
+  <xsl:template match='child::Test'>
+    <xsl:iterate select='1 to 10000'>
+      <xsl:param name='total' select='0'/>
+      <xsl:on-completion>never</xsl:on-completion>
+      <xsl:choose>
+        <xsl:when test='$total + . > 49000000'>
+          <xsl:break><xsl:value-of select='.'/>:<xsl:value-of select='$total + .'/></xsl:break>
+        </xsl:when>
+        <xsl:otherwise>
+          <xsl:next-iteration>
+            <xsl:with-param name='total' select='$total + .'/>
+          </xsl:next-iteration>
+        </xsl:otherwise>
+      </xsl:choose>
+    </xsl:iterate>
+  </xsl:template>
+</xsl:stylesheet>"#,
+        parse_from_str,
+        parse_from_str_with_ns,
+        make_doc,
+    )?;
+    assert_eq!(result.to_string(), "9899:49000050");
+    Ok(())
+}
+
+pub fn generic_iterate_completion<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    // Without a break, each item contributes to the result and the final parameter values are available on completion
+    let result = test_rig(
+        "<Test/>",
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform' version='3.0'>
+  <xsl:template match='child::Test'>
+    <xsl:iterate select='1 to 3'>
+      <xsl:param name='total' select='0'/>
+      <xsl:param name='count' select='0'/>
+      <xsl:on-completion><Total><xsl:value-of select='$count'/>:<xsl:value-of select='$total'/></Total></xsl:on-completion>
+      <Running><xsl:value-of select='$total + .'/></Running>
+      <xsl:next-iteration>
+        <xsl:with-param name='total' select='$total + .'/>
+        <xsl:with-param name='count' select='$count + 1'/>
+      </xsl:next-iteration>
+    </xsl:iterate>
+  </xsl:template>
+</xsl:stylesheet>"#,
+        parse_from_str,
+        parse_from_str_with_ns,
+        make_doc,
+    )?;
+    assert_xml_equal(
+        &result,
+        "<Running>1</Running><Running>3</Running><Running>6</Running><Total>3:6</Total>",
+    );
+    Ok(())
+}
+
+pub fn generic_callable_named_1<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let result = test_rig(
+        "<Test><one>blue</one><two>yellow</two><three>green</three><four>blue</four></Test>",
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='/'><xsl:apply-templates/></xsl:template>
+  <xsl:template match='child::Test'>
+    <xsl:call-template name='my_template'>
+      <xsl:with-param name='my_param' select='count(child::*)'/>
+    </xsl:call-template>
+  </xsl:template>
+  <xsl:template name='my_template'>
+    <xsl:param name='my_param'>default value</xsl:param>
+    <xsl:text>There are </xsl:text>
+    <xsl:sequence select='$my_param'/>
+    <xsl:text> child elements</xsl:text>
+  </xsl:template>
+</xsl:stylesheet>"#,
+        parse_from_str,
+        parse_from_str_with_ns,
+        make_doc,
+    )?;
+    if result.to_string() == "There are 4 child elements" {
+        Ok(())
+    } else {
+        Err(Error::new(
+            ErrorKind::Unknown,
+            format!(
+                "got result \"{}\", expected \"There are 4 child elements\"",
+                result.to_string()
+            ),
+        ))
+    }
+}
+pub fn generic_param_select_default<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    // The default of "strict" would raise an error if it were evaluated
+    let result = test_rig(
+        "<Test><one>blue</one><two>yellow</two><three>green</three><four>blue</four></Test>",
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform' xmlns:eg='http://example.org/'>
+  <xsl:template match='/'><xsl:apply-templates/></xsl:template>
+  <xsl:template match='child::Test'>
+    <xsl:call-template name='counted'/>
+    <xsl:text>;</xsl:text>
+    <xsl:call-template name='strict'>
+      <xsl:with-param name='p' select='"given"'/>
+    </xsl:call-template>
+  </xsl:template>
+  <xsl:template name='counted'>
+    <xsl:param name='p' select='count(child::*)'/>
+    <xsl:sequence select='$p'/>
+  </xsl:template>
+  <xsl:template name='strict'>
+    <xsl:param name='p' select='eg:undefined(.)'/>
+    <xsl:sequence select='$p'/>
+  </xsl:template>
+</xsl:stylesheet>"#,
+        parse_from_str,
+        parse_from_str_with_ns,
+        make_doc,
+    )?;
+    assert_eq!(result.to_string(), "4;given");
+    Ok(())
+}
+
+pub fn generic_tunnel_param_default<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let result = test_rig(
+        "<Test><deep/></Test>",
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='/'>
+    <xsl:apply-templates select='child::Test'>
+      <xsl:with-param name='colour' select='"red"' tunnel='yes'/>
+    </xsl:apply-templates>
+    <xsl:text>;</xsl:text>
+    <xsl:apply-templates select='child::Test'/>
+  </xsl:template>
+  <xsl:template match='child::Test'><xsl:call-template name='middle'/></xsl:template>
+  <xsl:template name='middle'><xsl:apply-templates select='descendant::deep'/></xsl:template>
+  <xsl:template match='child::deep'>
+    <xsl:param name='colour' select='"blue"' tunnel='yes'/>
+    <xsl:value-of select='$colour'/>
+  </xsl:template>
+</xsl:stylesheet>"#,
+        parse_from_str,
+        parse_from_str_with_ns,
+        make_doc,
+    )?;
+    assert_eq!(result.to_string(), "red;blue");
+    Ok(())
+}
+
+pub fn generic_param_required<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let run = |body: &str| {
+        test_rig(
+            "<Test><deep/></Test>",
+            format!(
+                r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform' xmlns:eg='http://example.org/'>
+  <xsl:template match='/'>{}</xsl:template>
+  <xsl:template name='named'>
+    <xsl:param name='p' required='yes'/>
+    <xsl:sequence select='$p'/>
+  </xsl:template>
+  <xsl:template match='child::deep'>
+    <xsl:param name='t' required='yes' tunnel='yes'/>
+    <xsl:sequence select='$t'/>
+  </xsl:template>
+  <xsl:function name='eg:f'>
+    <xsl:param name='a'/>
+    <xsl:param name='b'/>
+    <xsl:sequence select='$b'/>
+  </xsl:function>
+</xsl:stylesheet>"#,
+                body
+            ),
+            &parse_from_str,
+            &parse_from_str_with_ns,
+            &make_doc,
+        )
+    };
+    let code = |r: Result<Sequence<N>, Error>| {
+        r.expect_err("missing parameter was not detected")
+            .code
+            .map(|c| c.to_string())
+    };
+
+    // Required parameters that are supplied
+    assert_eq!(
+        run("<xsl:call-template name='named'><xsl:with-param name='p' select='1'/></xsl:call-template>")?
+            .to_string(),
+        "1"
+    );
+    assert_eq!(
+        run("<xsl:apply-templates select='descendant::deep'><xsl:with-param name='t' select='2' tunnel='yes'/></xsl:apply-templates>")?
+            .to_string(),
+        "2"
+    );
+    assert_eq!(run("<xsl:sequence select='eg:f(3, 4)'/>")?.to_string(), "4");
+
+    // Required parameters that are omitted
+    assert_eq!(
+        code(run("<xsl:call-template name='named'/>")),
+        Some(String::from("XTDE0700"))
+    );
+    assert_eq!(
+        code(run("<xsl:apply-templates select='descendant::deep'/>")),
+        Some(String::from("XTDE0700"))
+    );
+    assert_eq!(
+        code(run("<xsl:sequence select='eg:f(3)'/>")),
+        Some(String::from("XTDE0700"))
+    );
+    Ok(())
+}
+
+pub fn generic_param_default_invalid<N: Node, G, H, J>(
+    _parse_from_str: G,
+    parse_from_str_with_ns: J,
+    _make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let (styledoc, stylens) = parse_from_str_with_ns(
+        "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template name='both'>
+    <xsl:param name='p' select='1'>default value</xsl:param>
+  </xsl:template>
+</xsl:stylesheet>",
+    )?;
+    match from_tree(styledoc, stylens, None) {
+        Ok(_) => panic!("param with both select and content was not detected"),
+        Err(e) => assert_eq!(
+            e.code.map(|c| c.to_string()),
+            Some(String::from("XTSE0620"))
+        ),
+    }
+    let (styledoc, stylens) = parse_from_str_with_ns(
+        "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform' xmlns:eg='http://example.org/'>
+  <xsl:function name='eg:f'>
+    <xsl:param name='p' select='1'/>
+    <xsl:sequence select='$p'/>
+  </xsl:function>
+</xsl:stylesheet>",
+    )?;
+    match from_tree(styledoc, stylens, None) {
+        Ok(_) => panic!("function param with a default value was not detected"),
+        Err(e) => assert_eq!(
+            e.code.map(|c| c.to_string()),
+            Some(String::from("XTSE0760"))
+        ),
+    }
+    Ok(())
+}
+
+pub fn generic_callable_posn_1<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let result = test_rig(
+        "<Test><one>blue</one><two>yellow</two><three>green</three><four>blue</four></Test>",
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform' xmlns:eg='http://example.org/'>
+  <xsl:template match='/'><xsl:apply-templates/></xsl:template>
+  <xsl:template match='child::Test'>
+    <xsl:sequence select='eg:my_func(count(child::*))'/>
+  </xsl:template>
+  <xsl:function name='eg:my_func'>
+    <xsl:param name='my_param'/>
+    <xsl:text>There are </xsl:text>
+    <xsl:sequence select='$my_param'/>
+    <xsl:text> child elements</xsl:text>
+  </xsl:function>
+</xsl:stylesheet>"#,
+        parse_from_str,
+        parse_from_str_with_ns,
+        make_doc,
+    )?;
+    if result.to_string() == "There are 4 child elements" {
+        Ok(())
+    } else {
+        Err(Error::new(
+            ErrorKind::Unknown,
+            format!(
+                "got result \"{}\", expected \"There are 4 child elements\"",
+                result.to_string()
+            ),
+        ))
+    }
+}
+
+pub fn generic_call_function<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    // The stylesheet function from generic_callable_posn_1, called directly rather than from a template
+    let style = r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform' xmlns:eg='http://example.org/'>
+  <xsl:template match='/'><xsl:apply-templates/></xsl:template>
+  <xsl:template match='child::Test'>
+    <xsl:sequence select='eg:my_func(count(child::*))'/>
+  </xsl:template>
+  <xsl:function name='eg:my_func'>
+    <xsl:param name='my_param'/>
+    <xsl:text>There are </xsl:text>
+    <xsl:sequence select='$my_param'/>
+    <xsl:text> child elements</xsl:text>
+  </xsl:function>
+</xsl:stylesheet>"#;
+    let expected = test_rig(
+        "<Test><one>blue</one><two>yellow</two><three>green</three><four>blue</four></Test>",
+        style,
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+
+    let (styledoc, stylens) = parse_from_str_with_ns(style)?;
+    let mut ctxt = from_tree(styledoc, stylens, None)?;
+    ctxt.result_document(make_doc()?);
+    let mut stctxt = StaticContextBuilder::new()
+        .message(|_| Ok(()))
+        .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .build();
+    let my_func = QualifiedName::new(
+        Some(String::from("http://example.org/")),
+        None,
+        String::from("my_func"),
+    );
+    let result = ctxt.call_function(
+        &mut stctxt,
+        &my_func,
+        vec![vec![Item::Value(Rc::new(Value::from(4)))]],
+    )?;
+    assert_eq!(result.to_string(), expected.to_string());
+
+    // The number of arguments must match the function's parameters
+    let e = ctxt
+        .call_function(&mut stctxt, &my_func, vec![])
+        .expect_err("wrong number of arguments was not detected");
+    assert_eq!(e.code.map(|c| c.to_string()), Some("XPST0017".to_string()));
+    let e = ctxt
+        .call_function(
+            &mut stctxt,
+            &QualifiedName::new(
+                Some(String::from("http://example.org/")),
+                None,
+                String::from("missing"),
+            ),
+            vec![],
+        )
+        .expect_err("unknown function was not detected");
+    assert_eq!(e.code.map(|c| c.to_string()), Some("XPST0017".to_string()));
+    Ok(())
+}
+pub fn generic_function_as<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let run = |select: &str, function: &str| {
+        test_rig(
+            "<Test><one>blue</one><two>yellow</two><three>green</three><four>blue</four></Test>",
+            format!(
+                r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform' xmlns:xs='http://www.w3.org/2001/XMLSchema' xmlns:eg='http://example.org/'>
+  <xsl:template match='/'><xsl:apply-templates/></xsl:template>
+  <xsl:template match='child::Test'>
+    <xsl:sequence select='{}'/>
+  </xsl:template>
+  {}
+</xsl:stylesheet>"#,
+                select, function
+            ),
+            &parse_from_str,
+            &parse_from_str_with_ns,
+            &make_doc,
+        )
+    };
+
+    // A computed integer
+    let count = r#"<xsl:function name='eg:count' as='xs:integer'>
+    <xsl:param name='items'/>
+    <xsl:sequence select='count($items)'/>
+  </xsl:function>"#;
+    assert_eq!(run("eg:count(child::*)", count)?.to_string(), "4");
+    assert_eq!(
+        run("eg:count(child::*) instance of xs:integer", count)?.to_string(),
+        "true"
+    );
+
+    // A text node is atomized and cast to the declared type
+    let text = r#"<xsl:function name='eg:count' as='xs:integer'>
+    <xsl:param name='items'/>
+    <xsl:value-of select='count($items)'/>
+  </xsl:function>"#;
+    assert_eq!(
+        run("eg:count(child::*) instance of xs:integer", text)?.to_string(),
+        "true"
+    );
+
+    // The function from generic_callable_posn_1, with its result as a single string
+    let message = r#"<xsl:function name='eg:my_func' as='xs:string'>
+    <xsl:param name='my_param'/>
+    <xsl:value-of select='concat("There are ", $my_param, " child elements")'/>
+  </xsl:function>"#;
+    let result = run("eg:my_func(count(child::*))", message)?;
+    assert_eq!(result.len(), 1);
+    assert_eq!(result.to_string(), "There are 4 child elements");
+    assert_eq!(
+        run("eg:my_func(count(child::*)) instance of xs:string", message)?.to_string(),
+        "true"
+    );
+
+    // An integer is promoted to a double
+    let double = r#"<xsl:function name='eg:number' as='xs:double'>
+    <xsl:param name='n'/>
+    <xsl:sequence select='count($n)'/>
+  </xsl:function>"#;
+    assert_eq!(
+        run("eg:number(child::*) instance of xs:double", double)?.to_string(),
+        "true"
+    );
+
+    // Results that do not match the declared type
+    let code = |r: Result<Sequence<N>, Error>| {
+        r.expect_err("type mismatch was not detected")
+            .code
+            .map(|c| c.to_string())
+    };
+    assert_eq!(
+        code(run(
+            "eg:my_func(count(child::*))",
+            r#"<xsl:function name='eg:my_func' as='xs:string'>
+    <xsl:param name='my_param'/>
+    <xsl:text>There are </xsl:text>
+    <xsl:sequence select='$my_param'/>
+    <xsl:text> child elements</xsl:text>
+  </xsl:function>"#
+        )),
+        Some(String::from("XTTE0780"))
+    );
+    assert_eq!(
+        code(run(
+            "eg:four()",
+            r#"<xsl:function name='eg:four' as='xs:integer'>
+    <xsl:sequence select='"four"'/>
+  </xsl:function>"#
+        )),
+        Some(String::from("XTTE0780"))
+    );
+    assert_eq!(
+        code(run(
+            "eg:none()",
+            r#"<xsl:function name='eg:none' as='xs:integer+'/>"#
+        )),
+        Some(String::from("XTTE0780"))
+    );
+    Ok(())
+}
+pub fn generic_extension_function<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let run = |select: &str| {
+        let srcdoc = parse_from_str("<Test><n>21</n></Test>")?;
+        let (styledoc, stylens) = parse_from_str_with_ns(
+            format!(
+                r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform' xmlns:ext='http://example.org/ext'>
+  <xsl:template match='/'><xsl:sequence select='{}'/></xsl:template>
+</xsl:stylesheet>"#,
+                select
+            )
+            .as_str(),
+        )?;
+        let mut stctxt = StaticContextBuilder::new()
+            .message(|_| Ok(()))
+            .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+            .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+            .function(
+                QualifiedName::new(
+                    Some(String::from("http://example.org/ext")),
+                    None,
+                    String::from("double"),
+                ),
+                1,
+                Box::new(|args: &[Sequence<N>]| {
+                    Ok(vec![Item::Value(Rc::new(Value::from(
+                        args[0].to_int()? * 2,
+                    )))])
+                }),
+            )?
+            .build();
+        let mut ctxt = from_tree(styledoc, stylens, None)?;
+        ctxt.context(vec![Item::Node(srcdoc)], 0);
+        ctxt.result_document(make_doc()?);
+        ctxt.evaluate(&mut stctxt)
+    };
+
+    assert_eq!(run("ext:double(21)")?.to_string(), "42");
+    assert_eq!(run("ext:double(ext:double(5)) + 1")?.to_string(), "21");
+    assert_eq!(
+        run("ext:double(xs:integer(child::Test/child::n))")?.to_string(),
+        "42"
+    );
+    // The function is only registered with one parameter
+    assert_eq!(
+        run("ext:double(1, 2)")
+            .expect_err("wrong number of arguments was not detected")
+            .code
+            .map(|c| c.to_string()),
+        Some(String::from("XPST0017"))
+    );
+    // An extension function must be in a namespace, so that it cannot hide a builtin function
+    let e = StaticContextBuilder::<N, _, _, _>::new()
+        .message(|_| Ok(()))
+        .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .function(
+            QualifiedName::new(None, None, String::from("count")),
+            1,
+            Box::new(|_: &[Sequence<N>]| Ok(vec![])),
+        )
+        .err()
+        .expect("a function without a namespace was registered");
+    assert_eq!(
+        e.code.map(|c| c.to_string()),
+        Some(String::from("XTSE0740"))
+    );
+    Ok(())
+}
+
+pub fn generic_extension_element<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let run = |body: &str| {
+        let srcdoc = parse_from_str("<Test/>")?;
+        let (styledoc, stylens) = parse_from_str_with_ns(
+            format!(
+                r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform' xmlns:ext='http://example.org/ext' extension-element-prefixes='ext'>
+  <xsl:template match='/'><result>{}</result></xsl:template>
+</xsl:stylesheet>"#,
+                body
+            )
+            .as_str(),
+        )?;
+        let mut stctxt = StaticContextBuilder::new()
+            .message(|_| Ok(()))
+            .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+            .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+            .extension_element(
+                QualifiedName::new(
+                    Some(String::from("http://example.org/ext")),
+                    None,
+                    String::from("constant"),
+                ),
+                Box::new(|doc: &N, attributes: &[(QualifiedName, String)]| {
+                    let e = doc.new_element(QualifiedName::new(None, None, "constant"))?;
+                    for (a, v) in attributes {
+                        e.add_attribute(
+                            doc.new_attribute(a.clone(), Rc::new(Value::from(v.clone())))?,
+                        )?;
+                    }
+                    Ok(vec![Item::Node(e)])
+                }),
+            )
+            .build();
+        let mut ctxt = from_tree(styledoc, stylens, None)?;
+        ctxt.context(vec![Item::Node(srcdoc)], 0);
+        ctxt.result_document(make_doc()?);
+        ctxt.evaluate(&mut stctxt)
+    };
+
+    assert_eq!(
+        run("<ext:constant/>")?.to_xml(),
+        "<result><constant></constant></result>"
+    );
+    // Attributes are attribute value templates
+    assert_eq!(
+        run("<ext:constant value='{1 + 1}'/>")?.to_xml(),
+        "<result><constant value='2'></constant></result>"
+    );
+    // An extension instruction without a handler evaluates its fallback
+    assert_eq!(
+        run("<ext:other><xsl:fallback>no handler</xsl:fallback></ext:other>")?.to_xml(),
+        "<result>no handler</result>"
+    );
+    assert_eq!(
+        run("<ext:other/>")
+            .expect_err("missing extension instruction was not detected")
+            .code
+            .map(|c| c.to_string()),
+        Some(String::from("XTDE1450"))
+    );
+    Ok(())
+}
+
+pub fn generic_global_variables<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    // A global variable may refer to one that is declared later.
+    // The context item of a global variable is the document node of the source document.
+    let src = "<Test><one>blue</one><two>yellow</two><three>green</three><four>blue</four></Test>";
+    let style = r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:variable name='a' select='$b + 1'/>
+  <xsl:variable name='b' select='count(child::*/child::*)'/>
+  <xsl:variable name='top' select='local-name(./child::*)'/>
+  <xsl:param name='p' select='"default"'/>
+  <xsl:template match='/'>
+    <xsl:value-of select='$a'/>
+    <xsl:text>;</xsl:text>
+    <xsl:value-of select='$top'/>
+    <xsl:text>;</xsl:text>
+    <xsl:value-of select='$p'/>
+  </xsl:template>
+</xsl:stylesheet>"#;
+    let result = test_rig(
+        src,
+        style,
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    assert_eq!(result.to_string(), "5;Test;default");
+
+    // A value supplied for a global parameter replaces the default
+    let srcdoc = parse_from_str(src)?;
+    let (styledoc, stylens) = parse_from_str_with_ns(style)?;
+    let mut stctxt = StaticContextBuilder::new()
+        .message(|_| Ok(()))
+        .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .build();
+    let mut ctxt = from_tree(styledoc, stylens, None)?;
+    ctxt.context(vec![Item::Node(srcdoc)], 0);
+    ctxt.result_document(make_doc()?);
+    ctxt.global_parameter("p", vec![Item::Value(Rc::new(Value::from("supplied")))]);
+    let result = ctxt.evaluate(&mut stctxt)?;
+    assert_eq!(result.to_string(), "5;Test;supplied");
+    Ok(())
+}
+
+pub fn generic_global_circular<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let e = test_rig(
+        "<Test/>",
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:variable name='a' select='$b'/>
+  <xsl:variable name='b' select='$a'/>
+  <xsl:template match='/'><xsl:value-of select='$a'/></xsl:template>
+</xsl:stylesheet>"#,
+        parse_from_str,
+        parse_from_str_with_ns,
+        make_doc,
+    )
+    .expect_err("circular global variables were not detected");
+    assert_eq!(e.code.map(|c| c.to_string()), Some("XTDE0640".to_string()));
+    Ok(())
+}
+
+pub fn generic_global_duplicate<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let e = test_rig(
+        "<Test/>",
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:variable name='a' select='1'/>
+  <xsl:variable name='a' select='2'/>
+  <xsl:template match='/'><xsl:value-of select='$a'/></xsl:template>
+</xsl:stylesheet>"#,
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )
+    .expect_err("duplicate global variables were not detected");
+    assert_eq!(e.code.map(|c| c.to_string()), Some("XTSE0630".to_string()));
+    let e = test_rig(
+        "<Test/>",
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:param name='a' select='1'/>
+  <xsl:variable name='a' select='2'/>
+  <xsl:template match='/'><xsl:value-of select='$a'/></xsl:template>
+</xsl:stylesheet>"#,
+        parse_from_str,
+        parse_from_str_with_ns,
+        make_doc,
+    )
+    .expect_err("global parameter and variable with the same name were not detected");
+    assert_eq!(e.code.map(|c| c.to_string()), Some("XTSE0630".to_string()));
+    Ok(())
+}
+
+pub fn generic_template_match_or_name<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let e = test_rig(
+        "<Test/>",
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template>neither match nor name</xsl:template>
+</xsl:stylesheet>"#,
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )
+    .expect_err("template without match or name was not detected");
+    assert_eq!(e.code.map(|c| c.to_string()), Some("XTSE0500".to_string()));
+    assert_eq!(
+        e.message,
+        "xsl:template must have a match attribute, a name attribute, or both"
+    );
+    // A named template is not invoked by xsl:apply-templates, so it cannot have a mode
+    let e = test_rig(
+        "<Test/>",
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template name='named' mode='special'>named</xsl:template>
+</xsl:stylesheet>"#,
+        parse_from_str,
+        parse_from_str_with_ns,
+        make_doc,
+    )
+    .expect_err("named template with a mode was not detected");
+    assert_eq!(e.code.map(|c| c.to_string()), Some("XTSE0500".to_string()));
+    Ok(())
+}
+
+pub fn generic_unknown_attributes<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let run = |body: &str| {
+        test_rig(
+            "<Test>found</Test>",
+            format!(
+                r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform' xmlns:eg='http://example.org/'>
+  <xsl:template match='/'>{}</xsl:template>
+</xsl:stylesheet>"#,
+                body
+            ),
+            &parse_from_str,
+            &parse_from_str_with_ns,
+            &make_doc,
+        )
+    };
+
+    // A misspelt attribute is reported, with the attribute it was probably meant to be
+    let e = run("<xsl:value-of slect='child::Test'/>")
+        .expect_err("misspelt attribute was not detected");
+    assert_eq!(e.code.map(|c| c.to_string()), Some("XTSE0090".to_string()));
+    assert_eq!(
+        e.message,
+        "attribute \"slect\" is not allowed on xsl:value-of; did you mean \"select\"?"
+    );
+
+    // xsl:otherwise is only allowed in xsl:choose
+    let e = run("<xsl:if test='true()'><xsl:otherwise>no</xsl:otherwise></xsl:if>")
+        .expect_err("misplaced xsl:otherwise was not detected");
+    assert_eq!(e.code.map(|c| c.to_string()), Some("XTSE0010".to_string()));
+    assert_eq!(e.message, "xsl:otherwise must be a child of xsl:choose");
+
+    // An attribute in another namespace is an extension attribute, which is ignored
+    assert_eq!(
+        run("<xsl:value-of select='child::Test' eg:slect='ignored'/>")?.to_string(),
+        "found"
+    );
+    Ok(())
+}
+
+pub fn generic_global_context_item<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let style = r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform' xmlns:eg='http://example.org/'>
+  <xsl:variable name='doc' select='.'/>
+  <xsl:variable name='pos' select='position()'/>
+  <xsl:template match='/'><xsl:sequence select='eg:top()'/></xsl:template>
+  <xsl:template match='child::Test'><xsl:sequence select='$pos'/></xsl:template>
+  <xsl:function name='eg:top'>
+    <xsl:sequence select='local-name($doc/child::*)'/>
+  </xsl:function>
+</xsl:stylesheet>"#;
+
+    // With a source document, the global context item is its document node
+    let result = test_rig(
+        "<Test/>",
+        style,
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    assert_eq!(result.to_string(), "Test");
+
+    // The context position is absent in a global variable
+    let srcdoc = parse_from_str("<Test/>")?;
+    let (styledoc, stylens) = parse_from_str_with_ns(style)?;
+    let mut stctxt = StaticContextBuilder::new()
+        .message(|_| Ok(()))
+        .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .build();
+    let mut ctxt = from_tree(styledoc, stylens, None)?;
+    ctxt.context(vec![Item::Node(srcdoc.child_iter().next().unwrap())], 0);
+    ctxt.result_document(make_doc()?);
+    let e = ctxt
+        .evaluate(&mut stctxt)
+        .expect_err("context position in a global variable was not detected");
+    assert_eq!(e.code.map(|c| c.to_string()), Some("XPDY0002".to_string()));
+
+    // Without a source document, the global context item is absent
+    let (styledoc, stylens) = parse_from_str_with_ns(style)?;
+    let mut ctxt = from_tree(styledoc, stylens, None)?;
+    ctxt.result_document(make_doc()?);
+    let e = ctxt
+        .call_function(
+            &mut stctxt,
+            &QualifiedName::new(
+                Some(String::from("http://example.org/")),
+                None,
+                String::from("top"),
+            ),
+            vec![],
+        )
+        .expect_err("absent context item was not detected");
+    assert_eq!(e.code.map(|c| c.to_string()), Some("XPDY0002".to_string()));
+    Ok(())
+}
+
+pub fn generic_include<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let srcdoc =
+        parse_from_str("<Test>one<Level1/>two<Level2/>three<Level3/>four<Level4/></Test>")?;
+    let (styledoc, stylens) = parse_from_str_with_ns(
+        "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:include href='included.xsl'/>
+  <xsl:template match='child::Test'><xsl:apply-templates/></xsl:template>
+  <xsl:template match='child::Level1'>found Level1 element</xsl:template>
+  <xsl:template match='child::text()'><xsl:sequence select='.'/></xsl:template>
+</xsl:stylesheet>",
+    )?;
+    let pwd = std::env::current_dir().expect("unable to get current directory");
+    let pwds = pwd
+        .into_os_string()
+        .into_string()
+        .expect("unable to convert pwd");
+    let mut stctxt = StaticContextBuilder::new()
+        .message(|_| Ok(()))
+        .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .build();
+    let mut ctxt = from_document(
+        styledoc,
+        stylens,
+        Some(
+            Url::parse(format!("file://{}/tests/xsl/including.xsl", pwds.as_str()).as_str())
+                .expect("unable to parse URL"),
+        ),
+        |s| parse_from_str(s),
+        |_| Ok(String::new()),
+    )?;
+    ctxt.context(vec![Item::Node(srcdoc.clone())], 0);
+    ctxt.result_document(make_doc()?);
+    let result = ctxt.evaluate(&mut stctxt)?;
+    if result.to_string()
+        == "onefound Level1 elementtwofound Level2 elementthreefound Level3 elementfour"
+    {
+        Ok(())
+    } else {
+        Err(Error::new(ErrorKind::Unknown, format!("got result \"{}\", expected \"onefound Level1 elementtwofound Level2 elementthreefound Level3 elementfour\"", result.to_string())))
+    }
+}
+
+pub fn generic_include_cycle<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    _make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    // a.xsl includes b.xsl, which includes a.xsl by a different relative path
+    let modules = HashMap::from([(
+        "file:///xsl/b.xsl",
+        "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:include href='../xsl/./a.xsl'/>
+</xsl:stylesheet>",
+    )]);
+    let (styledoc, stylens) = parse_from_str_with_ns(
+        "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:include href='b.xsl'/>
+  <xsl:template match='/'>a</xsl:template>
+</xsl:stylesheet>",
+    )?;
+    match from_document(
+        styledoc,
+        stylens,
+        Some(Url::parse("file:///xsl/a.xsl").expect("unable to parse URL")),
+        |s| parse_from_str(s),
+        |u| {
+            modules.get(u.as_str()).map_or(
+                Err(Error::new(
+                    ErrorKind::Unknown,
+                    format!("no module \"{}\"", u),
+                )),
+                |m| Ok(m.to_string()),
+            )
+        },
+    ) {
+        Ok(_) => panic!("include cycle was not detected"),
+        Err(e) => {
+            assert_eq!(e.code.map(|c| c.to_string()), Some("XTSE0180".to_string()));
+            assert!(e
+                .message
+                .ends_with("file:///xsl/a.xsl -> file:///xsl/b.xsl -> file:///xsl/a.xsl"));
+            Ok(())
+        }
+    }
+}
+
+pub fn generic_include_diamond<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    // Both included modules include the shared module, using different relative paths
+    let modules = HashMap::from([
+        (
+            "file:///xsl/left.xsl",
+            "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:include href='shared.xsl'/>
+  <xsl:template match='child::Left'>left</xsl:template>
+</xsl:stylesheet>",
+        ),
+        (
+            "file:///xsl/sub/right.xsl",
+            "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:include href='../shared.xsl'/>
+  <xsl:template match='child::Right'>right</xsl:template>
+</xsl:stylesheet>",
+        ),
+        (
+            "file:///xsl/shared.xsl",
+            "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='child::Shared'>shared</xsl:template>
+</xsl:stylesheet>",
+        ),
+    ]);
+    let srcdoc = parse_from_str("<Test><Left/><Shared/><Right/></Test>")?;
+    let (styledoc, stylens) = parse_from_str_with_ns(
+        "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:include href='left.xsl'/>
+  <xsl:include href='sub/right.xsl'/>
+  <xsl:template match='child::Test'><xsl:apply-templates/></xsl:template>
+</xsl:stylesheet>",
+    )?;
+    let mut stctxt = StaticContextBuilder::new()
+        .message(|_| Ok(()))
+        .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .build();
+    let mut ctxt = from_document(
+        styledoc,
+        stylens,
+        Some(Url::parse("file:///xsl/main.xsl").expect("unable to parse URL")),
+        |s| parse_from_str(s),
+        |u| {
+            modules.get(u.as_str()).map_or(
+                Err(Error::new(
+                    ErrorKind::Unknown,
+                    format!("no module \"{}\"", u),
+                )),
+                |m| Ok(m.to_string()),
+            )
+        },
+    )?;
+    // The shared module's template is only defined once, just as the template in the left module
+    let test = srcdoc.first_child().expect("unable to find Test element");
+    let left = test
+        .child_iter()
+        .next()
+        .expect("unable to find Left element");
+    let shared = test
+        .child_iter()
+        .nth(1)
+        .expect("unable to find Shared element");
+    assert_eq!(
+        ctxt.find_templates(&mut stctxt, &Item::Node(shared), &None)?
+            .len(),
+        ctxt.find_templates(&mut stctxt, &Item::Node(left), &None)?
+            .len()
+    );
+    ctxt.context(vec![Item::Node(srcdoc.clone())], 0);
+    ctxt.result_document(make_doc()?);
+    let result = ctxt.evaluate(&mut stctxt)?;
+    assert_eq!(result.to_string(), "leftsharedright");
+    Ok(())
+}
+
+pub fn generic_result_document<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let (result, docs) = test_result_document_rig(
+        "<Test/>",
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform' version='3.0'>
+  <xsl:output name='compact' indent='no'/>
+  <xsl:output name='pretty' indent='yes' method='xml' encoding='UTF-8'/>
+  <xsl:template match='child::Test'>
+    <xsl:result-document href='compact.xml' format='compact'><Out><Item/></Out></xsl:result-document>
+    <xsl:result-document href='{name()}.xml' format='{concat("pre", "tty")}'><Out><Item/></Out></xsl:result-document>
+    <xsl:result-document href='override.xml' format='pretty' indent='no'><Out><Item/></Out></xsl:result-document>
+    <xsl:text>primary</xsl:text>
+  </xsl:template>
+</xsl:stylesheet>"#,
+        parse_from_str,
+        parse_from_str_with_ns,
+        make_doc,
+    )?;
+    assert_eq!(result.to_string(), "primary");
+    assert_eq!(docs.len(), 3);
+    assert_eq!(docs[0].0, "compact.xml");
+    assert_eq!(docs[0].1, "<Out><Item></Item></Out>");
+    assert!(!docs[0].2.get_indent());
+    assert_eq!(docs[1].0, "Test.xml");
+    assert_eq!(docs[1].1, "<Out>\n  <Item></Item>\n</Out>");
+    assert!(docs[1].2.get_indent());
+    assert_eq!(docs[1].2.get_method(), Some(String::from("xml")));
+    assert_eq!(docs[1].2.get_encoding(), Some(String::from("UTF-8")));
+    // Attributes on xsl:result-document override the named output definition
+    assert_eq!(docs[2].0, "override.xml");
+    assert_eq!(docs[2].1, "<Out><Item></Item></Out>");
+    assert!(!docs[2].2.get_indent());
+    assert_eq!(docs[2].2.get_method(), Some(String::from("xml")));
+    Ok(())
+}
+
+pub fn generic_result_document_unknown_format<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    match test_result_document_rig(
+        "<Test/>",
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform' version='3.0'>
+  <xsl:output name='compact' indent='no'/>
+  <xsl:template match='child::Test'>
+    <xsl:result-document href='out.xml' format='missing'><Out/></xsl:result-document>
+  </xsl:template>
+</xsl:stylesheet>"#,
+        parse_from_str,
+        parse_from_str_with_ns,
+        make_doc,
+    ) {
+        Ok(_) => panic!("unknown output definition was not detected"),
+        Err(e) => {
+            assert_eq!(e.code.map(|c| c.to_string()), Some("XTDE1460".to_string()));
+            Ok(())
+        }
+    }
+}
+
+pub fn generic_from_tree<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let srcdoc = parse_from_str("<Test><Level1>one</Level1></Test>")?;
+    let (styledoc, stylens) = parse_from_str_with_ns(
+        "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='child::Level1'>found <xsl:value-of select='.'/></xsl:template>
+</xsl:stylesheet>",
+    )?;
+    let mut stctxt = StaticContextBuilder::new()
+        .message(|_| Ok(()))
+        .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .build();
+    let mut ctxt = from_tree(styledoc, stylens, None)?;
+    ctxt.context(vec![Item::Node(srcdoc.clone())], 0);
+    ctxt.result_document(make_doc()?);
+    let result = ctxt.evaluate(&mut stctxt)?;
+    assert_eq!(result.to_string(), "found one");
+
+    // Without closures, other modules cannot be included
+    let (styledoc, stylens) = parse_from_str_with_ns(
+        "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:include href='included.xsl'/>
+</xsl:stylesheet>",
+    )?;
+    assert!(from_tree(
+        styledoc,
+        stylens,
+        Some(Url::parse("file:///xsl/including.xsl").expect("unable to parse URL"))
+    )
+    .is_err());
+    Ok(())
+}
+
+pub fn generic_document_1<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let srcdoc = parse_from_str("<Test><internal>on the inside</internal></Test>")?;
+    let (styledoc, stylens) = parse_from_str_with_ns(
+        r##"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='child::Test'><xsl:apply-templates/>|<xsl:apply-templates select='document("urn::test.org/test")'/></xsl:template>
+  <xsl:template match='child::internal'>found internal element</xsl:template>
+  <xsl:template match='child::external'>found external element</xsl:template>
+  <xsl:template match='child::text()'><xsl:sequence select='.'/></xsl:template>
+</xsl:stylesheet>"##,
+    )?;
+    let mut stctxt = StaticContextBuilder::new()
+        .message(|_| Ok(()))
+        .fetcher(|_url| {
+            Ok(String::from(
+                "<Outside><external>from outside</external></Outside>",
+            ))
+        })
+        .parser(|s| parse_from_str(s))
+        .build();
+    let mut ctxt = from_tree(styledoc, stylens, None)?;
+    ctxt.context(vec![Item::Node(srcdoc.clone())], 0);
+    ctxt.result_document(make_doc()?);
+    let result = ctxt.evaluate(&mut stctxt)?;
+    if result.to_string() == "found internal element|found external element" {
+        Ok(())
+    } else {
+        Err(Error::new(ErrorKind::Unknown, format!("got result \"{}\", expected \"onefound Level1 elementtwofound Level2 elementthreefound Level3 elementfour\"", result.to_string())))
+    }
+}
+
+pub fn generic_document_fragment<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let srcdoc = parse_from_str("<Test/>")?;
+    let (styledoc, stylens) = parse_from_str_with_ns(
+        r##"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='child::Test'>
+    <xsl:value-of select='document("http://example.org/doc.xml#second")'/>
+    <xsl:text>;</xsl:text>
+    <xsl:value-of select='local-name(document("http://example.org/doc.xml#second"))'/>
+    <xsl:text>;</xsl:text>
+    <xsl:value-of select='count(document("http://example.org/doc.xml#missing"))'/>
+    <xsl:text>;</xsl:text>
+    <xsl:value-of select='count(document("http://example.org/doc.xml")/child::Outside/child::*)'/>
+  </xsl:template>
+</xsl:stylesheet>"##,
+    )?;
+    let mut stctxt = StaticContextBuilder::new()
+        .message(|_| Ok(()))
+        .fetcher(|url: &Url| {
+            // The fragment identifier is not part of the resource that is fetched
+            assert_eq!(url.as_str(), "http://example.org/doc.xml");
+            Ok(String::from(
+                "<Outside><one xml:id='first'>one</one><two xml:id='second'>two</two></Outside>",
+            ))
+        })
+        .parser(|s| parse_from_str(s))
+        .build();
+    let mut ctxt = from_tree(styledoc, stylens, None)?;
+    ctxt.context(vec![Item::Node(srcdoc.clone())], 0);
+    ctxt.result_document(make_doc()?);
+    let result = ctxt.evaluate(&mut stctxt)?;
+    assert_eq!(result.to_string(), "two;two;0;2");
+    Ok(())
+}
+
+pub fn generic_source_document<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let srcdoc = parse_from_str("<Test>list</Test>")?;
+    let messages = RefCell::new(vec![]);
+    let mut stctxt = StaticContextBuilder::new()
+        .message(|m| {
+            messages.borrow_mut().push(m.to_string());
+            Ok(())
+        })
+        .fetcher(|url: &Url| {
+            assert_eq!(url.as_str(), "urn:example:list");
+            Ok(String::from(
+                "<list><item>one</item><item>two</item><item>three</item></list>",
+            ))
+        })
+        .parser(|s| parse_from_str(s))
+        .build();
+    let mut results = vec![];
+    for streamable in ["no", "yes"] {
+        let (styledoc, stylens) = parse_from_str_with_ns(
+            format!(
+                r##"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='child::Test'>
+    <xsl:source-document href='urn:example:{{.}}' streamable='{}'>
+      <xsl:value-of select='count(child::list/child::item)'/>
+      <xsl:apply-templates select='child::list/child::item'/>
+    </xsl:source-document>
+  </xsl:template>
+  <xsl:template match='child::item'>;<xsl:value-of select='.'/></xsl:template>
+</xsl:stylesheet>"##,
+                streamable
+            )
+            .as_str(),
+        )?;
+        let mut ctxt = from_tree(styledoc, stylens, None)?;
+        ctxt.context(vec![Item::Node(srcdoc.clone())], 0);
+        ctxt.result_document(make_doc()?);
+        results.push(ctxt.evaluate(&mut stctxt)?.to_string());
+    }
+    assert_eq!(results[0], "3;one;two;three");
+    assert_eq!(results[0], results[1]);
+    // The content counts the items, so the streamed document falls back to full materialization
+    assert_eq!(messages.borrow().len(), 1);
+    assert!(messages.borrow()[0].contains("urn:example:list"));
+
+    // The href attribute is required
+    let (styledoc, stylens) = parse_from_str_with_ns(
+        r##"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='/'><xsl:source-document/></xsl:template>
+</xsl:stylesheet>"##,
+    )?;
+    match from_tree(styledoc, stylens, None) {
+        Ok(_) => panic!("xsl:source-document without href was not detected"),
+        Err(e) => assert_eq!(e.code.map(|c| c.to_string()), Some("XTSE0010".to_string())),
+    }
+    Ok(())
+}
+
+pub fn generic_source_document_burst<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let srcdoc = parse_from_str("<Test>big</Test>")?;
+    let mut big = String::from("<!-- generated --><list xmlns:eg='urn:example' title='big'>");
+    let mut expected = String::from("items:");
+    for i in 1..=1000 {
+        big.push_str(
+            format!(
+                "<item n='{}'><name>item {}</name><value>{}</value></item><eg:note><![CDATA[<item/>]]></eg:note>",
+                i,
+                i,
+                i * 2
+            )
+            .as_str(),
+        );
+        expected.push_str(format!("{},", i * 2).as_str())
+    }
+    big.push_str("</list>");
+    expected.push_str("|1000 in big");
+    let messages = RefCell::new(vec![]);
+    // The number of nodes in each document that is parsed
+    let sizes = RefCell::new(vec![]);
+    let mut stctxt = StaticContextBuilder::new()
+        .message(|m| {
+            messages.borrow_mut().push(m.to_string());
+            Ok(())
+        })
+        .fetcher(|_: &Url| Ok(big.clone()))
+        .parser(|s| {
+            let d = parse_from_str(s)?;
+            sizes.borrow_mut().push(d.descend_iter().count());
+            Ok(d)
+        })
+        .build();
+    let mut results = vec![];
+    let mut peaks = vec![];
+    for streamable in ["no", "yes"] {
+        let (styledoc, stylens) = parse_from_str_with_ns(
+            format!(
+                r##"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='child::Test'>
+    <xsl:source-document href='urn:example:{{.}}' streamable='{}'>
+      <xsl:text>items:</xsl:text>
+      <xsl:apply-templates select='child::list/child::item'/>
+      <xsl:for-each select='child::list/child::item'>
+        <xsl:if test='position() = last()'>|<xsl:value-of select='position()'/> in <xsl:value-of select='../@title'/></xsl:if>
+      </xsl:for-each>
+    </xsl:source-document>
+  </xsl:template>
+  <xsl:template match='child::item'><xsl:value-of select='child::value'/>,</xsl:template>
+</xsl:stylesheet>"##,
+                streamable
+            )
+            .as_str(),
+        )?;
+        let mut ctxt = from_tree(styledoc, stylens, None)?;
+        ctxt.context(vec![Item::Node(srcdoc.clone())], 0);
+        ctxt.result_document(make_doc()?);
+        sizes.borrow_mut().clear();
+        results.push(ctxt.evaluate(&mut stctxt)?.to_string());
+        peaks.push(sizes.borrow().iter().max().copied().unwrap_or(0));
+    }
+    assert_eq!(results[0], expected);
+    assert_eq!(results[0], results[1]);
+    // Without streaming, the whole document is materialized at once
+    assert!(peaks[0] > 5000);
+    // In burst mode, each item is materialized on its own, once for each instruction
+    assert!(peaks[1] <= 10);
+    assert_eq!(sizes.borrow().len(), 2000);
+    assert!(messages.borrow().is_empty());
+    Ok(())
+}
+
+pub fn generic_parse_xml<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let srcdoc = parse_from_str("<Test/>")?;
+    let (styledoc, stylens) = parse_from_str_with_ns(
+        r##"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='child::Test'>
+    <xsl:value-of select='local-name(parse-xml("&lt;x>&lt;y/>&lt;/x>")/child::x/child::*)'/>
+    <xsl:text>;</xsl:text>
+    <xsl:value-of select='count(parse-xml(()))'/>
+  </xsl:template>
+</xsl:stylesheet>"##,
+    )?;
+    let mut stctxt = StaticContextBuilder::new()
+        .message(|_| Ok(()))
+        .fetcher(|_url| Ok(String::new()))
+        .parser(|s| parse_from_str(s))
+        .build();
+    let mut ctxt = from_tree(styledoc, stylens, None)?;
+    ctxt.context(vec![Item::Node(srcdoc.clone())], 0);
+    ctxt.result_document(make_doc()?);
+    let result = ctxt.evaluate(&mut stctxt)?;
+    assert_eq!(result.to_string(), "y;0");
+
+    // A string with two top-level elements is not a well-formed document
+    let (styledoc, stylens) = parse_from_str_with_ns(
+        r##"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='child::Test'>
+    <xsl:sequence select='parse-xml("&lt;x/>&lt;y/>")'/>
+  </xsl:template>
+</xsl:stylesheet>"##,
+    )?;
+    let mut ctxt = from_tree(styledoc, stylens, None)?;
+    ctxt.context(vec![Item::Node(srcdoc)], 0);
+    ctxt.result_document(make_doc()?);
+    let e = ctxt
+        .evaluate(&mut stctxt)
+        .expect_err("two top-level elements were not detected");
+    assert_eq!(e.code.map(|c| c.to_string()), Some("FODC0006".to_string()));
+    Ok(())
+}
+
+pub fn generic_serialize<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let result = test_rig(
+        "<Test/>",
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform' xmlns:output='http://www.w3.org/2010/xslt-xquery-serialization' exclude-result-prefixes='output'>
+  <xsl:variable name='e'><e a='1'><f>text</f></e></xsl:variable>
+  <xsl:variable name='params'>
+    <output:serialization-parameters>
+      <output:indent value='no'/>
+    </output:serialization-parameters>
+  </xsl:variable>
+  <xsl:variable name='decl'>
+    <output:serialization-parameters>
+      <output:omit-xml-declaration value='no'/>
+    </output:serialization-parameters>
+  </xsl:variable>
+  <xsl:variable name='text'>
+    <output:serialization-parameters>
+      <output:method value='text'/>
+    </output:serialization-parameters>
+  </xsl:variable>
+  <xsl:template match='/'>
+    <xsl:sequence select='serialize($e, $params/child::*)'/>
+    <xsl:text>|</xsl:text>
+    <xsl:sequence select='serialize($e, $decl/child::*)'/>
+    <xsl:text>|</xsl:text>
+    <xsl:sequence select='serialize($e, $text/child::*)'/>
+  </xsl:template>
+</xsl:stylesheet>"#,
+        parse_from_str,
+        parse_from_str_with_ns,
+        make_doc,
+    )?;
+    assert_eq!(
+        result.to_string(),
+        r#"<e a='1'><f>text</f></e>|<?xml version="1.0" encoding="UTF-8"?><e a='1'><f>text</f></e>|text"#
+    );
+    Ok(())
+}
+pub fn generic_serialize_adaptive_json<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let run = |select: &str| {
+        test_rig(
+            "<Test/>",
+            format!(
+                r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform' xmlns:output='http://www.w3.org/2010/xslt-xquery-serialization' exclude-result-prefixes='output'>
+  <xsl:variable name='e'><e a='1'>text</e></xsl:variable>
+  <xsl:variable name='adaptive'>
+    <output:serialization-parameters>
+      <output:method value='adaptive'/>
+    </output:serialization-parameters>
+  </xsl:variable>
+  <xsl:variable name='json'>
+    <output:serialization-parameters>
+      <output:method value='json'/>
+      <output:indent value='yes'/>
+    </output:serialization-parameters>
+  </xsl:variable>
+  <xsl:template match='/'>
+    <xsl:sequence select='{}'/>
+  </xsl:template>
+</xsl:stylesheet>"#,
+                select
+            ),
+            &parse_from_str,
+            &parse_from_str_with_ns,
+            &make_doc,
+        )
+    };
+
+    // Each item is serialized according to its kind, on its own line
+    assert_eq!(
+        run(r#"serialize(($e/child::e, 42, xs:double("1500"), "a string", true(), xs:date("2024-01-31"), $e/child::e/attribute::a), $adaptive/child::*)"#)?
+            .to_string(),
+        "<e a='1'>text</e>\n42\n1.5e3\n\"a string\"\ntrue()\nxs:date(\"2024-01-31\")\na=\"1\""
+    );
+
+    // A single item is serialized as a JSON value
+    vec![
+        (r#"serialize("a/b", $json/child::*)"#, r#""a\/b""#),
+        (r#"serialize(xs:double("1500"), $json/child::*)"#, "1500"),
+        ("serialize(false(), $json/child::*)", "false"),
+        ("serialize((), $json/child::*)", "null"),
+        (
+            "serialize($e/child::e, $json/child::*)",
+            r#""<e a='1'>text<\/e>""#,
+        ),
+    ]
+    .iter()
+    .try_for_each(|(select, expected)| {
+        assert_eq!(run(select)?.to_string(), *expected, "evaluating {}", select);
+        Ok::<(), Error>(())
+    })?;
+
+    // Sequences and numbers that cannot be serialized as JSON
+    vec![
+        ("serialize((1, 2), $json/child::*)", "SERE0023"),
+        (r#"serialize(xs:double("INF"), $json/child::*)"#, "SERE0020"),
+    ]
+    .iter()
+    .for_each(|(select, code)| {
+        assert_eq!(
+            run(select)
+                .expect_err(format!("evaluating {} succeeded", select).as_str())
+                .code
+                .map(|c| c.to_string()),
+            Some(code.to_string()),
+            "evaluating {}",
+            select
+        )
+    });
+    Ok(())
+}
+pub fn generic_transform_function<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let inner = r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:param name='suffix' select='"?"'/>
+  <xsl:template match='/'><inner><xsl:value-of select='count(child::Test/child::item)'/><xsl:value-of select='$suffix'/></inner></xsl:template>
+  <xsl:template name='main'><greeting>hello</greeting></xsl:template>
+</xsl:stylesheet>"#;
+    let srcdoc = parse_from_str("<Test><item>one</item><item>two</item></Test>")?;
+    let (styledoc, stylens) = parse_from_str_with_ns(
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:param name='inner'/>
+  <xsl:variable name='style' select='parse-xml($inner)'/>
+  <xsl:template match='/'>
+    <xsl:variable name='result' select='transform(map{"stylesheet-node": $style, "source-node": .})'/>
+    <xsl:value-of select='count($result), local-name($result?output)'/>
+    <xsl:text>;</xsl:text>
+    <xsl:value-of select='transform(map{"stylesheet-text": $inner, "source-node": .})?output'/>
+    <xsl:text>;</xsl:text>
+    <xsl:value-of select='transform(map{"stylesheet-node": $style, "source-node": ., "stylesheet-params": map{"suffix": "!"}})?("output")'/>
+    <xsl:text>;</xsl:text>
+    <xsl:value-of select='transform(map{"stylesheet-node": $style, "initial-template": "main"})?output'/>
+  </xsl:template>
+</xsl:stylesheet>"#,
+    )?;
+    let mut stctxt = StaticContextBuilder::new()
+        .message(|_| Ok(()))
+        .fetcher(|_url| Ok(String::new()))
+        .parser(|s| parse_from_str(s))
+        .build();
+    let mut ctxt = from_tree(styledoc, stylens, None)?;
+    ctxt.context(vec![Item::Node(srcdoc.clone())], 0);
+    ctxt.result_document(make_doc()?);
+    ctxt.global_parameter("inner", vec![Item::Value(Rc::new(Value::from(inner)))]);
+    let result = ctxt.evaluate(&mut stctxt)?;
+    assert_eq!(result.to_string(), "1 inner;2?;2!;hello");
+
+    // The options must be a map, whose entries have values of the right type
+    vec![
+        (
+            r#"transform(("stylesheet-text", $inner, "source-node", .))"#,
+            "FOXT0002",
+        ),
+        (
+            r#"transform(map{"stylesheet-node": "style", "source-node": .})"#,
+            "FOXT0002",
+        ),
+        (
+            r#"transform(map{"source-node": ., "source-node": .})"#,
+            "XQDY0137",
+        ),
+        (
+            r#"transform(map{"stylesheet-text": $inner, "source-node": .})?output?output"#,
+            "XPTY0004",
+        ),
+    ]
+    .iter()
+    .for_each(|(select, code)| {
+        let (styledoc, stylens) = parse_from_str_with_ns(
+            format!(
+                r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:param name='inner'/>
+  <xsl:template match='/'><xsl:sequence select='{}'/></xsl:template>
+</xsl:stylesheet>"#,
+                select
+            )
+            .as_str(),
+        )
+        .expect("unable to parse stylesheet");
+        let mut ctxt = from_tree(styledoc, stylens, None).expect("unable to compile stylesheet");
+        ctxt.context(vec![Item::Node(srcdoc.clone())], 0);
+        ctxt.result_document(make_doc().expect("unable to create result document"));
+        ctxt.global_parameter("inner", vec![Item::Value(Rc::new(Value::from(inner)))]);
+        assert_eq!(
+            ctxt.evaluate(&mut stctxt)
+                .expect_err(format!("evaluating {} succeeded", select).as_str())
+                .code
+                .map(|c| c.to_string()),
+            Some(code.to_string()),
+            "evaluating {}",
+            select
+        )
+    });
+
+    // With secure processing, a stylesheet may not be parsed from a string
+    let (styledoc, stylens) = parse_from_str_with_ns(
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:param name='inner'/>
+  <xsl:template match='/'><xsl:sequence select='transform(map{"stylesheet-text": $inner, "source-node": .})'/></xsl:template>
+</xsl:stylesheet>"#,
+    )?;
+    let mut stctxt = StaticContextBuilder::new()
+        .message(|_| Ok(()))
+        .fetcher(|_url| Ok(String::new()))
+        .parser(|s| parse_from_str(s))
+        .secure_processing(true)
+        .build();
+    let mut ctxt = from_tree(styledoc, stylens, None)?;
+    ctxt.context(vec![Item::Node(srcdoc)], 0);
+    ctxt.result_document(make_doc()?);
+    ctxt.global_parameter("inner", vec![Item::Value(Rc::new(Value::from(inner)))]);
+    let e = ctxt
+        .evaluate(&mut stctxt)
+        .expect_err("secure processing did not prevent parsing");
+    assert_eq!(e.kind, ErrorKind::Forbidden);
+    assert_eq!(e.code.map(|c| c.to_string()), Some("XRSP0001".to_string()));
+    Ok(())
+}
+
+pub fn generic_number_1<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let srcdoc = parse_from_str("<Test><t>one</t><t>two</t><t>three</t></Test>")?;
+    let (styledoc, stylens) = parse_from_str_with_ns(
+        r##"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='child::Test'><xsl:apply-templates/></xsl:template>
+  <xsl:template match='child::t'>t element <xsl:number/></xsl:template>
+  <xsl:template match='child::text()'><xsl:sequence select='.'/></xsl:template>
+</xsl:stylesheet>"##,
+    )?;
+    let mut stctxt = StaticContextBuilder::new()
+        .message(|_| Ok(()))
+        .fetcher(|_url| Ok(String::new()))
+        .parser(|s| parse_from_str(s))
+        .build();
+    let mut ctxt = from_tree(styledoc, stylens, None)?;
+    ctxt.context(vec![Item::Node(srcdoc.clone())], 0);
+    ctxt.result_document(make_doc()?);
+    let result = ctxt.evaluate(&mut stctxt)?;
+    assert_eq!(result.to_string(), "t element 1t element 2t element 3");
+    Ok(())
+}
+
+pub fn attr_set_1<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let result = test_rig(
+        "<Test><Level1>one</Level1><Level1>two</Level1></Test>",
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:attribute-set name='foo'>
+    <xsl:attribute name='bar'>from set foo</xsl:attribute>
+  </xsl:attribute-set>
+  <xsl:template match='child::Level1'><xsl:copy xsl:use-attribute-sets='foo'/></xsl:template>
+</xsl:stylesheet>"#,
+        parse_from_str,
+        parse_from_str_with_ns,
+        make_doc,
+    )?;
+    assert_eq!(
+        result.to_xml(),
+        "<Level1 bar='from set foo'></Level1><Level1 bar='from set foo'></Level1>"
+    );
+    Ok(())
+}
+
+pub fn attr_set_2<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let result = test_rig(
+        "<Test><Level1>one</Level1><Level1>two</Level1></Test>",
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:attribute-set name='foo'>
+    <xsl:attribute name='bar'>from set foo</xsl:attribute>
+  </xsl:attribute-set>
+  <xsl:template match='child::Level1'><MyElement xsl:use-attribute-sets='foo'><xsl:apply-templates/></MyElement></xsl:template>
+</xsl:stylesheet>"#,
+        parse_from_str,
+        parse_from_str_with_ns,
+        make_doc,
+    )?;
+    assert_xml_equal(
+        &result,
+        r#"<MyElement bar="from set foo">one</MyElement><MyElement bar="from set foo">two</MyElement>"#,
+    );
+    Ok(())
+}
+
+pub fn attr_set_3<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let result = test_rig(
+        "<Test><Level1>one</Level1><Level1>two</Level1></Test>",
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:attribute-set name='foo'>
+    <xsl:attribute name='bar'>from set foo</xsl:attribute>
+  </xsl:attribute-set>
+  <xsl:template match='child::Level1'><xsl:element name='Element' xsl:use-attribute-sets='foo'><xsl:apply-templates/></xsl:element></xsl:template>
+</xsl:stylesheet>"#,
+        parse_from_str,
+        parse_from_str_with_ns,
+        make_doc,
+    )?;
+    assert_xml_equal(
+        &result,
+        r#"<Element bar="from set foo">one</Element><Element bar="from set foo">two</Element>"#,
+    );
+    Ok(())
+}
+
+pub fn generic_unresolved_prefix<N: Node, G, H, J>(
+    _parse_from_str: G,
+    parse_from_str_with_ns: J,
+    _make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let (styledoc, stylens) = parse_from_str_with_ns(
+        "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform' xmlns:eg='http://example.org/'>
+  <xsl:template match='child::Level1' mode='x:special'>found</xsl:template>
+</xsl:stylesheet>",
+    )?;
+    match from_tree(styledoc, stylens, None) {
+        Ok(_) => panic!("unresolved prefix was not detected"),
+        Err(e) => {
+            assert_eq!(e.kind, ErrorKind::StaticNamespace);
+            assert_eq!(
+                e.code.map(|c| c.to_string()),
+                Some(String::from("XPST0081"))
+            );
+            // The namespaces in scope are listed, to help find the mistake
+            assert!(e.message.contains("\"x\""), "{}", e.message);
+            assert!(
+                e.message.contains("eg=http://example.org/"),
+                "{}",
+                e.message
+            );
+            assert!(
+                e.message
+                    .contains("xsl=http://www.w3.org/1999/XSL/Transform"),
+                "{}",
+                e.message
+            );
+        }
+    }
+    Ok(())
+}
+
+pub fn generic_dump_static_info<N: Node, G, H, J>(
+    _parse_from_str: G,
+    parse_from_str_with_ns: J,
+    _make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let (styledoc, stylens) = parse_from_str_with_ns(
+        "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform' xmlns:eg='http://example.org/'>
+  <xsl:key name='ids' match='child::*' use='@id'/>
+  <xsl:template match='child::Level1'>one</xsl:template>
+  <xsl:template name='named'>named</xsl:template>
+  <xsl:template match='child::Level2' mode='eg:special' priority='2'>two</xsl:template>
+  <xsl:function name='eg:double'><xsl:param name='n'/><xsl:sequence select='$n * 2'/></xsl:function>
+</xsl:stylesheet>",
+    )?;
+    let info = from_tree(styledoc, stylens, None)?.dump_static_info();
+    let templates: Vec<(Option<String>, String, Option<f64>, Option<String>)> = info
+        .templates
+        .iter()
+        .filter(|t| t.location.as_deref() != Some("built-in"))
+        .map(|t| {
+            (
+                t.mode.as_ref().map(|m| m.eqname()),
+                t.pattern.clone(),
+                t.priority,
+                t.location.clone(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        templates,
+        vec![
+            (
+                None,
+                String::from("child::Level1"),
+                Some(0.0),
+                Some(String::from("/xsl:stylesheet/xsl:template[1]"))
+            ),
+            (
+                Some(String::from("Q{http://example.org/}special")),
+                String::from("child::Level2"),
+                Some(2.0),
+                Some(String::from("/xsl:stylesheet/xsl:template[3]"))
+            ),
+        ]
+    );
+    assert_eq!(
+        info.named_templates,
+        vec![QualifiedName::new(None, None, "named")]
+    );
+    assert_eq!(
+        info.functions,
+        vec![QualifiedName::new(
+            Some(String::from("http://example.org/")),
+            None,
+            "double"
+        )]
+    );
+    assert_eq!(info.keys, vec![String::from("ids")]);
+    Ok(())
+}
+
+pub fn generic_default_collation<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let src =
+        "<Test><Level1>b</Level1><Level1>A</Level1><Level1>c</Level1><Level1>B</Level1></Test>";
+    let style = |dc: &str| {
+        format!(
+            "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'{}>
+  <xsl:template match='child::Test'><xsl:apply-templates><xsl:sort select='.'/></xsl:apply-templates></xsl:template>
+  <xsl:template match='child::Level1'><xsl:value-of select='.'/></xsl:template>
+</xsl:stylesheet>",
+            dc
+        )
+    };
+    // Without a default collation, the codepoint collation is used
+    let result = test_rig(
+        src,
+        style(""),
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    assert_eq!(result.to_string(), "ABbc");
+    // Unrecognised collations are skipped
+    let result = test_rig(
+        src,
+        style(" default-collation='http://example.org/unknown http://www.w3.org/2005/xpath-functions/collation/html-ascii-case-insensitive'"),
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    assert_eq!(result.to_string(), "AbBc");
+    Ok(())
+}
+
+pub fn generic_default_collation_unknown<N: Node, G, H, J>(
+    _parse_from_str: G,
+    parse_from_str_with_ns: J,
+    _make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let (styledoc, stylens) = parse_from_str_with_ns(
+        "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform' default-collation='http://example.org/unknown'>
+  <xsl:template match='/'>found</xsl:template>
+</xsl:stylesheet>",
+    )?;
+    match from_tree(styledoc, stylens, None) {
+        Ok(_) => panic!("unknown collation was not detected"),
+        Err(e) => {
+            assert_eq!(e.kind, ErrorKind::StaticData);
+            assert_eq!(
+                e.code.map(|c| c.to_string()),
+                Some(String::from("XTSE0125"))
+            );
+        }
+    }
+    Ok(())
+}
+
+pub fn generic_deep_tree<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    // A source document with elements nested 10,000 deep
+    let srcdoc = parse_from_str("<d/>")?;
+    let mut parent = srcdoc.first_child().unwrap();
+    for _ in 1..10000 {
+        let e = srcdoc.new_element(QualifiedName::new(None, None, "d"))?;
+        parent.push(e.clone())?;
+        parent = e;
+    }
+    parent.push(srcdoc.new_text(Rc::new(Value::from("deep")))?)?;
+
+    let transform = |style: &str, max_depth: Option<usize>| {
+        let (styledoc, stylens) = parse_from_str_with_ns(style)?;
+        let mut stctxt = StaticContextBuilder::new()
+            .message(|_| Ok(()))
+            .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+            .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+            .build();
+        let mut ctxt = from_tree(styledoc, stylens, None)?;
+        if let Some(d) = max_depth {
+            ctxt.max_depth(d)
+        }
+        ctxt.context(vec![Item::Node(srcdoc.clone())], 0);
+        ctxt.result_document(make_doc()?);
+        ctxt.evaluate(&mut stctxt)
+    };
+
+    // Copying the tree and computing its string-value do not recurse
+    let result = transform(
+        "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='/'><xsl:copy-of select='child::*'/>|<xsl:value-of select='.'/></xsl:template>
+</xsl:stylesheet>",
+        None,
+    )?;
+    let xml = result.to_xml();
+    assert!(xml.starts_with("<d><d><d>"));
+    assert!(xml.ends_with("</d></d></d>|deep"));
+    assert_eq!(xml.len(), 10000 * 7 + 4 + 5);
+
+    // An identity transformation recurses for every level, so it stops at the default depth limit rather than overflowing the stack
+    let identity = "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='child::*'><xsl:copy><xsl:apply-templates/></xsl:copy></xsl:template>
+</xsl:stylesheet>";
+    match transform(identity, None) {
+        Ok(_) => panic!("depth limit was not reached"),
+        Err(e) => {
+            assert_eq!(e.kind, ErrorKind::LimitExceeded);
+            assert_eq!(
+                e.code.map(|c| c.to_string()),
+                Some(String::from("XRLM0001"))
+            );
+            assert!(
+                e.message
+                    .contains(format!("({})", DEFAULT_MAX_DEPTH).as_str()),
+                "{}",
+                e.message
+            )
+        }
+    }
+
+    // The limit may be set
+    match transform(identity, Some(40)) {
+        Ok(_) => panic!("depth limit was not reached"),
+        Err(e) => {
+            assert_eq!(e.kind, ErrorKind::LimitExceeded);
+            assert!(e.message.contains("(40)"), "{}", e.message)
+        }
+    }
+    // Recursive named templates count towards the same limit
+    match transform(
+        "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='/'><xsl:call-template name='loop'/></xsl:template>
+  <xsl:template name='loop'>x<xsl:call-template name='loop'/></xsl:template>
+</xsl:stylesheet>",
+        Some(40),
+    ) {
+        Ok(_) => panic!("depth limit was not reached"),
+        Err(e) => assert_eq!(e.kind, ErrorKind::LimitExceeded),
+    }
+    Ok(())
+}
+
+pub fn generic_normalization_form<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    // The source has decomposed characters: "e" followed by a combining acute accent
+    let (_, docs) = test_result_document_rig(
+        "<Test word='cafe\u{301}'>re\u{301}sume\u{301}</Test>",
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:output name='composed' normalization-form='NFC'/>
+  <xsl:template match='/'>
+    <xsl:result-document href='nfc.xml' format='composed'><xsl:copy-of select='child::*'/></xsl:result-document>
+    <xsl:result-document href='nfd.xml' normalization-form='NFD'><xsl:copy-of select='child::*'/></xsl:result-document>
+    <xsl:result-document href='none.xml'><xsl:copy-of select='child::*'/></xsl:result-document>
+  </xsl:template>
+</xsl:stylesheet>"#,
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    assert_eq!(docs.len(), 3);
+    assert_eq!(docs[0].1, "<Test word='caf\u{e9}'>r\u{e9}sum\u{e9}</Test>");
+    assert_eq!(
+        docs[0].2.get_normalization_form(),
+        Some(String::from("NFC"))
+    );
+    assert_eq!(
+        docs[1].1,
+        "<Test word='cafe\u{301}'>re\u{301}sume\u{301}</Test>"
+    );
+    assert_eq!(
+        docs[2].1,
+        "<Test word='cafe\u{301}'>re\u{301}sume\u{301}</Test>"
+    );
+
+    // An unsupported normalization form is an error
+    let (styledoc, stylens) = parse_from_str_with_ns(
+        "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:output normalization-form='fully-normalized'/>
+  <xsl:template match='/'>found</xsl:template>
+</xsl:stylesheet>",
+    )?;
+    match from_tree(styledoc, stylens, None) {
+        Ok(_) => panic!("unsupported normalization form was not detected"),
+        Err(e) => assert_eq!(
+            e.code.map(|c| c.to_string()),
+            Some(String::from("SESU0011"))
+        ),
+    }
+    Ok(())
+}
+
+pub fn generic_number_multiple<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    // Successive format tokens apply to successive levels
+    let result = test_rig(
+        "<doc><s><s><s/><s/></s><s><s/></s></s><s><s><s/></s></s></doc>",
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='child::s'><xsl:number level='multiple' format='1.a.i'/>;<xsl:apply-templates/></xsl:template>
+</xsl:stylesheet>"#,
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    assert_eq!(
+        result.to_string(),
+        "1;1.a;1.a.i;1.a.ii;1.b;1.b.i;2;2.a;2.a.i;"
+    );
+    // The last format token, and the separator before it, are used for the remaining levels
+    let result = test_rig(
+        "<doc><s><s><s/><s/></s></s></doc>",
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='child::s'><xsl:number level='multiple' format='(A-1)'/><xsl:apply-templates/></xsl:template>
+</xsl:stylesheet>"#,
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    assert_eq!(result.to_string(), "(A)(A-1)(A-1-1)(A-1-2)");
+    Ok(())
+}
+
+pub fn generic_number_grouping<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let result = test_rig(
+        "<Test/>",
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='/'><xsl:number value='1234567' grouping-separator=',' grouping-size='3'/>|<xsl:number value='123' grouping-separator=',' grouping-size='3'/>|<xsl:number value='1234567' format='0000000001' grouping-separator='.' grouping-size='4'/></xsl:template>
+</xsl:stylesheet>"#,
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    assert_eq!(result.to_string(), "1,234,567|123|00.0123.4567");
+    Ok(())
+}
+
+pub fn generic_number_ordinal<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let result = test_rig(
+        "<Test><t/><t/><t/><t/></Test>",
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='child::t'><xsl:number ordinal='yes'/>;</xsl:template>
+</xsl:stylesheet>"#,
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    assert_eq!(result.to_string(), "1st;2nd;3rd;4th;");
+    let result = test_rig(
+        "<Test/>",
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='/'><xsl:number value='11' ordinal='yes'/>|<xsl:number value='22' ordinal='yes'/>|<xsl:number value='3' format='Ww' ordinal='yes'/></xsl:template>
+</xsl:stylesheet>"#,
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    assert_eq!(result.to_string(), "11th|22nd|Third");
+    Ok(())
+}
+
+pub fn generic_byte_order_mark<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let srcdoc = parse_from_str("<Test>one</Test>")?;
+    let (styledoc, stylens) = parse_from_str_with_ns(
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:output name='bom' byte-order-mark='yes'/>
+  <xsl:template match='/'>
+    <xsl:result-document href='utf8.xml' format='bom'><r/></xsl:result-document>
+    <xsl:result-document href='utf16.xml' format='bom' encoding='UTF-16'><r/></xsl:result-document>
+    <xsl:result-document href='none.xml'><r/></xsl:result-document>
+  </xsl:template>
+</xsl:stylesheet>"#,
+    )?;
+    let docs = Rc::new(RefCell::new(vec![]));
+    let d = docs.clone();
+    let mut stctxt = StaticContextBuilder::new()
+        .message(|_| Ok(()))
+        .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .result_document(move |_, content: Sequence<N>, od| {
+            d.borrow_mut().push(content.to_xml_bytes(od)?);
+            Ok(())
+        })
+        .build();
+    let mut ctxt = from_tree(styledoc, stylens, None)?;
+    ctxt.context(vec![Item::Node(srcdoc.clone())], 0);
+    ctxt.result_document(make_doc()?);
+    ctxt.evaluate(&mut stctxt)?;
+
+    let docs = docs.borrow();
+    assert_eq!(docs.len(), 3);
+    assert_eq!(
+        docs[0],
+        vec![0xEF, 0xBB, 0xBF, b'<', b'r', b'>', b'<', b'/', b'r', b'>']
+    );
+    assert_eq!(docs[1][..4], [0xFE, 0xFF, 0x00, b'<']);
+    assert_eq!(docs[1].len(), 2 + 7 * 2);
+    assert_eq!(docs[2], b"<r></r>".to_vec());
+    Ok(())
+}
+
+pub fn generic_default_mode<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    // The library's templates are in its default mode, so they do not shadow the main module's templates
+    let modules = HashMap::from([(
+        "file:///xsl/lib.xsl",
+        "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform' default-mode='lib'>
+  <xsl:template match='child::Item'>[lib <xsl:apply-templates mode='#default'/>]</xsl:template>
+  <xsl:template match='child::text()'>LIB</xsl:template>
+  <xsl:template match='child::Extra' mode='#unnamed'>extra</xsl:template>
+</xsl:stylesheet>",
+    )]);
+    let srcdoc = parse_from_str("<Test><Item>a</Item><Item>b</Item><Extra/></Test>")?;
+    let (styledoc, stylens) = parse_from_str_with_ns(
+        "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:include href='lib.xsl'/>
+  <xsl:template match='child::Test'><xsl:apply-templates/>|<xsl:apply-templates select='child::Item' mode='lib'/></xsl:template>
+  <xsl:template match='child::Item'>(main <xsl:apply-templates/>)</xsl:template>
+</xsl:stylesheet>",
+    )?;
+    let mut stctxt = StaticContextBuilder::new()
+        .message(|_| Ok(()))
+        .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .build();
+    let mut ctxt = from_document(
+        styledoc,
+        stylens,
+        Some(Url::parse("file:///xsl/main.xsl").expect("unable to parse URL")),
+        |s| parse_from_str(s),
+        |u| {
+            modules.get(u.as_str()).map_or(
+                Err(Error::new(
+                    ErrorKind::Unknown,
+                    format!("no module \"{}\"", u),
+                )),
+                |m| Ok(m.to_string()),
+            )
+        },
+    )?;
+    ctxt.context(vec![Item::Node(srcdoc.clone())], 0);
+    ctxt.result_document(make_doc()?);
+    let result = ctxt.evaluate(&mut stctxt)?;
+    assert_eq!(
+        result.to_string(),
+        "(main a)(main b)extra|[lib LIB][lib LIB]"
+    );
+    Ok(())
+}
+
+pub fn generic_default_validation_invalid<N: Node, G, H, J>(
+    _parse_from_str: G,
+    parse_from_str_with_ns: J,
+    _make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
 {
-    let srcdoc =
-        parse_from_str("<Test>one<Level1/>two<Level2/>three<Level3/>four<Level4/></Test>")?;
+    let (styledoc, stylens) = parse_from_str_with_ns(
+        "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform' default-validation='strict'>
+  <xsl:template match='/'>found</xsl:template>
+</xsl:stylesheet>",
+    )?;
+    match from_tree(styledoc, stylens, None) {
+        Ok(_) => panic!("invalid default-validation was not detected"),
+        Err(e) => {
+            assert_eq!(e.kind, ErrorKind::StaticData);
+            assert_eq!(
+                e.code.map(|c| c.to_string()),
+                Some(String::from("XTSE0020"))
+            );
+        }
+    }
+    Ok(())
+}
+
+pub fn generic_stylesheet_comments<N: Node, G, H, J, K>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+    parse_stripped: K,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+    K: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let src = "<Test><Level1>one</Level1><Level1>two</Level1></Test>";
+    let plain = "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='child::Test'><out><xsl:apply-templates/></out></xsl:template>
+  <xsl:template match='child::Level1'>
+    <xsl:choose>
+      <xsl:when test='. eq \"one\"'>first </xsl:when>
+      <xsl:otherwise><xsl:text>[second]</xsl:text></xsl:otherwise>
+    </xsl:choose>
+  </xsl:template>
+</xsl:stylesheet>";
+    let commented = "<?xml-stylesheet href='style.xsl'?>
+<!-- A heavily commented stylesheet -->
+<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <!-- The document element -->
+  <xsl:template match='child::Test'><!-- wrap --><out><?pi inside?><xsl:apply-templates/><!-- done --></out></xsl:template>
+  <?pi between?>
+  <!-- Each item -->
+  <xsl:template match='child::Level1'>
+    <!-- choose a label -->
+    <xsl:choose>
+      <!-- the first item -->
+      <xsl:when test='. eq \"one\"'>first<!-- the space is kept --> </xsl:when>
+      <?pi choose?>
+      <xsl:otherwise><xsl:text>[sec<!-- split -->ond]</xsl:text></xsl:otherwise>
+      <!-- end of choice -->
+    </xsl:choose>
+  </xsl:template>
+  <!-- the end -->
+</xsl:stylesheet>";
+    let expected = test_rig(
+        src,
+        plain,
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    assert_eq!(expected.to_xml(), "<out>first [second]</out>");
+    let result = test_rig(
+        src,
+        commented,
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    assert_eq!(result.to_xml(), expected.to_xml());
+    let stripped = test_rig(src, commented, &parse_from_str, &parse_stripped, &make_doc)?;
+    assert_eq!(stripped.to_xml(), expected.to_xml());
+    Ok(())
+}
+
+// The copied function gives the number of copies that have been made of nodes in the tree of the given node.
+pub fn generic_copy_of_direct<N: Node, G, H, J, K>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+    copied: K,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+    K: Fn(&N) -> usize,
+{
+    let items: String = (1..=200)
+        .map(|i| format!("<item n='{}'>value {}<!-- c --></item>", i, i))
+        .collect();
+    let src = format!("<doc><big>{}</big></doc>", items);
+    // The copy-of is at the top level of the template, so its result is serialised directly
+    let direct = "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='child::doc'><xsl:copy-of select='child::big'/></xsl:template>
+</xsl:stylesheet>";
+    // The copy-of is nested inside another instruction, so the nodes are copied
+    let nested = "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='child::doc'><xsl:if test='true()'><xsl:copy-of select='child::big'/></xsl:if></xsl:template>
+</xsl:stylesheet>";
+
+    let srcdoc = parse_from_str(src.as_str())?;
+    let (styledoc, stylens) = parse_from_str_with_ns(direct)?;
+    let mut stctxt = StaticContextBuilder::new()
+        .message(|_| Ok(()))
+        .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .build();
+    let mut ctxt = from_tree(styledoc, stylens, None)?;
+    ctxt.context(vec![Item::Node(srcdoc.clone())], 0);
+    ctxt.result_document(make_doc()?);
+    // When the result is only serialised, the source nodes are not copied
+    let before = copied(&srcdoc);
+    let serialised = ctxt.evaluate_to_string(&mut stctxt)?;
+    assert_eq!(copied(&srcdoc), before);
+    // When the result is returned, the nodes are copied
+    let result = ctxt.evaluate(&mut stctxt)?;
+    assert!(copied(&srcdoc) > before);
+    assert_eq!(result.len(), 1);
+    let big = srcdoc
+        .first_child()
+        .and_then(|d| d.first_child())
+        .expect("no big element");
+    match &result[0] {
+        Item::Node(n) => assert!(!n.is_same(&big)),
+        _ => panic!("result is not a node"),
+    }
+    assert_eq!(serialised, result.to_xml());
+
+    let expected = test_rig(
+        src.as_str(),
+        nested,
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    match &expected[0] {
+        Item::Node(n) => assert!(!n.is_same(&big)),
+        _ => panic!("result is not a node"),
+    }
+    assert_eq!(result.to_xml(), expected.to_xml());
+
+    // An element that inherits namespaces from its ancestors is copied, so that the namespaces are declared
+    let nssrc = "<doc xmlns:q='urn:q'><big><item>one</item></big></doc>";
+    let result = test_rig(
+        nssrc,
+        direct,
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    let expected = test_rig(
+        nssrc,
+        nested,
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    assert_eq!(result.to_xml(), expected.to_xml());
+    assert_eq!(
+        result.to_xml(),
+        "<big xmlns:q='urn:q'><item>one</item></big>"
+    );
+
+    // When the result is used as content of a constructed node, the source tree is unchanged
+    let copysrc = "<doc><big><item>one</item></big></doc>";
+    let srcdoc = parse_from_str(copysrc)?;
+    let (styledoc, stylens) = parse_from_str_with_ns(
+        "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='child::doc'><xsl:copy><xsl:apply-templates/></xsl:copy></xsl:template>
+  <xsl:template match='child::big'><xsl:copy-of select='.'/></xsl:template>
+</xsl:stylesheet>",
+    )?;
+    let mut ctxt = from_tree(styledoc, stylens, None)?;
+    ctxt.context(vec![Item::Node(srcdoc.clone())], 0);
+    ctxt.result_document(make_doc()?);
+    let result = ctxt.evaluate(&mut stctxt)?;
+    assert_eq!(result.to_xml(), copysrc);
+    assert_eq!(srcdoc.to_xml(), copysrc);
+
+    // A variable keeps the result of the template, so the nodes are copied even when the result is only serialised
+    let (styledoc, stylens) = parse_from_str_with_ns(
+        "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='child::doc'><xsl:variable name='v'><xsl:apply-templates/></xsl:variable><xsl:value-of select='count($v/parent::*)'/></xsl:template>
+  <xsl:template match='child::big'><xsl:copy-of select='.'/></xsl:template>
+</xsl:stylesheet>",
+    )?;
+    let mut ctxt = from_tree(styledoc, stylens, None)?;
+    ctxt.context(vec![Item::Node(srcdoc.clone())], 0);
+    ctxt.result_document(make_doc()?);
+    let before = copied(&srcdoc);
+    assert_eq!(ctxt.evaluate_to_string(&mut stctxt)?, "0");
+    assert!(copied(&srcdoc) > before);
+
+    // Neither is a nested copy-of serialised directly
+    let srcdoc = parse_from_str(src.as_str())?;
+    let (styledoc, stylens) = parse_from_str_with_ns(nested)?;
+    let mut ctxt = from_tree(styledoc, stylens, None)?;
+    ctxt.context(vec![Item::Node(srcdoc.clone())], 0);
+    ctxt.result_document(make_doc()?);
+    let before = copied(&srcdoc);
+    assert_eq!(ctxt.evaluate_to_string(&mut stctxt)?, serialised);
+    assert!(copied(&srcdoc) > before);
+    Ok(())
+}
+
+pub fn generic_complex_content<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    // Adjacent atomic values returned by a template become a single text node, separated by spaces
+    let result = test_rig(
+        "<Test><Item/></Test>",
+        "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='child::Test'><out><xsl:apply-templates/></out></xsl:template>
+  <xsl:template match='child::Item'><xsl:sequence select=\"(1, 2, 'x')\"/></xsl:template>
+</xsl:stylesheet>",
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    assert_eq!(result.to_xml(), "<out>1 2 x</out>");
+    match &result[0] {
+        Item::Node(out) => {
+            let children: Vec<N> = out.child_iter().collect();
+            assert_eq!(children.len(), 1);
+            assert_eq!(children[0].node_type(), NodeType::Text);
+            assert_eq!(children[0].to_string(), "1 2 x");
+        }
+        _ => panic!("result is not a node"),
+    }
+
+    // Text nodes are not separated from atomic values, and adjacent text nodes are merged
+    let result = test_rig(
+        "<Test/>",
+        "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='child::Test'><out>a<xsl:sequence select='(1, 2)'/><xsl:value-of select='3'/>b<xsl:text></xsl:text></out></xsl:template>
+</xsl:stylesheet>",
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    assert_eq!(result.to_xml(), "<out>a1 23b</out>");
+    match &result[0] {
+        Item::Node(out) => assert_eq!(out.child_iter().count(), 1),
+        _ => panic!("result is not a node"),
+    }
+
+    // The content of a result document is normalised in the same way
+    let (_, docs) = test_result_document_rig(
+        "<Test/>",
+        "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='child::Test'><xsl:result-document href='values.xml'><xsl:sequence select='(1, 2)'/>!</xsl:result-document></xsl:template>
+</xsl:stylesheet>",
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    assert_eq!(docs.len(), 1);
+    assert_eq!(docs[0].1, "1 2!");
+
+    // So is the value of a parameter that is defined by its content
+    let result = test_rig(
+        "<Test/>",
+        "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='child::Test'><xsl:call-template name='show'><xsl:with-param name='p'><xsl:sequence select='(1, 2)'/></xsl:with-param></xsl:call-template></xsl:template>
+  <xsl:template name='show'><xsl:param name='p'/><xsl:value-of select='count($p)'/>:<xsl:value-of select='$p'/></xsl:template>
+</xsl:stylesheet>",
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    assert_eq!(result.to_string(), "1:1 2");
+    Ok(())
+}
+
+pub fn generic_choose_short_circuit<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    // generate-id() of a string is a type error
+    let style = |first: &str| {
+        format!(
+            "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='child::Test'>
+    <xsl:choose>
+      <xsl:when test='{}'>first</xsl:when>
+      <xsl:when test=\"generate-id('x')\">second</xsl:when>
+      <xsl:otherwise><xsl:value-of select=\"generate-id('y')\"/></xsl:otherwise>
+    </xsl:choose>
+  </xsl:template>
+</xsl:stylesheet>",
+            first
+        )
+    };
+    let result = test_rig(
+        "<Test/>",
+        style("true()"),
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    assert_eq!(result.to_string(), "first");
+    // When the first condition is false, the later condition is evaluated and raises the error
+    match test_rig(
+        "<Test/>",
+        style("false()"),
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    ) {
+        Ok(r) => panic!("expected a type error, got \"{}\"", r.to_string()),
+        Err(e) => assert_eq!(e.kind, ErrorKind::TypeError),
+    }
+    Ok(())
+}
+
+pub fn generic_group_by_collation<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let src = "<Test><Fruit>Apple</Fruit><Fruit>apple</Fruit><Fruit>APPLE</Fruit></Test>";
+    let style = |coll: &str| {
+        format!(
+            "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='child::Test'><xsl:for-each-group select='child::Fruit' group-by='.' collation='{}'>[<xsl:value-of select='current-grouping-key()'/>:<xsl:value-of select='count(current-group())'/>]</xsl:for-each-group></xsl:template>
+</xsl:stylesheet>",
+            coll
+        )
+    };
+    let result = test_rig(
+        src,
+        style("http://www.w3.org/2005/xpath-functions/collation/codepoint"),
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    assert_eq!(result.to_string(), "[Apple:1][apple:1][APPLE:1]");
+    let result = test_rig(
+        src,
+        style("http://www.w3.org/2005/xpath-functions/collation/html-ascii-case-insensitive"),
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    assert_eq!(result.to_string(), "[Apple:3]");
+    Ok(())
+}
+
+pub fn generic_message_location<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let srcdoc = parse_from_str("<Test><Level1/></Test>")?;
+    let (styledoc, stylens) = parse_from_str_with_ns(
+        "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='child::Test'><xsl:message>first</xsl:message><xsl:apply-templates/></xsl:template>
+  <xsl:template match='child::Level1'>
+    <xsl:message terminate='yes' error-code='stop-here'>second</xsl:message>
+  </xsl:template>
+</xsl:stylesheet>",
+    )?;
+    let msgs: Rc<RefCell<Vec<MessageInfo>>> = Rc::new(RefCell::new(vec![]));
+    let handler_msgs = msgs.clone();
+    let mut stctxt = StaticContextBuilder::new()
+        .message(|_| Ok(()))
+        .message_handler(move |m| {
+            handler_msgs.borrow_mut().push(m.clone());
+            Ok(())
+        })
+        .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .build();
+    let mut ctxt = from_tree(
+        styledoc,
+        stylens,
+        Some(Url::parse("file:///style/main.xsl").expect("unable to parse URL")),
+    )?;
+    ctxt.context(vec![Item::Node(srcdoc.clone())], 0);
+    ctxt.result_document(make_doc()?);
+    let e = ctxt
+        .evaluate(&mut stctxt)
+        .expect_err("evaluation succeeded when it should have been terminated");
+
+    let first = Location {
+        module: Some(String::from("file:///style/main.xsl")),
+        line: 2,
+        column: 37,
+    };
+    let second = Location {
+        module: Some(String::from("file:///style/main.xsl")),
+        line: 4,
+        column: 5,
+    };
+    let msgs = msgs.borrow();
+    assert_eq!(msgs.len(), 2);
+    assert_eq!(msgs[0].message, "first");
+    assert!(!msgs[0].terminate);
+    assert_eq!(
+        msgs[0].code.as_ref().map(|c| c.to_string()),
+        Some(String::from("XTMM9000"))
+    );
+    assert_eq!(msgs[0].location, Some(first));
+    assert_eq!(msgs[1].message, "second");
+    assert!(msgs[1].terminate);
+    assert_eq!(
+        msgs[1].code.as_ref().map(|c| c.to_string()),
+        Some(String::from("stop-here"))
+    );
+    assert_eq!(msgs[1].location, Some(second.clone()));
+    // The location is also given by the error that terminates the transformation
+    assert_eq!(e.kind, ErrorKind::Terminated);
+    assert_eq!(e.location.as_deref(), Some(&second));
+    Ok(())
+}
+
+pub fn generic_syntax_error_location<N: Node, G, H, J>(
+    _: G,
+    parse_from_str_with_ns: J,
+    _: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let (styledoc, stylens) = parse_from_str_with_ns(
+        "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='child::Test'>
+    <xsl:value-of select='child::[1]'/>
+  </xsl:template>
+</xsl:stylesheet>",
+    )?;
+    let e = from_tree(
+        styledoc,
+        stylens,
+        Some(Url::parse("file:///style/main.xsl").expect("unable to parse URL")),
+    )
+    .expect_err("compiled stylesheet with a syntax error");
+    assert_eq!(e.kind, ErrorKind::StaticSyntax);
+    assert_eq!(
+        e.code.as_ref().map(|c| c.to_string()),
+        Some(String::from("XPST0003"))
+    );
+    assert!(e.message.contains("offset 7"));
+    assert_eq!(
+        e.location.as_deref(),
+        Some(&Location {
+            module: Some(String::from("file:///style/main.xsl")),
+            line: 3,
+            column: 5,
+        })
+    );
+    Ok(())
+}
+
+pub fn generic_secure_processing<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    // A document nested deeper than the secure limit, but not the default limit
+    let srcdoc = parse_from_str(format!("{}x{}", "<d>".repeat(150), "</d>".repeat(150)).as_str())?;
+
+    // Every callback is set up, so that only secure processing prevents access
+    let transform = |style: &str, secure: bool| {
+        let (styledoc, stylens) = parse_from_str_with_ns(style)?;
+        let mut stctxt = StaticContextBuilder::new()
+            .message(|_| Ok(()))
+            .fetcher(|_| Ok(String::from("<Data>fetched</Data>")))
+            .parser(|s| parse_from_str(s))
+            .result_document(|_, _, _| Ok(()))
+            .secure_processing(secure)
+            .build();
+        let mut ctxt = from_tree(styledoc, stylens, None)?;
+        ctxt.context(vec![Item::Node(srcdoc.clone())], 0);
+        ctxt.result_document(make_doc()?);
+        ctxt.evaluate(&mut stctxt)
+    };
+
+    let document = "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='/'><xsl:value-of select='document(\"file:///data.xml\")'/></xsl:template>
+</xsl:stylesheet>";
+    let result_document = "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='/'><xsl:result-document href='out.xml'><Out/></xsl:result-document></xsl:template>
+</xsl:stylesheet>";
+    let parse_xml = "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='/'><xsl:value-of select='parse-xml(\"&lt;Data>parsed&lt;/Data>\")'/></xsl:template>
+</xsl:stylesheet>";
+    let identity = "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='child::*'><xsl:copy><xsl:apply-templates/></xsl:copy></xsl:template>
+</xsl:stylesheet>";
+
+    // Without secure processing each operation succeeds
+    assert_eq!(transform(document, false)?.to_string(), "fetched");
+    assert!(transform(result_document, false).is_ok());
+    assert_eq!(transform(parse_xml, false)?.to_string(), "parsed");
+    assert!(transform(identity, false)?.to_xml().ends_with(">x</d></d>"));
+
+    // With secure processing, access to external resources and the parsing of documents is forbidden
+    for style in [document, result_document, parse_xml] {
+        match transform(style, true) {
+            Ok(_) => panic!("secure processing did not prevent access"),
+            Err(e) => {
+                assert_eq!(e.kind, ErrorKind::Forbidden);
+                assert_eq!(
+                    e.code.map(|c| c.to_string()),
+                    Some(String::from("XRSP0001"))
+                )
+            }
+        }
+    }
+    // and the depth of template invocation is limited
+    match transform(identity, true) {
+        Ok(_) => panic!("depth limit was not reached"),
+        Err(e) => {
+            assert_eq!(e.kind, ErrorKind::LimitExceeded);
+            assert!(e.message.contains("(100)"), "{}", e.message)
+        }
+    }
+
+    // A benign stylesheet is not affected
+    let result = transform(
+        "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='/'><Out><xsl:value-of select='count(descendant::*)'/></Out></xsl:template>
+</xsl:stylesheet>",
+        true,
+    )?;
+    assert_eq!(result.to_xml(), "<Out>150</Out>");
+    Ok(())
+}
+
+pub fn generic_collection<N: Node + 'static, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error> + Clone + 'static,
+    H: Fn() -> Result<N, Error>,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let srcdoc = parse_from_str("<Test/>")?;
+    // The in-memory collection is the document elements of three documents
+    let docs = ["<Doc>one</Doc>", "<Doc>two</Doc>", "<Doc>three</Doc>"]
+        .into_iter()
+        .map(|d| {
+            parse_from_str(d)?
+                .child_iter()
+                .next()
+                .map(Item::Node)
+                .ok_or(Error::new(ErrorKind::Unknown, "no document element"))
+        })
+        .collect::<Result<Sequence<N>, Error>>()?;
+    let default = vec![Item::Node(parse_from_str("<Doc>default</Doc>")?)];
+    let requests: Rc<RefCell<Vec<Option<String>>>> = Rc::new(RefCell::new(vec![]));
+    let r = requests.clone();
+
     let (styledoc, stylens) = parse_from_str_with_ns(
         "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
-  <xsl:include href='included.xsl'/>
-  <xsl:template match='child::Test'><xsl:apply-templates/></xsl:template>
-  <xsl:template match='child::Level1'>found Level1 element</xsl:template>
-  <xsl:template match='child::text()'><xsl:sequence select='.'/></xsl:template>
+  <xsl:template match='/'><Out><xsl:apply-templates select='collection(\"urn:docs\")'/><Count><xsl:value-of select='count(collection(\"urn:docs\"))'/></Count><Default><xsl:value-of select='collection()'/></Default></Out></xsl:template>
+  <xsl:template match='child::Doc'><Item><xsl:value-of select='.'/></Item></xsl:template>
 </xsl:stylesheet>",
     )?;
-    let pwd = std::env::current_dir().expect("unable to get current directory");
-    let pwds = pwd
-        .into_os_string()
-        .into_string()
-        .expect("unable to convert pwd");
     let mut stctxt = StaticContextBuilder::new()
         .message(|_| Ok(()))
         .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
         .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .collection(move |uri| {
+            r.borrow_mut().push(uri.map(String::from));
+            match uri {
+                Some("urn:docs") => Ok(docs.clone()),
+                Some(u) => Err(Error::new(
+                    ErrorKind::Unknown,
+                    format!("unknown collection \"{}\"", u),
+                )),
+                None => Ok(default.clone()),
+            }
+        })
         .build();
-    let mut ctxt = from_document(
-        styledoc,
-        stylens,
-        Some(
-            Url::parse(format!("file://{}/tests/xsl/including.xsl", pwds.as_str()).as_str())
-                .expect("unable to parse URL"),
-        ),
-        |s| parse_from_str(s),
-        |_| Ok(String::new()),
-    )?;
+    let mut ctxt = from_tree(styledoc, stylens, None)?;
     ctxt.context(vec![Item::Node(srcdoc.clone())], 0);
     ctxt.result_document(make_doc()?);
     let result = ctxt.evaluate(&mut stctxt)?;
-    if result.to_string()
-        == "onefound Level1 elementtwofound Level2 elementthreefound Level3 elementfour"
-    {
-        Ok(())
-    } else {
-        Err(Error::new(ErrorKind::Unknown, format!("got result \"{}\", expected \"onefound Level1 elementtwofound Level2 elementthreefound Level3 elementfour\"", result.to_string())))
+    assert_eq!(
+        result.to_xml(),
+        "<Out><Item>one</Item><Item>two</Item><Item>three</Item><Count>3</Count><Default>default</Default></Out>"
+    );
+    // Each collection is only resolved once
+    assert_eq!(
+        *requests.borrow(),
+        vec![Some(String::from("urn:docs")), None]
+    );
+
+    // Without a resolver, no collection is available
+    let (styledoc, stylens) = parse_from_str_with_ns(
+        "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='/'><xsl:value-of select='count(collection(\"file:///tmp/\"))'/></xsl:template>
+</xsl:stylesheet>",
+    )?;
+    let mut stctxt = StaticContextBuilder::new()
+        .message(|_| Ok(()))
+        .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .build();
+    let mut ctxt = from_tree(styledoc, stylens, None)?;
+    ctxt.context(vec![Item::Node(srcdoc.clone())], 0);
+    ctxt.result_document(make_doc()?);
+    assert_eq!(
+        ctxt.evaluate(&mut stctxt)
+            .expect_err("collection was available without a resolver")
+            .code
+            .map(|c| c.to_string()),
+        Some(String::from("FODC0002"))
+    );
+
+    // The directory resolver, installed by the application, makes a collection of the files in a directory
+    let dir = std::env::temp_dir().join(format!("xrust-collection-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).map_err(|e| Error::new(ErrorKind::Unknown, e.to_string()))?;
+    for (f, c) in [
+        ("b.xml", "<Doc>two</Doc>"),
+        ("a.xml", "<Doc>one</Doc>"),
+        ("c.txt", "not XML"),
+    ] {
+        std::fs::write(dir.join(f), c)
+            .map_err(|e| Error::new(ErrorKind::Unknown, e.to_string()))?;
     }
+    let url = Url::from_directory_path(&dir)
+        .map_err(|_| Error::new(ErrorKind::Unknown, "unable to make URL"))?;
+    let (styledoc, stylens) = parse_from_str_with_ns(
+        format!(
+            "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='/'><xsl:value-of select='collection(\"{}?select=*.xml\")'/></xsl:template>
+</xsl:stylesheet>",
+            url
+        )
+        .as_str(),
+    )?;
+    let mut stctxt = StaticContextBuilder::new()
+        .message(|_| Ok(()))
+        .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .collection(directory_collection(parse_from_str.clone()))
+        .build();
+    let mut ctxt = from_tree(styledoc, stylens, None)?;
+    ctxt.context(vec![Item::Node(srcdoc.clone())], 0);
+    ctxt.result_document(make_doc()?);
+    let result = ctxt.evaluate(&mut stctxt);
+    std::fs::remove_dir_all(&dir).map_err(|e| Error::new(ErrorKind::Unknown, e.to_string()))?;
+    assert_eq!(result?.to_string(), "one two");
+    Ok(())
 }
 
-pub fn generic_document_1<N: Node, G, H, J>(
+pub fn generic_uri_collection<N: Node + 'static, G, H, J>(
     parse_from_str: G,
     parse_from_str_with_ns: J,
     make_doc: H,
@@ -849,42 +5052,99 @@ where
     H: Fn() -> Result<N, Error>,
     J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
 {
-    let srcdoc = parse_from_str("<Test><internal>on the inside</internal></Test>")?;
+    let srcdoc = parse_from_str("<Test/>")?;
+    // A collection of two documents, along with their URIs
+    let docs = vec![
+        Item::Node(parse_from_str("<Doc>one</Doc>")?),
+        Item::Node(parse_from_str("<Doc>two</Doc>")?),
+    ];
+
     let (styledoc, stylens) = parse_from_str_with_ns(
-        r##"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
-  <xsl:template match='child::Test'><xsl:apply-templates/>|<xsl:apply-templates select='document("urn::test.org/test")'/></xsl:template>
-  <xsl:template match='child::internal'>found internal element</xsl:template>
-  <xsl:template match='child::external'>found external element</xsl:template>
-  <xsl:template match='child::text()'><xsl:sequence select='.'/></xsl:template>
-</xsl:stylesheet>"##,
+        "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='/'><Out><xsl:for-each select='collection(\"urn:docs\")'><Doc n='{position()}'><xsl:value-of select='.'/></Doc></xsl:for-each><xsl:for-each select='uri-collection(\"urn:docs\")'><Uri><xsl:value-of select='.'/></Uri></xsl:for-each></Out></xsl:template>
+</xsl:stylesheet>",
     )?;
     let mut stctxt = StaticContextBuilder::new()
         .message(|_| Ok(()))
-        .fetcher(|_url| {
-            Ok(String::from(
-                "<Outside><external>from outside</external></Outside>",
-            ))
+        .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .collection(move |uri| match uri {
+            Some("urn:docs") => Ok(docs.clone()),
+            _ => Err(Error::new(ErrorKind::Unknown, "unknown collection")),
+        })
+        .uri_collection(|uri| match uri {
+            Some("urn:docs") => Ok(vec![
+                String::from("urn:docs:one"),
+                String::from("urn:docs:two"),
+            ]),
+            _ => Err(Error::new(ErrorKind::Unknown, "unknown collection")),
         })
-        .parser(|s| parse_from_str(s))
         .build();
-    let mut ctxt = from_document(
-        styledoc,
-        stylens,
-        None,
-        |s| parse_from_str(s),
-        |_| Ok(String::new()),
-    )?;
+    let mut ctxt = from_tree(styledoc, stylens, None)?;
     ctxt.context(vec![Item::Node(srcdoc.clone())], 0);
     ctxt.result_document(make_doc()?);
     let result = ctxt.evaluate(&mut stctxt)?;
-    if result.to_string() == "found internal element|found external element" {
-        Ok(())
-    } else {
-        Err(Error::new(ErrorKind::Unknown, format!("got result \"{}\", expected \"onefound Level1 elementtwofound Level2 elementthreefound Level3 elementfour\"", result.to_string())))
+    assert_eq!(
+        result.to_xml(),
+        "<Out><Doc n='1'>one</Doc><Doc n='2'>two</Doc><Uri>urn:docs:one</Uri><Uri>urn:docs:two</Uri></Out>"
+    );
+
+    // Without a resolver, no collection is available
+    let (styledoc, stylens) = parse_from_str_with_ns(
+        "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='/'><xsl:value-of select='count(uri-collection(\"file:///tmp/\"))'/></xsl:template>
+</xsl:stylesheet>",
+    )?;
+    let mut stctxt = StaticContextBuilder::new()
+        .message(|_| Ok(()))
+        .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .build();
+    let mut ctxt = from_tree(styledoc, stylens, None)?;
+    ctxt.context(vec![Item::Node(srcdoc.clone())], 0);
+    ctxt.result_document(make_doc()?);
+    assert_eq!(
+        ctxt.evaluate(&mut stctxt)
+            .expect_err("collection was available without a resolver")
+            .code
+            .map(|c| c.to_string()),
+        Some(String::from("FODC0002"))
+    );
+
+    // With the directory resolver, the URIs of a collection are the URLs of the files in the directory
+    let dir = std::env::temp_dir().join(format!("xrust-uri-collection-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).map_err(|e| Error::new(ErrorKind::Unknown, e.to_string()))?;
+    for f in ["b.xml", "a.xml", "c.txt"] {
+        std::fs::write(dir.join(f), "<Doc/>")
+            .map_err(|e| Error::new(ErrorKind::Unknown, e.to_string()))?;
     }
+    let url = Url::from_directory_path(&dir)
+        .map_err(|_| Error::new(ErrorKind::Unknown, "unable to make URL"))?;
+    let (styledoc, stylens) = parse_from_str_with_ns(
+        format!(
+            "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='/'><xsl:value-of select='uri-collection(\"{}?select=*.xml\")'/></xsl:template>
+</xsl:stylesheet>",
+            url
+        )
+        .as_str(),
+    )?;
+    let mut stctxt = StaticContextBuilder::new()
+        .message(|_| Ok(()))
+        .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .uri_collection(directory_uri_collection())
+        .build();
+    let mut ctxt = from_tree(styledoc, stylens, None)?;
+    ctxt.context(vec![Item::Node(srcdoc)], 0);
+    ctxt.result_document(make_doc()?);
+    let result = ctxt.evaluate(&mut stctxt);
+    std::fs::remove_dir_all(&dir).map_err(|e| Error::new(ErrorKind::Unknown, e.to_string()))?;
+    assert_eq!(result?.to_string(), format!("{}a.xml {}b.xml", url, url));
+    Ok(())
 }
 
-pub fn generic_number_1<N: Node, G, H, J>(
+pub fn generic_nilled<N: Node, G, H, J>(
     parse_from_str: G,
     parse_from_str_with_ns: J,
     make_doc: H,
@@ -894,34 +5154,20 @@ where
     H: Fn() -> Result<N, Error>,
     J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
 {
-    let srcdoc = parse_from_str("<Test><t>one</t><t>two</t><t>three</t></Test>")?;
-    let (styledoc, stylens) = parse_from_str_with_ns(
-        r##"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
-  <xsl:template match='child::Test'><xsl:apply-templates/></xsl:template>
-  <xsl:template match='child::t'>t element <xsl:number/></xsl:template>
-  <xsl:template match='child::text()'><xsl:sequence select='.'/></xsl:template>
-</xsl:stylesheet>"##,
-    )?;
-    let mut stctxt = StaticContextBuilder::new()
-        .message(|_| Ok(()))
-        .fetcher(|_url| Ok(String::new()))
-        .parser(|s| parse_from_str(s))
-        .build();
-    let mut ctxt = from_document(
-        styledoc,
-        stylens,
-        None,
-        |s| parse_from_str(s),
-        |_| Ok(String::new()),
+    let result = test_rig(
+        "<Test xmlns:xsi='http://www.w3.org/2001/XMLSchema-instance'><a xsi:nil='true'/><b nil='true'>text</b></Test>",
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='/'><xsl:sequence select='nilled(child::Test/child::a)'/>|<xsl:sequence select='nilled(child::Test/child::b)'/>|<xsl:sequence select='count(nilled(child::Test/child::b/child::text()))'/></xsl:template>
+</xsl:stylesheet>"#,
+        parse_from_str,
+        parse_from_str_with_ns,
+        make_doc,
     )?;
-    ctxt.context(vec![Item::Node(srcdoc.clone())], 0);
-    ctxt.result_document(make_doc()?);
-    let result = ctxt.evaluate(&mut stctxt)?;
-    assert_eq!(result.to_string(), "t element 1t element 2t element 3");
+    assert_eq!(result.to_string(), "true|false|0");
     Ok(())
 }
 
-pub fn attr_set_1<N: Node, G, H, J>(
+pub fn generic_copy_of_attributes<N: Node, G, H, J>(
     parse_from_str: G,
     parse_from_str_with_ns: J,
     make_doc: H,
@@ -931,52 +5177,197 @@ where
     H: Fn() -> Result<N, Error>,
     J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
 {
+    let src = "<Test id='t'><a x='1'>text</a><b z='3'/></Test>";
+    // The attribute-copying identity idiom
     let result = test_rig(
-        "<Test><Level1>one</Level1><Level1>two</Level1></Test>",
+        src,
         r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
-  <xsl:attribute-set name='foo'>
-    <xsl:attribute name='bar'>from set foo</xsl:attribute>
-  </xsl:attribute-set>
-  <xsl:template match='child::Level1'><xsl:copy xsl:use-attribute-sets='foo'/></xsl:template>
+  <xsl:template match='/'><xsl:apply-templates/></xsl:template>
+  <xsl:template match='child::Test'><xsl:copy><xsl:copy-of select='attribute::*|child::node()'/></xsl:copy></xsl:template>
 </xsl:stylesheet>"#,
-        parse_from_str,
-        parse_from_str_with_ns,
-        make_doc,
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
     )?;
     assert_eq!(
         result.to_xml(),
-        "<Level1 bar='from set foo'></Level1><Level1 bar='from set foo'></Level1>"
+        "<Test id='t'><a x='1'>text</a><b z='3'></b></Test>"
     );
+    // Attributes held in a variable are copied onto new elements, and are not removed from the source document
+    let result = test_rig(
+        src,
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='/'><xsl:apply-templates select='child::Test/child::a'/></xsl:template>
+  <xsl:template match='child::a'><xsl:variable name='atts' select='attribute::*'/><one><xsl:copy-of select='$atts'/></one><two><xsl:sequence select='$atts'/></two><xsl:value-of select='count(attribute::*)'/></xsl:template>
+</xsl:stylesheet>"#,
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    assert_eq!(result.to_xml(), "<one x='1'></one><two x='1'></two>1");
+    // An attribute at the top level of the result is its string value
+    let result = test_rig(
+        src,
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='/'><xsl:copy-of select='child::Test/attribute::id'/></xsl:template>
+</xsl:stylesheet>"#,
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    assert_eq!(result.to_xml(), "t");
+    // Attributes cannot follow the children of an element
+    match test_rig(
+        src,
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='/'><xsl:for-each select='child::Test'><new>text<xsl:copy-of select='attribute::*'/></new></xsl:for-each></xsl:template>
+</xsl:stylesheet>"#,
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    ) {
+        Ok(_) => panic!("attribute added after children"),
+        Err(e) => assert_eq!(
+            e.code.map(|c| c.to_string()),
+            Some(String::from("XTDE0410"))
+        ),
+    }
     Ok(())
 }
 
-pub fn attr_set_2<N: Node, G, H, J>(
+pub fn generic_document_factory<N: Node, G, H, J>(
+    parse_from_str: G,
+    parse_from_str_with_ns: J,
+    make_doc: H,
+) -> Result<(), Error>
+where
+    G: Fn(&str) -> Result<N, Error>,
+    H: Fn() -> Result<N, Error> + 'static,
+    J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+{
+    let srcdoc = parse_from_str("<Test><a>one</a><a>two</a></Test>")?;
+    let (styledoc, stylens) = parse_from_str_with_ns(
+        "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform' version='3.0'>
+  <xsl:template match='/'><xsl:apply-templates/></xsl:template>
+  <xsl:template match='child::Test'><Primary><xsl:apply-templates/></Primary></xsl:template>
+  <xsl:template match='child::a'>
+    <xsl:result-document href='{.}.xml'><Out><xsl:value-of select='.'/></Out></xsl:result-document>
+    <Item/>
+  </xsl:template>
+</xsl:stylesheet>",
+    )?;
+    // Every document created by the factory
+    let made = Rc::new(RefCell::new(vec![]));
+    let m = made.clone();
+    let docs = Rc::new(RefCell::new(vec![]));
+    let d = docs.clone();
+    let mut stctxt = StaticContextBuilder::new()
+        .message(|_| Ok(()))
+        .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .document_factory(move || {
+            let doc = make_doc()?;
+            m.borrow_mut().push(doc.clone());
+            Ok(doc)
+        })
+        .result_document(move |uri, content: Sequence<N>, _| {
+            d.borrow_mut().push((uri.to_string(), content));
+            Ok(())
+        })
+        .build();
+    // No result document is given, so the primary result is constructed in a document from the factory
+    let mut ctxt = from_tree(styledoc, stylens, None)?;
+    ctxt.context(vec![Item::Node(srcdoc.clone())], 0);
+    let seq = ctxt.evaluate(&mut stctxt)?;
+    assert_eq!(
+        seq.to_xml(),
+        "<Primary><Item></Item><Item></Item></Primary>"
+    );
+
+    // One document for the primary result, and one for each secondary result
+    assert_eq!(made.borrow().len(), 3);
+    let docs = docs.borrow();
+    assert_eq!(docs.len(), 2);
+    assert_eq!(docs[0].0, "one.xml");
+    assert_eq!(docs[0].1.to_xml(), "<Out>one</Out>");
+    assert_eq!(docs[1].0, "two.xml");
+    assert_eq!(docs[1].1.to_xml(), "<Out>two</Out>");
+    Ok(())
+}
+
+pub fn generic_result_tree<N: Node, R: Node, G, H, J, K>(
     parse_from_str: G,
     parse_from_str_with_ns: J,
     make_doc: H,
+    make_result_doc: K,
 ) -> Result<(), Error>
 where
     G: Fn(&str) -> Result<N, Error>,
     H: Fn() -> Result<N, Error>,
     J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
+    K: Fn() -> Result<R, Error> + 'static,
 {
-    let result = test_rig(
-        "<Test><Level1>one</Level1><Level1>two</Level1></Test>",
-        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
-  <xsl:attribute-set name='foo'>
-    <xsl:attribute name='bar'>from set foo</xsl:attribute>
-  </xsl:attribute-set>
-  <xsl:template match='child::Level1'><MyElement xsl:use-attribute-sets='foo'><xsl:apply-templates/></MyElement></xsl:template>
-</xsl:stylesheet>"#,
-        parse_from_str,
-        parse_from_str_with_ns,
-        make_doc,
+    let srcdoc = parse_from_str("<Test><a n='1'>one</a><a n='2'>two</a></Test>")?;
+    let (styledoc, stylens) = parse_from_str_with_ns(
+        "<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform' version='3.0'>
+  <xsl:template match='/'><xsl:apply-templates/></xsl:template>
+  <xsl:template match='child::Test'><Primary><xsl:copy-of select='child::a[1]'/><xsl:apply-templates/></Primary></xsl:template>
+  <xsl:template match='child::a'>
+    <xsl:result-document href='{.}.xml'><Out kind='secondary'><xsl:value-of select='.'/></Out></xsl:result-document>
+    <Item><xsl:value-of select='.'/></Item>
+  </xsl:template>
+</xsl:stylesheet>",
     )?;
-    assert_eq!(result.to_xml(), "<MyElement bar='from set foo'>one</MyElement><MyElement bar='from set foo'>two</MyElement>");
+    let primary = make_result_doc()?;
+    let docs = Rc::new(RefCell::new(vec![]));
+    let d = docs.clone();
+    let mut stctxt = StaticContextBuilder::new()
+        .message(|_| Ok(()))
+        .fetcher(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .parser(|_| Err(Error::new(ErrorKind::NotImplemented, "not implemented")))
+        .result_document_in(make_result_doc, move |uri, doc: R, _| {
+            d.borrow_mut().push((uri.to_string(), doc));
+            Ok(())
+        })
+        .build();
+    let mut ctxt = from_tree(styledoc, stylens, None)?;
+    ctxt.context(vec![Item::Node(srcdoc.clone())], 0);
+    ctxt.result_document(make_doc()?);
+    let rd = ctxt.evaluate_into(&mut stctxt, primary)?;
+
+    // The primary result is in a document of the result tree implementation
+    assert_eq!(rd.node_type(), NodeType::Document);
+    let p = rd.child_iter().next().expect("no document element");
+    assert_eq!(p.name().get_localname(), "Primary");
+    let names: Vec<String> = p.child_iter().map(|c| c.name().get_localname()).collect();
+    assert_eq!(names, vec!["a", "Item", "Item"]);
+    let a = p.child_iter().next().expect("no copied element");
+    assert_eq!(
+        a.get_attribute(&QualifiedName::new(None, None, "n"))
+            .to_string(),
+        "1"
+    );
+    assert_eq!(rd.to_string(), "oneonetwo");
+
+    // So is each secondary result
+    let docs = docs.borrow();
+    assert_eq!(docs.len(), 2);
+    for (i, (uri, doc)) in ["one", "two"].iter().zip(docs.iter()) {
+        assert_eq!(uri, &format!("{}.xml", i));
+        assert_eq!(doc.node_type(), NodeType::Document);
+        let out = doc.child_iter().next().expect("no document element");
+        assert_eq!(out.name().get_localname(), "Out");
+        assert_eq!(
+            out.get_attribute(&QualifiedName::new(None, None, "kind"))
+                .to_string(),
+            "secondary"
+        );
+        assert_eq!(doc.to_string(), *i);
+    }
     Ok(())
 }
 
-pub fn attr_set_3<N: Node, G, H, J>(
+pub fn generic_apply_templates_attributes<N: Node, G, H, J>(
     parse_from_str: G,
     parse_from_str_with_ns: J,
     make_doc: H,
@@ -986,21 +5377,47 @@ where
     H: Fn() -> Result<N, Error>,
     J: Fn(&str) -> Result<(N, Vec<HashMap<String, String>>), Error>,
 {
+    let src = "<Test ref='one'><second name='foo'>I am foo</second><second name='one'>I am one</second></Test>";
+    // The built-in rule outputs the value of attributes that no template matches
     let result = test_rig(
-        "<Test><Level1>one</Level1><Level1>two</Level1></Test>",
+        src,
         r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
-  <xsl:attribute-set name='foo'>
-    <xsl:attribute name='bar'>from set foo</xsl:attribute>
-  </xsl:attribute-set>
-  <xsl:template match='child::Level1'><xsl:element name='Element' xsl:use-attribute-sets='foo'><xsl:apply-templates/></xsl:element></xsl:template>
+  <xsl:template match='/'><xsl:apply-templates/></xsl:template>
+  <xsl:template match='child::Test'><xsl:apply-templates select='@*'/><xsl:apply-templates select='child::second'/></xsl:template>
+  <xsl:template match='child::second'>(<xsl:apply-templates select='@*'/>)</xsl:template>
+  <xsl:template match='@ref'>[ref=<xsl:value-of select='.'/>]</xsl:template>
 </xsl:stylesheet>"#,
-        parse_from_str,
-        parse_from_str_with_ns,
-        make_doc,
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
     )?;
-    assert_eq!(
-        result.to_xml(),
-        "<Element bar='from set foo'>one</Element><Element bar='from set foo'>two</Element>"
-    );
+    assert_eq!(result.to_string(), "[ref=one](foo)(one)");
+    // A name test has a higher default priority than a wildcard
+    let result = test_rig(
+        src,
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='/'><xsl:apply-templates/></xsl:template>
+  <xsl:template match='child::Test'><xsl:apply-templates select='@*'/><xsl:apply-templates select='child::second'/></xsl:template>
+  <xsl:template match='child::second'>(<xsl:apply-templates select='@*'/>)</xsl:template>
+  <xsl:template match='@ref'>[ref=<xsl:value-of select='.'/>]</xsl:template>
+  <xsl:template match='attribute::*'>{<xsl:value-of select='name()'/>}</xsl:template>
+</xsl:stylesheet>"#,
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    assert_eq!(result.to_string(), "[ref=one]({name})({name})");
+    // An element pattern does not match attributes
+    let result = test_rig(
+        src,
+        r#"<xsl:stylesheet xmlns:xsl='http://www.w3.org/1999/XSL/Transform'>
+  <xsl:template match='/'><xsl:apply-templates select='child::Test/@*'/></xsl:template>
+  <xsl:template match='*'>E</xsl:template>
+</xsl:stylesheet>"#,
+        &parse_from_str,
+        &parse_from_str_with_ns,
+        &make_doc,
+    )?;
+    assert_eq!(result.to_string(), "one");
     Ok(())
 }